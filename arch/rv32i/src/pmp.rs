@@ -413,6 +413,29 @@ pub trait TORUserPMP<const MAX_REGIONS: usize> {
     /// disabled through [`TORUserPMP::disable_user_pmp`]).
     fn available_regions(&self) -> usize;
 
+    /// The number of physical PMP entries currently available for userspace
+    /// memory protection.
+    ///
+    /// The [`PMPUserMPU`] only allocates regions as long as the entries
+    /// required by all of them, as reported by
+    /// [`TORUserPMP::region_entries`], fit within this number. The default
+    /// implementation assumes every available region takes up two entries.
+    fn available_entries(&self) -> usize {
+        self.available_regions() * 2
+    }
+
+    /// The number of physical PMP entries used by
+    /// [`TORUserPMP::configure_pmp`] to enforce a region from `start` to
+    /// `end`.
+    ///
+    /// The default implementation always uses a pair of entries for a TOR
+    /// region. Implementations which encode regions that are a power of two
+    /// in size and naturally aligned (see [`NAPOTRegionSpec`]) as a single
+    /// NAPOT entry return `1` for such regions.
+    fn region_entries(&self, _start: *const u8, _end: *const u8) -> usize {
+        2
+    }
+
     /// Configure the user-mode memory protection.
     ///
     /// This method configures the user-mode memory protection, to be enforced
@@ -485,7 +508,8 @@ pub struct PMPUserMPUConfig<const MAX_REGIONS: usize> {
     /// Indicates if the configuration has changed since the last time it was
     /// written to hardware.
     is_dirty: Cell<bool>,
-    /// Array of MPU regions. Each region requires two physical PMP entries.
+    /// Array of MPU regions. Each region requires one or two physical PMP
+    /// entries, as reported by [`TORUserPMP::region_entries`].
     regions: [(TORUserPMPCFG, *const u8, *const u8); MAX_REGIONS],
     /// Which region index (into the `regions` array above) is used
     /// for app memory (if it has been configured).
@@ -562,6 +586,27 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> PMPUserMPU<
             pmp,
         }
     }
+
+    /// Check whether the enabled regions of `config` fit within the PMP
+    /// entries available to userspace, when the region at `index` is set to
+    /// span from `start` to `end`.
+    fn fits_entries(
+        &self,
+        config: &PMPUserMPUConfig<MAX_REGIONS>,
+        index: usize,
+        start: *const u8,
+        end: *const u8,
+    ) -> bool {
+        let used: usize = config
+            .regions
+            .iter()
+            .enumerate()
+            .filter(|(i, region)| *i != index && region.0 != TORUserPMPCFG::OFF)
+            .map(|(_, region)| self.pmp.region_entries(region.1, region.2))
+            .sum();
+
+        used + self.pmp.region_entries(start, end) <= self.pmp.available_entries()
+    }
 }
 
 impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::platform::mpu::MPU
@@ -584,7 +629,9 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
     }
 
     fn number_total_regions(&self) -> usize {
-        self.pmp.available_regions()
+        // Every region takes up at least one entry, even when encoded as a
+        // NAPOT region.
+        cmp::min(self.pmp.available_regions(), self.pmp.available_entries())
     }
 
     fn new_config(&self) -> Option<Self::MpuConfig> {
@@ -692,14 +739,25 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             }
         }
 
-        // Finally, check that this new region does not overlap with any
-        // existing configured userspace region:
+        // Check that this new region does not overlap with any existing
+        // configured userspace region:
         for region in config.regions.iter() {
             if region.0 != TORUserPMPCFG::OFF && region_overlaps(region, start as *const u8, size) {
                 return None;
             }
         }
 
+        // Finally, check that the PMP has enough entries left to enforce this
+        // region:
+        if !self.fits_entries(
+            config,
+            region_num,
+            start as *const u8,
+            (start + size) as *const u8,
+        ) {
+            return None;
+        }
+
         // All checks passed, store region allocation and mark config as dirty:
         config.regions[region_num] = (
             permissions.into(),
@@ -808,8 +866,8 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             return None;
         }
 
-        // Finally, check that this new region does not overlap with any
-        // existing configured userspace region:
+        // Check that this new region does not overlap with any existing
+        // configured userspace region:
         for region in config.regions.iter() {
             if region.0 != TORUserPMPCFG::OFF
                 && region_overlaps(region, start as *const u8, memory_block_size)
@@ -818,6 +876,17 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             }
         }
 
+        // Finally, check that the PMP has enough entries left to enforce this
+        // region:
+        if !self.fits_entries(
+            config,
+            region_num,
+            start as *const u8,
+            (start + pmp_region_size) as *const u8,
+        ) {
+            return None;
+        }
+
         // All checks passed, store region allocation, indicate the
         // app_memory_region, and mark config as dirty:
         config.regions[region_num] = (
@@ -855,6 +924,18 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
             return Err(());
         }
 
+        // Moving the break can turn a region encoded in a single NAPOT entry
+        // into one that requires two TOR entries. Make sure that these are
+        // still available:
+        if !self.fits_entries(
+            config,
+            region_num,
+            config.regions[region_num].1,
+            app_memory_break as *const u8,
+        ) {
+            return Err(());
+        }
+
         // If we're not out of memory, update the region configuration
         // accordingly:
         config.regions[region_num].0 = permissions.into();
@@ -875,7 +956,8 @@ impl<const MAX_REGIONS: usize, P: TORUserPMP<MAX_REGIONS> + 'static> kernel::pla
 
 #[cfg(test)]
 pub mod test {
-    use super::{TORUserPMP, TORUserPMPCFG};
+    use super::{NAPOTRegionSpec, TORUserPMP, TORUserPMPCFG};
+    use kernel::utilities::registers::LocalRegisterCopy;
 
     struct MockTORUserPMP;
     impl<const MPU_REGIONS: usize> TORUserPMP<MPU_REGIONS> for MockTORUserPMP {
@@ -1118,14 +1200,284 @@ pub mod test {
             )
             .is_none());
     }
+
+    #[test]
+    fn test_mpu_region_aligned_overlap() {
+        use crate::pmp::PMPUserMPU;
+        use kernel::platform::mpu::{Permissions, MPU};
+
+        let mpu: PMPUserMPU<8, MockTORUserPMP> = PMPUserMPU::new(MockTORUserPMP);
+        let mut config = mpu
+            .new_config()
+            .expect("Failed to allocate the first MPU config");
+
+        // Request a single byte at 0x20000001. The region is aligned up to
+        // the 4 byte boundary and rounded to 4 bytes, so it is programmed as
+        // 0x20000004 to 0x20000008:
+        let region_0 = mpu
+            .allocate_region(
+                0x20000001 as *const u8,
+                0x100,
+                1,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate an unaligned MPU region");
+        assert!(region_0.start_address() == 0x20000004 as *const u8);
+        assert!(region_0.size() == 4);
+
+        // A region starting at 0x20000002 does not overlap the requested byte,
+        // but is aligned up to the same 0x20000004 boundary. The overlap check
+        // must use the aligned region and refuse it:
+        assert!(mpu
+            .allocate_region(
+                0x20000002 as *const u8,
+                0x100,
+                4,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .is_none());
+
+        // Starting at 0x20000005, the region is aligned up to 0x20000008,
+        // adjacent to `region_0`. This should work:
+        let region_1 = mpu
+            .allocate_region(
+                0x20000005 as *const u8,
+                0x100,
+                4,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate an MPU region adjacent to another region");
+        assert!(region_1.start_address() == 0x20000008 as *const u8);
+        assert!(region_1.size() == 4);
+    }
+
+    /// A mock PMP with four entries, encoding NAPOT-eligible regions in a
+    /// single entry.
+    struct MockNAPOTUserPMP;
+    impl<const MPU_REGIONS: usize> TORUserPMP<MPU_REGIONS> for MockNAPOTUserPMP {
+        const CONST_ASSERT_CHECK: () = ();
+
+        fn available_regions(&self) -> usize {
+            MPU_REGIONS
+        }
+
+        fn available_entries(&self) -> usize {
+            4
+        }
+
+        fn region_entries(&self, start: *const u8, end: *const u8) -> usize {
+            if NAPOTRegionSpec::new(start, end as usize - start as usize).is_some() {
+                1
+            } else {
+                2
+            }
+        }
+
+        fn configure_pmp(
+            &self,
+            _regions: &[(TORUserPMPCFG, *const u8, *const u8); MPU_REGIONS],
+        ) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn enable_user_pmp(&self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn disable_user_pmp(&self) {}
+    }
+
+    #[test]
+    fn test_mpu_region_napot_entries() {
+        use crate::pmp::PMPUserMPU;
+        use kernel::platform::mpu::{Permissions, MPU};
+
+        let mpu: PMPUserMPU<4, MockNAPOTUserPMP> = PMPUserMPU::new(MockNAPOTUserPMP);
+        let mut config = mpu
+            .new_config()
+            .expect("Failed to allocate the first MPU config");
+
+        // A region which is not a power of two in size requires two entries:
+        let tor_region = mpu
+            .allocate_region(
+                0x10000000 as *const u8,
+                0x3000,
+                0x3000,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate a TOR region");
+
+        // Two NAPOT-eligible regions fit in the remaining two entries:
+        let napot_regions = [0x20000000, 0x20001000].map(|start| {
+            mpu.allocate_region(
+                start as *const u8,
+                0x1000,
+                0x1000,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate a NAPOT region")
+        });
+
+        // All entries are in use, even though a region slot is free:
+        assert!(mpu
+            .allocate_region(
+                0x30000000 as *const u8,
+                0x1000,
+                0x1000,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .is_none());
+
+        // Replacing the TOR region frees two entries, which now fit two more
+        // NAPOT regions than a TOR-only layout would:
+        mpu.remove_memory_region(tor_region, &mut config)
+            .expect("Failed to remove valid MPU region allocation");
+        for start in [0x30000000, 0x30001000] {
+            mpu.allocate_region(
+                start as *const u8,
+                0x1000,
+                0x1000,
+                Permissions::ReadOnly,
+                &mut config,
+            )
+            .expect("Failed to allocate a NAPOT region");
+        }
+
+        // Growing a region out of its NAPOT encoding requires a second
+        // entry, which isn't available:
+        mpu.remove_memory_region(napot_regions[0], &mut config)
+            .expect("Failed to remove valid MPU region allocation");
+        mpu.allocate_app_memory_region(
+            0x40000000 as *const u8,
+            0x4000,
+            0x4000,
+            0x1000,
+            0x1000,
+            Permissions::ReadWriteOnly,
+            &mut config,
+        )
+        .expect("Failed to allocate a NAPOT app memory region");
+        assert!(mpu
+            .update_app_memory_region(
+                0x40001004 as *const u8,
+                0x40003000 as *const u8,
+                Permissions::ReadWriteOnly,
+                &mut config,
+            )
+            .is_err());
+        mpu.update_app_memory_region(
+            0x40002000 as *const u8,
+            0x40003000 as *const u8,
+            Permissions::ReadWriteOnly,
+            &mut config,
+        )
+        .expect("Failed to grow the app memory region to a NAPOT size");
+    }
+
+    #[test]
+    fn test_simple_pmp_layout() {
+        use crate::pmp::simple::layout_entries;
+        use crate::pmp::{pmpcfg_octet, TORUserPMPCFG};
+        use kernel::platform::mpu::Permissions;
+
+        let rw: TORUserPMPCFG = Permissions::ReadWriteOnly.into();
+        let napot_rw = (rw.get() & !0x18) | 0x18;
+        let regions = [
+            (rw, 0x20000000 as *const u8, 0x20001000 as *const u8),
+            (TORUserPMPCFG::OFF, core::ptr::null(), core::ptr::null()),
+            (rw, 0x20002000 as *const u8, 0x20002C00 as *const u8),
+            (rw, 0x20004000 as *const u8, 0x20005000 as *const u8),
+        ];
+
+        let (pmpcfgs, pmpaddrs, used) = layout_entries::<4>(&regions).unwrap();
+        assert_eq!(used, 4);
+        assert_eq!(pmpcfgs, [napot_rw, 0, rw.get(), napot_rw]);
+        assert_eq!(
+            pmpaddrs,
+            [
+                (0x20000000 >> 2) | 0x1FF,
+                0x20002000 >> 2,
+                0x20002C00 >> 2,
+                (0x20004000 >> 2) | 0x1FF,
+            ]
+        );
+        assert_eq!(
+            LocalRegisterCopy::<u8, pmpcfg_octet::Register>::new(pmpcfgs[0])
+                .read_as_enum(pmpcfg_octet::a),
+            Some(pmpcfg_octet::a::Value::NAPOT)
+        );
+
+        // An unaligned region requires two entries, which don't fit:
+        assert!(layout_entries::<3>(&regions).is_err());
+    }
 }
 
 pub mod simple {
-    use super::{pmpcfg_octet, TORUserPMP, TORUserPMPCFG};
+    use super::{pmpcfg_octet, NAPOTRegionSpec, TORUserPMP, TORUserPMPCFG};
     use crate::csr;
     use core::fmt;
     use kernel::utilities::registers::{FieldValue, LocalRegisterCopy};
 
+    /// Returns the [`NAPOTRegionSpec`] of the region from `start` to `end`, if
+    /// it can be encoded as a single NAPOT entry.
+    fn napot_region(start: *const u8, end: *const u8) -> Option<NAPOTRegionSpec> {
+        (end as usize)
+            .checked_sub(start as usize)
+            .and_then(|size| NAPOTRegionSpec::new(start, size))
+    }
+
+    /// Lay out the enabled `regions` onto consecutive PMP entries, starting at
+    /// entry 0.
+    ///
+    /// Regions which meet the NAPOT constraints take up a single NAPOT entry,
+    /// all other regions a pair of entries, the second of which is TOR. Returns
+    /// the `pmpcfg` octets and `pmpaddr` values of all `ENTRIES` entries,
+    /// along with the number of entries used, or `Err(())` if the regions
+    /// don't fit.
+    pub(crate) fn layout_entries<const ENTRIES: usize>(
+        regions: &[(TORUserPMPCFG, *const u8, *const u8)],
+    ) -> Result<([u8; ENTRIES], [usize; ENTRIES], usize), ()> {
+        let mut pmpcfgs = [TORUserPMPCFG::OFF.get(); ENTRIES];
+        let mut pmpaddrs = [0; ENTRIES];
+        let mut used = 0;
+
+        for (pmpcfg, start, end) in regions
+            .iter()
+            .filter(|(pmpcfg, _, _)| *pmpcfg != TORUserPMPCFG::OFF)
+        {
+            if let Some(napot) = napot_region(*start, *end) {
+                if used + 1 > ENTRIES {
+                    return Err(());
+                }
+
+                let mut napot_pmpcfg = pmpcfg.get_reg();
+                napot_pmpcfg.modify(pmpcfg_octet::a::NAPOT);
+                pmpcfgs[used] = napot_pmpcfg.get();
+                pmpaddrs[used] = napot.napot_addr();
+                used += 1;
+            } else {
+                if used + 2 > ENTRIES {
+                    return Err(());
+                }
+
+                // The first entry of a TOR region is OFF, and only provides
+                // the region's start address:
+                pmpaddrs[used] = (*start as usize).overflowing_shr(2).0;
+                pmpcfgs[used + 1] = pmpcfg.get();
+                pmpaddrs[used + 1] = (*end as usize).overflowing_shr(2).0;
+                used += 2;
+            }
+        }
+
+        Ok((pmpcfgs, pmpaddrs, used))
+    }
+
     /// A "simple" RISC-V PMP implementation.
     ///
     /// The SimplePMP does not support locked regions, kernel memory protection,
@@ -1133,12 +1485,16 @@ pub mod simple {
     /// number of hardware PMP regions available. `AVAILABLE_ENTRIES` is
     /// expected to be set to the number of available entries.
     ///
-    /// [`SimplePMP`] implements [`TORUserPMP`] to expose all of its regions as
-    /// "top of range" (TOR) regions (each taking up two physical PMP entires)
-    /// for use as a user-mode memory protection mechanism.
+    /// [`SimplePMP`] implements [`TORUserPMP`] for use as a user-mode memory
+    /// protection mechanism. Regions which are a power of two in size and
+    /// naturally aligned are encoded as a single NAPOT entry. All other regions
+    /// are encoded as "top of range" (TOR) regions, each taking up two physical
+    /// PMP entries.
     ///
     /// Notably, [`SimplePMP`] implements `TORUserPMP<MPU_REGIONS>` over a
-    /// generic `MPU_REGIONS` where `MPU_REGIONS <= (AVAILABLE_ENTRIES / 2)`. As
+    /// generic `MPU_REGIONS` where `MPU_REGIONS <= AVAILABLE_ENTRIES`. Up to
+    /// `AVAILABLE_ENTRIES / 2` regions are always available, more regions can
+    /// only be allocated when some of them are encoded as NAPOT entries. As
     /// PMP re-configuration can have a significiant runtime overhead, users are
     /// free to specify a small `MPU_REGIONS` const-generic parameter to reduce
     /// the runtime overhead induced through PMP configuration, at the cost of
@@ -1206,91 +1562,65 @@ pub mod simple {
     impl<const AVAILABLE_ENTRIES: usize, const MPU_REGIONS: usize> TORUserPMP<MPU_REGIONS>
         for SimplePMP<AVAILABLE_ENTRIES>
     {
-        // Ensure that the MPU_REGIONS (starting at entry, and occupying two
-        // entries per region) don't overflow the available entires.
-        const CONST_ASSERT_CHECK: () = assert!(MPU_REGIONS <= (AVAILABLE_ENTRIES / 2));
+        // Ensure that the MPU_REGIONS (starting at entry, and occupying at
+        // least one entry per region) don't overflow the available entires.
+        const CONST_ASSERT_CHECK: () = assert!(MPU_REGIONS <= AVAILABLE_ENTRIES);
 
         fn available_regions(&self) -> usize {
-            // Always assume to have `MPU_REGIONS` usable TOR regions. We don't
+            // Always assume to have `MPU_REGIONS` usable regions. We don't
             // support locked regions, or kernel protection.
             MPU_REGIONS
         }
 
+        fn available_entries(&self) -> usize {
+            AVAILABLE_ENTRIES
+        }
+
+        fn region_entries(&self, start: *const u8, end: *const u8) -> usize {
+            if napot_region(start, end).is_some() {
+                1
+            } else {
+                2
+            }
+        }
+
         // This implementation is specific for 32-bit systems. We use
-        // `u32::from_be_bytes` and then cast to usize, as it manages to compile
+        // `u32::from_le_bytes` and then cast to usize, as it manages to compile
         // on 64-bit systems as well. However, this implementation will not work
         // on RV64I systems, due to the changed pmpcfgX CSR layout.
         fn configure_pmp(
             &self,
             regions: &[(TORUserPMPCFG, *const u8, *const u8); MPU_REGIONS],
         ) -> Result<(), ()> {
-            // Could use `iter_array_chunks` once that's stable.
-            let mut regions_iter = regions.iter();
-            let mut i = 0;
+            let (pmpcfgs, pmpaddrs, used) = layout_entries::<AVAILABLE_ENTRIES>(regions)?;
 
-            while let Some(even_region) = regions_iter.next() {
-                let odd_region_opt = regions_iter.next();
-
-                if let Some(odd_region) = odd_region_opt {
-                    // We can configure two regions at once which, given that we
-                    // start at index 0 (an even offset), translates to a single
-                    // CSR write for the pmpcfgX register:
-                    csr::CSR.pmpconfig_set(
-                        i / 2,
-                        u32::from_be_bytes([
-                            odd_region.0.get(),
-                            TORUserPMPCFG::OFF.get(),
-                            even_region.0.get(),
-                            TORUserPMPCFG::OFF.get(),
-                        ]) as usize,
-                    );
-
-                    // Now, set the addresses of the respective regions, if they
-                    // are enabled, respectively:
-                    if even_region.0 != TORUserPMPCFG::OFF {
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 0, (even_region.1 as usize).overflowing_shr(2).0);
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 1, (even_region.2 as usize).overflowing_shr(2).0);
-                    }
-
-                    if odd_region.0 != TORUserPMPCFG::OFF {
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 2, (odd_region.1 as usize).overflowing_shr(2).0);
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 3, (odd_region.2 as usize).overflowing_shr(2).0);
-                    }
+            // Each pmpcfgX register holds the octets of four entries, with the
+            // lowest entry in the least significant byte:
+            for (csr_idx, octets) in pmpcfgs.chunks(4).enumerate() {
+                let mut bytes = [TORUserPMPCFG::OFF.get(); 4];
+                bytes[..octets.len()].copy_from_slice(octets);
+                let value = u32::from_le_bytes(bytes) as usize;
 
-                    i += 2;
+                if octets.len() == 4 {
+                    csr::CSR.pmpconfig_set(csr_idx, value);
                 } else {
-                    // TODO: check overhead of code
-                    // Modify the first two pmpcfgX octets for this region:
+                    // Only modify the octets of entries which exist:
                     csr::CSR.pmpconfig_modify(
-                        i / 2,
+                        csr_idx,
                         FieldValue::<usize, csr::pmpconfig::pmpcfg::Register>::new(
-                            0x0000FFFF,
+                            (1 << (octets.len() * 8)) - 1,
                             0,
-                            u32::from_be_bytes([
-                                0,
-                                0,
-                                even_region.0.get(),
-                                TORUserPMPCFG::OFF.get(),
-                            ]) as usize,
+                            value,
                         ),
                     );
-
-                    // Set the addresses if the region is enabled:
-                    if even_region.0 != TORUserPMPCFG::OFF {
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 0, (even_region.1 as usize).overflowing_shr(2).0);
-                        csr::CSR
-                            .pmpaddr_set(i * 2 + 1, (even_region.2 as usize).overflowing_shr(2).0);
-                    }
-
-                    i += 1;
                 }
             }
 
+            // Set the addresses of the entries in use:
+            for (i, pmpaddr) in pmpaddrs.iter().take(used).enumerate() {
+                csr::CSR.pmpaddr_set(i, *pmpaddr);
+            }
+
             Ok(())
         }
 
@@ -4,14 +4,19 @@
 
 //! Component for Crc syscall interface.
 //!
-//! This provides one Component, `CrcComponent`, which implements a
-//! userspace syscall interface to the Crc peripheral.
+//! This provides two Components, `CrcComponent`, which implements a
+//! userspace syscall interface to the Crc peripheral, and
+//! `CrcSoftwareComponent`, which provides a software Crc implementation for
+//! chips without a Crc peripheral.
 //!
 //! Usage
 //! -----
 //! ```rust
 //! let crc = components::crc::CrcComponent::new(board_kernel, &sam4l::crccu::CrcCU)
 //!     .finalize(components::crc_component_static!(sam4l::crccu::Crccu));
+//!
+//! let crc_sw = components::crc::CrcSoftwareComponent::new()
+//!     .finalize(components::crc_software_component_static!());
 //! ```
 
 // Author: Philip Levis <pal@cs.stanford.edu>
@@ -19,6 +24,7 @@
 // Last modified: 6/2/2021
 
 use capsules_extra::crc::CrcDriver;
+use capsules_extra::crc_software::CrcSoftware;
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
@@ -80,3 +86,31 @@ impl<C: 'static + Crc<'static>> Component for CrcComponent<C> {
         crc
     }
 }
+
+#[macro_export]
+macro_rules! crc_software_component_static {
+    ($(,)?) => {{
+        kernel::static_buf!(capsules_extra::crc_software::CrcSoftware<'static>)
+    };};
+}
+
+pub struct CrcSoftwareComponent {}
+
+impl CrcSoftwareComponent {
+    pub fn new() -> CrcSoftwareComponent {
+        CrcSoftwareComponent {}
+    }
+}
+
+impl Component for CrcSoftwareComponent {
+    type StaticInput = &'static mut MaybeUninit<CrcSoftware<'static>>;
+    type Output = &'static CrcSoftware<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let crc_sw = s.write(CrcSoftware::new());
+
+        kernel::deferred_call::DeferredCallClient::register(crc_sw);
+
+        crc_sw
+    }
+}
@@ -0,0 +1,88 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for debouncing a GPIO input pin. The pin is configured as an
+//! input.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let debounce = components::debounce::DebounceComponent::new(
+//!     &gpio_port[SD_DETECT_PIN],
+//!     mux_alarm,
+//!     500,
+//! )
+//! .finalize(components::debounce_component_static!(nrf52832::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::debounce::Debounce;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! debounce_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let debounce = kernel::static_buf!(
+            capsules_extra::debounce::Debounce<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, debounce)
+    };};
+}
+
+pub type DebounceComponentType<A> = Debounce<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct DebounceComponent<A: 'static + Alarm<'static>, P: 'static + gpio::InterruptPin<'static>>
+{
+    pin: &'static P,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    window_ms: u32,
+}
+
+impl<A: 'static + Alarm<'static>, P: 'static + gpio::InterruptPin<'static>>
+    DebounceComponent<A, P>
+{
+    pub fn new(pin: &'static P, alarm_mux: &'static MuxAlarm<'static, A>, window_ms: u32) -> Self {
+        Self {
+            pin,
+            alarm_mux,
+            window_ms,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, P: 'static + gpio::InterruptPin<'static>> Component
+    for DebounceComponent<A, P>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Debounce<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static Debounce<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        self.pin.make_input();
+
+        let debounce = static_buffer
+            .1
+            .write(Debounce::new(self.pin, alarm, self.window_ms));
+        alarm.set_alarm_client(debounce);
+        self.pin.set_client(debounce);
+
+        debounce
+    }
+}
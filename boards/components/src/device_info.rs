@@ -0,0 +1,59 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for read-only device information.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let ficr = &*addr_of!(nrf52840::ficr::FICR_INSTANCE);
+//! let device_info = components::device_info::DeviceInfoComponent::new(
+//!     u64::from_le_bytes(ficr.id()),
+//!     ficr.flash_size(),
+//!     ficr.ram_size(),
+//! )
+//! .finalize(components::device_info_component_static!());
+//! ```
+
+use capsules_extra::device_info::DeviceInfo;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+
+#[macro_export]
+macro_rules! device_info_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::device_info::DeviceInfo)
+    };};
+}
+
+pub type DeviceInfoComponentType = capsules_extra::device_info::DeviceInfo;
+
+pub struct DeviceInfoComponent {
+    device_id: u64,
+    flash_size: u32,
+    ram_size: u32,
+}
+
+impl DeviceInfoComponent {
+    pub fn new(device_id: u64, flash_size: u32, ram_size: u32) -> Self {
+        Self {
+            device_id,
+            flash_size,
+            ram_size,
+        }
+    }
+}
+
+impl Component for DeviceInfoComponent {
+    type StaticInput = &'static mut MaybeUninit<DeviceInfo>;
+    type Output = &'static DeviceInfo;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        s.write(DeviceInfo::new(
+            self.device_id,
+            self.flash_size,
+            self.ram_size,
+        ))
+    }
+}
@@ -0,0 +1,90 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for fanning the interrupts of several GPIO pins in to one
+//! client.
+//!
+//! Usage
+//! -----
+//!
+//! The `gpio_aggregator_component_helper!` macro takes 'static references to
+//! GPIO pins, each with the edge it should interrupt on.
+//!
+//! ```rust
+//! let aggregator = components::gpio_aggregator::GpioAggregatorComponent::new(
+//!     components::gpio_aggregator_component_helper!(
+//!         nrf52840::gpio::GPIOPin,
+//!         (&gpio_port[COLUMN_0], kernel::hil::gpio::InterruptEdge::FallingEdge),
+//!         (&gpio_port[COLUMN_1], kernel::hil::gpio::InterruptEdge::FallingEdge),
+//!     ),
+//! )
+//! .finalize(components::gpio_aggregator_component_static!());
+//! aggregator.set_client(keypad);
+//! ```
+
+use capsules_extra::gpio_aggregator::GpioAggregator;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+
+#[macro_export]
+macro_rules! gpio_aggregator_component_helper {
+    ($Pin:ty, $(($P:expr, $E:expr)),+ $(,)?) => {{
+        use kernel::static_init;
+        use kernel::count_expressions;
+        use kernel::hil::gpio::InterruptValueWrapper;
+        const NUM_PINS: usize = count_expressions!($($P),+);
+
+        static_init!(
+            [(&'static dyn kernel::hil::gpio::InterruptWithValue<'static>, kernel::hil::gpio::InterruptEdge); NUM_PINS],
+            [
+                $(
+                    (static_init!(InterruptValueWrapper<$Pin>, InterruptValueWrapper::new($P))
+                    .finalize() as &'static dyn kernel::hil::gpio::InterruptWithValue<'static>,
+                    $E
+                    ),
+                )*
+            ]
+        )
+    };};
+}
+
+#[macro_export]
+macro_rules! gpio_aggregator_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::gpio_aggregator::GpioAggregator<'static>)
+    };};
+}
+
+pub struct GpioAggregatorComponent {
+    pins: &'static [(
+        &'static dyn gpio::InterruptWithValue<'static>,
+        gpio::InterruptEdge,
+    )],
+}
+
+impl GpioAggregatorComponent {
+    pub fn new(
+        pins: &'static [(
+            &'static dyn gpio::InterruptWithValue<'static>,
+            gpio::InterruptEdge,
+        )],
+    ) -> Self {
+        Self { pins }
+    }
+}
+
+impl Component for GpioAggregatorComponent {
+    type StaticInput = &'static mut MaybeUninit<GpioAggregator<'static>>;
+    type Output = &'static GpioAggregator<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let aggregator = static_buffer.write(GpioAggregator::new(self.pins));
+        for (pin, _) in self.pins.iter() {
+            pin.set_client(aggregator);
+        }
+
+        aggregator
+    }
+}
@@ -32,11 +32,29 @@
 //!     )
 //! );
 //! ```
+//!
+//! For an LCD behind a PCF8574 I2C backpack:
+//!
+//! ```rust
+//! let lcd = components::hd44780::HD44780Pcf8574Component::new(
+//!     mux_alarm,
+//!     mux_i2c,
+//!     0x27,
+//!     16,
+//!     2,
+//! )
+//! .finalize(components::hd44780_pcf8574_component_static!(
+//!     stm32f429zi::tim2::Tim2,
+//!     stm32f429zi::i2c::I2C
+//! ));
+//! ```
 
 use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
-use capsules_extra::hd44780::HD44780;
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::hd44780::{GpioOutputs, Pcf8574Outputs, HD44780};
 use core::mem::MaybeUninit;
 use kernel::component::Component;
+use kernel::hil::i2c;
 use kernel::hil::time;
 use kernel::hil::time::Alarm;
 
@@ -54,8 +72,36 @@ macro_rules! hd44780_component_static {
             >
         );
         let buffer = kernel::static_buf!([u8; capsules_extra::hd44780::BUF_LEN]);
+        let outputs = kernel::static_buf!(capsules_extra::hd44780::GpioOutputs<'static>);
+
+        (alarm, hd44780, buffer, outputs)
+    };};
+}
+
+#[macro_export]
+macro_rules! hd44780_pcf8574_component_static {
+    ($A:ty, $I:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let hd44780 = kernel::static_buf!(
+            capsules_extra::hd44780::HD44780<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+        let buffer = kernel::static_buf!([u8; capsules_extra::hd44780::BUF_LEN]);
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let i2c_buffer = kernel::static_buf!([u8; capsules_extra::hd44780::PCF8574_BUF_LEN]);
+        let outputs = kernel::static_buf!(
+            capsules_extra::hd44780::Pcf8574Outputs<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
 
-        (alarm, hd44780, buffer)
+        (alarm, hd44780, buffer, i2c_device, i2c_buffer, outputs)
     };};
 }
 
@@ -102,6 +148,7 @@ impl<A: 'static + time::Alarm<'static>> Component for HD44780Component<A> {
         &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
         &'static mut MaybeUninit<HD44780<'static, VirtualMuxAlarm<'static, A>>>,
         &'static mut MaybeUninit<[u8; capsules_extra::hd44780::BUF_LEN]>,
+        &'static mut MaybeUninit<GpioOutputs<'static>>,
     );
     type Output = &'static HD44780<'static, VirtualMuxAlarm<'static, A>>;
 
@@ -111,13 +158,91 @@ impl<A: 'static + time::Alarm<'static>> Component for HD44780Component<A> {
 
         let buffer = static_buffer.2.write([0; capsules_extra::hd44780::BUF_LEN]);
 
-        let hd44780 = static_buffer.1.write(capsules_extra::hd44780::HD44780::new(
+        let outputs = static_buffer.3.write(GpioOutputs::new(
             self.rs,
             self.en,
             self.data_4_pin,
             self.data_5_pin,
             self.data_6_pin,
             self.data_7_pin,
+        ));
+
+        let hd44780 = static_buffer.1.write(capsules_extra::hd44780::HD44780::new(
+            outputs,
+            buffer,
+            lcd_alarm,
+            self.width,
+            self.height,
+        ));
+        lcd_alarm.set_alarm_client(hd44780);
+
+        hd44780
+    }
+}
+
+pub struct HD44780Pcf8574Component<
+    A: 'static + time::Alarm<'static>,
+    I: 'static + i2c::I2CMaster<'static>,
+> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    width: u8,
+    height: u8,
+}
+
+impl<A: 'static + time::Alarm<'static>, I: 'static + i2c::I2CMaster<'static>>
+    HD44780Pcf8574Component<A, I>
+{
+    pub fn new(
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        width: u8,
+        height: u8,
+    ) -> HD44780Pcf8574Component<A, I> {
+        HD44780Pcf8574Component {
+            alarm_mux,
+            i2c_mux,
+            i2c_address,
+            width,
+            height,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Component
+    for HD44780Pcf8574Component<A, I>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<HD44780<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<[u8; capsules_extra::hd44780::BUF_LEN]>,
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; capsules_extra::hd44780::PCF8574_BUF_LEN]>,
+        &'static mut MaybeUninit<Pcf8574Outputs<'static, I2CDevice<'static, I>>>,
+    );
+    type Output = &'static HD44780<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let lcd_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        lcd_alarm.setup();
+
+        let buffer = static_buffer.2.write([0; capsules_extra::hd44780::BUF_LEN]);
+
+        let i2c_device = static_buffer
+            .3
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let i2c_buffer = static_buffer
+            .4
+            .write([0; capsules_extra::hd44780::PCF8574_BUF_LEN]);
+        let outputs = static_buffer
+            .5
+            .write(Pcf8574Outputs::new(i2c_device, i2c_buffer));
+        i2c_device.set_client(outputs);
+
+        let hd44780 = static_buffer.1.write(capsules_extra::hd44780::HD44780::new(
+            outputs,
             buffer,
             lcd_alarm,
             self.width,
@@ -10,6 +10,9 @@
 //!
 //! 2. `I2CComponent` provides a virtualized client to the I2C bus.
 //!
+//! 3. `I2CScanComponent` provides a scanner that probes the I2C bus for
+//!    devices.
+//!
 //! Usage
 //! -----
 //! ```rust
@@ -22,6 +25,7 @@
 // Author: Alexandru Radovici <msg4alex@gmail.com>
 
 use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::i2c_scan::I2CScanner;
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
@@ -46,6 +50,17 @@ macro_rules! i2c_component_static {
     };};
 }
 
+#[macro_export]
+macro_rules! i2c_scan_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let buffer = kernel::static_buf!([u8; 1]);
+        let scanner = kernel::static_buf!(capsules_extra::i2c_scan::I2CScanner<'static, $I>);
+        (i2c_device, buffer, scanner)
+    };};
+}
+
 #[macro_export]
 macro_rules! i2c_master_slave_component_static {
     ($I:ty $(,)?) => {{
@@ -123,6 +138,33 @@ impl<I: 'static + i2c::I2CMaster<'static>> Component for I2CComponent<I> {
     }
 }
 
+pub struct I2CScanComponent<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> I2CScanComponent<I> {
+    pub fn new(mux: &'static MuxI2C<'static, I>) -> Self {
+        I2CScanComponent { i2c_mux: mux }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for I2CScanComponent<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; 1]>,
+        &'static mut MaybeUninit<I2CScanner<'static, I>>,
+    );
+    type Output = &'static I2CScanner<'static, I>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let i2c_device = static_buffer.0.write(I2CDevice::new(self.i2c_mux, 0));
+        let buffer = static_buffer.1.write([0; 1]);
+        let scanner = static_buffer.2.write(I2CScanner::new(i2c_device, buffer));
+        i2c_device.set_client(scanner);
+        scanner
+    }
+}
+
 pub struct I2CMasterSlaveDriverComponent<I: 'static + i2c::I2CMasterSlave<'static>> {
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for a software (bit-banged) I2C master.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let i2c = components::i2c_bitbang::I2CBitBangComponent::new(
+//!     &gpio_port[SDA_PIN],
+//!     &gpio_port[SCL_PIN],
+//!     mux_alarm,
+//!     5,
+//! )
+//! .finalize(components::i2c_bitbang_component_static!(nrf52832::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::i2c_bitbang::I2CBitBang;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! i2c_bitbang_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let i2c = kernel::static_buf!(
+            capsules_extra::i2c_bitbang::I2CBitBang<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, i2c)
+    };};
+}
+
+pub type I2CBitBangComponentType<A> = I2CBitBang<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct I2CBitBangComponent<A: 'static + Alarm<'static>> {
+    sda: &'static dyn gpio::Pin,
+    scl: &'static dyn gpio::Pin,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    delay_us: u32,
+}
+
+impl<A: 'static + Alarm<'static>> I2CBitBangComponent<A> {
+    pub fn new(
+        sda: &'static dyn gpio::Pin,
+        scl: &'static dyn gpio::Pin,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        delay_us: u32,
+    ) -> Self {
+        Self {
+            sda,
+            scl,
+            alarm_mux,
+            delay_us,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for I2CBitBangComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<I2CBitBang<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static I2CBitBang<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let i2c = static_buffer
+            .1
+            .write(I2CBitBang::new(self.sda, self.scl, alarm, self.delay_us));
+        alarm.set_alarm_client(i2c);
+
+        i2c
+    }
+}
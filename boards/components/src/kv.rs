@@ -6,6 +6,9 @@
 
 use capsules_extra::kv_driver::KVStoreDriver;
 use capsules_extra::kv_store_permissions::KVStorePermissions;
+use capsules_extra::monotonic_counter::{
+    MonotonicCounter, MonotonicCounterDriver, KEY_BUFFER_LEN, VALUE_BUFFER_LEN,
+};
 use capsules_extra::tickv::{KVSystem, KeyType};
 use capsules_extra::tickv_kv_store::TicKVKVStore;
 use capsules_extra::virtual_kv::{MuxKVPermissions, VirtualKVPermissions};
@@ -248,3 +251,73 @@ impl<K: 'static + KVSystem<'static, K = T>, T: 'static + KeyType + Default> Comp
         kv_store
     }
 }
+
+/////////////////////
+// Monotonic Counter
+/////////////////////
+
+#[macro_export]
+macro_rules! monotonic_counter_component_static {
+    ($K:ty $(,)?) => {{
+        let counter =
+            kernel::static_buf!(capsules_extra::monotonic_counter::MonotonicCounter<'static, $K>);
+        let driver = kernel::static_buf!(
+            capsules_extra::monotonic_counter::MonotonicCounterDriver<'static, $K>
+        );
+        let key_buffer =
+            kernel::static_buf!([u8; capsules_extra::monotonic_counter::KEY_BUFFER_LEN]);
+        let value_buffer =
+            kernel::static_buf!([u8; capsules_extra::monotonic_counter::VALUE_BUFFER_LEN]);
+
+        (counter, driver, key_buffer, value_buffer)
+    };};
+}
+
+pub type MonotonicCounterComponentType<K> =
+    capsules_extra::monotonic_counter::MonotonicCounterDriver<'static, K>;
+
+pub struct MonotonicCounterComponent<K: hil::kv::KV<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    kv: &'static K,
+}
+
+impl<K: hil::kv::KV<'static>> MonotonicCounterComponent<K> {
+    pub fn new(board_kernel: &'static kernel::Kernel, driver_num: usize, kv: &'static K) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            kv,
+        }
+    }
+}
+
+impl<K: hil::kv::KV<'static>> Component for MonotonicCounterComponent<K> {
+    type StaticInput = (
+        &'static mut MaybeUninit<MonotonicCounter<'static, K>>,
+        &'static mut MaybeUninit<MonotonicCounterDriver<'static, K>>,
+        &'static mut MaybeUninit<[u8; KEY_BUFFER_LEN]>,
+        &'static mut MaybeUninit<[u8; VALUE_BUFFER_LEN]>,
+    );
+    type Output = &'static MonotonicCounterDriver<'static, K>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let key_buffer = static_buffer.2.write([0; KEY_BUFFER_LEN]);
+        let value_buffer = static_buffer.3.write([0; VALUE_BUFFER_LEN]);
+
+        let counter =
+            static_buffer
+                .0
+                .write(MonotonicCounter::new(self.kv, key_buffer, value_buffer));
+        self.kv.set_client(counter);
+
+        let driver = static_buffer.1.write(MonotonicCounterDriver::new(
+            counter,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        counter.set_client(driver);
+        driver
+    }
+}
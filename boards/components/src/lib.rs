@@ -27,20 +27,24 @@ pub mod crc;
 pub mod ctap;
 pub mod dac;
 pub mod date_time;
+pub mod debounce;
 pub mod debug_queue;
 pub mod debug_writer;
+pub mod device_info;
 pub mod eui64;
 pub mod flash;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700;
 pub mod gpio;
+pub mod gpio_aggregator;
 pub mod hd44780;
 pub mod hmac;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod i2c;
+pub mod i2c_bitbang;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod keyboard_hid;
@@ -57,6 +61,7 @@ pub mod lsm303agr;
 pub mod lsm303dlhc;
 pub mod lsm6dsox;
 pub mod ltc294x;
+pub mod max30102;
 pub mod mlx90614;
 pub mod mx25r6435f;
 pub mod ninedof;
@@ -68,8 +73,10 @@ pub mod process_console;
 pub mod process_printer;
 pub mod proximity;
 pub mod pwm;
+pub mod random_delay;
 pub mod rf233;
 pub mod rng;
+pub mod rotary_encoder;
 pub mod sched;
 pub mod screen;
 pub mod segger_rtt;
@@ -81,6 +88,7 @@ pub mod si7021;
 pub mod siphash;
 pub mod sound_pressure;
 pub mod spi;
+pub mod spi_bitbang;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod temperature;
@@ -90,7 +98,9 @@ pub mod test;
 pub mod text_screen;
 pub mod thread_network;
 pub mod tickv;
+pub mod timeout;
 pub mod touch;
 pub mod udp_driver;
 pub mod udp_mux;
 pub mod usb;
+pub mod vl53l0x;
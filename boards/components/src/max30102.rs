@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Components for the MAX30102 Pulse Oximeter.
+//!
+//! I2C Interface
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let max30102 = components::max30102::Max30102Component::new(
+//!     mux_i2c,
+//!     0x57,
+//!     &nrf52840::gpio::PORT[Pin::P0_14],
+//!     board_kernel,
+//!     capsules_extra::max30102::DRIVER_NUM,
+//! )
+//! .finalize(components::max30102_component_static!(nrf52840::i2c::TWI));
+//! ```
+
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::max30102::Max30102;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! max30102_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::max30102::BUF_LEN]);
+        let max30102 = kernel::static_buf!(
+            capsules_extra::max30102::Max30102<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+
+        (i2c_device, buffer, max30102)
+    };};
+}
+
+pub struct Max30102Component<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Max30102Component<I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+    ) -> Self {
+        Max30102Component {
+            i2c_mux,
+            i2c_address,
+            interrupt_pin,
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for Max30102Component<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; capsules_extra::max30102::BUF_LEN]>,
+        &'static mut MaybeUninit<Max30102<'static, I2CDevice<'static, I>>>,
+    );
+    type Output = &'static Max30102<'static, I2CDevice<'static, I>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let max30102_i2c = s.0.write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let buffer = s.1.write([0; capsules_extra::max30102::BUF_LEN]);
+        let max30102 = s.2.write(Max30102::new(
+            max30102_i2c,
+            self.interrupt_pin,
+            buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+
+        max30102_i2c.set_client(max30102);
+        self.interrupt_pin.set_client(max30102);
+
+        max30102
+    }
+}
@@ -0,0 +1,103 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for randomized delays for userspace.
+//!
+//! The `Rng` only has room for one client, so the board must give this
+//! component an `Rng` that is not also used by the `RngDriver`.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let random_delay = components::random_delay::RandomDelayComponent::new(
+//!     board_kernel,
+//!     capsules_extra::random_delay::DRIVER_NUM,
+//!     mux_alarm,
+//!     rng,
+//! )
+//! .finalize(components::random_delay_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_core::rng::Entropy32ToRandom<'static, nrf52840::trng::Trng<'static>>
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::random_delay::RandomDelay;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::rng::Rng;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! random_delay_component_static {
+    ($A:ty, $R:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let random_delay = kernel::static_buf!(
+            capsules_extra::random_delay::RandomDelay<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                $R,
+            >
+        );
+
+        (alarm, random_delay)
+    };};
+}
+
+pub type RandomDelayComponentType<A, R> = RandomDelay<'static, VirtualMuxAlarm<'static, A>, R>;
+
+pub struct RandomDelayComponent<A: 'static + time::Alarm<'static>, R: 'static + Rng<'static>> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    rng: &'static R,
+}
+
+impl<A: 'static + time::Alarm<'static>, R: 'static + Rng<'static>> RandomDelayComponent<A, R> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        rng: &'static R,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            alarm_mux,
+            rng,
+        }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>, R: 'static + Rng<'static>> Component
+    for RandomDelayComponent<A, R>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<RandomDelay<'static, VirtualMuxAlarm<'static, A>, R>>,
+    );
+    type Output = &'static RandomDelay<'static, VirtualMuxAlarm<'static, A>, R>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let random_delay = static_buffer.1.write(RandomDelay::new(
+            alarm,
+            self.rng,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        alarm.set_alarm_client(random_delay);
+        self.rng.set_client(random_delay);
+
+        random_delay
+    }
+}
@@ -5,7 +5,9 @@
 //! Component for random number generator using `Entropy32ToRandom`.
 //!
 //! This provides one Component, RngComponent, which implements a userspace
-//! syscall interface to the RNG peripheral (TRNG).
+//! syscall interface to the RNG peripheral (TRNG). EntropyHealthComponent can
+//! be placed between the TRNG and RngComponent to run continuous health tests
+//! on the raw entropy.
 //!
 //! Usage
 //! -----
@@ -18,6 +20,7 @@
 // Last modified: 07/12/2019
 
 use capsules_core::rng;
+use capsules_extra::entropy_health::EntropyHealth;
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
@@ -88,3 +91,33 @@ impl<E: Entropy32<'static>> Component for RngComponent<E> {
         rng
     }
 }
+
+#[macro_export]
+macro_rules! entropy_health_component_static {
+    ($E: ty $(,)?) => {{
+        kernel::static_buf!(capsules_extra::entropy_health::EntropyHealth<'static, $E>)
+    };};
+}
+
+pub type EntropyHealthComponentType<E> = EntropyHealth<'static, E>;
+
+/// Runs continuous health tests on a raw entropy source, see
+/// `capsules_extra::entropy_health`.
+pub struct EntropyHealthComponent<E: Entropy32<'static> + 'static> {
+    trng: &'static E,
+}
+
+impl<E: Entropy32<'static>> EntropyHealthComponent<E> {
+    pub fn new(trng: &'static E) -> Self {
+        Self { trng }
+    }
+}
+
+impl<E: Entropy32<'static>> Component for EntropyHealthComponent<E> {
+    type StaticInput = &'static mut MaybeUninit<EntropyHealth<'static, E>>;
+    type Output = &'static EntropyHealth<'static, E>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        static_buffer.write(EntropyHealth::new(self.trng))
+    }
+}
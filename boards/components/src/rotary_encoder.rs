@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for a quadrature rotary encoder on two GPIO pins.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let encoder = components::rotary_encoder::RotaryEncoderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::rotary_encoder::DRIVER_NUM,
+//!     &gpio_port[ENCODER_A_PIN],
+//!     &gpio_port[ENCODER_B_PIN],
+//! )
+//! .finalize(components::rotary_encoder_component_static!());
+//! ```
+
+use capsules_extra::rotary_encoder::{RotaryEncoder, RotaryEncoderDriver};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::gpio;
+
+#[macro_export]
+macro_rules! rotary_encoder_component_static {
+    () => {{
+        let encoder = kernel::static_buf!(capsules_extra::rotary_encoder::RotaryEncoder<'static>);
+        let driver =
+            kernel::static_buf!(capsules_extra::rotary_encoder::RotaryEncoderDriver<'static>);
+
+        (encoder, driver)
+    };};
+}
+
+pub struct RotaryEncoderComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    pin_a: &'static dyn gpio::Interrupt<'static>,
+    pin_b: &'static dyn gpio::Interrupt<'static>,
+}
+
+impl RotaryEncoderComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        pin_a: &'static dyn gpio::Interrupt<'static>,
+        pin_b: &'static dyn gpio::Interrupt<'static>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            pin_a,
+            pin_b,
+        }
+    }
+}
+
+impl Component for RotaryEncoderComponent {
+    type StaticInput = (
+        &'static mut MaybeUninit<RotaryEncoder<'static>>,
+        &'static mut MaybeUninit<RotaryEncoderDriver<'static>>,
+    );
+    type Output = &'static RotaryEncoderDriver<'static>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let encoder = static_buffer
+            .0
+            .write(RotaryEncoder::new(self.pin_a, self.pin_b));
+        self.pin_a.set_client(encoder);
+        self.pin_b.set_client(encoder);
+
+        let driver = static_buffer.1.write(RotaryEncoderDriver::new(
+            encoder,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        encoder.set_client(driver);
+        encoder.enable();
+
+        driver
+    }
+}
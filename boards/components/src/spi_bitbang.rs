@@ -0,0 +1,87 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for a software (bit-banged) SPI master.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let spi = components::spi_bitbang::SpiBitBangComponent::new(
+//!     &gpio_port[MOSI_PIN],
+//!     &gpio_port[MISO_PIN],
+//!     &gpio_port[SCK_PIN],
+//!     mux_alarm,
+//! )
+//! .finalize(components::spi_bitbang_component_static!(nrf52832::rtc::Rtc));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::spi_bitbang::SpiBitBang;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::time::Alarm;
+
+#[macro_export]
+macro_rules! spi_bitbang_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let spi = kernel::static_buf!(
+            capsules_extra::spi_bitbang::SpiBitBang<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, spi)
+    };};
+}
+
+pub type SpiBitBangComponentType<A> = SpiBitBang<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct SpiBitBangComponent<A: 'static + Alarm<'static>> {
+    mosi: &'static dyn gpio::Pin,
+    miso: &'static dyn gpio::Pin,
+    sclk: &'static dyn gpio::Pin,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + Alarm<'static>> SpiBitBangComponent<A> {
+    pub fn new(
+        mosi: &'static dyn gpio::Pin,
+        miso: &'static dyn gpio::Pin,
+        sclk: &'static dyn gpio::Pin,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Self {
+            mosi,
+            miso,
+            sclk,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>> Component for SpiBitBangComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<SpiBitBang<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static SpiBitBang<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let spi = static_buffer
+            .1
+            .write(SpiBitBang::new(self.mosi, self.miso, self.sclk, alarm));
+        alarm.set_alarm_client(spi);
+
+        spi
+    }
+}
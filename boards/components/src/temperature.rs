@@ -10,13 +10,44 @@
 //! let temp = TemperatureComponent::new(board_kernel, nrf52::temperature::TEMP)
 //!     .finalize(components::temperature_component_static!());
 //! ```
+//!
+//! With a calibration for the sensor:
+//!
+//! ```rust
+//! let temp = TemperatureComponent::new(board_kernel, nrf52::temperature::TEMP)
+//!     .with_calibration(capsules_extra::temperature::Calibration {
+//!         scale: 1000,
+//!         offset: -150,
+//!     })
+//!     .finalize(components::temperature_component_static!());
+//! ```
+//!
+//! With threshold alarms, sampling the sensor every second while any app has
+//! a threshold set:
+//!
+//! ```rust
+//! let temp = TemperatureAlarmComponent::new(
+//!     board_kernel,
+//!     capsules_extra::temperature::DRIVER_NUM,
+//!     &base_peripherals.temp,
+//!     mux_alarm,
+//!     1000,
+//! )
+//! .finalize(components::temperature_alarm_component_static!(
+//!     nrf52832::temperature::Temp,
+//!     nrf52832::rtc::Rtc
+//! ));
+//! ```
 
-use capsules_extra::temperature::TemperatureSensor;
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::temperature::{Calibration, TemperatureSensor};
+use capsules_extra::temperature_alarm::TemperatureAlarm;
 use core::mem::MaybeUninit;
 use kernel::capabilities;
 use kernel::component::Component;
 use kernel::create_capability;
 use kernel::hil;
+use kernel::hil::time::Alarm;
 
 #[macro_export]
 macro_rules! temperature_component_static {
@@ -31,6 +62,7 @@ pub struct TemperatureComponent<T: 'static + hil::sensors::TemperatureDriver<'st
     board_kernel: &'static kernel::Kernel,
     driver_num: usize,
     temp_sensor: &'static T,
+    calibration: Calibration,
 }
 
 impl<T: 'static + hil::sensors::TemperatureDriver<'static>> TemperatureComponent<T> {
@@ -43,8 +75,15 @@ impl<T: 'static + hil::sensors::TemperatureDriver<'static>> TemperatureComponent
             board_kernel,
             driver_num,
             temp_sensor,
+            calibration: Calibration::IDENTITY,
         }
     }
+
+    /// Calibrate the readings returned to userspace.
+    pub fn with_calibration(mut self, calibration: Calibration) -> Self {
+        self.calibration = calibration;
+        self
+    }
 }
 
 impl<T: 'static + hil::sensors::TemperatureDriver<'static>> Component for TemperatureComponent<T> {
@@ -58,8 +97,89 @@ impl<T: 'static + hil::sensors::TemperatureDriver<'static>> Component for Temper
             self.temp_sensor,
             self.board_kernel.create_grant(self.driver_num, &grant_cap),
         ));
+        temp.set_calibration(self.calibration);
+
+        hil::sensors::TemperatureDriver::set_client(self.temp_sensor, temp);
+        temp
+    }
+}
+
+#[macro_export]
+macro_rules! temperature_alarm_component_static {
+    ($T:ty, $A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let temp = kernel::static_buf!(
+            capsules_extra::temperature_alarm::TemperatureAlarm<
+                'static,
+                $T,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, temp)
+    };};
+}
+
+pub type TemperatureAlarmComponentType<T, A> =
+    capsules_extra::temperature_alarm::TemperatureAlarm<'static, T, VirtualMuxAlarm<'static, A>>;
+
+pub struct TemperatureAlarmComponent<
+    T: 'static + hil::sensors::TemperatureDriver<'static>,
+    A: 'static + Alarm<'static>,
+> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    temp_sensor: &'static T,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    sample_period_ms: u32,
+}
+
+impl<T: 'static + hil::sensors::TemperatureDriver<'static>, A: 'static + Alarm<'static>>
+    TemperatureAlarmComponent<T, A>
+{
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        temp_sensor: &'static T,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        sample_period_ms: u32,
+    ) -> TemperatureAlarmComponent<T, A> {
+        TemperatureAlarmComponent {
+            board_kernel,
+            driver_num,
+            temp_sensor,
+            alarm_mux,
+            sample_period_ms,
+        }
+    }
+}
+
+impl<T: 'static + hil::sensors::TemperatureDriver<'static>, A: 'static + Alarm<'static>> Component
+    for TemperatureAlarmComponent<T, A>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<TemperatureAlarm<'static, T, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static TemperatureAlarm<'static, T, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let temp = s.1.write(TemperatureAlarm::new(
+            self.temp_sensor,
+            alarm,
+            self.sample_period_ms,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
 
         hil::sensors::TemperatureDriver::set_client(self.temp_sensor, temp);
+        alarm.set_alarm_client(temp);
         temp
     }
 }
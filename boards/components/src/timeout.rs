@@ -0,0 +1,67 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Component for a one-shot timeout on its own virtual alarm.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! let timeout = components::timeout::TimeoutComponent::new(mux_alarm)
+//!     .finalize(components::timeout_component_static!(nrf52840::rtc::Rtc));
+//! timeout.set_client(driver);
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::timeout::Timeout;
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::time::{self, Alarm};
+
+#[macro_export]
+macro_rules! timeout_component_static {
+    ($A:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let timeout = kernel::static_buf!(
+            capsules_extra::timeout::Timeout<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+            >
+        );
+
+        (alarm, timeout)
+    };};
+}
+
+pub type TimeoutComponentType<A> = Timeout<'static, VirtualMuxAlarm<'static, A>>;
+
+pub struct TimeoutComponent<A: 'static + time::Alarm<'static>> {
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + time::Alarm<'static>> TimeoutComponent<A> {
+    pub fn new(alarm_mux: &'static MuxAlarm<'static, A>) -> Self {
+        Self { alarm_mux }
+    }
+}
+
+impl<A: 'static + time::Alarm<'static>> Component for TimeoutComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<Timeout<'static, VirtualMuxAlarm<'static, A>>>,
+    );
+    type Output = &'static Timeout<'static, VirtualMuxAlarm<'static, A>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        alarm.setup();
+
+        let timeout = static_buffer.1.write(Timeout::new(alarm));
+        alarm.set_alarm_client(timeout);
+
+        timeout
+    }
+}
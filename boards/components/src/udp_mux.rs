@@ -83,7 +83,9 @@ macro_rules! udp_mux_component_static {
                 sixlowpan_compression::Context,
             >
         );
-        let rx_state = kernel::static_buf!(sixlowpan_state::RxState<'static>);
+        let rx_state = kernel::static_buf!(
+            sixlowpan_state::RxState<'static, <$A as kernel::hil::time::Time>::Ticks>
+        );
         let ip6_send = kernel::static_buf!(
             capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
                 'static,
@@ -197,7 +199,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
                 sixlowpan_compression::Context,
             >,
         >,
-        &'static mut MaybeUninit<sixlowpan_state::RxState<'static>>,
+        &'static mut MaybeUninit<sixlowpan_state::RxState<'static, A::Ticks>>,
         &'static mut MaybeUninit<
             capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
                 'static,
@@ -258,7 +260,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan_state);
         let default_rx_state =
             s.3.write(sixlowpan_state::RxState::new(sixlowpan_rx_buffer));
-        sixlowpan_state.add_rx_state(default_rx_state);
+        sixlowpan.add_rx_state(default_rx_state);
         udp_mac.set_receive_client(sixlowpan);
 
         let udp_dgram_buffer = s.13.write([0; MAX_PAYLOAD_LEN]);
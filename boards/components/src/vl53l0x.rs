@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Components for the VL53L0X Time-of-Flight distance sensor.
+//!
+//! I2C Interface
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let vl53l0x = components::vl53l0x::Vl53l0xComponent::new(
+//!     mux_i2c,
+//!     0x29,
+//!     &nrf52840::gpio::PORT[Pin::P0_14],
+//!     board_kernel,
+//!     capsules_extra::vl53l0x::DRIVER_NUM,
+//! )
+//! .finalize(components::vl53l0x_component_static!(nrf52840::i2c::TWI));
+//! ```
+
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::vl53l0x::Vl53l0x;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! vl53l0x_component_static {
+    ($I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let buffer = kernel::static_buf!([u8; capsules_extra::vl53l0x::BUF_LEN]);
+        let vl53l0x = kernel::static_buf!(
+            capsules_extra::vl53l0x::Vl53l0x<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+
+        (i2c_device, buffer, vl53l0x)
+    };};
+}
+
+pub struct Vl53l0xComponent<I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Vl53l0xComponent<I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        interrupt_pin: &'static dyn gpio::InterruptPin<'static>,
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+    ) -> Self {
+        Vl53l0xComponent {
+            i2c_mux,
+            i2c_address,
+            interrupt_pin,
+            board_kernel,
+            driver_num,
+        }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>> Component for Vl53l0xComponent<I> {
+    type StaticInput = (
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; capsules_extra::vl53l0x::BUF_LEN]>,
+        &'static mut MaybeUninit<Vl53l0x<'static, I2CDevice<'static, I>>>,
+    );
+    type Output = &'static Vl53l0x<'static, I2CDevice<'static, I>>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let vl53l0x_i2c = s.0.write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let buffer = s.1.write([0; capsules_extra::vl53l0x::BUF_LEN]);
+        let vl53l0x = s.2.write(Vl53l0x::new(
+            vl53l0x_i2c,
+            self.interrupt_pin,
+            buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+
+        vl53l0x_i2c.set_client(vl53l0x);
+        self.interrupt_pin.set_client(vl53l0x);
+
+        vl53l0x
+    }
+}
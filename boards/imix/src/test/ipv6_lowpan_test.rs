@@ -150,7 +150,7 @@ pub unsafe fn initialize_all(
     );
     mux_mac.add_user(radio_mac);
     let default_rx_state = static_init!(
-        RxState<'static>,
+        RxState<'static, <sam4l::ast::Ast<'static> as time::Time>::Ticks>,
         RxState::new(&mut *addr_of_mut!(RX_STATE_BUF))
     );
 
@@ -193,7 +193,7 @@ pub unsafe fn initialize_all(
         LowpanTest::new(sixlowpan_tx, radio_mac, alarm)
     );
 
-    sixlowpan_state.add_rx_state(default_rx_state);
+    sixlowpan.add_rx_state(default_rx_state);
     sixlowpan_state.set_rx_client(lowpan_frag_test);
     lowpan_frag_test.alarm.set_alarm_client(lowpan_frag_test);
 
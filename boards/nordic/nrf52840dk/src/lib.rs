@@ -77,6 +77,7 @@ use capsules_extra::net::ieee802154::MacAddress;
 use capsules_extra::net::ipv6::ip_utils::IPAddr;
 use kernel::component::Component;
 use kernel::hil::led::LedLow;
+use kernel::hil::radio::{RadioChannel, RadioConfig};
 use kernel::hil::time::Counter;
 #[allow(unused_imports)]
 use kernel::hil::usb::Client;
@@ -120,12 +121,16 @@ const I2C_SDA_PIN: Pin = Pin::P0_26;
 const I2C_SCL_PIN: Pin = Pin::P0_27;
 
 // Constants related to the configuration of the 15.4 network stack
-const PAN_ID: u16 = 0xABCD;
 const DST_MAC_ADDR: capsules_extra::net::ieee802154::MacAddress =
     capsules_extra::net::ieee802154::MacAddress::Short(49138);
 const DEFAULT_CTX_PREFIX_LEN: u8 = 8; //Length of context for 6LoWPAN compression
 const DEFAULT_CTX_PREFIX: [u8; 16] = [0x0_u8; 16]; //Context for 6LoWPAN Compression
 
+/// Correction applied to the die temperature before it is returned to
+/// userspace. Adjust the offset to the error measured on a given unit.
+const TEMPERATURE_CALIBRATION: capsules_extra::temperature::Calibration =
+    capsules_extra::temperature::Calibration::IDENTITY;
+
 /// Debug Writer
 pub mod io;
 
@@ -184,10 +189,14 @@ type TemperatureDriver =
     components::temperature::TemperatureComponentType<nrf52840::temperature::Temp<'static>>;
 
 // IEEE 802.15.4
-type Ieee802154MacDevice = components::ieee802154::Ieee802154ComponentMacDeviceType<
+/// 802.15.4 MAC device shared by the users of [`Ieee802154MuxMac`].
+pub type Ieee802154MacDevice = components::ieee802154::Ieee802154ComponentMacDeviceType<
     nrf52840::ieee802154_radio::Radio<'static>,
     nrf52840::aes::AesECB<'static>,
 >;
+/// Mux sharing the 802.15.4 MAC between its users.
+pub type Ieee802154MuxMac =
+    capsules_extra::ieee802154::virtual_mac::MuxMac<'static, Ieee802154MacDevice>;
 /// Userspace 802.15.4 driver with in-kernel packet framing and MAC layer.
 pub type Ieee802154Driver = components::ieee802154::Ieee802154ComponentType<
     nrf52840::ieee802154_radio::Radio<'static>,
@@ -241,6 +250,7 @@ pub struct Platform {
         >,
     >,
     kv_driver: &'static KVDriver,
+    device_info: &'static components::device_info::DeviceInfoComponentType,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
 }
@@ -265,6 +275,7 @@ impl SyscallDriverLookup for Platform {
             capsules_core::i2c_master_slave_driver::DRIVER_NUM => f(Some(self.i2c_master_slave)),
             capsules_core::spi_controller::DRIVER_NUM => f(Some(self.spi_controller)),
             capsules_extra::kv_driver::DRIVER_NUM => f(Some(self.kv_driver)),
+            capsules_extra::device_info::DRIVER_NUM => f(Some(self.device_info)),
             _ => f(None),
         }
     }
@@ -302,15 +313,48 @@ impl KernelResources<Chip> for Platform {
     }
 }
 
+/// Configuration of the 15.4 network stack created by [`ieee802154_udp`].
+///
+/// The 15.4 and BLE stacks share the radio, boards that do not need 15.4
+/// simply do not call [`ieee802154_udp`].
+#[derive(Clone, Copy, Debug)]
+pub struct Ieee802154Config {
+    /// PAN ID of the network to join.
+    pub pan_id: u16,
+    /// Radio channel to communicate on.
+    pub channel: RadioChannel,
+    /// Short MAC address of this board. If `None`, the address is derived
+    /// from the device ID, so that boards running the same image do not
+    /// collide.
+    pub short_address: Option<u16>,
+}
+
+impl Default for Ieee802154Config {
+    fn default() -> Self {
+        Ieee802154Config {
+            pan_id: 0xABCD,
+            channel: RadioChannel::Channel26,
+            short_address: None,
+        }
+    }
+}
+
 /// Create the capsules needed for the in-kernel UDP and 15.4 stack.
+///
+/// The userspace 15.4 driver and the 6LoWPAN stack under UDP each use their
+/// own `MacUser` on the returned MAC mux. A board can attach further users,
+/// for example a second 6LoWPAN stack, by creating a `MacUser` on the mux and
+/// registering it with `add_user()` before the user transmits.
 pub unsafe fn ieee802154_udp(
     board_kernel: &'static kernel::Kernel,
     nrf52840_peripherals: &'static Nrf52840DefaultPeripherals<'static>,
     mux_alarm: &'static MuxAlarm<nrf52840::rtc::Rtc>,
+    config: Ieee802154Config,
 ) -> (
     &'static Eui64Driver,
     &'static Ieee802154Driver,
     &'static capsules_extra::net::udp::UDPDriver<'static>,
+    &'static Ieee802154MuxMac,
 ) {
     //--------------------------------------------------------------------------
     // AES
@@ -327,17 +371,24 @@ pub unsafe fn ieee802154_udp(
     //--------------------------------------------------------------------------
 
     let device_id = nrf52840::ficr::FICR_INSTANCE.id();
-    let device_id_bottom_16: u16 = u16::from_le_bytes([device_id[0], device_id[1]]);
+    let device_id_bottom_16: u16 = config
+        .short_address
+        .unwrap_or(u16::from_le_bytes([device_id[0], device_id[1]]));
 
     let eui64_driver = components::eui64::Eui64Component::new(u64::from_le_bytes(device_id))
         .finalize(components::eui64_component_static!());
 
+    // The channel is applied when the radio is initialized.
+    nrf52840_peripherals
+        .ieee802154_radio
+        .set_channel(config.channel);
+
     let (ieee802154_driver, mux_mac) = components::ieee802154::Ieee802154Component::new(
         board_kernel,
         capsules_extra::ieee802154::DRIVER_NUM,
         &nrf52840_peripherals.ieee802154_radio,
         aes_mux,
-        PAN_ID,
+        config.pan_id,
         device_id_bottom_16,
         device_id,
     )
@@ -389,7 +440,7 @@ pub unsafe fn ieee802154_udp(
     )
     .finalize(components::udp_driver_component_static!(nrf52840::rtc::Rtc));
 
-    (eui64_driver, ieee802154_driver, udp_driver)
+    (eui64_driver, ieee802154_driver, udp_driver, mux_mac)
 }
 
 /// This is in a separate, inline(never) function so that its stack frame is
@@ -646,6 +697,7 @@ pub unsafe fn start() -> (
         capsules_extra::temperature::DRIVER_NUM,
         &base_peripherals.temp,
     )
+    .with_calibration(TEMPERATURE_CALIBRATION)
     .finalize(components::temperature_component_static!(
         nrf52840::temperature::Temp
     ));
@@ -883,6 +935,18 @@ pub unsafe fn start() -> (
     // PLATFORM SETUP, SCHEDULER, AND START KERNEL LOOP
     //--------------------------------------------------------------------------
 
+    //--------------------------------------------------------------------------
+    // DEVICE INFO
+    //--------------------------------------------------------------------------
+
+    let ficr = &*addr_of!(nrf52840::ficr::FICR_INSTANCE);
+    let device_info = components::device_info::DeviceInfoComponent::new(
+        u64::from_le_bytes(ficr.id()),
+        ficr.flash_size(),
+        ficr.ram_size(),
+    )
+    .finalize(components::device_info_component_static!());
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
@@ -906,6 +970,7 @@ pub unsafe fn start() -> (
         i2c_master_slave,
         spi_controller,
         kv_driver,
+        device_info,
         scheduler,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
     };
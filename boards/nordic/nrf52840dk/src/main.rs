@@ -88,8 +88,12 @@ pub unsafe fn main() {
     // IEEE 802.15.4 and UDP
     //--------------------------------------------------------------------------
 
-    let (eui64_driver, ieee802154_driver, udp_driver) =
-        nrf52840dk_lib::ieee802154_udp(board_kernel, default_peripherals, mux_alarm);
+    let (eui64_driver, ieee802154_driver, udp_driver, _mux_mac) = nrf52840dk_lib::ieee802154_udp(
+        board_kernel,
+        default_peripherals,
+        mux_alarm,
+        nrf52840dk_lib::Ieee802154Config::default(),
+    );
 
     let platform = Platform {
         base: base_platform,
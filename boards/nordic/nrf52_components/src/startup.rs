@@ -130,11 +130,28 @@ impl<'a> Component for NrfStartupComponent<'a> {
 
 pub struct NrfClockComponent<'a> {
     clock: &'a nrf52::clock::Clock,
+    low_power: bool,
 }
 
 impl<'a> NrfClockComponent<'a> {
     pub fn new(clock: &'a nrf52::clock::Clock) -> Self {
-        Self { clock }
+        Self {
+            clock,
+            low_power: false,
+        }
+    }
+
+    /// In low power mode the HFXO is not started here, and only runs while a
+    /// peripheral (e.g. the radio) holds a request for it. Otherwise the HFXO
+    /// is started and kept running.
+    ///
+    /// Either way this does not wait for the crystals to start, so the rest
+    /// of the board can be set up in the meantime. Peripherals that need the
+    /// HFXO wait for it in `Clock::high_request()`, and the RTC only starts
+    /// counting once the LFXO has started.
+    pub fn low_power(mut self, low_power: bool) -> Self {
+        self.low_power = low_power;
+        self
     }
 }
 
@@ -142,17 +159,18 @@ impl<'a> Component for NrfClockComponent<'a> {
     type StaticInput = ();
     type Output = ();
     fn finalize(self, _s: Self::StaticInput) -> Self::Output {
-        // Start all of the clocks. Low power operation will require a better
-        // approach than this.
         self.clock.low_stop();
         self.clock.high_stop();
 
         self.clock
             .low_set_source(nrf52::clock::LowClockSource::XTAL);
         self.clock.low_start();
-        self.clock.high_start();
-        while !self.clock.low_started() {}
-        while !self.clock.high_started() {}
+
+        // Unless running in low power mode, hold a request on the HFXO that
+        // is never released so that it keeps running.
+        if !self.low_power {
+            self.clock.high_request_start();
+        }
     }
 }
 
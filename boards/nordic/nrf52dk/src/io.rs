@@ -37,7 +37,7 @@ impl IoWrite for Writer {
         if !self.initialized {
             self.initialized = true;
             let _ = uart.configure(uart::Parameters {
-                baud_rate: 115200,
+                baud_rate: crate::UART_BAUD_RATE,
                 stop_bits: uart::StopBits::One,
                 parity: uart::Parity::None,
                 hw_flow_control: false,
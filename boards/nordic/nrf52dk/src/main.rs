@@ -17,8 +17,6 @@
 //! -------------------
 //!
 //! ### `GPIOs`
-//! * P0.27 -> (top left header)
-//! * P0.26 -> (top left header)
 //! * P0.02 -> (top left header)
 //! * P0.25 -> (top left header)
 //! * P0.24 -> (top left header)
@@ -46,6 +44,10 @@
 //! * P0.16 -> Button4
 //! * P0.21 -> Reset Button
 //!
+//! ### `I2C`
+//! * P0.26 -> SDA (top left header)
+//! * P0.27 -> SCL (top left header)
+//!
 //! ### `UART`
 //! * P0.05 -> RTS
 //! * P0.06 -> TXD
@@ -75,6 +77,7 @@ use core::ptr::{addr_of, addr_of_mut};
 
 use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
 use kernel::component::Component;
+use kernel::hil::i2c::I2CMaster;
 use kernel::hil::led::LedLow;
 use kernel::hil::time::Counter;
 use kernel::platform::{KernelResources, SyscallDriverLookup};
@@ -103,6 +106,12 @@ const UART_RTS: Option<Pin> = Some(Pin::P0_05);
 const UART_TXD: Pin = Pin::P0_06;
 const UART_CTS: Option<Pin> = Some(Pin::P0_07);
 const UART_RXD: Pin = Pin::P0_08;
+/// Baud rate of the console, also used by the panic writer.
+pub const UART_BAUD_RATE: u32 = 115200;
+
+// I2C pins on the Arduino header
+const I2C_SDA_PIN: Pin = Pin::P0_26;
+const I2C_SCL_PIN: Pin = Pin::P0_27;
 
 // SPI not used, but keep pins around
 const _SPI_MOSI: Pin = Pin::P0_22;
@@ -123,6 +132,10 @@ mod tests;
 const FAULT_RESPONSE: capsules_system::process_policies::PanicFaultPolicy =
     capsules_system::process_policies::PanicFaultPolicy {};
 
+// Watchdog timeout. The nRF52 watchdog can not be stopped once started, so it
+// is left disabled unless a timeout is set here.
+const WATCHDOG_TIMEOUT_MS: Option<u32> = None;
+
 // Number of concurrent processes this platform supports.
 const NUM_PROCS: usize = 4;
 
@@ -138,17 +151,26 @@ static mut PROCESS_PRINTER: Option<&'static capsules_system::process_printer::Pr
 #[link_section = ".stack_buffer"]
 pub static mut STACK_MEMORY: [u8; 0x1000] = [0; 0x1000];
 
-type TemperatureDriver =
-    components::temperature::TemperatureComponentType<nrf52832::temperature::Temp<'static>>;
-type RngDriver = components::rng::RngComponentType<nrf52832::trng::Trng<'static>>;
+type TemperatureDriver = components::temperature::TemperatureAlarmComponentType<
+    nrf52832::temperature::Temp<'static>,
+    nrf52832::rtc::Rtc<'static>,
+>;
+type RngDriver = components::rng::RngComponentType<
+    components::rng::EntropyHealthComponentType<nrf52832::trng::Trng<'static>>,
+>;
+type CrcDriver =
+    capsules_extra::crc::CrcDriver<'static, capsules_extra::crc_software::CrcSoftware<'static>>;
 
 /// Supported drivers by the platform
 pub struct Platform {
+    adc: &'static capsules_core::adc::AdcDedicated<'static, nrf52832::adc::Adc<'static>>,
     ble_radio: &'static capsules_extra::ble_advertising_driver::BLE<
         'static,
         nrf52832::ble_radio::Radio<'static>,
         VirtualMuxAlarm<'static, Rtc<'static>>,
     >,
+    i2c_master:
+        &'static capsules_core::i2c_master::I2CMasterDriver<'static, nrf52832::i2c::TWI<'static>>,
     button: &'static capsules_core::button::Button<'static, nrf52832::gpio::GPIOPin<'static>>,
     pconsole: &'static capsules_core::process_console::ProcessConsole<
         'static,
@@ -164,6 +186,7 @@ pub struct Platform {
         4,
     >,
     rng: &'static RngDriver,
+    crc: &'static CrcDriver,
     temp: &'static TemperatureDriver,
     ipc: kernel::ipc::IPC<{ NUM_PROCS as u8 }>,
     analog_comparator: &'static capsules_extra::analog_comparator::AnalogComparator<
@@ -179,6 +202,7 @@ pub struct Platform {
     >,
     scheduler: &'static RoundRobinSched<'static>,
     systick: cortexm4::systick::SysTick,
+    watchdog: &'static nrf52832::wdt::Wdt,
 }
 
 impl SyscallDriverLookup for Platform {
@@ -193,6 +217,9 @@ impl SyscallDriverLookup for Platform {
             capsules_core::led::DRIVER_NUM => f(Some(self.led)),
             capsules_core::button::DRIVER_NUM => f(Some(self.button)),
             capsules_core::rng::DRIVER_NUM => f(Some(self.rng)),
+            capsules_extra::crc::DRIVER_NUM => f(Some(self.crc)),
+            capsules_core::adc::DRIVER_NUM => f(Some(self.adc)),
+            capsules_core::i2c_master::DRIVER_NUM => f(Some(self.i2c_master)),
             capsules_extra::ble_advertising_driver::DRIVER_NUM => f(Some(self.ble_radio)),
             capsules_extra::temperature::DRIVER_NUM => f(Some(self.temp)),
             capsules_extra::analog_comparator::DRIVER_NUM => f(Some(self.analog_comparator)),
@@ -210,7 +237,7 @@ impl KernelResources<nrf52832::chip::NRF52<'static, Nrf52832DefaultPeripherals<'
     type ProcessFault = ();
     type Scheduler = RoundRobinSched<'static>;
     type SchedulerTimer = cortexm4::systick::SysTick;
-    type WatchDog = ();
+    type WatchDog = nrf52832::wdt::Wdt;
     type ContextSwitchCallback = ();
 
     fn syscall_driver_lookup(&self) -> &Self::SyscallDriverLookup {
@@ -229,7 +256,7 @@ impl KernelResources<nrf52832::chip::NRF52<'static, Nrf52832DefaultPeripherals<'
         &self.systick
     }
     fn watchdog(&self) -> &Self::WatchDog {
-        &()
+        self.watchdog
     }
     fn context_switch_callback(&self) -> &Self::ContextSwitchCallback {
         &()
@@ -273,9 +300,7 @@ pub unsafe fn start() -> (
             // Top mid header on DK board
             6 => &nrf52832_peripherals.gpio_port[Pin::P0_12],
             7 => &nrf52832_peripherals.gpio_port[Pin::P0_11],
-            // Top left header on DK board
-            8 => &nrf52832_peripherals.gpio_port[Pin::P0_27],
-            9 => &nrf52832_peripherals.gpio_port[Pin::P0_26],
+            // Top left header on DK board. P0.27 and P0.26 are used for I2C.
             10 => &nrf52832_peripherals.gpio_port[Pin::P0_02],
             11 => &nrf52832_peripherals.gpio_port[Pin::P0_25]
         ),
@@ -374,7 +399,7 @@ pub unsafe fn start() -> (
     PROCESS_PRINTER = Some(process_printer);
 
     // Create a shared UART channel for the console and for kernel debug.
-    let uart_mux = components::console::UartMuxComponent::new(channel, 115200)
+    let uart_mux = components::console::UartMuxComponent::new(channel, UART_BAUD_RATE)
         .finalize(components::uart_mux_component_static!());
 
     let pconsole = components::process_console::ProcessConsoleComponent::new(
@@ -393,6 +418,10 @@ pub unsafe fn start() -> (
         uart_mux,
     )
     .finalize(components::console_component_static!());
+    // Processes can't change the console baud rate, which is shared with
+    // kernel debug output. To allow it, pass `uart_mux` to
+    // `console.set_uart_configure()` and only give trusted processes
+    // permission to use console command 4.
     // Create the debugger object that handles calls to `debug!()`.
     components::debug_writer::DebugWriterComponent::new(uart_mux)
         .finalize(components::debug_writer_component_static!());
@@ -408,21 +437,80 @@ pub unsafe fn start() -> (
         nrf52832::ble_radio::Radio
     ));
 
-    let temp = components::temperature::TemperatureComponent::new(
+    // Sample the temperature every second while an app has a threshold set.
+    let temp = components::temperature::TemperatureAlarmComponent::new(
         board_kernel,
         capsules_extra::temperature::DRIVER_NUM,
         &base_peripherals.temp,
+        mux_alarm,
+        1000,
     )
-    .finalize(components::temperature_component_static!(
-        nrf52832::temperature::Temp
+    .finalize(components::temperature_alarm_component_static!(
+        nrf52832::temperature::Temp,
+        nrf52832::rtc::Rtc
     ));
 
-    let rng = components::rng::RngComponent::new(
+    // Check the raw TRNG output before handing it to apps.
+    let trng = components::rng::EntropyHealthComponent::new(&base_peripherals.trng).finalize(
+        components::entropy_health_component_static!(nrf52832::trng::Trng),
+    );
+    let rng =
+        components::rng::RngComponent::new(board_kernel, capsules_core::rng::DRIVER_NUM, trng)
+            .finalize(components::rng_component_static!(
+                capsules_extra::entropy_health::EntropyHealth<'static, nrf52832::trng::Trng>
+            ));
+
+    // The nRF52832 has no CRC unit, compute CRCs in software.
+    let crc_sw = components::crc::CrcSoftwareComponent::new()
+        .finalize(components::crc_software_component_static!());
+    let crc =
+        components::crc::CrcComponent::new(board_kernel, capsules_extra::crc::DRIVER_NUM, crc_sw)
+            .finalize(components::crc_component_static!(
+                capsules_extra::crc_software::CrcSoftware<'static>
+            ));
+
+    let i2c_master_buffer = static_init!(
+        [u8; capsules_core::i2c_master::BUFFER_LENGTH],
+        [0; capsules_core::i2c_master::BUFFER_LENGTH]
+    );
+    let i2c_master = static_init!(
+        capsules_core::i2c_master::I2CMasterDriver<'static, nrf52832::i2c::TWI<'static>>,
+        capsules_core::i2c_master::I2CMasterDriver::new(
+            &base_peripherals.twi1,
+            i2c_master_buffer,
+            board_kernel.create_grant(
+                capsules_core::i2c_master::DRIVER_NUM,
+                &memory_allocation_capability
+            ),
+        )
+    );
+    base_peripherals.twi1.configure(
+        nrf52832::pinmux::Pinmux::new(I2C_SCL_PIN as u32),
+        nrf52832::pinmux::Pinmux::new(I2C_SDA_PIN as u32),
+    );
+    base_peripherals.twi1.set_master_client(i2c_master);
+
+    // The analog inputs on the Arduino header, A0 to A5.
+    let adc_channels = static_init!(
+        [nrf52832::adc::AdcChannelSetup; 6],
+        [
+            nrf52832::adc::AdcChannelSetup::new(nrf52832::adc::AdcChannel::AnalogInput1),
+            nrf52832::adc::AdcChannelSetup::new(nrf52832::adc::AdcChannel::AnalogInput2),
+            nrf52832::adc::AdcChannelSetup::new(nrf52832::adc::AdcChannel::AnalogInput4),
+            nrf52832::adc::AdcChannelSetup::new(nrf52832::adc::AdcChannel::AnalogInput5),
+            nrf52832::adc::AdcChannelSetup::new(nrf52832::adc::AdcChannel::AnalogInput6),
+            nrf52832::adc::AdcChannelSetup::new(nrf52832::adc::AdcChannel::AnalogInput7),
+        ]
+    );
+    let adc = components::adc::AdcDedicatedComponent::new(
+        &base_peripherals.adc,
+        adc_channels,
         board_kernel,
-        capsules_core::rng::DRIVER_NUM,
-        &base_peripherals.trng,
+        capsules_core::adc::DRIVER_NUM,
     )
-    .finalize(components::rng_component_static!(nrf52832::trng::Trng));
+    .finalize(components::adc_dedicated_component_static!(
+        nrf52832::adc::Adc
+    ));
 
     // Initialize AC using AIN5 (P0.29) as VIN+ and VIN- as AIN0 (P0.02)
     // These are hardcoded pin assignments specified in the driver
@@ -441,10 +529,16 @@ pub unsafe fn start() -> (
 
     nrf52_components::NrfClockComponent::new(&base_peripherals.clock).finalize(());
 
+    if let Some(timeout_ms) = WATCHDOG_TIMEOUT_MS {
+        base_peripherals.wdt.set_timeout_ms(timeout_ms);
+    }
+
     let scheduler = components::sched::round_robin::RoundRobinComponent::new(&*addr_of!(PROCESSES))
         .finalize(components::round_robin_component_static!(NUM_PROCS));
 
     let platform = Platform {
+        adc,
+        i2c_master,
         button,
         ble_radio,
         pconsole,
@@ -452,6 +546,7 @@ pub unsafe fn start() -> (
         led,
         gpio,
         rng,
+        crc,
         temp,
         alarm,
         analog_comparator,
@@ -462,9 +557,11 @@ pub unsafe fn start() -> (
         ),
         scheduler,
         systick: cortexm4::systick::SysTick::new_with_calibration(64000000),
+        watchdog: &base_peripherals.wdt,
     };
 
     let _ = platform.pconsole.start();
+    base_peripherals.adc.calibrate();
     debug!("Initialization complete. Entering main loop\r");
     debug!("{}", &*addr_of!(nrf52832::ficr::FICR_INSTANCE));
 
@@ -23,6 +23,8 @@ struct Expiration<T: Ticks> {
 #[derive(Copy, Clone)]
 pub struct AlarmData<T: Ticks> {
     expiration: Option<Expiration<T>>,
+    /// Period of a repeating alarm, which is re-armed each time it fires.
+    period: Option<T>,
 }
 
 const ALARM_CALLBACK_NUM: usize = 0;
@@ -30,7 +32,10 @@ const NUM_UPCALLS: u8 = 1;
 
 impl<T: Ticks> Default for AlarmData<T> {
     fn default() -> AlarmData<T> {
-        AlarmData { expiration: None }
+        AlarmData {
+            expiration: None,
+            period: None,
+        }
     }
 }
 
@@ -63,7 +68,9 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
     ///   [`Expiration`], should it be the earliest, and
     /// - `F`: a call-back function invoked when the [`Expiration`] has already
     ///   expired. The callback is porivded the expiration value and a reference
-    ///   to its user-data.
+    ///   to its user-data. If it re-arms the alarm, it returns the new
+    ///   [`Expiration`], which is then a candidate for "earliest" expiration in
+    ///   place of the expired one.
     ///
     /// Whether an [`Expiration`] has expired or not is determined with respect
     /// to `now`. If `now` is in `[exp.reference; exp.reference + exp.dt)`, it
@@ -74,31 +81,36 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
     /// empty iterator, or all [`Expiration`]s have expired.
     ///
     /// To stop iteration on any expired [`Expiration`], its callback can return
-    /// `Some(R)`. Then this function will return `Err(Expiration, UD, R)`.
+    /// `Err(R)`. Then this function will return `Err(Expiration, UD, R)`.
     /// This avoids consuming the entire iterator.
-    fn earliest_alarm<UD, R, F: FnOnce(Expiration<A::Ticks>, &UD) -> Option<R>>(
+    fn earliest_alarm<
+        UD,
+        R,
+        F: FnOnce(Expiration<A::Ticks>, &UD) -> Result<Option<Expiration<A::Ticks>>, R>,
+    >(
         now: A::Ticks,
         expirations: impl Iterator<Item = (Expiration<A::Ticks>, UD, F)>,
     ) -> Result<Option<(Expiration<A::Ticks>, UD)>, (Expiration<A::Ticks>, UD, R)> {
         let mut earliest: Option<(Expiration<A::Ticks>, UD)> = None;
 
-        for (exp, ud, expired_handler) in expirations {
-            let Expiration {
-                reference: exp_ref,
-                dt: exp_dt,
-            } = exp;
-
+        for (mut exp, ud, expired_handler) in expirations {
             // Pre-compute the absolute "end" time of this expiration (the
             // point at which it should fire):
-            let exp_end = exp_ref.wrapping_add(exp_dt);
+            let mut exp_end = exp.reference.wrapping_add(exp.dt);
 
             // If `now` is not within `[reference, reference + dt)`, this
             // alarm has expired. Call the expired handler. If it returns
-            // false, stop here.
-            if !now.within_range(exp_ref, exp_end) {
-                let expired_handler_res = expired_handler(exp, &ud);
-                if let Some(retval) = expired_handler_res {
-                    return Err((exp, ud, retval));
+            // an error, stop here.
+            if !now.within_range(exp.reference, exp_end) {
+                match expired_handler(exp, &ud) {
+                    Err(retval) => return Err((exp, ud, retval)),
+                    Ok(Some(rearmed)) => {
+                        // The alarm has been re-armed. Its next expiration
+                        // is the candidate now:
+                        exp = rearmed;
+                        exp_end = exp.reference.wrapping_add(exp.dt);
+                    }
+                    Ok(None) => {}
                 }
             }
 
@@ -161,25 +173,33 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
             // risking reentrancy here.
 
             // Enter the app's grant again:
-            let _ = self.app_alarms.enter(*process_id, |alarm_state, upcalls| {
-                // Reset this app's alarm:
-                alarm_state.expiration = None;
-
-                // Deliver the upcall:
-                upcalls
-                    .schedule_upcall(
-                        ALARM_CALLBACK_NUM,
-                        (
-                            now.into_u32_left_justified() as usize,
-                            expired.reference.wrapping_add(expired.dt).into_usize(),
-                            0,
-                        ),
-                    )
-                    .ok();
-            });
+            let rearmed = self
+                .app_alarms
+                .enter(*process_id, |alarm_state, upcalls| {
+                    // Reset this app's alarm, or re-arm it if it is repeating:
+                    alarm_state.expiration = alarm_state.period.map(|period| {
+                        Self::next_period(now, expired.reference.wrapping_add(expired.dt), period)
+                    });
+
+                    // Deliver the upcall:
+                    upcalls
+                        .schedule_upcall(
+                            ALARM_CALLBACK_NUM,
+                            (
+                                now.into_u32_left_justified() as usize,
+                                expired.reference.wrapping_add(expired.dt).into_usize(),
+                                0,
+                            ),
+                        )
+                        .ok();
+
+                    alarm_state.expiration
+                })
+                .unwrap_or(None);
 
-            // Proceed iteration across expirations:
-            None::<()>
+            // Proceed iteration across expirations, with the re-armed
+            // expiration of a repeating alarm as a candidate:
+            Ok::<_, ()>(rearmed)
         };
 
         // Compute the earliest alarm, and invoke the `expired_handler` for
@@ -220,6 +240,30 @@ impl<'a, A: Alarm<'a>> AlarmDriver<'a, A> {
         }
     }
 
+    /// Computes the next [`Expiration`] of a repeating alarm that was due at
+    /// `fired`.
+    ///
+    /// The next period starts at `fired` rather than `now`, so that the alarm
+    /// does not drift. If more than one period has passed since `fired`, the
+    /// periods that were missed are skipped instead of being fired in a burst.
+    fn next_period(now: A::Ticks, fired: A::Ticks, period: A::Ticks) -> Expiration<A::Ticks> {
+        let late = now.wrapping_sub(fired);
+        if late > A::Ticks::from(u32::MAX) {
+            // Only possible with timers wider than 32 bit. So many periods
+            // were missed that we simply start over from now:
+            return Expiration {
+                reference: now,
+                dt: period,
+            };
+        }
+
+        let missed = late.into_u32() / period.into_u32();
+        Expiration {
+            reference: fired.wrapping_add(A::Ticks::from(missed * period.into_u32())),
+            dt: period,
+        }
+    }
+
     fn rearm_u32_left_justified_expiration(
         now: A::Ticks,
         reference_u32: Option<u32>,
@@ -370,6 +414,8 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
     /// - `5`: Set an alarm to fire at a given clock value `time` relative to `now`
     /// - `6`: Set an alarm to fire at a given clock value `time` relative to a provided
     ///        reference point.
+    /// - `7`: Set a repeating alarm to fire every `period` clock ticks, starting
+    ///        at `now + period`. It is stopped with command `3`.
     fn command(
         &self,
         cmd_type: usize,
@@ -425,8 +471,9 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                                 (CommandReturn::failure(ErrorCode::ALREADY), false)
                             }
                             Some(_old_expiraton) => {
-                                // Clear the expiration:
+                                // Clear the expiration, and stop repeating:
                                 td.expiration = None;
+                                td.period = None;
 
                                 // Ask for the timer to be re-armed. We can't do
                                 // this here, as it would re-enter the grant
@@ -452,6 +499,7 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                         // timers.
                         //
                         // All of this is done in the following helper method:
+                        td.period = None;
                         let new_exp_left_justified = Self::rearm_u32_left_justified_expiration(
                             // Current time:
                             now,
@@ -475,6 +523,7 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                         // timers.
                         //
                         // All of this is done in the following helper method:
+                        td.period = None;
                         let new_exp_left_justified = Self::rearm_u32_left_justified_expiration(
                             // Current time:
                             now,
@@ -493,6 +542,35 @@ impl<'a, A: Alarm<'a>> SyscallDriver for AlarmDriver<'a, A> {
                         // the grant region:
                         (CommandReturn::success_u32(new_exp_left_justified), true)
                     }
+                    7 => {
+                        // Set repeating expiration.
+                        //
+                        // The first expiration is set relative to now, just
+                        // like command 5. Its (unshifted) `dt` is then used
+                        // as the period:
+                        if data == 0 {
+                            // Don't re-arm the timer:
+                            return (CommandReturn::failure(ErrorCode::INVAL), false);
+                        }
+                        let new_exp_left_justified = Self::rearm_u32_left_justified_expiration(
+                            // Current time:
+                            now,
+                            // No userspace-provided reference:
+                            None,
+                            // Left-justified period:
+                            data as u32,
+                            // Reference to the `Option<Expiration>`, also used
+                            // to update the counter of armed alarms:
+                            &mut td.expiration,
+                        );
+                        td.period = td.expiration.map(|exp| exp.dt);
+
+                        // Report success, with the left-justified time at which
+                        // the alarm will first fire. Also ask for the timer to
+                        // be re-armed. We can't do this here, as it would
+                        // re-enter the grant region:
+                        (CommandReturn::success_u32(new_exp_left_justified), true)
+                    }
 
                     // Unknown command:
                     //
@@ -586,7 +664,7 @@ mod test {
                 <[(
                     Expiration<kernel::hil::time::Ticks32>,
                     (),
-                    fn(_, &()) -> Option<()>
+                    fn(_, &()) -> Result<Option<Expiration<Ticks32>>, ()>
                 ); 0] as IntoIterator>::into_iter([])
             )
             .unwrap()
@@ -597,7 +675,7 @@ mod test {
     #[test]
     fn test_earliest_alarm_multiple_unexpired() {
         // Should never be called:
-        let exp_handler = |exp, id: &usize| -> Option<()> {
+        let exp_handler = |exp, id: &usize| -> Result<Option<Expiration<Ticks32>>, ()> {
             panic!("Alarm should not be expired: {:?}, id: {}", exp, id)
         };
 
@@ -648,11 +726,11 @@ mod test {
     fn test_earliest_alarm_multiple_expired() {
         let exp_list: [Cell<bool>; 7] = Default::default();
 
-        let exp_handler = |_exp, id: &usize| -> Option<()> {
+        let exp_handler = |_exp, id: &usize| -> Result<Option<Expiration<Ticks32>>, ()> {
             exp_list[*id].set(true);
 
             // Don't stop iterating on the first expired alarm:
-            None
+            Ok(None)
         };
 
         let (earliest, id) = AlarmDriver::<MockAlarm<Ticks32, Freq10MHz>>::earliest_alarm(
@@ -748,14 +826,14 @@ mod test {
     fn test_earliest_alarm_expired_stop() {
         let exp_list: [Cell<bool>; 4] = Default::default();
 
-        let exp_handler = |_exp, id: &usize| -> Option<&'static str> {
+        let exp_handler = |_exp, id: &usize| -> Result<Option<Expiration<Ticks32>>, &'static str> {
             exp_list[*id].set(true);
 
             // Stop iterating on id == 3
             if *id == 3 {
-                Some("stopped")
+                Err("stopped")
             } else {
-                None
+                Ok(None)
             }
         };
 
@@ -821,6 +899,104 @@ mod test {
         assert!(bool_exp_list == [false, true, false, true,]);
     }
 
+    #[test]
+    fn test_earliest_alarm_rearmed_repeating() {
+        // A repeating alarm with a period of 10 was due at 40, and a one-shot
+        // alarm is due at 100. The repeating alarm is handled at 42, the way
+        // `process_rearm_or_callback` does it:
+        let fired: [Cell<bool>; 2] = Default::default();
+        let periods: [Option<Ticks32>; 2] = [Some(10_u32.into()), None];
+
+        let exp_handler =
+            |exp: Expiration<Ticks32>, id: &usize| -> Result<Option<Expiration<Ticks32>>, ()> {
+                fired[*id].set(true);
+                Ok(periods[*id].map(|period| {
+                    AlarmDriver::<MockAlarm<Ticks32, Freq10MHz>>::next_period(
+                        42_u32.into(),
+                        exp.reference.wrapping_add(exp.dt),
+                        period,
+                    )
+                }))
+            };
+
+        let (earliest, id) = AlarmDriver::<MockAlarm<Ticks32, Freq10MHz>>::earliest_alarm(
+            // Now:
+            42_u32.into(),
+            // Expirations:
+            [
+                (
+                    // Expired at 40, should fire and be re-armed for 50:
+                    Expiration {
+                        reference: 30_u32.into(),
+                        dt: 10_u32.into(),
+                    },
+                    0,
+                    &exp_handler,
+                ),
+                (
+                    // Will expire at 100, should not fire:
+                    Expiration {
+                        reference: 20_u32.into(),
+                        dt: 80_u32.into(),
+                    },
+                    1,
+                    &exp_handler,
+                ),
+            ]
+            .into_iter(),
+        )
+        .unwrap()
+        .unwrap();
+
+        // The timer must be armed for the next period of the repeating alarm,
+        // not for the later one-shot alarm:
+        assert!(earliest.reference.into_u32() == 40);
+        assert!(earliest.dt.into_u32() == 10);
+        assert!(id == 0);
+        assert!(fired[0].get());
+        assert!(!fired[1].get());
+    }
+
+    #[test]
+    fn test_next_period_on_time() {
+        // Due at 100 with a period of 10, handled right away:
+        let exp = AlarmDriver::<MockAlarm<Ticks32, Freq10MHz>>::next_period(
+            100_u32.into(),
+            100_u32.into(),
+            10_u32.into(),
+        );
+
+        assert!(exp.reference.into_u32() == 100);
+        assert!(exp.dt.into_u32() == 10);
+    }
+
+    #[test]
+    fn test_next_period_skips_missed() {
+        // Due at 100 with a period of 10, but only handled at 135. The
+        // periods due at 110, 120 and 130 are skipped:
+        let exp = AlarmDriver::<MockAlarm<Ticks32, Freq10MHz>>::next_period(
+            135_u32.into(),
+            100_u32.into(),
+            10_u32.into(),
+        );
+
+        assert!(exp.reference.into_u32() == 130);
+        assert!(exp.dt.into_u32() == 10);
+    }
+
+    #[test]
+    fn test_next_period_wrapping() {
+        // Due just before the timer wraps, handled just after:
+        let exp = AlarmDriver::<MockAlarm<Ticks24, Freq10MHz>>::next_period(
+            5_u32.into(),
+            0x00FFFFF0_u32.into(),
+            0x10_u32.into(),
+        );
+
+        assert!(exp.reference.into_u32() == 0);
+        assert!(exp.dt.into_u32() == 0x10);
+    }
+
     #[test]
     fn test_rearm_24bit_left_justified_noref_basic() {
         let mut expiration = None;
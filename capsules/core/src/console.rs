@@ -40,6 +40,30 @@
 //! When the buffer has been written successfully, the buffer is released from
 //! the driver. Successive writes must call `allow` each time a buffer is to be
 //! written.
+//!
+//! Data Handling
+//! -------------
+//!
+//! The console passes bytes through unmodified in both directions. There is
+//! no line buffering, no echo, and no CR/LF translation, so processes can use
+//! it for binary protocols as well as for text. A receive completes when the
+//! requested number of bytes has arrived or it is aborted with command `3`,
+//! not at the end of a line. Line editing and echo, where wanted, are up to
+//! the process.
+//!
+//! Kernel debug output does not go through this capsule. The `DebugWriter`
+//! uses its own `UartDevice` and buffers on the UART mux, so its output is
+//! interleaved with the console's at the granularity of whole transmissions
+//! but never alters the bytes a process sends or receives.
+//!
+//! Baud Rate
+//! ---------
+//!
+//! If the board passes the UART to `set_uart_configure()`, usually the
+//! `MuxUart`, processes can change the baud rate of the UART with command `4`.
+//! This affects every user of the UART, including kernel debug output, so it
+//! is off by default. Boards that enable it should restrict the command to
+//! trusted processes with TBF command permissions.
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, GrantKernelData, UpcallCount};
 use kernel::hil::uart;
@@ -111,6 +135,7 @@ pub struct Console<'a> {
     tx_buffer: TakeCell<'static, [u8]>,
     rx_in_progress: OptionalCell<ProcessId>,
     rx_buffer: TakeCell<'static, [u8]>,
+    uart_configure: OptionalCell<&'a dyn uart::Configure>,
 }
 
 impl<'a> Console<'a> {
@@ -132,9 +157,30 @@ impl<'a> Console<'a> {
             tx_buffer: TakeCell::new(tx_buffer),
             rx_in_progress: OptionalCell::empty(),
             rx_buffer: TakeCell::new(rx_buffer),
+            uart_configure: OptionalCell::empty(),
         }
     }
 
+    /// Allows processes to change the baud rate of `uart_configure`.
+    pub fn set_uart_configure(&self, uart_configure: &'a dyn uart::Configure) {
+        self.uart_configure.set(uart_configure);
+    }
+
+    /// Changes the baud rate, keeping the 8N1 framing the console uses.
+    fn set_baud_rate(&self, baud_rate: usize) -> Result<(), ErrorCode> {
+        let baud_rate = u32::try_from(baud_rate).map_err(|_| ErrorCode::INVAL)?;
+        self.uart_configure
+            .map_or(Err(ErrorCode::NOSUPPORT), |uart_configure| {
+                uart_configure.configure(uart::Parameters {
+                    baud_rate,
+                    width: uart::Width::Eight,
+                    stop_bits: uart::StopBits::One,
+                    parity: uart::Parity::None,
+                    hw_flow_control: false,
+                })
+            })
+    }
+
     /// Internal helper function for setting up a new send transaction
     fn send_new(
         &self,
@@ -283,6 +329,9 @@ impl SyscallDriver for Console<'_> {
     ///        passed in `arg1`
     /// - `3`: Cancel any in progress receives and return (via callback)
     ///        what has been received so far.
+    /// - `4`: Set the baud rate of the UART to `arg1`. Transmissions in
+    ///        progress finish at the old baud rate. Returns `NOSUPPORT` if
+    ///        the board does not allow changing the baud rate.
     fn command(
         &self,
         cmd_num: usize,
@@ -310,6 +359,10 @@ impl SyscallDriver for Console<'_> {
                         let _ = self.uart.receive_abort();
                         Ok(())
                     }
+                    4 => {
+                        // Set baud rate
+                        self.set_baud_rate(arg1)
+                    }
                     _ => Err(ErrorCode::NOSUPPORT),
                 }
             })
@@ -57,6 +57,7 @@ pub enum NUM {
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
     Kv                    = 0x50003,
+    MonotonicCounter      = 0x50004,
 
     // Sensors
     Temperature           = 0x60000,
@@ -67,6 +68,7 @@ pub enum NUM {
     SoundPressure         = 0x60006,
     AirQuality            = 0x60007,
     Pressure              = 0x60008,
+    Orientation           = 0x60009,
 
     // Sensor ICs
     Tsl2561               = 0x70000,
@@ -76,6 +78,8 @@ pub enum NUM {
     Lsm303dlch            = 0x70006,
     Mlx90614              = 0x70007,
     Lsm6dsoxtr            = 0x70008,
+    Max30102              = 0x70009,
+    Vl53l0x               = 0x7000A,
 
     // Other ICs
     Ltc294x               = 0x80000,
@@ -93,5 +97,10 @@ pub enum NUM {
     KeyboardHid           = 0x90005,
     DateTime              = 0x90007,
     CycleCount            = 0x90008,
+    Signaler              = 0x90009,
+    TouchSlider           = 0x9000A,
+    RotaryEncoder         = 0x9000B,
+    RandomDelay           = 0x9000C,
+    DeviceInfo            = 0x9000D,
 }
 }
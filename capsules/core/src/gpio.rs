@@ -109,6 +109,36 @@ impl<'a, IP: gpio::InterruptPin<'a>> GPIO<'a, IP> {
         }
     }
 
+    fn configure_pin(&self, pin_num: u32, config: usize) -> CommandReturn {
+        let maybe_pin = self.pins[pin_num as usize];
+        if let Some(pin) = maybe_pin {
+            let floating_state = match config & 0xFF {
+                0 => gpio::FloatingState::PullNone,
+                1 => gpio::FloatingState::PullUp,
+                2 => gpio::FloatingState::PullDown,
+                _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+            };
+            let drive_strength = match (config >> 8) & 0xFF {
+                0 => gpio::DriveStrength::Standard,
+                1 => gpio::DriveStrength::High,
+                2 => gpio::DriveStrength::StandardOpenDrain,
+                3 => gpio::DriveStrength::HighOpenDrain,
+                _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+            };
+            // Set the drive strength first, so that the pin is left unchanged
+            // if the chip does not support it.
+            match pin.set_drive_strength(drive_strength) {
+                Ok(()) => {
+                    pin.set_floating_state(floating_state);
+                    CommandReturn::success()
+                }
+                Err(e) => CommandReturn::failure(e),
+            }
+        } else {
+            CommandReturn::failure(ErrorCode::NODEVICE)
+        }
+    }
+
     fn configure_interrupt(&self, pin_num: u32, config: usize) -> CommandReturn {
         let pins = self.pins;
         let index = pin_num as usize;
@@ -172,6 +202,11 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
     ///                   Set to `0` to interrupt on either edge.
     ///                   Set to `1` for rising edge.
     ///                   Set to `2` for falling edge.
+    ///   - `drive_config`: Output drive setting.
+    ///                   Set to `0` for standard drive.
+    ///                   Set to `1` for high drive.
+    ///                   Set to `2` for standard drive, open-drain.
+    ///                   Set to `3` for high drive, open-drain.
     ///
     /// ### `command_num`
     ///
@@ -186,6 +221,9 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
     /// - `8`: Disable interrupt on `pin`.
     /// - `9`: Disable `pin`.
     /// - `10`: Get number of GPIO ports supported.
+    /// - `11`: Configure `pin` with `pin_config` in 0x000000XX and
+    ///         `drive_config` in 0x0000XX00 of the second argument. Returns
+    ///         `NOSUPPORT` if the chip does not support `drive_config`.
     fn command(
         &self,
         command_num: usize,
@@ -332,6 +370,17 @@ impl<'a, IP: gpio::InterruptPin<'a>> SyscallDriver for GPIO<'a, IP> {
             // number of pins
             10 => CommandReturn::success_u32(pins.len() as u32),
 
+            // configure pull resistor and drive strength
+            11 => {
+                let config = data2;
+                if pin_index >= pins.len() {
+                    /* impossible pin */
+                    CommandReturn::failure(ErrorCode::INVAL)
+                } else {
+                    self.configure_pin(pin_index as u32, config)
+                }
+            }
+
             // default
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
@@ -82,7 +82,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
             mnode.map(|node| {
                 node.buffer.take().map(|buf| {
                     match node.operation.get() {
-                        Op::Write(len) => match self.i2c.write(node.addr, buf, len) {
+                        Op::Write(len) => match self.i2c.write(node.addr.get(), buf, len) {
                             Ok(()) => {}
                             Err((error, buffer)) => {
                                 node.buffer.replace(buffer);
@@ -90,7 +90,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
                                 node.mux.do_next_op_async();
                             }
                         },
-                        Op::Read(len) => match self.i2c.read(node.addr, buf, len) {
+                        Op::Read(len) => match self.i2c.read(node.addr.get(), buf, len) {
                             Ok(()) => {}
                             Err((error, buffer)) => {
                                 node.buffer.replace(buffer);
@@ -99,7 +99,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> MuxI2C<'a, I, S> {
                             }
                         },
                         Op::WriteRead(wlen, rlen) => {
-                            match self.i2c.write_read(node.addr, buf, wlen, rlen) {
+                            match self.i2c.write_read(node.addr.get(), buf, wlen, rlen) {
                                 Ok(()) => {}
                                 Err((error, buffer)) => {
                                     node.buffer.replace(buffer);
@@ -205,7 +205,7 @@ enum Op {
 
 pub struct I2CDevice<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a> = NoSMBus> {
     mux: &'a MuxI2C<'a, I, S>,
-    addr: u8,
+    addr: Cell<u8>,
     enabled: Cell<bool>,
     buffer: TakeCell<'static, [u8]>,
     operation: Cell<Op>,
@@ -217,7 +217,7 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CDevice<'a, I, S> {
     pub fn new(mux: &'a MuxI2C<'a, I, S>, addr: u8) -> I2CDevice<'a, I, S> {
         I2CDevice {
             mux,
-            addr,
+            addr: Cell::new(addr),
             enabled: Cell::new(false),
             buffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
@@ -230,6 +230,12 @@ impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CDevice<'a, I, S> {
         self.mux.i2c_devices.push_head(self);
         self.client.set(client);
     }
+
+    /// Changes the address of the device on the bus. This must only be
+    /// called while no operation is pending.
+    pub fn set_address(&self, addr: u8) {
+        self.addr.set(addr);
+    }
 }
 
 impl<'a, I: i2c::I2CMaster<'a>, S: i2c::SMBusMaster<'a>> I2CClient for I2CDevice<'a, I, S> {
@@ -3,6 +3,18 @@
 // Copyright Tock Contributors 2022.
 
 //! Virtualize a SPI master bus to enable multiple users of the SPI bus.
+//!
+//! Each `VirtualSpiMasterDevice` keeps its own chip select, clock polarity,
+//! clock phase and rate, and the mux applies them to the bus before each of
+//! the device's transfers, so devices using different SPI modes can share a
+//! bus.
+//!
+//! A device can keep its chip select asserted across several transfers with
+//! `hold_low()`, for example to send a command and then stream data from
+//! another buffer. The mux serves no other device until the device calls
+//! `release_low()` and its next transfer completes, which deasserts chip
+//! select. This relies on the underlying `SpiMaster` supporting `hold_low()`
+//! and `release_low()`.
 
 use core::cell::Cell;
 use kernel::collections::list::{List, ListLink, ListNode};
@@ -18,6 +30,8 @@ pub struct MuxSpiMaster<'a, Spi: hil::spi::SpiMaster<'a>> {
     spi: &'a Spi,
     devices: List<'a, VirtualSpiMasterDevice<'a, Spi>>,
     inflight: OptionalCell<&'a VirtualSpiMasterDevice<'a, Spi>>,
+    /// The device holding chip select asserted between its transfers.
+    holder: OptionalCell<&'a VirtualSpiMasterDevice<'a, Spi>>,
     deferred_call: DeferredCall,
 }
 
@@ -47,20 +61,27 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> MuxSpiMaster<'a, Spi> {
             spi,
             devices: List::new(),
             inflight: OptionalCell::empty(),
+            holder: OptionalCell::empty(),
             deferred_call: DeferredCall::new(),
         }
     }
 
     fn do_next_op(&self) {
         if self.inflight.is_none() {
-            let mnode = self
-                .devices
-                .iter()
-                .find(|node| node.operation.get() != Op::Idle);
+            // While a device holds chip select, only it can use the bus.
+            let mnode = self.devices.iter().find(|node| {
+                node.operation.get() != Op::Idle
+                    && self
+                        .holder
+                        .map_or(true, |holder| core::ptr::eq(holder, *node))
+            });
             mnode.map(|node| {
                 let configuration = node.configuration.get();
-                let cs = configuration.chip_select;
-                let _ = self.spi.specify_chip_select(cs);
+                let holding = self.holder.is_some();
+                if !holding {
+                    let cs = configuration.chip_select;
+                    let _ = self.spi.specify_chip_select(cs);
+                }
 
                 let op = node.operation.get();
                 // Need to set idle here in case callback changes state
@@ -70,6 +91,14 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> MuxSpiMaster<'a, Spi> {
                         // Only async operations want to block by setting
                         // the devices as inflight.
                         self.inflight.set(node);
+                        if node.hold_cs.get() && !holding {
+                            self.spi.hold_low();
+                            self.holder.set(node);
+                        } else if !node.hold_cs.get() && holding {
+                            // Deassert chip select after this last transfer.
+                            self.spi.release_low();
+                            self.holder.clear();
+                        }
                         node.txbuffer.take().map(|txbuffer| {
                             let rresult = self.spi.set_rate(configuration.rate);
                             let polresult = self.spi.set_polarity(configuration.polarity);
@@ -173,6 +202,8 @@ pub struct VirtualSpiMasterDevice<'a, Spi: hil::spi::SpiMaster<'a>> {
     txbuffer: TakeCell<'static, [u8]>,
     rxbuffer: TakeCell<'static, [u8]>,
     operation: Cell<Op>,
+    /// Keep chip select asserted after each transfer.
+    hold_cs: Cell<bool>,
     next: ListLink<'a, VirtualSpiMasterDevice<'a, Spi>>,
     client: OptionalCell<&'a dyn hil::spi::SpiMasterClient>,
 }
@@ -193,6 +224,7 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> VirtualSpiMasterDevice<'a, Spi> {
             txbuffer: TakeCell::empty(),
             rxbuffer: TakeCell::empty(),
             operation: Cell::new(Op::Idle),
+            hold_cs: Cell::new(false),
             next: ListLink::empty(),
             client: OptionalCell::empty(),
         }
@@ -202,6 +234,20 @@ impl<'a, Spi: hil::spi::SpiMaster<'a>> VirtualSpiMasterDevice<'a, Spi> {
     pub fn setup(&'a self) {
         self.mux.devices.push_head(self);
     }
+
+    /// Keep chip select asserted after this device's transfers, starting
+    /// with the next one. No other device can use the bus until chip select
+    /// is released.
+    pub fn hold_low(&self) {
+        self.hold_cs.set(true);
+    }
+
+    /// Deassert chip select after this device's next transfer, and let
+    /// other devices use the bus again once it completes. A device holding
+    /// chip select must issue that transfer to release the bus.
+    pub fn release_low(&self) {
+        self.hold_cs.set(false);
+    }
 }
 
 impl<'a, Spi: hil::spi::SpiMaster<'a>> hil::spi::SpiMasterClient
@@ -396,3 +442,251 @@ impl<'a, Spi: hil::spi::SpiSlave<'a>> hil::spi::SpiSlaveDevice<'a> for SpiSlaveD
         self.spi.get_phase()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use kernel::hil::spi::{ClockPhase, ClockPolarity, SpiMaster, SpiMasterDevice};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// Settings of the bus when a transfer was started.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Transfer {
+        chip_select: u8,
+        rate: u32,
+        polarity: ClockPolarity,
+        phase: ClockPhase,
+    }
+
+    struct MockSpi {
+        chip_select: Cell<u8>,
+        rate: Cell<u32>,
+        polarity: Cell<ClockPolarity>,
+        phase: Cell<ClockPhase>,
+        transfers: RefCell<Vec<Transfer>>,
+        pending: TakeCell<'static, [u8]>,
+        hold: Cell<bool>,
+        asserted: Cell<bool>,
+        deasserts: Cell<usize>,
+    }
+
+    impl MockSpi {
+        fn new() -> MockSpi {
+            MockSpi {
+                chip_select: Cell::new(0),
+                rate: Cell::new(0),
+                polarity: Cell::new(ClockPolarity::IdleLow),
+                phase: Cell::new(ClockPhase::SampleLeading),
+                transfers: RefCell::new(Vec::new()),
+                pending: TakeCell::empty(),
+                hold: Cell::new(false),
+                asserted: Cell::new(false),
+                deasserts: Cell::new(0),
+            }
+        }
+
+        fn deassert(&self) {
+            if self.asserted.take() {
+                self.deasserts.set(self.deasserts.get() + 1);
+            }
+        }
+    }
+
+    impl<'a> SpiMaster<'a> for MockSpi {
+        type ChipSelect = u8;
+
+        fn init(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn set_client(&self, _client: &'a dyn SpiMasterClient) {}
+        fn is_busy(&self) -> bool {
+            self.pending.is_some()
+        }
+        fn read_write_bytes(
+            &self,
+            write_buffer: &'static mut [u8],
+            read_buffer: Option<&'static mut [u8]>,
+            _len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8], Option<&'static mut [u8]>)> {
+            if self.pending.is_some() {
+                return Err((ErrorCode::BUSY, write_buffer, read_buffer));
+            }
+            self.transfers.borrow_mut().push(Transfer {
+                chip_select: self.chip_select.get(),
+                rate: self.rate.get(),
+                polarity: self.polarity.get(),
+                phase: self.phase.get(),
+            });
+            self.pending.replace(write_buffer);
+            self.asserted.set(true);
+            Ok(())
+        }
+        fn write_byte(&self, _val: u8) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+        fn read_byte(&self) -> Result<u8, ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+        fn read_write_byte(&self, _val: u8) -> Result<u8, ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+        fn specify_chip_select(&self, cs: u8) -> Result<(), ErrorCode> {
+            self.deassert();
+            self.chip_select.set(cs);
+            Ok(())
+        }
+        fn set_rate(&self, rate: u32) -> Result<u32, ErrorCode> {
+            self.rate.set(rate);
+            Ok(rate)
+        }
+        fn get_rate(&self) -> u32 {
+            self.rate.get()
+        }
+        fn set_polarity(&self, polarity: ClockPolarity) -> Result<(), ErrorCode> {
+            self.polarity.set(polarity);
+            Ok(())
+        }
+        fn get_polarity(&self) -> ClockPolarity {
+            self.polarity.get()
+        }
+        fn set_phase(&self, phase: ClockPhase) -> Result<(), ErrorCode> {
+            self.phase.set(phase);
+            Ok(())
+        }
+        fn get_phase(&self) -> ClockPhase {
+            self.phase.get()
+        }
+        fn hold_low(&self) {
+            self.hold.set(true);
+        }
+        fn release_low(&self) {
+            self.hold.set(false);
+        }
+    }
+
+    fn buffer() -> &'static mut [u8] {
+        Box::leak(Box::new([0; 4]))
+    }
+
+    /// Completes the transfer in progress on the bus, if any.
+    fn complete(spi: &MockSpi, mux: &MuxSpiMaster<MockSpi>) -> bool {
+        spi.pending
+            .take()
+            .map(|write_buffer| {
+                if !spi.hold.get() {
+                    spi.deassert();
+                }
+                mux.read_write_done(write_buffer, None, 4, Ok(()))
+            })
+            .is_some()
+    }
+
+    #[test]
+    fn devices_keep_their_configuration() {
+        let spi = MockSpi::new();
+        let mux = MuxSpiMaster::new(&spi);
+        let flash = VirtualSpiMasterDevice::new(&mux, 0);
+        flash.setup();
+        let sensor = VirtualSpiMasterDevice::new(&mux, 1);
+        sensor.setup();
+
+        let flash_transfer = Transfer {
+            chip_select: 0,
+            rate: 8_000_000,
+            polarity: ClockPolarity::IdleLow,
+            phase: ClockPhase::SampleLeading,
+        };
+        let sensor_transfer = Transfer {
+            chip_select: 1,
+            rate: 1_000_000,
+            polarity: ClockPolarity::IdleHigh,
+            phase: ClockPhase::SampleTrailing,
+        };
+        assert_eq!(
+            flash.configure(
+                flash_transfer.polarity,
+                flash_transfer.phase,
+                flash_transfer.rate
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            sensor.configure(
+                sensor_transfer.polarity,
+                sensor_transfer.phase,
+                sensor_transfer.rate
+            ),
+            Ok(())
+        );
+
+        // Alternating transfers, one at a time.
+        for _ in 0..2 {
+            assert!(flash.read_write_bytes(buffer(), None, 4).is_ok());
+            assert!(complete(&spi, &mux));
+            assert!(sensor.read_write_bytes(buffer(), None, 4).is_ok());
+            assert!(complete(&spi, &mux));
+        }
+
+        // A transfer queued behind the other device's transfer.
+        assert!(sensor.read_write_bytes(buffer(), None, 4).is_ok());
+        assert!(flash.read_write_bytes(buffer(), None, 4).is_ok());
+        assert!(complete(&spi, &mux));
+        assert!(complete(&spi, &mux));
+        assert!(!complete(&spi, &mux));
+
+        assert_eq!(
+            *spi.transfers.borrow(),
+            [
+                flash_transfer,
+                sensor_transfer,
+                flash_transfer,
+                sensor_transfer,
+                sensor_transfer,
+                flash_transfer,
+            ]
+        );
+    }
+
+    #[test]
+    fn chip_select_held_across_transfers() {
+        let spi = MockSpi::new();
+        let mux = MuxSpiMaster::new(&spi);
+        let flash = VirtualSpiMasterDevice::new(&mux, 0);
+        flash.setup();
+        let sensor = VirtualSpiMasterDevice::new(&mux, 1);
+        sensor.setup();
+
+        // The command phase, with the sensor's transfer queued behind it.
+        flash.hold_low();
+        assert!(flash.read_write_bytes(buffer(), None, 4).is_ok());
+        assert!(sensor.read_write_bytes(buffer(), None, 4).is_ok());
+        assert!(complete(&spi, &mux));
+
+        // Chip select stays asserted and the sensor has to wait.
+        assert!(spi.pending.is_none());
+        assert!(spi.asserted.get());
+        assert_eq!(spi.deasserts.get(), 0);
+
+        // The data phase releases chip select once it completes.
+        flash.release_low();
+        assert!(flash.read_write_bytes(buffer(), None, 4).is_ok());
+        assert!(spi.asserted.get());
+        assert!(complete(&spi, &mux));
+        assert_eq!(spi.deasserts.get(), 1);
+
+        // Then the sensor gets the bus.
+        assert!(complete(&spi, &mux));
+        assert_eq!(spi.deasserts.get(), 2);
+        let chip_selects: Vec<u8> = spi
+            .transfers
+            .borrow()
+            .iter()
+            .map(|transfer| transfer.chip_select)
+            .collect();
+        assert_eq!(chip_selects, [0, 0, 1]);
+    }
+}
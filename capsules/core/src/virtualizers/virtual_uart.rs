@@ -58,7 +58,10 @@ pub const RX_BUF_LEN: usize = 64;
 
 pub struct MuxUart<'a> {
     uart: &'a dyn uart::Uart<'a>,
-    speed: u32,
+    params: Cell<uart::Parameters>,
+    /// Parameters requested while a transmission was in flight, which are
+    /// applied once it completes.
+    pending_params: Cell<Option<uart::Parameters>>,
     devices: List<'a, UartDevice<'a>>,
     inflight: OptionalCell<&'a UartDevice<'a>>,
     buffer: TakeCell<'static, [u8]>,
@@ -73,6 +76,11 @@ impl<'a> uart::TransmitClient for MuxUart<'a> {
         tx_len: usize,
         rcode: Result<(), ErrorCode>,
     ) {
+        if let Some(params) = self.pending_params.take() {
+            if self.uart.configure(params).is_ok() {
+                self.params.set(params);
+            }
+        }
         self.inflight.map(move |device| {
             self.inflight.clear();
             device.transmitted_buffer(tx_buffer, tx_len, rcode);
@@ -214,7 +222,14 @@ impl<'a> MuxUart<'a> {
     pub fn new(uart: &'a dyn uart::Uart<'a>, buffer: &'static mut [u8], speed: u32) -> MuxUart<'a> {
         MuxUart {
             uart,
-            speed,
+            params: Cell::new(uart::Parameters {
+                baud_rate: speed,
+                width: uart::Width::Eight,
+                stop_bits: uart::StopBits::One,
+                parity: uart::Parity::None,
+                hw_flow_control: false,
+            }),
+            pending_params: Cell::new(None),
             devices: List::new(),
             inflight: OptionalCell::empty(),
             buffer: TakeCell::new(buffer),
@@ -224,13 +239,7 @@ impl<'a> MuxUart<'a> {
     }
 
     pub fn initialize(&self) {
-        let _ = self.uart.configure(uart::Parameters {
-            baud_rate: self.speed,
-            width: uart::Width::Eight,
-            stop_bits: uart::StopBits::One,
-            parity: uart::Parity::None,
-            hw_flow_control: false,
-        });
+        let _ = self.uart.configure(self.params.get());
     }
 
     fn do_next_op(&self) {
@@ -313,6 +322,28 @@ impl<'a> MuxUart<'a> {
     }
 }
 
+/// Reconfigures the shared UART, for example to change its baud rate.
+///
+/// If a transmission is in flight, the new parameters are applied once it
+/// completes so that its characters are not garbled. If the UART then
+/// rejects them, for example because it does not support the baud rate, it
+/// keeps its old parameters and the error is dropped.
+impl uart::Configure for MuxUart<'_> {
+    fn configure(&self, params: uart::Parameters) -> Result<(), ErrorCode> {
+        if params.baud_rate == 0 {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.inflight.is_some() {
+            self.pending_params.set(Some(params));
+            Ok(())
+        } else {
+            self.uart.configure(params)?;
+            self.params.set(params);
+            Ok(())
+        }
+    }
+}
+
 impl DeferredCallClient for MuxUart<'_> {
     fn handle_deferred_call(&self) {
         self.do_next_op();
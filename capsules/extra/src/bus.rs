@@ -51,12 +51,33 @@ impl BusWidth {
         match self {
             BusWidth::Bits8 => 1,
             BusWidth::Bits16BE | BusWidth::Bits16LE => 2,
-            BusWidth::Bits32BE | BusWidth::Bits32LE => 3,
-            BusWidth::Bits64BE | BusWidth::Bits64LE => 4,
+            BusWidth::Bits32BE | BusWidth::Bits32LE => 4,
+            BusWidth::Bits64BE | BusWidth::Bits64LE => 8,
+        }
+    }
+
+    /// Whether data items of this width are in a different byte order on the
+    /// bus than in memory.
+    fn differs_from_native(&self) -> bool {
+        match self {
+            BusWidth::Bits8 => false,
+            BusWidth::Bits16LE | BusWidth::Bits32LE | BusWidth::Bits64LE => {
+                cfg!(target_endian = "big")
+            }
+            BusWidth::Bits16BE | BusWidth::Bits32BE | BusWidth::Bits64BE => {
+                cfg!(target_endian = "little")
+            }
         }
     }
 }
 
+/// Reverses the bytes of each `width` sized data item in `buffer`.
+fn swap_items(buffer: &mut [u8], width: usize) {
+    for item in buffer.chunks_exact_mut(width) {
+        item.reverse();
+    }
+}
+
 pub trait Bus<'a> {
     /// Set the address to write to
     ///
@@ -86,13 +107,23 @@ pub trait Bus<'a> {
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])>;
 
+    /// Wait until all previous operations have left the bus
+    ///
+    /// Buses that buffer data return Ok(()) and call command_complete without
+    /// a buffer once the buffer is empty. Buses that complete one operation
+    /// at a time, like SPI and I2C, have nothing to wait for and return
+    /// ALREADY. Returns BUSY if an operation is still in progress.
+    fn flush(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::ALREADY)
+    }
+
     fn set_client(&self, client: &'a dyn Client);
 }
 
 pub trait Client {
-    /// Called when set_addr, write or read are complete
+    /// Called when set_addr, write, read or flush are complete
     ///
-    /// set_address does not return a buffer
+    /// set_address and flush do not return a buffer
     /// write and read return a buffer
     /// len should be set to the number of data elements written
     fn command_complete(
@@ -109,6 +140,7 @@ enum BusStatus {
     SetAddress,
     Write,
     Read,
+    Flush,
 }
 
 /*********** SPI ************/
@@ -117,6 +149,8 @@ pub struct SpiMasterBus<'a, S: SpiMasterDevice<'a>> {
     spi: &'a S,
     read_write_buffer: OptionalCell<&'static mut [u8]>,
     bus_width: Cell<usize>,
+    native_endian: Cell<bool>,
+    swap: Cell<bool>,
     client: OptionalCell<&'a dyn Client>,
     addr_buffer: OptionalCell<&'static mut [u8]>,
     status: Cell<BusStatus>,
@@ -128,12 +162,24 @@ impl<'a, S: SpiMasterDevice<'a>> SpiMasterBus<'a, S> {
             spi,
             read_write_buffer: OptionalCell::empty(),
             bus_width: Cell::new(1),
+            native_endian: Cell::new(false),
+            swap: Cell::new(false),
             client: OptionalCell::empty(),
             addr_buffer: OptionalCell::new(addr_buffer),
             status: Cell::new(BusStatus::Idle),
         }
     }
 
+    /// By default buffers are sent and received as is, so they have to hold
+    /// data items in the byte order of the requested `BusWidth`. When
+    /// `native_endian` is set, buffers hold data items in the native byte
+    /// order instead, and the bus reorders their bytes in place where the
+    /// requested `BusWidth` differs. The buffer returned to the client is in
+    /// the native byte order.
+    pub fn set_native_endian(&self, native_endian: bool) {
+        self.native_endian.set(native_endian);
+    }
+
     pub fn set_read_write_buffer(&self, buffer: &'static mut [u8]) {
         self.read_write_buffer.replace(buffer);
     }
@@ -176,13 +222,20 @@ impl<'a, S: SpiMasterDevice<'a>> Bus<'a> for SpiMasterBus<'a, S> {
         buffer: &'static mut [u8],
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
-        // endianess does not matter as the buffer is sent as is
         let bytes = data_width.width_in_bytes();
         self.bus_width.set(bytes);
+        self.swap
+            .set(self.native_endian.get() && data_width.differs_from_native());
         if buffer.len() >= len * bytes {
+            if self.swap.get() {
+                swap_items(&mut buffer[..len * bytes], bytes);
+            }
             self.status.set(BusStatus::Write);
             if let Err((error, buffer, _)) = self.spi.read_write_bytes(buffer, None, len * bytes) {
                 self.status.set(BusStatus::Idle);
+                if self.swap.get() {
+                    swap_items(&mut buffer[..len * bytes], bytes);
+                }
                 Err((error, buffer))
             } else {
                 Ok(())
@@ -198,9 +251,10 @@ impl<'a, S: SpiMasterDevice<'a>> Bus<'a> for SpiMasterBus<'a, S> {
         buffer: &'static mut [u8],
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
-        // endianess does not matter as the buffer is read as is
         let bytes = data_width.width_in_bytes();
         self.bus_width.set(bytes);
+        self.swap
+            .set(self.native_endian.get() && data_width.differs_from_native());
         self.read_write_buffer.take().map_or_else(
             || panic!("bus::read: spi did not return the read write buffer"),
             move |write_buffer| {
@@ -251,6 +305,11 @@ impl<'a, S: SpiMasterDevice<'a>> SpiMasterClient for SpiMasterBus<'a, S> {
                     self.read_write_buffer.replace(buffer);
                     buffer = buf;
                 }
+                if self.swap.get() {
+                    // Restore the written data, or convert the read data, to
+                    // the native byte order.
+                    swap_items(&mut buffer[..len], self.bus_width.get());
+                }
                 self.client.map(move |client| {
                     client.command_complete(Some(buffer), len / self.bus_width.get(), status)
                 });
@@ -410,7 +469,10 @@ impl<'a, B: Bus8080<'static>> Bus8080Bus<'a, B> {
 impl<'a, B: Bus8080<'static>> Bus<'a> for Bus8080Bus<'a, B> {
     fn set_addr(&self, addr_width: BusWidth, addr: usize) -> Result<(), ErrorCode> {
         if let Some(bus_width) = Self::to_bus8080_width(addr_width) {
-            self.bus.set_addr(bus_width, addr)
+            self.status.set(BusStatus::SetAddress);
+            self.bus
+                .set_addr(bus_width, addr)
+                .inspect_err(|_| self.status.set(BusStatus::Idle))
         } else {
             Err(ErrorCode::INVAL)
         }
@@ -423,7 +485,10 @@ impl<'a, B: Bus8080<'static>> Bus<'a> for Bus8080Bus<'a, B> {
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
         if let Some(bus_width) = Self::to_bus8080_width(data_width) {
-            self.bus.write(bus_width, buffer, len)
+            self.status.set(BusStatus::Write);
+            self.bus
+                .write(bus_width, buffer, len)
+                .inspect_err(|_| self.status.set(BusStatus::Idle))
         } else {
             Err((ErrorCode::INVAL, buffer))
         }
@@ -436,12 +501,25 @@ impl<'a, B: Bus8080<'static>> Bus<'a> for Bus8080Bus<'a, B> {
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])> {
         if let Some(bus_width) = Self::to_bus8080_width(data_width) {
-            self.bus.read(bus_width, buffer, len)
+            self.status.set(BusStatus::Read);
+            self.bus
+                .read(bus_width, buffer, len)
+                .inspect_err(|_| self.status.set(BusStatus::Idle))
         } else {
             Err((ErrorCode::INVAL, buffer))
         }
     }
 
+    fn flush(&self) -> Result<(), ErrorCode> {
+        if !matches!(self.status.get(), BusStatus::Idle) {
+            return Err(ErrorCode::BUSY);
+        }
+        self.status.set(BusStatus::Flush);
+        self.bus
+            .flush()
+            .inspect_err(|_| self.status.set(BusStatus::Idle))
+    }
+
     fn set_client(&self, client: &'a dyn Client) {
         self.client.replace(client);
     }
@@ -454,9 +532,248 @@ impl<'a, B: Bus8080<'static>> bus8080::Client for Bus8080Bus<'a, B> {
         len: usize,
         status: Result<(), ErrorCode>,
     ) {
+        // The bus is idle again before the client is told, so that it can
+        // start the next command from the callback.
         self.status.set(BusStatus::Idle);
         self.client.map(|client| {
             client.command_complete(buffer, len, status);
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    type Transfer = (&'static mut [u8], Option<&'static mut [u8]>, usize);
+
+    /// Records the bytes sent, and answers reads with `response`.
+    #[derive(Default)]
+    struct MockSpi {
+        sent: RefCell<Vec<u8>>,
+        response: Vec<u8>,
+        pending: Cell<Option<Transfer>>,
+    }
+
+    impl MockSpi {
+        fn complete(&self, bus: &SpiMasterBus<'static, MockSpi>) {
+            let (write, read, len) = self.pending.take().unwrap();
+            bus.read_write_done(write, read, len, Ok(()));
+        }
+    }
+
+    impl<'a> SpiMasterDevice<'a> for MockSpi {
+        fn set_client(&self, _client: &'a dyn SpiMasterClient) {}
+        fn configure(&self, _: ClockPolarity, _: ClockPhase, _: u32) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn read_write_bytes(
+            &self,
+            write_buffer: &'static mut [u8],
+            mut read_buffer: Option<&'static mut [u8]>,
+            len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8], Option<&'static mut [u8]>)> {
+            self.sent
+                .borrow_mut()
+                .extend_from_slice(&write_buffer[..len]);
+            if let Some(read_buffer) = read_buffer.as_mut() {
+                read_buffer[..len].copy_from_slice(&self.response[..len]);
+            }
+            self.pending.set(Some((write_buffer, read_buffer, len)));
+            Ok(())
+        }
+        fn set_rate(&self, _rate: u32) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn get_rate(&self) -> u32 {
+            0
+        }
+        fn set_polarity(&self, _polarity: ClockPolarity) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn get_polarity(&self) -> ClockPolarity {
+            ClockPolarity::IdleLow
+        }
+        fn set_phase(&self, _phase: ClockPhase) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn get_phase(&self) -> ClockPhase {
+            ClockPhase::SampleLeading
+        }
+    }
+
+    /// Only accepts addresses that fit in 8 bits.
+    struct MockBus8080;
+
+    impl Bus8080<'static> for MockBus8080 {
+        fn set_addr(&self, _addr_width: bus8080::BusWidth, addr: usize) -> Result<(), ErrorCode> {
+            if addr > 0xFF {
+                Err(ErrorCode::INVAL)
+            } else {
+                Ok(())
+            }
+        }
+        fn write(
+            &self,
+            _data_width: bus8080::BusWidth,
+            _buffer: &'static mut [u8],
+            _len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            Ok(())
+        }
+        fn read(
+            &self,
+            _data_width: bus8080::BusWidth,
+            _buffer: &'static mut [u8],
+            _len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            Ok(())
+        }
+        fn flush(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn set_client(&self, _client: &'static dyn bus8080::Client) {}
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        buffer: Cell<Option<&'static mut [u8]>>,
+        len: Cell<usize>,
+        status: Cell<Option<Result<(), ErrorCode>>>,
+    }
+
+    impl Client for MockClient {
+        fn command_complete(
+            &self,
+            buffer: Option<&'static mut [u8]>,
+            len: usize,
+            status: Result<(), ErrorCode>,
+        ) {
+            self.buffer.set(buffer);
+            self.len.set(len);
+            self.status.set(Some(status));
+        }
+    }
+
+    fn setup(
+        native_endian: bool,
+        response: &[u8],
+    ) -> (
+        &'static MockSpi,
+        &'static MockClient,
+        &'static SpiMasterBus<'static, MockSpi>,
+    ) {
+        let spi: &MockSpi = Box::leak(Box::new(MockSpi {
+            response: response.to_vec(),
+            ..Default::default()
+        }));
+        let client: &MockClient = Box::leak(Box::default());
+        let bus = Box::leak(Box::new(SpiMasterBus::new(
+            spi,
+            Box::leak(Box::new([0; 1])),
+        )));
+        bus.set_read_write_buffer(Box::leak(Box::new([0; 16])));
+        bus.set_native_endian(native_endian);
+        bus.set_client(client);
+        (spi, client, bus)
+    }
+
+    /// Writes two 16-bit items holding `0x1234` and `0x5678` in native byte
+    /// order and returns the bytes sent on the bus.
+    fn write_u16(native_endian: bool, data_width: BusWidth) -> Vec<u8> {
+        let (spi, client, bus) = setup(native_endian, &[]);
+        let buffer = Box::leak(Box::new([0; 4]));
+        buffer[..2].copy_from_slice(&0x1234u16.to_ne_bytes());
+        buffer[2..].copy_from_slice(&0x5678u16.to_ne_bytes());
+        let original = *buffer;
+
+        assert!(bus.write(data_width, buffer, 2).is_ok());
+        spi.complete(bus);
+
+        // The client gets its data back unchanged.
+        assert_eq!(client.len.get(), 2);
+        assert_eq!(client.buffer.take().unwrap(), &original);
+        spi.sent.take()
+    }
+
+    #[test]
+    fn write_native_endian() {
+        assert_eq!(
+            write_u16(true, BusWidth::Bits16BE),
+            [0x12, 0x34, 0x56, 0x78]
+        );
+        assert_eq!(
+            write_u16(true, BusWidth::Bits16LE),
+            [0x34, 0x12, 0x78, 0x56]
+        );
+    }
+
+    #[test]
+    fn write_as_is() {
+        let mut native = Vec::new();
+        native.extend_from_slice(&0x1234u16.to_ne_bytes());
+        native.extend_from_slice(&0x5678u16.to_ne_bytes());
+        assert_eq!(write_u16(false, BusWidth::Bits16BE), native);
+        assert_eq!(write_u16(false, BusWidth::Bits16LE), native);
+    }
+
+    #[test]
+    fn read_native_endian() {
+        let (spi, client, bus) = setup(true, &[0x12, 0x34, 0x56, 0x78]);
+
+        assert!(bus
+            .read(BusWidth::Bits32BE, Box::leak(Box::new([0; 8])), 1)
+            .is_ok());
+        spi.complete(bus);
+
+        assert_eq!(client.len.get(), 1);
+        assert_eq!(
+            client.buffer.take().unwrap()[..4],
+            0x12345678u32.to_ne_bytes()
+        );
+    }
+
+    #[test]
+    fn bus8080_set_addr_errors() {
+        let client: &MockClient = Box::leak(Box::default());
+        let bus = Bus8080Bus::new(&MockBus8080);
+        bus.set_client(client);
+
+        // A rejected address is reported, and leaves the bus idle.
+        assert_eq!(
+            bus.set_addr(BusWidth::Bits16BE, 0x1234),
+            Err(ErrorCode::INVAL)
+        );
+        assert!(matches!(bus.status.get(), BusStatus::Idle));
+
+        assert_eq!(bus.set_addr(BusWidth::Bits8, 0x2A), Ok(()));
+        assert!(matches!(bus.status.get(), BusStatus::SetAddress));
+        bus8080::Client::command_complete(&bus, None, 0, Err(ErrorCode::FAIL));
+        assert_eq!(client.status.take(), Some(Err(ErrorCode::FAIL)));
+        assert!(matches!(bus.status.get(), BusStatus::Idle));
+    }
+
+    #[test]
+    fn flush() {
+        // SPI completes one operation at a time, so there is nothing to
+        // wait for.
+        let (_spi, _client, spi_bus) = setup(false, &[]);
+        assert_eq!(spi_bus.flush(), Err(ErrorCode::ALREADY));
+
+        let client: &MockClient = Box::leak(Box::default());
+        let bus = Bus8080Bus::new(&MockBus8080);
+        bus.set_client(client);
+
+        assert_eq!(bus.flush(), Ok(()));
+        assert_eq!(bus.flush(), Err(ErrorCode::BUSY));
+        bus8080::Client::command_complete(&bus, None, 0, Ok(()));
+        assert_eq!(client.status.take(), Some(Ok(())));
+        assert!(client.buffer.take().is_none());
+        assert!(matches!(bus.status.get(), BusStatus::Idle));
+    }
+}
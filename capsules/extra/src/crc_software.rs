@@ -0,0 +1,255 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Software implementation of the CRC HIL.
+//!
+//! This is for chips without a CRC unit, such as the nRF52. It computes the
+//! same values as the SAM4L CRCCU for all [`CrcAlgorithm`]s: input bytes are
+//! consumed from LSB to MSB, the CRC-32 and CRC-32C outputs are bit-reversed
+//! and then bit-inverted, and the CRC-16-CCITT output is not post-processed.
+//!
+//! The CRC is computed bit by bit on the CPU, in the call to
+//! [`Crc::input`]. Chunks should therefore be kept small enough to not block
+//! the kernel for too long; the [`crate::crc::CrcDriver`] capsule feeds data
+//! in chunks of its kernel buffer size. Callbacks are issued from a deferred
+//! call.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let crc_sw = components::crc::CrcSoftwareComponent::new()
+//!     .finalize(components::crc_software_component_static!());
+//! let crc = components::crc::CrcComponent::new(
+//!     board_kernel,
+//!     capsules_extra::crc::DRIVER_NUM,
+//!     crc_sw,
+//! )
+//! .finalize(components::crc_component_static!(
+//!     capsules_extra::crc_software::CrcSoftware<'static>
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::crc::{Client, Crc, CrcAlgorithm, CrcOutput};
+use kernel::utilities::cells::{MapCell, OptionalCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+const CRC32_POLY: u32 = 0x04C11DB7;
+const CRC32C_POLY: u32 = 0x1EDC6F41;
+const CRC16_CCITT_POLY: u16 = 0x1021;
+
+/// The value of the CRC register before any input is consumed.
+fn initial(algorithm: CrcAlgorithm) -> u32 {
+    match algorithm {
+        CrcAlgorithm::Crc32 | CrcAlgorithm::Crc32C => 0xFFFFFFFF,
+        CrcAlgorithm::Crc16CCITT => 0xFFFF,
+    }
+}
+
+/// Feeds `data` into the CRC register `crc`.
+fn update(algorithm: CrcAlgorithm, mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        // Bytes are consumed from LSB to MSB.
+        let byte = byte.reverse_bits();
+        crc = match algorithm {
+            CrcAlgorithm::Crc32 | CrcAlgorithm::Crc32C => {
+                let poly = match algorithm {
+                    CrcAlgorithm::Crc32C => CRC32C_POLY,
+                    _ => CRC32_POLY,
+                };
+                let mut crc = crc ^ ((byte as u32) << 24);
+                for _ in 0..8 {
+                    crc = if crc & 0x80000000 != 0 {
+                        (crc << 1) ^ poly
+                    } else {
+                        crc << 1
+                    };
+                }
+                crc
+            }
+            CrcAlgorithm::Crc16CCITT => {
+                let mut crc = (crc as u16) ^ ((byte as u16) << 8);
+                for _ in 0..8 {
+                    crc = if crc & 0x8000 != 0 {
+                        (crc << 1) ^ CRC16_CCITT_POLY
+                    } else {
+                        crc << 1
+                    };
+                }
+                crc as u32
+            }
+        };
+    }
+    crc
+}
+
+/// Applies the output post-processing of `algorithm` to the CRC register.
+fn output(algorithm: CrcAlgorithm, crc: u32) -> CrcOutput {
+    match algorithm {
+        CrcAlgorithm::Crc32 => CrcOutput::Crc32(!crc.reverse_bits()),
+        CrcAlgorithm::Crc32C => CrcOutput::Crc32C(!crc.reverse_bits()),
+        CrcAlgorithm::Crc16CCITT => CrcOutput::Crc16CCITT(crc as u16),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    /// A chunk has been consumed, `input_done` is pending.
+    Input,
+    /// `crc_done` is pending.
+    Compute,
+}
+
+pub struct CrcSoftware<'a> {
+    client: OptionalCell<&'a dyn Client>,
+    algorithm: OptionalCell<CrcAlgorithm>,
+    crc: Cell<u32>,
+    state: Cell<State>,
+    buffer: MapCell<SubSliceMut<'static, u8>>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> CrcSoftware<'a> {
+    pub fn new() -> CrcSoftware<'a> {
+        CrcSoftware {
+            client: OptionalCell::empty(),
+            algorithm: OptionalCell::empty(),
+            crc: Cell::new(0),
+            state: Cell::new(State::Idle),
+            buffer: MapCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+}
+
+impl<'a> Crc<'a> for CrcSoftware<'a> {
+    fn set_client(&self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+
+    fn algorithm_supported(&self, algorithm: CrcAlgorithm) -> bool {
+        match algorithm {
+            CrcAlgorithm::Crc32 => true,
+            CrcAlgorithm::Crc32C => true,
+            CrcAlgorithm::Crc16CCITT => true,
+        }
+    }
+
+    fn set_algorithm(&self, algorithm: CrcAlgorithm) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.algorithm.set(algorithm);
+        self.crc.set(initial(algorithm));
+        Ok(())
+    }
+
+    fn input(
+        &self,
+        mut data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        let algorithm = match self.algorithm.get() {
+            Some(algorithm) => algorithm,
+            None => return Err((ErrorCode::RESERVE, data)),
+        };
+        if self.state.get() != State::Idle {
+            return Err((ErrorCode::BUSY, data));
+        }
+
+        self.crc
+            .set(update(algorithm, self.crc.get(), data.as_slice()));
+
+        // The whole chunk has been consumed.
+        let len = data.len();
+        data.slice(len..);
+        self.buffer.put(data);
+        self.state.set(State::Input);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn compute(&self) -> Result<(), ErrorCode> {
+        if self.algorithm.is_none() {
+            return Err(ErrorCode::RESERVE);
+        }
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.state.set(State::Compute);
+        self.deferred_call.set();
+        Ok(())
+    }
+
+    fn disable(&self) {}
+}
+
+impl DeferredCallClient for CrcSoftware<'_> {
+    fn handle_deferred_call(&self) {
+        let state = self.state.get();
+        self.state.set(State::Idle);
+        match state {
+            State::Idle => {}
+            State::Input => {
+                if let Some(buffer) = self.buffer.take() {
+                    self.client
+                        .map(move |client| client.input_done(Ok(()), buffer));
+                }
+            }
+            State::Compute => {
+                if let Some(algorithm) = self.algorithm.get() {
+                    let result = output(algorithm, self.crc.get());
+                    // Start the next CRC from scratch.
+                    self.crc.set(initial(algorithm));
+                    self.client.map(|client| client.crc_done(Ok(result)));
+                }
+            }
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn crc(algorithm: CrcAlgorithm, chunks: &[&[u8]]) -> CrcOutput {
+        let crc = chunks.iter().fold(initial(algorithm), |crc, chunk| {
+            update(algorithm, crc, chunk)
+        });
+        output(algorithm, crc)
+    }
+
+    #[test]
+    fn check_values() {
+        match crc(CrcAlgorithm::Crc32, &[b"123456789"]) {
+            CrcOutput::Crc32(crc) => assert_eq!(crc, 0xCBF43926),
+            _ => panic!("wrong output type"),
+        }
+        match crc(CrcAlgorithm::Crc32C, &[b"123456789"]) {
+            CrcOutput::Crc32C(crc) => assert_eq!(crc, 0xE3069283),
+            _ => panic!("wrong output type"),
+        }
+        // The value the SAM4L CRCCU computes for this input.
+        match crc(CrcAlgorithm::Crc16CCITT, &[b"ABCDEFG"]) {
+            CrcOutput::Crc16CCITT(crc) => assert_eq!(crc, 0x1541),
+            _ => panic!("wrong output type"),
+        }
+    }
+
+    #[test]
+    fn chunked_input() {
+        match crc(CrcAlgorithm::Crc32, &[b"1234", b"", b"56789"]) {
+            CrcOutput::Crc32(crc) => assert_eq!(crc, 0xCBF43926),
+            _ => panic!("wrong output type"),
+        }
+    }
+}
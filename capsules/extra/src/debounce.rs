@@ -0,0 +1,252 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Debounces a GPIO input pin.
+//!
+//! Mechanical switches, buttons and card detect contacts bounce: a single
+//! press produces a train of edges over a few milliseconds. This capsule sits
+//! between an interrupt pin and a client and only reports a transition once
+//! the pin has been stable for a configurable window.
+//!
+//! Every edge restarts the window. When the window expires without another
+//! edge, the pin is read, and the client is notified if the level differs
+//! from the last one reported. A bounce that ends on the level it started
+//! from is therefore not reported at all.
+//!
+//! The pin must be configured as an input by the board, which
+//! `DebounceComponent` does.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let debounce = components::debounce::DebounceComponent::new(
+//!     &gpio_port[SD_DETECT_PIN],
+//!     mux_alarm,
+//!     500,
+//! )
+//! .finalize(components::debounce_component_static!(nrf52832::rtc::Rtc));
+//! debounce.set_client(client);
+//! debounce.enable();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::OptionalCell;
+
+/// Receives the debounced level of a pin.
+pub trait Client {
+    /// Called when the pin has settled on a new level.
+    fn changed(&self, value: bool);
+}
+
+pub struct Debounce<'a, A: Alarm<'a>> {
+    pin: &'a dyn gpio::Interrupt<'a>,
+    alarm: &'a A,
+    window_ms: u32,
+    value: Cell<bool>,
+    enabled: Cell<bool>,
+    client: OptionalCell<&'a dyn Client>,
+}
+
+impl<'a, A: Alarm<'a>> Debounce<'a, A> {
+    pub fn new(pin: &'a dyn gpio::Interrupt<'a>, alarm: &'a A, window_ms: u32) -> Self {
+        Debounce {
+            pin,
+            alarm,
+            window_ms,
+            value: Cell::new(false),
+            enabled: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+
+    /// Samples the pin and starts watching it for changes.
+    pub fn enable(&self) {
+        self.value.set(self.pin.read());
+        self.enabled.set(true);
+        self.pin.enable_interrupts(gpio::InterruptEdge::EitherEdge);
+    }
+
+    /// Stops watching the pin. A pending transition is dropped.
+    pub fn disable(&self) {
+        self.pin.disable_interrupts();
+        let _ = self.alarm.disarm();
+        self.enabled.set(false);
+    }
+
+    /// The last stable level of the pin, or its current level if it is not
+    /// being watched.
+    pub fn read(&self) -> bool {
+        if self.enabled.get() {
+            self.value.get()
+        } else {
+            self.pin.read()
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> gpio::Client for Debounce<'a, A> {
+    fn fired(&self) {
+        // Wait for the pin to be quiet for a whole window.
+        let window = self.alarm.ticks_from_ms(self.window_ms);
+        self.alarm.set_alarm(self.alarm.now(), window);
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for Debounce<'a, A> {
+    fn alarm(&self) {
+        let value = self.pin.read();
+        if value != self.value.get() {
+            self.value.set(value);
+            self.client.map(|client| client.changed(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use gpio::Client as _;
+    use kernel::hil::time::{Freq1KHz, Ticks, Ticks32, Time};
+    use kernel::ErrorCode;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct MockPin {
+        value: Cell<bool>,
+    }
+
+    impl gpio::Input for MockPin {
+        fn read(&self) -> bool {
+            self.value.get()
+        }
+    }
+
+    impl<'a> gpio::Interrupt<'a> for MockPin {
+        fn set_client(&self, _client: &'a dyn gpio::Client) {}
+        fn enable_interrupts(&self, _mode: gpio::InterruptEdge) {}
+        fn disable_interrupts(&self) {}
+        fn is_pending(&self) -> bool {
+            false
+        }
+    }
+
+    /// An alarm whose time only advances when the test calls `advance()`.
+    #[derive(Default)]
+    struct MockAlarm {
+        now: Cell<u32>,
+        expiry: Cell<Option<u32>>,
+    }
+
+    impl MockAlarm {
+        fn advance(&self, debounce: &Debounce<'_, Self>, ms: u32) {
+            for _ in 0..ms {
+                self.now.set(self.now.get() + 1);
+                if self.expiry.get() == Some(self.now.get()) {
+                    self.expiry.set(None);
+                    debounce.alarm();
+                }
+            }
+        }
+    }
+
+    impl Time for MockAlarm {
+        type Ticks = Ticks32;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get().into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+            self.expiry.set(Some(reference.wrapping_add(dt).into_u32()));
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            self.expiry.get().unwrap_or(0).into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.expiry.set(None);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.expiry.get().is_some()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            1.into()
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        events: RefCell<Vec<bool>>,
+    }
+
+    impl Client for MockClient {
+        fn changed(&self, value: bool) {
+            self.events.borrow_mut().push(value);
+        }
+    }
+
+    /// Toggles the pin every `period_ms` for `edges` edges.
+    fn bounce(pin: &MockPin, alarm: &MockAlarm, debounce: &Debounce<MockAlarm>, edges: usize) {
+        for _ in 0..edges {
+            pin.value.set(!pin.value.get());
+            debounce.fired();
+            alarm.advance(debounce, 2);
+        }
+    }
+
+    #[test]
+    fn bouncing_edge_train() {
+        let pin = MockPin::default();
+        let alarm = MockAlarm::default();
+        let client = MockClient::default();
+        let debounce = Debounce::new(&pin, &alarm, 10);
+        debounce.set_client(&client);
+
+        // Before it is enabled, the pin is read directly.
+        pin.value.set(true);
+        assert!(debounce.read());
+        pin.value.set(false);
+        assert!(!debounce.read());
+
+        debounce.enable();
+
+        // Press: an odd number of edges ends high.
+        bounce(&pin, &alarm, &debounce, 7);
+        assert!(client.events.borrow().is_empty());
+        alarm.advance(&debounce, 10);
+        assert_eq!(*client.events.borrow(), [true]);
+        assert!(debounce.read());
+
+        // A glitch that returns to the stable level is not reported.
+        bounce(&pin, &alarm, &debounce, 4);
+        alarm.advance(&debounce, 10);
+        assert_eq!(*client.events.borrow(), [true]);
+
+        // Release.
+        bounce(&pin, &alarm, &debounce, 5);
+        alarm.advance(&debounce, 10);
+        assert_eq!(*client.events.borrow(), [true, false]);
+        assert!(!debounce.read());
+    }
+}
@@ -0,0 +1,59 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Provides read-only device information to userspace.
+//!
+//! Apps can read the unique ID of the device, for example for provisioning
+//! or to derive per-device keys, and the sizes of its flash and RAM. The
+//! board reads these from the chip, for example from the FICR on nRF52
+//! chips, and passes them in. Nothing else from the chip is exposed, in
+//! particular no key material.
+
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::DeviceInfo as usize;
+
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+pub struct DeviceInfo {
+    device_id: u64,
+    flash_size: u32,
+    ram_size: u32,
+}
+
+impl DeviceInfo {
+    pub fn new(device_id: u64, flash_size: u32, ram_size: u32) -> DeviceInfo {
+        DeviceInfo {
+            device_id,
+            flash_size,
+            ram_size,
+        }
+    }
+}
+
+impl SyscallDriver for DeviceInfo {
+    /// Read device information.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: The lower 32 bits of the 64-bit device ID.
+    /// - `2`: The upper 32 bits of the 64-bit device ID.
+    /// - `3`: The flash size in bytes.
+    /// - `4`: The RAM size in bytes.
+    fn command(&self, command_num: usize, _: usize, _: usize, _: ProcessId) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.device_id as u32),
+            2 => CommandReturn::success_u32((self.device_id >> 32) as u32),
+            3 => CommandReturn::success_u32(self.flash_size),
+            4 => CommandReturn::success_u32(self.ram_size),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Continuous health tests for a hardware entropy source.
+//!
+//! Sits between a raw `Entropy32` source, such as a TRNG peripheral, and its
+//! client, and runs the two continuous health tests of NIST SP 800-90B
+//! (section 4.4) on the raw output:
+//!
+//! - The Repetition Count Test fails if a sample repeats too many times in a
+//!   row, which catches a source stuck at one value.
+//! - The Adaptive Proportion Test fails if, in a window of samples, the first
+//!   sample of the window occurs too often, which catches a source that has
+//!   become heavily biased.
+//!
+//! Each byte of the raw 32-bit words is one sample, and the cutoffs assume a
+//! conservative min-entropy of 4 bits per byte with a false positive rate of
+//! 2^-20. Raw words are held back until the window they belong to has passed
+//! both tests, and only then given to the client.
+//!
+//! A failure is permanent: the client gets a `FAIL` error, and every later
+//! `get()` returns `FAIL` until the chip is reset.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let trng = components::rng::EntropyHealthComponent::new(&base_peripherals.trng)
+//!     .finalize(components::entropy_health_component_static!(
+//!         nrf52832::trng::Trng
+//!     ));
+//! let rng = components::rng::RngComponent::new(board_kernel, capsules_core::rng::DRIVER_NUM, trng)
+//!     .finalize(components::rng_component_static!(
+//!         capsules_extra::entropy_health::EntropyHealth<'static, nrf52832::trng::Trng>
+//!     ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::entropy::{self, Client32, Continue, Entropy32};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Number of identical samples in a row that fails the Repetition Count Test.
+pub const REPETITION_CUTOFF: usize = 6;
+
+/// Number of samples in an Adaptive Proportion Test window.
+pub const WINDOW_SAMPLES: usize = 512;
+
+/// Number of occurrences of the first sample of a window, within the window,
+/// that fails the Adaptive Proportion Test.
+pub const PROPORTION_CUTOFF: usize = 62;
+
+const WINDOW_WORDS: usize = WINDOW_SAMPLES / 4;
+
+/// State of the two health tests.
+struct HealthTests {
+    last: Cell<u8>,
+    repetitions: Cell<usize>,
+    window_sample: Cell<u8>,
+    window_count: Cell<usize>,
+    window_index: Cell<usize>,
+}
+
+impl HealthTests {
+    fn new() -> Self {
+        HealthTests {
+            last: Cell::new(0),
+            repetitions: Cell::new(0),
+            window_sample: Cell::new(0),
+            window_count: Cell::new(0),
+            window_index: Cell::new(0),
+        }
+    }
+
+    /// Feeds one sample through both tests. Returns `false` if a test failed.
+    fn sample(&self, sample: u8) -> bool {
+        // Repetition Count Test
+        if self.repetitions.get() > 0 && sample == self.last.get() {
+            self.repetitions.set(self.repetitions.get() + 1);
+        } else {
+            self.last.set(sample);
+            self.repetitions.set(1);
+        }
+        if self.repetitions.get() >= REPETITION_CUTOFF {
+            return false;
+        }
+
+        // Adaptive Proportion Test
+        let index = self.window_index.get();
+        if index == 0 {
+            self.window_sample.set(sample);
+            self.window_count.set(1);
+        } else if sample == self.window_sample.get() {
+            self.window_count.set(self.window_count.get() + 1);
+        }
+        self.window_index.set((index + 1) % WINDOW_SAMPLES);
+        self.window_count.get() < PROPORTION_CUTOFF
+    }
+}
+
+pub struct EntropyHealth<'a, E: Entropy32<'a>> {
+    egen: &'a E,
+    client: OptionalCell<&'a dyn Client32>,
+    tests: HealthTests,
+    /// Raw words of the window currently being tested.
+    window: [Cell<u32>; WINDOW_WORDS],
+    window_len: Cell<usize>,
+    failed: Cell<bool>,
+}
+
+impl<'a, E: Entropy32<'a>> EntropyHealth<'a, E> {
+    pub fn new(egen: &'a E) -> Self {
+        Self {
+            egen,
+            client: OptionalCell::empty(),
+            tests: HealthTests::new(),
+            window: core::array::from_fn(|_| Cell::new(0)),
+            window_len: Cell::new(0),
+            failed: Cell::new(false),
+        }
+    }
+
+    /// Whether a health test has failed.
+    pub fn has_failed(&self) -> bool {
+        self.failed.get()
+    }
+}
+
+impl<'a, E: Entropy32<'a>> Entropy32<'a> for EntropyHealth<'a, E> {
+    fn get(&self) -> Result<(), ErrorCode> {
+        if self.failed.get() {
+            return Err(ErrorCode::FAIL);
+        }
+        self.egen.get()
+    }
+
+    fn cancel(&self) -> Result<(), ErrorCode> {
+        self.egen.cancel()
+    }
+
+    fn set_client(&'a self, client: &'a dyn Client32) {
+        self.egen.set_client(self);
+        self.client.set(client);
+    }
+}
+
+impl<'a, E: Entropy32<'a>> entropy::Client32 for EntropyHealth<'a, E> {
+    fn entropy_available(
+        &self,
+        entropy: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> Continue {
+        self.client.map_or(Continue::Done, |client| {
+            if error != Ok(()) {
+                return client.entropy_available(&mut core::iter::empty(), error);
+            }
+
+            for word in entropy {
+                if !word.to_le_bytes().iter().all(|&b| self.tests.sample(b)) {
+                    self.failed.set(true);
+                    client.entropy_available(&mut core::iter::empty(), Err(ErrorCode::FAIL));
+                    return Continue::Done;
+                }
+
+                let len = self.window_len.get();
+                self.window[len].set(word);
+                if len + 1 < WINDOW_WORDS {
+                    self.window_len.set(len + 1);
+                    continue;
+                }
+
+                // The window passed; hand it over. Words the client does not
+                // consume are dropped.
+                self.window_len.set(0);
+                let mut passed = self.window.iter().map(Cell::get);
+                if client.entropy_available(&mut passed, Ok(())) == Continue::Done {
+                    return Continue::Done;
+                }
+            }
+            Continue::More
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    struct MockEntropy;
+
+    impl<'a> Entropy32<'a> for MockEntropy {
+        fn get(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn cancel(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn set_client(&'a self, _client: &'a dyn Client32) {}
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        words: RefCell<Vec<u32>>,
+        errors: RefCell<Vec<ErrorCode>>,
+    }
+
+    impl Client32 for MockClient {
+        fn entropy_available(
+            &self,
+            entropy: &mut dyn Iterator<Item = u32>,
+            error: Result<(), ErrorCode>,
+        ) -> Continue {
+            if let Err(e) = error {
+                self.errors.borrow_mut().push(e);
+            }
+            self.words.borrow_mut().extend(entropy);
+            Continue::More
+        }
+    }
+
+    /// A xorshift generator standing in for a healthy source.
+    fn healthy(count: usize) -> impl Iterator<Item = u32> {
+        let mut x: u32 = 0x12345678;
+        (0..count).map(move |_| {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            x
+        })
+    }
+
+    #[test]
+    fn healthy_source() {
+        let egen = MockEntropy;
+        let client = MockClient::default();
+        let health = EntropyHealth::new(&egen);
+        health.client.set(&client);
+
+        // Nothing is released until a whole window has passed.
+        let mut raw = healthy(3 * WINDOW_WORDS / 2);
+        assert_eq!(health.entropy_available(&mut raw, Ok(())), Continue::More);
+        assert_eq!(
+            *client.words.borrow(),
+            healthy(WINDOW_WORDS).collect::<Vec<_>>()
+        );
+        assert!(client.errors.borrow().is_empty());
+        assert_eq!(health.get(), Ok(()));
+    }
+
+    #[test]
+    fn stuck_at_zero() {
+        let egen = MockEntropy;
+        let client = MockClient::default();
+        let health = EntropyHealth::new(&egen);
+        health.client.set(&client);
+
+        let mut raw = core::iter::repeat(0).take(WINDOW_WORDS);
+        assert_eq!(health.entropy_available(&mut raw, Ok(())), Continue::Done);
+        assert!(client.words.borrow().is_empty());
+        assert_eq!(*client.errors.borrow(), [ErrorCode::FAIL]);
+        assert!(health.has_failed());
+        assert_eq!(health.get(), Err(ErrorCode::FAIL));
+    }
+
+    #[test]
+    fn biased_source() {
+        let tests = HealthTests::new();
+        // Never repeats, but every other sample is the window's first one.
+        let passed = (0..WINDOW_SAMPLES)
+            .map(|i| if i % 2 == 0 { 0 } else { i as u8 | 1 })
+            .all(|sample| tests.sample(sample));
+        assert!(!passed);
+    }
+}
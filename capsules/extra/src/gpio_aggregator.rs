@@ -0,0 +1,266 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Fans the interrupts of a set of GPIO pins in to a single client.
+//!
+//! Boards with many inputs feeding one logical handler, such as the columns
+//! of a keypad matrix, would otherwise need one client per pin. This capsule
+//! registers itself as the client of every pin in the set and calls its own
+//! client with the index of the pin that fired and the level of that pin.
+//!
+//! Each pin interrupts on its own edge, chosen by the board. The pins are
+//! wrapped in `InterruptValueWrapper`s, and the aggregator sets the value of
+//! each wrapper to the index of its pin, so the index is known without
+//! polling the other pins.
+//!
+//! The pins must be configured as inputs by the board.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let aggregator = components::gpio_aggregator::GpioAggregatorComponent::new(
+//!     components::gpio_aggregator_component_helper!(
+//!         nrf52840::gpio::GPIOPin,
+//!         (&gpio_port[COLUMN_0], kernel::hil::gpio::InterruptEdge::FallingEdge),
+//!         (&gpio_port[COLUMN_1], kernel::hil::gpio::InterruptEdge::FallingEdge),
+//!     ),
+//! )
+//! .finalize(components::gpio_aggregator_component_static!());
+//! aggregator.set_client(keypad);
+//! aggregator.enable().unwrap();
+//! ```
+
+use kernel::hil::gpio;
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Receives the interrupts of all pins of a `GpioAggregator`.
+pub trait Client {
+    /// The pin at `index` fired, and now reads `level`.
+    fn pin_fired(&self, index: usize, level: bool);
+}
+
+pub struct GpioAggregator<'a> {
+    pins: &'a [(&'a dyn gpio::InterruptWithValue<'a>, gpio::InterruptEdge)],
+    client: OptionalCell<&'a dyn Client>,
+}
+
+impl<'a> GpioAggregator<'a> {
+    pub fn new(
+        pins: &'a [(&'a dyn gpio::InterruptWithValue<'a>, gpio::InterruptEdge)],
+    ) -> GpioAggregator<'a> {
+        for (index, (pin, _)) in pins.iter().enumerate() {
+            pin.set_value(index as u32);
+        }
+        GpioAggregator {
+            pins,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+
+    /// The number of pins in the set.
+    pub fn len(&self) -> usize {
+        self.pins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    /// Enables the interrupts of all pins. Returns the error of the last
+    /// pin that could not be enabled, after trying all of them.
+    pub fn enable(&self) -> Result<(), ErrorCode> {
+        let mut result = Ok(());
+        for (pin, edge) in self.pins.iter() {
+            if let Err(err) = pin.enable_interrupts(*edge) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    /// Disables the interrupts of all pins.
+    pub fn disable(&self) {
+        for (pin, _) in self.pins.iter() {
+            pin.disable_interrupts();
+        }
+    }
+
+    /// Enables the interrupt of the pin at `index` only.
+    pub fn enable_pin(&self, index: usize) -> Result<(), ErrorCode> {
+        let (pin, edge) = self.pins.get(index).ok_or(ErrorCode::INVAL)?;
+        pin.enable_interrupts(*edge)
+    }
+
+    /// Disables the interrupt of the pin at `index` only.
+    pub fn disable_pin(&self, index: usize) -> Result<(), ErrorCode> {
+        let (pin, _) = self.pins.get(index).ok_or(ErrorCode::INVAL)?;
+        pin.disable_interrupts();
+        Ok(())
+    }
+
+    /// Reads the level of the pin at `index`.
+    pub fn read(&self, index: usize) -> Result<bool, ErrorCode> {
+        self.pins
+            .get(index)
+            .map(|(pin, _)| pin.read())
+            .ok_or(ErrorCode::INVAL)
+    }
+}
+
+impl gpio::ClientWithValue for GpioAggregator<'_> {
+    fn fired(&self, value: u32) {
+        let index = value as usize;
+        if let Some((pin, _)) = self.pins.get(index) {
+            let level = pin.read();
+            self.client.map(|client| client.pin_fired(index, level));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::{Cell, RefCell};
+    use gpio::ClientWithValue as _;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct MockPin {
+        level: Cell<bool>,
+        value: Cell<u32>,
+        enabled: Cell<bool>,
+        client: OptionalCell<&'static dyn gpio::ClientWithValue>,
+    }
+
+    impl MockPin {
+        /// Sets the pin to `level` and interrupts if it is enabled.
+        fn fire(&self, level: bool) {
+            self.level.set(level);
+            if self.enabled.get() {
+                self.client.map(|client| client.fired(self.value.get()));
+            }
+        }
+    }
+
+    impl gpio::Input for MockPin {
+        fn read(&self) -> bool {
+            self.level.get()
+        }
+    }
+
+    impl gpio::InterruptWithValue<'static> for MockPin {
+        fn set_client(&self, client: &'static dyn gpio::ClientWithValue) {
+            self.client.set(client);
+        }
+        fn enable_interrupts(&self, _edge: gpio::InterruptEdge) -> Result<(), ErrorCode> {
+            self.enabled.set(true);
+            Ok(())
+        }
+        fn disable_interrupts(&self) {
+            self.enabled.set(false);
+        }
+        fn is_pending(&self) -> bool {
+            false
+        }
+        fn set_value(&self, value: u32) {
+            self.value.set(value);
+        }
+        fn value(&self) -> u32 {
+            self.value.get()
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        fired: RefCell<Vec<(usize, bool)>>,
+    }
+
+    impl Client for MockClient {
+        fn pin_fired(&self, index: usize, level: bool) {
+            self.fired.borrow_mut().push((index, level));
+        }
+    }
+
+    fn aggregator(
+        count: usize,
+    ) -> (
+        Vec<&'static MockPin>,
+        &'static GpioAggregator<'static>,
+        &'static MockClient,
+    ) {
+        let pins: Vec<&'static MockPin> = (0..count)
+            .map(|_| &*Box::leak(Box::new(MockPin::default())))
+            .collect();
+        let set: Vec<(
+            &'static dyn gpio::InterruptWithValue<'static>,
+            gpio::InterruptEdge,
+        )> = pins
+            .iter()
+            .map(|&pin| {
+                (
+                    pin as &'static dyn gpio::InterruptWithValue<'static>,
+                    gpio::InterruptEdge::EitherEdge,
+                )
+            })
+            .collect();
+        let aggregator = Box::leak(Box::new(GpioAggregator::new(Box::leak(
+            set.into_boxed_slice(),
+        ))));
+        for pin in pins.iter() {
+            gpio::InterruptWithValue::set_client(*pin, aggregator);
+        }
+        let client = Box::leak(Box::new(MockClient::default()));
+        aggregator.set_client(client);
+        (pins, aggregator, client)
+    }
+
+    #[test]
+    fn fires_from_multiple_pins() {
+        let (pins, aggregator, client) = aggregator(3);
+        assert_eq!(aggregator.enable(), Ok(()));
+        pins[2].fire(true);
+        pins[0].fire(true);
+        pins[2].fire(false);
+        pins[1].fire(true);
+        assert_eq!(
+            *client.fired.borrow(),
+            [(2, true), (0, true), (2, false), (1, true)]
+        );
+    }
+
+    #[test]
+    fn disabled_pin_does_not_fire() {
+        let (pins, aggregator, client) = aggregator(2);
+        assert_eq!(aggregator.enable(), Ok(()));
+        assert_eq!(aggregator.disable_pin(0), Ok(()));
+        pins[0].fire(true);
+        pins[1].fire(true);
+        assert_eq!(*client.fired.borrow(), [(1, true)]);
+
+        assert_eq!(aggregator.enable_pin(0), Ok(()));
+        pins[0].fire(false);
+        assert_eq!(*client.fired.borrow(), [(1, true), (0, false)]);
+
+        assert_eq!(aggregator.enable_pin(2), Err(ErrorCode::INVAL));
+        aggregator.disable();
+        pins[1].fire(false);
+        assert_eq!(client.fired.borrow().len(), 2);
+    }
+
+    #[test]
+    fn unknown_value_ignored() {
+        let (_, aggregator, client) = aggregator(2);
+        aggregator.fired(5);
+        assert!(client.fired.borrow().is_empty());
+    }
+}
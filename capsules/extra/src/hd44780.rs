@@ -8,8 +8,10 @@
 //! already defined in the kernel, and modifying them means re-compiling the
 //! kernel with the modifications.
 //!
-//! This capsule takes an alarm, an array of pins and one buffer initialized
-//! to 0.
+//! This capsule takes an alarm, the outputs the LCD is connected through and
+//! one buffer initialized to 0. The LCD is driven in 4-bit mode, either
+//! directly from GPIO pins (`GpioOutputs`) or through a PCF8574 I2C backpack
+//! (`Pcf8574Outputs`).
 //!
 //! This capsule uses the TextScreen capsule and implements the TextScreen trait,
 //! through which it can receive commands (specific driver commands or write
@@ -25,6 +27,9 @@
 //! commands. If a command is sent while this capsule is busy, it will return a
 //! "BUSY" code.
 
+//! Up to eight custom characters can be defined with `define_character()`,
+//! and are then printed as the character codes 0 to 7.
+//!
 //! Usage
 //! -----
 //! ```rust,ignore
@@ -54,6 +59,7 @@
 
 use core::cell::Cell;
 use kernel::hil::gpio;
+use kernel::hil::i2c::{self, I2CClient, I2CDevice};
 use kernel::hil::text_screen::{TextScreen, TextScreenClient};
 use kernel::hil::time::{self, Alarm, Frequency};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
@@ -64,6 +70,7 @@ static LCD_CLEARDISPLAY: u8 = 0x01;
 static LCD_ENTRYMODESET: u8 = 0x04;
 static LCD_DISPLAYCONTROL: u8 = 0x08;
 static LCD_FUNCTIONSET: u8 = 0x20;
+static LCD_SETCGRAMADDR: u8 = 0x40;
 static LCD_SETDDRAMADDR: u8 = 0x80;
 
 /// flags for display entry mode
@@ -85,6 +92,159 @@ static LCD_5X8DOTS: u8 = 0x00;
 
 pub const BUF_LEN: usize = 4;
 
+/// Number of custom characters the LCD can store.
+pub const NUM_CUSTOM_CHARACTERS: u8 = 8;
+
+/// The outputs the LCD is connected through in 4-bit mode.
+///
+/// Every change must be visible on the LCD pins before the next one is
+/// made. The driver waits at least 2 ms between changes, which leaves
+/// enough time for outputs that are updated asynchronously.
+pub trait Hd44780Outputs {
+    /// Sets the register select pin: low for commands, high for data.
+    fn set_rs(&self, high: bool);
+    /// Sets the enable pin. Data is latched on the falling edge.
+    fn set_en(&self, high: bool);
+    /// Sets the data pins D4 to D7 to the lower four bits of `nibble`.
+    fn set_data(&self, nibble: u8);
+}
+
+/// LCD pins connected directly to GPIO pins.
+pub struct GpioOutputs<'a> {
+    rs_pin: &'a dyn gpio::Pin,
+    en_pin: &'a dyn gpio::Pin,
+    data_pins: [&'a dyn gpio::Pin; 4],
+}
+
+impl<'a> GpioOutputs<'a> {
+    pub fn new(
+        rs_pin: &'a dyn gpio::Pin,
+        en_pin: &'a dyn gpio::Pin,
+        data_4_pin: &'a dyn gpio::Pin,
+        data_5_pin: &'a dyn gpio::Pin,
+        data_6_pin: &'a dyn gpio::Pin,
+        data_7_pin: &'a dyn gpio::Pin,
+    ) -> GpioOutputs<'a> {
+        let data_pins = [data_4_pin, data_5_pin, data_6_pin, data_7_pin];
+        rs_pin.make_output();
+        en_pin.make_output();
+        for pin in data_pins.iter() {
+            pin.make_output();
+        }
+        GpioOutputs {
+            rs_pin,
+            en_pin,
+            data_pins,
+        }
+    }
+}
+
+fn set_pin(pin: &dyn gpio::Pin, high: bool) {
+    if high {
+        pin.set();
+    } else {
+        pin.clear();
+    }
+}
+
+impl Hd44780Outputs for GpioOutputs<'_> {
+    fn set_rs(&self, high: bool) {
+        set_pin(self.rs_pin, high);
+    }
+
+    fn set_en(&self, high: bool) {
+        set_pin(self.en_pin, high);
+    }
+
+    fn set_data(&self, nibble: u8) {
+        for (bit, pin) in self.data_pins.iter().enumerate() {
+            set_pin(*pin, (nibble >> bit) & 0x01 != 0);
+        }
+    }
+}
+
+/// Bits of the PCF8574 port in the common LCD backpack wiring.
+const PCF8574_RS: u8 = 1 << 0;
+const PCF8574_EN: u8 = 1 << 2;
+const PCF8574_BACKLIGHT: u8 = 1 << 3;
+const PCF8574_DATA_SHIFT: u8 = 4;
+
+/// Size of the buffer needed by `Pcf8574Outputs`.
+pub const PCF8574_BUF_LEN: usize = 1;
+
+/// LCD pins connected through a PCF8574 I2C port expander backpack, with RS
+/// on P0, EN on P2, the backlight on P3 and D4 to D7 on P4 to P7.
+///
+/// Every change is written to the expander. If a write is still in
+/// progress, the latest state is written once it completes.
+pub struct Pcf8574Outputs<'a, I: I2CDevice> {
+    i2c: &'a I,
+    buffer: TakeCell<'static, [u8]>,
+    /// The state of the expander port.
+    port: Cell<u8>,
+    /// Whether `port` changed while a write was in progress.
+    dirty: Cell<bool>,
+}
+
+impl<'a, I: I2CDevice> Pcf8574Outputs<'a, I> {
+    pub fn new(i2c: &'a I, buffer: &'static mut [u8; PCF8574_BUF_LEN]) -> Pcf8574Outputs<'a, I> {
+        Pcf8574Outputs {
+            i2c,
+            buffer: TakeCell::new(buffer),
+            port: Cell::new(PCF8574_BACKLIGHT),
+            dirty: Cell::new(false),
+        }
+    }
+
+    /// Turns the backlight on or off.
+    pub fn set_backlight(&self, on: bool) {
+        self.update(PCF8574_BACKLIGHT, if on { PCF8574_BACKLIGHT } else { 0 });
+    }
+
+    fn update(&self, mask: u8, value: u8) {
+        self.port.set((self.port.get() & !mask) | (value & mask));
+        match self.buffer.take() {
+            Some(buffer) => self.write(buffer),
+            None => self.dirty.set(true),
+        }
+    }
+
+    fn write(&self, buffer: &'static mut [u8]) {
+        buffer[0] = self.port.get();
+        self.dirty.set(false);
+        self.i2c.enable();
+        if let Err((_error, buffer)) = self.i2c.write(buffer, 1) {
+            self.i2c.disable();
+            self.buffer.replace(buffer);
+        }
+    }
+}
+
+impl<I: I2CDevice> I2CClient for Pcf8574Outputs<'_, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], _status: Result<(), i2c::Error>) {
+        if self.dirty.get() {
+            self.write(buffer);
+        } else {
+            self.i2c.disable();
+            self.buffer.replace(buffer);
+        }
+    }
+}
+
+impl<I: I2CDevice> Hd44780Outputs for Pcf8574Outputs<'_, I> {
+    fn set_rs(&self, high: bool) {
+        self.update(PCF8574_RS, if high { PCF8574_RS } else { 0 });
+    }
+
+    fn set_en(&self, high: bool) {
+        self.update(PCF8574_EN, if high { PCF8574_EN } else { 0 });
+    }
+
+    fn set_data(&self, nibble: u8) {
+        self.update(0xF0, nibble << PCF8574_DATA_SHIFT);
+    }
+}
+
 /// The states the program can be in.
 #[derive(Copy, Clone, PartialEq)]
 enum LCDStatus {
@@ -110,15 +270,12 @@ enum LCDStatus {
     PulseHigh,
     Command,
     Clear,
+    CharacterData,
+    CharacterDataLow,
 }
 
 pub struct HD44780<'a, A: Alarm<'a>> {
-    rs_pin: &'a dyn gpio::Pin,
-    en_pin: &'a dyn gpio::Pin,
-    data_4_pin: &'a dyn gpio::Pin,
-    data_5_pin: &'a dyn gpio::Pin,
-    data_6_pin: &'a dyn gpio::Pin,
-    data_7_pin: &'a dyn gpio::Pin,
+    outputs: &'a dyn Hd44780Outputs,
 
     width: Cell<u8>,
     height: Cell<u8>,
@@ -148,34 +305,22 @@ pub struct HD44780<'a, A: Alarm<'a>> {
     write_len: Cell<u8>,
     write_buffer_len: Cell<u8>,
     write_offset: Cell<u8>,
+
+    character_pattern: Cell<[u8; 8]>,
+    character_offset: Cell<usize>,
+    defining_character: Cell<bool>,
 }
 
 impl<'a, A: Alarm<'a>> HD44780<'a, A> {
     pub fn new(
-        rs_pin: &'a dyn gpio::Pin,
-        en_pin: &'a dyn gpio::Pin,
-        data_4_pin: &'a dyn gpio::Pin,
-        data_5_pin: &'a dyn gpio::Pin,
-        data_6_pin: &'a dyn gpio::Pin,
-        data_7_pin: &'a dyn gpio::Pin,
+        outputs: &'a dyn Hd44780Outputs,
         row_offsets: &'static mut [u8],
         alarm: &'a A,
         width: u8,
         height: u8,
     ) -> HD44780<'a, A> {
-        rs_pin.make_output();
-        en_pin.make_output();
-        data_4_pin.make_output();
-        data_5_pin.make_output();
-        data_6_pin.make_output();
-        data_7_pin.make_output();
         let hd44780 = HD44780 {
-            rs_pin,
-            en_pin,
-            data_4_pin,
-            data_5_pin,
-            data_6_pin,
-            data_7_pin,
+            outputs,
             width: Cell::new(width),
             height: Cell::new(height),
             display_function: Cell::new(LCD_4BITMODE | LCD_1LINE | LCD_5X8DOTS),
@@ -197,6 +342,9 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
             write_len: Cell::new(0),
             write_buffer_len: Cell::new(0),
             write_offset: Cell::new(0),
+            character_pattern: Cell::new([0; 8]),
+            character_offset: Cell::new(0),
+            defining_character: Cell::new(false),
         };
         hd44780.init(width, height);
 
@@ -281,7 +429,7 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
     ///
     fn pulse(&self, after_pulse_status: LCDStatus) {
         self.lcd_after_pulse_status.set(after_pulse_status);
-        self.en_pin.clear();
+        self.outputs.set_en(false);
         self.set_delay(500, LCDStatus::PulseLow);
     }
 
@@ -296,30 +444,7 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
     ///  self.write_4_bits(27, LCDStatus::Idle);
     ///
     fn write_4_bits(&self, value: u8, next_status: LCDStatus) {
-        if (value >> 0) & 0x01 != 0 {
-            self.data_4_pin.set();
-        } else {
-            self.data_4_pin.clear();
-        }
-
-        if (value >> 1) & 0x01 != 0 {
-            self.data_5_pin.set();
-        } else {
-            self.data_5_pin.clear();
-        }
-
-        if (value >> 2) & 0x01 != 0 {
-            self.data_6_pin.set();
-        } else {
-            self.data_6_pin.clear();
-        }
-
-        if (value >> 3) & 0x01 != 0 {
-            self.data_7_pin.set();
-        } else {
-            self.data_7_pin.clear();
-        }
-
+        self.outputs.set_data(value & 0x0F);
         self.pulse(next_status);
     }
 
@@ -332,38 +457,44 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
             // the execution of a command was just finished and a callback to the
             // screen capsule will be sent (according to the command type)
             LCDStatus::Idle => {
-                self.text_screen_client.map(|client| {
-                    if self.begin_done.get() {
-                        self.begin_done.set(false);
-                        self.initialized.set(true);
-                        client.command_complete(Ok(()));
-                    } else if self.write_len.get() > 0 {
-                        self.write_character();
-                    } else if self.done_printing.get() {
-                        self.done_printing.set(false);
-                        if self.write_buffer.is_some() {
-                            self.write_buffer.take().map(|buffer| {
-                                client.write_complete(
-                                    buffer,
-                                    self.write_buffer_len.get() as usize,
-                                    Ok(()),
-                                )
-                            });
-                        }
-                    } else {
-                        client.command_complete(Ok(()));
-                    }
-                });
+                if self.defining_character.get() {
+                    // Custom characters are defined by the kernel, not
+                    // through the text screen, so there is no callback.
+                    self.defining_character.set(false);
+                    return;
+                }
+                if self.begin_done.get() {
+                    self.begin_done.set(false);
+                    self.initialized.set(true);
+                    self.text_screen_client
+                        .map(|client| client.command_complete(Ok(())));
+                } else if self.write_len.get() > 0 {
+                    self.write_character();
+                } else if self.done_printing.get() {
+                    self.done_printing.set(false);
+                    self.text_screen_client.map(|client| {
+                        self.write_buffer.take().map(|buffer| {
+                            client.write_complete(
+                                buffer,
+                                self.write_buffer_len.get() as usize,
+                                Ok(()),
+                            )
+                        });
+                    });
+                } else {
+                    self.text_screen_client
+                        .map(|client| client.command_complete(Ok(())));
+                }
             }
 
             LCDStatus::Begin0 => {
-                self.rs_pin.clear();
-                self.en_pin.clear();
+                self.outputs.set_rs(false);
+                self.outputs.set_en(false);
 
                 if (self.display_function.get() & LCD_8BITMODE) == 0 {
                     self.write_4_bits(0x03, LCDStatus::Begin0_1);
                 } else {
-                    self.rs_pin.clear();
+                    self.outputs.set_rs(false);
                     self.lcd_command(
                         (LCD_FUNCTIONSET | self.display_function.get()) >> 4,
                         LCDStatus::Begin4,
@@ -461,7 +592,7 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
             }
 
             LCDStatus::PulseLow => {
-                self.en_pin.set();
+                self.outputs.set_en(true);
                 self.set_delay(500, LCDStatus::PulseHigh);
             }
 
@@ -473,9 +604,28 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
             }
 
             LCDStatus::PulseHigh => {
-                self.en_pin.clear();
+                self.outputs.set_en(false);
                 self.set_delay(500, self.lcd_after_pulse_status.get());
             }
+
+            LCDStatus::CharacterData => {
+                let offset = self.character_offset.get();
+                if offset < 8 {
+                    let value = self.character_pattern.get()[offset];
+                    self.character_offset.set(offset + 1);
+                    self.outputs.set_rs(true);
+                    self.command_to_finish.set(value);
+                    self.write_4_bits(value >> 4, LCDStatus::CharacterDataLow);
+                } else {
+                    // Switch back to writing the display, at the home
+                    // position.
+                    self.lcd_command(LCD_SETDDRAMADDR, LCDStatus::Idle);
+                }
+            }
+
+            LCDStatus::CharacterDataLow => {
+                self.write_4_bits(self.command_to_finish.get(), LCDStatus::CharacterData);
+            }
         }
     }
 
@@ -509,7 +659,7 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
     fn lcd_command(&self, value: u8, next_state: LCDStatus) {
         self.lcd_after_command_status.set(next_state);
         self.command_to_finish.set(value);
-        self.rs_pin.clear();
+        self.outputs.set_rs(false);
         self.write_4_bits(value >> 4, LCDStatus::Command);
     }
 
@@ -564,7 +714,7 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
         if self.write_len.get() == 0 {
             self.done_printing.set(true);
         }
-        self.rs_pin.set();
+        self.outputs.set_rs(true);
         self.command_to_finish.set(value);
         self.write_4_bits(value >> 4, LCDStatus::Printing);
     }
@@ -590,6 +740,29 @@ impl<'a, A: Alarm<'a>> HD44780<'a, A> {
     }
 }
 
+impl<'a, A: Alarm<'a>> HD44780<'a, A> {
+    /// Defines the custom character with code `location` (0 to 7). Each byte
+    /// of `pattern` is a row of the character, from the top, with the five
+    /// pixels of the row in the lower five bits.
+    ///
+    /// The cursor is moved to the home position afterwards. No callback is
+    /// made when the character has been defined, until then other
+    /// operations return `BUSY`.
+    pub fn define_character(&self, location: u8, pattern: &[u8; 8]) -> Result<(), ErrorCode> {
+        if location >= NUM_CUSTOM_CHARACTERS {
+            return Err(ErrorCode::INVAL);
+        }
+        if self.lcd_status.get() != LCDStatus::Idle || !self.initialized.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.character_pattern.set(pattern.map(|row| row & 0x1F));
+        self.character_offset.set(0);
+        self.defining_character.set(true);
+        self.lcd_command(LCD_SETCGRAMADDR | (location << 3), LCDStatus::CharacterData);
+        Ok(())
+    }
+}
+
 impl<'a, A: Alarm<'a>> time::AlarmClient for HD44780<'a, A> {
     /// `alarm()` is called after each alarm finished, and depending on the
     /// current state of the program, the next step in being decided.
@@ -600,7 +773,7 @@ impl<'a, A: Alarm<'a>> time::AlarmClient for HD44780<'a, A> {
 
 impl<'a, A: Alarm<'a>> TextScreen<'a> for HD44780<'a, A> {
     fn get_size(&self) -> (usize, usize) {
-        (16, 2)
+        (self.width.get() as usize, self.height.get() as usize)
     }
 
     fn print(
@@ -683,3 +856,226 @@ impl<'a, A: Alarm<'a>> TextScreen<'a> for HD44780<'a, A> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use kernel::hil::time::{AlarmClient, Freq1KHz, Ticks, Ticks32, Time};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Event {
+        Rs(bool),
+        En(bool),
+        Data(u8),
+        Delay(u32),
+    }
+
+    #[derive(Default)]
+    struct MockOutputs {
+        events: core::cell::RefCell<Vec<Event>>,
+    }
+
+    impl Hd44780Outputs for MockOutputs {
+        fn set_rs(&self, high: bool) {
+            self.events.borrow_mut().push(Event::Rs(high));
+        }
+
+        fn set_en(&self, high: bool) {
+            self.events.borrow_mut().push(Event::En(high));
+        }
+
+        fn set_data(&self, nibble: u8) {
+            self.events.borrow_mut().push(Event::Data(nibble));
+        }
+    }
+
+    /// An alarm that only fires when the test calls `fire()`, and records
+    /// every delay in the outputs' event log.
+    struct MockAlarm<'a> {
+        outputs: &'a MockOutputs,
+        armed: Cell<bool>,
+        dt: Cell<Ticks32>,
+    }
+
+    impl MockAlarm<'static> {
+        fn fire(&self, hd44780: &HD44780<'static, Self>) -> bool {
+            if !self.armed.get() {
+                return false;
+            }
+            self.armed.set(false);
+            hd44780.alarm();
+            true
+        }
+    }
+
+    impl Time for MockAlarm<'_> {
+        type Ticks = Ticks32;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> Ticks32 {
+            0.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm<'a> {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Self::Ticks, dt: Self::Ticks) {
+            self.outputs
+                .events
+                .borrow_mut()
+                .push(Event::Delay(dt.into_u32()));
+            self.dt.set(dt);
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            self.dt.get()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            0.into()
+        }
+    }
+
+    /// Returns the `(rs, nibble)` pairs latched by the LCD, checking that
+    /// the enable pin is held high for a delay and that RS and the data are
+    /// stable while it is.
+    fn latched(events: &[Event]) -> Vec<(bool, u8)> {
+        let mut rs = false;
+        let mut data = 0;
+        let mut en_high = false;
+        let mut delayed = false;
+        let mut latched = Vec::new();
+
+        for event in events {
+            match *event {
+                Event::Rs(high) => {
+                    assert!(!en_high, "RS changed while EN was high");
+                    rs = high;
+                }
+                Event::Data(nibble) => {
+                    assert!(!en_high, "data changed while EN was high");
+                    data = nibble;
+                }
+                Event::En(true) => {
+                    en_high = true;
+                    delayed = false;
+                }
+                Event::En(false) => {
+                    if en_high {
+                        assert!(delayed, "EN pulse too short");
+                        latched.push((rs, data));
+                    }
+                    en_high = false;
+                }
+                Event::Delay(ticks) => {
+                    assert!(ticks > 0);
+                    delayed = true;
+                }
+            }
+        }
+        latched
+    }
+
+    fn run(alarm: &MockAlarm<'static>, hd44780: &HD44780<'static, MockAlarm<'static>>) {
+        let mut steps = 0;
+        while alarm.fire(hd44780) {
+            steps += 1;
+            assert!(steps < 1000, "the LCD never became idle");
+        }
+    }
+
+    #[test]
+    fn init_and_print() {
+        let outputs: &MockOutputs = Box::leak(Box::default());
+        let alarm: &MockAlarm = Box::leak(Box::new(MockAlarm {
+            outputs,
+            armed: Cell::new(false),
+            dt: Cell::new(0.into()),
+        }));
+        let hd44780 = HD44780::new(outputs, Box::leak(Box::new([0; BUF_LEN])), alarm, 16, 2);
+
+        hd44780.display_on().unwrap();
+        run(alarm, &hd44780);
+        assert_eq!(
+            latched(&outputs.events.borrow()),
+            [
+                // Switch to 4-bit mode.
+                (false, 0x3),
+                (false, 0x3),
+                (false, 0x3),
+                (false, 0x2),
+                // Function set: 4-bit, 2 lines, 5x8 dots.
+                (false, 0x2),
+                (false, 0x8),
+                // Display on, cursor on.
+                (false, 0x0),
+                (false, 0xE),
+                // Clear.
+                (false, 0x0),
+                (false, 0x1),
+                // Entry mode: left to right.
+                (false, 0x0),
+                (false, 0x6),
+            ]
+        );
+
+        outputs.events.borrow_mut().clear();
+        let _ = hd44780.print(Box::leak(Box::new(*b"Hi")), 2);
+        run(alarm, &hd44780);
+        assert_eq!(
+            latched(&outputs.events.borrow()),
+            [(true, 0x4), (true, 0x8), (true, 0x6), (true, 0x9)]
+        );
+
+        outputs.events.borrow_mut().clear();
+        hd44780
+            .define_character(2, &[0x00, 0x0A, 0x1F, 0x1F, 0x0E, 0x04, 0x00, 0xFF])
+            .unwrap();
+        assert_eq!(hd44780.define_character(2, &[0; 8]), Err(ErrorCode::BUSY));
+        run(alarm, &hd44780);
+        assert_eq!(
+            latched(&outputs.events.borrow()),
+            [
+                // Set CGRAM address 0x10.
+                (false, 0x5),
+                (false, 0x0),
+                // The rows of the character.
+                (true, 0x0),
+                (true, 0x0),
+                (true, 0x0),
+                (true, 0xA),
+                (true, 0x1),
+                (true, 0xF),
+                (true, 0x1),
+                (true, 0xF),
+                (true, 0x0),
+                (true, 0xE),
+                (true, 0x0),
+                (true, 0x4),
+                (true, 0x0),
+                (true, 0x0),
+                (true, 0x1),
+                (true, 0xF),
+                // Back to DDRAM address 0.
+                (false, 0x8),
+                (false, 0x0),
+            ]
+        );
+        assert_eq!(hd44780.define_character(8, &[0; 8]), Err(ErrorCode::INVAL));
+    }
+}
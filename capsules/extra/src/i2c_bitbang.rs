@@ -0,0 +1,812 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Software (bit-banged) I2C master over two GPIO pins.
+//!
+//! This lets boards without a hardware I2C controller, or without one wired to
+//! the right pins, talk to low-rate I2C devices. Every clock edge is driven
+//! from an alarm, so the bus runs slowly: each bit takes two alarm periods.
+//!
+//! The pins are used in open-drain fashion: a line is driven low by
+//! configuring the pin as an output set low, and released (pulled high by the
+//! bus pull-up resistors) by configuring the pin as an input. External
+//! pull-ups are therefore required on both SDA and SCL.
+//!
+//! After releasing SCL, the clock line is read back, and the transfer waits
+//! for as long as a device holds it low (clock stretching), up to
+//! [`MAX_STRETCH`] alarm periods.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let i2c = components::i2c_bitbang::I2CBitBangComponent::new(
+//!     &gpio_port[SDA_PIN],
+//!     &gpio_port[SCL_PIN],
+//!     mux_alarm,
+//!     5, // microseconds per half clock period
+//! )
+//! .finalize(components::i2c_bitbang_component_static!(nrf52832::rtc::Rtc));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::gpio;
+use kernel::hil::i2c::{self, Error, I2CHwMasterClient};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+
+/// Number of alarm periods to wait for a device stretching the clock before
+/// giving up on the transfer.
+pub const MAX_STRETCH: usize = 100;
+
+/// The step performed the next time the alarm fires.
+#[derive(Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    /// SDA has been pulled low with SCL high; pull SCL low.
+    StartHold,
+    /// SDA holds the next bit; release SCL.
+    ClockRise,
+    /// SCL has been released; sample SDA once it is high and pull SCL low.
+    ClockHigh,
+    /// SDA has been pulled low with SCL low; release SCL.
+    StopClock,
+    /// SCL has been released; release SDA once SCL is high.
+    StopHigh,
+    /// The bus is free again; report the result.
+    StopDone,
+    /// SDA has been released with SCL low; release SCL.
+    RestartClock,
+    /// SCL has been released; pull SDA low once SCL is high.
+    RestartHigh,
+}
+
+/// The byte currently being transferred.
+#[derive(Clone, Copy, PartialEq)]
+enum Transfer {
+    Address { read: bool },
+    Write(usize),
+    Read(usize),
+}
+
+pub struct I2CBitBang<'a, A: Alarm<'a>> {
+    sda: &'a dyn gpio::Pin,
+    scl: &'a dyn gpio::Pin,
+    alarm: &'a A,
+    delay_us: u32,
+    client: OptionalCell<&'a dyn I2CHwMasterClient>,
+    buffer: TakeCell<'static, [u8]>,
+    addr: Cell<u8>,
+    write_len: Cell<usize>,
+    read_len: Cell<usize>,
+    phase: Cell<Phase>,
+    transfer: Cell<Transfer>,
+    /// Bit being transferred within the byte; 8 is the acknowledge bit.
+    bit: Cell<u8>,
+    byte: Cell<u8>,
+    stretch: Cell<usize>,
+    status: Cell<Result<(), Error>>,
+}
+
+impl<'a, A: Alarm<'a>> I2CBitBang<'a, A> {
+    pub fn new(
+        sda: &'a dyn gpio::Pin,
+        scl: &'a dyn gpio::Pin,
+        alarm: &'a A,
+        delay_us: u32,
+    ) -> I2CBitBang<'a, A> {
+        I2CBitBang {
+            sda,
+            scl,
+            alarm,
+            delay_us,
+            client: OptionalCell::empty(),
+            buffer: TakeCell::empty(),
+            addr: Cell::new(0),
+            write_len: Cell::new(0),
+            read_len: Cell::new(0),
+            phase: Cell::new(Phase::Idle),
+            transfer: Cell::new(Transfer::Address { read: false }),
+            bit: Cell::new(0),
+            byte: Cell::new(0),
+            stretch: Cell::new(0),
+            status: Cell::new(Ok(())),
+        }
+    }
+
+    fn drive_low(pin: &dyn gpio::Pin) {
+        pin.clear();
+        pin.make_output();
+    }
+
+    fn release(pin: &dyn gpio::Pin) {
+        pin.make_input();
+    }
+
+    fn wait(&self, phase: Phase) {
+        self.phase.set(phase);
+        let delay = self.alarm.ticks_from_us(self.delay_us);
+        self.alarm.set_alarm(self.alarm.now(), delay);
+    }
+
+    /// Returns `true` if SCL is high. Otherwise waits another period for a
+    /// device stretching the clock, or aborts the transfer after
+    /// `MAX_STRETCH` periods.
+    fn scl_released(&self) -> bool {
+        if self.scl.read() {
+            self.stretch.set(0);
+            return true;
+        }
+        let stretch = self.stretch.get() + 1;
+        if stretch > MAX_STRETCH {
+            // Give up on the transfer and release the bus.
+            self.stretch.set(0);
+            Self::release(self.sda);
+            Self::release(self.scl);
+            self.status.set(Err(Error::Busy));
+            self.wait(Phase::StopDone);
+        } else {
+            self.stretch.set(stretch);
+            self.wait(self.phase.get());
+        }
+        false
+    }
+
+    fn start_transfer(
+        &self,
+        addr: u8,
+        buffer: &'static mut [u8],
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        if self.phase.get() != Phase::Idle {
+            return Err((Error::Busy, buffer));
+        }
+        if write_len > buffer.len() || read_len > buffer.len() {
+            return Err((Error::Overrun, buffer));
+        }
+        // Both lines must be released by every other device to start.
+        if !self.sda.read() || !self.scl.read() {
+            return Err((Error::Busy, buffer));
+        }
+
+        self.buffer.replace(buffer);
+        self.addr.set(addr);
+        self.write_len.set(write_len);
+        self.read_len.set(read_len);
+        self.status.set(Ok(()));
+        self.begin_byte(Transfer::Address {
+            read: write_len == 0 && read_len > 0,
+        });
+
+        // START condition: SDA falls while SCL is high.
+        Self::drive_low(self.sda);
+        self.wait(Phase::StartHold);
+        Ok(())
+    }
+
+    fn begin_byte(&self, transfer: Transfer) {
+        self.transfer.set(transfer);
+        self.bit.set(0);
+        let byte = match transfer {
+            Transfer::Address { read } => (self.addr.get() << 1) | read as u8,
+            Transfer::Write(i) => self.buffer.map_or(0, |buffer| buffer[i]),
+            Transfer::Read(_) => 0,
+        };
+        self.byte.set(byte);
+    }
+
+    /// With SCL low, puts the current bit on SDA.
+    fn setup_bit(&self) {
+        let bit = self.bit.get();
+        let high = match self.transfer.get() {
+            Transfer::Address { .. } | Transfer::Write(_) => {
+                // The device acknowledges, so SDA is released for bit 8.
+                bit == 8 || self.byte.get() & (0x80 >> bit) != 0
+            }
+            Transfer::Read(i) => {
+                // Acknowledge every byte but the last one.
+                bit != 8 || i + 1 == self.read_len.get()
+            }
+        };
+        if high {
+            Self::release(self.sda);
+        } else {
+            Self::drive_low(self.sda);
+        }
+        self.wait(Phase::ClockRise);
+    }
+
+    /// With SCL low after the acknowledge bit, continues with the next byte or
+    /// ends the transfer.
+    fn next_byte(&self) {
+        let next = match self.transfer.get() {
+            Transfer::Address { read: true } => Transfer::Read(0),
+            Transfer::Address { read: false } => Transfer::Write(0),
+            Transfer::Write(i) => Transfer::Write(i + 1),
+            Transfer::Read(i) => Transfer::Read(i + 1),
+        };
+        match next {
+            Transfer::Write(i) if i < self.write_len.get() => {
+                self.begin_byte(next);
+                self.setup_bit();
+            }
+            Transfer::Write(_) if self.read_len.get() > 0 => {
+                // Repeated START to switch to reading.
+                Self::release(self.sda);
+                self.wait(Phase::RestartClock);
+            }
+            Transfer::Read(i) if i < self.read_len.get() => {
+                self.begin_byte(next);
+                self.setup_bit();
+            }
+            _ => self.stop(),
+        }
+    }
+
+    /// With SCL low, begins the STOP condition.
+    fn stop(&self) {
+        Self::drive_low(self.sda);
+        self.wait(Phase::StopClock);
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for I2CBitBang<'a, A> {
+    fn alarm(&self) {
+        match self.phase.get() {
+            Phase::Idle => {}
+            Phase::StartHold => {
+                Self::drive_low(self.scl);
+                self.setup_bit();
+            }
+            Phase::ClockRise => {
+                Self::release(self.scl);
+                self.wait(Phase::ClockHigh);
+            }
+            Phase::ClockHigh => {
+                if !self.scl_released() {
+                    return;
+                }
+                let sda = self.sda.read();
+                Self::drive_low(self.scl);
+
+                let bit = self.bit.get();
+                if bit < 8 {
+                    if let Transfer::Read(_) = self.transfer.get() {
+                        self.byte.set((self.byte.get() << 1) | sda as u8);
+                    }
+                    self.bit.set(bit + 1);
+                    self.setup_bit();
+                    return;
+                }
+
+                // Acknowledge bit.
+                match self.transfer.get() {
+                    Transfer::Address { .. } if sda => {
+                        self.status.set(Err(Error::AddressNak));
+                        self.stop();
+                    }
+                    Transfer::Write(_) if sda => {
+                        self.status.set(Err(Error::DataNak));
+                        self.stop();
+                    }
+                    Transfer::Read(i) => {
+                        let byte = self.byte.get();
+                        self.buffer.map(|buffer| buffer[i] = byte);
+                        self.next_byte();
+                    }
+                    _ => self.next_byte(),
+                }
+            }
+            Phase::StopClock => {
+                Self::release(self.scl);
+                self.wait(Phase::StopHigh);
+            }
+            Phase::StopHigh => {
+                if !self.scl_released() {
+                    return;
+                }
+                // STOP condition: SDA rises while SCL is high.
+                Self::release(self.sda);
+                self.wait(Phase::StopDone);
+            }
+            Phase::StopDone => {
+                self.phase.set(Phase::Idle);
+                let status = self.status.get();
+                self.buffer.take().map(|buffer| {
+                    self.client
+                        .map(move |client| client.command_complete(buffer, status));
+                });
+            }
+            Phase::RestartClock => {
+                Self::release(self.scl);
+                self.wait(Phase::RestartHigh);
+            }
+            Phase::RestartHigh => {
+                if !self.scl_released() {
+                    return;
+                }
+                self.begin_byte(Transfer::Address { read: true });
+                Self::drive_low(self.sda);
+                self.wait(Phase::StartHold);
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> i2c::I2CMaster<'a> for I2CBitBang<'a, A> {
+    fn set_master_client(&self, master_client: &'a dyn I2CHwMasterClient) {
+        self.client.set(master_client);
+    }
+
+    fn enable(&self) {
+        self.sda.set_floating_state(gpio::FloatingState::PullNone);
+        self.scl.set_floating_state(gpio::FloatingState::PullNone);
+        Self::release(self.sda);
+        Self::release(self.scl);
+    }
+
+    fn disable(&self) {
+        self.sda.deactivate_to_low_power();
+        self.scl.deactivate_to_low_power();
+    }
+
+    fn write_read(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        write_len: usize,
+        read_len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.start_transfer(addr, data, write_len, read_len)
+    }
+
+    fn write(
+        &self,
+        addr: u8,
+        data: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.start_transfer(addr, data, len, 0)
+    }
+
+    fn read(
+        &self,
+        addr: u8,
+        buffer: &'static mut [u8],
+        len: usize,
+    ) -> Result<(), (Error, &'static mut [u8])> {
+        self.start_transfer(addr, buffer, 0, len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use kernel::hil::gpio::{Configuration, Configure, FloatingState, Input, Output};
+    use kernel::hil::time::{Freq1MHz, Ticks32, Time};
+    use kernel::ErrorCode;
+    use std::boxed::Box;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    /// What the simulated device observed on the bus.
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Start,
+        Stop,
+        /// A byte sent by the master, and whether the device acknowledged it.
+        Write(u8, bool),
+        /// A byte sent by the device, and whether the master acknowledged it.
+        Read(u8, bool),
+    }
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum DeviceState {
+        Idle,
+        Receive { address: bool },
+        Transmit,
+    }
+
+    /// An open-drain line, low if either the master or the device pulls it
+    /// low.
+    #[derive(Default)]
+    struct Line {
+        output: Cell<bool>,
+        value: Cell<bool>,
+        device_low: Cell<bool>,
+    }
+
+    impl Line {
+        fn level(&self) -> bool {
+            !(self.device_low.get() || (self.output.get() && !self.value.get()))
+        }
+
+        fn master_released(&self) -> bool {
+            !self.output.get() || self.value.get()
+        }
+    }
+
+    /// A bus with a single device that answers to `address`, returns
+    /// `read_data` when read from, and can stretch the clock after
+    /// acknowledging its address.
+    struct Bus {
+        sda: Line,
+        scl: Line,
+        prev_sda: Cell<bool>,
+        prev_scl: Cell<bool>,
+        address: u8,
+        read_data: RefCell<VecDeque<u8>>,
+        /// Alarm periods to hold SCL low for after the address.
+        stretch: Cell<usize>,
+        stretching: Cell<usize>,
+        state: Cell<DeviceState>,
+        clocked: Cell<bool>,
+        bits: Cell<u8>,
+        shift: Cell<u8>,
+        acked: Cell<bool>,
+        events: RefCell<Vec<Event>>,
+    }
+
+    impl Bus {
+        fn new(address: u8, read_data: &[u8]) -> Self {
+            Bus {
+                sda: Line::default(),
+                scl: Line::default(),
+                prev_sda: Cell::new(true),
+                prev_scl: Cell::new(true),
+                address,
+                read_data: RefCell::new(read_data.iter().copied().collect()),
+                stretch: Cell::new(0),
+                stretching: Cell::new(0),
+                state: Cell::new(DeviceState::Idle),
+                clocked: Cell::new(false),
+                bits: Cell::new(0),
+                shift: Cell::new(0),
+                acked: Cell::new(false),
+                events: RefCell::new(Vec::new()),
+            }
+        }
+
+        /// Reacts to a change of the lines.
+        fn update(&self) {
+            let (sda, scl) = (self.sda.level(), self.scl.level());
+            let (prev_sda, prev_scl) = (self.prev_sda.get(), self.prev_scl.get());
+            self.prev_sda.set(sda);
+            self.prev_scl.set(scl);
+
+            if scl && prev_scl && sda != prev_sda {
+                self.sda.device_low.set(false);
+                self.clocked.set(false);
+                self.bits.set(0);
+                self.shift.set(0);
+                if sda {
+                    self.state.set(DeviceState::Idle);
+                    self.events.borrow_mut().push(Event::Stop);
+                } else {
+                    self.state.set(DeviceState::Receive { address: true });
+                    self.events.borrow_mut().push(Event::Start);
+                }
+            } else if scl && !prev_scl {
+                self.clock_high(sda);
+            } else if !scl && prev_scl && self.clocked.take() {
+                self.clock_low();
+                self.prev_sda.set(self.sda.level());
+            }
+        }
+
+        fn clock_high(&self, sda: bool) {
+            self.clocked.set(true);
+            let bits = self.bits.get();
+            match self.state.get() {
+                DeviceState::Receive { .. } if bits < 8 => {
+                    self.shift.set((self.shift.get() << 1) | sda as u8);
+                }
+                DeviceState::Receive { .. } => {
+                    let ack = self.sda.device_low.get();
+                    self.events
+                        .borrow_mut()
+                        .push(Event::Write(self.shift.get(), ack));
+                }
+                DeviceState::Transmit if bits == 8 => {
+                    self.acked.set(!sda);
+                    self.events
+                        .borrow_mut()
+                        .push(Event::Read(self.shift.get(), !sda));
+                }
+                _ => {}
+            }
+        }
+
+        fn clock_low(&self) {
+            let bits = self.bits.get() + 1;
+            self.bits.set(bits % 9);
+            let state = self.state.get();
+            match (state, bits) {
+                (DeviceState::Receive { address }, 8) => {
+                    // Acknowledge the address if it matches, and all data.
+                    let ack = !address || self.shift.get() >> 1 == self.address;
+                    self.sda.device_low.set(ack);
+                }
+                (DeviceState::Receive { address: true }, 9) => {
+                    self.sda.device_low.set(false);
+                    let shift = self.shift.get();
+                    self.shift.set(0);
+                    if shift >> 1 != self.address {
+                        self.state.set(DeviceState::Idle);
+                    } else if shift & 1 == 1 {
+                        self.state.set(DeviceState::Transmit);
+                        self.transmit_byte();
+                    } else {
+                        self.state.set(DeviceState::Receive { address: false });
+                    }
+                    if self.stretch.get() > 0 {
+                        self.stretching.set(self.stretch.get());
+                        self.scl.device_low.set(true);
+                    }
+                }
+                (DeviceState::Receive { address: false }, 9) => {
+                    self.sda.device_low.set(false);
+                    self.shift.set(0);
+                }
+                (DeviceState::Transmit, 8) => self.sda.device_low.set(false),
+                (DeviceState::Transmit, 9) if self.acked.get() => self.transmit_byte(),
+                (DeviceState::Transmit, 9) => self.state.set(DeviceState::Idle),
+                (DeviceState::Transmit, _) => self.transmit_bit(bits),
+                _ => {}
+            }
+        }
+
+        fn transmit_byte(&self) {
+            let byte = self.read_data.borrow_mut().pop_front().unwrap_or(0xFF);
+            self.shift.set(byte);
+            self.transmit_bit(0);
+        }
+
+        fn transmit_bit(&self, bit: u8) {
+            self.sda
+                .device_low
+                .set(self.shift.get() & (0x80 >> bit) == 0);
+        }
+
+        /// Called every alarm period to end a clock stretch.
+        fn tick(&self) {
+            let stretching = self.stretching.get();
+            if stretching > 0 {
+                self.stretching.set(stretching - 1);
+                if stretching == 1 {
+                    self.scl.device_low.set(false);
+                    self.update();
+                }
+            }
+        }
+    }
+
+    /// The master's connection to one line of the bus.
+    struct MockPin<'a> {
+        bus: &'a Bus,
+        line: &'a Line,
+    }
+
+    impl Configure for MockPin<'_> {
+        fn configuration(&self) -> Configuration {
+            Configuration::InputOutput
+        }
+        fn make_output(&self) -> Configuration {
+            self.line.output.set(true);
+            self.bus.update();
+            Configuration::InputOutput
+        }
+        fn disable_output(&self) -> Configuration {
+            self.make_input()
+        }
+        fn make_input(&self) -> Configuration {
+            self.line.output.set(false);
+            self.bus.update();
+            Configuration::Input
+        }
+        fn disable_input(&self) -> Configuration {
+            Configuration::InputOutput
+        }
+        fn deactivate_to_low_power(&self) {}
+        fn set_floating_state(&self, _state: FloatingState) {}
+        fn floating_state(&self) -> FloatingState {
+            FloatingState::PullNone
+        }
+    }
+
+    impl Input for MockPin<'_> {
+        fn read(&self) -> bool {
+            self.line.level()
+        }
+    }
+
+    impl Output for MockPin<'_> {
+        fn set(&self) {
+            self.line.value.set(true);
+            self.bus.update();
+        }
+        fn clear(&self) {
+            self.line.value.set(false);
+            self.bus.update();
+        }
+        fn toggle(&self) -> bool {
+            self.line.value.set(!self.line.value.get());
+            self.bus.update();
+            self.line.value.get()
+        }
+    }
+
+    /// An alarm that only fires when the test calls `alarm()`.
+    #[derive(Default)]
+    struct MockAlarm {
+        armed: Cell<bool>,
+    }
+
+    impl Time for MockAlarm {
+        type Ticks = Ticks32;
+        type Frequency = Freq1MHz;
+
+        fn now(&self) -> Ticks32 {
+            0.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Self::Ticks, _dt: Self::Ticks) {
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            0.into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            0.into()
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        buffer: Cell<Option<&'static mut [u8]>>,
+        status: Cell<Option<Result<(), Error>>>,
+    }
+
+    impl I2CHwMasterClient for MockClient {
+        fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+            self.buffer.set(Some(buffer));
+            self.status.set(Some(status));
+        }
+    }
+
+    struct Harness {
+        bus: &'static Bus,
+        alarm: &'static MockAlarm,
+        client: &'static MockClient,
+        i2c: &'static I2CBitBang<'static, MockAlarm>,
+    }
+
+    impl Harness {
+        fn new(bus: Bus) -> Self {
+            let bus = Box::leak(Box::new(bus));
+            let alarm = Box::leak(Box::<MockAlarm>::default());
+            let client = Box::leak(Box::<MockClient>::default());
+            let i2c = Box::leak(Box::new(I2CBitBang::new(
+                Box::leak(Box::new(MockPin {
+                    bus,
+                    line: &bus.sda,
+                })),
+                Box::leak(Box::new(MockPin {
+                    bus,
+                    line: &bus.scl,
+                })),
+                alarm,
+                5,
+            )));
+            i2c::I2CMaster::set_master_client(i2c, client);
+            i2c::I2CMaster::enable(i2c);
+            Harness {
+                bus,
+                alarm,
+                client,
+                i2c,
+            }
+        }
+
+        /// Fires the alarm until the transfer completes, and returns how
+        /// often it fired.
+        fn run(&self) -> usize {
+            let mut periods = 0;
+            while self.alarm.armed.get() {
+                self.alarm.armed.set(false);
+                self.bus.tick();
+                self.i2c.alarm();
+                periods += 1;
+            }
+            periods
+        }
+    }
+
+    #[test]
+    fn write_read_with_repeated_start() {
+        let bus = Bus::new(0x50, &[0x5A, 0xC3]);
+        // Hold the clock for a few periods after each address.
+        bus.stretch.set(3);
+        let h = Harness::new(bus);
+
+        let buffer = Box::leak(Box::new([0x10, 0, 0]));
+        assert!(i2c::I2CMaster::write_read(h.i2c, 0x50, buffer, 1, 2).is_ok());
+        h.run();
+
+        assert_eq!(
+            *h.bus.events.borrow(),
+            [
+                Event::Start,
+                Event::Write(0xA0, true),
+                Event::Write(0x10, true),
+                Event::Start,
+                Event::Write(0xA1, true),
+                Event::Read(0x5A, true),
+                Event::Read(0xC3, false),
+                Event::Stop,
+            ]
+        );
+        assert_eq!(h.client.status.get(), Some(Ok(())));
+        assert_eq!(h.client.buffer.take().unwrap(), [0x5A, 0xC3, 0]);
+        assert!(h.bus.sda.level() && h.bus.scl.level());
+    }
+
+    #[test]
+    fn address_nak() {
+        let h = Harness::new(Bus::new(0x50, &[]));
+
+        let buffer = Box::leak(Box::new([0x10, 0x20]));
+        assert!(i2c::I2CMaster::write(h.i2c, 0x51, buffer, 2).is_ok());
+        h.run();
+
+        assert_eq!(
+            *h.bus.events.borrow(),
+            [Event::Start, Event::Write(0xA2, false), Event::Stop]
+        );
+        assert_eq!(h.client.status.get(), Some(Err(Error::AddressNak)));
+        assert!(h.bus.sda.level() && h.bus.scl.level());
+    }
+
+    #[test]
+    fn clock_stretch_timeout() {
+        let bus = Bus::new(0x50, &[]);
+        // Never release the clock after the address.
+        bus.stretch.set(usize::MAX);
+        let h = Harness::new(bus);
+
+        let buffer = Box::leak(Box::new([0x10]));
+        assert!(i2c::I2CMaster::write(h.i2c, 0x50, buffer, 1).is_ok());
+        let periods = h.run();
+
+        assert_eq!(
+            *h.bus.events.borrow(),
+            [Event::Start, Event::Write(0xA0, true)]
+        );
+        assert_eq!(h.client.status.get(), Some(Err(Error::Busy)));
+        assert!(periods > MAX_STRETCH);
+        // The master has let go of the bus.
+        assert!(h.bus.sda.master_released() && h.bus.scl.master_released());
+        assert!(!h.bus.scl.level());
+    }
+}
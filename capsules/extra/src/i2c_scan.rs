@@ -0,0 +1,250 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Scans an I2C bus for devices.
+//!
+//! Bringing up sensors on a new board starts with finding out which
+//! addresses respond. The scanner probes a range of 7-bit addresses with a
+//! zero-length write and records which ones acknowledge. The result is
+//! reported to the client as a bitmap, with bit `n` set if address `n`
+//! acknowledged.
+//!
+//! The scanner uses a single virtual I2C device on the bus mux, and moves it
+//! from address to address, so it can share the bus with the board's other
+//! I2C devices. A probe that fails with anything but an address NAK, for
+//! example because arbitration was lost to a stuck SDA line, aborts the scan
+//! and is reported to the client.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let scanner = components::i2c::I2CScanComponent::new(mux_i2c)
+//!     .finalize(components::i2c_scan_component_static!(nrf52840::i2c::TWI));
+//! scanner.set_client(client);
+//! scanner.scan(0x08, 0x77).unwrap();
+//! ```
+
+use core::cell::Cell;
+
+use capsules_core::virtualizers::virtual_i2c::I2CDevice;
+use kernel::hil::i2c::{self, Error, I2CClient, I2CDevice as _};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// The highest 7-bit I2C address.
+pub const MAX_ADDRESS: u8 = 0x7F;
+
+pub trait I2CScanClient {
+    /// The scan has finished. Bit `n` of `found` is set if address `n`
+    /// acknowledged. If the scan was aborted `result` is the error, and
+    /// `found` covers the addresses probed before it.
+    fn scan_done(&self, found: u128, result: Result<(), ErrorCode>);
+}
+
+pub struct I2CScanner<'a, I: i2c::I2CMaster<'a>> {
+    i2c: &'a I2CDevice<'a, I>,
+    buffer: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a dyn I2CScanClient>,
+    /// The address being probed, and the last address to probe.
+    addr: Cell<u8>,
+    end: Cell<u8>,
+    found: Cell<u128>,
+}
+
+impl<'a, I: i2c::I2CMaster<'a>> I2CScanner<'a, I> {
+    pub fn new(i2c: &'a I2CDevice<'a, I>, buffer: &'static mut [u8]) -> I2CScanner<'a, I> {
+        I2CScanner {
+            i2c,
+            buffer: TakeCell::new(buffer),
+            client: OptionalCell::empty(),
+            addr: Cell::new(0),
+            end: Cell::new(0),
+            found: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn I2CScanClient) {
+        self.client.set(client);
+    }
+
+    /// Probes every address from `start_addr` to `end_addr`, inclusive.
+    pub fn scan(&self, start_addr: u8, end_addr: u8) -> Result<(), ErrorCode> {
+        if start_addr > end_addr || end_addr > MAX_ADDRESS {
+            return Err(ErrorCode::INVAL);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+
+        self.end.set(end_addr);
+        self.found.set(0);
+        self.i2c.enable();
+        self.probe(start_addr, buffer)
+            .inspect_err(|_| self.i2c.disable())
+    }
+
+    fn probe(&self, addr: u8, buffer: &'static mut [u8]) -> Result<(), ErrorCode> {
+        self.addr.set(addr);
+        self.i2c.set_address(addr);
+        self.i2c.write(buffer, 0).map_err(|(error, buffer)| {
+            self.buffer.replace(buffer);
+            error.into()
+        })
+    }
+
+    fn finish(&self, result: Result<(), ErrorCode>) {
+        self.i2c.disable();
+        let found = self.found.get();
+        self.client.map(|client| client.scan_done(found, result));
+    }
+}
+
+impl<'a, I: i2c::I2CMaster<'a>> I2CClient for I2CScanner<'a, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        let addr = self.addr.get();
+        match status {
+            // Nothing was written, so a data NAK still means the address
+            // was acknowledged.
+            Ok(()) | Err(Error::DataNak) => self.found.set(self.found.get() | 1 << addr),
+            Err(Error::AddressNak) => {}
+            Err(error) => {
+                self.buffer.replace(buffer);
+                self.finish(Err(error.into()));
+                return;
+            }
+        }
+
+        if addr >= self.end.get() {
+            self.buffer.replace(buffer);
+            self.finish(Ok(()));
+        } else if let Err(error) = self.probe(addr + 1, buffer) {
+            self.finish(Err(error));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use capsules_core::virtualizers::virtual_i2c::MuxI2C;
+    use kernel::hil::i2c::{I2CHwMasterClient, I2CMaster};
+    use std::boxed::Box;
+
+    struct MockI2C {
+        pending: TakeCell<'static, [u8]>,
+        addr: Cell<u8>,
+    }
+
+    impl<'a> I2CMaster<'a> for MockI2C {
+        fn set_master_client(&self, _master_client: &'a dyn I2CHwMasterClient) {}
+        fn enable(&self) {}
+        fn disable(&self) {}
+        fn write_read(
+            &self,
+            _addr: u8,
+            data: &'static mut [u8],
+            _write_len: usize,
+            _read_len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            Err((Error::NotSupported, data))
+        }
+        fn write(
+            &self,
+            addr: u8,
+            data: &'static mut [u8],
+            _len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            self.addr.set(addr);
+            self.pending.replace(data);
+            Ok(())
+        }
+        fn read(
+            &self,
+            _addr: u8,
+            buffer: &'static mut [u8],
+            _len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            Err((Error::NotSupported, buffer))
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        done: Cell<Option<(u128, Result<(), ErrorCode>)>>,
+    }
+
+    impl I2CScanClient for MockClient {
+        fn scan_done(&self, found: u128, result: Result<(), ErrorCode>) {
+            self.done.set(Some((found, result)));
+        }
+    }
+
+    fn setup() -> (
+        &'static MockI2C,
+        &'static MuxI2C<'static, MockI2C>,
+        &'static I2CScanner<'static, MockI2C>,
+        &'static MockClient,
+    ) {
+        let i2c = Box::leak(Box::new(MockI2C {
+            pending: TakeCell::empty(),
+            addr: Cell::new(0),
+        }));
+        let mux = Box::leak(Box::new(MuxI2C::new(i2c, None)));
+        let device = Box::leak(Box::new(I2CDevice::new(mux, 0)));
+        let scanner = Box::leak(Box::new(I2CScanner::new(
+            device,
+            Box::leak(Box::new([0; 1])),
+        )));
+        device.set_client(scanner);
+        let client = Box::leak(Box::new(MockClient::default()));
+        scanner.set_client(client);
+        (i2c, mux, scanner, client)
+    }
+
+    /// Completes outstanding probes, acknowledging the addresses in `acks`,
+    /// until the bus is idle.
+    fn run(i2c: &MockI2C, mux: &MuxI2C<'static, MockI2C>, acks: &[u8], error_at: Option<u8>) {
+        while let Some(buffer) = i2c.pending.take() {
+            let addr = i2c.addr.get();
+            let status = if Some(addr) == error_at {
+                Err(Error::ArbitrationLost)
+            } else if acks.contains(&addr) {
+                Ok(())
+            } else {
+                Err(Error::AddressNak)
+            };
+            mux.command_complete(buffer, status);
+        }
+    }
+
+    #[test]
+    fn scan_finds_devices() {
+        let (i2c, mux, scanner, client) = setup();
+        assert_eq!(scanner.scan(0x10, 0x20), Ok(()));
+        assert_eq!(scanner.scan(0x10, 0x20), Err(ErrorCode::BUSY));
+        run(i2c, mux, &[0x0F, 0x12, 0x1D, 0x21], None);
+        assert_eq!(client.done.get(), Some((1 << 0x12 | 1 << 0x1D, Ok(()))));
+
+        // The scanner can be used again.
+        assert_eq!(scanner.scan(0x08, 0x08), Ok(()));
+        run(i2c, mux, &[], None);
+        assert_eq!(client.done.get(), Some((0, Ok(()))));
+    }
+
+    #[test]
+    fn scan_aborts_on_bus_error() {
+        let (i2c, mux, scanner, client) = setup();
+        assert_eq!(scanner.scan(0x70, 0x80), Err(ErrorCode::INVAL));
+        assert_eq!(scanner.scan(0x10, 0x0F), Err(ErrorCode::INVAL));
+
+        assert_eq!(scanner.scan(0x10, 0x20), Ok(()));
+        run(i2c, mux, &[0x11], Some(0x14));
+        assert_eq!(
+            client.done.get(),
+            Some((1 << 0x11, Err(ErrorCode::RESERVE)))
+        );
+        assert_eq!(scanner.scan(0x10, 0x20), Ok(()));
+    }
+}
@@ -17,6 +17,7 @@
 
 use crate::ieee802154::framer::Frame;
 use crate::net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
+use kernel::hil::radio;
 use kernel::ErrorCode;
 
 pub trait MacDevice<'a> {
@@ -77,6 +78,24 @@ pub trait MacDevice<'a> {
     /// transmission process fails, the buffer inside the frame is returned so
     /// that it can be re-used.
     fn transmit(&self, frame: Frame) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Sets the client notified when energy detection finishes. MAC devices
+    /// that do not support energy detection ignore the client.
+    fn set_energy_detect_client(&self, _client: &'a dyn radio::EnergyDetectClient) {}
+
+    /// Measures the energy on the current channel without transmitting. The
+    /// result, in dBm, is passed to the energy detect client. Returns `BUSY`
+    /// if a frame is being transmitted or received, and `NOSUPPORT` if the
+    /// MAC device cannot measure the channel energy.
+    fn energy_detect(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    /// The number of received frames dropped because their length was
+    /// invalid, for example longer than the receive buffer.
+    fn rx_dropped(&self) -> usize {
+        0
+    }
 }
 
 /// Trait to be implemented by any user of the IEEE 802.15.4 device that
@@ -111,6 +130,8 @@ pub trait RxClient {
     /// caveat that the auxiliary security header is still included if the frame
     /// was previously secured.
     /// - `lqi`: The link quality indicator of the received frame.
+    /// - `rssi`: The received signal strength of the frame in dBm, or `None`
+    /// if the radio does not measure it.
     /// - `data_offset`: Offset of the data payload relative to
     /// `buf`, so that the payload of the frame is contained in
     /// `buf[data_offset..data_offset + data_len]`.
@@ -120,6 +141,7 @@ pub trait RxClient {
         buf: &'a [u8],
         header: Header<'a>,
         lqi: u8,
+        rssi: Option<i8>,
         data_offset: usize,
         data_len: usize,
     );
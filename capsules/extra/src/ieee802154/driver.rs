@@ -75,12 +75,16 @@ const USER_FRAME_MAX_SIZE: usize = USER_FRAME_METADATA_SIZE + radio::MAX_FRAME_S
 
 /// IDs for subscribed upcalls.
 mod upcall {
-    /// Frame is received
+    /// Frame is received, with its LQI, its RSSI in dBm as a signed value,
+    /// and 1 if the RSSI was measured or 0 if the radio does not measure it.
     pub const FRAME_RECEIVED: usize = 0;
     /// Frame is transmitted
     pub const FRAME_TRANSMITTED: usize = 1;
+    /// Energy detection finished, with a status code and the energy on the
+    /// channel in dBm as a signed value.
+    pub const ENERGY_DETECTED: usize = 2;
     /// Number of upcalls.
-    pub const COUNT: u8 = 2;
+    pub const COUNT: u8 = 3;
 }
 
 /// Ids for read-only allow buffers
@@ -221,6 +225,8 @@ impl KeyDescriptor {
 #[derive(Default)]
 pub struct App {
     pending_tx: Option<(u16, Option<(SecurityLevel, KeyId)>)>,
+    /// The app is waiting for the result of energy detection.
+    pending_energy_detect: bool,
 }
 
 pub struct RadioDriver<'a, M: device::MacDevice<'a>> {
@@ -248,6 +254,11 @@ pub struct RadioDriver<'a, M: device::MacDevice<'a>> {
     >,
     /// ID of app whose transmission request is being processed.
     current_app: OptionalCell<ProcessId>,
+    /// Energy detection has been started on the MAC device.
+    energy_detecting: Cell<bool>,
+    /// Number of received frames dropped here because their length did not
+    /// fit in the receive buffer or in a user frame.
+    rx_dropped: Cell<usize>,
 
     /// Buffer that stores the IEEE 802.15.4 frame to be transmitted.
     kernel_tx: TakeCell<'static, [u8]>,
@@ -287,6 +298,8 @@ impl<'a, M: device::MacDevice<'a>> RadioDriver<'a, M> {
             num_keys: Cell::new(0),
             apps: grant,
             current_app: OptionalCell::empty(),
+            energy_detecting: Cell::new(false),
+            rx_dropped: Cell::new(0),
             kernel_tx: TakeCell::new(kernel_tx),
             deferred_call: DeferredCall::new(),
             saved_processid: OptionalCell::empty(),
@@ -414,6 +427,23 @@ impl<'a, M: device::MacDevice<'a>> RadioDriver<'a, M> {
         }
     }
 
+    /// Starts energy detection for an app, or adds the app to the energy
+    /// detection in progress.
+    fn energy_detect(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        if self.current_app.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if !self.energy_detecting.get() {
+            self.mac.energy_detect()?;
+            self.energy_detecting.set(true);
+        }
+        self.apps
+            .enter(processid, |app, _| {
+                app.pending_energy_detect = true;
+            })
+            .map_err(ErrorCode::from)
+    }
+
     /// If the driver is currently idle and there are pending transmissions,
     /// pick an app with a pending transmission and return its `ProcessId`.
     fn get_next_tx_if_idle(&self) -> Option<ProcessId> {
@@ -668,6 +698,11 @@ impl<'a, M: device::MacDevice<'a>> SyscallDriver for RadioDriver<'a, M> {
     ///        parameters to encrypt, form headers, and transmit the frame.
     /// - `28`: Set long address.
     /// - `29`: Get the long MAC address.
+    /// - `30`: Measure the energy on the current channel. The result is
+    ///        delivered through the energy detected upcall. Returns `BUSY`
+    ///        while a frame is being transmitted or received.
+    /// - `31`: Get the number of received frames that were dropped because
+    ///        their length was invalid.
     fn command(
         &self,
         command_number: usize,
@@ -967,6 +1002,11 @@ impl<'a, M: device::MacDevice<'a>> SyscallDriver for RadioDriver<'a, M> {
                 let addr = u64::from_be_bytes(self.mac.get_address_long());
                 CommandReturn::success_u64(addr)
             }
+            30 => self.energy_detect(processid).into(),
+            31 => {
+                let dropped = self.mac.rx_dropped().wrapping_add(self.rx_dropped.get());
+                CommandReturn::success_u32(dropped as u32)
+            }
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -976,6 +1016,24 @@ impl<'a, M: device::MacDevice<'a>> SyscallDriver for RadioDriver<'a, M> {
     }
 }
 
+impl<'a, M: device::MacDevice<'a>> radio::EnergyDetectClient for RadioDriver<'a, M> {
+    fn energy_detect_done(&self, result: Result<i8, ErrorCode>) {
+        self.energy_detecting.set(false);
+        let (status, dbm) = match result {
+            Ok(dbm) => (kernel::errorcode::into_statuscode(Ok(())), dbm as usize),
+            Err(err) => (kernel::errorcode::into_statuscode(Err(err)), 0),
+        };
+        self.apps.each(|_, app, upcalls| {
+            if app.pending_energy_detect {
+                app.pending_energy_detect = false;
+                upcalls
+                    .schedule_upcall(upcall::ENERGY_DETECTED, (status, dbm, 0))
+                    .ok();
+            }
+        });
+    }
+}
+
 impl<'a, M: device::MacDevice<'a>> device::TxClient for RadioDriver<'a, M> {
     fn send_done(&self, spi_buf: &'static mut [u8], acked: bool, result: Result<(), ErrorCode>) {
         self.kernel_tx.replace(spi_buf);
@@ -1003,9 +1061,21 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for RadioDriver<'a, M> {
         buf: &'b [u8],
         header: Header<'b>,
         lqi: u8,
+        rssi: Option<i8>,
         data_offset: usize,
         data_len: usize,
     ) {
+        let mic_len = header.security.map_or(0, |sec| sec.level.mic_len());
+        let frame_len = data_offset + data_len + mic_len;
+
+        // Do not trust the lengths of the frame any further than the buffer
+        // it was received in. Every user frame in the ring buffers holds at
+        // most `MAX_FRAME_SIZE` bytes.
+        if frame_len > buf.len() || frame_len > radio::MAX_FRAME_SIZE {
+            self.rx_dropped.set(self.rx_dropped.get().wrapping_add(1));
+            return;
+        }
+
         self.apps.each(|_, _, kernel_data| {
             let read_present = kernel_data
                 .get_readwrite_processbuffer(rw_allow::READ)
@@ -1038,9 +1108,6 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for RadioDriver<'a, M> {
                             return false;
                         }
 
-                        let mic_len = header.security.map_or(0, |sec| sec.level.mic_len());
-                        let frame_len = data_offset + data_len + mic_len;
-
                         let mut read_index = rbuf[0].get() as usize;
                         let mut write_index = rbuf[1].get() as usize;
 
@@ -1085,9 +1152,13 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for RadioDriver<'a, M> {
                 })
                 .unwrap_or(false);
             if read_present {
-                // Place lqi as argument to be included in upcall.
+                // Place lqi and rssi as arguments to be included in upcall.
+                let rssi_args = rssi.map_or((0, 0), |rssi| (rssi as usize, 1));
                 kernel_data
-                    .schedule_upcall(upcall::FRAME_RECEIVED, (lqi as usize, 0, 0))
+                    .schedule_upcall(
+                        upcall::FRAME_RECEIVED,
+                        (lqi as usize, rssi_args.0, rssi_args.1),
+                    )
                     .ok();
             }
         });
@@ -301,16 +301,16 @@ enum RxState {
     /// There is no frame that has been received.
     Idle,
     /// There is a secured frame that needs to be decrypted.
-    /// ReadyToDecrypt(FrameInfo, buf, lqi)
-    ReadyToDecrypt(FrameInfo, &'static mut [u8], u8),
+    /// ReadyToDecrypt(FrameInfo, buf, lqi, rssi)
+    ReadyToDecrypt(FrameInfo, &'static mut [u8], u8, Option<i8>),
     /// A secured frame is currently being decrypted by the decryption facility.
-    /// Decrypting(FrameInfo, lqi)
+    /// Decrypting(FrameInfo, lqi, rssi)
     #[allow(dead_code)]
-    Decrypting(FrameInfo, u8),
+    Decrypting(FrameInfo, u8, Option<i8>),
     /// There is an unsecured frame that needs to be re-parsed and exposed to
-    /// the client. ReadyToYield(FrameInfo, buf, lqi)
+    /// the client. ReadyToYield(FrameInfo, buf, lqi, rssi)
     #[allow(dead_code)]
-    ReadyToYield(FrameInfo, &'static mut [u8], u8),
+    ReadyToYield(FrameInfo, &'static mut [u8], u8, Option<i8>),
 }
 
 /// This struct wraps an IEEE 802.15.4 radio device `kernel::hil::radio::Radio`
@@ -342,6 +342,9 @@ pub struct Framer<'a, M: Mac<'a>, A: AES128CCM<'a>> {
     rx_state: MapCell<RxState>,
     rx_client: OptionalCell<&'a dyn RxClient>,
     crypt_buf: MapCell<SubSliceMut<'static, u8>>,
+    /// Number of received frames dropped because their length does not fit
+    /// in the receive buffer or is shorter than their MAC header.
+    rx_dropped: Cell<usize>,
 }
 
 impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
@@ -361,6 +364,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
             rx_state: MapCell::new(RxState::Idle),
             rx_client: OptionalCell::empty(),
             crypt_buf: MapCell::new(crypt_buf),
+            rx_dropped: Cell::new(0),
         }
     }
 
@@ -409,6 +413,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
         buf: &'static mut [u8],
         frame_len: usize,
         lqi: u8,
+        rssi: Option<i8>,
     ) -> RxState {
         // Try to decode the MAC header. Three possible results can occur:
         // 1) The frame should be dropped and the buffer returned to the radio
@@ -434,7 +439,14 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                 // exposing it to the user. At that time, the data payload field
                 // will not include the payload IEs.
                 let mic_len = header.security.map_or(0, |sec| sec.level.mic_len());
-                let data_len = frame_len - data_offset - mic_len;
+                let data_len = match frame_len.checked_sub(data_offset + mic_len) {
+                    Some(data_len) => data_len,
+                    None => {
+                        // The header decoded past the end of the frame.
+                        self.rx_dropped.set(self.rx_dropped.get().wrapping_add(1));
+                        return None;
+                    }
+                };
                 if let Some(security) = header.security {
                     // IEEE 802.15.4-2015: 9.2.3, incoming frame security procedure
                     // for security-enabled headers
@@ -503,7 +515,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                     // byte. We only pass the 15.4 packet up the stack and slice buf accordingly.
                     let frame_buffer = &buf[radio::PSDU_OFFSET..(buf.len() - LQI_SIZE)];
                     self.rx_client.map(|client| {
-                        client.receive(frame_buffer, header, lqi, data_offset, data_len);
+                        client.receive(frame_buffer, header, lqi, rssi, data_offset, data_len);
                     });
                     None
                 }
@@ -516,7 +528,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                 self.mac.set_receive_buffer(buf);
                 RxState::Idle
             }
-            Some(frame_info) => RxState::ReadyToDecrypt(frame_info, buf, lqi),
+            Some(frame_info) => RxState::ReadyToDecrypt(frame_info, buf, lqi, rssi),
         }
     }
 
@@ -597,7 +609,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
         self.rx_state.take().map(|state| {
             let next_state = match state {
                 RxState::Idle => RxState::Idle,
-                RxState::ReadyToDecrypt(info, buf, lqi) => {
+                RxState::ReadyToDecrypt(info, buf, lqi, rssi) => {
                     match info.security_params {
                         None => {
                             // `ReadyToDecrypt` should only be entered when
@@ -657,11 +669,11 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                                     // Scenario 1
                                     Some(Ok(())) => {
                                         self.mac.set_receive_buffer(buf);
-                                        RxState::Decrypting(info, lqi)
+                                        RxState::Decrypting(info, lqi, rssi)
                                     }
                                     // Scenario 2
                                     Some(Err((ErrorCode::BUSY, buf))) => {
-                                        RxState::ReadyToDecrypt(info, buf, lqi)
+                                        RxState::ReadyToDecrypt(info, buf, lqi, rssi)
                                     }
                                     // Scenario 3
                                     Some(Err((_, fail_crypt_buf))) => {
@@ -679,12 +691,12 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                         }
                     }
                 }
-                RxState::Decrypting(info, lqi) => {
+                RxState::Decrypting(info, lqi, rssi) => {
                     // This state should be advanced only by the hardware
                     // encryption callback.
-                    RxState::Decrypting(info, lqi)
+                    RxState::Decrypting(info, lqi, rssi)
                 }
-                RxState::ReadyToYield(info, buf, lqi) => {
+                RxState::ReadyToYield(info, buf, lqi, rssi) => {
                     // Between the secured and unsecured frames, the
                     // unsecured frame length remains constant but the data
                     // offsets may change due to the presence of PayloadIEs.
@@ -714,6 +726,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> Framer<'a, M, A> {
                                 frame_buffer,
                                 header,
                                 lqi,
+                                rssi,
                                 data_offset,
                                 frame_len - data_offset,
                             );
@@ -874,6 +887,25 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> MacDevice<'a> for Framer<'a, M, A> {
             }
         }
     }
+
+    fn set_energy_detect_client(&self, client: &'a dyn radio::EnergyDetectClient) {
+        self.mac.set_energy_detect_client(client);
+    }
+
+    fn energy_detect(&self) -> Result<(), ErrorCode> {
+        // A frame being secured is about to be transmitted.
+        let tx_idle = self
+            .tx_state
+            .map_or(false, |state| matches!(state, TxState::Idle));
+        if !tx_idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.mac.energy_detect()
+    }
+
+    fn rx_dropped(&self) -> usize {
+        self.rx_dropped.get()
+    }
 }
 
 impl<'a, M: Mac<'a>, A: AES128CCM<'a>> radio::TxClient for Framer<'a, M, A> {
@@ -891,6 +923,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> radio::RxClient for Framer<'a, M, A> {
         buf: &'static mut [u8],
         frame_len: usize,
         lqi: u8,
+        rssi: Option<i8>,
         crc_valid: bool,
         _: Result<(), ErrorCode>,
     ) {
@@ -900,12 +933,24 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> radio::RxClient for Framer<'a, M, A> {
             return;
         }
 
+        // The length comes from the PHR of the frame as it was received, so
+        // it cannot be trusted. Drop frames that claim to be longer than the
+        // standard allows or than the buffer they were received in, before
+        // anything slices the buffer with their length.
+        if frame_len + radio::MFR_SIZE > radio::MAX_FRAME_SIZE
+            || radio::PSDU_OFFSET + frame_len + LQI_SIZE > buf.len()
+        {
+            self.rx_dropped.set(self.rx_dropped.get().wrapping_add(1));
+            self.mac.set_receive_buffer(buf);
+            return;
+        }
+
         self.rx_state.take().map(move |state| {
             let next_state = match state {
                 RxState::Idle => {
                     // We can start processing a new received frame only if
                     // the reception pipeline is free
-                    self.incoming_frame_security(buf, frame_len, lqi)
+                    self.incoming_frame_security(buf, frame_len, lqi, rssi)
                 }
                 other_state => {
                     // This should never occur unless something other than
@@ -981,9 +1026,9 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
         if let Some(buf) = opt_buf {
             self.rx_state.take().map(|state| {
                 match state {
-                    RxState::Decrypting(info, lqi) => {
+                    RxState::Decrypting(info, lqi, rssi) => {
                         let next_state = if tag_is_valid {
-                            RxState::ReadyToYield(info, buf, lqi)
+                            RxState::ReadyToYield(info, buf, lqi, rssi)
                         } else {
                             // The CRC tag is invalid, meaning the packet was corrupted. Drop this packet
                             // and reset reception pipeline
@@ -995,7 +1040,7 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
                     }
                     other_state => {
                         rx_waiting = match other_state {
-                            RxState::ReadyToDecrypt(_, _, _) => true,
+                            RxState::ReadyToDecrypt(..) => true,
                             _ => false,
                         };
                         self.rx_state.replace(other_state);
@@ -1017,3 +1062,164 @@ impl<'a, M: Mac<'a>, A: AES128CCM<'a>> CCMClient for Framer<'a, M, A> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use kernel::hil::radio::RxClient as _;
+    use kernel::utilities::cells::TakeCell;
+    use std::boxed::Box;
+
+    /// A MAC layer that only keeps the receive buffer it is given back.
+    struct MockMac {
+        rx_buf: TakeCell<'static, [u8]>,
+    }
+
+    impl Mac<'static> for MockMac {
+        fn initialize(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn set_config_client(&self, _client: &'static dyn radio::ConfigClient) {}
+        fn set_transmit_client(&self, _client: &'static dyn radio::TxClient) {}
+        fn set_receive_client(&self, _client: &'static dyn radio::RxClient) {}
+        fn set_receive_buffer(&self, buffer: &'static mut [u8]) {
+            self.rx_buf.replace(buffer);
+        }
+        fn get_address(&self) -> u16 {
+            0
+        }
+        fn get_address_long(&self) -> [u8; 8] {
+            [0; 8]
+        }
+        fn get_pan(&self) -> u16 {
+            0
+        }
+        fn set_address(&self, _addr: u16) {}
+        fn set_address_long(&self, _addr: [u8; 8]) {}
+        fn set_pan(&self, _id: u16) {}
+        fn config_commit(&self) {}
+        fn is_on(&self) -> bool {
+            true
+        }
+        fn transmit(
+            &self,
+            full_mac_frame: &'static mut [u8],
+            _frame_len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            Err((ErrorCode::FAIL, full_mac_frame))
+        }
+    }
+
+    struct MockCcm;
+
+    impl AES128CCM<'static> for MockCcm {
+        fn set_client(&'static self, _client: &'static dyn CCMClient) {}
+        fn set_key(&self, _key: &[u8]) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn set_nonce(&self, _nonce: &[u8]) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn crypt(
+            &self,
+            buf: &'static mut [u8],
+            _a_off: usize,
+            _m_off: usize,
+            _m_len: usize,
+            _mic_len: usize,
+            _confidential: bool,
+            _encrypting: bool,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            Err((ErrorCode::NOSUPPORT, buf))
+        }
+    }
+
+    #[derive(Default)]
+    struct MockRxClient {
+        received: Cell<usize>,
+    }
+
+    impl RxClient for MockRxClient {
+        fn receive<'b>(
+            &self,
+            _buf: &'b [u8],
+            _header: Header<'b>,
+            _lqi: u8,
+            _rssi: Option<i8>,
+            _data_offset: usize,
+            _data_len: usize,
+        ) {
+            self.received.set(self.received.get() + 1);
+        }
+    }
+
+    type TestFramer = Framer<'static, MockMac, MockCcm>;
+
+    fn framer() -> (&'static TestFramer, &'static MockMac, &'static MockRxClient) {
+        let mac = Box::leak(Box::new(MockMac {
+            rx_buf: TakeCell::empty(),
+        }));
+        let crypt_buf = Box::leak(Box::new([0; radio::MAX_BUF_SIZE]));
+        let framer = Box::leak(Box::new(Framer::new(
+            mac,
+            Box::leak(Box::new(MockCcm)),
+            SubSliceMut::new(crypt_buf),
+        )));
+        let client = Box::leak(Box::new(MockRxClient::default()));
+        framer.set_receive_client(client);
+        (framer, mac, client)
+    }
+
+    /// Data frame with short addresses and a compressed PAN ID, whose MAC
+    /// header is 9 bytes long.
+    const DATA_HEADER: [u8; 9] = [0x41, 0x88, 0x01, 0xcd, 0xab, 0xff, 0xff, 0x01, 0x00];
+
+    #[test]
+    fn oversized_frame_dropped() {
+        const GUARD: u8 = 0xa5;
+        let (framer, mac, client) = framer();
+
+        // The receive buffer is followed by guard bytes, which must survive
+        // the frame.
+        let memory = Box::leak(Box::new([GUARD; radio::MAX_BUF_SIZE + 16]));
+        let (buf, guard) = memory.split_at_mut(radio::MAX_BUF_SIZE);
+        buf[radio::PHR_OFFSET] = 0xff;
+        framer.receive(buf, 0xff - radio::MFR_SIZE, 0xff, None, true, Ok(()));
+
+        assert_eq!(client.received.get(), 0);
+        assert_eq!(framer.rx_dropped(), 1);
+        assert!(guard.iter().all(|&byte| byte == GUARD));
+        let buf = mac.rx_buf.take().expect("buffer not returned to the MAC");
+        assert_eq!(buf.len(), radio::MAX_BUF_SIZE);
+        assert!(buf[radio::PSDU_OFFSET..].iter().all(|&byte| byte == GUARD));
+
+        // A length that fits the standard but not a smaller buffer is
+        // dropped too.
+        let buf = Box::leak(Box::new([0; 32]));
+        framer.receive(buf, 64, 0xff, None, true, Ok(()));
+        assert_eq!(client.received.get(), 0);
+        assert_eq!(framer.rx_dropped(), 2);
+        assert!(mac.rx_buf.is_some());
+    }
+
+    #[test]
+    fn frame_shorter_than_header_dropped() {
+        let (framer, mac, client) = framer();
+
+        let buf = Box::leak(Box::new([0; radio::MAX_BUF_SIZE]));
+        buf[radio::PSDU_OFFSET..radio::PSDU_OFFSET + DATA_HEADER.len()]
+            .copy_from_slice(&DATA_HEADER);
+        framer.receive(buf, 4, 0xff, None, true, Ok(()));
+        assert_eq!(client.received.get(), 0);
+        assert_eq!(framer.rx_dropped(), 1);
+
+        // The same frame with its full length is received.
+        let buf = mac.rx_buf.take().unwrap();
+        framer.receive(buf, DATA_HEADER.len() + 3, 0xff, None, true, Ok(()));
+        assert_eq!(client.received.get(), 1);
+        assert_eq!(framer.rx_dropped(), 1);
+        assert!(mac.rx_buf.is_some());
+    }
+}
@@ -62,6 +62,17 @@ pub trait Mac<'a> {
         full_mac_frame: &'static mut [u8],
         frame_len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Sets the notified client for energy detection completions. MAC
+    /// protocols that do not support energy detection ignore the client.
+    fn set_energy_detect_client(&self, _client: &'a dyn radio::EnergyDetectClient) {}
+
+    /// Measures the energy on the current channel without transmitting.
+    /// Returns `NOSUPPORT` if the MAC protocol does not allow it, for example
+    /// because it may have turned the radio off.
+    fn energy_detect(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
 
 ///
@@ -160,6 +171,14 @@ impl<'a, R: radio::Radio<'a>> Mac<'a> for AwakeMac<'a, R> {
         full_mac_frame.copy_within(0..frame_len, PSDU_OFFSET);
         self.radio.transmit(full_mac_frame, frame_len)
     }
+
+    fn set_energy_detect_client(&self, client: &'a dyn radio::EnergyDetectClient) {
+        self.radio.set_energy_detect_client(client)
+    }
+
+    fn energy_detect(&self) -> Result<(), ErrorCode> {
+        self.radio.energy_detect()
+    }
 }
 
 impl<'a, R: radio::Radio<'a>> radio::TxClient for AwakeMac<'a, R> {
@@ -176,6 +195,7 @@ impl<'a, R: radio::Radio<'a>> radio::RxClient for AwakeMac<'a, R> {
         buf: &'static mut [u8],
         frame_len: usize,
         lqi: u8,
+        rssi: Option<i8>,
         crc_valid: bool,
         result: Result<(), ErrorCode>,
     ) {
@@ -195,7 +215,7 @@ impl<'a, R: radio::Radio<'a>> radio::RxClient for AwakeMac<'a, R> {
         if addr_match {
             // debug!("[AwakeMAC] Rcvd a 15.4 frame addressed to this device");
             self.rx_client.map(move |c| {
-                c.receive(buf, frame_len, lqi, crc_valid, result);
+                c.receive(buf, frame_len, lqi, rssi, crc_valid, result);
             });
         } else {
             // debug!("[AwakeMAC] Received a packet, but not addressed to us");
@@ -36,6 +36,8 @@
 //! "dropped" packets, this may be the cause. The process can mitigate this
 //! issue by increasing the size of the ring buffer provided to the capsule.
 
+use core::cell::Cell;
+
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
 use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
@@ -45,7 +47,8 @@ use kernel::{ErrorCode, ProcessId};
 
 /// IDs for subscribed upcalls.
 mod upcall {
-    /// Frame is received
+    /// Frame is received, with its LQI, its RSSI in dBm as a signed value,
+    /// and 1 if the RSSI was measured or 0 if the radio does not measure it.
     pub const FRAME_RECEIVED: usize = 0;
     /// Frame is transmitted
     pub const FRAME_TRANSMITTED: usize = 1;
@@ -93,6 +96,10 @@ pub struct RadioDriver<'a, R: hil::radio::Radio<'a>> {
 
     /// Buffer that stores the IEEE 802.15.4 frame to be transmitted.
     kernel_tx: TakeCell<'static, [u8]>,
+
+    /// Number of received frames dropped because their length did not fit
+    /// in the receive buffer or in a user frame.
+    rx_dropped: Cell<usize>,
 }
 
 impl<'a, R: hil::radio::Radio<'a>> RadioDriver<'a, R> {
@@ -111,6 +118,7 @@ impl<'a, R: hil::radio::Radio<'a>> RadioDriver<'a, R> {
             apps: grant,
             current_app: OptionalCell::empty(),
             kernel_tx: TakeCell::new(kernel_tx),
+            rx_dropped: Cell::new(0),
         }
     }
 
@@ -208,6 +216,8 @@ impl<'a, R: hil::radio::Radio<'a>> SyscallDriver for RadioDriver<'a, R> {
     /// - `29`: Get the long MAC address.
     /// - `30`: Turn the radio on.
     /// - `31`: Turn the radio off.
+    /// - `32`: Get the number of received frames that were dropped because
+    ///   their length was invalid.
     fn command(
         &self,
         command_number: usize,
@@ -293,6 +303,7 @@ impl<'a, R: hil::radio::Radio<'a>> SyscallDriver for RadioDriver<'a, R> {
             }
             30 => self.radio.start().into(),
             31 => self.radio.stop().into(),
+            32 => CommandReturn::success_u32(self.rx_dropped.get() as u32),
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -325,6 +336,7 @@ impl<'a, R: hil::radio::Radio<'a>> hil::radio::RxClient for RadioDriver<'a, R> {
         buf: &'static mut [u8],
         frame_len: usize,
         lqi: u8,
+        rssi: Option<i8>,
         crc_valid: bool,
         result: Result<(), ErrorCode>,
     ) {
@@ -335,6 +347,17 @@ impl<'a, R: hil::radio::Radio<'a>> hil::radio::RxClient for RadioDriver<'a, R> {
             return;
         }
 
+        // The length comes from the PHR of the received frame. Drop frames
+        // that claim to be longer than the standard allows or than the
+        // buffer they were received in.
+        if frame_len + hil::radio::MFR_SIZE > hil::radio::MAX_FRAME_SIZE
+            || hil::radio::PSDU_OFFSET + frame_len > buf.len()
+        {
+            self.rx_dropped.set(self.rx_dropped.get().wrapping_add(1));
+            self.radio.set_receive_buffer(buf);
+            return;
+        }
+
         self.apps.each(|_, _, kernel_data| {
             let read_present = kernel_data
                 .get_readwrite_processbuffer(rw_allow::READ)
@@ -440,9 +463,13 @@ impl<'a, R: hil::radio::Radio<'a>> hil::radio::RxClient for RadioDriver<'a, R> {
                 })
                 .unwrap_or(false);
             if read_present {
-                // Place lqi as argument to be included in upcall.
+                // Place lqi and rssi as arguments to be included in upcall.
+                let rssi_args = rssi.map_or((0, 0), |rssi| (rssi as usize, 1));
                 kernel_data
-                    .schedule_upcall(upcall::FRAME_RECEIVED, (lqi as usize, 0, 0))
+                    .schedule_upcall(
+                        upcall::FRAME_RECEIVED,
+                        (lqi as usize, rssi_args.0, rssi_args.1),
+                    )
                     .ok();
             }
         });
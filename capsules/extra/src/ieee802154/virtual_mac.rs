@@ -12,6 +12,10 @@
 //! Every radio frame received is provided to all listening clients so that each
 //! client can perform its own frame filtering logic.
 //!
+//! Energy detection on the channel is sequenced with the transmissions: a user
+//! can only start one while no frame is in flight, and transmissions wait
+//! until it has finished. The result goes to the user that asked for it.
+//!
 //! Usage
 //! -----
 //!
@@ -24,6 +28,7 @@
 //!     capsules::ieee802154::virtual_mac::MuxMac::new(&'static mac_device));
 //! mac_device.set_transmit_client(mux_mac);
 //! mac_device.set_receive_client(mux_mac);
+//! mac_device.set_energy_detect_client(mux_mac);
 //!
 //! // Everything that uses the virtualized MAC device must create one of these.
 //! let virtual_mac = static_init!(
@@ -36,6 +41,7 @@ use crate::ieee802154::{device, framer};
 use crate::net::ieee802154::{Header, KeyId, MacAddress, PanID, SecurityLevel};
 
 use kernel::collections::list::{List, ListLink, ListNode};
+use kernel::hil::radio;
 use kernel::utilities::cells::{MapCell, OptionalCell};
 use kernel::ErrorCode;
 
@@ -46,6 +52,8 @@ pub struct MuxMac<'a, M: device::MacDevice<'a>> {
     mac: &'a M,
     users: List<'a, MacUser<'a, M>>,
     inflight: OptionalCell<&'a MacUser<'a, M>>,
+    /// User whose energy detection is in progress.
+    energy_detect_user: OptionalCell<&'a MacUser<'a, M>>,
 }
 
 impl<'a, M: device::MacDevice<'a>> device::TxClient for MuxMac<'a, M> {
@@ -63,26 +71,45 @@ impl<'a, M: device::MacDevice<'a>> device::RxClient for MuxMac<'a, M> {
         buf: &'b [u8],
         header: Header<'b>,
         lqi: u8,
+        rssi: Option<i8>,
         data_offset: usize,
         data_len: usize,
     ) {
         for user in self.users.iter() {
-            user.receive(buf, header, lqi, data_offset, data_len);
+            user.receive(buf, header, lqi, rssi, data_offset, data_len);
         }
     }
 }
 
+impl<'a, M: device::MacDevice<'a>> radio::EnergyDetectClient for MuxMac<'a, M> {
+    fn energy_detect_done(&self, result: Result<i8, ErrorCode>) {
+        self.energy_detect_user.take().map(|user| {
+            user.energy_detect_client
+                .map(|client| client.energy_detect_done(result));
+        });
+        self.do_next_op_async();
+    }
+}
+
 impl<'a, M: device::MacDevice<'a>> MuxMac<'a, M> {
     pub const fn new(mac: &'a M) -> MuxMac<'a, M> {
         MuxMac {
             mac,
             users: List::new(),
             inflight: OptionalCell::empty(),
+            energy_detect_user: OptionalCell::empty(),
         }
     }
 
     /// Registers a MAC user with this MAC mux device. Each MAC user should only
     /// be registered once.
+    ///
+    /// A user must be registered before it transmits, as the mux only looks
+    /// for pending operations on registered users, and it only receives the
+    /// frames that arrive after it is registered. Users can be added at any
+    /// point during board setup. Users are searched for pending transmissions
+    /// starting with the one registered last, so when several users have a
+    /// frame queued the most recently registered user sends first.
     pub fn add_user(&self, user: &'a MacUser<'a, M>) {
         self.users.push_head(user);
     }
@@ -90,7 +117,7 @@ impl<'a, M: device::MacDevice<'a>> MuxMac<'a, M> {
     /// Gets the next `MacUser` and operation to perform if an operation is not
     /// already underway.
     fn get_next_op_if_idle(&self) -> Option<(&'a MacUser<'a, M>, Op)> {
-        if self.inflight.is_some() {
+        if self.inflight.is_some() || self.energy_detect_user.is_some() {
             return None;
         }
 
@@ -165,6 +192,25 @@ impl<'a, M: device::MacDevice<'a>> MuxMac<'a, M> {
     ///
     /// If the newly-enqueued transmission is immediately executed by this mux
     /// device but fails immediately, return the buffer synchronously.
+    /// Starts energy detection for `user` if no frame is in flight and no
+    /// other energy detection is in progress.
+    fn energy_detect(&self, user: &MacUser<'a, M>) -> Result<(), ErrorCode> {
+        if self.inflight.is_some() || self.energy_detect_user.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        // As in `do_next_op_sync`, the user is found in the list by comparing
+        // raw pointers, since only references from the list live long enough
+        // to be kept.
+        let user = self
+            .users
+            .iter()
+            .find(|node| core::ptr::eq(*node, user))
+            .ok_or(ErrorCode::FAIL)?;
+        self.mac.energy_detect()?;
+        self.energy_detect_user.set(user);
+        Ok(())
+    }
+
     fn do_next_op_sync(
         &self,
         new_node: &MacUser<'a, M>,
@@ -203,6 +249,7 @@ pub struct MacUser<'a, M: device::MacDevice<'a>> {
     next: ListLink<'a, MacUser<'a, M>>,
     tx_client: OptionalCell<&'a dyn device::TxClient>,
     rx_client: OptionalCell<&'a dyn device::RxClient>,
+    energy_detect_client: OptionalCell<&'a dyn radio::EnergyDetectClient>,
 }
 
 impl<'a, M: device::MacDevice<'a>> MacUser<'a, M> {
@@ -213,6 +260,7 @@ impl<'a, M: device::MacDevice<'a>> MacUser<'a, M> {
             next: ListLink::empty(),
             tx_client: OptionalCell::empty(),
             rx_client: OptionalCell::empty(),
+            energy_detect_client: OptionalCell::empty(),
         }
     }
 }
@@ -229,12 +277,13 @@ impl<'a, M: device::MacDevice<'a>> MacUser<'a, M> {
         buf: &'b [u8],
         header: Header<'b>,
         lqi: u8,
+        rssi: Option<i8>,
         data_offset: usize,
         data_len: usize,
     ) {
         self.rx_client
             .get()
-            .map(move |client| client.receive(buf, header, lqi, data_offset, data_len));
+            .map(move |client| client.receive(buf, header, lqi, rssi, data_offset, data_len));
     }
 }
 
@@ -318,4 +367,16 @@ impl<'a, M: device::MacDevice<'a>> device::MacDevice<'a> for MacUser<'a, M> {
             },
         }
     }
+
+    fn set_energy_detect_client(&self, client: &'a dyn radio::EnergyDetectClient) {
+        self.energy_detect_client.set(client);
+    }
+
+    fn energy_detect(&self) -> Result<(), ErrorCode> {
+        self.mux.energy_detect(self)
+    }
+
+    fn rx_dropped(&self) -> usize {
+        self.mux.mac.rx_dropped()
+    }
 }
@@ -313,6 +313,7 @@ impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> XMac<'a, R, A> {
         buf: &'static mut [u8],
         len: usize,
         lqi: u8,
+        rssi: Option<i8>,
         crc_valid: bool,
         result: Result<(), ErrorCode>,
     ) {
@@ -320,7 +321,7 @@ impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> XMac<'a, R, A> {
         self.sleep();
 
         self.rx_client.map(move |c| {
-            c.receive(buf, len, lqi, crc_valid, result);
+            c.receive(buf, len, lqi, rssi, crc_valid, result);
         });
     }
 }
@@ -579,6 +580,7 @@ impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> radio::RxClient for XMac<'a, R, A> {
         buf: &'static mut [u8],
         frame_len: usize,
         lqi: u8,
+        rssi: Option<i8>,
         crc_valid: bool,
         result: Result<(), ErrorCode>,
     ) {
@@ -640,7 +642,7 @@ impl<'a, R: radio::Radio<'a>, A: Alarm<'a>> radio::RxClient for XMac<'a, R, A> {
 
         if data_received {
             self.rx_pending.set(false);
-            self.call_rx_client(buf, frame_len, lqi, crc_valid, result);
+            self.call_rx_client(buf, frame_len, lqi, rssi, crc_valid, result);
         } else {
             self.radio.set_receive_buffer(buf);
         }
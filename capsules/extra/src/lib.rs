@@ -29,14 +29,19 @@ pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
 pub mod crc;
+pub mod crc_software;
 pub mod cycle_count;
 pub mod dac;
 pub mod date_time;
+pub mod debounce;
 pub mod debug_process_restart;
+pub mod device_info;
+pub mod entropy_health;
 pub mod eui64;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
+pub mod gpio_aggregator;
 pub mod gpio_async;
 pub mod hd44780;
 pub mod hmac;
@@ -44,6 +49,8 @@ pub mod hmac_sha256;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
+pub mod i2c_bitbang;
+pub mod i2c_scan;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod kv_driver;
@@ -60,22 +67,30 @@ pub mod lsm303xx;
 pub mod lsm6dsoxtr;
 pub mod ltc294x;
 pub mod max17205;
+pub mod max30102;
 pub mod mcp230xx;
 pub mod mlx90614;
+pub mod monotonic_counter;
 pub mod mx25r6435f;
 pub mod ninedof;
+pub mod nmea;
+pub mod nmea_i2c;
+pub mod nmea_uart;
 pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
+pub mod orientation;
 pub mod panic_button;
 pub mod pca9544a;
 pub mod pressure;
 pub mod proximity;
 pub mod public_key_crypto;
 pub mod pwm;
+pub mod random_delay;
 pub mod read_only_state;
 pub mod rf233;
 pub mod rf233_const;
+pub mod rotary_encoder;
 pub mod screen;
 pub mod screen_shared;
 pub mod sdcard;
@@ -87,19 +102,26 @@ pub mod sha256;
 pub mod sht3x;
 pub mod sht4x;
 pub mod si7021;
+pub mod signaler;
 pub mod sip_hash;
 pub mod sound_pressure;
+pub mod spi_bitbang;
 pub mod ssd1306;
 pub mod st77xx;
 pub mod symmetric_encryption;
 pub mod temperature;
+pub mod temperature_alarm;
 pub mod temperature_rp2040;
 pub mod temperature_stm;
 pub mod text_screen;
 pub mod tickv;
 pub mod tickv_kv_store;
+pub mod timeout;
 pub mod touch;
+pub mod touch_slider;
 pub mod tsl2561;
+pub mod uart_rx_ring;
 pub mod usb;
 pub mod usb_hid_driver;
 pub mod virtual_kv;
+pub mod vl53l0x;
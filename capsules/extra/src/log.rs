@@ -30,7 +30,8 @@
 //!     * Read:     Read back previously written entries in whole. Entries are read in their
 //!                 entirety (no partial reads) from oldest to newest.
 //!     * Seek:     Seek to different entries to begin reading from a different entry (can only
-//!                 seek to the start of entries).
+//!                 seek to the start of entries). Seeks can also move forward or backward by a
+//!                 number of entries, or to the end of the log to read only new entries.
 //!     * Append:   Append new data entries onto the end of a log. Can fail if the new entry is too
 //!                 large to fit within the log.
 //!     * Sync:     Sync a log to flash to ensure that all changes are persistent.
@@ -300,15 +301,16 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
         }
     }
 
-    /// Returns the ID of the next entry to read or an error if no entry could be retrieved.
+    /// Returns the ID of the next entry to read from the given read position or an error if no
+    /// entry could be retrieved.
     /// Result<(), ErrorCode>s used:
     ///     * FAIL: reached end of log, nothing to read.
     ///     * RESERVE: client or internal pagebuffer missing.
-    fn get_next_entry(&self) -> Result<EntryID, Result<(), ErrorCode>> {
+    fn get_next_entry(&self, read_entry_id: EntryID) -> Result<EntryID, Result<(), ErrorCode>> {
         self.pagebuffer
             .take()
             .map_or(Err(Err(ErrorCode::RESERVE)), move |pagebuffer| {
-                let mut entry_id = self.read_entry_id.get();
+                let mut entry_id = read_entry_id;
 
                 // Skip page header if at start of page or skip padded bytes if at end of page.
                 if entry_id % self.page_size == 0 {
@@ -352,6 +354,24 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
             })
     }
 
+    /// Returns the read position after the next entry to read from the given read position, or
+    /// `None` if the end of the log was reached.
+    /// Result<(), ErrorCode>s used:
+    ///     * FAIL: entry header invalid.
+    ///     * RESERVE: internal pagebuffer missing.
+    fn skip_entry(&self, read_entry_id: EntryID) -> Result<Option<EntryID>, ErrorCode> {
+        match self.get_next_entry(read_entry_id) {
+            Ok(entry_id) => {
+                let entry_length = self
+                    .read_entry_header(entry_id)
+                    .map_err(Result::unwrap_err)?;
+                Ok(Some(entry_id + ENTRY_HEADER_SIZE + entry_length))
+            }
+            Err(Err(ErrorCode::FAIL)) => Ok(None),
+            Err(error) => Err(error.unwrap_err()),
+        }
+    }
+
     /// Reads the next entry into a buffer. Returns the number of bytes read on success, or an
     /// error otherwise.
     /// Result<(), ErrorCode>s used:
@@ -360,7 +380,7 @@ impl<'a, F: Flash + 'static> Log<'a, F> {
     ///     * SIZE: buffer not large enough to contain entry being read.
     fn read_entry(&self, buffer: &mut [u8], length: usize) -> Result<usize, Result<(), ErrorCode>> {
         // Get next entry to read. Immediately returns FAIL in event of failure.
-        let entry_id = self.get_next_entry()?;
+        let entry_id = self.get_next_entry(self.read_entry_id.get())?;
         let entry_length = self.read_entry_header(entry_id)?;
 
         // Read entry into buffer.
@@ -649,6 +669,50 @@ impl<'a, F: Flash + 'static> LogRead<'a> for Log<'a, F> {
         }
     }
 
+    /// Seek forward or backward by a number of entries, stopping at the start or end of the log.
+    /// Entries only record their own length, so the log is scanned from its oldest entry to
+    /// count them.
+    /// Result<(), ErrorCode>s used:
+    ///     * Ok(()): seek succeeded.
+    ///     * BUSY: log busy with another operation, try again later.
+    ///     * INVAL: read entry ID not valid within current log.
+    ///     * FAIL: entry header invalid.
+    ///     * RESERVE: internal pagebuffer missing.
+    fn seek_relative(&self, entries: isize) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let read_entry_id = self.read_entry_id.get();
+        if read_entry_id > self.append_entry_id.get() || read_entry_id < self.oldest_entry_id.get()
+        {
+            return Err(ErrorCode::INVAL);
+        }
+
+        // Count the entries before the read position and in the whole log.
+        let mut entry_id = self.oldest_entry_id.get();
+        let mut read_index = 0;
+        let mut total = 0;
+        while let Some(next_entry_id) = self.skip_entry(entry_id)? {
+            entry_id = next_entry_id;
+            total += 1;
+            if entry_id <= read_entry_id {
+                read_index = total;
+            }
+        }
+
+        let target = (read_index as isize)
+            .saturating_add(entries)
+            .clamp(0, total as isize) as usize;
+        if target == total {
+            return self.seek_to_end();
+        }
+        let mut entry_id = self.oldest_entry_id.get();
+        for _ in 0..target {
+            entry_id = self.skip_entry(entry_id)?.ok_or(ErrorCode::FAIL)?;
+        }
+        self.seek(entry_id)
+    }
+
     /// Get approximate log capacity in bytes.
     fn get_size(&self) -> usize {
         self.capacity
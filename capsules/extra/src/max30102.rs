@@ -0,0 +1,889 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! SyscallDriver for the MAX30102 Pulse Oximeter and Heart-Rate Sensor.
+//!
+//! I2C Interface
+//!
+//! <https://www.analog.com/media/en/technical-documentation/data-sheets/MAX30102.pdf>
+//!
+//! The driver runs the sensor in SpO2 mode, where every sample is a pair of
+//! 18-bit red and IR readings. Samples are buffered in the sensor's 32 entry
+//! FIFO and the INT pin is asserted once the FIFO is almost full. The driver
+//! then reads all available samples in a single burst and copies them to the
+//! userspace buffer. Computing SpO2 and the heart rate is left to userspace.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let max30102 = components::max30102::Max30102Component::new(
+//!     mux_i2c,
+//!     0x57,
+//!     &nrf52840::gpio::PORT[Pin::P0_14],
+//!     board_kernel,
+//!     capsules_extra::max30102::DRIVER_NUM,
+//! )
+//! .finalize(components::max30102_component_static!(nrf52840::i2c::TWI));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Read-write allow
+//!
+//! - `0`: Buffer the samples are copied to. Each sample is 8 bytes long and
+//!   consists of the red and the IR reading, each a little endian `u32`.
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called when samples have been copied to the buffer. The first
+//!   argument is a status code, the second the number of samples copied and
+//!   the third the number of samples lost to a FIFO overflow.
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Start sampling. `data1` holds the red LED pulse amplitude in bits
+//!   0-7 and the IR LED pulse amplitude in bits 8-15. `data2` is the sample
+//!   rate, encoded as the `SPO2_SR` field of the SpO2 configuration register
+//!   (0 = 50 Hz, 1 = 100 Hz, ..., 7 = 3200 Hz).
+//! - `2`: Stop sampling and put the sensor into shutdown.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::Max30102 as usize;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    pub const SAMPLES: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Number of samples the sensor FIFO can hold.
+pub const FIFO_DEPTH: usize = 32;
+
+/// Number of bytes of a single sample (red and IR) in the FIFO.
+const SAMPLE_LEN: usize = 6;
+
+/// Number of bytes a sample takes up in the userspace buffer.
+const APP_SAMPLE_LEN: usize = 8;
+
+/// Recommended buffer length for this driver. This is large enough to read
+/// the whole FIFO in a single burst.
+pub const BUF_LEN: usize = FIFO_DEPTH * SAMPLE_LEN;
+
+#[allow(dead_code)]
+enum Registers {
+    IntStatus1 = 0x00,
+    IntStatus2 = 0x01,
+    IntEnable1 = 0x02,
+    IntEnable2 = 0x03,
+    FifoWrPtr = 0x04,
+    OvfCounter = 0x05,
+    FifoRdPtr = 0x06,
+    FifoData = 0x07,
+    FifoConfig = 0x08,
+    ModeConfig = 0x09,
+    Spo2Config = 0x0a,
+    Led1Pa = 0x0c,
+    Led2Pa = 0x0d,
+    PartId = 0xff,
+}
+
+/// Interrupt when the FIFO is almost full
+const INT_A_FULL_EN: u8 = 1 << 7;
+/// Roll the FIFO over when it is full instead of dropping new samples
+const FIFO_ROLLOVER_EN: u8 = 1 << 4;
+/// Interrupt once 17 samples are in the FIFO (15 empty slots left)
+const FIFO_A_FULL: u8 = 0x0f;
+/// Shut the sensor down
+const MODE_SHDN: u8 = 1 << 7;
+/// SpO2 mode, both the red and IR LEDs are used
+const MODE_SPO2: u8 = 0x03;
+/// 4096 nA ADC range, 411 us pulse width (18-bit resolution)
+const SPO2_ADC_RGE_PW: u8 = (0b01 << 5) | 0b11;
+/// Mask for a FIFO pointer or the overflow counter
+const FIFO_PTR_MASK: u8 = 0x1f;
+
+/// Returns the number of samples waiting in the sensor FIFO.
+///
+/// `write_ptr` and `read_ptr` are the FIFO_WR_PTR and FIFO_RD_PTR registers.
+/// Both wrap at the FIFO depth, so when they are equal the FIFO is either
+/// empty or, if samples were lost (`overflow` is not zero), completely full.
+fn fifo_samples_available(write_ptr: u8, overflow: u8, read_ptr: u8) -> usize {
+    let write_ptr = (write_ptr & FIFO_PTR_MASK) as usize;
+    let read_ptr = (read_ptr & FIFO_PTR_MASK) as usize;
+
+    if write_ptr == read_ptr && (overflow & FIFO_PTR_MASK) != 0 {
+        FIFO_DEPTH
+    } else {
+        (write_ptr + FIFO_DEPTH - read_ptr) % FIFO_DEPTH
+    }
+}
+
+/// Decodes an 18-bit sample stored big endian in the FIFO.
+fn fifo_sample(buf: &[u8]) -> u32 {
+    (((buf[0] as u32) << 16) | ((buf[1] as u32) << 8) | buf[2] as u32) & 0x3ffff
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Resetting the FIFO pointers
+    ResetFifo,
+    /// Configuring the FIFO, mode and sample rate
+    Configure,
+    /// Setting the LED pulse amplitudes
+    SetLedAmplitude,
+    /// Enabling the FIFO almost full interrupt
+    EnableInterrupt,
+    /// Waiting for the INT pin
+    Running,
+    /// Reading the interrupt status and FIFO pointers
+    ReadPointers,
+    /// Reading the samples out of the FIFO
+    ReadFifo(usize, usize),
+    /// Putting the sensor into shutdown
+    Shutdown,
+}
+
+/// What the sensor reports back to the syscall driver.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Event {
+    /// Samples have been read into the buffer. Holds the number of samples
+    /// and the number of samples lost to a FIFO overflow.
+    Samples(usize, usize),
+    /// The sensor has been shut down.
+    Stopped,
+    /// An I2C transfer failed and sampling has stopped.
+    Error(ErrorCode),
+}
+
+/// The sensor state machine. It is kept apart from the grant so that it can
+/// be tested with a simulated sensor.
+struct Sensor<'a, I: i2c::I2CDevice> {
+    i2c: &'a I,
+    interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    led_amplitude: Cell<(u8, u8)>,
+    sample_rate: Cell<u8>,
+}
+
+impl<'a, I: i2c::I2CDevice> Sensor<'a, I> {
+    fn start(
+        &self,
+        red_amplitude: u8,
+        ir_amplitude: u8,
+        sample_rate: usize,
+    ) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        if sample_rate > 7 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.led_amplitude.set((red_amplitude, ir_amplitude));
+        self.sample_rate.set(sample_rate as u8);
+
+        self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+            self.interrupt_pin.make_input();
+            self.i2c.enable();
+
+            // Clear the FIFO write pointer, overflow counter and read pointer
+            buffer[0] = Registers::FifoWrPtr as u8;
+            buffer[1] = 0;
+            buffer[2] = 0;
+            buffer[3] = 0;
+
+            self.write(buffer, 4, State::ResetFifo)
+        })
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Running => {
+                self.interrupt_pin.disable_interrupts();
+                self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+                    self.i2c.enable();
+                    buffer[0] = Registers::ModeConfig as u8;
+                    buffer[1] = MODE_SHDN;
+
+                    self.write(buffer, 2, State::Shutdown)
+                })
+            }
+            State::Idle => Err(ErrorCode::ALREADY),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn write(&self, buffer: &'static mut [u8], len: usize, next: State) -> Result<(), ErrorCode> {
+        match self.i2c.write(buffer, len) {
+            Ok(()) => {
+                self.state.set(next);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Idle);
+                Err(error.into())
+            }
+        }
+    }
+
+    fn write_read(
+        &self,
+        buffer: &'static mut [u8],
+        write_len: usize,
+        read_len: usize,
+        next: State,
+    ) -> Result<(), ErrorCode> {
+        match self.i2c.write_read(buffer, write_len, read_len) {
+            Ok(()) => {
+                self.state.set(next);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Idle);
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Handles the INT pin being asserted.
+    fn fired(&self) -> Option<Event> {
+        if self.state.get() != State::Running {
+            return None;
+        }
+        self.buffer.take().and_then(|buffer| {
+            self.i2c.enable();
+
+            // Read the interrupt status registers, which clears the
+            // interrupt, followed by the FIFO pointers.
+            buffer[0] = Registers::IntStatus1 as u8;
+            self.write_read(buffer, 1, 7, State::ReadPointers)
+                .err()
+                .map(Event::Error)
+        })
+    }
+
+    /// Handles a completed I2C transfer.
+    fn command_complete(
+        &self,
+        buffer: &'static mut [u8],
+        status: Result<(), i2c::Error>,
+    ) -> Option<Event> {
+        if let Err(i2c_error) = status {
+            self.interrupt_pin.disable_interrupts();
+            self.buffer.replace(buffer);
+            self.i2c.disable();
+            self.state.set(State::Idle);
+            return Some(Event::Error(i2c_error.into()));
+        }
+
+        let res = match self.state.get() {
+            State::ResetFifo => {
+                buffer[0] = Registers::FifoConfig as u8;
+                buffer[1] = FIFO_ROLLOVER_EN | FIFO_A_FULL;
+                buffer[2] = MODE_SPO2;
+                buffer[3] = SPO2_ADC_RGE_PW | (self.sample_rate.get() << 2);
+
+                self.write(buffer, 4, State::Configure)
+            }
+            State::Configure => {
+                let (red, ir) = self.led_amplitude.get();
+                buffer[0] = Registers::Led1Pa as u8;
+                buffer[1] = red;
+                buffer[2] = ir;
+
+                self.write(buffer, 3, State::SetLedAmplitude)
+            }
+            State::SetLedAmplitude => {
+                buffer[0] = Registers::IntEnable1 as u8;
+                buffer[1] = INT_A_FULL_EN;
+
+                self.write(buffer, 2, State::EnableInterrupt)
+            }
+            State::EnableInterrupt => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Running);
+                self.interrupt_pin
+                    .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+                Ok(())
+            }
+            State::ReadPointers => {
+                // buffer[0..7] holds IntStatus1 to FifoRdPtr
+                let write_ptr = buffer[Registers::FifoWrPtr as usize];
+                let overflow = buffer[Registers::OvfCounter as usize] & FIFO_PTR_MASK;
+                let read_ptr = buffer[Registers::FifoRdPtr as usize];
+
+                let samples = core::cmp::min(
+                    fifo_samples_available(write_ptr, overflow, read_ptr),
+                    buffer.len() / SAMPLE_LEN,
+                );
+
+                if samples == 0 {
+                    self.buffer.replace(buffer);
+                    self.i2c.disable();
+                    self.state.set(State::Running);
+                    Ok(())
+                } else {
+                    // The read pointer is incremented by the sensor
+                    // as the data register is read.
+                    buffer[0] = Registers::FifoData as u8;
+                    self.write_read(
+                        buffer,
+                        1,
+                        samples * SAMPLE_LEN,
+                        State::ReadFifo(samples, overflow as usize),
+                    )
+                }
+            }
+            State::ReadFifo(samples, lost) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Running);
+                return Some(Event::Samples(samples, lost));
+            }
+            State::Shutdown => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Idle);
+                return Some(Event::Stopped);
+            }
+            State::Idle | State::Running => {
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+        };
+
+        res.err().map(|e| {
+            self.interrupt_pin.disable_interrupts();
+            Event::Error(e)
+        })
+    }
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct Max30102<'a, I: i2c::I2CDevice> {
+    sensor: Sensor<'a, I>,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    owning_process: OptionalCell<ProcessId>,
+}
+
+impl<'a, I: i2c::I2CDevice> Max30102<'a, I> {
+    pub fn new(
+        i2c: &'a I,
+        interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+        buffer: &'static mut [u8],
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> Max30102<'a, I> {
+        Max30102 {
+            sensor: Sensor {
+                i2c,
+                interrupt_pin,
+                buffer: TakeCell::new(buffer),
+                state: Cell::new(State::Idle),
+                led_amplitude: Cell::new((0, 0)),
+                sample_rate: Cell::new(0),
+            },
+            apps: grant,
+            owning_process: OptionalCell::empty(),
+        }
+    }
+
+    fn handle_event(&self, event: Option<Event>) {
+        match event {
+            Some(Event::Samples(samples, lost)) => {
+                self.sensor
+                    .buffer
+                    .map(|buffer| self.deliver_samples(buffer, samples, lost));
+            }
+            Some(Event::Stopped) => self.schedule_upcall(Ok(()), 0, 0),
+            Some(Event::Error(e)) => self.schedule_upcall(Err(e), 0, 0),
+            None => {}
+        }
+    }
+
+    fn schedule_upcall(&self, status: Result<(), ErrorCode>, samples: usize, lost: usize) {
+        self.owning_process.map(|pid| {
+            let _ = self.apps.enter(pid, |_app, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        0,
+                        (kernel::errorcode::into_statuscode(status), samples, lost),
+                    )
+                    .ok();
+            });
+        });
+    }
+
+    fn deliver_samples(&self, buffer: &[u8], samples: usize, lost: usize) {
+        self.owning_process.map(|pid| {
+            let _ = self.apps.enter(pid, |_app, kernel_data| {
+                let copied = kernel_data
+                    .get_readwrite_processbuffer(rw_allow::SAMPLES)
+                    .and_then(|app_buffer| {
+                        app_buffer.mut_enter(|app_buffer| {
+                            let count = core::cmp::min(samples, app_buffer.len() / APP_SAMPLE_LEN);
+                            for (chunk, sample) in app_buffer
+                                .chunks(APP_SAMPLE_LEN)
+                                .zip(buffer.chunks(SAMPLE_LEN))
+                                .take(count)
+                            {
+                                let red = fifo_sample(&sample[0..3]);
+                                let ir = fifo_sample(&sample[3..6]);
+                                chunk[0..4].copy_from_slice(&red.to_le_bytes());
+                                chunk[4..8].copy_from_slice(&ir.to_le_bytes());
+                            }
+                            count
+                        })
+                    })
+                    .unwrap_or(0);
+
+                kernel_data
+                    .schedule_upcall(
+                        0,
+                        (kernel::errorcode::into_statuscode(Ok(())), copied, lost),
+                    )
+                    .ok();
+            });
+        });
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> gpio::Client for Max30102<'a, I> {
+    fn fired(&self) {
+        self.handle_event(self.sensor.fired());
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> i2c::I2CClient for Max30102<'a, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        self.handle_event(self.sensor.command_complete(buffer, status));
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> SyscallDriver for Max30102<'a, I> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        process_id: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            // Handle this first as it should be returned
+            // unconditionally
+            return CommandReturn::success();
+        }
+        // Check if this non-virtualized driver is already in use by
+        // some (alive) process
+        let match_or_empty_or_nonexistant = self.owning_process.map_or(true, |current_process| {
+            self.apps
+                .enter(current_process, |_, _| current_process == process_id)
+                .unwrap_or(true)
+        });
+        if match_or_empty_or_nonexistant {
+            self.owning_process.set(process_id);
+        } else {
+            return CommandReturn::failure(ErrorCode::NOMEM);
+        }
+
+        match command_num {
+            // Start sampling
+            1 => self
+                .sensor
+                .start(data1 as u8, (data1 >> 8) as u8, data2)
+                .into(),
+            // Stop sampling
+            2 => self.sensor.stop().into(),
+            // default
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// A simulated sensor behind an I2C device. Transfers complete when
+    /// `complete()` is called.
+    struct MockSensor {
+        registers: Cell<[u8; 16]>,
+        fifo: RefCell<[[u8; SAMPLE_LEN]; FIFO_DEPTH]>,
+        /// Every transfer, as the bytes written and the number of bytes read.
+        transfers: RefCell<Vec<(Vec<u8>, usize)>>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl MockSensor {
+        fn new() -> MockSensor {
+            MockSensor {
+                registers: Cell::new([0; 16]),
+                fifo: RefCell::new([[0; SAMPLE_LEN]; FIFO_DEPTH]),
+                transfers: RefCell::new(Vec::new()),
+                buffer: TakeCell::empty(),
+            }
+        }
+
+        fn set_register(&self, register: Registers, value: u8) {
+            let mut registers = self.registers.get();
+            registers[register as usize] = value;
+            self.registers.set(registers);
+        }
+
+        fn register(&self, register: Registers) -> u8 {
+            self.registers.get()[register as usize]
+        }
+
+        fn transfer(&self, buffer: &'static mut [u8], write_len: usize, read_len: usize) {
+            self.transfers
+                .borrow_mut()
+                .push((buffer[..write_len].to_vec(), read_len));
+            self.buffer.replace(buffer);
+        }
+
+        /// Applies the pending transfer to the sensor and completes it.
+        fn complete(&self, driver: &Sensor<MockSensor>) -> Option<Event> {
+            let buffer = self.buffer.take().unwrap();
+            let (written, read_len) = self.transfers.borrow().last().unwrap().clone();
+            let reg = written[0] as usize;
+            let mut registers = self.registers.get();
+            if reg == Registers::FifoData as usize {
+                // Reading the data register advances the read pointer.
+                let mut read_ptr = registers[Registers::FifoRdPtr as usize] as usize;
+                for sample in buffer[..read_len].chunks_mut(SAMPLE_LEN) {
+                    sample.copy_from_slice(&self.fifo.borrow()[read_ptr]);
+                    read_ptr = (read_ptr + 1) % FIFO_DEPTH;
+                }
+                registers[Registers::FifoRdPtr as usize] = read_ptr as u8;
+                registers[Registers::OvfCounter as usize] = 0;
+            } else {
+                registers[reg..reg + written.len() - 1].copy_from_slice(&written[1..]);
+                buffer[..read_len].copy_from_slice(&registers[reg..reg + read_len]);
+            }
+            self.registers.set(registers);
+            driver.command_complete(buffer, Ok(()))
+        }
+    }
+
+    impl i2c::I2CDevice for MockSensor {
+        fn enable(&self) {}
+        fn disable(&self) {}
+
+        fn write_read(
+            &self,
+            data: &'static mut [u8],
+            write_len: usize,
+            read_len: usize,
+        ) -> Result<(), (i2c::Error, &'static mut [u8])> {
+            self.transfer(data, write_len, read_len);
+            Ok(())
+        }
+
+        fn write(
+            &self,
+            data: &'static mut [u8],
+            len: usize,
+        ) -> Result<(), (i2c::Error, &'static mut [u8])> {
+            self.transfer(data, len, 0);
+            Ok(())
+        }
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            _len: usize,
+        ) -> Result<(), (i2c::Error, &'static mut [u8])> {
+            Err((i2c::Error::NotSupported, buffer))
+        }
+    }
+
+    #[derive(Default)]
+    struct MockPin {
+        interrupts: Cell<bool>,
+    }
+
+    impl gpio::Configure for MockPin {
+        fn configuration(&self) -> gpio::Configuration {
+            gpio::Configuration::Input
+        }
+        fn make_output(&self) -> gpio::Configuration {
+            gpio::Configuration::Input
+        }
+        fn disable_output(&self) -> gpio::Configuration {
+            gpio::Configuration::Input
+        }
+        fn make_input(&self) -> gpio::Configuration {
+            gpio::Configuration::Input
+        }
+        fn disable_input(&self) -> gpio::Configuration {
+            gpio::Configuration::Input
+        }
+        fn deactivate_to_low_power(&self) {}
+        fn set_floating_state(&self, _state: gpio::FloatingState) {}
+        fn floating_state(&self) -> gpio::FloatingState {
+            gpio::FloatingState::PullNone
+        }
+    }
+
+    impl gpio::Output for MockPin {
+        fn set(&self) {}
+        fn clear(&self) {}
+        fn toggle(&self) -> bool {
+            false
+        }
+    }
+
+    impl gpio::Input for MockPin {
+        fn read(&self) -> bool {
+            true
+        }
+    }
+
+    impl<'a> gpio::Interrupt<'a> for MockPin {
+        fn set_client(&self, _client: &'a dyn gpio::Client) {}
+        fn enable_interrupts(&self, _mode: gpio::InterruptEdge) {
+            self.interrupts.set(true);
+        }
+        fn disable_interrupts(&self) {
+            self.interrupts.set(false);
+        }
+        fn is_pending(&self) -> bool {
+            false
+        }
+    }
+
+    fn new_driver(
+        buffer_len: usize,
+    ) -> (
+        &'static MockSensor,
+        &'static MockPin,
+        &'static Sensor<'static, MockSensor>,
+    ) {
+        let sensor = Box::leak(Box::new(MockSensor::new()));
+        let pin = Box::leak(Box::new(MockPin::default()));
+        let buffer = Box::leak(std::vec![0; buffer_len].into_boxed_slice());
+        let driver = Box::leak(Box::new(Sensor {
+            i2c: sensor,
+            interrupt_pin: pin,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            led_amplitude: Cell::new((0, 0)),
+            sample_rate: Cell::new(0),
+        }));
+        (sensor, pin, driver)
+    }
+
+    /// Starts sampling and runs the configuration sequence.
+    fn start(sensor: &MockSensor, driver: &Sensor<MockSensor>) {
+        driver.start(0x24, 0x42, 1).unwrap();
+        for _ in 0..4 {
+            assert_eq!(sensor.complete(driver), None);
+        }
+    }
+
+    /// Fills the FIFO slots from `first` up to, but not including, `end`
+    /// and sets the pointers accordingly.
+    fn fill_fifo(sensor: &MockSensor, first: usize, end: usize, overflow: u8) {
+        let mut slot = first;
+        loop {
+            sensor.fifo.borrow_mut()[slot] = [0, 0, slot as u8, 0x01, 0, slot as u8];
+            slot = (slot + 1) % FIFO_DEPTH;
+            if slot == end {
+                break;
+            }
+        }
+        sensor.set_register(Registers::FifoRdPtr, first as u8);
+        sensor.set_register(Registers::FifoWrPtr, end as u8);
+        sensor.set_register(Registers::OvfCounter, overflow);
+    }
+
+    /// Handles an interrupt and returns the number of bytes the burst read of
+    /// the FIFO asked for, and the event reported once it completed.
+    fn interrupt(sensor: &MockSensor, driver: &Sensor<MockSensor>) -> (usize, Option<Event>) {
+        assert_eq!(driver.fired(), None);
+        assert_eq!(
+            *sensor.transfers.borrow().last().unwrap(),
+            (std::vec![Registers::IntStatus1 as u8], 7)
+        );
+        assert_eq!(sensor.complete(driver), None);
+        let (written, read_len) = sensor.transfers.borrow().last().unwrap().clone();
+        assert_eq!(written, [Registers::FifoData as u8]);
+        let event = sensor.complete(driver);
+        assert_eq!(driver.state.get(), State::Running);
+        (read_len, event)
+    }
+
+    #[test]
+    fn configure() {
+        let (sensor, pin, driver) = new_driver(BUF_LEN);
+        start(sensor, driver);
+
+        let transfers = sensor.transfers.borrow();
+        assert_eq!(
+            *transfers,
+            [
+                (std::vec![Registers::FifoWrPtr as u8, 0, 0, 0], 0),
+                (
+                    std::vec![
+                        Registers::FifoConfig as u8,
+                        FIFO_ROLLOVER_EN | FIFO_A_FULL,
+                        MODE_SPO2,
+                        SPO2_ADC_RGE_PW | (1 << 2)
+                    ],
+                    0
+                ),
+                (std::vec![Registers::Led1Pa as u8, 0x24, 0x42], 0),
+                (std::vec![Registers::IntEnable1 as u8, INT_A_FULL_EN], 0),
+            ]
+        );
+        assert_eq!(driver.state.get(), State::Running);
+        assert!(pin.interrupts.get());
+    }
+
+    #[test]
+    fn burst_read_wraps_around() {
+        let (sensor, _pin, driver) = new_driver(BUF_LEN);
+        start(sensor, driver);
+
+        // 12 samples, in slots 26 to 31 and 0 to 5.
+        fill_fifo(sensor, 26, 6, 0);
+        assert_eq!(
+            interrupt(sensor, driver),
+            (12 * SAMPLE_LEN, Some(Event::Samples(12, 0)))
+        );
+        assert_eq!(sensor.register(Registers::FifoRdPtr), 6);
+
+        // The samples were read in FIFO order.
+        driver.buffer.map(|buffer| {
+            let slots: Vec<u32> = buffer[..12 * SAMPLE_LEN]
+                .chunks(SAMPLE_LEN)
+                .map(|sample| fifo_sample(&sample[0..3]))
+                .collect();
+            assert_eq!(slots, [26, 27, 28, 29, 30, 31, 0, 1, 2, 3, 4, 5]);
+            assert_eq!(fifo_sample(&buffer[3..6]), 0x10000 | 26);
+        });
+    }
+
+    #[test]
+    fn burst_read_overflow() {
+        let (sensor, _pin, driver) = new_driver(BUF_LEN);
+        start(sensor, driver);
+
+        // The FIFO rolled over, so the pointers are equal and it is full.
+        fill_fifo(sensor, 9, 9, 4);
+        assert_eq!(
+            interrupt(sensor, driver),
+            (FIFO_DEPTH * SAMPLE_LEN, Some(Event::Samples(FIFO_DEPTH, 4)))
+        );
+        assert_eq!(sensor.register(Registers::FifoRdPtr), 9);
+    }
+
+    #[test]
+    fn burst_read_limited_by_buffer() {
+        let (sensor, _pin, driver) = new_driver(10 * SAMPLE_LEN);
+        start(sensor, driver);
+
+        // The rest of the samples are read on the next interrupt.
+        fill_fifo(sensor, 0, 17, 0);
+        assert_eq!(
+            interrupt(sensor, driver),
+            (10 * SAMPLE_LEN, Some(Event::Samples(10, 0)))
+        );
+        assert_eq!(
+            interrupt(sensor, driver),
+            (7 * SAMPLE_LEN, Some(Event::Samples(7, 0)))
+        );
+        assert_eq!(sensor.register(Registers::FifoRdPtr), 17);
+    }
+
+    #[test]
+    fn empty_fifo() {
+        let (sensor, _pin, driver) = new_driver(BUF_LEN);
+        start(sensor, driver);
+
+        fill_fifo(sensor, 3, 4, 0);
+        sensor.set_register(Registers::FifoWrPtr, 3);
+        assert_eq!(driver.fired(), None);
+        assert_eq!(sensor.complete(driver), None);
+
+        // No burst read is started.
+        assert_eq!(sensor.transfers.borrow().len(), 5);
+        assert_eq!(driver.state.get(), State::Running);
+        assert!(sensor.buffer.is_none());
+    }
+
+    #[test]
+    fn stop() {
+        let (sensor, pin, driver) = new_driver(BUF_LEN);
+        start(sensor, driver);
+
+        driver.stop().unwrap();
+        assert!(!pin.interrupts.get());
+        assert_eq!(sensor.complete(driver), Some(Event::Stopped));
+        assert_eq!(sensor.register(Registers::ModeConfig), MODE_SHDN);
+        assert_eq!(driver.state.get(), State::Idle);
+    }
+
+    #[test]
+    fn fifo_empty() {
+        assert_eq!(fifo_samples_available(0, 0, 0), 0);
+        assert_eq!(fifo_samples_available(17, 0, 17), 0);
+    }
+
+    #[test]
+    fn fifo_pointer_arithmetic() {
+        assert_eq!(fifo_samples_available(17, 0, 0), 17);
+        assert_eq!(fifo_samples_available(31, 0, 30), 1);
+        // The write pointer has wrapped around
+        assert_eq!(fifo_samples_available(3, 0, 20), 15);
+        assert_eq!(fifo_samples_available(0, 0, 31), 1);
+    }
+
+    #[test]
+    fn fifo_overflow() {
+        // When samples were lost the pointers are equal but the FIFO is full
+        assert_eq!(fifo_samples_available(5, 1, 5), FIFO_DEPTH);
+        assert_eq!(fifo_samples_available(5, 31, 5), FIFO_DEPTH);
+    }
+
+    #[test]
+    fn fifo_sample_decode() {
+        assert_eq!(fifo_sample(&[0x03, 0xff, 0xff]), 0x3ffff);
+        // Only the lower 18 bits are valid
+        assert_eq!(fifo_sample(&[0xfc, 0x12, 0x34]), 0x1234);
+        assert_eq!(fifo_sample(&[0x01, 0x02, 0x03]), 0x10203);
+    }
+}
@@ -0,0 +1,618 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Persistent monotonic counter.
+//!
+//! Provides a 64-bit counter that survives reboots and never decreases, for
+//! uses such as anti-rollback checks and nonce generation. The counter is
+//! stored in a key-value store, normally TicKV.
+//!
+//! The KV store cannot replace a value atomically: setting an existing key
+//! removes the old value before the new one is written, so a reset in between
+//! would lose the counter. To avoid this the counter is kept in two slots
+//! under two fixed keys. An increment reads both slots, takes the larger value
+//! as the current count, and writes the incremented count over the slot
+//! holding the smaller (older) value. The slot with the current count is never
+//! touched, so whenever an increment is interrupted the count read back on the
+//! next boot is at least the last value handed out.
+//!
+//! Only one increment can be in flight at a time, further requests fail with
+//! `BUSY` until it completes.
+//!
+//! The counter uses the `hil::kv::KV` interface directly, so it needs a KV
+//! store of its own and cannot share one with the userspace KV driver.
+//!
+//! ```text
+//! +------------------------------+
+//! |  Monotonic counter driver    |
+//! +------------------------------+
+//!
+//!    MonotonicCounterClient
+//!
+//! +------------------------------+
+//! |  Monotonic counter           |
+//! +------------------------------+
+//!
+//!    hil::kv::KV
+//!
+//! +------------------------------+
+//! |  TicKV K-V store             |
+//! +------------------------------+
+//! ```
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let counter_driver = components::kv::MonotonicCounterComponent::new(
+//!     board_kernel,
+//!     capsules_extra::monotonic_counter::DRIVER_NUM,
+//!     tickv_kv_store,
+//! )
+//! .finalize(components::monotonic_counter_component_static!(TicKVKVStore));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::errorcode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::kv;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::MonotonicCounter as usize;
+
+/// Keys of the two slots holding the counter.
+pub const SLOT_KEYS: [&[u8]; 2] = [b"tock.monotonic_counter.0", b"tock.monotonic_counter.1"];
+
+/// Length of the key buffer. All slot keys must fit.
+pub const KEY_BUFFER_LEN: usize = 24;
+
+/// Length of the value buffer, one stored count.
+pub const VALUE_BUFFER_LEN: usize = 8;
+
+pub trait MonotonicCounterClient {
+    /// An increment has finished. On success `result` holds the new count,
+    /// which has been stored.
+    fn increment_done(&self, result: Result<u64, ErrorCode>);
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Reading the given slot.
+    Read(usize),
+    /// Writing the new count to a slot.
+    Write(u64),
+}
+
+pub struct MonotonicCounter<'a, K: kv::KV<'a>> {
+    kv: &'a K,
+    client: OptionalCell<&'a dyn MonotonicCounterClient>,
+    key_buffer: TakeCell<'static, [u8]>,
+    value_buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    /// Counts read from the slots, `None` if the slot is empty.
+    slots: Cell<[Option<u64>; 2]>,
+}
+
+impl<'a, K: kv::KV<'a>> MonotonicCounter<'a, K> {
+    pub fn new(
+        kv: &'a K,
+        key_buffer: &'static mut [u8; KEY_BUFFER_LEN],
+        value_buffer: &'static mut [u8; VALUE_BUFFER_LEN],
+    ) -> MonotonicCounter<'a, K> {
+        MonotonicCounter {
+            kv,
+            client: OptionalCell::empty(),
+            key_buffer: TakeCell::new(key_buffer),
+            value_buffer: TakeCell::new(value_buffer),
+            state: Cell::new(State::Idle),
+            slots: Cell::new([None; 2]),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn MonotonicCounterClient) {
+        self.client.set(client);
+    }
+
+    /// Increments the stored count. The new count is passed to
+    /// `increment_done()` once it has been stored.
+    pub fn increment_and_get(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.read_slot(0)
+    }
+
+    /// Takes the buffers and sets up the key of `slot`.
+    fn buffers(
+        &self,
+        slot: usize,
+    ) -> Result<(SubSliceMut<'static, u8>, SubSliceMut<'static, u8>), ErrorCode> {
+        let key_buf = self.key_buffer.take().ok_or(ErrorCode::FAIL)?;
+        let Some(value_buf) = self.value_buffer.take() else {
+            self.key_buffer.replace(key_buf);
+            return Err(ErrorCode::FAIL);
+        };
+
+        let key_len = SLOT_KEYS[slot].len();
+        key_buf[..key_len].copy_from_slice(SLOT_KEYS[slot]);
+        let mut key = SubSliceMut::new(key_buf);
+        key.slice(..key_len);
+        Ok((key, SubSliceMut::new(value_buf)))
+    }
+
+    fn return_buffers(&self, key: SubSliceMut<'static, u8>, value: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+        self.value_buffer.replace(value.take());
+    }
+
+    fn read_slot(&self, slot: usize) -> Result<(), ErrorCode> {
+        let (key, value) = self.buffers(slot)?;
+        self.state.set(State::Read(slot));
+        self.kv.get(key, value).map_err(|(key, value, error)| {
+            self.return_buffers(key, value);
+            self.state.set(State::Idle);
+            error
+        })
+    }
+
+    fn write_slot(&self, slot: usize, count: u64) -> Result<(), ErrorCode> {
+        let (key, mut value) = self.buffers(slot)?;
+        value.as_slice()[..VALUE_BUFFER_LEN].copy_from_slice(&count.to_le_bytes());
+        self.state.set(State::Write(count));
+        self.kv.set(key, value).map_err(|(key, value, error)| {
+            self.return_buffers(key, value);
+            self.state.set(State::Idle);
+            error
+        })
+    }
+
+    /// Both slots have been read, stores the next count.
+    fn write_next(&self) -> Result<(), ErrorCode> {
+        let slots = self.slots.get();
+        let current = slots.iter().flatten().copied().max().unwrap_or(0);
+        let next = current.checked_add(1).ok_or(ErrorCode::FAIL)?;
+
+        // Overwrite an empty slot if there is one, otherwise the older one.
+        let slot = match slots {
+            [None, _] => 0,
+            [_, None] => 1,
+            [Some(a), Some(b)] => usize::from(a > b),
+        };
+        self.write_slot(slot, next)
+    }
+
+    fn finish(&self, result: Result<u64, ErrorCode>) {
+        self.state.set(State::Idle);
+        self.client.map(|client| client.increment_done(result));
+    }
+}
+
+impl<'a, K: kv::KV<'a>> kv::KVClient for MonotonicCounter<'a, K> {
+    fn get_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        mut value: SubSliceMut<'static, u8>,
+    ) {
+        let State::Read(slot) = self.state.get() else {
+            self.return_buffers(key, value);
+            return;
+        };
+
+        let count = match result {
+            Ok(()) if value.len() == VALUE_BUFFER_LEN => {
+                let mut bytes = [0; VALUE_BUFFER_LEN];
+                bytes.copy_from_slice(value.as_slice());
+                Some(u64::from_le_bytes(bytes))
+            }
+            // The slot has never been written.
+            Err(ErrorCode::NOSUPPORT) => None,
+            // Anything else could hide the current count, so give up rather
+            // than risk counting backwards.
+            _ => {
+                self.return_buffers(key, value);
+                self.finish(Err(ErrorCode::FAIL));
+                return;
+            }
+        };
+        self.return_buffers(key, value);
+
+        let mut slots = self.slots.get();
+        slots[slot] = count;
+        self.slots.set(slots);
+
+        let next = if slot == 0 {
+            self.read_slot(1)
+        } else {
+            self.write_next()
+        };
+        if let Err(error) = next {
+            self.finish(Err(error));
+        }
+    }
+
+    fn set_complete(
+        &self,
+        result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.return_buffers(key, value);
+        if let State::Write(count) = self.state.get() {
+            self.finish(result.map(|()| count));
+        }
+    }
+
+    fn add_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.return_buffers(key, value);
+    }
+
+    fn update_complete(
+        &self,
+        _result: Result<(), ErrorCode>,
+        key: SubSliceMut<'static, u8>,
+        value: SubSliceMut<'static, u8>,
+    ) {
+        self.return_buffers(key, value);
+    }
+
+    fn delete_complete(&self, _result: Result<(), ErrorCode>, key: SubSliceMut<'static, u8>) {
+        self.key_buffer.replace(key.take());
+    }
+}
+
+/// IDs for subscribed upcalls.
+mod upcall {
+    /// Increment done callback.
+    pub const INCREMENT_DONE: usize = 0;
+    /// Number of upcalls.
+    pub const COUNT: u8 = 1;
+}
+
+/// Userspace interface to the monotonic counter.
+///
+/// Command 1 increments the counter. Upcall 0 reports the result, with the
+/// new count split into its low and high 32 bits.
+pub struct MonotonicCounterDriver<'a, K: kv::KV<'a>> {
+    counter: &'a MonotonicCounter<'a, K>,
+    apps: Grant<(), UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    /// App whose increment is in flight.
+    processid: OptionalCell<ProcessId>,
+}
+
+impl<'a, K: kv::KV<'a>> MonotonicCounterDriver<'a, K> {
+    pub fn new(
+        counter: &'a MonotonicCounter<'a, K>,
+        grant: Grant<(), UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> MonotonicCounterDriver<'a, K> {
+        MonotonicCounterDriver {
+            counter,
+            apps: grant,
+            processid: OptionalCell::empty(),
+        }
+    }
+}
+
+impl<'a, K: kv::KV<'a>> MonotonicCounterClient for MonotonicCounterDriver<'a, K> {
+    fn increment_done(&self, result: Result<u64, ErrorCode>) {
+        self.processid.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_, kernel_data| {
+                let (status, count) = match result {
+                    Ok(count) => (errorcode::into_statuscode(Ok(())), count),
+                    Err(error) => (errorcode::into_statuscode(Err(error)), 0),
+                };
+                kernel_data
+                    .schedule_upcall(
+                        upcall::INCREMENT_DONE,
+                        (status, count as u32 as usize, (count >> 32) as usize),
+                    )
+                    .ok();
+            });
+        });
+    }
+}
+
+impl<'a, K: kv::KV<'a>> SyscallDriver for MonotonicCounterDriver<'a, K> {
+    /// Control the monotonic counter.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Increment the counter. The new count is delivered by upcall 0.
+    ///   Returns `BUSY` while an increment is in flight.
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                if self.processid.is_some() {
+                    return CommandReturn::failure(ErrorCode::BUSY);
+                }
+                match self.counter.increment_and_get() {
+                    Ok(()) => {
+                        self.processid.set(processid);
+                        CommandReturn::success()
+                    }
+                    Err(error) => CommandReturn::failure(error),
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// In-memory KV store. Operations complete when `complete()` is called,
+    /// and `set` removes the old value before it stores the new one, like
+    /// TicKV does.
+    struct MockKV<'a> {
+        client: OptionalCell<&'a dyn kv::KVClient>,
+        store: TakeCell<'static, Vec<(Vec<u8>, Vec<u8>)>>,
+        pending: Cell<Option<bool>>,
+        key: TakeCell<'static, [u8]>,
+        key_len: Cell<usize>,
+        value: TakeCell<'static, [u8]>,
+    }
+
+    impl<'a> MockKV<'a> {
+        fn new() -> Self {
+            MockKV {
+                client: OptionalCell::empty(),
+                store: TakeCell::new(Box::leak(Box::new(Vec::new()))),
+                pending: Cell::new(None),
+                key: TakeCell::empty(),
+                key_len: Cell::new(0),
+                value: TakeCell::empty(),
+            }
+        }
+
+        fn hold(&self, key: SubSliceMut<'static, u8>, value: SubSliceMut<'static, u8>, set: bool) {
+            self.key_len.set(key.len());
+            self.key.replace(key.take());
+            self.value.replace(value.take());
+            self.pending.set(Some(set));
+        }
+
+        /// Completes the pending operation.
+        fn complete(&self) {
+            let Some(set) = self.pending.take() else {
+                return;
+            };
+            let key_buf = self.key.take().unwrap();
+            let value_buf = self.value.take().unwrap();
+            let name = key_buf[..self.key_len.get()].to_vec();
+            let mut key = SubSliceMut::new(key_buf);
+            key.slice(..self.key_len.get());
+            let mut value = SubSliceMut::new(value_buf);
+
+            let store = self.store.take().unwrap();
+            if set {
+                store.retain(|(k, _)| *k != name);
+                store.push((name, value.as_slice().to_vec()));
+                self.store.replace(store);
+                self.client
+                    .map(|client| client.set_complete(Ok(()), key, value));
+            } else {
+                let found = store.iter().find(|(k, _)| *k == name).cloned();
+                self.store.replace(store);
+                let result = found
+                    .map(|(_, stored)| {
+                        value.slice(..stored.len());
+                        value.as_slice().copy_from_slice(&stored);
+                    })
+                    .ok_or(ErrorCode::NOSUPPORT);
+                self.client
+                    .map(|client| client.get_complete(result, key, value));
+            }
+        }
+
+        /// Loses power during the pending operation. A set has removed the
+        /// old value but not written the new one.
+        fn crash(&self) {
+            if self.pending.take() == Some(true) {
+                let name = self.key.take().unwrap()[..self.key_len.get()].to_vec();
+                self.store.map(|store| store.retain(|(k, _)| *k != name));
+            }
+        }
+
+        /// Completes operations until the store is idle.
+        fn run(&self) {
+            while self.pending.get().is_some() {
+                self.complete();
+            }
+        }
+    }
+
+    impl<'a> kv::KV<'a> for MockKV<'a> {
+        fn set_client(&self, client: &'a dyn kv::KVClient) {
+            self.client.set(client);
+        }
+
+        fn get(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            self.hold(key, value, false);
+            Ok(())
+        }
+
+        fn set(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            self.hold(key, value, true);
+            Ok(())
+        }
+
+        fn add(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            Err((key, value, ErrorCode::NOSUPPORT))
+        }
+
+        fn update(
+            &self,
+            key: SubSliceMut<'static, u8>,
+            value: SubSliceMut<'static, u8>,
+        ) -> Result<
+            (),
+            (
+                SubSliceMut<'static, u8>,
+                SubSliceMut<'static, u8>,
+                ErrorCode,
+            ),
+        > {
+            Err((key, value, ErrorCode::NOSUPPORT))
+        }
+
+        fn delete(
+            &self,
+            key: SubSliceMut<'static, u8>,
+        ) -> Result<(), (SubSliceMut<'static, u8>, ErrorCode)> {
+            Err((key, ErrorCode::NOSUPPORT))
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        result: Cell<Option<Result<u64, ErrorCode>>>,
+    }
+
+    impl MonotonicCounterClient for MockClient {
+        fn increment_done(&self, result: Result<u64, ErrorCode>) {
+            self.result.set(Some(result));
+        }
+    }
+
+    /// Boots a new counter on `kv`, as after a reset.
+    fn boot(
+        kv: &'static MockKV<'static>,
+    ) -> (
+        &'static MonotonicCounter<'static, MockKV<'static>>,
+        &'static MockClient,
+    ) {
+        let counter = Box::leak(Box::new(MonotonicCounter::new(
+            kv,
+            Box::leak(Box::new([0; KEY_BUFFER_LEN])),
+            Box::leak(Box::new([0; VALUE_BUFFER_LEN])),
+        )));
+        kv::KV::set_client(kv, counter);
+        let client = Box::leak(Box::new(MockClient::default()));
+        counter.set_client(client);
+        (counter, client)
+    }
+
+    fn increment(
+        kv: &MockKV,
+        counter: &MonotonicCounter<'static, MockKV<'static>>,
+        client: &MockClient,
+    ) -> Result<u64, ErrorCode> {
+        counter.increment_and_get()?;
+        kv.run();
+        client.result.take().unwrap()
+    }
+
+    #[test]
+    fn increments_serialized() {
+        let kv = Box::leak(Box::new(MockKV::new()));
+        let (counter, client) = boot(kv);
+
+        assert_eq!(counter.increment_and_get(), Ok(()));
+        assert_eq!(counter.increment_and_get(), Err(ErrorCode::BUSY));
+        kv.run();
+        assert_eq!(client.result.take(), Some(Ok(1)));
+
+        for expected in 2..6 {
+            assert_eq!(increment(kv, counter, client), Ok(expected));
+        }
+
+        // The count survives a reset.
+        let (counter, client) = boot(kv);
+        assert_eq!(increment(kv, counter, client), Ok(6));
+    }
+
+    #[test]
+    fn crash_never_counts_backwards() {
+        let kv = Box::leak(Box::new(MockKV::new()));
+
+        // Lose power at every step of an increment in turn, and check the
+        // count never goes below the last one handed out.
+        let mut last = 0;
+        for steps in 0..3 {
+            for _ in 0..3 {
+                let (counter, client) = boot(kv);
+                last = increment(kv, counter, client).unwrap();
+            }
+
+            let (counter, client) = boot(kv);
+            assert_eq!(counter.increment_and_get(), Ok(()));
+            for _ in 0..steps {
+                kv.complete();
+            }
+            kv.crash();
+            assert_eq!(client.result.take(), None);
+
+            let (counter, client) = boot(kv);
+            let after = increment(kv, counter, client).unwrap();
+            assert!(after > last, "count went from {} to {}", last, after);
+            last = after;
+        }
+        assert_eq!(last, 12);
+    }
+}
@@ -53,6 +53,15 @@
 //! mx25r6435f_spi.set_client(mx25r6435f);
 //! mx25r6435f_virtual_alarm.set_client(mx25r6435f);
 //! ```
+//!
+//! Power Down
+//! ----------
+//!
+//! The chip can be put into deep power-down with `power_down()` from
+//! `hil::flash::PowerControl`. A read, write or erase issued while the chip is
+//! powered down releases it from deep power-down, waits out the recovery time,
+//! and then runs. `power_down()` can be called again once the operation
+//! completes.
 
 use core::cell::Cell;
 use core::ops::{Index, IndexMut};
@@ -124,6 +133,20 @@ enum Opcodes {
     PP = 0x02,   // Page Program (write)
     RDID = 0x9f, // Read Identification
     RDSR = 0x05, // Read Status Register
+    DP = 0xb9,   // Deep Power-down
+    RDP = 0xab,  // Release from Deep Power-down
+}
+
+/// Time from releasing the chip from deep power-down until it accepts
+/// commands (tRDP).
+const WAKE_TIME_US: u32 = 35;
+
+/// An operation waiting for the chip to wake from deep power-down.
+#[derive(Clone, Copy, PartialEq)]
+enum PendingOperation {
+    Read { sector_index: u32 },
+    Write { sector_index: u32 },
+    Erase { sector_index: u32 },
 }
 
 #[derive(Clone, Copy, PartialEq)]
@@ -171,6 +194,10 @@ enum State {
     },
 
     ReadId,
+
+    PowerDown,
+    Wake,
+    WakeWait,
 }
 
 pub struct MX25R6435F<
@@ -188,6 +215,12 @@ pub struct MX25R6435F<
     rxbuffer: TakeCell<'static, [u8]>,
     client: OptionalCell<&'a dyn hil::flash::Client<MX25R6435F<'a, S, P, A>>>,
     client_sector: TakeCell<'static, Mx25r6435fSector>,
+    /// The chip is in deep power-down.
+    powered_down: Cell<bool>,
+    /// `power_down()` was called while the chip was waking up.
+    power_down_requested: Cell<bool>,
+    /// Operation to run once the chip has woken up.
+    pending: Cell<Option<PendingOperation>>,
 }
 
 impl<
@@ -215,6 +248,9 @@ impl<
             rxbuffer: TakeCell::new(rxbuffer),
             client: OptionalCell::empty(),
             client_sector: TakeCell::empty(),
+            powered_down: Cell::new(false),
+            power_down_requested: Cell::new(false),
+            pending: Cell::new(None),
         }
     }
 
@@ -257,6 +293,93 @@ impl<
             })
     }
 
+    /// Sends a single byte command, moving to `state` until it is sent.
+    fn send_command(&self, opcode: Opcodes, state: State) -> Result<(), ErrorCode> {
+        self.configure_spi()?;
+
+        self.txbuffer
+            .take()
+            .map_or(Err(ErrorCode::RESERVE), |txbuffer| {
+                txbuffer[0] = opcode as u8;
+                self.state.set(state);
+                if let Err((err, txbuffer, _)) = self.spi.read_write_bytes(txbuffer, None, 1) {
+                    self.txbuffer.replace(txbuffer);
+                    self.state.set(State::Idle);
+                    Err(err)
+                } else {
+                    Ok(())
+                }
+            })
+    }
+
+    /// Wakes the chip to run `operation`, if it is powered down. Returns
+    /// `None` if the chip is awake and the operation can run right away.
+    fn wake_for(&self, operation: PendingOperation) -> Option<Result<(), ErrorCode>> {
+        match self.state.get() {
+            State::Idle if self.powered_down.get() => {
+                self.pending.set(Some(operation));
+                let result = self.send_command(Opcodes::RDP, State::Wake);
+                if result.is_err() {
+                    self.pending.set(None);
+                }
+                Some(result)
+            }
+            State::Wake | State::WakeWait if self.pending.get().is_none() => {
+                // Already waking up, the operation wins over any power down
+                // requested in the meantime.
+                self.power_down_requested.set(false);
+                self.pending.set(Some(operation));
+                Some(Ok(()))
+            }
+            State::PowerDown | State::Wake | State::WakeWait => Some(Err(ErrorCode::BUSY)),
+            _ => None,
+        }
+    }
+
+    /// The chip has recovered from deep power-down.
+    fn wake_done(&self) {
+        self.state.set(State::Idle);
+        self.powered_down.set(false);
+
+        if self.power_down_requested.take() {
+            // `power_down()` was called while the wake up was in progress.
+            let _ = self.send_command(Opcodes::DP, State::PowerDown);
+            return;
+        }
+
+        // Run the operation that woke the chip up, and report it to the client
+        // if it cannot start.
+        match self.pending.take() {
+            Some(PendingOperation::Read { sector_index }) => {
+                self.client_sector.take().map(|sector| {
+                    if let Err((_, sector)) = self.read_sector(sector_index, sector) {
+                        self.client.map(move |client| {
+                            client.read_complete(sector, Err(hil::flash::Error::FlashError));
+                        });
+                    }
+                });
+            }
+            Some(PendingOperation::Write { sector_index }) => {
+                self.client_sector.take().map(|sector| {
+                    if let Err((_, sector)) = self.write_sector(sector_index, sector) {
+                        self.client.map(move |client| {
+                            client.write_complete(sector, Err(hil::flash::Error::FlashError));
+                        });
+                    }
+                });
+            }
+            Some(PendingOperation::Erase { sector_index }) => {
+                if self.erase_sector(sector_index).is_err() {
+                    self.state.set(State::Idle);
+                    self.client.map(|client| {
+                        client.erase_complete(Err(hil::flash::Error::FlashError));
+                    });
+                }
+            }
+            None => {}
+        }
+    }
+
     fn enable_write(&self) -> Result<(), ErrorCode> {
         self.write_protect_pin.map(|pin| {
             pin.set();
@@ -572,6 +695,17 @@ impl<
                     }
                 });
             }
+            State::PowerDown => {
+                self.state.set(State::Idle);
+                self.powered_down.set(true);
+                self.txbuffer.replace(write_buffer);
+            }
+            State::Wake => {
+                self.state.set(State::WakeWait);
+                self.txbuffer.replace(write_buffer);
+                let delay = self.alarm.ticks_from_us(WAKE_TIME_US);
+                self.alarm.set_alarm(self.alarm.now(), delay);
+            }
             _ => {}
         }
     }
@@ -585,6 +719,11 @@ impl<
     > hil::time::AlarmClient for MX25R6435F<'a, S, P, A>
 {
     fn alarm(&self) {
+        if self.state.get() == State::WakeWait {
+            self.wake_done();
+            return;
+        }
+
         // After the timer expires we still have to check that the erase/write
         // operation has finished.
         self.txbuffer.take().map(|write_buffer| {
@@ -625,7 +764,15 @@ impl<
         page_number: usize,
         buf: &'static mut Self::Page,
     ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
-        self.read_sector(page_number as u32, buf)
+        let sector_index = page_number as u32;
+        match self.wake_for(PendingOperation::Read { sector_index }) {
+            None => self.read_sector(sector_index, buf),
+            Some(Ok(())) => {
+                self.client_sector.replace(buf);
+                Ok(())
+            }
+            Some(Err(err)) => Err((err, buf)),
+        }
     }
 
     fn write_page(
@@ -633,10 +780,54 @@ impl<
         page_number: usize,
         buf: &'static mut Self::Page,
     ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
-        self.write_sector(page_number as u32, buf)
+        let sector_index = page_number as u32;
+        match self.wake_for(PendingOperation::Write { sector_index }) {
+            None => self.write_sector(sector_index, buf),
+            Some(Ok(())) => {
+                self.client_sector.replace(buf);
+                Ok(())
+            }
+            Some(Err(err)) => Err((err, buf)),
+        }
     }
 
     fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
-        self.erase_sector(page_number as u32)
+        let sector_index = page_number as u32;
+        self.wake_for(PendingOperation::Erase { sector_index })
+            .unwrap_or_else(|| self.erase_sector(sector_index))
+    }
+}
+
+impl<
+        'a,
+        S: hil::spi::SpiMasterDevice<'a> + 'a,
+        P: hil::gpio::Pin + 'a,
+        A: hil::time::Alarm<'a> + 'a,
+    > hil::flash::PowerControl for MX25R6435F<'a, S, P, A>
+{
+    fn power_down(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle if self.powered_down.get() => Err(ErrorCode::ALREADY),
+            State::Idle => self.send_command(Opcodes::DP, State::PowerDown),
+            State::Wake | State::WakeWait if self.pending.get().is_none() => {
+                // Go back to sleep as soon as the chip has woken up.
+                self.power_down_requested.set(true);
+                Ok(())
+            }
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    fn wake(&self) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle if self.powered_down.get() => self.send_command(Opcodes::RDP, State::Wake),
+            State::Idle => Err(ErrorCode::ALREADY),
+            State::Wake | State::WakeWait => {
+                self.power_down_requested.set(false);
+                Ok(())
+            }
+            State::PowerDown => Err(ErrorCode::BUSY),
+            _ => Err(ErrorCode::ALREADY),
+        }
     }
 }
@@ -8,6 +8,7 @@ use crate::net::ipv6::{IP6Header, IP6Packet, TransportHeader};
 use crate::net::udp::UDPHeader;
 use crate::net::util;
 use crate::net::util::{network_slice_to_u16, u16_to_network_slice};
+use core::cell::Cell;
 /// Implements the 6LoWPAN specification for sending IPv6 datagrams over
 /// 802.15.4 packets efficiently, as detailed in RFC 6282.
 use core::mem;
@@ -155,6 +156,83 @@ impl ContextStore for Context {
     }
 }
 
+/// The maximum number of contexts that can be identified by the 4-bit context
+/// identifiers in the LoWPAN_IPHC header.
+pub const MAX_CONTEXTS: usize = 16;
+
+/// A `ContextStore` backed by a fixed-size array of up to 16 contexts, indexed
+/// by their context ID.
+///
+/// Contexts can be added and removed at runtime, for example by a border
+/// router configuring the mesh prefix. Address lookups return the context
+/// with the longest matching prefix.
+pub struct ArrayContextStore {
+    contexts: [Cell<Option<Context>>; MAX_CONTEXTS],
+}
+
+impl ArrayContextStore {
+    /// Creates a new store with `ctx_0` as context 0. Context 0 must always
+    /// be available and should contain the mesh-local prefix.
+    pub fn new(ctx_0: Context) -> ArrayContextStore {
+        let store = ArrayContextStore {
+            contexts: Default::default(),
+        };
+        store.contexts[0].set(Some(Context { id: 0, ..ctx_0 }));
+        store
+    }
+
+    /// Sets the context with ID `ctx.id`, replacing any existing one.
+    ///
+    /// Returns `Err(())` if the ID is not a valid 4-bit context ID.
+    pub fn set_context(&self, ctx: Context) -> Result<(), ()> {
+        let slot = self.contexts.get(ctx.id as usize).ok_or(())?;
+        slot.set(Some(ctx));
+        Ok(())
+    }
+
+    /// Removes the context with ID `ctx_id`.
+    ///
+    /// Returns `Err(())` if the ID is not valid or is 0, as context 0 must
+    /// always be available.
+    pub fn clear_context(&self, ctx_id: u8) -> Result<(), ()> {
+        if ctx_id == 0 {
+            return Err(());
+        }
+        let slot = self.contexts.get(ctx_id as usize).ok_or(())?;
+        slot.set(None);
+        Ok(())
+    }
+}
+
+impl ContextStore for ArrayContextStore {
+    fn get_context_from_addr(&self, ip_addr: IPAddr) -> Option<Context> {
+        self.contexts
+            .iter()
+            .filter_map(|slot| slot.get())
+            .filter(|ctx| util::matches_prefix(&ip_addr.0, &ctx.prefix, ctx.prefix_len))
+            .fold(None, |best: Option<Context>, ctx| match best {
+                Some(best) if best.prefix_len >= ctx.prefix_len => Some(best),
+                _ => Some(ctx),
+            })
+    }
+
+    fn get_context_from_id(&self, ctx_id: u8) -> Option<Context> {
+        self.contexts
+            .get(ctx_id as usize)
+            .and_then(|slot| slot.get())
+    }
+
+    fn get_context_from_prefix(&self, prefix: &[u8], prefix_len: u8) -> Option<Context> {
+        self.contexts
+            .iter()
+            .filter_map(|slot| slot.get())
+            .find(|ctx| {
+                prefix_len == ctx.prefix_len
+                    && util::matches_prefix(prefix, &ctx.prefix, prefix_len)
+            })
+    }
+}
+
 pub fn is_lowpan(packet: &[u8]) -> bool {
     (packet[0] & iphc::DISPATCH[0]) == iphc::DISPATCH[0]
 }
@@ -1183,3 +1261,103 @@ fn decompress_udp_checksum(
         checksum
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(prefix: [u8; 16], prefix_len: u8, id: u8) -> Context {
+        Context {
+            prefix,
+            prefix_len,
+            id,
+            compress: true,
+        }
+    }
+
+    fn addr(prefix: &[u8]) -> IPAddr {
+        let mut ip_addr = IPAddr([0; 16]);
+        ip_addr.0[..prefix.len()].copy_from_slice(prefix);
+        ip_addr
+    }
+
+    #[test]
+    fn context_0_always_present() {
+        let store = ArrayContextStore::new(context([0xfd; 16], 64, 5));
+        assert_eq!(store.get_context_0().id, 0);
+        assert!(store.clear_context(0).is_err());
+        assert!(store.get_context_from_id(0).is_some());
+    }
+
+    #[test]
+    fn set_and_clear_by_id() {
+        let store = ArrayContextStore::new(context([0xfd; 16], 64, 0));
+        assert!(store.get_context_from_id(3).is_none());
+
+        store.set_context(context([0x20; 16], 48, 3)).unwrap();
+        assert_eq!(store.get_context_from_id(3).unwrap().prefix_len, 48);
+
+        store.clear_context(3).unwrap();
+        assert!(store.get_context_from_id(3).is_none());
+
+        assert!(store.set_context(context([0x20; 16], 48, 16)).is_err());
+        assert!(store.clear_context(16).is_err());
+        assert!(store.get_context_from_id(16).is_none());
+    }
+
+    #[test]
+    fn longest_prefix_match() {
+        let mut prefix = [0; 16];
+        prefix[..4].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        let store = ArrayContextStore::new(context([0xfd; 16], 8, 0));
+        store.set_context(context(prefix, 32, 1)).unwrap();
+        prefix[4] = 0xab;
+        store.set_context(context(prefix, 40, 2)).unwrap();
+
+        let ctx = store.get_context_from_addr(addr(&[0x20, 0x01, 0x0d, 0xb8, 0xab]));
+        assert_eq!(ctx.unwrap().id, 2);
+
+        let ctx = store.get_context_from_addr(addr(&[0x20, 0x01, 0x0d, 0xb8, 0xac]));
+        assert_eq!(ctx.unwrap().id, 1);
+
+        let ctx = store.get_context_from_addr(addr(&[0xfd, 0x00]));
+        assert_eq!(ctx.unwrap().id, 0);
+
+        assert!(store.get_context_from_addr(addr(&[0xfe, 0x80])).is_none());
+    }
+
+    #[test]
+    fn non_byte_aligned_prefix() {
+        let mut prefix = [0; 16];
+        // 2001:db8:a000::/35
+        prefix[..5].copy_from_slice(&[0x20, 0x01, 0x0d, 0xb8, 0xa0]);
+        let store = ArrayContextStore::new(context([0xfd; 16], 8, 0));
+        store.set_context(context(prefix, 35, 1)).unwrap();
+        // 2001:db8:a000::/33, only the top bit of the fifth byte matters
+        store.set_context(context(prefix, 33, 2)).unwrap();
+
+        // The first three bits of the fifth byte match
+        let ctx = store.get_context_from_addr(addr(&[0x20, 0x01, 0x0d, 0xb8, 0xbf]));
+        assert_eq!(ctx.unwrap().id, 1);
+
+        // Only the first bit of the fifth byte matches
+        let ctx = store.get_context_from_addr(addr(&[0x20, 0x01, 0x0d, 0xb8, 0x80]));
+        assert_eq!(ctx.unwrap().id, 2);
+
+        // The first bit of the fifth byte differs
+        assert!(store
+            .get_context_from_addr(addr(&[0x20, 0x01, 0x0d, 0xb8, 0x20]))
+            .is_none());
+
+        assert_eq!(
+            store
+                .get_context_from_prefix(&[0x20, 0x01, 0x0d, 0xb8, 0xbf], 35)
+                .unwrap()
+                .id,
+            1
+        );
+        assert!(store
+            .get_context_from_prefix(&[0x20, 0x01, 0x0d, 0xb8, 0xbf], 34)
+            .is_none());
+    }
+}
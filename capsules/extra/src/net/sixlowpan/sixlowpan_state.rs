@@ -243,12 +243,12 @@ use core::cmp::min;
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::hil::radio;
 use kernel::hil::time;
-use kernel::hil::time::{Frequency, Ticks};
+use kernel::hil::time::{ConvertTicks, Ticks};
 use kernel::utilities::cells::{MapCell, TakeCell};
 use kernel::ErrorCode;
 
-// Reassembly timeout in seconds
-const FRAG_TIMEOUT: u32 = 60;
+/// Default reassembly timeout in seconds
+pub const DEFAULT_FRAG_TIMEOUT: u32 = 60;
 
 /// Objects that implement this trait can set themselves to be the client
 /// for the [Sixlowpan](struct.Sixlowpan.html) struct, and will then receive
@@ -304,7 +304,6 @@ fn is_fragment(packet: &[u8]) -> bool {
 pub trait SixlowpanState<'a> {
     fn next_dgram_tag(&self) -> u16;
     fn get_ctx_store(&self) -> &dyn ContextStore;
-    fn add_rx_state(&self, rx_state: &'a RxState<'a>);
     fn set_rx_client(&'a self, client: &'a dyn SixlowpanRxClient);
 }
 
@@ -629,7 +628,7 @@ impl<'a> TxState<'a> {
 /// keep track of ongoing packet reassemblies. The number of `RxState`s is the
 /// number of packets that can be reassembled at the same time. Generally,
 /// two `RxState`s are sufficient for normal-case operation.
-pub struct RxState<'a> {
+pub struct RxState<'a, T: Ticks> {
     packet: TakeCell<'static, [u8]>,
     bitmap: MapCell<Bitmap>,
     dst_mac_addr: Cell<MacAddress>,
@@ -640,25 +639,25 @@ pub struct RxState<'a> {
     // free to use for a new packet.
     busy: Cell<bool>,
     // The time when packet reassembly started for the current packet.
-    start_time: Cell<u32>,
+    start_time: Cell<T>,
 
-    next: ListLink<'a, RxState<'a>>,
+    next: ListLink<'a, RxState<'a, T>>,
 }
 
-impl<'a> ListNode<'a, RxState<'a>> for RxState<'a> {
-    fn next(&'a self) -> &'a ListLink<RxState<'a>> {
+impl<'a, T: Ticks> ListNode<'a, RxState<'a, T>> for RxState<'a, T> {
+    fn next(&'a self) -> &'a ListLink<RxState<'a, T>> {
         &self.next
     }
 }
 
-impl<'a> RxState<'a> {
+impl<'a, T: Ticks> RxState<'a, T> {
     /// Creates a new `RxState`
     ///
     /// # Arguments
     ///
     /// `packet` - A buffer for reassembling an IPv6 packet. Currently, we
     /// assume this to be 1280 bytes long (the minimum IPv6 MTU size).
-    pub fn new(packet: &'static mut [u8]) -> RxState<'a> {
+    pub fn new(packet: &'static mut [u8]) -> RxState<'a, T> {
         RxState {
             packet: TakeCell::new(packet),
             bitmap: MapCell::new(Bitmap::new()),
@@ -667,7 +666,7 @@ impl<'a> RxState<'a> {
             dgram_tag: Cell::new(0),
             dgram_size: Cell::new(0),
             busy: Cell::new(false),
-            start_time: Cell::new(0),
+            start_time: Cell::new(T::from(0)),
             next: ListLink::empty(),
         }
     }
@@ -688,8 +687,11 @@ impl<'a> RxState<'a> {
 
     // Checks if a given RxState is free or expired (and thus, can be freed).
     // This function implements the reassembly timeout for 6LoWPAN lazily.
-    fn is_busy(&self, frequency: u32, current_time: u32) -> bool {
-        let expired = current_time >= (self.start_time.get() + FRAG_TIMEOUT * frequency);
+    // `timeout_tics` is the reassembly timeout converted to clock tics.
+    fn is_busy(&self, timeout_tics: T, current_time: T) -> bool {
+        let start = self.start_time.get();
+        let expired =
+            self.busy.get() && !current_time.within_range(start, start.wrapping_add(timeout_tics));
         if expired {
             self.end_receive(None, Err(ErrorCode::FAIL));
         }
@@ -702,7 +704,7 @@ impl<'a> RxState<'a> {
         dst_mac_addr: MacAddress,
         dgram_size: u16,
         dgram_tag: u16,
-        current_tics: u32,
+        current_tics: T,
     ) {
         self.dst_mac_addr.set(dst_mac_addr);
         self.src_mac_addr.set(src_mac_addr);
@@ -766,7 +768,7 @@ impl<'a> RxState<'a> {
     ) {
         self.busy.set(false);
         self.bitmap.map(|bitmap| bitmap.clear());
-        self.start_time.set(0);
+        self.start_time.set(T::from(0));
         client.map(move |client| {
             // Since packet is borrowed from the upper layer, failing to return it
             // in the callback represents a significant error that should never
@@ -793,14 +795,18 @@ impl<'a> RxState<'a> {
 ///
 /// Finally, `set_client` controls the client that will receive transmission
 /// completion and reception callbacks.
+///
+/// Partially reassembled packets are dropped once they are older than the
+/// reassembly timeout, which can be changed with `set_reassembly_timeout`.
 pub struct Sixlowpan<'a, A: time::Alarm<'a>, C: ContextStore> {
     pub ctx_store: C,
     clock: &'a A,
     tx_dgram_tag: Cell<u16>,
+    frag_timeout: Cell<u32>,
     rx_client: Cell<Option<&'a dyn SixlowpanRxClient>>,
 
     // Receive state
-    rx_states: List<'a, RxState<'a>>,
+    rx_states: List<'a, RxState<'a, A::Ticks>>,
 }
 
 // This function is called after receiving a frame
@@ -810,6 +816,7 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> RxClient for Sixlowpan<'a, A, C> {
         buf: &'b [u8],
         header: Header<'b>,
         _lqi: u8,
+        _rssi: Option<i8>,
         data_offset: usize,
         data_len: usize,
     ) {
@@ -848,14 +855,6 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> SixlowpanState<'a> for Sixlowpan<'
         &self.ctx_store
     }
 
-    /// Adds an additional `RxState` for reassembling IPv6 packets
-    ///
-    /// Each [RxState](struct.RxState.html) struct allows an additional IPv6
-    /// packet to be reassembled concurrently.
-    fn add_rx_state(&self, rx_state: &'a RxState<'a>) {
-        self.rx_states.push_head(rx_state);
-    }
-
     /// Sets the [SixlowpanClient](trait.SixlowpanClient.html) that will receive
     /// transmission completion and new packet reception callbacks.
     fn set_rx_client(&'a self, client: &'a dyn SixlowpanRxClient) {
@@ -882,19 +881,47 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
             ctx_store,
             clock,
             tx_dgram_tag: Cell::new(0),
+            frag_timeout: Cell::new(DEFAULT_FRAG_TIMEOUT),
             rx_client: Cell::new(None),
 
             rx_states: List::new(),
         }
     }
 
+    /// Adds an additional `RxState` for reassembling IPv6 packets
+    ///
+    /// Each [RxState](struct.RxState.html) struct allows an additional IPv6
+    /// packet to be reassembled concurrently.
+    pub fn add_rx_state(&self, rx_state: &'a RxState<'a, A::Ticks>) {
+        self.rx_states.push_head(rx_state);
+    }
+
+    /// Sets the reassembly timeout, in seconds.
+    ///
+    /// Partially reassembled packets older than this are dropped the next
+    /// time their `RxState` is needed for a new packet. Defaults to
+    /// `DEFAULT_FRAG_TIMEOUT`.
+    pub fn set_reassembly_timeout(&self, seconds: u32) {
+        self.frag_timeout.set(seconds);
+    }
+
+    /// Returns the reassembly timeout, in seconds.
+    pub fn get_reassembly_timeout(&self) -> u32 {
+        self.frag_timeout.get()
+    }
+
+    // The reassembly timeout converted to clock tics
+    fn frag_timeout_tics(&self) -> A::Ticks {
+        self.clock.ticks_from_seconds(self.frag_timeout.get())
+    }
+
     fn receive_frame(
         &self,
         packet: &[u8],
         packet_len: usize,
         src_mac_addr: MacAddress,
         dst_mac_addr: MacAddress,
-    ) -> (Option<&RxState<'a>>, Result<(), ErrorCode>) {
+    ) -> (Option<&RxState<'a, A::Ticks>>, Result<(), ErrorCode>) {
         if is_fragment(packet) {
             let (is_frag1, dgram_size, dgram_tag, dgram_offset) = get_frag_hdr(&packet[0..5]);
             let offset_to_payload = if is_frag1 {
@@ -922,18 +949,18 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
         payload_len: usize,
         src_mac_addr: MacAddress,
         dst_mac_addr: MacAddress,
-    ) -> (Option<&RxState<'a>>, Result<(), ErrorCode>) {
+    ) -> (Option<&RxState<'a, A::Ticks>>, Result<(), ErrorCode>) {
         let rx_state = self
             .rx_states
             .iter()
-            .find(|state| !state.is_busy(self.clock.now().into_u32(), A::Frequency::frequency()));
+            .find(|state| !state.is_busy(self.frag_timeout_tics(), self.clock.now()));
         rx_state.map_or((None, Err(ErrorCode::NOMEM)), |state| {
             state.start_receive(
                 src_mac_addr,
                 dst_mac_addr,
                 payload_len as u16,
                 0,
-                self.clock.now().into_u32(),
+                self.clock.now(),
             );
             // The packet buffer should *always* be there; in particular,
             // since this state is not busy, it must have the packet buffer.
@@ -984,7 +1011,7 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
         dgram_size: u16,
         dgram_tag: u16,
         dgram_offset: usize,
-    ) -> (Option<&RxState<'a>>, Result<(), ErrorCode>) {
+    ) -> (Option<&RxState<'a, A::Ticks>>, Result<(), ErrorCode>) {
         // First try to find an rx_state in the middle of assembly
         let mut rx_state = self
             .rx_states
@@ -993,9 +1020,10 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
 
         // Else find a free state
         if rx_state.is_none() {
-            rx_state = self.rx_states.iter().find(|state| {
-                !state.is_busy(self.clock.now().into_u32(), A::Frequency::frequency())
-            });
+            rx_state = self
+                .rx_states
+                .iter()
+                .find(|state| !state.is_busy(self.frag_timeout_tics(), self.clock.now()));
             // Initialize new state
             rx_state.map(|state| {
                 state.start_receive(
@@ -1003,7 +1031,7 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
                     dst_mac_addr,
                     dgram_size,
                     dgram_tag,
-                    self.clock.now().into_u32(),
+                    self.clock.now(),
                 )
             });
             if rx_state.is_none() {
@@ -1047,3 +1075,129 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
         // TODO: Need to get buffer back from Mac layer on disassociation
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::net::sixlowpan::sixlowpan_compression::Context;
+    use kernel::hil::time::{Alarm, AlarmClient, Freq32KHz, Ticks24, Time};
+    use std::boxed::Box;
+
+    /// A 24-bit counter whose time only advances when the test sets it.
+    #[derive(Default)]
+    struct MockAlarm {
+        now: Cell<u32>,
+    }
+
+    impl Time for MockAlarm {
+        type Ticks = Ticks24;
+        type Frequency = Freq32KHz;
+
+        fn now(&self) -> Ticks24 {
+            self.now.get().into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Self::Ticks, _dt: Self::Ticks) {}
+
+        fn get_alarm(&self) -> Self::Ticks {
+            0.into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            false
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            1.into()
+        }
+    }
+
+    const SRC: MacAddress = MacAddress::Short(1);
+    const DST: MacAddress = MacAddress::Short(2);
+
+    fn new_rx_state() -> &'static RxState<'static, Ticks24> {
+        Box::leak(Box::new(RxState::new(Box::leak(Box::new([0_u8; 1280])))))
+    }
+
+    fn new_sixlowpan(alarm: &'static MockAlarm) -> &'static Sixlowpan<'static, MockAlarm, Context> {
+        let context = Context {
+            prefix: [0; 16],
+            prefix_len: 0,
+            id: 0,
+            compress: false,
+        };
+        let sixlowpan = Box::leak(Box::new(Sixlowpan::new(context, alarm)));
+        sixlowpan.add_rx_state(new_rx_state());
+        sixlowpan
+    }
+
+    /// Builds a non-final FRAGN fragment carrying 8 bytes at offset 8.
+    fn fragment(dgram_tag: u16) -> [u8; 13] {
+        let mut frame = [0xaa; 13];
+        set_frag_hdr(72, dgram_tag, 8, &mut frame[0..5], false);
+        frame
+    }
+
+    #[test]
+    fn rx_state_expires_across_counter_wrap() {
+        let state = new_rx_state();
+        let timeout = Ticks24::from(0x100);
+        state.start_receive(SRC, DST, 64, 1, Ticks24::from(0xff_fff0));
+
+        // 0x20 ticks have passed since the counter wrapped.
+        assert!(state.is_busy(timeout, Ticks24::from(0x10)));
+        assert!(state.is_busy(timeout, Ticks24::from(0xef)));
+        assert!(!state.is_busy(timeout, Ticks24::from(0xf0)));
+    }
+
+    #[test]
+    fn set_reassembly_timeout_converts_to_ticks() {
+        let alarm = Box::leak(Box::new(MockAlarm::default()));
+        let sixlowpan = new_sixlowpan(alarm);
+        assert_eq!(sixlowpan.get_reassembly_timeout(), DEFAULT_FRAG_TIMEOUT);
+
+        sixlowpan.set_reassembly_timeout(2);
+        assert_eq!(sixlowpan.get_reassembly_timeout(), 2);
+        assert_eq!(sixlowpan.frag_timeout_tics(), Ticks24::from(2 * 32768));
+
+        // Longer than the counter can represent saturates.
+        sixlowpan.set_reassembly_timeout(1000);
+        assert_eq!(sixlowpan.frag_timeout_tics(), Ticks24::max_value());
+    }
+
+    #[test]
+    fn stale_reassembly_is_dropped_after_timeout() {
+        let alarm = Box::leak(Box::new(MockAlarm::default()));
+        alarm.now.set(0xff_0000);
+        let sixlowpan = new_sixlowpan(alarm);
+        sixlowpan.set_reassembly_timeout(1);
+
+        let first = fragment(1);
+        let (done, result) = sixlowpan.receive_frame(&first, first.len(), SRC, DST);
+        assert!(done.is_none());
+        assert_eq!(result, Ok(()));
+
+        // The only RxState is still reassembling the first datagram.
+        let second = fragment(2);
+        alarm.now.set(0xff_0000 + 32767);
+        let (_, result) = sixlowpan.receive_frame(&second, second.len(), SRC, DST);
+        assert_eq!(result, Err(ErrorCode::NOMEM));
+
+        // One second later the counter has wrapped, and the first datagram
+        // has timed out.
+        alarm.now.set((0xff_0000 + 32768) & Ticks24::MASK);
+        let (done, result) = sixlowpan.receive_frame(&second, second.len(), SRC, DST);
+        assert!(done.is_none());
+        assert_eq!(result, Ok(()));
+    }
+}
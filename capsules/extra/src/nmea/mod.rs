@@ -0,0 +1,341 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Shared support for NMEA 0183 sentence readers.
+//!
+//! GNSS receivers output a stream of `$...*hh\r\n` sentences. The
+//! `SentenceAssembler` frames that stream into complete sentences one byte
+//! at a time, independent of the transport the bytes arrive over. It is used
+//! by the [I2C](crate::nmea_i2c) and [UART](crate::nmea_uart) readers.
+//!
+//! Every sentence ends in a `*hh` checksum, the XOR of all characters between
+//! `$` and `*` as two hex digits. Sentences with a missing or wrong checksum
+//! are reported as `ErrorCode::FAIL`. For receivers that omit the checksum
+//! verification can be turned off.
+//!
+//! The `NmeaReader` holds the transport independent half of a reader: it
+//! feeds received chunks of the stream to the assembler, asks the transport
+//! for more until a sentence is complete, and delivers the sentence to the
+//! `NmeaClient`.
+//!
+//! The [`parse`] module decodes the common sentence types into structured
+//! fields, so clients do not need to handle the raw text.
+
+pub mod parse;
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::sensors::NmeaClient;
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Maximum length of an assembled sentence. NMEA 0183 limits sentences to
+/// 82 characters, but some receivers emit longer proprietary sentences.
+pub const NMEA_BUFFER_LEN: usize = 128;
+
+/// Byte returned by some receivers when no data is available.
+const IDLE_BYTE: u8 = 0xFF;
+
+/// Checks the `*hh` checksum at the end of `sentence`, which starts with `$`
+/// and does not include the CRLF.
+fn checksum_valid(sentence: &[u8]) -> bool {
+    if sentence.len() < 4 || sentence[sentence.len() - 3] != b'*' {
+        return false;
+    }
+    let (body, checksum) = sentence.split_at(sentence.len() - 3);
+    let expected = core::str::from_utf8(&checksum[1..])
+        .ok()
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    let actual = body[1..].iter().fold(0, |acc, byte| acc ^ byte);
+    expected == Some(actual)
+}
+
+/// Assembles a byte stream into complete NMEA sentences.
+pub struct SentenceAssembler {
+    buffer: TakeCell<'static, [u8]>,
+    /// Number of bytes of the current sentence in `buffer`. Zero if we are
+    /// waiting for the start of a sentence.
+    len: Cell<usize>,
+    verify_checksum: bool,
+}
+
+impl SentenceAssembler {
+    pub fn new(
+        buffer: &'static mut [u8; NMEA_BUFFER_LEN],
+        verify_checksum: bool,
+    ) -> SentenceAssembler {
+        SentenceAssembler {
+            buffer: TakeCell::new(buffer),
+            len: Cell::new(0),
+            verify_checksum,
+        }
+    }
+
+    /// Adds `byte` to the sentence being assembled. Returns the length of the
+    /// sentence, without the CRLF, once it is complete. The sentence can then
+    /// be retrieved with `sentence()` until the next byte is pushed.
+    pub fn push(&self, byte: u8) -> Option<usize> {
+        self.buffer.map_or(None, |sentence| {
+            let len = self.len.get();
+
+            match byte {
+                b'$' => {
+                    // Start of a sentence. If we were in the middle of another
+                    // one it was truncated, so drop it and resync here.
+                    sentence[0] = byte;
+                    self.len.set(1);
+                    None
+                }
+                _ if len == 0 => {
+                    // Skip idle and padding bytes between sentences.
+                    None
+                }
+                IDLE_BYTE => {
+                    // The receiver ran out of data in the middle of a
+                    // sentence.
+                    None
+                }
+                b'\n' => {
+                    self.len.set(0);
+                    if sentence[len - 1] == b'\r' {
+                        Some(len - 1)
+                    } else {
+                        Some(len)
+                    }
+                }
+                _ => {
+                    if len >= sentence.len() {
+                        // The sentence is longer than the buffer. Drop it
+                        // and wait for the next one.
+                        self.len.set(0);
+                    } else {
+                        sentence[len] = byte;
+                        self.len.set(len + 1);
+                    }
+                    None
+                }
+            }
+        })
+    }
+
+    /// Adds the bytes of `data` to the sentence being assembled, stopping as
+    /// soon as a sentence is complete. Returns the number of bytes consumed
+    /// and the length of the sentence, if one was completed.
+    pub fn push_slice(&self, data: &[u8]) -> (usize, Option<usize>) {
+        for (i, byte) in data.iter().enumerate() {
+            if let Some(len) = self.push(*byte) {
+                return (i + 1, Some(len));
+            }
+        }
+        (data.len(), None)
+    }
+
+    /// Calls `f` with the complete sentence of `len` bytes, or an error if
+    /// the sentence is corrupt.
+    pub fn sentence<F: FnOnce(Result<&str, ErrorCode>)>(&self, len: usize, f: F) {
+        self.buffer.map(|sentence| {
+            let sentence = &sentence[..len];
+            let result = if self.verify_checksum && !checksum_valid(sentence) {
+                Err(ErrorCode::FAIL)
+            } else {
+                core::str::from_utf8(sentence).map_err(|_| ErrorCode::FAIL)
+            };
+            f(result);
+        });
+    }
+}
+
+/// Transport independent part of an `NmeaDriver`.
+///
+/// The transport passes a closure that starts receiving the next chunk of the
+/// stream into the chunk buffer, returning the buffer if that fails. Once the
+/// receive completes, the transport hands the buffer back with `received()`.
+pub struct NmeaReader<'a> {
+    client: OptionalCell<&'a dyn NmeaClient>,
+    buffer: TakeCell<'static, [u8]>,
+    /// Offset of the first byte in `buffer` that has not been consumed.
+    offset: Cell<usize>,
+    /// Number of valid bytes in `buffer`.
+    len: Cell<usize>,
+    assembler: SentenceAssembler,
+    reading: Cell<bool>,
+    /// Length of a sentence assembled from left over bytes, waiting to be
+    /// delivered from a deferred call.
+    pending_len: OptionalCell<usize>,
+    deferred_call: DeferredCall,
+}
+
+impl<'a> NmeaReader<'a> {
+    pub fn new(
+        buffer: &'static mut [u8],
+        sentence_buffer: &'static mut [u8; NMEA_BUFFER_LEN],
+        verify_checksum: bool,
+    ) -> NmeaReader<'a> {
+        NmeaReader {
+            client: OptionalCell::empty(),
+            buffer: TakeCell::new(buffer),
+            offset: Cell::new(0),
+            len: Cell::new(0),
+            assembler: SentenceAssembler::new(sentence_buffer, verify_checksum),
+            reading: Cell::new(false),
+            pending_len: OptionalCell::empty(),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn NmeaClient) {
+        self.client.set(client);
+    }
+
+    /// Starts reading the next sentence, calling `receive` if more of the
+    /// stream is needed.
+    pub fn read_sentence<F>(&self, receive: F) -> Result<(), ErrorCode>
+    where
+        F: FnOnce(&'static mut [u8]) -> Result<(), (ErrorCode, &'static mut [u8])>,
+    {
+        if self.reading.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+
+        // The previous receive may have ended in the middle of the buffer,
+        // so first try to assemble a sentence from the bytes left over.
+        if let Some(len) = self.assemble(buffer) {
+            self.buffer.replace(buffer);
+            self.reading.set(true);
+            self.pending_len.set(len);
+            self.deferred_call.set();
+            return Ok(());
+        }
+
+        self.receive(buffer, receive)?;
+        self.reading.set(true);
+        Ok(())
+    }
+
+    /// Handles the completion of a receive into `buffer`, which returned
+    /// either the number of bytes received or an error. If no sentence is
+    /// complete yet the next chunk is received with `receive`.
+    pub fn received<F>(
+        &self,
+        buffer: &'static mut [u8],
+        result: Result<usize, ErrorCode>,
+        receive: F,
+    ) where
+        F: FnOnce(&'static mut [u8]) -> Result<(), (ErrorCode, &'static mut [u8])>,
+    {
+        let rx_len = match result {
+            Ok(rx_len) => core::cmp::min(rx_len, buffer.len()),
+            Err(error) => {
+                self.buffer.replace(buffer);
+                self.fail(error);
+                return;
+            }
+        };
+
+        self.len.set(rx_len);
+        match self.assemble(buffer) {
+            Some(len) => {
+                self.buffer.replace(buffer);
+                self.deliver(len);
+            }
+            None => {
+                // No complete sentence yet, keep receiving.
+                if let Err(error) = self.receive(buffer, receive) {
+                    self.fail(error);
+                }
+            }
+        }
+    }
+
+    /// Delivers a sentence assembled by `read_sentence()`. Called by the
+    /// transport from its deferred call handler.
+    pub fn handle_deferred_call(&self) {
+        self.pending_len.take().map(|len| self.deliver(len));
+    }
+
+    /// Registers the transport as the handler of the deferred call used by
+    /// `read_sentence()`.
+    pub fn register_deferred_call<DC: DeferredCallClient>(&self, client: &'static DC) {
+        self.deferred_call.register(client);
+    }
+
+    /// Assembles the unconsumed bytes in the chunk buffer into a sentence.
+    /// Returns the length of the sentence once it is complete.
+    fn assemble(&self, buffer: &[u8]) -> Option<usize> {
+        let offset = self.offset.get();
+        let (used, len) = self.assembler.push_slice(&buffer[offset..self.len.get()]);
+        self.offset.set(offset + used);
+        len
+    }
+
+    /// Receives the next chunk of the stream with `receive`.
+    fn receive<F>(&self, buffer: &'static mut [u8], receive: F) -> Result<(), ErrorCode>
+    where
+        F: FnOnce(&'static mut [u8]) -> Result<(), (ErrorCode, &'static mut [u8])>,
+    {
+        self.offset.set(0);
+        self.len.set(0);
+        receive(buffer).map_err(|(error, buffer)| {
+            self.buffer.replace(buffer);
+            error
+        })
+    }
+
+    /// Delivers a complete sentence of `len` bytes to the client, or an
+    /// error if the sentence is corrupt.
+    fn deliver(&self, len: usize) {
+        self.reading.set(false);
+        self.assembler.sentence(len, |result| {
+            self.client.map(|client| client.callback(result));
+        });
+    }
+
+    fn fail(&self, error: ErrorCode) {
+        self.reading.set(false);
+        self.client.map(|client| client.callback(Err(error)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+
+    #[test]
+    fn checksum() {
+        assert!(checksum_valid(
+            b"$GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76"
+        ));
+        assert!(checksum_valid(b"$GPGLL,,,,,,V,N*64"));
+        assert!(!checksum_valid(b"$GPGLL,,,,,,V,N*65"));
+        assert!(!checksum_valid(b"$GPGLL,,,,,,V,N"));
+        assert!(!checksum_valid(b"$GPGLL,,,,,,V,N*6"));
+        assert!(!checksum_valid(b"$GPGLL,,,,,,V,N*zz"));
+    }
+
+    #[test]
+    fn assemble() {
+        let buffer = Box::leak(Box::new([0; NMEA_BUFFER_LEN]));
+        let assembler = SentenceAssembler::new(buffer, true);
+
+        // Bytes before the first `$` and idle bytes are skipped.
+        let stream = b"\xff,N*64\r\n$GPGLL,,,\xff\xff,,,V,N*64\r\n$GPGLL";
+        let (used, len) = assembler.push_slice(stream);
+        assert_eq!(used, stream.len() - 6);
+        assert_eq!(len, Some(18));
+        assembler.sentence(len.unwrap(), |sentence| {
+            assert_eq!(sentence, Ok("$GPGLL,,,,,,V,N*64"))
+        });
+
+        // The leftover partial sentence is dropped when the next one starts,
+        // and that one has a bad checksum.
+        let (_, len) = assembler.push_slice(b"$GPGLL,,,,,,V,N*65\n");
+        assembler.sentence(len.unwrap(), |sentence| {
+            assert_eq!(sentence, Err(ErrorCode::FAIL))
+        });
+    }
+}
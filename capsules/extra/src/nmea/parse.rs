@@ -0,0 +1,417 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Parsers for common NMEA 0183 sentence types.
+//!
+//! The parsers take a complete sentence, as delivered to an `NmeaClient`,
+//! and return its fields. Sentences from any talker (`GP`, `GN`, `GL`, ...)
+//! are accepted. Fields that the receiver left empty, for example before it
+//! has a fix, are returned as `None`.
+//!
+//! No floating point is used. Positions are in decimal degrees scaled by
+//! 10^7, so one unit is about 1 cm at the equator.
+//!
+//! ```rust,ignore
+//! fn callback(&self, sentence: Result<&str, ErrorCode>) {
+//!     if let Ok(Sentence::Gga(gga)) = sentence.and_then(parse::parse) {
+//!         // Use gga.latitude, gga.longitude, ...
+//!     }
+//! }
+//! ```
+
+use core::str::FromStr;
+use kernel::ErrorCode;
+
+/// Time of day in UTC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Time {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub milliseconds: u16,
+}
+
+/// Calendar date.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Date {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+/// Fix data from a GGA sentence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gga {
+    pub time: Option<Time>,
+    /// Latitude in degrees scaled by 10^7, positive to the north.
+    pub latitude: Option<i32>,
+    /// Longitude in degrees scaled by 10^7, positive to the east.
+    pub longitude: Option<i32>,
+    /// Fix quality. 0 means no fix, 1 a GNSS fix and 2 a differential fix.
+    pub fix_quality: u8,
+    /// Number of satellites used for the fix.
+    pub satellites: u8,
+    /// Altitude above mean sea level in millimetres.
+    pub altitude: Option<i32>,
+}
+
+/// Recommended minimum data from an RMC sentence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rmc {
+    pub time: Option<Time>,
+    /// Whether the receiver reports the data as valid.
+    pub valid: bool,
+    /// Latitude in degrees scaled by 10^7, positive to the north.
+    pub latitude: Option<i32>,
+    /// Longitude in degrees scaled by 10^7, positive to the east.
+    pub longitude: Option<i32>,
+    /// Speed over ground in thousandths of a knot.
+    pub speed: Option<u32>,
+    /// Course over ground in millidegrees from true north.
+    pub course: Option<u32>,
+    pub date: Option<Date>,
+}
+
+/// A parsed sentence.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sentence {
+    Gga(Gga),
+    Rmc(Rmc),
+}
+
+/// Parses `sentence` based on its type. Returns `NOSUPPORT` for sentence
+/// types that have no parser, and `INVAL` if the sentence is malformed.
+pub fn parse(sentence: &str) -> Result<Sentence, ErrorCode> {
+    match sentence_type(sentence)? {
+        "GGA" => parse_gga(sentence).map(Sentence::Gga),
+        "RMC" => parse_rmc(sentence).map(Sentence::Rmc),
+        _ => Err(ErrorCode::NOSUPPORT),
+    }
+}
+
+/// Parses a GGA sentence.
+pub fn parse_gga(sentence: &str) -> Result<Gga, ErrorCode> {
+    let mut fields = fields(sentence, "GGA")?;
+    let mut next = || fields.next().ok_or(ErrorCode::INVAL);
+
+    let time = parse_time(next()?)?;
+    let latitude = parse_coordinate(next()?, next()?, 2, b'N', b'S')?;
+    let longitude = parse_coordinate(next()?, next()?, 3, b'E', b'W')?;
+    let fix_quality = parse_optional(next()?)?.unwrap_or(0);
+    let satellites = parse_optional(next()?)?.unwrap_or(0);
+    let _hdop = next()?;
+    let altitude = parse_optional_fixed(next()?, 3)?
+        .map(|altitude| i32::try_from(altitude).map_err(|_| ErrorCode::INVAL))
+        .transpose()?;
+
+    Ok(Gga {
+        time,
+        latitude,
+        longitude,
+        fix_quality,
+        satellites,
+        altitude,
+    })
+}
+
+/// Parses an RMC sentence.
+pub fn parse_rmc(sentence: &str) -> Result<Rmc, ErrorCode> {
+    let mut fields = fields(sentence, "RMC")?;
+    let mut next = || fields.next().ok_or(ErrorCode::INVAL);
+
+    let time = parse_time(next()?)?;
+    let valid = next()? == "A";
+    let latitude = parse_coordinate(next()?, next()?, 2, b'N', b'S')?;
+    let longitude = parse_coordinate(next()?, next()?, 3, b'E', b'W')?;
+    let speed = parse_optional_fixed(next()?, 3)?
+        .map(|speed| u32::try_from(speed).map_err(|_| ErrorCode::INVAL))
+        .transpose()?;
+    let course = parse_optional_fixed(next()?, 3)?
+        .map(|course| u32::try_from(course).map_err(|_| ErrorCode::INVAL))
+        .transpose()?;
+    let date = parse_date(next()?)?;
+
+    Ok(Rmc {
+        time,
+        valid,
+        latitude,
+        longitude,
+        speed,
+        course,
+        date,
+    })
+}
+
+/// Returns the sentence type, such as `GGA`, without the talker ID.
+fn sentence_type(sentence: &str) -> Result<&str, ErrorCode> {
+    let address = sentence
+        .strip_prefix('$')
+        .and_then(|sentence| sentence.split(',').next())
+        .ok_or(ErrorCode::INVAL)?;
+    // Proprietary sentences start with `P` and have no talker ID.
+    match address.get(2..) {
+        Some(sentence_type) if !address.starts_with('P') => Ok(sentence_type),
+        _ => Err(ErrorCode::NOSUPPORT),
+    }
+}
+
+/// Returns an iterator over the data fields of `sentence`, after checking
+/// that it is of type `expected`. The checksum is not included.
+fn fields<'a>(
+    sentence: &'a str,
+    expected: &str,
+) -> Result<impl Iterator<Item = &'a str>, ErrorCode> {
+    if sentence_type(sentence)? != expected {
+        return Err(ErrorCode::INVAL);
+    }
+    let data = match sentence.rfind('*') {
+        Some(checksum) => &sentence[..checksum],
+        None => sentence,
+    };
+    Ok(data.split(',').skip(1))
+}
+
+/// Parses an integer field, which may be empty.
+fn parse_optional<T: FromStr>(field: &str) -> Result<Option<T>, ErrorCode> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    field.parse().map(Some).map_err(|_| ErrorCode::INVAL)
+}
+
+/// Parses a decimal number into an integer scaled by 10^`decimals`. Digits
+/// beyond that precision are dropped.
+fn parse_fixed(field: &str, decimals: u32) -> Result<i64, ErrorCode> {
+    let (negative, field) = match field.strip_prefix('-') {
+        Some(field) => (true, field),
+        None => (false, field),
+    };
+    let (integer, fraction) = field.split_once('.').unwrap_or((field, ""));
+    if integer.is_empty() && fraction.is_empty() {
+        return Err(ErrorCode::INVAL);
+    }
+
+    let mut value: i64 = 0;
+    for digit in integer.bytes() {
+        if !digit.is_ascii_digit() {
+            return Err(ErrorCode::INVAL);
+        }
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add((digit - b'0') as i64))
+            .ok_or(ErrorCode::INVAL)?;
+    }
+    for i in 0..decimals {
+        let digit = match fraction.as_bytes().get(i as usize) {
+            Some(digit) if digit.is_ascii_digit() => (digit - b'0') as i64,
+            Some(_) => return Err(ErrorCode::INVAL),
+            None => 0,
+        };
+        value = value
+            .checked_mul(10)
+            .and_then(|value| value.checked_add(digit))
+            .ok_or(ErrorCode::INVAL)?;
+    }
+    if !fraction.bytes().all(|digit| digit.is_ascii_digit()) {
+        return Err(ErrorCode::INVAL);
+    }
+
+    Ok(if negative { -value } else { value })
+}
+
+/// Parses a decimal number field, which may be empty.
+fn parse_optional_fixed(field: &str, decimals: u32) -> Result<Option<i64>, ErrorCode> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    parse_fixed(field, decimals).map(Some)
+}
+
+/// Parses a `hhmmss.sss` time field.
+fn parse_time(field: &str) -> Result<Option<Time>, ErrorCode> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let digits = |range: core::ops::Range<usize>| -> Result<u8, ErrorCode> {
+        field
+            .get(range)
+            .and_then(|digits| digits.parse().ok())
+            .ok_or(ErrorCode::INVAL)
+    };
+    let time = Time {
+        hours: digits(0..2)?,
+        minutes: digits(2..4)?,
+        seconds: digits(4..6)?,
+        milliseconds: match field.get(6..) {
+            Some("") => 0,
+            Some(fraction) if fraction.starts_with('.') => parse_fixed(&field[6..], 3)? as u16,
+            _ => return Err(ErrorCode::INVAL),
+        },
+    };
+    // Allow a leap second.
+    if time.hours > 23 || time.minutes > 59 || time.seconds > 60 {
+        return Err(ErrorCode::INVAL);
+    }
+    Ok(Some(time))
+}
+
+/// Parses a `ddmmyy` date field. Two digit years are taken to be in the
+/// 21st century.
+fn parse_date(field: &str) -> Result<Option<Date>, ErrorCode> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    if field.len() != 6 {
+        return Err(ErrorCode::INVAL);
+    }
+    let digits = |range: core::ops::Range<usize>| -> Result<u8, ErrorCode> {
+        field
+            .get(range)
+            .and_then(|digits| digits.parse().ok())
+            .ok_or(ErrorCode::INVAL)
+    };
+    let date = Date {
+        day: digits(0..2)?,
+        month: digits(2..4)?,
+        year: 2000 + digits(4..6)? as u16,
+    };
+    if !(1..=31).contains(&date.day) || !(1..=12).contains(&date.month) {
+        return Err(ErrorCode::INVAL);
+    }
+    Ok(Some(date))
+}
+
+/// Parses a `(d)ddmm.mmmm` coordinate with `degree_digits` digits of
+/// degrees and its hemisphere field. Returns degrees scaled by 10^7,
+/// negative in the `negative` hemisphere.
+fn parse_coordinate(
+    field: &str,
+    hemisphere: &str,
+    degree_digits: usize,
+    positive: u8,
+    negative: u8,
+) -> Result<Option<i32>, ErrorCode> {
+    if field.is_empty() {
+        return Ok(None);
+    }
+    let degrees = field
+        .get(..degree_digits)
+        .filter(|degrees| degrees.bytes().all(|digit| digit.is_ascii_digit()))
+        .and_then(|degrees| degrees.parse::<i64>().ok())
+        .ok_or(ErrorCode::INVAL)?;
+    let minutes = parse_fixed(&field[degree_digits..], 7)?;
+    if minutes < 0 || minutes >= 60 * 10_000_000 {
+        return Err(ErrorCode::INVAL);
+    }
+
+    let value = degrees * 10_000_000 + minutes / 60;
+    let value = match hemisphere.as_bytes() {
+        [h] if *h == positive => value,
+        [h] if *h == negative => -value,
+        _ => return Err(ErrorCode::INVAL),
+    };
+    i32::try_from(value).map(Some).map_err(|_| ErrorCode::INVAL)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gga() {
+        let gga =
+            parse_gga("$GPGGA,092750.000,5321.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76")
+                .unwrap();
+        assert_eq!(
+            gga,
+            Gga {
+                time: Some(Time {
+                    hours: 9,
+                    minutes: 27,
+                    seconds: 50,
+                    milliseconds: 0,
+                }),
+                latitude: Some(533613366),
+                longitude: Some(-65056200),
+                fix_quality: 1,
+                satellites: 8,
+                altitude: Some(61700),
+            }
+        );
+    }
+
+    #[test]
+    fn gga_no_fix() {
+        let gga = parse_gga("$GNGGA,,,,,,0,00,99.99,,,,,,*56").unwrap();
+        assert_eq!(
+            gga,
+            Gga {
+                time: None,
+                latitude: None,
+                longitude: None,
+                fix_quality: 0,
+                satellites: 0,
+                altitude: None,
+            }
+        );
+    }
+
+    #[test]
+    fn rmc() {
+        let rmc = parse("$GNRMC,225446.33,A,4916.45,N,12311.12,W,000.5,054.7,191194,020.3,E,A*68")
+            .unwrap();
+        assert_eq!(
+            rmc,
+            Sentence::Rmc(Rmc {
+                time: Some(Time {
+                    hours: 22,
+                    minutes: 54,
+                    seconds: 46,
+                    milliseconds: 330,
+                }),
+                valid: true,
+                latitude: Some(492741666),
+                longitude: Some(-1231853333),
+                speed: Some(500),
+                course: Some(54700),
+                date: Some(Date {
+                    year: 2094,
+                    month: 11,
+                    day: 19,
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn rmc_void() {
+        let rmc = parse_rmc("$GPRMC,,V,,,,,,,,,,N*53").unwrap();
+        assert!(!rmc.valid);
+        assert_eq!(rmc.latitude, None);
+        assert_eq!(rmc.date, None);
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(
+            parse("$GPGSV,3,1,11,03,03,111,00*74"),
+            Err(ErrorCode::NOSUPPORT)
+        );
+        assert_eq!(parse("$PUBX,00*33"), Err(ErrorCode::NOSUPPORT));
+        assert_eq!(parse("GPGGA,"), Err(ErrorCode::INVAL));
+        assert_eq!(
+            parse_rmc("$GPGGA,,,,,,0,00,,,,,,,*66"),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(parse_gga("$GPGGA,,,,,,0"), Err(ErrorCode::INVAL));
+        assert_eq!(
+            parse_gga("$GPGGA,092750.000,5361.6802,N,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76"),
+            Err(ErrorCode::INVAL)
+        );
+        assert_eq!(
+            parse_gga("$GPGGA,092750.000,5321.6802,X,00630.3372,W,1,8,1.03,61.7,M,55.2,M,,*76"),
+            Err(ErrorCode::INVAL)
+        );
+    }
+}
@@ -0,0 +1,107 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! NMEA 0183 sentence reader for GNSS receivers with an I2C interface.
+//!
+//! Receivers such as the u-blox modules expose their NMEA output stream over
+//! I2C (DDC). The stream is read in fixed size chunks and assembled into
+//! complete `$...\r\n` sentences, which are handed to the client one at a
+//! time. When the receiver has no data available it returns `0xFF` idle
+//! bytes, which are skipped.
+//!
+//! Sentences are framed and their checksums verified by the shared
+//! [`SentenceAssembler`](crate::nmea::SentenceAssembler).
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let nmea_i2c = static_init!(I2CDevice, I2CDevice::new(mux_i2c, 0x42));
+//! let i2c_buffer = static_init!(
+//!     [u8; capsules_extra::nmea_i2c::I2C_BUFFER_LEN],
+//!     [0; capsules_extra::nmea_i2c::I2C_BUFFER_LEN]
+//! );
+//! let sentence_buffer = static_init!(
+//!     [u8; capsules_extra::nmea::NMEA_BUFFER_LEN],
+//!     [0; capsules_extra::nmea::NMEA_BUFFER_LEN]
+//! );
+//! let nmea = static_init!(
+//!     capsules_extra::nmea_i2c::I2cNmea<'static, I2CDevice>,
+//!     capsules_extra::nmea_i2c::I2cNmea::new(nmea_i2c, i2c_buffer, sentence_buffer, true)
+//! );
+//! nmea_i2c.set_client(nmea);
+//! kernel::deferred_call::DeferredCallClient::register(nmea);
+//! ```
+
+use crate::nmea::{NmeaReader, NMEA_BUFFER_LEN};
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::i2c::{self, I2CClient, I2CDevice};
+use kernel::hil::sensors::{NmeaClient, NmeaDriver};
+use kernel::ErrorCode;
+
+/// Number of bytes read from the receiver in a single I2C transaction.
+pub const I2C_BUFFER_LEN: usize = 24;
+
+pub struct I2cNmea<'a, I: I2CDevice> {
+    i2c: &'a I,
+    reader: NmeaReader<'a>,
+}
+
+impl<'a, I: I2CDevice> I2cNmea<'a, I> {
+    pub fn new(
+        i2c: &'a I,
+        i2c_buffer: &'static mut [u8; I2C_BUFFER_LEN],
+        sentence_buffer: &'static mut [u8; NMEA_BUFFER_LEN],
+        verify_checksum: bool,
+    ) -> I2cNmea<'a, I> {
+        I2cNmea {
+            i2c,
+            reader: NmeaReader::new(i2c_buffer, sentence_buffer, verify_checksum),
+        }
+    }
+
+    /// Reads the next chunk of the NMEA stream from the receiver.
+    fn read_chunk(&self, buffer: &'static mut [u8]) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.i2c.enable();
+        self.i2c
+            .read(buffer, I2C_BUFFER_LEN)
+            .map_err(|(error, buffer)| {
+                self.i2c.disable();
+                (error.into(), buffer)
+            })
+    }
+}
+
+impl<'a, I: I2CDevice> I2CClient for I2cNmea<'a, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        self.i2c.disable();
+        let result = status
+            .map(|()| I2C_BUFFER_LEN)
+            .map_err(|error| error.into());
+        self.reader
+            .received(buffer, result, |buffer| self.read_chunk(buffer));
+    }
+}
+
+impl<'a, I: I2CDevice> NmeaDriver<'a> for I2cNmea<'a, I> {
+    fn set_client(&self, client: &'a dyn NmeaClient) {
+        self.reader.set_client(client);
+    }
+
+    fn read_sentence(&self) -> Result<(), ErrorCode> {
+        self.reader.read_sentence(|buffer| self.read_chunk(buffer))
+    }
+}
+
+impl<'a, I: I2CDevice> DeferredCallClient for I2cNmea<'a, I> {
+    fn handle_deferred_call(&self) {
+        self.reader.handle_deferred_call();
+    }
+
+    fn register(&'static self) {
+        self.reader.register_deferred_call(self);
+    }
+}
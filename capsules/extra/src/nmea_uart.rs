@@ -0,0 +1,311 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! NMEA 0183 sentence reader for GNSS receivers with a UART interface.
+//!
+//! Most GNSS modules continuously output NMEA sentences over a UART, usually
+//! at 9600 baud. While a sentence is requested the stream is received in
+//! fixed size chunks and assembled into complete `$...\r\n` sentences, which
+//! are handed to the client one at a time. Bytes that arrive while no read is
+//! in progress are lost, so the first sentence after a read starts is
+//! normally the next complete one in the stream.
+//!
+//! A UART receive only completes once its buffer is full. Receivers send
+//! their sentences in bursts, so the end of a burst rarely fills the buffer
+//! exactly. To not hold the last sentence of a burst until the next one, a
+//! receive that has not completed within `RX_TIMEOUT_MS` is aborted and the
+//! bytes received so far are assembled.
+//!
+//! This provides the same `NmeaDriver` interface as the
+//! [I2C reader](crate::nmea_i2c), so the transport can be swapped without
+//! changing the client. Sentences are framed and their checksums verified by
+//! the shared [`SentenceAssembler`](crate::nmea::SentenceAssembler).
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let nmea_uart = static_init!(UartDevice, UartDevice::new(uart_mux, true));
+//! nmea_uart.setup();
+//! let nmea_alarm = static_init!(VirtualMuxAlarm<'static, Rtc>, VirtualMuxAlarm::new(mux_alarm));
+//! nmea_alarm.setup();
+//! let rx_buffer = static_init!(
+//!     [u8; capsules_extra::nmea_uart::RX_BUFFER_LEN],
+//!     [0; capsules_extra::nmea_uart::RX_BUFFER_LEN]
+//! );
+//! let sentence_buffer = static_init!(
+//!     [u8; capsules_extra::nmea::NMEA_BUFFER_LEN],
+//!     [0; capsules_extra::nmea::NMEA_BUFFER_LEN]
+//! );
+//! let nmea = static_init!(
+//!     capsules_extra::nmea_uart::UartNmea<'static, UartDevice, VirtualMuxAlarm<'static, Rtc>>,
+//!     capsules_extra::nmea_uart::UartNmea::new(
+//!         nmea_uart,
+//!         nmea_alarm,
+//!         rx_buffer,
+//!         sentence_buffer,
+//!         true,
+//!     )
+//! );
+//! nmea_uart.set_receive_client(nmea);
+//! nmea_alarm.set_alarm_client(nmea);
+//! kernel::deferred_call::DeferredCallClient::register(nmea);
+//! ```
+
+use crate::nmea::{NmeaReader, NMEA_BUFFER_LEN};
+use kernel::deferred_call::DeferredCallClient;
+use kernel::hil::sensors::{NmeaClient, NmeaDriver};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::hil::uart;
+use kernel::ErrorCode;
+
+/// Number of bytes received from the UART in a single receive operation.
+/// This matches the chunk size of the I2C reader.
+pub const RX_BUFFER_LEN: usize = crate::nmea_i2c::I2C_BUFFER_LEN;
+
+/// Time after which a receive that has not filled the buffer is aborted.
+/// At 9600 baud a full buffer takes 25 ms.
+pub const RX_TIMEOUT_MS: u32 = 50;
+
+pub struct UartNmea<'a, U: uart::Receive<'a>, A: Alarm<'a>> {
+    uart: &'a U,
+    alarm: &'a A,
+    reader: NmeaReader<'a>,
+}
+
+impl<'a, U: uart::Receive<'a>, A: Alarm<'a>> UartNmea<'a, U, A> {
+    pub fn new(
+        uart: &'a U,
+        alarm: &'a A,
+        rx_buffer: &'static mut [u8; RX_BUFFER_LEN],
+        sentence_buffer: &'static mut [u8; NMEA_BUFFER_LEN],
+        verify_checksum: bool,
+    ) -> UartNmea<'a, U, A> {
+        UartNmea {
+            uart,
+            alarm,
+            reader: NmeaReader::new(rx_buffer, sentence_buffer, verify_checksum),
+        }
+    }
+
+    /// Receives the next chunk of the NMEA stream from the receiver.
+    fn receive_chunk(
+        &self,
+        buffer: &'static mut [u8],
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        self.uart.receive_buffer(buffer, RX_BUFFER_LEN)?;
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(RX_TIMEOUT_MS));
+        Ok(())
+    }
+}
+
+impl<'a, U: uart::Receive<'a>, A: Alarm<'a>> uart::ReceiveClient for UartNmea<'a, U, A> {
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        let _ = self.alarm.disarm();
+        let result = match rval {
+            // Aborted by the timeout, use what was received.
+            Ok(()) | Err(ErrorCode::CANCEL) => Ok(rx_len),
+            Err(error) => Err(error),
+        };
+        self.reader
+            .received(buffer, result, |buffer| self.receive_chunk(buffer));
+    }
+}
+
+impl<'a, U: uart::Receive<'a>, A: Alarm<'a>> AlarmClient for UartNmea<'a, U, A> {
+    fn alarm(&self) {
+        // The receive completes with the bytes received so far.
+        let _ = self.uart.receive_abort();
+    }
+}
+
+impl<'a, U: uart::Receive<'a>, A: Alarm<'a>> NmeaDriver<'a> for UartNmea<'a, U, A> {
+    fn set_client(&self, client: &'a dyn NmeaClient) {
+        self.reader.set_client(client);
+    }
+
+    fn read_sentence(&self) -> Result<(), ErrorCode> {
+        self.reader
+            .read_sentence(|buffer| self.receive_chunk(buffer))
+    }
+}
+
+impl<'a, U: uart::Receive<'a>, A: Alarm<'a>> DeferredCallClient for UartNmea<'a, U, A> {
+    fn handle_deferred_call(&self) {
+        self.reader.handle_deferred_call();
+    }
+
+    fn register(&'static self) {
+        self.reader.register_deferred_call(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::{Cell, RefCell};
+    use kernel::hil::time::{Freq1KHz, Ticks32, Time};
+    use kernel::utilities::cells::{OptionalCell, TakeCell};
+    use std::boxed::Box;
+    use std::string::{String, ToString};
+    use std::vec::Vec;
+
+    /// UART that receives bytes into the outstanding buffer without
+    /// completing the receive until it is full or aborted.
+    struct MockUart<'a> {
+        client: OptionalCell<&'a dyn uart::ReceiveClient>,
+        buffer: TakeCell<'static, [u8]>,
+        len: Cell<usize>,
+    }
+
+    impl<'a> MockUart<'a> {
+        fn new() -> Self {
+            MockUart {
+                client: OptionalCell::empty(),
+                buffer: TakeCell::empty(),
+                len: Cell::new(0),
+            }
+        }
+
+        fn feed(&self, data: &[u8]) {
+            self.buffer.map(|buffer| {
+                let len = self.len.get();
+                buffer[len..len + data.len()].copy_from_slice(data);
+                self.len.set(len + data.len());
+            });
+        }
+    }
+
+    impl<'a> uart::Receive<'a> for MockUart<'a> {
+        fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+            self.client.set(client);
+        }
+
+        fn receive_buffer(
+            &self,
+            rx_buffer: &'static mut [u8],
+            _rx_len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            if self.buffer.is_some() {
+                return Err((ErrorCode::BUSY, rx_buffer));
+            }
+            self.len.set(0);
+            self.buffer.replace(rx_buffer);
+            Ok(())
+        }
+
+        fn receive_word(&self) -> Result<(), ErrorCode> {
+            Err(ErrorCode::FAIL)
+        }
+
+        fn receive_abort(&self) -> Result<(), ErrorCode> {
+            self.buffer.take().map_or(Ok(()), |buffer| {
+                let len = self.len.get();
+                self.client.map(|client| {
+                    client.received_buffer(
+                        buffer,
+                        len,
+                        Err(ErrorCode::CANCEL),
+                        uart::Error::Aborted,
+                    )
+                });
+                Err(ErrorCode::BUSY)
+            })
+        }
+    }
+
+    #[derive(Default)]
+    struct MockAlarm {
+        armed: Cell<bool>,
+    }
+
+    impl Time for MockAlarm {
+        type Ticks = Ticks32;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> Ticks32 {
+            0.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Self::Ticks, _dt: Self::Ticks) {
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            0.into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            1.into()
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        sentences: RefCell<Vec<Result<String, ErrorCode>>>,
+    }
+
+    impl NmeaClient for MockClient {
+        fn callback(&self, sentence: Result<&str, ErrorCode>) {
+            self.sentences
+                .borrow_mut()
+                .push(sentence.map(|s| s.to_string()));
+        }
+    }
+
+    #[test]
+    fn timeout_flushes_last_sentence() {
+        let uart = Box::leak(Box::new(MockUart::new()));
+        let alarm: &'static MockAlarm = Box::leak(Box::default());
+        let client: &'static MockClient = Box::leak(Box::default());
+        let nmea = Box::leak(Box::new(UartNmea::new(
+            uart,
+            alarm,
+            Box::leak(Box::new([0; RX_BUFFER_LEN])),
+            Box::leak(Box::new([0; NMEA_BUFFER_LEN])),
+            true,
+        )));
+        uart::Receive::set_receive_client(uart, nmea);
+        NmeaDriver::set_client(nmea, client);
+
+        assert_eq!(nmea.read_sentence(), Ok(()));
+        assert!(alarm.is_armed());
+
+        // The burst ends before the receive buffer is full.
+        uart.feed(b"$GPGLL,,,,,,V,N*64\r\n");
+        assert!(client.sentences.borrow().is_empty());
+
+        nmea.alarm();
+        assert_eq!(
+            *client.sentences.borrow(),
+            [Ok("$GPGLL,,,,,,V,N*64".to_string())]
+        );
+        assert!(!alarm.is_armed());
+        assert!(uart.buffer.is_none());
+    }
+}
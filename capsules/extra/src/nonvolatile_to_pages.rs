@@ -11,6 +11,12 @@
 //! This module is designed to be used on top of any flash storage and below any
 //! user of `NonvolatileStorage`. This module handles different sized pages.
 //!
+//! When created with [`NonvolatileToPages::new_verified`], every page write is
+//! followed by a read of the same page, which is compared with what was
+//! written. If they differ the write stops, and `write_done()` reports only
+//! the bytes before the page that failed to verify. This costs a page read
+//! per page written and a second page buffer.
+//!
 //! ```plain
 //! hil::nonvolatile_storage::NonvolatileStorage
 //!                ┌─────────────┐
@@ -53,6 +59,8 @@ enum State {
     Idle,
     Read,
     Write,
+    /// Reading back a page that was just written.
+    Verify,
 }
 
 pub struct NonvolatileToPages<'a, F: hil::flash::Flash + 'static> {
@@ -76,6 +84,12 @@ pub struct NonvolatileToPages<'a, F: hil::flash::Flash + 'static> {
     remaining_length: Cell<usize>,
     /// Where we are in the user buffer.
     buffer_index: Cell<usize>,
+    /// Buffer to read written pages back into, if writes are verified.
+    verify_buffer: TakeCell<'static, F::Page>,
+    /// Page number of the page write in progress, and how many bytes of the
+    /// user buffer were written before it.
+    write_page_number: Cell<usize>,
+    write_start_index: Cell<usize>,
 }
 
 impl<'a, F: hil::flash::Flash> NonvolatileToPages<'a, F> {
@@ -90,8 +104,93 @@ impl<'a, F: hil::flash::Flash> NonvolatileToPages<'a, F> {
             length: Cell::new(0),
             remaining_length: Cell::new(0),
             buffer_index: Cell::new(0),
+            verify_buffer: TakeCell::empty(),
+            write_page_number: Cell::new(0),
+            write_start_index: Cell::new(0),
         }
     }
+
+    /// Creates a `NonvolatileToPages` that reads back and checks every page it
+    /// writes, using `verify_buffer` for the read.
+    pub fn new_verified(
+        driver: &'a F,
+        buffer: &'static mut F::Page,
+        verify_buffer: &'static mut F::Page,
+    ) -> NonvolatileToPages<'a, F> {
+        let nv_to_pages = Self::new(driver, buffer);
+        nv_to_pages.verify_buffer.replace(verify_buffer);
+        nv_to_pages
+    }
+
+    /// Writes `pagebuffer` to `page_number`, which holds the user buffer from
+    /// `buffer_index` on.
+    fn write_page(
+        &self,
+        page_number: usize,
+        buffer_index: usize,
+        pagebuffer: &'static mut F::Page,
+    ) -> Result<(), ErrorCode> {
+        self.write_page_number.set(page_number);
+        self.write_start_index.set(buffer_index);
+        self.driver
+            .write_page(page_number, pagebuffer)
+            .map_err(|(error_code, pagebuffer)| {
+                self.pagebuffer.replace(pagebuffer);
+                error_code
+            })
+    }
+
+    /// Ends a write whose last page did not verify, reporting only the bytes
+    /// before that page as written.
+    fn verify_failed(&self, pagebuffer: &'static mut F::Page) {
+        self.pagebuffer.replace(pagebuffer);
+        self.state.set(State::Idle);
+        self.buffer.take().map(|buffer| {
+            let written_length = self.write_start_index.get();
+            self.client
+                .map(move |client| client.write_done(buffer, written_length));
+        });
+    }
+
+    /// Continues a write after a page has been written.
+    fn write_next(&self, pagebuffer: &'static mut F::Page) {
+        // After a write we could be done, need to do another write, or need to
+        // do a read.
+        self.buffer.take().map(move |buffer| {
+            let page_size = pagebuffer.as_mut().len();
+
+            if self.remaining_length.get() == 0 {
+                // Done!
+                self.pagebuffer.replace(pagebuffer);
+                self.state.set(State::Idle);
+                self.client
+                    .map(move |client| client.write_done(buffer, self.length.get()));
+            } else if self.remaining_length.get() >= page_size {
+                // Write an entire page!
+                let buffer_index = self.buffer_index.get();
+                let page_number = self.address.get() / page_size;
+
+                // Copy data into page buffer.
+                pagebuffer.as_mut()[..page_size]
+                    .copy_from_slice(&buffer[buffer_index..(page_size + buffer_index)]);
+
+                self.buffer.replace(buffer);
+                self.remaining_length.subtract(page_size);
+                self.address.add(page_size);
+                self.buffer_index.set(buffer_index + page_size);
+                let _ = self.write_page(page_number, buffer_index, pagebuffer);
+            } else {
+                // Write a partial page!
+                self.buffer.replace(buffer);
+                if let Err((_, pagebuffer)) = self
+                    .driver
+                    .read_page(self.address.get() / page_size, pagebuffer)
+                {
+                    self.pagebuffer.replace(pagebuffer);
+                }
+            }
+        });
+    }
 }
 
 impl<'a, F: hil::flash::Flash> hil::nonvolatile_storage::NonvolatileStorage<'a>
@@ -165,13 +264,7 @@ impl<'a, F: hil::flash::Flash> hil::nonvolatile_storage::NonvolatileStorage<'a>
                     self.remaining_length.set(length - page_size);
                     self.buffer_index.set(page_size);
 
-                    match self.driver.write_page(address / page_size, pagebuffer) {
-                        Ok(()) => Ok(()),
-                        Err((error_code, pagebuffer)) => {
-                            self.pagebuffer.replace(pagebuffer);
-                            Err(error_code)
-                        }
-                    }
+                    self.write_page(address / page_size, 0, pagebuffer)
                 } else {
                     // Need to do a read first.
                     self.buffer.replace(buffer);
@@ -262,8 +355,21 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
                     self.remaining_length.subtract(len);
                     self.address.add(len);
                     self.buffer_index.set(buffer_index + len);
-                    if let Err((_, pagebuffer)) = self.driver.write_page(page_number, pagebuffer) {
-                        self.pagebuffer.replace(pagebuffer);
+                    let _ = self.write_page(page_number, buffer_index, pagebuffer);
+                });
+            }
+            State::Verify => {
+                // Compare the page we read back with what we wrote, which is
+                // still in the page buffer.
+                self.pagebuffer.take().map(|written| {
+                    let matches = written.as_mut() == pagebuffer.as_mut();
+                    self.verify_buffer.replace(pagebuffer);
+                    self.state.set(State::Write);
+
+                    if matches {
+                        self.write_next(written);
+                    } else {
+                        self.verify_failed(written);
                     }
                 });
             }
@@ -276,45 +382,184 @@ impl<F: hil::flash::Flash> hil::flash::Client<F> for NonvolatileToPages<'_, F> {
         pagebuffer: &'static mut F::Page,
         _result: Result<(), hil::flash::Error>,
     ) {
-        // After a write we could be done, need to do another write, or need to
-        // do a read.
-        self.buffer.take().map(move |buffer| {
-            let page_size = pagebuffer.as_mut().len();
-
-            if self.remaining_length.get() == 0 {
-                // Done!
+        match self.verify_buffer.take() {
+            Some(verify_buffer) => {
+                // Read the page back before moving on. The page buffer keeps
+                // what we wrote to compare against.
                 self.pagebuffer.replace(pagebuffer);
-                self.state.set(State::Idle);
-                self.client
-                    .map(move |client| client.write_done(buffer, self.length.get()));
-            } else if self.remaining_length.get() >= page_size {
-                // Write an entire page!
-                let buffer_index = self.buffer_index.get();
-                let page_number = self.address.get() / page_size;
-
-                // Copy data into page buffer.
-                pagebuffer.as_mut()[..page_size]
-                    .copy_from_slice(&buffer[buffer_index..(page_size + buffer_index)]);
-
-                self.buffer.replace(buffer);
-                self.remaining_length.subtract(page_size);
-                self.address.add(page_size);
-                self.buffer_index.set(buffer_index + page_size);
-                if let Err((_, pagebuffer)) = self.driver.write_page(page_number, pagebuffer) {
-                    self.pagebuffer.replace(pagebuffer);
-                }
-            } else {
-                // Write a partial page!
-                self.buffer.replace(buffer);
-                if let Err((_, pagebuffer)) = self
+                self.state.set(State::Verify);
+                if let Err((_, verify_buffer)) = self
                     .driver
-                    .read_page(self.address.get() / page_size, pagebuffer)
+                    .read_page(self.write_page_number.get(), verify_buffer)
                 {
-                    self.pagebuffer.replace(pagebuffer);
+                    // The page cannot be checked, so count it as bad.
+                    self.verify_buffer.replace(verify_buffer);
+                    self.pagebuffer
+                        .take()
+                        .map(|pagebuffer| self.verify_failed(pagebuffer));
                 }
             }
-        });
+            None => self.write_next(pagebuffer),
+        }
     }
 
     fn erase_complete(&self, _result: Result<(), hil::flash::Error>) {}
 }
+
+impl<F: hil::flash::Flash + hil::flash::PowerControl> hil::flash::PowerControl
+    for NonvolatileToPages<'_, F>
+{
+    fn power_down(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.driver.power_down()
+    }
+
+    fn wake(&self) -> Result<(), ErrorCode> {
+        self.driver.wake()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use hil::flash::Client as _;
+    use hil::nonvolatile_storage::NonvolatileStorage;
+    use std::boxed::Box;
+
+    const PAGE_SIZE: usize = 8;
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Op {
+        Read(usize),
+        Write(usize),
+    }
+
+    /// Flash with four pages whose operations complete when the test calls
+    /// `run()`. Writes to `bad_page` drop the low bit of every byte.
+    struct MockFlash {
+        pages: RefCell<[[u8; PAGE_SIZE]; 4]>,
+        pending: Cell<Option<Op>>,
+        buffer: TakeCell<'static, [u8; PAGE_SIZE]>,
+        bad_page: Cell<Option<usize>>,
+    }
+
+    impl hil::flash::Flash for MockFlash {
+        type Page = [u8; PAGE_SIZE];
+
+        fn read_page(
+            &self,
+            page_number: usize,
+            buf: &'static mut Self::Page,
+        ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+            self.buffer.replace(buf);
+            self.pending.set(Some(Op::Read(page_number)));
+            Ok(())
+        }
+
+        fn write_page(
+            &self,
+            page_number: usize,
+            buf: &'static mut Self::Page,
+        ) -> Result<(), (ErrorCode, &'static mut Self::Page)> {
+            self.buffer.replace(buf);
+            self.pending.set(Some(Op::Write(page_number)));
+            Ok(())
+        }
+
+        fn erase_page(&self, _page_number: usize) -> Result<(), ErrorCode> {
+            Err(ErrorCode::NOSUPPORT)
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        written: Cell<Option<usize>>,
+    }
+
+    impl hil::nonvolatile_storage::NonvolatileStorageClient for MockClient {
+        fn read_done(&self, _buffer: &'static mut [u8], _length: usize) {}
+        fn write_done(&self, _buffer: &'static mut [u8], length: usize) {
+            self.written.set(Some(length));
+        }
+    }
+
+    fn setup(
+        bad_page: Option<usize>,
+    ) -> (
+        &'static MockFlash,
+        &'static NonvolatileToPages<'static, MockFlash>,
+        &'static MockClient,
+    ) {
+        let flash = Box::leak(Box::new(MockFlash {
+            pages: RefCell::new([[0; PAGE_SIZE]; 4]),
+            pending: Cell::new(None),
+            buffer: TakeCell::empty(),
+            bad_page: Cell::new(bad_page),
+        }));
+        let nv_to_pages = Box::leak(Box::new(NonvolatileToPages::new_verified(
+            flash,
+            Box::leak(Box::new([0; PAGE_SIZE])),
+            Box::leak(Box::new([0; PAGE_SIZE])),
+        )));
+        let client = Box::leak(Box::new(MockClient::default()));
+        nv_to_pages.set_client(client);
+        (flash, nv_to_pages, client)
+    }
+
+    fn run(flash: &MockFlash, nv_to_pages: &NonvolatileToPages<'static, MockFlash>) {
+        while let Some(op) = flash.pending.take() {
+            let buf = flash.buffer.take().unwrap();
+            match op {
+                Op::Read(page) => {
+                    *buf = flash.pages.borrow()[page];
+                    nv_to_pages.read_complete(buf, Ok(()));
+                }
+                Op::Write(page) => {
+                    let mut data = *buf;
+                    if flash.bad_page.get() == Some(page) {
+                        data.iter_mut().for_each(|byte| *byte &= !1);
+                    }
+                    flash.pages.borrow_mut()[page] = data;
+                    nv_to_pages.write_complete(buf, Ok(()));
+                }
+            }
+        }
+    }
+
+    fn write(flash: &MockFlash, nv_to_pages: &NonvolatileToPages<'static, MockFlash>) {
+        let data = Box::leak(Box::new([0x55; 20]));
+        assert_eq!(nv_to_pages.write(data, 4, 20), Ok(()));
+        run(flash, nv_to_pages);
+    }
+
+    #[test]
+    fn verified_write() {
+        let (flash, nv_to_pages, client) = setup(None);
+        write(flash, nv_to_pages);
+        assert_eq!(client.written.get(), Some(20));
+        assert_eq!(
+            flash.pages.borrow()[0],
+            [0, 0, 0, 0, 0x55, 0x55, 0x55, 0x55]
+        );
+        assert_eq!(flash.pages.borrow()[3], [0; PAGE_SIZE]);
+    }
+
+    #[test]
+    fn verify_failure_stops_write() {
+        let (flash, nv_to_pages, client) = setup(Some(2));
+        write(flash, nv_to_pages);
+        // Pages 0 and 1 hold the first 12 bytes, page 2 did not verify.
+        assert_eq!(client.written.get(), Some(12));
+        assert_eq!(flash.pages.borrow()[3], [0; PAGE_SIZE]);
+
+        // The next operation can go ahead.
+        flash.bad_page.set(None);
+        write(flash, nv_to_pages);
+        assert_eq!(client.written.get(), Some(20));
+    }
+}
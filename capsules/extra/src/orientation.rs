@@ -0,0 +1,505 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Provides userspace with fused orientation (roll, pitch and yaw).
+//!
+//! The capsule samples an accelerometer, a gyroscope and optionally a
+//! magnetometer at a fixed rate and combines them with a complementary
+//! filter. The gyroscope is integrated to track fast changes, and the
+//! result is slowly pulled towards the angles given by the gravity vector
+//! (roll and pitch) and the tilt compensated magnetic heading (yaw). Without
+//! a magnetometer yaw is only integrated from the gyroscope and will drift.
+//!
+//! All arithmetic is done in integers. Angles are in millidegrees. The
+//! gyroscope must report millidegrees per second, as the drivers in this
+//! crate do. The accelerometer and magnetometer readings may use any unit,
+//! only their direction is used.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let orientation_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! orientation_alarm.setup();
+//!
+//! let orientation = static_init!(
+//!     capsules_extra::orientation::Orientation<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     >,
+//!     capsules_extra::orientation::Orientation::new(
+//!         lsm6dsox,
+//!         Some(lsm303agr),
+//!         orientation_alarm,
+//!         20,
+//!         board_kernel.create_grant(
+//!             capsules_extra::orientation::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! orientation_alarm.set_alarm_client(orientation);
+//! hil::sensors::NineDof::set_client(lsm6dsox, orientation);
+//! hil::sensors::NineDof::set_client(lsm303agr, orientation);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called with the roll, pitch and yaw in millidegrees, as `i32`,
+//!   after every filter update while the app is listening.
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Start listening for orientation updates.
+//! - `2`: Stop listening for orientation updates.
+//! - `3`: Set the filter gain to `data1`, in thousandths. This is the weight
+//!   given to the integrated gyroscope at every update, so higher values
+//!   trust the gyroscope more and correct drift more slowly.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::sensors::{NineDof, NineDofClient};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::Orientation as usize;
+
+/// Default weight of the integrated gyroscope, in thousandths.
+pub const DEFAULT_GAIN: u32 = 980;
+
+/// Integer square root.
+fn isqrt(n: u64) -> u64 {
+    if n < 2 {
+        return n;
+    }
+    // Newton's method, starting from a value that is at least the root.
+    let mut x = 1 << ((64 - n.leading_zeros()).div_ceil(2));
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Returns the angle of the vector (`x`, `y`) in millidegrees, in the range
+/// -180000 to 180000. Accurate to about 0.3 degrees.
+fn atan2(y: i64, x: i64) -> i32 {
+    if x == 0 && y == 0 {
+        return 0;
+    }
+
+    // atan(z) ~= 45z - z(|z| - 1)(14.02 + 3.79|z|) degrees for |z| <= 1,
+    // with z scaled by 1000.
+    let atan = |num: i64, den: i64| -> i64 {
+        let z = num * 1000 / den;
+        45 * z - (z * (z.abs() - 1000) / 1000) * (14020 + 3790 * z.abs() / 1000) / 1000
+    };
+
+    let angle = if x.abs() >= y.abs() {
+        let angle = atan(y, x);
+        if x > 0 {
+            angle
+        } else if y >= 0 {
+            angle + 180000
+        } else {
+            angle - 180000
+        }
+    } else if y > 0 {
+        90000 - atan(x, y)
+    } else {
+        -90000 - atan(x, y)
+    };
+    angle as i32
+}
+
+/// Wraps an angle in millidegrees into the range -180000 to 180000.
+fn wrap(angle: i32) -> i32 {
+    let angle = angle % 360000;
+    if angle > 180000 {
+        angle - 360000
+    } else if angle < -180000 {
+        angle + 360000
+    } else {
+        angle
+    }
+}
+
+/// Returns the sine of `angle`, in millidegrees, scaled by 1000. Uses
+/// Bhaskara's approximation, accurate to about 0.002.
+fn sin(angle: i32) -> i64 {
+    let angle = wrap(angle) as i64;
+    let t = angle.abs();
+    let p = t * (180000 - t);
+    let s = 4000 * p / (40_500_000_000 - p);
+    if angle < 0 {
+        -s
+    } else {
+        s
+    }
+}
+
+/// Returns the cosine of `angle`, in millidegrees, scaled by 1000.
+fn cos(angle: i32) -> i64 {
+    sin(angle + 90000)
+}
+
+/// Moves `from` towards `to` by `(1000 - gain) / 1000` of the shortest
+/// distance between the two angles.
+fn blend(from: i32, to: i32, gain: u32) -> i32 {
+    let diff = wrap(to - from) as i64;
+    wrap(from + (diff * (1000 - gain as i64) / 1000) as i32)
+}
+
+/// A complementary filter fusing motion sensor readings into roll, pitch
+/// and yaw, all in millidegrees.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ComplementaryFilter {
+    pub roll: i32,
+    pub pitch: i32,
+    pub yaw: i32,
+    initialized: bool,
+}
+
+impl ComplementaryFilter {
+    /// Updates the orientation with a new set of readings, taken `dt_ms`
+    /// milliseconds after the previous set. `gyro` is in millidegrees per
+    /// second. The first update sets the orientation directly from the
+    /// accelerometer and magnetometer.
+    pub fn update(
+        &mut self,
+        accel: [i32; 3],
+        gyro: [i32; 3],
+        mag: Option<[i32; 3]>,
+        dt_ms: u32,
+        gain: u32,
+    ) {
+        let [ax, ay, az] = accel.map(|v| v as i64);
+        let accel_roll = atan2(ay, az);
+        let accel_pitch = atan2(-ax, isqrt((ay * ay + az * az) as u64) as i64);
+
+        let integrate =
+            |angle: i32, rate: i32| wrap(angle + (rate as i64 * dt_ms as i64 / 1000) as i32);
+
+        if self.initialized {
+            self.roll = blend(integrate(self.roll, gyro[0]), accel_roll, gain);
+            self.pitch = blend(integrate(self.pitch, gyro[1]), accel_pitch, gain);
+            self.yaw = integrate(self.yaw, gyro[2]);
+        } else {
+            self.roll = accel_roll;
+            self.pitch = accel_pitch;
+        }
+
+        if let Some(mag) = mag {
+            // Rotate the magnetic field back into the horizontal plane.
+            let [mx, my, mz] = mag.map(|v| v as i64);
+            let (sr, cr) = (sin(self.roll), cos(self.roll));
+            let (sp, cp) = (sin(self.pitch), cos(self.pitch));
+            let xh = mx * cp / 1000 + (my * sr / 1000 + mz * cr / 1000) * sp / 1000;
+            let yh = my * cr / 1000 - mz * sr / 1000;
+            let heading = atan2(-yh, xh);
+
+            self.yaw = if self.initialized {
+                blend(self.yaw, heading, gain)
+            } else {
+                heading
+            };
+        }
+
+        self.initialized = true;
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    Idle,
+    ReadAccelerometer,
+    ReadGyroscope,
+    ReadMagnetometer,
+}
+
+#[derive(Default)]
+pub struct App {
+    listening: bool,
+}
+
+pub struct Orientation<'a, A: time::Alarm<'a>> {
+    sensor: &'a dyn NineDof<'a>,
+    magnetometer: Option<&'a dyn NineDof<'a>>,
+    alarm: &'a A,
+    period_ms: u32,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    state: Cell<State>,
+    accel: Cell<[i32; 3]>,
+    gyro: Cell<[i32; 3]>,
+    filter: Cell<ComplementaryFilter>,
+    gain: Cell<u32>,
+}
+
+impl<'a, A: time::Alarm<'a>> Orientation<'a, A> {
+    /// `sensor` provides the accelerometer and gyroscope readings. If
+    /// `magnetometer` is given it is used to correct the yaw. The filter is
+    /// updated every `period_ms` milliseconds while any app is listening.
+    pub fn new(
+        sensor: &'a dyn NineDof<'a>,
+        magnetometer: Option<&'a dyn NineDof<'a>>,
+        alarm: &'a A,
+        period_ms: u32,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Orientation<'a, A> {
+        Orientation {
+            sensor,
+            magnetometer,
+            alarm,
+            period_ms,
+            apps: grant,
+            state: Cell::new(State::Idle),
+            accel: Cell::new([0; 3]),
+            gyro: Cell::new([0; 3]),
+            filter: Cell::new(ComplementaryFilter::default()),
+            gain: Cell::new(DEFAULT_GAIN),
+        }
+    }
+
+    /// Sets the weight given to the integrated gyroscope, in thousandths.
+    pub fn set_gain(&self, gain: u32) -> Result<(), ErrorCode> {
+        if gain > 1000 {
+            return Err(ErrorCode::INVAL);
+        }
+        self.gain.set(gain);
+        Ok(())
+    }
+
+    fn any_listening(&self) -> bool {
+        self.apps
+            .iter()
+            .any(|app| app.enter(|app, _| app.listening))
+    }
+
+    /// Starts sampling if it is not already running.
+    fn start(&self) {
+        if self.state.get() == State::Idle && !self.alarm.is_armed() {
+            self.filter.set(ComplementaryFilter::default());
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+        }
+    }
+
+    /// Finishes a set of readings and schedules the next one.
+    fn finish_sample(&self, mag: Option<[i32; 3]>) {
+        self.state.set(State::Idle);
+
+        let mut filter = self.filter.get();
+        filter.update(
+            self.accel.get(),
+            self.gyro.get(),
+            mag,
+            self.period_ms,
+            self.gain.get(),
+        );
+        self.filter.set(filter);
+
+        self.apps.each(|_, app, kernel_data| {
+            if app.listening {
+                kernel_data
+                    .schedule_upcall(
+                        0,
+                        (
+                            filter.roll as usize,
+                            filter.pitch as usize,
+                            filter.yaw as usize,
+                        ),
+                    )
+                    .ok();
+            }
+        });
+
+        if self.any_listening() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+        }
+    }
+
+    /// Abandons the current set of readings and retries at the next period.
+    fn abort_sample(&self) {
+        self.state.set(State::Idle);
+        if self.any_listening() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> time::AlarmClient for Orientation<'a, A> {
+    fn alarm(&self) {
+        if !self.any_listening() {
+            return;
+        }
+        self.state.set(State::ReadAccelerometer);
+        if self.sensor.read_accelerometer().is_err() {
+            self.abort_sample();
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> NineDofClient for Orientation<'a, A> {
+    fn callback(&self, arg1: usize, arg2: usize, arg3: usize) {
+        let reading = [arg1 as i32, arg2 as i32, arg3 as i32];
+        match self.state.get() {
+            State::ReadAccelerometer => {
+                self.accel.set(reading);
+                self.state.set(State::ReadGyroscope);
+                if self.sensor.read_gyroscope().is_err() {
+                    self.abort_sample();
+                }
+            }
+            State::ReadGyroscope => {
+                self.gyro.set(reading);
+                match self.magnetometer {
+                    Some(magnetometer) => {
+                        self.state.set(State::ReadMagnetometer);
+                        if magnetometer.read_magnetometer().is_err() {
+                            self.finish_sample(None);
+                        }
+                    }
+                    None => self.finish_sample(None),
+                }
+            }
+            State::ReadMagnetometer => self.finish_sample(Some(reading)),
+            State::Idle => {}
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>> SyscallDriver for Orientation<'a, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            // Start listening
+            1 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.listening = true;
+                })
+                .map_or_else(
+                    |err| CommandReturn::failure(err.into()),
+                    |()| {
+                        self.start();
+                        CommandReturn::success()
+                    },
+                ),
+
+            // Stop listening
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.listening = false;
+                })
+                .map_or_else(
+                    |err| CommandReturn::failure(err.into()),
+                    |()| CommandReturn::success(),
+                ),
+
+            // Set the filter gain
+            3 => self.set_gain(data1 as u32).into(),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `angle` is within 0.5 degrees of `expected`.
+    fn assert_angle(angle: i32, expected: i32) {
+        assert!(
+            wrap(angle - expected).abs() < 500,
+            "{} is not close to {}",
+            angle,
+            expected
+        );
+    }
+
+    #[test]
+    fn trigonometry() {
+        for deg in (-180..=180).step_by(15) {
+            let angle = deg * 1000;
+            let (s, c) = (sin(angle), cos(angle));
+            assert_angle(atan2(s * 1000, c * 1000), angle);
+        }
+        assert_eq!(isqrt(0), 0);
+        assert_eq!(isqrt(99), 9);
+        assert_eq!(isqrt(100), 10);
+        assert_eq!(isqrt(u32::MAX as u64), 65535);
+    }
+
+    #[test]
+    fn steady_tilt() {
+        // The board is held still, rolled by 30 degrees and pitched by -20
+        // degrees, facing 60 degrees from magnetic north.
+        let accel = [342, 470, 814];
+        let mag = [41, -4386, -2400];
+
+        let mut filter = ComplementaryFilter::default();
+        filter.update(
+            [0, 0, 1000],
+            [0; 3],
+            Some([3000, 0, -4000]),
+            20,
+            DEFAULT_GAIN,
+        );
+        assert_angle(filter.roll, 0);
+        assert_angle(filter.pitch, 0);
+        assert_angle(filter.yaw, 0);
+
+        // The filter converges to the new orientation.
+        for _ in 0..500 {
+            filter.update(accel, [0; 3], Some(mag), 20, DEFAULT_GAIN);
+        }
+        assert_angle(filter.roll, 30000);
+        assert_angle(filter.pitch, -20000);
+        assert_angle(filter.yaw, 60000);
+    }
+
+    #[test]
+    fn gyro_integration() {
+        // Without a magnetometer yaw follows the gyroscope, turning at 90
+        // degrees per second for one second.
+        let mut filter = ComplementaryFilter::default();
+        for _ in 0..51 {
+            filter.update([0, 0, 1000], [0, 0, 90000], None, 20, DEFAULT_GAIN);
+        }
+        assert_angle(filter.roll, 0);
+        assert_angle(filter.pitch, 0);
+        assert_angle(filter.yaw, 90000);
+    }
+}
@@ -0,0 +1,320 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Provides userspace with randomized delays.
+//!
+//! Randomizing when something happens is useful for timing-attack
+//! mitigation and for backoff in network protocols. An app asks for a delay
+//! between a minimum and a maximum number of microseconds. The capsule draws
+//! a random number from an `Rng`, picks a delay uniformly in that range, and
+//! schedules the app's upcall once the delay has passed.
+//!
+//! Drawing randomness is asynchronous, so a request first waits for the
+//! `Rng` and then for the alarm. Random numbers are drawn for all apps
+//! waiting for one at the same time, and all delays share one alarm. A
+//! request can be cancelled at either stage.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let random_delay = components::random_delay::RandomDelayComponent::new(
+//!     board_kernel,
+//!     capsules_extra::random_delay::DRIVER_NUM,
+//!     mux_alarm,
+//!     rng,
+//! )
+//! .finalize(components::random_delay_component_static!(
+//!     nrf52840::rtc::Rtc<'static>,
+//!     capsules_core::rng::Entropy32ToRandom<'static, nrf52840::trng::Trng<'static>>
+//! ));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! - Command 1 starts a delay of between `data1` and `data2` microseconds,
+//!   inclusive.
+//! - Command 2 cancels the app's delay.
+//! - Upcall 0 is scheduled when the delay expires, with a status code and
+//!   the delay that was used in microseconds, or with the error if no random
+//!   number could be drawn.
+
+use core::cell::Cell;
+
+use kernel::errorcode::into_statuscode;
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::rng::{self, Rng};
+use kernel::hil::time::{self, Alarm, ConvertTicks, Ticks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::RandomDelay as usize;
+
+/// ID of the delay expired upcall.
+const UPCALL_NUM: usize = 0;
+
+#[derive(Clone, Copy)]
+enum DelayState<T: Ticks> {
+    Idle,
+    /// Waiting for a random number to pick the delay.
+    Drawing {
+        min_us: u32,
+        max_us: u32,
+    },
+    /// Waiting for the delay of `delay_us` to expire.
+    Waiting {
+        reference: T,
+        dt: T,
+        delay_us: u32,
+    },
+}
+
+pub struct App<T: Ticks> {
+    state: DelayState<T>,
+}
+
+impl<T: Ticks> Default for App<T> {
+    fn default() -> App<T> {
+        App {
+            state: DelayState::Idle,
+        }
+    }
+}
+
+/// Picks a delay uniformly in `min_us..=max_us` from a random number.
+fn pick_delay(random: u32, min_us: u32, max_us: u32) -> u32 {
+    let span = u64::from(max_us - min_us) + 1;
+    min_us + ((u64::from(random) * span) >> 32) as u32
+}
+
+pub struct RandomDelay<'a, A: Alarm<'a>, R: Rng<'a>> {
+    alarm: &'a A,
+    rng: &'a R,
+    apps: Grant<App<A::Ticks>, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    /// A random number has been requested from the `Rng`.
+    drawing: Cell<bool>,
+}
+
+impl<'a, A: Alarm<'a>, R: Rng<'a>> RandomDelay<'a, A, R> {
+    pub fn new(
+        alarm: &'a A,
+        rng: &'a R,
+        grant: Grant<App<A::Ticks>, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> RandomDelay<'a, A, R> {
+        RandomDelay {
+            alarm,
+            rng,
+            apps: grant,
+            drawing: Cell::new(false),
+        }
+    }
+
+    fn start(&self, min_us: u32, max_us: u32, processid: ProcessId) -> Result<(), ErrorCode> {
+        if min_us > max_us {
+            return Err(ErrorCode::INVAL);
+        }
+        self.apps
+            .enter(processid, |app, _| match app.state {
+                DelayState::Idle => {
+                    app.state = DelayState::Drawing { min_us, max_us };
+                    Ok(())
+                }
+                _ => Err(ErrorCode::BUSY),
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        if self.drawing.get() {
+            // The number drawn for the other apps will cover this one too.
+            return Ok(());
+        }
+        match self.rng.get() {
+            Ok(()) => {
+                self.drawing.set(true);
+                Ok(())
+            }
+            Err(err) => {
+                let _ = self.apps.enter(processid, |app, _| {
+                    app.state = DelayState::Idle;
+                });
+                Err(err)
+            }
+        }
+    }
+
+    fn cancel(&self, processid: ProcessId) -> Result<(), ErrorCode> {
+        self.apps
+            .enter(processid, |app, _| match app.state {
+                DelayState::Idle => Err(ErrorCode::ALREADY),
+                _ => {
+                    app.state = DelayState::Idle;
+                    Ok(())
+                }
+            })
+            .unwrap_or_else(|err| Err(err.into()))?;
+
+        // Stop drawing if no other app needs a random number.
+        if self.drawing.get() && !self.any_drawing() && self.rng.cancel().is_ok() {
+            self.drawing.set(false);
+        }
+        self.rearm();
+        Ok(())
+    }
+
+    fn any_drawing(&self) -> bool {
+        let mut drawing = false;
+        self.apps.each(|_, app, _| {
+            drawing |= matches!(app.state, DelayState::Drawing { .. });
+        });
+        drawing
+    }
+
+    /// Arms the alarm for the delay that expires first, or disarms it if
+    /// there is none.
+    fn rearm(&self) {
+        let now = self.alarm.now();
+        let mut next: Option<(A::Ticks, A::Ticks, A::Ticks)> = None;
+        self.apps.each(|_, app, _| {
+            if let DelayState::Waiting { reference, dt, .. } = app.state {
+                let end = reference.wrapping_add(dt);
+                let remaining = if now.within_range(reference, end) {
+                    end.wrapping_sub(now)
+                } else {
+                    A::Ticks::from(0)
+                };
+                if next.map_or(true, |(_, _, next_remaining)| remaining < next_remaining) {
+                    next = Some((reference, dt, remaining));
+                }
+            }
+        });
+        match next {
+            Some((reference, dt, _)) => self.alarm.set_alarm(reference, dt),
+            None => {
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, R: Rng<'a>> rng::Client for RandomDelay<'a, A, R> {
+    fn randomness_available(
+        &self,
+        randomness: &mut dyn Iterator<Item = u32>,
+        error: Result<(), ErrorCode>,
+    ) -> rng::Continue {
+        if error == Err(ErrorCode::CANCEL) && self.any_drawing() {
+            // An app started a delay after the cancel could not stop the
+            // draw, so keep drawing for it.
+            return rng::Continue::More;
+        }
+        if let Err(err) = error {
+            self.drawing.set(false);
+            if err != ErrorCode::CANCEL {
+                // No delay can be picked, so fail every app waiting for one.
+                self.apps.each(|_, app, upcalls| {
+                    if let DelayState::Drawing { .. } = app.state {
+                        app.state = DelayState::Idle;
+                        let _ = upcalls.schedule_upcall(UPCALL_NUM, (into_statuscode(error), 0, 0));
+                    }
+                });
+            }
+            return rng::Continue::Done;
+        }
+
+        let now = self.alarm.now();
+        let mut more = false;
+        self.apps.each(|_, app, _| {
+            if let DelayState::Drawing { min_us, max_us } = app.state {
+                match randomness.next() {
+                    Some(random) => {
+                        let delay_us = pick_delay(random, min_us, max_us);
+                        app.state = DelayState::Waiting {
+                            reference: now,
+                            dt: self.alarm.ticks_from_us(delay_us),
+                            delay_us,
+                        };
+                    }
+                    None => more = true,
+                }
+            }
+        });
+        self.rearm();
+
+        if more {
+            rng::Continue::More
+        } else {
+            self.drawing.set(false);
+            rng::Continue::Done
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, R: Rng<'a>> time::AlarmClient for RandomDelay<'a, A, R> {
+    fn alarm(&self) {
+        let now = self.alarm.now();
+        self.apps.each(|_, app, upcalls| {
+            if let DelayState::Waiting {
+                reference,
+                dt,
+                delay_us,
+            } = app.state
+            {
+                if !now.within_range(reference, reference.wrapping_add(dt)) {
+                    app.state = DelayState::Idle;
+                    let _ = upcalls.schedule_upcall(
+                        UPCALL_NUM,
+                        (into_statuscode(Ok(())), delay_us as usize, 0),
+                    );
+                }
+            }
+        });
+        self.rearm();
+    }
+}
+
+impl<'a, A: Alarm<'a>, R: Rng<'a>> SyscallDriver for RandomDelay<'a, A, R> {
+    /// Start and cancel randomized delays.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Start a delay of between `data1` and `data2` microseconds,
+    ///   inclusive. Returns `BUSY` if the app already has a delay pending.
+    /// - `2`: Cancel the pending delay. Returns `ALREADY` if there is none.
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => self.start(data1 as u32, data2 as u32, processid).into(),
+            2 => self.cancel(processid).into(),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_in_range() {
+        assert_eq!(pick_delay(0, 100, 200), 100);
+        assert_eq!(pick_delay(u32::MAX, 100, 200), 200);
+        assert_eq!(pick_delay(u32::MAX / 2, 100, 200), 150);
+        assert_eq!(pick_delay(u32::MAX, 7, 7), 7);
+        assert_eq!(pick_delay(u32::MAX, 0, u32::MAX), u32::MAX);
+        assert_eq!(pick_delay(12345, 0, u32::MAX), 12345);
+    }
+}
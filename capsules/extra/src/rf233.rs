@@ -918,7 +918,7 @@ impl<'a, S: spi::SpiMasterDevice<'a>> spi::SpiMasterClient for RF233<'a, S> {
                     let frame_len = rbuf[1] as usize - radio::MFR_SIZE;
 
                     // lqi is currently unimplemented for rf233 and is subsequently hardcoded to zero
-                    client.receive(rbuf, frame_len, 0, self.crc_valid.get(), Ok(()));
+                    client.receive(rbuf, frame_len, 0, None, self.crc_valid.get(), Ok(()));
                 });
             }
 
@@ -0,0 +1,325 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Decodes a quadrature rotary encoder on two GPIO pins.
+//!
+//! The A and B outputs of a rotary encoder step through the Gray code
+//! sequence `00 -> 01 -> 11 -> 10 -> 00` when turned one way, and through the
+//! reverse sequence when turned the other way. The decoder interrupts on both
+//! edges of both pins, looks up each change of the pin levels in a transition
+//! table, and counts one step of the encoder every four transitions in the
+//! same direction.
+//!
+//! Contact bounce shows up as a transition that is immediately undone, which
+//! counts forwards and then backwards again, so it does not move the
+//! position. A change of both pins at once means an edge was missed. The
+//! direction of such a change cannot be known, so it is ignored.
+//!
+//! The client is notified of every step with the change and the new
+//! position. Userspace can read the position and subscribe to the same
+//! notifications through `RotaryEncoderDriver`.
+//!
+//! The pins must be configured as inputs by the board.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let encoder = components::rotary_encoder::RotaryEncoderComponent::new(
+//!     board_kernel,
+//!     capsules_extra::rotary_encoder::DRIVER_NUM,
+//!     &gpio_port[ENCODER_A_PIN],
+//!     &gpio_port[ENCODER_B_PIN],
+//! )
+//! .finalize(components::rotary_encoder_component_static!());
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::gpio;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::OptionalCell;
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::RotaryEncoder as usize;
+
+/// Number of transitions in one step of the encoder.
+const TRANSITIONS_PER_STEP: i8 = 4;
+
+/// Direction of a change of the pin levels, indexed by the old levels in
+/// bits 2-3 and the new levels in bits 0-1, with A as the high bit. No change
+/// and a change of both pins count as 0.
+const TRANSITIONS: [i8; 16] = [0, 1, -1, 0, -1, 0, 0, 1, 1, 0, 0, -1, 0, -1, 1, 0];
+
+/// Receives the movements of the encoder.
+pub trait Client {
+    /// The encoder moved by `delta` steps to `position`.
+    fn moved(&self, delta: i32, position: i32);
+}
+
+pub struct RotaryEncoder<'a> {
+    pin_a: &'a dyn gpio::Interrupt<'a>,
+    pin_b: &'a dyn gpio::Interrupt<'a>,
+    client: OptionalCell<&'a dyn Client>,
+    /// Levels of the pins after the last interrupt.
+    levels: Cell<u8>,
+    /// Transitions counted towards the next step.
+    transitions: Cell<i8>,
+    position: Cell<i32>,
+}
+
+impl<'a> RotaryEncoder<'a> {
+    pub fn new(
+        pin_a: &'a dyn gpio::Interrupt<'a>,
+        pin_b: &'a dyn gpio::Interrupt<'a>,
+    ) -> RotaryEncoder<'a> {
+        RotaryEncoder {
+            pin_a,
+            pin_b,
+            client: OptionalCell::empty(),
+            levels: Cell::new(0),
+            transitions: Cell::new(0),
+            position: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+
+    /// Samples the pins and starts decoding.
+    pub fn enable(&self) {
+        self.levels.set(self.read_levels());
+        self.transitions.set(0);
+        self.pin_a
+            .enable_interrupts(gpio::InterruptEdge::EitherEdge);
+        self.pin_b
+            .enable_interrupts(gpio::InterruptEdge::EitherEdge);
+    }
+
+    /// Stops decoding. The position is kept.
+    pub fn disable(&self) {
+        self.pin_a.disable_interrupts();
+        self.pin_b.disable_interrupts();
+    }
+
+    /// The position in steps since the decoder was created.
+    pub fn position(&self) -> i32 {
+        self.position.get()
+    }
+
+    fn read_levels(&self) -> u8 {
+        (u8::from(self.pin_a.read()) << 1) | u8::from(self.pin_b.read())
+    }
+}
+
+impl gpio::Client for RotaryEncoder<'_> {
+    fn fired(&self) {
+        let levels = self.read_levels();
+        let direction = TRANSITIONS[usize::from(self.levels.get() << 2 | levels)];
+        self.levels.set(levels);
+        if direction == 0 {
+            return;
+        }
+
+        let transitions = self.transitions.get() + direction;
+        if transitions.abs() < TRANSITIONS_PER_STEP {
+            self.transitions.set(transitions);
+            return;
+        }
+        self.transitions.set(0);
+
+        let delta = i32::from(transitions.signum());
+        let position = self.position.get().wrapping_add(delta);
+        self.position.set(position);
+        self.client.map(|client| client.moved(delta, position));
+    }
+}
+
+/// ID of the movement upcall.
+const UPCALL_NUM: usize = 0;
+
+/// Userspace interface to a rotary encoder.
+///
+/// Command 1 reads the position. Upcall 0 is scheduled for every app on each
+/// step, with the change and the new position.
+pub struct RotaryEncoderDriver<'a> {
+    encoder: &'a RotaryEncoder<'a>,
+    apps: Grant<(), UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+}
+
+impl<'a> RotaryEncoderDriver<'a> {
+    pub fn new(
+        encoder: &'a RotaryEncoder<'a>,
+        grant: Grant<(), UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> RotaryEncoderDriver<'a> {
+        RotaryEncoderDriver {
+            encoder,
+            apps: grant,
+        }
+    }
+}
+
+impl Client for RotaryEncoderDriver<'_> {
+    fn moved(&self, delta: i32, position: i32) {
+        self.apps.each(|_, _, upcalls| {
+            upcalls
+                .schedule_upcall(UPCALL_NUM, (delta as usize, position as usize, 0))
+                .ok();
+        });
+    }
+}
+
+impl SyscallDriver for RotaryEncoderDriver<'_> {
+    /// Read the rotary encoder.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Return the position in steps, as a signed 32-bit value.
+    fn command(
+        &self,
+        command_num: usize,
+        _data1: usize,
+        _data2: usize,
+        _processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+            1 => CommandReturn::success_u32(self.encoder.position() as u32),
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use gpio::Client as _;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    #[derive(Default)]
+    struct MockPin {
+        value: Cell<bool>,
+    }
+
+    impl gpio::Input for MockPin {
+        fn read(&self) -> bool {
+            self.value.get()
+        }
+    }
+
+    impl<'a> gpio::Interrupt<'a> for MockPin {
+        fn set_client(&self, _client: &'a dyn gpio::Client) {}
+        fn enable_interrupts(&self, _mode: gpio::InterruptEdge) {}
+        fn disable_interrupts(&self) {}
+        fn is_pending(&self) -> bool {
+            false
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        moves: RefCell<Vec<(i32, i32)>>,
+    }
+
+    impl Client for MockClient {
+        fn moved(&self, delta: i32, position: i32) {
+            self.moves.borrow_mut().push((delta, position));
+        }
+    }
+
+    struct Encoder {
+        a: &'static MockPin,
+        b: &'static MockPin,
+        decoder: &'static RotaryEncoder<'static>,
+        client: &'static MockClient,
+    }
+
+    impl Encoder {
+        fn new() -> Encoder {
+            let a = Box::leak(Box::new(MockPin::default()));
+            let b = Box::leak(Box::new(MockPin::default()));
+            let decoder = Box::leak(Box::new(RotaryEncoder::new(a, b)));
+            let client = Box::leak(Box::new(MockClient::default()));
+            decoder.set_client(client);
+            decoder.enable();
+            Encoder {
+                a,
+                b,
+                decoder,
+                client,
+            }
+        }
+
+        /// Sets the pins to `levels`, A in bit 1 and B in bit 0, and
+        /// interrupts.
+        fn set(&self, levels: u8) {
+            self.a.value.set(levels & 0b10 != 0);
+            self.b.value.set(levels & 0b01 != 0);
+            self.decoder.fired();
+        }
+
+        fn turn(&self, sequence: &[u8]) {
+            sequence.iter().for_each(|&levels| self.set(levels));
+        }
+    }
+
+    const CLOCKWISE: [u8; 4] = [0b01, 0b11, 0b10, 0b00];
+    const COUNTER_CLOCKWISE: [u8; 4] = [0b10, 0b11, 0b01, 0b00];
+
+    #[test]
+    fn counts_steps() {
+        let encoder = Encoder::new();
+        encoder.turn(&CLOCKWISE);
+        encoder.turn(&CLOCKWISE);
+        encoder.turn(&COUNTER_CLOCKWISE);
+        encoder.turn(&COUNTER_CLOCKWISE);
+        encoder.turn(&COUNTER_CLOCKWISE);
+        assert_eq!(
+            *encoder.client.moves.borrow(),
+            [(1, 1), (1, 2), (-1, 1), (-1, 0), (-1, -1)]
+        );
+        assert_eq!(encoder.decoder.position(), -1);
+    }
+
+    #[test]
+    fn bounce_not_counted() {
+        let encoder = Encoder::new();
+        // A bounces on the first edge, and B on the last one.
+        encoder.turn(&[0b01, 0b00, 0b01, 0b00, 0b01, 0b11, 0b10]);
+        encoder.turn(&[0b00, 0b10, 0b00, 0b10, 0b00]);
+        assert_eq!(*encoder.client.moves.borrow(), [(1, 1)]);
+
+        // Bouncing back over the edge that completed the step does not
+        // count another step either.
+        encoder.turn(&[0b10, 0b00, 0b10, 0b00]);
+        assert_eq!(*encoder.client.moves.borrow(), [(1, 1)]);
+        assert_eq!(encoder.decoder.position(), 1);
+    }
+
+    #[test]
+    fn missed_edge_ignored() {
+        let encoder = Encoder::new();
+        // The 01 -> 11 edge is missed, so 01 -> 10 changes both pins.
+        encoder.turn(&[0b01, 0b10, 0b00]);
+        assert!(encoder.client.moves.borrow().is_empty());
+
+        // Decoding carries on from the levels after the missed edge.
+        encoder.turn(&CLOCKWISE);
+        encoder.turn(&CLOCKWISE);
+        assert_eq!(encoder.decoder.position(), 2);
+    }
+}
@@ -34,6 +34,13 @@
 //!      capsules::virtual_alarm::VirtualMuxAlarm::new(mux_alarm));
 //! sdcard_virtual_alarm.setup();
 //!
+//! let sd_detect = components::debounce::DebounceComponent::new(
+//!     &SD_DETECT_PIN,
+//!     mux_alarm,
+//!     500,
+//! )
+//! .finalize(components::debounce_component_static!(nrf52833::rtc::Rtc));
+//!
 //! let sdcard_tx_buffer = static_init!([u8; capsules::sdcard::TXRX_BUFFER_LENGTH],
 //!                                     [0; capsules::sdcard::TXRX_BUFFER_LENGTH]);
 //! let sdcard_rx_buffer = static_init!([u8; capsules::sdcard::TXRX_BUFFER_LENGTH],
@@ -45,12 +52,13 @@
 //!         capsules::virtual_alarm::VirtualMuxAlarm<'static, nrf52833::rtc::Rtc>>,
 //!     capsules::sdcard::SDCard::new(sdcard_spi,
 //!                                   sdcard_virtual_alarm,
-//!                                   Some(&SD_DETECT_PIN),
+//!                                   Some(sd_detect),
 //!                                   sdcard_tx_buffer,
-//!                                   sdcard_rx_buffer));
+//!                                   sdcard_rx_buffer,
+//!                                   capsules::sdcard::SDCardTimeouts::default()));
 //! sdcard_spi.set_client(sdcard);
 //! sdcard_virtual_alarm.set_alarm_client(sdcard);
-//! SD_DETECT_PIN.set_client(sdcard);
+//! sd_detect.set_client(sdcard);
 //!
 //! let sdcard_kernel_buffer = static_init!([u8; capsules::sdcard::KERNEL_BUFFER_LENGTH],
 //!                                         [0; capsules::sdcard::KERNEL_BUFFER_LENGTH]);
@@ -79,12 +87,13 @@ use core::cmp;
 
 use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
 use kernel::hil;
-use kernel::hil::time::ConvertTicks;
-use kernel::processbuffer::{ReadableProcessBuffer, WriteableProcessBuffer};
+use kernel::processbuffer::{ReadableProcessBuffer, ReadableProcessSlice, WriteableProcessBuffer};
 use kernel::syscall::{CommandReturn, SyscallDriver};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::{ErrorCode, ProcessId};
 
+use crate::debounce::{self, Debounce};
+
 /// Syscall driver number.
 use capsules_core::driver;
 pub const DRIVER_NUM: usize = driver::NUM::SdCard as usize;
@@ -110,6 +119,55 @@ mod rw_allow {
 ///  * Both RXBUFFER and TXBUFFER must be longer  than the SD card's block size
 pub const TXRX_BUFFER_LENGTH: usize = 515;
 
+/// How often, and how many times in a row, the SD card is polled while it is
+/// busy before an operation fails with `TimeoutFailure`.
+///
+/// Card detection is debounced by the `Debounce` given to `SDCard::new()`,
+/// and its interval is set there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SDCardTimeouts {
+    /// Interval between polls while the card initializes, in ms.
+    pub init_interval_ms: u32,
+    /// Interval between polls while the card reads or writes data, in ms.
+    pub data_interval_ms: u32,
+    /// Number of polls in a row after which the operation fails.
+    pub max_retries: u32,
+}
+
+impl Default for SDCardTimeouts {
+    fn default() -> Self {
+        SDCardTimeouts {
+            init_interval_ms: 10,
+            data_interval_ms: 1,
+            max_retries: 100,
+        }
+    }
+}
+
+impl SDCardTimeouts {
+    /// Sets `max_retries` so that initialization is given at least
+    /// `timeout_ms` before it fails.
+    pub fn with_init_timeout_ms(mut self, timeout_ms: u32) -> Self {
+        let interval_ms = cmp::max(self.init_interval_ms, 1);
+        self.max_retries = timeout_ms.div_ceil(interval_ms).saturating_sub(1);
+        self
+    }
+
+    /// The time after which a card that is still initializing is given up
+    /// on, in ms.
+    pub fn init_timeout_ms(&self) -> u32 {
+        self.init_interval_ms
+            .saturating_mul(self.max_retries.saturating_add(1))
+    }
+
+    /// The time after which a card that is still reading or writing data is
+    /// given up on, in ms.
+    pub fn data_timeout_ms(&self) -> u32 {
+        self.data_interval_ms
+            .saturating_mul(self.max_retries.saturating_add(1))
+    }
+}
+
 /// SD Card capsule, capable of being built on top of by other kernel capsules
 pub struct SDCard<'a, A: hil::time::Alarm<'a>> {
     spi: &'a dyn hil::spi::SpiMasterDevice<'a>,
@@ -118,12 +176,13 @@ pub struct SDCard<'a, A: hil::time::Alarm<'a>> {
 
     alarm: &'a A,
     alarm_state: Cell<AlarmState>,
-    alarm_count: Cell<u8>,
+    alarm_count: Cell<u32>,
+    timeouts: SDCardTimeouts,
 
     is_initialized: Cell<bool>,
     card_type: Cell<SDCardType>,
 
-    detect_pin: Cell<Option<&'a dyn hil::gpio::InterruptPin<'a>>>,
+    detect_pin: Cell<Option<&'a Debounce<'a, A>>>,
 
     txbuffer: TakeCell<'static, [u8]>,
     rxbuffer: TakeCell<'static, [u8]>,
@@ -131,6 +190,7 @@ pub struct SDCard<'a, A: hil::time::Alarm<'a>> {
     client: OptionalCell<&'a dyn SDCardClient>,
     client_buffer: TakeCell<'static, [u8]>,
     client_offset: Cell<usize>,
+    range: OptionalCell<Range>,
 }
 
 /// SD card command codes
@@ -197,8 +257,6 @@ enum SpiState {
 enum AlarmState {
     Idle,
 
-    DetectionChange,
-
     RepeatHCSInit,
     RepeatAppSpecificInit,
     RepeatGenericInit,
@@ -209,14 +267,21 @@ enum AlarmState {
     WaitForWriteBusy,
 }
 
-/// Error codes returned if an SD card transaction fails
+/// Reasons an SD card transaction fails
 #[derive(Clone, Copy, Debug, PartialEq)]
-enum SdCardError {
-    CardStateChanged = -10001,
-    InitializationFailure = -10002,
-    ReadFailure = -10003,
-    WriteFailure = -10004,
-    TimeoutFailure = -10005,
+pub enum SdCardError {
+    /// The card was inserted or removed during the transaction.
+    CardStateChanged,
+    /// The card did not complete initialization.
+    InitializationFailure,
+    /// The card rejected a read.
+    ReadFailure,
+    /// The card rejected a write.
+    WriteFailure,
+    /// The card stayed busy for longer than the configured timeout.
+    TimeoutFailure,
+    /// The transaction was cancelled with `abort()`.
+    Aborted,
 }
 
 /// SD card types, determined during initialization
@@ -229,6 +294,20 @@ enum SDCardType {
     SDv2BlockAddressable = 0x04 | 0x08,
 }
 
+/// A read or write of part of a block, done on top of a whole block read
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Range {
+    Read {
+        offset: usize,
+        len: usize,
+    },
+    Write {
+        sector: u32,
+        offset: usize,
+        len: usize,
+    },
+}
+
 // Constants used in driver
 const SUCCESS_STATUS: u8 = 0x00;
 const INITIALIZING_STATUS: u8 = 0x01;
@@ -240,7 +319,7 @@ pub trait SDCardClient {
     fn init_done(&self, block_size: u32, total_size: u64);
     fn read_done(&self, data: &'static mut [u8], len: usize);
     fn write_done(&self, buffer: &'static mut [u8]);
-    fn error(&self, error: u32);
+    fn error(&self, error: SdCardError);
 }
 
 /// Functions for initializing and accessing an SD card
@@ -249,18 +328,20 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
     ///
     /// spi - virtualized SPI to use for communication with SD card
     /// alarm - virtualized Timer with a granularity of at least 1 ms
-    /// detect_pin - active low GPIO pin used to detect if an SD card is
-    ///     installed
+    /// detect_pin - debounced active low GPIO pin used to detect if an SD
+    ///     card is installed
     /// txbuffer - buffer for holding SPI write data, at least 515 bytes in
     ///     length
     /// rxbuffer - buffer for holding SPI read data, at least 515 bytes in
     ///     length
+    /// timeouts - how often and how long to poll the card while it is busy
     pub fn new(
-        spi: &'a dyn hil::spi::SpiMasterDevice,
+        spi: &'a dyn hil::spi::SpiMasterDevice<'a>,
         alarm: &'a A,
-        detect_pin: Option<&'static dyn hil::gpio::InterruptPin<'a>>,
+        detect_pin: Option<&'a Debounce<'a, A>>,
         txbuffer: &'static mut [u8; 515],
         rxbuffer: &'static mut [u8; 515],
+        timeouts: SDCardTimeouts,
     ) -> SDCard<'a, A> {
         // initialize buffers
         for byte in txbuffer.iter_mut() {
@@ -270,12 +351,6 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
             *byte = 0xFF;
         }
 
-        // handle optional detect pin
-        let pin = detect_pin.map_or(None, |pin| {
-            pin.make_input();
-            Some(pin)
-        });
-
         // set up and return struct
         SDCard {
             spi,
@@ -284,14 +359,16 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
             alarm,
             alarm_state: Cell::new(AlarmState::Idle),
             alarm_count: Cell::new(0),
+            timeouts,
             is_initialized: Cell::new(false),
             card_type: Cell::new(SDCardType::Uninitialized),
-            detect_pin: Cell::new(pin),
+            detect_pin: Cell::new(detect_pin),
             txbuffer: TakeCell::new(txbuffer),
             rxbuffer: TakeCell::new(rxbuffer),
             client: OptionalCell::empty(),
             client_buffer: TakeCell::empty(),
             client_offset: Cell::new(0),
+            range: OptionalCell::empty(),
         }
     }
 
@@ -382,7 +459,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
     }
 
     /// wrapper for easy reading of bytes over SPI
-    fn read_bytes(
+    fn spi_read_bytes(
         &self,
         write_buffer: &'static mut [u8],
         read_buffer: &'static mut [u8],
@@ -405,7 +482,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
     }
 
     /// wrapper for easy writing of bytes over SPI
-    fn write_bytes(
+    fn spi_write_bytes(
         &self,
         write_buffer: &'static mut [u8],
         read_buffer: &'static mut [u8],
@@ -508,7 +585,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
+                        client.error(SdCardError::InitializationFailure);
                     });
                 }
             }
@@ -566,10 +643,10 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
 
-                    // try again after 10 ms
+                    // try again after the init interval
                     self.alarm_state.set(AlarmState::RepeatHCSInit);
-                    let delay = self.alarm.ticks_from_ms(10);
-                    self.alarm.set_alarm(self.alarm.now(), delay);
+                    self.alarm
+                        .set_alarm_from_now_ms(self.timeouts.init_interval_ms);
                 } else {
                     // error, send callback and quit
                     self.txbuffer.replace(write_buffer);
@@ -578,7 +655,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
+                        client.error(SdCardError::InitializationFailure);
                     });
                 }
             }
@@ -607,7 +684,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
+                        client.error(SdCardError::InitializationFailure);
                     });
                 }
             }
@@ -662,10 +739,10 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
 
-                    // try again after 10 ms
+                    // try again after the init interval
                     self.alarm_state.set(AlarmState::RepeatAppSpecificInit);
-                    let delay = self.alarm.ticks_from_ms(10);
-                    self.alarm.set_alarm(self.alarm.now(), delay);
+                    self.alarm
+                        .set_alarm_from_now_ms(self.timeouts.init_interval_ms);
                 } else {
                     // error, send callback and quit
                     self.txbuffer.replace(write_buffer);
@@ -674,7 +751,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
+                        client.error(SdCardError::InitializationFailure);
                     });
                 }
             }
@@ -700,10 +777,10 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
 
-                    // try again after 10 ms
+                    // try again after the init interval
                     self.alarm_state.set(AlarmState::RepeatGenericInit);
-                    let delay = self.alarm.ticks_from_ms(10);
-                    self.alarm.set_alarm(self.alarm.now(), delay);
+                    self.alarm
+                        .set_alarm_from_now_ms(self.timeouts.init_interval_ms);
                 } else {
                     // error, send callback and quit
                     self.txbuffer.replace(write_buffer);
@@ -712,7 +789,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
+                        client.error(SdCardError::InitializationFailure);
                     });
                 }
             }
@@ -735,7 +812,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
+                        client.error(SdCardError::InitializationFailure);
                     });
                 }
             }
@@ -797,7 +874,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::InitializationFailure as u32);
+                        client.error(SdCardError::InitializationFailure);
                     });
                 }
             }
@@ -810,11 +887,11 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     if count <= 1 {
                         // check for data block to be ready
                         self.state.set(SpiState::WaitReadBlock);
-                        self.read_bytes(write_buffer, read_buffer, 1);
+                        self.spi_read_bytes(write_buffer, read_buffer, 1);
                     } else {
                         // check for data block to be ready
                         self.state.set(SpiState::WaitReadBlocks { count });
-                        self.read_bytes(write_buffer, read_buffer, 1);
+                        self.spi_read_bytes(write_buffer, read_buffer, 1);
                     }
                 } else {
                     // error, send callback and quit
@@ -824,7 +901,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
+                        client.error(SdCardError::ReadFailure);
                     });
                 }
             }
@@ -834,7 +911,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     // data ready to read. Read block plus CRC
                     self.alarm_count.set(0);
                     self.state.set(SpiState::ReadBlockComplete);
-                    self.read_bytes(write_buffer, read_buffer, 512 + 2);
+                    self.spi_read_bytes(write_buffer, read_buffer, 512 + 2);
                 } else if read_buffer[0] == 0xFF {
                     // line is idling high, data is not ready
 
@@ -842,10 +919,10 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
 
-                    // try again after 1 ms
+                    // try again after the data interval
                     self.alarm_state.set(AlarmState::WaitForDataBlock);
-                    let delay = self.alarm.ticks_from_ms(1);
-                    self.alarm.set_alarm(self.alarm.now(), delay);
+                    self.alarm
+                        .set_alarm_from_now_ms(self.timeouts.data_interval_ms);
                 } else {
                     // error, send callback and quit
                     self.txbuffer.replace(write_buffer);
@@ -854,7 +931,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
+                        client.error(SdCardError::ReadFailure);
                     });
                 }
             }
@@ -864,6 +941,12 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                 self.txbuffer.replace(write_buffer);
                 self.rxbuffer.replace(read_buffer);
 
+                if let Some(range) = self.range.take() {
+                    self.state.set(SpiState::Idle);
+                    self.complete_range(range);
+                    return;
+                }
+
                 // read finished, perform callback
                 self.state.set(SpiState::Idle);
                 self.rxbuffer.map(|read_buffer| {
@@ -891,7 +974,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     // data ready to read. Read block plus CRC
                     self.alarm_count.set(0);
                     self.state.set(SpiState::ReceivedBlock { count });
-                    self.read_bytes(write_buffer, read_buffer, 512 + 2);
+                    self.spi_read_bytes(write_buffer, read_buffer, 512 + 2);
                 } else if read_buffer[0] == 0xFF {
                     // line is idling high, data is not ready
 
@@ -899,11 +982,11 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
 
-                    // try again after 1 ms
+                    // try again after the data interval
                     self.alarm_state
                         .set(AlarmState::WaitForDataBlocks { count });
-                    let delay = self.alarm.ticks_from_ms(1);
-                    self.alarm.set_alarm(self.alarm.now(), delay);
+                    self.alarm
+                        .set_alarm_from_now_ms(self.timeouts.data_interval_ms);
                 } else {
                     // error, send callback and quit
                     self.txbuffer.replace(write_buffer);
@@ -912,7 +995,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
+                        client.error(SdCardError::ReadFailure);
                     });
                 }
             }
@@ -946,7 +1029,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     // check for next data block to be ready
                     self.state
                         .set(SpiState::WaitReadBlocks { count: count - 1 });
-                    self.read_bytes(write_buffer, read_buffer, 1);
+                    self.spi_read_bytes(write_buffer, read_buffer, 1);
                 }
             }
 
@@ -974,7 +1057,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::ReadFailure as u32);
+                        client.error(SdCardError::ReadFailure);
                     });
                 }
             }
@@ -1016,7 +1099,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
 
                         // write data packet
                         self.state.set(SpiState::WriteBlockResponse);
-                        self.write_bytes(write_buffer, read_buffer, 515);
+                        self.spi_write_bytes(write_buffer, read_buffer, 515);
                     } else {
                         // multi-block SD card writes are unimplemented
                         // This should have returned an error already, but if
@@ -1027,7 +1110,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                         self.alarm_state.set(AlarmState::Idle);
                         self.alarm_count.set(0);
                         self.client.map(move |client| {
-                            client.error(SdCardError::WriteFailure as u32);
+                            client.error(SdCardError::WriteFailure);
                         });
                     }
                 } else {
@@ -1038,7 +1121,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::WriteFailure as u32);
+                        client.error(SdCardError::WriteFailure);
                     });
                 }
             }
@@ -1046,14 +1129,14 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
             SpiState::WriteBlockResponse => {
                 // Get data packet
                 self.state.set(SpiState::WriteBlockBusy);
-                self.read_bytes(write_buffer, read_buffer, 1);
+                self.spi_read_bytes(write_buffer, read_buffer, 1);
             }
 
             SpiState::WriteBlockBusy => {
                 if (read_buffer[0] & 0x1F) == 0x05 {
                     // check if sd card is busy
                     self.state.set(SpiState::WaitWriteBlockBusy);
-                    self.read_bytes(write_buffer, read_buffer, 1);
+                    self.spi_read_bytes(write_buffer, read_buffer, 1);
                 } else {
                     // error, send callback and quit
                     self.txbuffer.replace(write_buffer);
@@ -1062,7 +1145,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.alarm_state.set(AlarmState::Idle);
                     self.alarm_count.set(0);
                     self.client.map(move |client| {
-                        client.error(SdCardError::WriteFailure as u32);
+                        client.error(SdCardError::WriteFailure);
                     });
                 }
             }
@@ -1087,10 +1170,10 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.txbuffer.replace(write_buffer);
                     self.rxbuffer.replace(read_buffer);
 
-                    // try again after 1 ms
+                    // try again after the data interval
                     self.alarm_state.set(AlarmState::WaitForWriteBusy);
-                    let delay = self.alarm.ticks_from_ms(1);
-                    self.alarm.set_alarm(self.alarm.now(), delay);
+                    self.alarm
+                        .set_alarm_from_now_ms(self.timeouts.data_interval_ms);
                 }
             }
 
@@ -1108,31 +1191,19 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
     fn process_alarm_states(&self) {
         // keep track of how many times the alarm has been called in a row
         let repeats = self.alarm_count.get();
-        if repeats > 100 {
+        if repeats >= self.timeouts.max_retries {
             // error, send callback and quit
             self.state.set(SpiState::Idle);
             self.alarm_state.set(AlarmState::Idle);
             self.alarm_count.set(0);
             self.client.map(move |client| {
-                client.error(SdCardError::TimeoutFailure as u32);
+                client.error(SdCardError::TimeoutFailure);
             });
         } else {
             self.alarm_count.set(repeats + 1);
         }
 
         match self.alarm_state.get() {
-            AlarmState::DetectionChange => {
-                // perform callback
-                self.client.map(move |client| {
-                    client.card_detection_changed(self.is_installed());
-                });
-
-                // re-enable interrupts
-                self.detect_changes();
-                self.alarm_count.set(0);
-                self.alarm_state.set(AlarmState::Idle);
-            }
-
             AlarmState::RepeatHCSInit => {
                 // check card initialization again
                 self.txbuffer.take().map(|write_buffer| {
@@ -1198,7 +1269,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.rxbuffer.take().map(move |read_buffer| {
                         // wait until ready and then read data block, then done
                         self.state.set(SpiState::WaitReadBlock);
-                        self.read_bytes(write_buffer, read_buffer, 1);
+                        self.spi_read_bytes(write_buffer, read_buffer, 1);
                     });
                 });
 
@@ -1211,7 +1282,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.rxbuffer.take().map(move |read_buffer| {
                         // wait until ready and then read data block, then done
                         self.state.set(SpiState::WaitReadBlocks { count });
-                        self.read_bytes(write_buffer, read_buffer, 1);
+                        self.spi_read_bytes(write_buffer, read_buffer, 1);
                     });
                 });
 
@@ -1224,7 +1295,7 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
                     self.rxbuffer.take().map(move |read_buffer| {
                         // check if sd card is busy
                         self.state.set(SpiState::WaitWriteBlockBusy);
-                        self.read_bytes(write_buffer, read_buffer, 1);
+                        self.spi_read_bytes(write_buffer, read_buffer, 1);
                     });
                 });
 
@@ -1254,149 +1325,310 @@ impl<'a, A: hil::time::Alarm<'a>> SDCard<'a, A> {
         self.is_initialized.get()
     }
 
+    /// whether the card is addressed by block rather than by byte
+    pub fn is_block_addressable(&self) -> bool {
+        self.card_type.get() == SDCardType::SDv2BlockAddressable
+    }
+
+    /// whether a transaction with the card is in progress
+    ///
+    /// After an `abort()` during an SPI transfer the card stays busy until
+    /// the transfer completes and its buffers are returned.
+    pub fn is_busy(&self) -> bool {
+        self.state.get() != SpiState::Idle
+            || self.alarm_state.get() != AlarmState::Idle
+            || self.txbuffer.is_none()
+    }
+
+    /// Cancels the transaction in progress, if any.
+    ///
+    /// The pending alarm is cancelled, both state machines return to `Idle`,
+    /// the client receives a final `error` callback with
+    /// `SdCardError::Aborted`, and the buffer passed to `read_blocks()` or
+    /// `write_blocks()` is returned.
+    ///
+    /// The SPI transfer in progress, if any, cannot be stopped, and the SPI
+    /// layer holds the SD card's transmit and receive buffers until it
+    /// completes. They are reclaimed in the transfer's callback rather than
+    /// here, so the hardware never writes into a buffer that has been handed
+    /// out again. The card reports `is_busy()` until then.
+    ///
+    /// Returns `ALREADY` if no transaction is in progress.
+    pub fn abort(&self) -> Result<Option<&'static mut [u8]>, ErrorCode> {
+        if self.state.get() == SpiState::Idle && self.alarm_state.get() == AlarmState::Idle {
+            return Err(ErrorCode::ALREADY);
+        }
+
+        let _ = self.alarm.disarm();
+        self.alarm_state.set(AlarmState::Idle);
+        self.alarm_count.set(0);
+        // a transfer in flight finds the state machine idle and only returns
+        // the buffers
+        self.state.set(SpiState::Idle);
+        self.after_state.set(SpiState::Idle);
+        self.range.clear();
+
+        let buffer = self.client_buffer.take();
+        self.client.map(move |client| {
+            client.error(SdCardError::Aborted);
+        });
+        Ok(buffer)
+    }
+
     /// watches SD card detect pin for changes, sends callback on change
     pub fn detect_changes(&self) {
         self.detect_pin.get().map(|pin| {
-            pin.enable_interrupts(hil::gpio::InterruptEdge::EitherEdge);
+            pin.enable();
         });
     }
 
     pub fn initialize(&self) -> Result<(), ErrorCode> {
+        // leave a transaction in progress alone
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
         // if not already, set card to uninitialized again
         self.is_initialized.set(false);
+        // each operation gets the full retry budget
+        self.alarm_count.set(0);
 
         // no point in initializing if the card is not installed
         if self.is_installed() {
             // reset the SD card in order to start initializing it
-            self.txbuffer
-                .take()
-                .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                    self.rxbuffer
-                        .take()
-                        .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                            self.state.set(SpiState::InitReset);
-                            self.send_command(SDCmd::CMD0_Reset, 0x0, txbuffer, rxbuffer, 10);
-
-                            // command started successfully
-                            Ok(())
-                        })
-                })
+            let (txbuffer, rxbuffer) = self.take_buffers()?;
+            self.state.set(SpiState::InitReset);
+            self.send_command(SDCmd::CMD0_Reset, 0x0, txbuffer, rxbuffer, 10);
+
+            // command started successfully
+            Ok(())
         } else {
             // no sd card installed
             Err(ErrorCode::UNINSTALLED)
         }
     }
 
+    /// Reads `count` blocks starting at block `sector` into `buffer`.
+    /// Completes with `read_done`.
+    ///
+    /// Returns the buffer if the read could not be started.
     pub fn read_blocks(
         &self,
         buffer: &'static mut [u8],
         sector: u32,
         count: u32,
-    ) -> Result<(), ErrorCode> {
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
         // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
-
-                                self.state.set(SpiState::StartReadBlocks { count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD17_ReadSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                } else {
-                                    self.send_command(
-                                        SDCmd::CMD18_ReadMultiple,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-                                }
+        if !self.is_installed() {
+            // sd card not installed
+            return Err((ErrorCode::UNINSTALLED, buffer));
+        }
+        if !self.is_initialized() {
+            // sd card not initialized
+            return Err((ErrorCode::RESERVE, buffer));
+        }
+        let (txbuffer, rxbuffer) = match self.take_buffers() {
+            Ok(buffers) => buffers,
+            Err(error) => return Err((error, buffer)),
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(0);
+        self.alarm_count.set(0);
+        self.range.clear();
+
+        // convert block address to byte address for non-block
+        //  access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
 
-                                // command started successfully
-                                Ok(())
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
-            }
+        self.state.set(SpiState::StartReadBlocks { count });
+        if count == 1 {
+            self.send_command(SDCmd::CMD17_ReadSingle, address, txbuffer, rxbuffer, 10);
         } else {
-            // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
+            self.send_command(SDCmd::CMD18_ReadMultiple, address, txbuffer, rxbuffer, 10);
         }
+
+        // command started successfully
+        Ok(())
     }
 
+    /// Writes the first block of `buffer` to block `sector`. Only a single
+    /// block can be written at a time. Completes with `write_done`.
+    ///
+    /// Returns the buffer if the write could not be started.
     pub fn write_blocks(
         &self,
         buffer: &'static mut [u8],
         sector: u32,
         count: u32,
-    ) -> Result<(), ErrorCode> {
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
         // only if initialized and installed
-        if self.is_installed() {
-            if self.is_initialized() {
-                self.txbuffer
-                    .take()
-                    .map_or(Err(ErrorCode::NOMEM), |txbuffer| {
-                        self.rxbuffer
-                            .take()
-                            .map_or(Err(ErrorCode::NOMEM), move |rxbuffer| {
-                                // save the user buffer for later
-                                self.client_buffer.replace(buffer);
-                                self.client_offset.set(0);
-
-                                // convert block address to byte address for non-block
-                                //  access cards
-                                let mut address = sector;
-                                if self.card_type.get() != SDCardType::SDv2BlockAddressable {
-                                    address *= 512;
-                                }
+        if !self.is_installed() {
+            // sd card not installed
+            return Err((ErrorCode::UNINSTALLED, buffer));
+        }
+        if !self.is_initialized() {
+            // sd card not initialized
+            return Err((ErrorCode::RESERVE, buffer));
+        }
+        if count != 1 {
+            // can't write multiple blocks yet
+            return Err((ErrorCode::NOSUPPORT, buffer));
+        }
+        let (txbuffer, rxbuffer) = match self.take_buffers() {
+            Ok(buffers) => buffers,
+            Err(error) => return Err((error, buffer)),
+        };
+
+        // save the user buffer for later
+        self.client_buffer.replace(buffer);
+        self.client_offset.set(0);
+        self.alarm_count.set(0);
+
+        // convert block address to byte address for non-block
+        //  access cards
+        let mut address = sector;
+        if self.card_type.get() != SDCardType::SDv2BlockAddressable {
+            address *= 512;
+        }
 
-                                self.state.set(SpiState::StartWriteBlocks { count });
-                                if count == 1 {
-                                    self.send_command(
-                                        SDCmd::CMD24_WriteSingle,
-                                        address,
-                                        txbuffer,
-                                        rxbuffer,
-                                        10,
-                                    );
-
-                                    // command started successfully
-                                    Ok(())
-                                } else {
-                                    // can't write multiple blocks yet
-                                    Err(ErrorCode::NOSUPPORT)
-                                }
-                            })
-                    })
-            } else {
-                // sd card not initialized
-                Err(ErrorCode::RESERVE)
+        self.state.set(SpiState::StartWriteBlocks { count });
+        self.send_command(SDCmd::CMD24_WriteSingle, address, txbuffer, rxbuffer, 10);
+
+        // command started successfully
+        Ok(())
+    }
+
+    /// takes the SPI buffers to start a new transaction, or returns `BUSY`
+    /// if a transaction, or the transfer of an aborted one, is in progress
+    fn take_buffers(&self) -> Result<(&'static mut [u8], &'static mut [u8]), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        match (self.txbuffer.take(), self.rxbuffer.take()) {
+            (Some(txbuffer), Some(rxbuffer)) => Ok((txbuffer, rxbuffer)),
+            (txbuffer, rxbuffer) => {
+                // put back whichever buffer was there
+                txbuffer.map(|txbuffer| self.txbuffer.replace(txbuffer));
+                rxbuffer.map(|rxbuffer| self.rxbuffer.replace(rxbuffer));
+                Err(ErrorCode::NOMEM)
+            }
+        }
+    }
+
+    /// Reads `len` bytes starting at byte `address` of the card into the
+    /// start of `buffer`.
+    ///
+    /// The range must lie within a single block. `buffer` must be at least a
+    /// block long, as the whole block is read into it first. Completes with
+    /// `read_done`.
+    ///
+    /// Returns the buffer if the read could not be started.
+    pub fn read_bytes(
+        &self,
+        buffer: &'static mut [u8],
+        address: u64,
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let (sector, offset) = match Self::range_location(buffer, address, len) {
+            Ok(location) => location,
+            Err(error) => return Err((error, buffer)),
+        };
+        self.read_blocks(buffer, sector, 1)?;
+        self.range.set(Range::Read { offset, len });
+        Ok(())
+    }
+
+    /// Writes the first `len` bytes of `buffer` to the card, starting at byte
+    /// `address`, leaving the rest of the block unchanged.
+    ///
+    /// The range must lie within a single block. `buffer` must be at least a
+    /// block long, as the block is read into it, the new bytes are spliced
+    /// in, and the whole block is written back. Completes with `write_done`.
+    ///
+    /// Returns the buffer, unchanged, if the write could not be started.
+    pub fn write_bytes(
+        &self,
+        buffer: &'static mut [u8],
+        address: u64,
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        let (sector, offset) = match Self::range_location(buffer, address, len) {
+            Ok(location) => location,
+            Err(error) => return Err((error, buffer)),
+        };
+        self.read_blocks(buffer, sector, 1)?;
+        // move the new bytes to their place in the block, which is only read
+        // into rxbuffer
+        self.client_buffer.map(|buffer| {
+            buffer.copy_within(0..len, offset);
+        });
+        self.range.set(Range::Write {
+            sector,
+            offset,
+            len,
+        });
+        Ok(())
+    }
+
+    /// returns the sector and the offset within it of a range of bytes
+    fn range_location(buffer: &[u8], address: u64, len: usize) -> Result<(u32, usize), ErrorCode> {
+        let offset = (address % 512) as usize;
+        if len == 0 || offset + len > 512 {
+            return Err(ErrorCode::INVAL);
+        }
+        if buffer.len() < 512 {
+            return Err(ErrorCode::SIZE);
+        }
+        let sector = u32::try_from(address / 512).map_err(|_| ErrorCode::INVAL)?;
+        Ok((sector, offset))
+    }
+
+    /// finishes a range access once its block has been read into rxbuffer
+    fn complete_range(&self, range: Range) {
+        let buffer = match self.client_buffer.take() {
+            Some(buffer) => buffer,
+            None => {
+                // still end the operation for the client
+                let error = match range {
+                    Range::Read { .. } => SdCardError::ReadFailure,
+                    Range::Write { .. } => SdCardError::WriteFailure,
+                };
+                self.client.map(move |client| {
+                    client.error(error);
+                });
+                return;
+            }
+        };
+        self.rxbuffer.map(|block| match range {
+            Range::Read { offset, len } => {
+                buffer[..len].copy_from_slice(&block[offset..offset + len]);
+            }
+            Range::Write { offset, len, .. } => {
+                // keep the new bytes already in place
+                buffer[..offset].copy_from_slice(&block[..offset]);
+                buffer[offset + len..512].copy_from_slice(&block[offset + len..512]);
+            }
+        });
+
+        match range {
+            Range::Read { len, .. } => {
+                self.client.map(move |client| {
+                    client.read_done(buffer, len);
+                });
+            }
+            Range::Write { sector, .. } => {
+                if let Err((_error, buffer)) = self.write_blocks(buffer, sector, 1) {
+                    // keep the buffer, like the other failed transactions
+                    self.client_buffer.replace(buffer);
+                    self.client.map(move |client| {
+                        client.error(SdCardError::WriteFailure);
+                    });
+                }
             }
-        } else {
-            // sd card not installed
-            Err(ErrorCode::UNINSTALLED)
         }
     }
 }
@@ -1425,8 +1657,8 @@ impl<'a, A: hil::time::Alarm<'a>> hil::time::AlarmClient for SDCard<'a, A> {
 }
 
 /// Handle callbacks from the card detection pin
-impl<'a, A: hil::time::Alarm<'a>> hil::gpio::Client for SDCard<'a, A> {
-    fn fired(&self) {
+impl<'a, A: hil::time::Alarm<'a>> debounce::Client for SDCard<'a, A> {
+    fn changed(&self, _value: bool) {
         // check if there was an open transaction with the sd card
         if self.alarm_state.get() != AlarmState::Idle || self.state.get() != SpiState::Idle {
             // something was running when this occurred. Kill the transaction and
@@ -1434,22 +1666,16 @@ impl<'a, A: hil::time::Alarm<'a>> hil::gpio::Client for SDCard<'a, A> {
             self.state.set(SpiState::Idle);
             self.alarm_state.set(AlarmState::Idle);
             self.client.map(move |client| {
-                client.error(SdCardError::CardStateChanged as u32);
+                client.error(SdCardError::CardStateChanged);
             });
         }
 
         // either the card is new or gone, in either case it isn't initialized
         self.is_initialized.set(false);
 
-        // disable additional interrupts
-        self.detect_pin.get().map(|pin| {
-            pin.disable_interrupts();
+        self.client.map(move |client| {
+            client.card_detection_changed(self.is_installed());
         });
-
-        // run a timer for 500 ms in order to let the sd card settle
-        self.alarm_state.set(AlarmState::DetectionChange);
-        let delay = self.alarm.ticks_from_ms(500);
-        self.alarm.set_alarm(self.alarm.now(), delay);
     }
 }
 
@@ -1476,6 +1702,34 @@ pub struct App;
 /// Buffer for SD card driver, assigned in board `main.rs` files
 pub const KERNEL_BUFFER_LENGTH: usize = 512;
 
+/// The error code passed to userspace in the second argument of the error
+/// upcall, as a signed 32-bit value. These values are part of the syscall
+/// interface and must not change.
+fn userspace_error_code(error: SdCardError) -> i32 {
+    match error {
+        SdCardError::CardStateChanged => -10001,
+        SdCardError::InitializationFailure => -10002,
+        SdCardError::ReadFailure => -10003,
+        SdCardError::WriteFailure => -10004,
+        SdCardError::TimeoutFailure => -10005,
+        SdCardError::Aborted => -10006,
+    }
+}
+
+/// Copies an application's write data into the block in `kernel_buf`.
+///
+/// Limited to the minimum length between kernel_buf, write_buffer, and 512
+/// (block size). A short write is padded with zeros rather than whatever an
+/// earlier read or write left in kernel_buf. Returns the number of bytes
+/// copied.
+fn copy_write_block(write_buffer: &ReadableProcessSlice, kernel_buf: &mut [u8]) -> usize {
+    let block_len = cmp::min(kernel_buf.len(), 512);
+    let write_len = cmp::min(write_buffer.len(), block_len);
+    write_buffer[..write_len].copy_to_slice(&mut kernel_buf[..write_len]);
+    kernel_buf[write_len..block_len].fill(0);
+    write_len
+}
+
 /// Functions for SDCardDriver
 impl<'a, A: hil::time::Alarm<'a>> SDCardDriver<'a, A> {
     /// Create new SD card userland interface
@@ -1570,10 +1824,12 @@ impl<'a, A: hil::time::Alarm<'a>> SDCardClient for SDCardDriver<'a, A> {
         });
     }
 
-    fn error(&self, error: u32) {
+    fn error(&self, error: SdCardError) {
         self.current_process.map(|process_id| {
             let _ = self.grants.enter(process_id, |_app, kernel_data| {
-                kernel_data.schedule_upcall(0, (4, error as usize, 0)).ok();
+                kernel_data
+                    .schedule_upcall(0, (4, userspace_error_code(error) as usize, 0))
+                    .ok();
             });
         });
     }
@@ -1612,6 +1868,10 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
                 CommandReturn::success_u32(value)
             }
 
+            // a transaction, or the transfer of an aborted one, is still
+            // holding the card
+            2..=4 if self.sdcard.is_busy() => CommandReturn::failure(ErrorCode::BUSY),
+
             // initialize
             2 => match self.sdcard.initialize() {
                 Ok(()) => CommandReturn::success(),
@@ -1621,8 +1881,12 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
             // read_block
             3 => self.kernel_buf.take().map_or(
                 CommandReturn::failure(ErrorCode::BUSY),
-                |kernel_buf| {
-                    CommandReturn::from(self.sdcard.read_blocks(kernel_buf, data as u32, 1))
+                |kernel_buf| match self.sdcard.read_blocks(kernel_buf, data as u32, 1) {
+                    Ok(()) => CommandReturn::success(),
+                    Err((e, kernel_buf)) => {
+                        self.kernel_buf.replace(kernel_buf);
+                        CommandReturn::failure(e)
+                    }
                 },
             ),
 
@@ -1639,18 +1903,15 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
                                         Err(ErrorCode::BUSY),
                                         |kernel_buf| {
                                             // copy over write data from application
-                                            // Limit to minimum length between kernel_buf,
-                                            // write_buffer, and 512 (block size)
-                                            for (kernel_byte, write_byte) in kernel_buf
-                                                .iter_mut()
-                                                .zip(write_buffer.iter())
-                                                .take(512)
-                                            {
-                                                *kernel_byte = write_byte.get();
-                                            }
+                                            copy_write_block(write_buffer, kernel_buf);
 
                                             // begin writing
-                                            self.sdcard.write_blocks(kernel_buf, data as u32, 1)
+                                            self.sdcard
+                                                .write_blocks(kernel_buf, data as u32, 1)
+                                                .map_err(|(e, kernel_buf)| {
+                                                    self.kernel_buf.replace(kernel_buf);
+                                                    e
+                                                })
                                         },
                                     )
                                 })
@@ -1661,6 +1922,29 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
                 CommandReturn::from(result)
             }
 
+            // card info: bit 0 installed, bit 1 initialized, bit 2 block
+            // addressable, along with the block size
+            5 => {
+                if self.sdcard.is_busy() {
+                    return CommandReturn::failure(ErrorCode::BUSY);
+                }
+                let flags = (self.sdcard.is_installed() as u32)
+                    | (self.sdcard.is_initialized() as u32) << 1
+                    | (self.sdcard.is_block_addressable() as u32) << 2;
+                CommandReturn::success_u32_u32(flags, 512)
+            }
+
+            // abort the operation in progress
+            6 => match self.sdcard.abort() {
+                Ok(buffer) => {
+                    if let Some(buffer) = buffer {
+                        self.kernel_buf.replace(buffer);
+                    }
+                    CommandReturn::success()
+                }
+                Err(e) => CommandReturn::failure(e),
+            },
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -1669,3 +1953,393 @@ impl<'a, A: hil::time::Alarm<'a>> SyscallDriver for SDCardDriver<'a, A> {
         self.grants.enter(processid, |_, _| {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use kernel::hil::spi::{ClockPhase, ClockPolarity, SpiMasterClient, SpiMasterDevice};
+    use kernel::hil::time::{Alarm, AlarmClient, Freq1KHz, Ticks, Ticks32, Time};
+    use std::boxed::Box;
+    use std::vec;
+    use std::vec::Vec;
+
+    type Transfer = (&'static mut [u8], Option<&'static mut [u8]>, usize);
+
+    /// What the card sends back on the next one byte transfer.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Next {
+        Nothing,
+        ReadToken(u32),
+        ReadData(u32),
+        WriteData(u32),
+        DataResponse,
+    }
+
+    /// An initialized, block addressable card on a SPI bus. Each transfer is
+    /// answered as it is started, and completes when the test calls
+    /// `complete()`.
+    struct MockCard {
+        blocks: RefCell<Vec<[u8; 512]>>,
+        next: Cell<Next>,
+        pending: Cell<Option<Transfer>>,
+    }
+
+    impl MockCard {
+        fn new() -> Self {
+            MockCard {
+                blocks: RefCell::new(vec![[0; 512]; 4]),
+                next: Cell::new(Next::Nothing),
+                pending: Cell::new(None),
+            }
+        }
+
+        fn answer(&self, write: &[u8], read: &mut [u8], len: usize) {
+            read[..len].fill(0xFF);
+            if len >= 8 && write[..2] == [0xFF, 0xFF] && write[2] & 0xC0 == 0x40 {
+                let arg = u32::from_be_bytes([write[3], write[4], write[5], write[6]]);
+                self.next.set(match write[2] & 0x3F {
+                    17 => Next::ReadToken(arg),
+                    24 => Next::WriteData(arg),
+                    _ => Next::Nothing,
+                });
+                // R1 status
+                read[8] = SUCCESS_STATUS;
+                return;
+            }
+            match self.next.get() {
+                Next::ReadToken(sector) if len == 1 => {
+                    read[0] = DATA_TOKEN;
+                    self.next.set(Next::ReadData(sector));
+                }
+                Next::ReadData(sector) if len == 514 => {
+                    read[..512].copy_from_slice(&self.blocks.borrow()[sector as usize]);
+                    self.next.set(Next::Nothing);
+                }
+                Next::WriteData(sector) if len == 515 && write[0] == DATA_TOKEN => {
+                    self.blocks.borrow_mut()[sector as usize].copy_from_slice(&write[1..513]);
+                    self.next.set(Next::DataResponse);
+                }
+                Next::DataResponse if len == 1 => {
+                    // data accepted
+                    read[0] = 0x05;
+                    self.next.set(Next::Nothing);
+                }
+                _ => {}
+            }
+        }
+
+        fn complete(&self, sdcard: &SDCard<'static, MockAlarm>) {
+            let (write, read, len) = self.pending.take().unwrap();
+            sdcard.read_write_done(write, read, len, Ok(()));
+        }
+
+        /// Completes transfers until the card is left alone.
+        fn run(&self, sdcard: &SDCard<'static, MockAlarm>) {
+            while self.is_pending() {
+                self.complete(sdcard);
+            }
+        }
+
+        fn is_pending(&self) -> bool {
+            let pending = self.pending.take();
+            let is_pending = pending.is_some();
+            self.pending.set(pending);
+            is_pending
+        }
+    }
+
+    impl<'a> SpiMasterDevice<'a> for MockCard {
+        fn set_client(&self, _client: &'a dyn SpiMasterClient) {}
+        fn configure(&self, _: ClockPolarity, _: ClockPhase, _: u32) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn read_write_bytes(
+            &self,
+            write_buffer: &'static mut [u8],
+            mut read_buffer: Option<&'static mut [u8]>,
+            len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8], Option<&'static mut [u8]>)> {
+            if let Some(read_buffer) = read_buffer.as_mut() {
+                self.answer(write_buffer, read_buffer, len);
+            }
+            self.pending.set(Some((write_buffer, read_buffer, len)));
+            Ok(())
+        }
+        fn set_rate(&self, _rate: u32) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn get_rate(&self) -> u32 {
+            0
+        }
+        fn set_polarity(&self, _polarity: ClockPolarity) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn get_polarity(&self) -> ClockPolarity {
+            ClockPolarity::IdleLow
+        }
+        fn set_phase(&self, _phase: ClockPhase) -> Result<(), ErrorCode> {
+            Ok(())
+        }
+        fn get_phase(&self) -> ClockPhase {
+            ClockPhase::SampleLeading
+        }
+    }
+
+    /// An alarm that never fires on its own.
+    #[derive(Default)]
+    struct MockAlarm {
+        expiry: Cell<Option<u32>>,
+    }
+
+    impl Time for MockAlarm {
+        type Ticks = Ticks32;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> Ticks32 {
+            0.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+            self.expiry.set(Some(reference.wrapping_add(dt).into_u32()));
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            self.expiry.get().unwrap_or(0).into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.expiry.set(None);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.expiry.get().is_some()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            1.into()
+        }
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        ReadDone(Vec<u8>, usize),
+        WriteDone,
+        Error(SdCardError),
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        events: RefCell<Vec<Event>>,
+        buffer: Cell<Option<&'static mut [u8]>>,
+    }
+
+    impl SDCardClient for MockClient {
+        fn card_detection_changed(&self, _installed: bool) {}
+        fn init_done(&self, _block_size: u32, _total_size: u64) {}
+        fn read_done(&self, data: &'static mut [u8], len: usize) {
+            self.events
+                .borrow_mut()
+                .push(Event::ReadDone(data[..len].to_vec(), len));
+            self.buffer.set(Some(data));
+        }
+        fn write_done(&self, buffer: &'static mut [u8]) {
+            self.events.borrow_mut().push(Event::WriteDone);
+            self.buffer.set(Some(buffer));
+        }
+        fn error(&self, error: SdCardError) {
+            self.events.borrow_mut().push(Event::Error(error));
+        }
+    }
+
+    fn block() -> &'static mut [u8] {
+        Box::leak(Box::new([0; 512]))
+    }
+
+    fn new_sdcard() -> (
+        &'static MockCard,
+        &'static SDCard<'static, MockAlarm>,
+        &'static MockClient,
+    ) {
+        let card: &'static MockCard = Box::leak(Box::new(MockCard::new()));
+        let alarm: &'static MockAlarm = Box::leak(Box::default());
+        let client: &'static MockClient = Box::leak(Box::default());
+        let sdcard = Box::leak(Box::new(SDCard::new(
+            card,
+            alarm,
+            None,
+            Box::leak(Box::new([0; TXRX_BUFFER_LENGTH])),
+            Box::leak(Box::new([0; TXRX_BUFFER_LENGTH])),
+            SDCardTimeouts::default(),
+        )));
+        sdcard.set_client(client);
+        // skip initialization
+        sdcard.is_initialized.set(true);
+        sdcard.card_type.set(SDCardType::SDv2BlockAddressable);
+        (card, sdcard, client)
+    }
+
+    #[test]
+    fn abort_during_transfer() {
+        let (card, sdcard, client) = new_sdcard();
+        card.blocks.borrow_mut()[1].fill(0x5A);
+
+        sdcard.read_blocks(block(), 1, 1).unwrap();
+        assert!(card.is_pending());
+
+        // The buffer comes straight back, but the SPI transfer still holds
+        // the card's own buffers.
+        assert!(sdcard.abort().unwrap().is_some());
+        assert_eq!(
+            *client.events.borrow(),
+            [Event::Error(SdCardError::Aborted)]
+        );
+        assert!(sdcard.is_busy());
+
+        // Nothing new can start until then, and buffers are handed back.
+        let buffer = block();
+        buffer[0] = 0x42;
+        let (error, buffer) = sdcard.read_blocks(buffer, 1, 1).unwrap_err();
+        assert_eq!(error, ErrorCode::BUSY);
+        assert_eq!(buffer[0], 0x42);
+        let (error, buffer) = sdcard.write_blocks(buffer, 1, 1).unwrap_err();
+        assert_eq!(error, ErrorCode::BUSY);
+        assert_eq!(buffer[0], 0x42);
+        assert_eq!(sdcard.initialize(), Err(ErrorCode::BUSY));
+
+        // Once the transfer completes, the card can be used again.
+        card.run(sdcard);
+        assert!(!sdcard.is_busy());
+        assert_eq!(client.events.borrow().len(), 1);
+        sdcard.read_blocks(buffer, 1, 1).unwrap();
+        card.run(sdcard);
+        assert_eq!(
+            client.events.borrow()[1],
+            Event::ReadDone(vec![0x5A; 512], 512)
+        );
+    }
+
+    #[test]
+    fn unaligned_read_bytes() {
+        let (card, sdcard, client) = new_sdcard();
+        for (i, byte) in card.blocks.borrow_mut()[2].iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+
+        // The last bytes of block 2, at a block addressable byte address.
+        sdcard.read_bytes(block(), 2 * 512 + 509, 3).unwrap();
+        card.run(sdcard);
+        assert_eq!(
+            *client.events.borrow(),
+            [Event::ReadDone(vec![253, 254, 255], 3)]
+        );
+    }
+
+    #[test]
+    fn unaligned_write_bytes() {
+        let (card, sdcard, client) = new_sdcard();
+        card.blocks.borrow_mut()[1].fill(0x11);
+
+        let buffer = block();
+        buffer[..4].copy_from_slice(&[1, 2, 3, 4]);
+        sdcard.write_bytes(buffer, 512 + 301, 4).unwrap();
+        card.run(sdcard);
+        assert_eq!(*client.events.borrow(), [Event::WriteDone]);
+
+        // Only the four bytes changed.
+        let mut expected = [0x11; 512];
+        expected[301..305].copy_from_slice(&[1, 2, 3, 4]);
+        assert_eq!(card.blocks.borrow()[1], expected);
+        assert_eq!(card.blocks.borrow()[0], [0; 512]);
+    }
+
+    #[test]
+    fn range_errors_return_the_buffer() {
+        let (card, sdcard, client) = new_sdcard();
+
+        let buffer = block();
+        buffer[..4].copy_from_slice(&[1, 2, 3, 4]);
+
+        // Empty, and crossing into the next block.
+        let (error, buffer) = sdcard.write_bytes(buffer, 512, 0).unwrap_err();
+        assert_eq!(error, ErrorCode::INVAL);
+        let (error, buffer) = sdcard.write_bytes(buffer, 510, 4).unwrap_err();
+        assert_eq!(error, ErrorCode::INVAL);
+        let (error, buffer) = sdcard.read_bytes(buffer, 510, 4).unwrap_err();
+        assert_eq!(error, ErrorCode::INVAL);
+
+        // Shorter than a block.
+        let short: &'static mut [u8] = Box::leak(Box::new([0; 8]));
+        let (error, _) = sdcard.read_bytes(short, 0, 4).unwrap_err();
+        assert_eq!(error, ErrorCode::SIZE);
+
+        // The card is busy, so the data is left where it was.
+        sdcard.read_blocks(block(), 0, 1).unwrap();
+        let (error, buffer) = sdcard.write_bytes(buffer, 100, 4).unwrap_err();
+        assert_eq!(error, ErrorCode::BUSY);
+        assert_eq!(buffer[..4], [1, 2, 3, 4]);
+        assert_eq!(buffer[100..104], [0; 4]);
+
+        // Not initialized.
+        card.run(sdcard);
+        sdcard.is_initialized.set(false);
+        let (error, buffer) = sdcard.write_bytes(buffer, 100, 4).unwrap_err();
+        assert_eq!(error, ErrorCode::RESERVE);
+        assert_eq!(buffer[..4], [1, 2, 3, 4]);
+        assert_eq!(client.events.borrow().len(), 1);
+    }
+
+    #[test]
+    fn copy_write_block_pads_and_truncates() {
+        let mut kernel_buf = [0xAA; 512];
+
+        // A short write is padded with zeros.
+        let short = [0x5A; 10];
+        assert_eq!(copy_write_block((&short[..]).into(), &mut kernel_buf), 10);
+        assert_eq!(kernel_buf[..10], [0x5A; 10]);
+        assert!(kernel_buf[10..].iter().all(|&byte| byte == 0));
+
+        // An exact block is copied as is.
+        let exact: Vec<u8> = (0..512).map(|i| i as u8).collect();
+        assert_eq!(copy_write_block((&exact[..]).into(), &mut kernel_buf), 512);
+        assert_eq!(kernel_buf[..], exact[..]);
+
+        // Only the first block of a larger buffer is copied.
+        let oversized = [0x33; 600];
+        assert_eq!(
+            copy_write_block((&oversized[..]).into(), &mut kernel_buf),
+            512
+        );
+        assert_eq!(kernel_buf, [0x33; 512]);
+    }
+
+    #[test]
+    fn write_then_read_back() {
+        let (card, sdcard, client) = new_sdcard();
+        let pattern: Vec<u8> = (0..100).map(|i| (i * 7) as u8).collect();
+
+        // As command 4 does with a short application buffer.
+        let kernel_buf = block();
+        kernel_buf.fill(0xAA);
+        copy_write_block((&pattern[..]).into(), kernel_buf);
+        sdcard.write_blocks(kernel_buf, 3, 1).unwrap();
+        card.run(sdcard);
+        assert_eq!(*client.events.borrow(), [Event::WriteDone]);
+
+        let kernel_buf = client.buffer.take().unwrap();
+        kernel_buf.fill(0);
+        sdcard.read_blocks(kernel_buf, 3, 1).unwrap();
+        card.run(sdcard);
+
+        let mut expected = pattern.clone();
+        expected.resize(512, 0);
+        assert_eq!(client.events.borrow()[1], Event::ReadDone(expected, 512));
+    }
+}
@@ -0,0 +1,469 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Signal messages as Morse code or alert patterns on an on/off output.
+//!
+//! The output can be anything that can be switched on and off through the
+//! `hil::led::Led` interface: an LED, a buzzer or a vibration motor driven
+//! from a GPIO pin. Messages are copied into a kernel buffer and played out
+//! in the background using an alarm.
+//!
+//! Morse timing follows the usual conventions, expressed in units: a dot is
+//! one unit on, a dash three units on, elements of a letter are separated by
+//! one unit off, letters by three units off and words by seven units off.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let signaler_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! signaler_alarm.setup();
+//!
+//! let message_buffer = static_init!(
+//!     [u8; capsules_extra::signaler::MAX_MESSAGE_LEN],
+//!     [0; capsules_extra::signaler::MAX_MESSAGE_LEN]
+//! );
+//! let signaler = static_init!(
+//!     capsules_extra::signaler::Signaler<
+//!         'static,
+//!         LedHigh<'static, nrf52840::gpio::GPIOPin>,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     >,
+//!     capsules_extra::signaler::Signaler::new(
+//!         buzzer_pin,
+//!         signaler_alarm,
+//!         message_buffer,
+//!         board_kernel.create_grant(
+//!             capsules_extra::signaler::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! signaler_alarm.set_alarm_client(signaler);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Read-only allow
+//!
+//! - `0`: The ASCII text to send as Morse code.
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called when a message or alert has finished playing, or was
+//!   stopped. The first argument is a status code.
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Send the first `data1` bytes of the allowed buffer as Morse code.
+//!   `data2` is the length of a unit in milliseconds, or 0 for the default.
+//! - `2`: Play the alert pattern with ID `data1`. `data2` is the length of a
+//!   unit in milliseconds, or 0 for the default.
+//! - `3`: Stop playing.
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::led;
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::processbuffer::ReadableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::Signaler as usize;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    pub const MESSAGE: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Recommended length of the message buffer.
+pub const MAX_MESSAGE_LEN: usize = 64;
+
+/// Length of a Morse unit if the app does not choose one.
+pub const DEFAULT_UNIT_MS: u32 = 100;
+
+/// A single step of a signal: the output is switched on or off for a number
+/// of units.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Step {
+    pub on: bool,
+    pub units: u32,
+}
+
+const fn on(units: u32) -> Step {
+    Step { on: true, units }
+}
+
+const fn off(units: u32) -> Step {
+    Step { on: false, units }
+}
+
+/// Library of alert patterns, indexed by their ID.
+///
+/// - `0`: A single short beep.
+/// - `1`: Two short beeps.
+/// - `2`: Three short beeps.
+/// - `3`: A single long beep.
+/// - `4`: Short, short, long ("attention").
+/// - `5`: SOS.
+pub const ALERT_PATTERNS: [&[Step]; 6] = [
+    &[on(1)],
+    &[on(1), off(1), on(1)],
+    &[on(1), off(1), on(1), off(1), on(1)],
+    &[on(7)],
+    &[on(1), off(1), on(1), off(1), on(3)],
+    &[
+        on(1),
+        off(1),
+        on(1),
+        off(1),
+        on(1),
+        off(3),
+        on(3),
+        off(1),
+        on(3),
+        off(1),
+        on(3),
+        off(3),
+        on(1),
+        off(1),
+        on(1),
+        off(1),
+        on(1),
+    ],
+];
+
+/// Returns the Morse code for an ASCII character as a string of `.` and `-`,
+/// or `None` if the character cannot be encoded.
+fn morse_code(c: u8) -> Option<&'static [u8]> {
+    const LETTERS: [&[u8]; 26] = [
+        b".-", b"-...", b"-.-.", b"-..", b".", b"..-.", b"--.", b"....", b"..", b".---", b"-.-",
+        b".-..", b"--", b"-.", b"---", b".--.", b"--.-", b".-.", b"...", b"-", b"..-", b"...-",
+        b".--", b"-..-", b"-.--", b"--..",
+    ];
+    const DIGITS: [&[u8]; 10] = [
+        b"-----", b".----", b"..---", b"...--", b"....-", b".....", b"-....", b"--...", b"---..",
+        b"----.",
+    ];
+
+    match c {
+        b'a'..=b'z' => Some(LETTERS[(c - b'a') as usize]),
+        b'A'..=b'Z' => Some(LETTERS[(c - b'A') as usize]),
+        b'0'..=b'9' => Some(DIGITS[(c - b'0') as usize]),
+        b'.' => Some(b".-.-.-"),
+        b',' => Some(b"--..--"),
+        b'?' => Some(b"..--.."),
+        b'/' => Some(b"-..-."),
+        b'=' => Some(b"-...-"),
+        _ => None,
+    }
+}
+
+/// Position while encoding a message as Morse code.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct MorseCursor {
+    /// Index of the character being encoded.
+    character: usize,
+    /// Index of the next element of that character.
+    element: usize,
+    /// Gap to insert before the next element.
+    gap: Option<u32>,
+}
+
+/// Returns the next step of `message` encoded as Morse code and advances
+/// `cursor`, or `None` once the message is complete. Characters that cannot
+/// be encoded are skipped, spaces separate words.
+fn next_morse_step(message: &[u8], cursor: &mut MorseCursor) -> Option<Step> {
+    if let Some(gap) = cursor.gap.take() {
+        return Some(off(gap));
+    }
+
+    // Skip anything before the first character we can encode.
+    while morse_code(*message.get(cursor.character)?).is_none() {
+        cursor.character += 1;
+    }
+
+    let code = morse_code(message[cursor.character])?;
+    let units = if code[cursor.element] == b'.' { 1 } else { 3 };
+    cursor.element += 1;
+
+    if cursor.element < code.len() {
+        // Gap between elements of the same character.
+        cursor.gap = Some(1);
+    } else {
+        // Move on to the next character, noting whether we cross a word
+        // boundary on the way.
+        cursor.character += 1;
+        cursor.element = 0;
+        let mut word_gap = false;
+        while let Some(&c) = message.get(cursor.character) {
+            if morse_code(c).is_some() {
+                break;
+            }
+            word_gap |= c == b' ';
+            cursor.character += 1;
+        }
+        if cursor.character < message.len() {
+            cursor.gap = Some(if word_gap { 7 } else { 3 });
+        }
+    }
+
+    Some(on(units))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Signal {
+    /// Sending the message in the buffer as Morse code.
+    Morse(MorseCursor),
+    /// Playing an alert pattern, with the index of the next step.
+    Alert(usize, usize),
+}
+
+#[derive(Default)]
+pub struct App;
+
+pub struct Signaler<'a, L: led::Led, A: time::Alarm<'a>> {
+    output: &'a L,
+    alarm: &'a A,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    /// The app the current signal is played for.
+    active_app: OptionalCell<ProcessId>,
+    message: TakeCell<'static, [u8]>,
+    message_len: Cell<usize>,
+    signal: OptionalCell<Signal>,
+    unit_ms: Cell<u32>,
+}
+
+impl<'a, L: led::Led, A: time::Alarm<'a>> Signaler<'a, L, A> {
+    pub fn new(
+        output: &'a L,
+        alarm: &'a A,
+        message: &'static mut [u8],
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    ) -> Signaler<'a, L, A> {
+        output.init();
+        Signaler {
+            output,
+            alarm,
+            apps: grant,
+            active_app: OptionalCell::empty(),
+            message: TakeCell::new(message),
+            message_len: Cell::new(0),
+            signal: OptionalCell::empty(),
+            unit_ms: Cell::new(DEFAULT_UNIT_MS),
+        }
+    }
+
+    /// Sends `text` as Morse code. `text` is copied, so it does not need to
+    /// outlive the call.
+    pub fn send_morse(&self, text: &[u8], unit_ms: u32) -> Result<(), ErrorCode> {
+        if self.signal.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        let len = self.message.map_or(Err(ErrorCode::NOMEM), |message| {
+            let len = cmp::min(text.len(), message.len());
+            message[..len].copy_from_slice(&text[..len]);
+            Ok(len)
+        })?;
+        self.message_len.set(len);
+        self.start(Signal::Morse(MorseCursor::default()), unit_ms)
+    }
+
+    /// Plays the alert pattern with ID `pattern_id` from `ALERT_PATTERNS`.
+    pub fn play_alert(&self, pattern_id: usize, unit_ms: u32) -> Result<(), ErrorCode> {
+        if self.signal.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        if pattern_id >= ALERT_PATTERNS.len() {
+            return Err(ErrorCode::INVAL);
+        }
+        self.start(Signal::Alert(pattern_id, 0), unit_ms)
+    }
+
+    /// Stops the current signal and switches the output off.
+    pub fn stop(&self) -> Result<(), ErrorCode> {
+        if self.signal.is_none() {
+            return Err(ErrorCode::OFF);
+        }
+        let _ = self.alarm.disarm();
+        self.finish(Err(ErrorCode::CANCEL));
+        Ok(())
+    }
+
+    fn start(&self, signal: Signal, unit_ms: u32) -> Result<(), ErrorCode> {
+        self.unit_ms.set(if unit_ms == 0 {
+            DEFAULT_UNIT_MS
+        } else {
+            unit_ms
+        });
+        self.signal.set(signal);
+        if self.next_step() {
+            Ok(())
+        } else {
+            // Nothing to play, e.g. the message only contained spaces.
+            self.signal.clear();
+            Err(ErrorCode::INVAL)
+        }
+    }
+
+    /// Moves on to the next step of the current signal. Returns `false` if
+    /// the signal is complete.
+    fn next_step(&self) -> bool {
+        let step = self.signal.take().and_then(|signal| match signal {
+            Signal::Morse(mut cursor) => self.message.map_or(None, |message| {
+                let step = next_morse_step(&message[..self.message_len.get()], &mut cursor);
+                self.signal.set(Signal::Morse(cursor));
+                step
+            }),
+            Signal::Alert(id, index) => {
+                self.signal.set(Signal::Alert(id, index + 1));
+                ALERT_PATTERNS[id].get(index).copied()
+            }
+        });
+
+        match step {
+            Some(step) => {
+                if step.on {
+                    self.output.on();
+                } else {
+                    self.output.off();
+                }
+                let duration_ms = step.units.saturating_mul(self.unit_ms.get());
+                self.alarm
+                    .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(duration_ms));
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn finish(&self, status: Result<(), ErrorCode>) {
+        self.output.off();
+        self.signal.clear();
+        self.active_app.take().map(|processid| {
+            let _ = self.apps.enter(processid, |_app, kernel_data| {
+                kernel_data
+                    .schedule_upcall(0, (kernel::errorcode::into_statuscode(status), 0, 0))
+                    .ok();
+            });
+        });
+    }
+
+    /// Checks whether `processid` may use the signaler, which is the case if
+    /// no signal is playing or the signal is played for this app.
+    fn is_valid_app(&self, processid: ProcessId) -> bool {
+        self.signal.is_none()
+            || self
+                .active_app
+                .map_or(true, |owning_app| owning_app == processid)
+    }
+}
+
+impl<'a, L: led::Led, A: time::Alarm<'a>> time::AlarmClient for Signaler<'a, L, A> {
+    fn alarm(&self) {
+        if self.signal.is_some() && !self.next_step() {
+            self.finish(Ok(()));
+        }
+    }
+}
+
+impl<'a, L: led::Led, A: time::Alarm<'a>> SyscallDriver for Signaler<'a, L, A> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            return CommandReturn::success();
+        }
+        if !self.is_valid_app(processid) {
+            return CommandReturn::failure(ErrorCode::RESERVE);
+        }
+
+        let res = match command_num {
+            // Send Morse code
+            1 => self
+                .apps
+                .enter(processid, |_app, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::MESSAGE)
+                        .and_then(|message| {
+                            message.enter(|message| {
+                                let len = cmp::min(data1, message.len());
+                                // Copy into a local buffer, the app buffer is
+                                // a slice of `Cell`s.
+                                let mut text = [0; MAX_MESSAGE_LEN];
+                                let len = cmp::min(len, text.len());
+                                message[..len].copy_to_slice(&mut text[..len]);
+                                self.send_morse(&text[..len], data2 as u32)
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))
+                })
+                .unwrap_or_else(|err| Err(err.into())),
+            // Play an alert pattern
+            2 => self.play_alert(data1, data2 as u32),
+            // Stop
+            3 => self.stop(),
+            _ => return CommandReturn::failure(ErrorCode::NOSUPPORT),
+        };
+
+        if res.is_ok() && command_num != 3 {
+            self.active_app.set(processid);
+        }
+        res.into()
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checks that `message` encodes to exactly `expected`.
+    fn check_encoding(message: &[u8], expected: &[Step]) {
+        let mut cursor = MorseCursor::default();
+        for step in expected {
+            assert_eq!(next_morse_step(message, &mut cursor), Some(*step));
+        }
+        assert_eq!(next_morse_step(message, &mut cursor), None);
+    }
+
+    #[test]
+    fn morse_sos() {
+        check_encoding(b"SOS", ALERT_PATTERNS[5]);
+    }
+
+    #[test]
+    fn morse_word_gap() {
+        check_encoding(b"e  t", &[on(1), off(7), on(3)]);
+    }
+
+    #[test]
+    fn morse_skips_unknown() {
+        check_encoding(b" #e", &[on(1)]);
+        check_encoding(b"e#t ", &[on(1), off(3), on(3)]);
+        check_encoding(b"  ", &[]);
+    }
+}
@@ -0,0 +1,522 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Software (bit-banged) SPI master over GPIO pins.
+//!
+//! This lets boards without a usable SPI controller run SPI devices, such as
+//! SD cards or flash chips, from plain GPIO pins. Every clock edge is driven
+//! from an alarm, so the bus runs slowly: each bit takes two alarm periods,
+//! and the achievable rate is bounded by the alarm's resolution.
+//!
+//! All four clock polarity and phase modes are supported, and bytes can be
+//! sent MSB or LSB first (see [`SpiBitBang::set_data_order`]). The chip
+//! select is an active low GPIO pin, as with the nRF52 SPIM.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let spi = components::spi_bitbang::SpiBitBangComponent::new(
+//!     &gpio_port[MOSI_PIN],
+//!     &gpio_port[MISO_PIN],
+//!     &gpio_port[SCK_PIN],
+//!     mux_alarm,
+//! )
+//! .finalize(components::spi_bitbang_component_static!(nrf52832::rtc::Rtc));
+//! let mux_spi = components::spi::SpiMuxComponent::new(spi).finalize(
+//!     components::spi_mux_component_static!(
+//!         capsules_extra::spi_bitbang::SpiBitBang<'static, VirtualMuxAlarm<'static, Rtc>>
+//!     ),
+//! );
+//! ```
+
+use core::cell::Cell;
+use core::cmp;
+
+use kernel::hil::gpio;
+use kernel::hil::spi::{ClockPhase, ClockPolarity, DataOrder, SpiMaster, SpiMasterClient};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Rate used until `set_rate` is called.
+pub const DEFAULT_RATE: u32 = 10_000;
+
+/// The clock edge performed the next time the alarm fires.
+#[derive(Clone, Copy, PartialEq)]
+enum Edge {
+    Idle,
+    Leading,
+    Trailing,
+}
+
+pub struct SpiBitBang<'a, A: Alarm<'a>> {
+    mosi: &'a dyn gpio::Pin,
+    miso: &'a dyn gpio::Pin,
+    sclk: &'a dyn gpio::Pin,
+    chip_select: OptionalCell<&'a dyn gpio::Pin>,
+    alarm: &'a A,
+    client: OptionalCell<&'a dyn SpiMasterClient>,
+    half_period_us: Cell<u32>,
+    polarity: Cell<ClockPolarity>,
+    phase: Cell<ClockPhase>,
+    data_order: Cell<DataOrder>,
+    hold_low: Cell<bool>,
+    write_buffer: TakeCell<'static, [u8]>,
+    read_buffer: TakeCell<'static, [u8]>,
+    len: Cell<usize>,
+    edge: Cell<Edge>,
+    /// Byte being transferred, and bit within it.
+    index: Cell<usize>,
+    bit: Cell<u8>,
+    /// Bits read so far for the current byte.
+    received: Cell<u8>,
+}
+
+impl<'a, A: Alarm<'a>> SpiBitBang<'a, A> {
+    pub fn new(
+        mosi: &'a dyn gpio::Pin,
+        miso: &'a dyn gpio::Pin,
+        sclk: &'a dyn gpio::Pin,
+        alarm: &'a A,
+    ) -> SpiBitBang<'a, A> {
+        SpiBitBang {
+            mosi,
+            miso,
+            sclk,
+            chip_select: OptionalCell::empty(),
+            alarm,
+            client: OptionalCell::empty(),
+            half_period_us: Cell::new(Self::half_period_us(DEFAULT_RATE)),
+            polarity: Cell::new(ClockPolarity::IdleLow),
+            phase: Cell::new(ClockPhase::SampleLeading),
+            data_order: Cell::new(DataOrder::MSBFirst),
+            hold_low: Cell::new(false),
+            write_buffer: TakeCell::empty(),
+            read_buffer: TakeCell::empty(),
+            len: Cell::new(0),
+            edge: Cell::new(Edge::Idle),
+            index: Cell::new(0),
+            bit: Cell::new(0),
+            received: Cell::new(0),
+        }
+    }
+
+    /// Sets whether bytes are sent most or least significant bit first.
+    pub fn set_data_order(&self, order: DataOrder) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.data_order.set(order);
+        Ok(())
+    }
+
+    pub fn get_data_order(&self) -> DataOrder {
+        self.data_order.get()
+    }
+
+    fn half_period_us(rate: u32) -> u32 {
+        cmp::max(1, 500_000 / cmp::max(1, rate))
+    }
+
+    fn mask(&self) -> u8 {
+        match self.data_order.get() {
+            DataOrder::MSBFirst => 0x80 >> self.bit.get(),
+            DataOrder::LSBFirst => 1 << self.bit.get(),
+        }
+    }
+
+    fn set_clock(&self, active: bool) {
+        let high = active == (self.polarity.get() == ClockPolarity::IdleLow);
+        if high {
+            self.sclk.set();
+        } else {
+            self.sclk.clear();
+        }
+    }
+
+    fn shift_out(&self) {
+        let index = self.index.get();
+        let high = self
+            .write_buffer
+            .map_or(false, |buffer| buffer[index] & self.mask() != 0);
+        if high {
+            self.mosi.set();
+        } else {
+            self.mosi.clear();
+        }
+    }
+
+    fn sample(&self) {
+        if self.miso.read() {
+            self.received.set(self.received.get() | self.mask());
+        }
+    }
+
+    fn wait(&self, edge: Edge) {
+        self.edge.set(edge);
+        let delay = self.alarm.ticks_from_us(self.half_period_us.get());
+        self.alarm.set_alarm(self.alarm.now(), delay);
+    }
+
+    /// With the clock idle, starts the current bit.
+    fn start_bit(&self) {
+        if self.phase.get() == ClockPhase::SampleLeading {
+            self.shift_out();
+        }
+        self.wait(Edge::Leading);
+    }
+
+    fn finish(&self) {
+        self.edge.set(Edge::Idle);
+        if !self.hold_low.get() {
+            self.chip_select.map(|cs| cs.set());
+        }
+        let len = self.len.get();
+        let read_buffer = self.read_buffer.take();
+        self.write_buffer.take().map(|write_buffer| {
+            self.client
+                .map(move |client| client.read_write_done(write_buffer, read_buffer, len, Ok(())));
+        });
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for SpiBitBang<'a, A> {
+    fn alarm(&self) {
+        match self.edge.get() {
+            Edge::Idle => {}
+            Edge::Leading => {
+                self.set_clock(true);
+                match self.phase.get() {
+                    ClockPhase::SampleLeading => self.sample(),
+                    ClockPhase::SampleTrailing => self.shift_out(),
+                }
+                self.wait(Edge::Trailing);
+            }
+            Edge::Trailing => {
+                self.set_clock(false);
+                if self.phase.get() == ClockPhase::SampleTrailing {
+                    self.sample();
+                }
+
+                let bit = self.bit.get() + 1;
+                if bit < 8 {
+                    self.bit.set(bit);
+                    self.start_bit();
+                    return;
+                }
+
+                // The byte is complete.
+                let index = self.index.get();
+                let received = self.received.get();
+                self.read_buffer.map(|buffer| buffer[index] = received);
+                self.bit.set(0);
+                self.received.set(0);
+                if index + 1 < self.len.get() {
+                    self.index.set(index + 1);
+                    self.start_bit();
+                } else {
+                    self.finish();
+                }
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> SpiMaster<'a> for SpiBitBang<'a, A> {
+    type ChipSelect = &'a dyn gpio::Pin;
+
+    fn init(&self) -> Result<(), ErrorCode> {
+        self.mosi.make_output();
+        self.sclk.make_output();
+        self.miso.make_input();
+        self.set_clock(false);
+        Ok(())
+    }
+
+    fn set_client(&self, client: &'a dyn SpiMasterClient) {
+        self.client.set(client);
+    }
+
+    fn is_busy(&self) -> bool {
+        self.edge.get() != Edge::Idle
+    }
+
+    fn read_write_bytes(
+        &self,
+        write_buffer: &'static mut [u8],
+        read_buffer: Option<&'static mut [u8]>,
+        len: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8], Option<&'static mut [u8]>)> {
+        if self.is_busy() {
+            return Err((ErrorCode::BUSY, write_buffer, read_buffer));
+        }
+        if self.chip_select.is_none() {
+            return Err((ErrorCode::NODEVICE, write_buffer, read_buffer));
+        }
+        let len = cmp::min(
+            len,
+            read_buffer.as_ref().map_or(write_buffer.len(), |rb| {
+                cmp::min(rb.len(), write_buffer.len())
+            }),
+        );
+        if len == 0 {
+            return Err((ErrorCode::SIZE, write_buffer, read_buffer));
+        }
+
+        self.write_buffer.replace(write_buffer);
+        if let Some(read_buffer) = read_buffer {
+            self.read_buffer.replace(read_buffer);
+        }
+        self.len.set(len);
+        self.index.set(0);
+        self.bit.set(0);
+        self.received.set(0);
+
+        self.set_clock(false);
+        self.chip_select.map(|cs| cs.clear());
+        self.start_bit();
+        Ok(())
+    }
+
+    fn write_byte(&self, _val: u8) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn read_byte(&self) -> Result<u8, ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn read_write_byte(&self, _val: u8) -> Result<u8, ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
+    fn specify_chip_select(&self, cs: Self::ChipSelect) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        cs.make_output();
+        cs.set();
+        self.chip_select.set(cs);
+        Ok(())
+    }
+
+    fn set_rate(&self, rate: u32) -> Result<u32, ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.half_period_us.set(Self::half_period_us(rate));
+        Ok(self.get_rate())
+    }
+
+    fn get_rate(&self) -> u32 {
+        500_000 / self.half_period_us.get()
+    }
+
+    fn set_polarity(&self, polarity: ClockPolarity) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.polarity.set(polarity);
+        self.set_clock(false);
+        Ok(())
+    }
+
+    fn get_polarity(&self) -> ClockPolarity {
+        self.polarity.get()
+    }
+
+    fn set_phase(&self, phase: ClockPhase) -> Result<(), ErrorCode> {
+        if self.is_busy() {
+            return Err(ErrorCode::BUSY);
+        }
+        self.phase.set(phase);
+        Ok(())
+    }
+
+    fn get_phase(&self) -> ClockPhase {
+        self.phase.get()
+    }
+
+    fn hold_low(&self) {
+        self.hold_low.set(true);
+    }
+
+    fn release_low(&self) {
+        self.hold_low.set(false);
+        if !self.is_busy() {
+            self.chip_select.map(|cs| cs.set());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use kernel::hil::gpio::{Configuration, Configure, FloatingState, Input, Output};
+    use kernel::hil::time::{Freq1MHz, Ticks32, Time};
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// A pin driving, or reading, a shared wire.
+    struct MockPin<'a> {
+        wire: &'a Cell<bool>,
+    }
+
+    impl Configure for MockPin<'_> {
+        fn configuration(&self) -> Configuration {
+            Configuration::InputOutput
+        }
+        fn make_output(&self) -> Configuration {
+            Configuration::InputOutput
+        }
+        fn disable_output(&self) -> Configuration {
+            Configuration::InputOutput
+        }
+        fn make_input(&self) -> Configuration {
+            Configuration::InputOutput
+        }
+        fn disable_input(&self) -> Configuration {
+            Configuration::InputOutput
+        }
+        fn deactivate_to_low_power(&self) {}
+        fn set_floating_state(&self, _state: FloatingState) {}
+        fn floating_state(&self) -> FloatingState {
+            FloatingState::PullNone
+        }
+    }
+
+    impl Input for MockPin<'_> {
+        fn read(&self) -> bool {
+            self.wire.get()
+        }
+    }
+
+    impl Output for MockPin<'_> {
+        fn set(&self) {
+            self.wire.set(true);
+        }
+        fn clear(&self) {
+            self.wire.set(false);
+        }
+        fn toggle(&self) -> bool {
+            self.wire.set(!self.wire.get());
+            self.wire.get()
+        }
+    }
+
+    /// An alarm that only fires when the test calls `fire()`.
+    #[derive(Default)]
+    struct MockAlarm {
+        armed: Cell<bool>,
+    }
+
+    impl Time for MockAlarm {
+        type Ticks = Ticks32;
+        type Frequency = Freq1MHz;
+
+        fn now(&self) -> Ticks32 {
+            0.into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, _reference: Self::Ticks, _dt: Self::Ticks) {
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            0.into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            0.into()
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        read_buffer: Cell<Option<&'static mut [u8]>>,
+        len: Cell<usize>,
+    }
+
+    impl SpiMasterClient for MockClient {
+        fn read_write_done(
+            &self,
+            _write_buffer: &'static mut [u8],
+            read_buffer: Option<&'static mut [u8]>,
+            len: usize,
+            _status: Result<(), ErrorCode>,
+        ) {
+            self.read_buffer.set(read_buffer);
+            self.len.set(len);
+        }
+    }
+
+    /// Runs a transfer with MOSI tied to MISO and returns the bytes read.
+    fn loopback(polarity: ClockPolarity, phase: ClockPhase, order: DataOrder) -> Vec<u8> {
+        let data = Box::leak(Box::new(Cell::new(false)));
+        let clock = Box::leak(Box::new(Cell::new(false)));
+        let cs = Box::leak(Box::new(Cell::new(false)));
+        let alarm = Box::leak(Box::<MockAlarm>::default());
+        let client = Box::leak(Box::<MockClient>::default());
+        let spi = SpiBitBang::new(
+            Box::leak(Box::new(MockPin { wire: data })),
+            Box::leak(Box::new(MockPin { wire: data })),
+            Box::leak(Box::new(MockPin { wire: clock })),
+            alarm,
+        );
+        spi.set_client(client);
+        spi.init().unwrap();
+        spi.specify_chip_select(Box::leak(Box::new(MockPin { wire: cs })))
+            .unwrap();
+        spi.set_polarity(polarity).unwrap();
+        spi.set_phase(phase).unwrap();
+        spi.set_data_order(order).unwrap();
+        let idle = clock.get();
+        assert_eq!(idle, polarity == ClockPolarity::IdleHigh);
+
+        let write = Box::leak(Box::new([0xA5, 0x3C, 0x01, 0x80]));
+        let read = Box::leak(Box::new([0; 4]));
+        assert!(spi.read_write_bytes(write, Some(read), 4).is_ok());
+        assert!(!cs.get());
+        let mut edges = 0;
+        while alarm.armed.get() {
+            alarm.armed.set(false);
+            spi.alarm();
+            edges += 1;
+        }
+
+        assert_eq!(edges, 4 * 8 * 2);
+        assert!(!spi.is_busy());
+        assert!(cs.get());
+        assert_eq!(clock.get(), idle);
+        assert_eq!(client.len.get(), 4);
+        client.read_buffer.take().unwrap().to_vec()
+    }
+
+    #[test]
+    fn loopback_all_modes() {
+        for polarity in [ClockPolarity::IdleLow, ClockPolarity::IdleHigh] {
+            for phase in [ClockPhase::SampleLeading, ClockPhase::SampleTrailing] {
+                for order in [DataOrder::MSBFirst, DataOrder::LSBFirst] {
+                    assert_eq!(loopback(polarity, phase, order), [0xA5, 0x3C, 0x01, 0x80]);
+                }
+            }
+        }
+    }
+}
@@ -27,11 +27,14 @@
 //!
 //! * `0`: check whether the driver exists
 //! * `1`: read the temperature
+//! * `2`: read the temperature averaged over `data1` samples, taken back to
+//!   back. `data1` must be between 1 and [`MAX_SAMPLES`].
 //!
 //!
 //! The possible return from the 'command' system call indicates the following:
 //!
 //! * `Ok(())`:    The operation has been successful.
+//! * `BUSY`:      An averaged read was requested while a read is ongoing.
 //! * `NOSUPPORT`: Invalid `cmd`.
 //! * `NOMEM`:     Insufficient memory available.
 //! * `INVAL`:     Invalid address of the buffer or other error.
@@ -54,6 +57,16 @@
 //!
 //! kernel::hil::sensors::TemperatureDriver::set_client(si7021, temp);
 //! ```
+//!
+//! Readings can be corrected with a linear [`Calibration`] before they are
+//! returned to userspace, for sensors with a known per-unit error:
+//!
+//! ```rust,ignore
+//! temp.set_calibration(capsules_extra::temperature::Calibration {
+//!     scale: 1000,
+//!     offset: -150,
+//! });
+//! ```
 
 use core::cell::Cell;
 
@@ -66,6 +79,40 @@ use kernel::{ErrorCode, ProcessId};
 use capsules_core::driver;
 pub const DRIVER_NUM: usize = driver::NUM::Temperature as usize;
 
+/// The largest number of samples an averaged read can take, to bound how long
+/// the sensor is kept busy.
+pub const MAX_SAMPLES: usize = 16;
+
+/// Value of [`Calibration::scale`] that leaves readings unscaled.
+pub const CALIBRATION_SCALE_ONE: i32 = 1000;
+
+/// Linear correction of readings, in hundredths of a degree Celsius:
+/// `reading * scale / CALIBRATION_SCALE_ONE + offset`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Calibration {
+    /// Scale in thousandths.
+    pub scale: i32,
+    /// Offset in hundredths of a degree Celsius.
+    pub offset: i32,
+}
+
+impl Calibration {
+    /// Returns readings unchanged.
+    pub const IDENTITY: Calibration = Calibration {
+        scale: CALIBRATION_SCALE_ONE,
+        offset: 0,
+    };
+
+    /// Applies the calibration to a reading, saturating at the range of
+    /// `i32`.
+    pub fn apply(&self, reading: i32) -> i32 {
+        let calibrated = i64::from(reading) * i64::from(self.scale)
+            / i64::from(CALIBRATION_SCALE_ONE)
+            + i64::from(self.offset);
+        calibrated.clamp(i64::from(i32::MIN), i64::from(i32::MAX)) as i32
+    }
+}
+
 #[derive(Default)]
 pub struct App {
     subscribed: bool,
@@ -75,6 +122,12 @@ pub struct TemperatureSensor<'a, T: hil::sensors::TemperatureDriver<'a>> {
     driver: &'a T,
     apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
     busy: Cell<bool>,
+    /// Number of samples to average for the ongoing read.
+    samples: Cell<usize>,
+    /// Number of samples taken so far, and their sum.
+    taken: Cell<usize>,
+    sum: Cell<i64>,
+    calibration: Cell<Calibration>,
 }
 
 impl<'a, T: hil::sensors::TemperatureDriver<'a>> TemperatureSensor<'a, T> {
@@ -86,9 +139,29 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> TemperatureSensor<'a, T> {
             driver,
             apps: grant,
             busy: Cell::new(false),
+            samples: Cell::new(1),
+            taken: Cell::new(0),
+            sum: Cell::new(0),
+            calibration: Cell::new(Calibration::IDENTITY),
         }
     }
 
+    /// Sets the calibration applied to readings. Readings are returned
+    /// unchanged until this is called.
+    pub fn set_calibration(&self, calibration: Calibration) {
+        self.calibration.set(calibration);
+    }
+
+    fn start_read(&self, samples: usize) -> Result<(), ErrorCode> {
+        self.samples.set(samples);
+        self.taken.set(0);
+        self.sum.set(0);
+        self.busy.set(true);
+        self.driver
+            .read_temperature()
+            .inspect_err(|_| self.busy.set(false))
+    }
+
     fn enqueue_command(&self, processid: ProcessId) -> CommandReturn {
         self.apps
             .enter(processid, |app, _| {
@@ -98,11 +171,7 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> TemperatureSensor<'a, T> {
 
                 // If we do not already have an ongoing read, start one now.
                 if !self.busy.get() {
-                    self.busy.set(true);
-                    match self.driver.read_temperature() {
-                        Ok(()) => CommandReturn::success(),
-                        Err(e) => CommandReturn::failure(e),
-                    }
+                    self.start_read(1).into()
                 } else {
                     // Just return success and we will get the upcall when the
                     // temperature read is ready.
@@ -111,19 +180,54 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> TemperatureSensor<'a, T> {
             })
             .unwrap_or_else(|err| CommandReturn::failure(err.into()))
     }
+
+    fn enqueue_average(&self, samples: usize, processid: ProcessId) -> CommandReturn {
+        if samples == 0 || samples > MAX_SAMPLES {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+        // The samples of an ongoing read can not be extended.
+        if self.busy.get() {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        self.apps
+            .enter(processid, |app, _| {
+                app.subscribed = true;
+                self.start_read(samples).into()
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
 }
 
 impl<'a, T: hil::sensors::TemperatureDriver<'a>> hil::sensors::TemperatureClient
     for TemperatureSensor<'a, T>
 {
     fn callback(&self, temp_val: Result<i32, ErrorCode>) {
+        let temp_val = temp_val.map(|temp_val| {
+            self.sum.set(self.sum.get() + temp_val as i64);
+            self.taken.set(self.taken.get() + 1);
+        });
+
+        // Chain the next sample of an averaged read.
+        if temp_val.is_ok()
+            && self.taken.get() < self.samples.get()
+            && self.driver.read_temperature().is_ok()
+        {
+            return;
+        }
+
         // We completed the operation so we clear the busy flag in case we get
         // another measurement request.
         self.busy.set(false);
 
-        // Return the temperature reading to any waiting client.
-        if let Ok(temp_val) = temp_val {
+        // Return the mean of the samples taken to any waiting client, even if
+        // a later sample failed.
+        let taken = self.taken.get();
+        if taken > 0 {
             // TODO: forward error conditions
+            let temp_val = self
+                .calibration
+                .get()
+                .apply((self.sum.get() / taken as i64) as i32);
             for cntr in self.apps.iter() {
                 cntr.enter(|app, upcalls| {
                     if app.subscribed {
@@ -140,7 +244,7 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> SyscallDriver for TemperatureSe
     fn command(
         &self,
         command_num: usize,
-        _: usize,
+        data1: usize,
         _: usize,
         processid: ProcessId,
     ) -> CommandReturn {
@@ -150,6 +254,10 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> SyscallDriver for TemperatureSe
 
             // read temperature
             1 => self.enqueue_command(processid),
+
+            // read averaged temperature
+            2 => self.enqueue_average(data1, processid),
+
             _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
         }
     }
@@ -158,3 +266,34 @@ impl<'a, T: hil::sensors::TemperatureDriver<'a>> SyscallDriver for TemperatureSe
         self.apps.enter(processid, |_, _| {})
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calibration() {
+        assert_eq!(Calibration::IDENTITY.apply(2150), 2150);
+        assert_eq!(Calibration::IDENTITY.apply(-475), -475);
+
+        let offset = Calibration {
+            scale: CALIBRATION_SCALE_ONE,
+            offset: -150,
+        };
+        assert_eq!(offset.apply(2150), 2000);
+
+        let scaled = Calibration {
+            scale: 1020,
+            offset: 25,
+        };
+        assert_eq!(scaled.apply(2000), 2065);
+        assert_eq!(scaled.apply(-1000), -995);
+
+        let extreme = Calibration {
+            scale: 2 * CALIBRATION_SCALE_ONE,
+            offset: 0,
+        };
+        assert_eq!(extreme.apply(i32::MAX), i32::MAX);
+        assert_eq!(extreme.apply(i32::MIN), i32::MIN);
+    }
+}
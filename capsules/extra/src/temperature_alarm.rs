@@ -0,0 +1,316 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Provides userspace with access to temperature sensors, with optional
+//! threshold alarms.
+//!
+//! This capsule is a superset of [`crate::temperature::TemperatureSensor`]
+//! and uses the same driver number. In addition to one-shot reads, an app can
+//! register a low and a high bound. While any app has bounds registered, the
+//! capsule samples the sensor periodically and notifies apps when a reading
+//! crosses one of their bounds, so the app does not need to poll.
+//!
+//! To avoid a stream of upcalls for readings hovering around a bound, a bound
+//! is re-armed only once the temperature has moved back past it by
+//! [`HYSTERESIS`].
+//!
+//! Userspace Interface
+//! -------------------
+//!
+//! ### `subscribe` System Call
+//!
+//! * `0`: Upcall for one-shot readings: `(temperature, 0, 0)`.
+//! * `1`: Upcall for threshold crossings: `(temperature, crossing, 0)`, where
+//!   `crossing` is `1` if the high bound was reached and `2` if the low bound
+//!   was reached.
+//!
+//! Temperatures are in hundredths of degrees centigrade.
+//!
+//! ### `command` System Call
+//!
+//! * `0`: check whether the driver exists
+//! * `1`: read the temperature
+//! * `2`: set the threshold; `data1` is the low bound and `data2` the high
+//!   bound, both as signed 32-bit values. Returns `INVAL` if the low bound is
+//!   not below the high bound.
+//! * `3`: clear the threshold
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let temp = components::temperature::TemperatureAlarmComponent::new(
+//!     board_kernel,
+//!     capsules_extra::temperature::DRIVER_NUM,
+//!     &base_peripherals.temp,
+//!     mux_alarm,
+//!     1000,
+//! )
+//! .finalize(components::temperature_alarm_component_static!(
+//!     nrf52832::temperature::Temp,
+//!     nrf52832::rtc::Rtc
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil;
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+pub use crate::temperature::DRIVER_NUM;
+
+/// How far, in hundredths of degrees centigrade, a reading has to move back
+/// past a bound before the bound can trigger again.
+pub const HYSTERESIS: i32 = 50;
+
+mod upcall {
+    pub const READING: usize = 0;
+    pub const THRESHOLD: usize = 1;
+    pub const COUNT: u8 = 2;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Level {
+    Normal,
+    High,
+    Low,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Crossing {
+    High = 1,
+    Low = 2,
+}
+
+#[derive(Clone, Copy)]
+struct Threshold {
+    low: i32,
+    high: i32,
+    level: Level,
+}
+
+/// Returns the new level for a reading of `temp`, and the crossing to report,
+/// if any.
+fn evaluate(level: Level, low: i32, high: i32, temp: i32) -> (Level, Option<Crossing>) {
+    match level {
+        Level::Normal if temp >= high => (Level::High, Some(Crossing::High)),
+        Level::Normal if temp <= low => (Level::Low, Some(Crossing::Low)),
+        Level::High if temp < high - HYSTERESIS => evaluate(Level::Normal, low, high, temp),
+        Level::Low if temp > low + HYSTERESIS => evaluate(Level::Normal, low, high, temp),
+        _ => (level, None),
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    subscribed: bool,
+    threshold: Option<Threshold>,
+}
+
+pub struct TemperatureAlarm<'a, T: hil::sensors::TemperatureDriver<'a>, A: Alarm<'a>> {
+    driver: &'a T,
+    alarm: &'a A,
+    sample_period_ms: u32,
+    apps: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    busy: Cell<bool>,
+}
+
+impl<'a, T: hil::sensors::TemperatureDriver<'a>, A: Alarm<'a>> TemperatureAlarm<'a, T, A> {
+    pub fn new(
+        driver: &'a T,
+        alarm: &'a A,
+        sample_period_ms: u32,
+        grant: Grant<App, UpcallCount<{ upcall::COUNT }>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> TemperatureAlarm<'a, T, A> {
+        TemperatureAlarm {
+            driver,
+            alarm,
+            sample_period_ms,
+            apps: grant,
+            busy: Cell::new(false),
+        }
+    }
+
+    fn read(&self) -> Result<(), ErrorCode> {
+        // If a read is already ongoing, its result will be delivered.
+        if self.busy.get() {
+            return Ok(());
+        }
+        self.driver.read_temperature().map(|()| self.busy.set(true))
+    }
+
+    fn enqueue_command(&self, processid: ProcessId) -> CommandReturn {
+        self.apps
+            .enter(processid, |app, _| {
+                app.subscribed = true;
+                self.read().into()
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
+
+    fn set_threshold(&self, low: i32, high: i32, processid: ProcessId) -> CommandReturn {
+        if low >= high {
+            return CommandReturn::failure(ErrorCode::INVAL);
+        }
+        let res = self.apps.enter(processid, |app, _| {
+            app.threshold = Some(Threshold {
+                low,
+                high,
+                level: Level::Normal,
+            });
+        });
+        match res {
+            Ok(()) => {
+                if !self.alarm.is_armed() && !self.busy.get() {
+                    self.schedule_sample();
+                }
+                CommandReturn::success()
+            }
+            Err(err) => CommandReturn::failure(err.into()),
+        }
+    }
+
+    fn clear_threshold(&self, processid: ProcessId) -> CommandReturn {
+        self.apps
+            .enter(processid, |app, _| {
+                app.threshold = None;
+                CommandReturn::success()
+            })
+            .unwrap_or_else(|err| CommandReturn::failure(err.into()))
+    }
+
+    fn schedule_sample(&self) {
+        let interval = self.alarm.ticks_from_ms(self.sample_period_ms);
+        self.alarm.set_alarm(self.alarm.now(), interval);
+    }
+
+    fn any_threshold(&self) -> bool {
+        self.apps
+            .iter()
+            .any(|cntr| cntr.enter(|app, _| app.threshold.is_some()))
+    }
+}
+
+impl<'a, T: hil::sensors::TemperatureDriver<'a>, A: Alarm<'a>> AlarmClient
+    for TemperatureAlarm<'a, T, A>
+{
+    fn alarm(&self) {
+        if self.read().is_err() && self.any_threshold() {
+            // Try again at the next period.
+            self.schedule_sample();
+        }
+    }
+}
+
+impl<'a, T: hil::sensors::TemperatureDriver<'a>, A: Alarm<'a>> hil::sensors::TemperatureClient
+    for TemperatureAlarm<'a, T, A>
+{
+    fn callback(&self, temp_val: Result<i32, ErrorCode>) {
+        self.busy.set(false);
+
+        if let Ok(temp_val) = temp_val {
+            for cntr in self.apps.iter() {
+                cntr.enter(|app, upcalls| {
+                    if app.subscribed {
+                        app.subscribed = false;
+                        upcalls
+                            .schedule_upcall(upcall::READING, (temp_val as usize, 0, 0))
+                            .ok();
+                    }
+                    if let Some(threshold) = app.threshold.as_mut() {
+                        let (level, crossing) =
+                            evaluate(threshold.level, threshold.low, threshold.high, temp_val);
+                        threshold.level = level;
+                        if let Some(crossing) = crossing {
+                            upcalls
+                                .schedule_upcall(
+                                    upcall::THRESHOLD,
+                                    (temp_val as usize, crossing as usize, 0),
+                                )
+                                .ok();
+                        }
+                    }
+                });
+            }
+        }
+
+        if !self.alarm.is_armed() && self.any_threshold() {
+            self.schedule_sample();
+        }
+    }
+}
+
+impl<'a, T: hil::sensors::TemperatureDriver<'a>, A: Alarm<'a>> SyscallDriver
+    for TemperatureAlarm<'a, T, A>
+{
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        data2: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            // driver existence check
+            0 => CommandReturn::success(),
+
+            // read temperature
+            1 => self.enqueue_command(processid),
+
+            // set threshold
+            2 => self.set_threshold(data1 as i32, data2 as i32, processid),
+
+            // clear threshold
+            3 => self.clear_threshold(processid),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossings() {
+        assert_eq!(evaluate(Level::Normal, 0, 1000, 500), (Level::Normal, None));
+        assert_eq!(
+            evaluate(Level::Normal, 0, 1000, 1000),
+            (Level::High, Some(Crossing::High))
+        );
+        assert_eq!(
+            evaluate(Level::Normal, 0, 1000, -10),
+            (Level::Low, Some(Crossing::Low))
+        );
+    }
+
+    #[test]
+    fn hysteresis() {
+        // Hovering around the high bound does not trigger again.
+        assert_eq!(evaluate(Level::High, 0, 1000, 990), (Level::High, None));
+        assert_eq!(evaluate(Level::High, 0, 1000, 1010), (Level::High, None));
+        // Dropping past the hysteresis re-arms the bound.
+        assert_eq!(
+            evaluate(Level::High, 0, 1000, 1000 - HYSTERESIS - 1),
+            (Level::Normal, None)
+        );
+        assert_eq!(
+            evaluate(Level::Low, 0, 1000, HYSTERESIS),
+            (Level::Low, None)
+        );
+        // A swing straight from one bound to the other is reported.
+        assert_eq!(
+            evaluate(Level::Low, 0, 1000, 1200),
+            (Level::High, Some(Crossing::High))
+        );
+    }
+}
@@ -199,8 +199,12 @@ pub trait KVSystem<'a> {
 enum Operation {
     None,
     Init,
+    // Initialisation failed, so there is no store to operate on.
+    InitFailed,
     GetKey,
     AppendKey,
+    // Invalidating the object of an append whose write failed.
+    FlushAppend,
     InvalidateKey,
     GarbageCollect,
 }
@@ -338,10 +342,52 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> TicKVSystem<'a, F,
         self.operation.set(Operation::Init);
     }
 
+    /// Initialisation has failed, for example because the flash holds a
+    /// corrupt region. There is no store to run the operation queued during
+    /// init on, so fail it to the client, as well as any later operation.
+    fn fail_init(&self) {
+        self.operation.set(Operation::InitFailed);
+        match self.next_operation.get() {
+            Operation::None | Operation::Init | Operation::InitFailed | Operation::FlushAppend => {}
+            Operation::GetKey => {
+                self.client.map(|cb| {
+                    cb.get_value_complete(
+                        Err(ErrorCode::NODEVICE),
+                        self.key_buffer.take().unwrap(),
+                        self.value_buffer.take().unwrap(),
+                    );
+                });
+            }
+            Operation::AppendKey => {
+                self.client.map(|cb| {
+                    cb.append_key_complete(
+                        Err(ErrorCode::NODEVICE),
+                        self.key_buffer.take().unwrap(),
+                        self.value_buffer.take().unwrap(),
+                    );
+                });
+            }
+            Operation::InvalidateKey => {
+                self.client.map(|cb| {
+                    cb.invalidate_key_complete(
+                        Err(ErrorCode::NODEVICE),
+                        self.key_buffer.take().unwrap(),
+                    );
+                });
+            }
+            Operation::GarbageCollect => {
+                self.client.map(|cb| {
+                    cb.garbage_collect_complete(Err(ErrorCode::NODEVICE));
+                });
+            }
+        }
+        self.next_operation.set(Operation::None);
+    }
+
     fn complete_init(&self) {
         self.operation.set(Operation::None);
         match self.next_operation.get() {
-            Operation::None | Operation::Init => {}
+            Operation::None | Operation::Init | Operation::InitFailed | Operation::FlushAppend => {}
             Operation::GetKey => {
                 match self.get_value(
                     self.key_buffer.take().unwrap(),
@@ -439,7 +485,17 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                 | Ok(tickv::success_codes::SuccessCode::Written) => {
                     self.complete_init();
                 }
-                _ => {}
+                Ok(_)
+                | Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+                | Err(tickv::error_codes::ErrorCode::WriteNotReady(_))
+                | Err(tickv::error_codes::ErrorCode::EraseNotReady(_)) => {
+                    // Need to do another flash operation.
+                }
+                Err(_) => {
+                    // Including `CorruptRegion`, which is left for the
+                    // platform to recover from.
+                    self.fail_init();
+                }
             },
             Operation::GetKey => {
                 match ret {
@@ -568,7 +624,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
         }
     }
 
-    fn write_complete(&self, pagebuffer: &'static mut F::Page, _result: Result<(), flash::Error>) {
+    fn write_complete(&self, pagebuffer: &'static mut F::Page, result: Result<(), flash::Error>) {
         self.tickv
             .tickv
             .controller
@@ -579,11 +635,43 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
             Operation::Init => {
                 self.complete_init();
             }
-            Operation::AppendKey => {
+            Operation::AppendKey => match result {
+                Ok(()) => {
+                    self.tickv.tickv.append_complete();
+                    self.operation.set(Operation::None);
+                    self.client.map(|cb| {
+                        cb.append_key_complete(
+                            Ok(()),
+                            self.key_buffer.take().unwrap(),
+                            self.value_buffer.take().unwrap(),
+                        );
+                    });
+                }
+                Err(_) => {
+                    // The object may be partially written, so invalidate it
+                    // before reporting the failure.
+                    match self.tickv.tickv.flush_pending() {
+                        Ok(tickv::success_codes::SuccessCode::Queued) => {
+                            self.operation.set(Operation::FlushAppend);
+                        }
+                        _ => {
+                            self.operation.set(Operation::None);
+                            self.client.map(|cb| {
+                                cb.append_key_complete(
+                                    Err(ErrorCode::FAIL),
+                                    self.key_buffer.take().unwrap(),
+                                    self.value_buffer.take().unwrap(),
+                                );
+                            });
+                        }
+                    }
+                }
+            },
+            Operation::FlushAppend => {
                 self.operation.set(Operation::None);
                 self.client.map(|cb| {
                     cb.append_key_complete(
-                        Ok(()),
+                        Err(ErrorCode::FAIL),
                         self.key_buffer.take().unwrap(),
                         self.value_buffer.take().unwrap(),
                     );
@@ -619,7 +707,17 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> flash::Client<F>
                 | Ok(tickv::success_codes::SuccessCode::Written) => {
                     self.complete_init();
                 }
-                _ => {}
+                Ok(_)
+                | Err(tickv::error_codes::ErrorCode::ReadNotReady(_))
+                | Err(tickv::error_codes::ErrorCode::WriteNotReady(_))
+                | Err(tickv::error_codes::ErrorCode::EraseNotReady(_)) => {
+                    // Need to do another flash operation.
+                }
+                Err(_) => {
+                    // Including `CorruptRegion`, which is left for the
+                    // platform to recover from.
+                    self.fail_init();
+                }
             },
             Operation::GarbageCollect => match ret {
                 Ok(tickv::success_codes::SuccessCode::Complete)
@@ -679,7 +777,9 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                     }
                     Err((buf, e)) => {
                         let tock_error = match e {
-                            tickv::error_codes::ErrorCode::ObjectTooLarge => ErrorCode::SIZE,
+                            tickv::error_codes::ErrorCode::ObjectTooLarge
+                            | tickv::error_codes::ErrorCode::ValueTooLarge => ErrorCode::SIZE,
+                            tickv::error_codes::ErrorCode::Busy => ErrorCode::BUSY,
                             _ => ErrorCode::FAIL,
                         };
                         Err((key, SubSliceMut::new(buf), tock_error))
@@ -694,6 +794,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                 self.value_buffer.replace(value);
                 Ok(())
             }
+            Operation::InitFailed => Err((key, value, ErrorCode::NODEVICE)),
             _ => {
                 // An operation is already in process.
                 Err((key, value, ErrorCode::BUSY))
@@ -729,6 +830,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                 self.value_buffer.replace(value);
                 Ok(())
             }
+            Operation::InitFailed => Err((key, value, ErrorCode::NODEVICE)),
             _ => {
                 // An operation is already in process.
                 Err((key, value, ErrorCode::BUSY))
@@ -759,6 +861,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                 self.key_buffer.replace(key);
                 Ok(())
             }
+            Operation::InitFailed => Err((key, ErrorCode::NODEVICE)),
             _ => {
                 // An operation is already in process.
                 Err((key, ErrorCode::BUSY))
@@ -781,6 +884,7 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
                 self.next_operation.set(Operation::GarbageCollect);
                 Ok(())
             }
+            Operation::InitFailed => Err(ErrorCode::NODEVICE),
             _ => {
                 // An operation is already in process.
                 Err(ErrorCode::BUSY)
@@ -788,3 +892,279 @@ impl<'a, F: Flash, H: Hasher<'a, 8>, const PAGE_SIZE: usize> KVSystem<'a>
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use core::cell::RefCell;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    const PAGE_SIZE: usize = 64;
+    const PAGES: usize = 4;
+
+    struct MockPage([u8; PAGE_SIZE]);
+
+    impl Default for MockPage {
+        fn default() -> Self {
+            MockPage([0; PAGE_SIZE])
+        }
+    }
+
+    impl AsMut<[u8]> for MockPage {
+        fn as_mut(&mut self) -> &mut [u8] {
+            &mut self.0
+        }
+    }
+
+    /// Flash whose operations complete when `complete_read()` or `run()` is
+    /// called.
+    struct MockFlash {
+        pages: RefCell<[[u8; PAGE_SIZE]; PAGES]>,
+        read: Cell<Option<usize>>,
+        write: Cell<Option<usize>>,
+        erase: Cell<Option<usize>>,
+        buffer: TakeCell<'static, MockPage>,
+        // The next write stores the page but reports an error, as a torn
+        // write would.
+        fail_write: Cell<bool>,
+    }
+
+    impl MockFlash {
+        fn new() -> Self {
+            MockFlash {
+                pages: RefCell::new([[0xFF; PAGE_SIZE]; PAGES]),
+                read: Cell::new(None),
+                write: Cell::new(None),
+                erase: Cell::new(None),
+                buffer: TakeCell::empty(),
+                fail_write: Cell::new(false),
+            }
+        }
+
+        fn complete_read<C: flash::Client<Self>>(&self, client: &C) {
+            let page = self.read.take().unwrap();
+            let buffer = self.buffer.take().unwrap();
+            buffer.0 = self.pages.borrow()[page];
+            client.read_complete(buffer, Ok(()));
+        }
+
+        /// Completes flash operations until none is pending.
+        fn run<C: flash::Client<Self>>(&self, client: &C) {
+            loop {
+                if self.read.get().is_some() {
+                    self.complete_read(client);
+                } else if let Some(page) = self.write.take() {
+                    let buffer = self.buffer.take().unwrap();
+                    self.pages.borrow_mut()[page] = buffer.0;
+                    let result = if self.fail_write.take() {
+                        Err(flash::Error::FlashError)
+                    } else {
+                        Ok(())
+                    };
+                    client.write_complete(buffer, result);
+                } else if let Some(page) = self.erase.take() {
+                    self.pages.borrow_mut()[page] = [0xFF; PAGE_SIZE];
+                    client.erase_complete(Ok(()));
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    impl Flash for MockFlash {
+        type Page = MockPage;
+
+        fn read_page(
+            &self,
+            page_number: usize,
+            buf: &'static mut MockPage,
+        ) -> Result<(), (ErrorCode, &'static mut MockPage)> {
+            self.read.set(Some(page_number));
+            self.buffer.replace(buf);
+            Ok(())
+        }
+
+        fn write_page(
+            &self,
+            page_number: usize,
+            buf: &'static mut MockPage,
+        ) -> Result<(), (ErrorCode, &'static mut MockPage)> {
+            self.write.set(Some(page_number));
+            self.buffer.replace(buf);
+            Ok(())
+        }
+
+        fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode> {
+            self.erase.set(Some(page_number));
+            Ok(())
+        }
+    }
+
+    struct MockHasher;
+
+    impl<'a> Hasher<'a, 8> for MockHasher {
+        fn set_client(&'a self, _client: &'a dyn hasher::Client<8>) {}
+
+        fn add_data(
+            &self,
+            data: SubSlice<'static, u8>,
+        ) -> Result<usize, (ErrorCode, SubSlice<'static, u8>)> {
+            Err((ErrorCode::NOSUPPORT, data))
+        }
+
+        fn add_mut_data(
+            &self,
+            data: SubSliceMut<'static, u8>,
+        ) -> Result<usize, (ErrorCode, SubSliceMut<'static, u8>)> {
+            Err((ErrorCode::NOSUPPORT, data))
+        }
+
+        fn run(
+            &'a self,
+            digest: &'static mut [u8; 8],
+        ) -> Result<(), (ErrorCode, &'static mut [u8; 8])> {
+            Err((ErrorCode::NOSUPPORT, digest))
+        }
+
+        fn clear_data(&self) {}
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        appends: RefCell<Vec<Result<(), ErrorCode>>>,
+        gets: RefCell<Vec<Result<(), ErrorCode>>>,
+    }
+
+    impl KVSystemClient<TicKVKeyType> for MockClient {
+        fn generate_key_complete(
+            &self,
+            _result: Result<(), ErrorCode>,
+            _unhashed_key: SubSliceMut<'static, u8>,
+            _key_buf: &'static mut TicKVKeyType,
+        ) {
+        }
+
+        fn append_key_complete(
+            &self,
+            result: Result<(), ErrorCode>,
+            _key: &'static mut TicKVKeyType,
+            _value: SubSliceMut<'static, u8>,
+        ) {
+            self.appends.borrow_mut().push(result);
+        }
+
+        fn get_value_complete(
+            &self,
+            result: Result<(), ErrorCode>,
+            _key: &'static mut TicKVKeyType,
+            _ret_buf: SubSliceMut<'static, u8>,
+        ) {
+            self.gets.borrow_mut().push(result);
+        }
+
+        fn invalidate_key_complete(
+            &self,
+            _result: Result<(), ErrorCode>,
+            _key: &'static mut TicKVKeyType,
+        ) {
+        }
+
+        fn garbage_collect_complete(&self, _result: Result<(), ErrorCode>) {}
+    }
+
+    #[test]
+    fn corrupt_region_fails_queued_operation() {
+        let flash = Box::leak(Box::new(MockFlash::new()));
+        // The main key is looked up in region 0. Give it an object header
+        // with an unknown version.
+        flash.pages.borrow_mut()[0][0] = tickv::tickv::VERSION + 0x40;
+
+        let kv = Box::leak(Box::new(TicKVSystem::<_, _, PAGE_SIZE>::new(
+            flash,
+            Box::leak(Box::new(MockHasher)),
+            Box::leak(Box::new([0; PAGE_SIZE])),
+            Box::leak(Box::new(MockPage::default())),
+            0,
+            PAGE_SIZE * PAGES,
+        )));
+        let client = Box::leak(Box::new(MockClient::default()));
+        kv.set_client(client);
+
+        kv.initialise();
+
+        // Queued behind initialisation.
+        let key = Box::leak(Box::new([0, 0, 0, 0, 0, 0, 0, 1]));
+        let value = SubSliceMut::new(Box::leak(Box::new([0_u8; 8])) as &mut [u8]);
+        assert!(kv.get_value(key, value).is_ok());
+
+        flash.complete_read(kv);
+        assert_eq!(*client.gets.borrow(), [Err(ErrorCode::NODEVICE)]);
+
+        // There is no store, so later operations are rejected without
+        // touching the flash.
+        flash.read.set(None);
+        let key = Box::leak(Box::new([0, 0, 0, 0, 0, 0, 0, 1]));
+        let value = SubSliceMut::new(Box::leak(Box::new([0_u8; 8])) as &mut [u8]);
+        assert_eq!(
+            kv.get_value(key, value).map_err(|(_, _, e)| e),
+            Err(ErrorCode::NODEVICE)
+        );
+        let key = Box::leak(Box::new([0, 0, 0, 0, 0, 0, 0, 1]));
+        let value = SubSliceMut::new(Box::leak(Box::new([0_u8; 8])) as &mut [u8]);
+        assert_eq!(
+            kv.append_key(key, value).map_err(|(_, _, e)| e),
+            Err(ErrorCode::NODEVICE)
+        );
+        let key = Box::leak(Box::new([0, 0, 0, 0, 0, 0, 0, 1]));
+        assert_eq!(
+            kv.invalidate_key(key).map_err(|(_, e)| e),
+            Err(ErrorCode::NODEVICE)
+        );
+        assert_eq!(kv.garbage_collect(), Err(ErrorCode::NODEVICE));
+        assert_eq!(flash.read.get(), None);
+    }
+
+    #[test]
+    fn failed_append_write_is_invalidated() {
+        let flash = Box::leak(Box::new(MockFlash::new()));
+        let kv = Box::leak(Box::new(TicKVSystem::<_, _, PAGE_SIZE>::new(
+            flash,
+            Box::leak(Box::new(MockHasher)),
+            Box::leak(Box::new([0; PAGE_SIZE])),
+            Box::leak(Box::new(MockPage::default())),
+            0,
+            PAGE_SIZE * PAGES,
+        )));
+        let client = Box::leak(Box::new(MockClient::default()));
+        kv.set_client(client);
+
+        kv.initialise();
+        flash.run(kv);
+
+        let key = Box::leak(Box::new([0, 0, 0, 0, 0, 0, 0, 1]));
+        let value = SubSliceMut::new(Box::leak(Box::new([0x23_u8; 8])) as &mut [u8]);
+        assert!(kv.append_key(key, value).is_ok());
+        flash.fail_write.set(true);
+        flash.run(kv);
+        assert_eq!(*client.appends.borrow(), [Err(ErrorCode::FAIL)]);
+
+        // The object reached flash, but was invalidated before the failure
+        // was reported.
+        let key = Box::leak(Box::new([0, 0, 0, 0, 0, 0, 0, 1]));
+        let value = SubSliceMut::new(Box::leak(Box::new([0_u8; 8])) as &mut [u8]);
+        assert!(kv.get_value(key, value).is_ok());
+        flash.run(kv);
+        assert_eq!(*client.gets.borrow(), [Err(ErrorCode::NOSUPPORT)]);
+
+        // The key can be appended again.
+        let key = Box::leak(Box::new([0, 0, 0, 0, 0, 0, 0, 1]));
+        let value = SubSliceMut::new(Box::leak(Box::new([0x23_u8; 8])) as &mut [u8]);
+        assert!(kv.append_key(key, value).is_ok());
+        flash.run(kv);
+        assert_eq!(*client.appends.borrow(), [Err(ErrorCode::FAIL), Ok(())]);
+    }
+}
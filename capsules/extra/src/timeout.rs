@@ -0,0 +1,209 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! One-shot timeout for asynchronous operations.
+//!
+//! Drivers that must give up on an operation whose callback never arrives
+//! arm a `Timeout` before starting the operation and disarm it when the
+//! callback comes. If the time runs out first, the client's `timed_out` is
+//! called instead, and the driver can abort the operation.
+//!
+//! Each `Timeout` needs its own alarm, normally a `VirtualMuxAlarm`, so that
+//! drivers do not have to share an alarm between their own use and their
+//! timeouts.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let timeout = components::timeout::TimeoutComponent::new(mux_alarm)
+//!     .finalize(components::timeout_component_static!(nrf52840::rtc::Rtc));
+//! timeout.set_client(driver);
+//!
+//! // In the driver:
+//! self.timeout.arm(100);
+//! start_operation();
+//!
+//! // In the callback of the operation:
+//! if self.timeout.disarm().is_ok() {
+//!     // The operation completed in time.
+//! }
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Receives the expiry of a `Timeout`.
+pub trait Client {
+    /// The timeout expired before it was disarmed.
+    fn timed_out(&self);
+}
+
+pub struct Timeout<'a, A: Alarm<'a>> {
+    alarm: &'a A,
+    armed: Cell<bool>,
+    client: OptionalCell<&'a dyn Client>,
+}
+
+impl<'a, A: Alarm<'a>> Timeout<'a, A> {
+    pub fn new(alarm: &'a A) -> Timeout<'a, A> {
+        Timeout {
+            alarm,
+            armed: Cell::new(false),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+
+    /// Starts the timeout, to expire in `ms` milliseconds. Arming a timeout
+    /// that is already armed restarts it.
+    pub fn arm(&self, ms: u32) {
+        self.armed.set(true);
+        self.alarm
+            .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(ms));
+    }
+
+    /// Stops the timeout. Returns `ALREADY` if it was not armed, which
+    /// includes the case where it has already expired.
+    pub fn disarm(&self) -> Result<(), ErrorCode> {
+        if !self.armed.replace(false) {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.alarm.disarm()
+    }
+
+    /// Whether the timeout is armed and has not expired yet.
+    pub fn is_armed(&self) -> bool {
+        self.armed.get()
+    }
+}
+
+impl<'a, A: Alarm<'a>> AlarmClient for Timeout<'a, A> {
+    fn alarm(&self) {
+        if self.armed.replace(false) {
+            self.client.map(|client| client.timed_out());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use kernel::hil::time::{Freq1KHz, Ticks, Ticks32, Time};
+
+    /// An alarm whose time only advances when the test calls `advance()`.
+    #[derive(Default)]
+    struct MockAlarm {
+        now: Cell<u32>,
+        expiry: Cell<Option<u32>>,
+    }
+
+    impl MockAlarm {
+        fn advance(&self, timeout: &Timeout<'_, Self>, ticks: u32) {
+            for _ in 0..ticks {
+                self.now.set(self.now.get() + 1);
+                if self.expiry.get() == Some(self.now.get()) {
+                    self.expiry.set(None);
+                    timeout.alarm();
+                }
+            }
+        }
+    }
+
+    impl Time for MockAlarm {
+        type Ticks = Ticks32;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> Ticks32 {
+            self.now.get().into()
+        }
+    }
+
+    impl<'a> Alarm<'a> for MockAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+
+        fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+            self.expiry.set(Some(reference.wrapping_add(dt).into_u32()));
+        }
+
+        fn get_alarm(&self) -> Self::Ticks {
+            self.expiry.get().unwrap_or(0).into()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.expiry.set(None);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.expiry.get().is_some()
+        }
+
+        fn minimum_dt(&self) -> Self::Ticks {
+            1.into()
+        }
+    }
+
+    #[derive(Default)]
+    struct MockClient {
+        timeouts: Cell<usize>,
+    }
+
+    impl Client for MockClient {
+        fn timed_out(&self) {
+            self.timeouts.set(self.timeouts.get() + 1);
+        }
+    }
+
+    #[test]
+    fn fires_when_not_disarmed() {
+        let alarm = MockAlarm::default();
+        let client = MockClient::default();
+        let timeout = Timeout::new(&alarm);
+        timeout.set_client(&client);
+
+        // The alarm runs at 1 kHz, so 50 ms is 50 ticks.
+        timeout.arm(50);
+        alarm.advance(&timeout, 49);
+        assert_eq!(client.timeouts.get(), 0);
+        assert!(timeout.is_armed());
+        alarm.advance(&timeout, 1);
+        assert_eq!(client.timeouts.get(), 1);
+        assert!(!timeout.is_armed());
+        assert_eq!(timeout.disarm(), Err(ErrorCode::ALREADY));
+
+        // It does not fire again.
+        alarm.advance(&timeout, 100);
+        assert_eq!(client.timeouts.get(), 1);
+    }
+
+    #[test]
+    fn disarmed_does_not_fire() {
+        let alarm = MockAlarm::default();
+        let client = MockClient::default();
+        let timeout = Timeout::new(&alarm);
+        timeout.set_client(&client);
+
+        timeout.arm(50);
+        alarm.advance(&timeout, 30);
+        assert_eq!(timeout.disarm(), Ok(()));
+        alarm.advance(&timeout, 100);
+        assert_eq!(client.timeouts.get(), 0);
+
+        // Re-arming restarts the whole time.
+        timeout.arm(20);
+        alarm.advance(&timeout, 10);
+        timeout.arm(20);
+        alarm.advance(&timeout, 19);
+        assert_eq!(client.timeouts.get(), 0);
+        alarm.advance(&timeout, 1);
+        assert_eq!(client.timeouts.get(), 1);
+    }
+}
@@ -0,0 +1,435 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Provides userspace with a continuous slider built from a row of
+//! capacitive touch pads.
+//!
+//! Every pad is read through an ADC channel. The first scan after an app
+//! starts listening, or after a calibration request, records the untouched
+//! reading of every pad. A touch is the distance of a reading from that
+//! baseline. The strongest pad and its neighbours are combined into a
+//! centroid, which gives a position between 0 (the first pad) and 100 (the
+//! last pad) with a finer resolution than the number of pads.
+//!
+//! A touch is only reported once it has been seen on a number of
+//! consecutive scans, and likewise for a release, so that noise on a single
+//! scan does not produce spurious events.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let slider_alarm = static_init!(
+//!     VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!     VirtualMuxAlarm::new(mux_alarm)
+//! );
+//! slider_alarm.setup();
+//!
+//! let slider = static_init!(
+//!     capsules_extra::touch_slider::TouchSlider<
+//!         'static,
+//!         VirtualMuxAlarm<'static, nrf52840::rtc::Rtc>,
+//!         4,
+//!     >,
+//!     capsules_extra::touch_slider::TouchSlider::new(
+//!         [pad0, pad1, pad2, pad3],
+//!         slider_alarm,
+//!         20,
+//!         capsules_extra::touch_slider::SliderFilter::new(2000, 3),
+//!         board_kernel.create_grant(
+//!             capsules_extra::touch_slider::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! slider_alarm.set_alarm_client(slider);
+//! pad0.set_client(slider);
+//! pad1.set_client(slider);
+//! pad2.set_client(slider);
+//! pad3.set_client(slider);
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called with `1` and the position when the slider is touched or the
+//!   touch moves, and with `0` and the last position when it is released.
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Start listening for slider events.
+//! - `2`: Stop listening for slider events.
+//! - `3`: Recalibrate the untouched pad readings on the next scan. The pads
+//!   must not be touched during that scan.
+//! - `4`: Get the number of pads.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::adc;
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::TouchSlider as usize;
+
+/// The highest slider position, reported when only the last pad is touched.
+pub const MAX_POSITION: u8 = 100;
+
+/// Returns the slider position for the given per-pad touch strengths, or
+/// `None` if no pad is above `threshold`.
+///
+/// Only the strongest pad and its direct neighbours are used, so a second
+/// finger or noise on a distant pad does not pull the position. On the
+/// first and last pad there is only one neighbour, which lets the position
+/// reach the ends of the range.
+pub fn centroid(strengths: &[u16], threshold: u16) -> Option<u8> {
+    let (peak, &max) = strengths
+        .iter()
+        .enumerate()
+        .max_by_key(|&(i, s)| (*s, core::cmp::Reverse(i)))?;
+    if max < threshold {
+        return None;
+    }
+    if strengths.len() < 2 {
+        return Some(0);
+    }
+
+    let first = peak.saturating_sub(1);
+    let last = (peak + 1).min(strengths.len() - 1);
+    let (mut weighted, mut total) = (0u64, 0u64);
+    for (i, &s) in strengths.iter().enumerate().take(last + 1).skip(first) {
+        weighted += i as u64 * s as u64;
+        total += s as u64;
+    }
+
+    // Scale the pad index to 0..=MAX_POSITION, rounding to nearest.
+    let span = (strengths.len() - 1) as u64 * total;
+    Some(((weighted * MAX_POSITION as u64 * 2 + span) / (span * 2)) as u8)
+}
+
+/// A change reported by the slider.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SliderEvent {
+    /// The slider was touched at the given position.
+    Touch(u8),
+    /// The touch moved to the given position.
+    Move(u8),
+    /// The touch ended at the given position.
+    Release(u8),
+}
+
+/// Turns scans of touch strengths into debounced slider events.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SliderFilter {
+    threshold: u16,
+    debounce: u8,
+    touched: bool,
+    position: u8,
+    /// Number of consecutive scans that disagree with `touched`.
+    pending: u8,
+}
+
+impl SliderFilter {
+    /// A pad counts as touched when its strength is at least `threshold`.
+    /// A touch or release is reported once it has been seen on `debounce`
+    /// consecutive scans.
+    pub const fn new(threshold: u16, debounce: u8) -> SliderFilter {
+        SliderFilter {
+            threshold,
+            debounce,
+            touched: false,
+            position: 0,
+            pending: 0,
+        }
+    }
+
+    /// Forgets the current touch without reporting a release.
+    pub fn reset(&mut self) {
+        self.touched = false;
+        self.pending = 0;
+    }
+
+    /// Updates the filter with the strengths from one scan and returns the
+    /// resulting event, if any.
+    pub fn update(&mut self, strengths: &[u16]) -> Option<SliderEvent> {
+        let position = centroid(strengths, self.threshold);
+        if position.is_some() == self.touched {
+            self.pending = 0;
+            let position = position?;
+            if position == self.position {
+                return None;
+            }
+            self.position = position;
+            return Some(SliderEvent::Move(position));
+        }
+
+        self.pending += 1;
+        if self.pending < self.debounce {
+            return None;
+        }
+        self.pending = 0;
+        self.touched = !self.touched;
+        match position {
+            Some(position) => {
+                self.position = position;
+                Some(SliderEvent::Touch(position))
+            }
+            None => Some(SliderEvent::Release(self.position)),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct App {
+    listening: bool,
+}
+
+pub struct TouchSlider<'a, A: time::Alarm<'a>, const N: usize> {
+    pads: [&'a dyn adc::AdcChannel<'a>; N],
+    alarm: &'a A,
+    period_ms: u32,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    /// Index of the pad being sampled, if a scan is in progress.
+    scanning: Cell<Option<usize>>,
+    readings: Cell<[u16; N]>,
+    baseline: Cell<[u16; N]>,
+    calibrate: Cell<bool>,
+    filter: Cell<SliderFilter>,
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> TouchSlider<'a, A, N> {
+    /// `pads` are the ADC channels of the pads in order along the slider.
+    /// The pads are scanned every `period_ms` milliseconds while any app is
+    /// listening.
+    pub fn new(
+        pads: [&'a dyn adc::AdcChannel<'a>; N],
+        alarm: &'a A,
+        period_ms: u32,
+        filter: SliderFilter,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> TouchSlider<'a, A, N> {
+        TouchSlider {
+            pads,
+            alarm,
+            period_ms,
+            apps: grant,
+            scanning: Cell::new(None),
+            readings: Cell::new([0; N]),
+            baseline: Cell::new([0; N]),
+            calibrate: Cell::new(true),
+            filter: Cell::new(filter),
+        }
+    }
+
+    fn any_listening(&self) -> bool {
+        self.apps
+            .iter()
+            .any(|app| app.enter(|app, _| app.listening))
+    }
+
+    /// Starts scanning if it is not already running.
+    fn start(&self) {
+        if self.scanning.get().is_none() && !self.alarm.is_armed() {
+            self.calibrate.set(true);
+            let mut filter = self.filter.get();
+            filter.reset();
+            self.filter.set(filter);
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+        }
+    }
+
+    /// Samples the pad at `index`, or finishes the scan if all pads have
+    /// been sampled.
+    fn sample_pad(&self, index: usize) {
+        match self.pads.get(index) {
+            Some(pad) => {
+                self.scanning.set(Some(index));
+                if pad.sample().is_err() {
+                    self.end_scan();
+                }
+            }
+            None => {
+                self.finish_scan();
+                self.end_scan();
+            }
+        }
+    }
+
+    fn finish_scan(&self) {
+        let readings = self.readings.get();
+        if self.calibrate.get() {
+            self.calibrate.set(false);
+            self.baseline.set(readings);
+            return;
+        }
+
+        let baseline = self.baseline.get();
+        let mut strengths = [0; N];
+        for (strength, (reading, base)) in strengths
+            .iter_mut()
+            .zip(readings.iter().zip(baseline.iter()))
+        {
+            *strength = reading.abs_diff(*base);
+        }
+
+        let mut filter = self.filter.get();
+        let event = filter.update(&strengths);
+        self.filter.set(filter);
+
+        let args = match event {
+            Some(SliderEvent::Touch(position)) | Some(SliderEvent::Move(position)) => {
+                (1, position as usize, 0)
+            }
+            Some(SliderEvent::Release(position)) => (0, position as usize, 0),
+            None => return,
+        };
+        self.apps.each(|_, app, kernel_data| {
+            if app.listening {
+                kernel_data.schedule_upcall(0, args).ok();
+            }
+        });
+    }
+
+    /// Schedules the next scan if anyone is still listening.
+    fn end_scan(&self) {
+        self.scanning.set(None);
+        if self.any_listening() {
+            self.alarm
+                .set_alarm(self.alarm.now(), self.alarm.ticks_from_ms(self.period_ms));
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> time::AlarmClient for TouchSlider<'a, A, N> {
+    fn alarm(&self) {
+        if self.any_listening() {
+            self.sample_pad(0);
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> adc::Client for TouchSlider<'a, A, N> {
+    fn sample_ready(&self, sample: u16) {
+        if let Some(index) = self.scanning.get() {
+            let mut readings = self.readings.get();
+            readings[index] = sample;
+            self.readings.set(readings);
+            self.sample_pad(index + 1);
+        }
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, const N: usize> SyscallDriver for TouchSlider<'a, A, N> {
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            // Start listening
+            1 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.listening = true;
+                })
+                .map_or_else(
+                    |err| CommandReturn::failure(err.into()),
+                    |()| {
+                        self.start();
+                        CommandReturn::success()
+                    },
+                ),
+
+            // Stop listening
+            2 => self
+                .apps
+                .enter(processid, |app, _| {
+                    app.listening = false;
+                })
+                .map_or_else(
+                    |err| CommandReturn::failure(err.into()),
+                    |()| CommandReturn::success(),
+                ),
+
+            // Recalibrate
+            3 => {
+                self.calibrate.set(true);
+                CommandReturn::success()
+            }
+
+            // Number of pads
+            4 => CommandReturn::success_u32(N as u32),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn centroid_interpolation() {
+        // Nothing above the threshold.
+        assert_eq!(centroid(&[0, 10, 20, 0, 0], 100), None);
+
+        // A single pad, including the edge pads.
+        assert_eq!(centroid(&[500, 0, 0, 0, 0], 100), Some(0));
+        assert_eq!(centroid(&[0, 0, 500, 0, 0], 100), Some(50));
+        assert_eq!(centroid(&[0, 0, 0, 0, 500], 100), Some(100));
+
+        // Halfway between the second and third pad.
+        assert_eq!(centroid(&[0, 400, 400, 0, 0], 100), Some(38));
+
+        // A quarter of the way from the third to the fourth pad.
+        assert_eq!(centroid(&[0, 0, 600, 200, 0], 100), Some(56));
+
+        // Between the edge pad and its only neighbour.
+        assert_eq!(centroid(&[300, 100, 0, 0, 0], 100), Some(6));
+
+        // A distant pad does not pull the position.
+        assert_eq!(centroid(&[0, 0, 500, 0, 300], 100), Some(50));
+    }
+
+    #[test]
+    fn debounce() {
+        let mut filter = SliderFilter::new(100, 2);
+
+        // A single noisy scan is ignored.
+        assert_eq!(filter.update(&[0, 500, 0]), None);
+        assert_eq!(filter.update(&[0, 0, 0]), None);
+
+        assert_eq!(filter.update(&[0, 500, 0]), None);
+        assert_eq!(filter.update(&[0, 500, 0]), Some(SliderEvent::Touch(50)));
+        assert_eq!(filter.update(&[0, 500, 0]), None);
+        assert_eq!(filter.update(&[0, 0, 500]), Some(SliderEvent::Move(100)));
+
+        // A single dropped scan does not release the touch.
+        assert_eq!(filter.update(&[0, 0, 0]), None);
+        assert_eq!(filter.update(&[0, 0, 500]), None);
+
+        assert_eq!(filter.update(&[0, 0, 0]), None);
+        assert_eq!(filter.update(&[0, 0, 0]), Some(SliderEvent::Release(100)));
+        assert_eq!(filter.update(&[0, 0, 0]), None);
+    }
+}
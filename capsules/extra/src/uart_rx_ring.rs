@@ -0,0 +1,272 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Continuous UART reception into a software ring buffer.
+//!
+//! A UART receive only captures bytes while a `receive_buffer()` call is
+//! outstanding, so a client that does not immediately start the next receive
+//! after each one completes loses whatever arrives in between. This capsule
+//! keeps a receive outstanding at all times, copies every received chunk
+//! into a ring buffer, and lets its client read the bytes out of the ring at
+//! its own pace with the non-blocking [`UartRxRing::read`].
+//!
+//! If the client does not drain the ring before it fills up, the oldest
+//! bytes are overwritten. Every overwritten byte is counted, and the count is
+//! available from [`UartRxRing::overflow_count`].
+//!
+//! A receive completes once the receive buffer is full, so the size of the
+//! receive buffer sets how many bytes are batched before the client is
+//! notified. The ring holds one byte less than the length of its buffer.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! # use kernel::static_init;
+//!
+//! let rx_uart = static_init!(UartDevice, UartDevice::new(uart_mux, true));
+//! rx_uart.setup();
+//! let rx_buffer = static_init!([u8; 16], [0; 16]);
+//! let ring_buffer = static_init!([u8; 512], [0; 512]);
+//! let rx_ring = static_init!(
+//!     capsules_extra::uart_rx_ring::UartRxRing<'static, UartDevice>,
+//!     capsules_extra::uart_rx_ring::UartRxRing::new(rx_uart, rx_buffer, ring_buffer)
+//! );
+//! rx_uart.set_receive_client(rx_ring);
+//! rx_ring.set_client(client);
+//! rx_ring.start().unwrap();
+//! ```
+
+use core::cell::Cell;
+use kernel::collections::queue::Queue;
+use kernel::collections::ring_buffer::RingBuffer;
+use kernel::hil::uart;
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Client notified when bytes have been added to the ring.
+pub trait RxRingClient {
+    /// New bytes are available to `read()`. `len` is the total number of
+    /// bytes in the ring.
+    fn data_available(&self, len: usize);
+}
+
+pub struct UartRxRing<'a, U: uart::Receive<'a>> {
+    uart: &'a U,
+    client: OptionalCell<&'a dyn RxRingClient>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    ring: MapCell<RingBuffer<'static, u8>>,
+    /// Number of bytes overwritten before they were read.
+    overflows: Cell<usize>,
+}
+
+impl<'a, U: uart::Receive<'a>> UartRxRing<'a, U> {
+    pub fn new(
+        uart: &'a U,
+        rx_buffer: &'static mut [u8],
+        ring_buffer: &'static mut [u8],
+    ) -> UartRxRing<'a, U> {
+        UartRxRing {
+            uart,
+            client: OptionalCell::empty(),
+            rx_buffer: TakeCell::new(rx_buffer),
+            ring: MapCell::new(RingBuffer::new(ring_buffer)),
+            overflows: Cell::new(0),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn RxRingClient) {
+        self.client.set(client);
+    }
+
+    /// Starts receiving. Reception continues until `stop()` is called or the
+    /// UART fails to start a receive.
+    pub fn start(&self) -> Result<(), ErrorCode> {
+        let buffer = self.rx_buffer.take().ok_or(ErrorCode::ALREADY)?;
+        self.receive(buffer)
+    }
+
+    /// Stops receiving. The bytes received so far stay in the ring.
+    pub fn stop(&self) -> Result<(), ErrorCode> {
+        self.uart.receive_abort()
+    }
+
+    /// Copies up to `buf.len()` of the oldest bytes out of the ring, and
+    /// returns how many were copied.
+    pub fn read(&self, buf: &mut [u8]) -> usize {
+        self.ring.map_or(0, |ring| {
+            buf.iter_mut()
+                .zip(core::iter::from_fn(|| ring.dequeue()))
+                .map(|(dst, byte)| *dst = byte)
+                .count()
+        })
+    }
+
+    /// Number of bytes waiting to be read.
+    pub fn available(&self) -> usize {
+        self.ring.map_or(0, |ring| ring.len())
+    }
+
+    /// Number of received bytes that were overwritten before they were read.
+    pub fn overflow_count(&self) -> usize {
+        self.overflows.get()
+    }
+
+    fn receive(&self, buffer: &'static mut [u8]) -> Result<(), ErrorCode> {
+        let len = buffer.len();
+        self.uart
+            .receive_buffer(buffer, len)
+            .map_err(|(error, buffer)| {
+                self.rx_buffer.replace(buffer);
+                error
+            })
+    }
+}
+
+impl<'a, U: uart::Receive<'a>> uart::ReceiveClient for UartRxRing<'a, U> {
+    fn received_buffer(
+        &self,
+        buffer: &'static mut [u8],
+        rx_len: usize,
+        rval: Result<(), ErrorCode>,
+        _error: uart::Error,
+    ) {
+        let rx_len = core::cmp::min(rx_len, buffer.len());
+        self.ring.map(|ring| {
+            for &byte in &buffer[..rx_len] {
+                if ring.push(byte).is_some() {
+                    self.overflows.set(self.overflows.get() + 1);
+                }
+            }
+        });
+
+        // Re-arm straight away to keep the gap without a receive short. A
+        // cancelled receive means `stop()` was called.
+        if rval == Err(ErrorCode::CANCEL) {
+            self.rx_buffer.replace(buffer);
+        } else {
+            let _ = self.receive(buffer);
+        }
+
+        if rx_len > 0 {
+            let len = self.available();
+            self.client.map(|client| client.data_available(len));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+
+    struct MockUart<'a> {
+        client: OptionalCell<&'a dyn uart::ReceiveClient>,
+        buffer: TakeCell<'static, [u8]>,
+    }
+
+    impl<'a> MockUart<'a> {
+        fn new() -> Self {
+            MockUart {
+                client: OptionalCell::empty(),
+                buffer: TakeCell::empty(),
+            }
+        }
+
+        /// Completes the outstanding receive with `data`.
+        fn feed(&self, data: &[u8]) {
+            let buffer = self.buffer.take().expect("no receive outstanding");
+            buffer[..data.len()].copy_from_slice(data);
+            self.client.map(|client| {
+                client.received_buffer(buffer, data.len(), Ok(()), uart::Error::None)
+            });
+        }
+    }
+
+    impl<'a> uart::Receive<'a> for MockUart<'a> {
+        fn set_receive_client(&self, client: &'a dyn uart::ReceiveClient) {
+            self.client.set(client);
+        }
+
+        fn receive_buffer(
+            &self,
+            rx_buffer: &'static mut [u8],
+            _rx_len: usize,
+        ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+            if self.buffer.is_some() {
+                return Err((ErrorCode::BUSY, rx_buffer));
+            }
+            self.buffer.replace(rx_buffer);
+            Ok(())
+        }
+
+        fn receive_word(&self) -> Result<(), ErrorCode> {
+            Err(ErrorCode::FAIL)
+        }
+
+        fn receive_abort(&self) -> Result<(), ErrorCode> {
+            self.buffer.take().map_or(Ok(()), |buffer| {
+                self.client.map(|client| {
+                    client.received_buffer(buffer, 0, Err(ErrorCode::CANCEL), uart::Error::Aborted)
+                });
+                Err(ErrorCode::BUSY)
+            })
+        }
+    }
+
+    fn setup(uart: &'static MockUart<'static>) -> &'static UartRxRing<'static, MockUart<'static>> {
+        let rx_buffer = Box::leak(Box::new([0; 4]));
+        let ring_buffer = Box::leak(Box::new([0; 8]));
+        let rx_ring = Box::leak(Box::new(UartRxRing::new(uart, rx_buffer, ring_buffer)));
+        uart::Receive::set_receive_client(uart, rx_ring);
+        rx_ring
+    }
+
+    #[test]
+    fn overflow_counted() {
+        let uart = Box::leak(Box::new(MockUart::new()));
+        let rx_ring = setup(uart);
+        assert_eq!(rx_ring.start(), Ok(()));
+        assert_eq!(rx_ring.start(), Err(ErrorCode::ALREADY));
+
+        // The ring holds 7 bytes, so 12 bytes without reading overwrite the
+        // oldest 5.
+        uart.feed(&[0, 1, 2, 3]);
+        uart.feed(&[4, 5, 6, 7]);
+        uart.feed(&[8, 9, 10, 11]);
+        assert_eq!(rx_ring.overflow_count(), 5);
+        assert_eq!(rx_ring.available(), 7);
+
+        let mut buf = [0; 16];
+        assert_eq!(rx_ring.read(&mut buf), 7);
+        assert_eq!(buf[..7], [5, 6, 7, 8, 9, 10, 11]);
+
+        // Once drained, bytes that fit are not counted.
+        uart.feed(&[12, 13, 14, 15]);
+        assert_eq!(rx_ring.read(&mut buf[..2]), 2);
+        assert_eq!(buf[..2], [12, 13]);
+        assert_eq!(rx_ring.overflow_count(), 5);
+        assert_eq!(rx_ring.available(), 2);
+    }
+
+    #[test]
+    fn stop_keeps_data() {
+        let uart = Box::leak(Box::new(MockUart::new()));
+        let rx_ring = setup(uart);
+        assert_eq!(rx_ring.start(), Ok(()));
+
+        uart.feed(&[1, 2, 3]);
+        assert_eq!(rx_ring.stop(), Err(ErrorCode::BUSY));
+        assert!(uart.buffer.is_none());
+
+        let mut buf = [0; 4];
+        assert_eq!(rx_ring.read(&mut buf), 3);
+        assert_eq!(buf[..3], [1, 2, 3]);
+
+        // Reception can be restarted after stopping.
+        assert_eq!(rx_ring.start(), Ok(()));
+    }
+}
@@ -0,0 +1,1047 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! SyscallDriver for the VL53L0X Time-of-Flight ranging sensor.
+//!
+//! I2C Interface
+//!
+//! <https://www.st.com/resource/en/datasheet/vl53l0x.pdf>
+//!
+//! ST does not document the registers of the sensor, it only provides a C
+//! API. The initialization and ranging sequences below follow that API, as
+//! reverse engineered by the Pololu VL53L0X Arduino library. The sensor is
+//! initialized the first time a measurement is requested, which includes
+//! configuring the reference SPADs, loading the default tuning settings and
+//! running the VHV and phase calibrations.
+//!
+//! The sensor's GPIO1 pin is configured to go low when a new measurement is
+//! ready. The driver then reads the range result and clears the interrupt.
+//! In continuous mode the sensor either measures back-to-back, or waits the
+//! requested inter-measurement period between measurements.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let vl53l0x = components::vl53l0x::Vl53l0xComponent::new(
+//!     mux_i2c,
+//!     0x29,
+//!     &nrf52840::gpio::PORT[Pin::P0_14],
+//!     board_kernel,
+//!     capsules_extra::vl53l0x::DRIVER_NUM,
+//! )
+//! .finalize(components::vl53l0x_component_static!(nrf52840::i2c::TWI));
+//! ```
+//!
+//! Syscall Interface
+//! -----------------
+//!
+//! ### Subscribe
+//!
+//! - `0`: Called after every measurement. The first argument is a status
+//!   code, the second the distance in millimeters and the third the
+//!   [`RangeStatus`] of the measurement.
+//!
+//! ### Command
+//!
+//! - `0`: Driver existence check.
+//! - `1`: Take a single measurement.
+//! - `2`: Start continuous ranging. `data1` is the inter-measurement period
+//!   in milliseconds, or `0` to measure back-to-back.
+//! - `3`: Stop continuous ranging.
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use capsules_core::driver;
+
+/// Syscall driver number.
+pub const DRIVER_NUM: usize = driver::NUM::Vl53l0x as usize;
+
+/// Recommended buffer length for this driver.
+pub const BUF_LEN: usize = 14;
+
+/// Number of times a register is polled while waiting for the sensor before
+/// giving up.
+const MAX_POLLS: usize = 500;
+
+/// Distances at or above this value are reported by the sensor when no
+/// target was found.
+const OUT_OF_RANGE_MM: u16 = 8190;
+
+/// The device range status for a valid measurement.
+const DEVICE_RANGE_COMPLETE: u8 = 11;
+
+#[allow(dead_code)]
+enum Registers {
+    SysrangeStart = 0x00,
+    SystemSequenceConfig = 0x01,
+    SystemIntermeasurementPeriod = 0x04,
+    SystemInterruptConfigGpio = 0x0a,
+    SystemInterruptClear = 0x0b,
+    ResultInterruptStatus = 0x13,
+    ResultRangeStatus = 0x14,
+    MsrcConfigControl = 0x60,
+    FinalRangeConfigMinCountRateRtnLimit = 0x44,
+    GpioHvMuxActiveHigh = 0x84,
+    VhvConfigPadSclSdaExtsupHv = 0x89,
+    GlobalConfigSpadEnablesRef0 = 0xb0,
+    OscCalibrateVal = 0xf8,
+    IdentificationModelId = 0xc0,
+}
+
+/// The quality of a measurement, as reported to userspace.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RangeStatus {
+    /// The distance is valid.
+    Valid = 0,
+    /// No target was found within range.
+    OutOfRange = 1,
+    /// The measurement failed, for instance because the return signal was
+    /// too weak or the sensor detected a hardware fault.
+    Invalid = 2,
+}
+
+/// Decodes the distance in millimeters and the status of a measurement from
+/// the 12 bytes starting at `RESULT_RANGE_STATUS`.
+fn decode_result(buffer: &[u8]) -> (u16, RangeStatus) {
+    let device_status = (buffer[0] & 0x78) >> 3;
+    let distance = u16::from_be_bytes([buffer[10], buffer[11]]);
+
+    let status = if distance >= OUT_OF_RANGE_MM {
+        RangeStatus::OutOfRange
+    } else if device_status == DEVICE_RANGE_COMPLETE {
+        RangeStatus::Valid
+    } else {
+        RangeStatus::Invalid
+    };
+    (distance, status)
+}
+
+/// Clears all but the first `count` reference SPADs in `map`, starting at
+/// SPAD 12 for aperture SPADs and at SPAD 0 otherwise.
+fn filter_ref_spads(map: &mut [u8; 6], count: u8, aperture: bool) {
+    let first = if aperture { 12 } else { 0 };
+    let mut enabled = 0;
+    for i in 0..48 {
+        let bit = 1 << (i % 8);
+        if i < first || enabled == count {
+            map[i / 8] &= !bit;
+        } else if map[i / 8] & bit != 0 {
+            enabled += 1;
+        }
+    }
+}
+
+/// A single operation in one of the register sequences below. Most steps are
+/// one I2C transfer, read-modify-write steps are two.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+    /// Write a value to a register.
+    Write(u8, u8),
+    /// Write a big endian 16-bit value to a register.
+    Write16(u8, u16),
+    /// Set the bits in the mask of a register.
+    SetBits(u8, u8),
+    /// Clear the bits in the mask of a register.
+    ClearBits(u8, u8),
+    /// Read a register until one of the bits in the mask is set.
+    WaitForBits(u8, u8),
+    /// Save the "stop variable" the sensor needs to start ranging.
+    ReadStopVariable,
+    /// Restore the stop variable.
+    WriteStopVariable,
+    /// Read the number and type of the reference SPADs.
+    ReadSpadInfo,
+    /// Enable only the reference SPADs reported by `ReadSpadInfo`.
+    SetRefSpads,
+    /// Read the oscillator calibration, needed to set the inter-measurement
+    /// period.
+    ReadOscCalibrate,
+    /// Set the inter-measurement period for timed continuous ranging.
+    SetPeriod,
+    /// Start single, back-to-back or timed ranging.
+    StartRanging,
+    /// Read the range result.
+    ReadResult,
+}
+
+/// Which transfer of a step is being performed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Phase {
+    First,
+    Second,
+}
+
+/// What to do after a transfer has completed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Progress {
+    /// Move on to the next step.
+    Next,
+    /// Perform the second transfer of this step.
+    Second,
+    /// Repeat this step, the sensor is not ready yet.
+    Poll,
+}
+
+/// How to range once the sensor is started.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Mode {
+    Single,
+    Continuous(u32),
+}
+
+/// Values read from the sensor and carried between steps.
+#[derive(Clone, Copy, Debug)]
+struct Context {
+    mode: Mode,
+    stop_variable: u8,
+    spad_count: u8,
+    spad_aperture: bool,
+    spad_map: [u8; 6],
+    osc_calibrate: u16,
+    /// Value read by the first transfer of a read-modify-write step.
+    value: u8,
+    result: (u16, RangeStatus),
+}
+
+impl Context {
+    const fn new() -> Context {
+        Context {
+            mode: Mode::Single,
+            stop_variable: 0,
+            spad_count: 0,
+            spad_aperture: false,
+            spad_map: [0; 6],
+            osc_calibrate: 0,
+            value: 0,
+            result: (0, RangeStatus::Invalid),
+        }
+    }
+}
+
+impl Step {
+    /// Fills `buffer` for the transfer of this step in `phase`. Returns the
+    /// number of bytes to write and the number of bytes to read afterwards.
+    fn transfer(&self, phase: Phase, ctx: &Context, buffer: &mut [u8]) -> (usize, usize) {
+        let mut read = |reg: u8, len: usize| {
+            buffer[0] = reg;
+            (1, len)
+        };
+
+        match (*self, phase) {
+            (Step::Write(reg, value), _) => {
+                buffer[0] = reg;
+                buffer[1] = value;
+                (2, 0)
+            }
+            (Step::Write16(reg, value), _) => {
+                buffer[0] = reg;
+                buffer[1..3].copy_from_slice(&value.to_be_bytes());
+                (3, 0)
+            }
+            (Step::SetBits(reg, _), Phase::First)
+            | (Step::ClearBits(reg, _), Phase::First)
+            | (Step::WaitForBits(reg, _), _) => read(reg, 1),
+            (Step::SetBits(reg, mask), Phase::Second) => {
+                buffer[0] = reg;
+                buffer[1] = ctx.value | mask;
+                (2, 0)
+            }
+            (Step::ClearBits(reg, mask), Phase::Second) => {
+                buffer[0] = reg;
+                buffer[1] = ctx.value & !mask;
+                (2, 0)
+            }
+            (Step::ReadStopVariable, _) => read(0x91, 1),
+            (Step::WriteStopVariable, _) => {
+                buffer[0] = 0x91;
+                buffer[1] = ctx.stop_variable;
+                (2, 0)
+            }
+            (Step::ReadSpadInfo, _) => read(0x92, 1),
+            (Step::SetRefSpads, Phase::First) => {
+                read(Registers::GlobalConfigSpadEnablesRef0 as u8, 6)
+            }
+            (Step::SetRefSpads, Phase::Second) => {
+                buffer[0] = Registers::GlobalConfigSpadEnablesRef0 as u8;
+                buffer[1..7].copy_from_slice(&ctx.spad_map);
+                (7, 0)
+            }
+            (Step::ReadOscCalibrate, _) => read(Registers::OscCalibrateVal as u8, 2),
+            (Step::SetPeriod, _) => {
+                let period = match ctx.mode {
+                    Mode::Continuous(period_ms) if ctx.osc_calibrate != 0 => {
+                        period_ms.saturating_mul(ctx.osc_calibrate as u32)
+                    }
+                    Mode::Continuous(period_ms) => period_ms,
+                    Mode::Single => 0,
+                };
+                buffer[0] = Registers::SystemIntermeasurementPeriod as u8;
+                buffer[1..5].copy_from_slice(&period.to_be_bytes());
+                (5, 0)
+            }
+            (Step::StartRanging, _) => {
+                buffer[0] = Registers::SysrangeStart as u8;
+                buffer[1] = match ctx.mode {
+                    Mode::Single => 0x01,
+                    Mode::Continuous(0) => 0x02,
+                    Mode::Continuous(_) => 0x04,
+                };
+                (2, 0)
+            }
+            (Step::ReadResult, _) => read(Registers::ResultRangeStatus as u8, 12),
+        }
+    }
+
+    /// Processes the completed transfer of this step in `phase`, with any
+    /// data read at the start of `buffer`.
+    fn complete(&self, phase: Phase, ctx: &mut Context, buffer: &[u8]) -> Progress {
+        match (*self, phase) {
+            (Step::SetBits(_, _), Phase::First) | (Step::ClearBits(_, _), Phase::First) => {
+                ctx.value = buffer[0];
+                Progress::Second
+            }
+            (Step::WaitForBits(_, mask), _) => {
+                if buffer[0] & mask != 0 {
+                    Progress::Next
+                } else {
+                    Progress::Poll
+                }
+            }
+            (Step::ReadStopVariable, _) => {
+                ctx.stop_variable = buffer[0];
+                Progress::Next
+            }
+            (Step::ReadSpadInfo, _) => {
+                ctx.spad_count = buffer[0] & 0x7f;
+                ctx.spad_aperture = buffer[0] & 0x80 != 0;
+                Progress::Next
+            }
+            (Step::SetRefSpads, Phase::First) => {
+                ctx.spad_map.copy_from_slice(&buffer[0..6]);
+                filter_ref_spads(&mut ctx.spad_map, ctx.spad_count, ctx.spad_aperture);
+                Progress::Second
+            }
+            (Step::ReadOscCalibrate, _) => {
+                ctx.osc_calibrate = u16::from_be_bytes([buffer[0], buffer[1]]);
+                Progress::Next
+            }
+            (Step::ReadResult, _) => {
+                ctx.result = decode_result(buffer);
+                Progress::Next
+            }
+            _ => Progress::Next,
+        }
+    }
+}
+
+/// Initialization: data init, static init and reference calibration.
+const INIT: &[Step] = &[
+    // Use 2V8 I/O levels and standard mode I2C
+    Step::SetBits(Registers::VhvConfigPadSclSdaExtsupHv as u8, 0x01),
+    Step::Write(0x88, 0x00),
+    Step::Write(0x80, 0x01),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x00, 0x00),
+    Step::ReadStopVariable,
+    Step::Write(0x00, 0x01),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x80, 0x00),
+    // Disable the SIGNAL_RATE_MSRC and SIGNAL_RATE_PRE_RANGE limit checks
+    Step::SetBits(Registers::MsrcConfigControl as u8, 0x12),
+    // Signal rate limit of 0.25 MCPS, in 9.7 fixed point
+    Step::Write16(
+        Registers::FinalRangeConfigMinCountRateRtnLimit as u8,
+        0x0020,
+    ),
+    Step::Write(Registers::SystemSequenceConfig as u8, 0xff),
+    // Read the reference SPAD count and type from NVM
+    Step::Write(0x80, 0x01),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x00, 0x00),
+    Step::Write(0xff, 0x06),
+    Step::SetBits(0x83, 0x04),
+    Step::Write(0xff, 0x07),
+    Step::Write(0x81, 0x01),
+    Step::Write(0x80, 0x01),
+    Step::Write(0x94, 0x6b),
+    Step::Write(0x83, 0x00),
+    Step::WaitForBits(0x83, 0xff),
+    Step::Write(0x83, 0x01),
+    Step::ReadSpadInfo,
+    Step::Write(0x81, 0x00),
+    Step::Write(0xff, 0x06),
+    Step::ClearBits(0x83, 0x04),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x00, 0x01),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x80, 0x00),
+    // Enable only the reference SPADs read above
+    Step::Write(0xff, 0x01),
+    Step::Write(0x4f, 0x00),
+    Step::Write(0x4e, 0x2c),
+    Step::Write(0xff, 0x00),
+    Step::Write(0xb6, 0xb4),
+    Step::SetRefSpads,
+    // Default tuning settings
+    Step::Write(0xff, 0x01),
+    Step::Write(0x00, 0x00),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x09, 0x00),
+    Step::Write(0x10, 0x00),
+    Step::Write(0x11, 0x00),
+    Step::Write(0x24, 0x01),
+    Step::Write(0x25, 0xff),
+    Step::Write(0x75, 0x00),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x4e, 0x2c),
+    Step::Write(0x48, 0x00),
+    Step::Write(0x30, 0x20),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x30, 0x09),
+    Step::Write(0x54, 0x00),
+    Step::Write(0x31, 0x04),
+    Step::Write(0x32, 0x03),
+    Step::Write(0x40, 0x83),
+    Step::Write(0x46, 0x25),
+    Step::Write(0x60, 0x00),
+    Step::Write(0x27, 0x00),
+    Step::Write(0x50, 0x06),
+    Step::Write(0x51, 0x00),
+    Step::Write(0x52, 0x96),
+    Step::Write(0x56, 0x08),
+    Step::Write(0x57, 0x30),
+    Step::Write(0x61, 0x00),
+    Step::Write(0x62, 0x00),
+    Step::Write(0x64, 0x00),
+    Step::Write(0x65, 0x00),
+    Step::Write(0x66, 0xa0),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x22, 0x32),
+    Step::Write(0x47, 0x14),
+    Step::Write(0x49, 0xff),
+    Step::Write(0x4a, 0x00),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x7a, 0x0a),
+    Step::Write(0x7b, 0x00),
+    Step::Write(0x78, 0x21),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x23, 0x34),
+    Step::Write(0x42, 0x00),
+    Step::Write(0x44, 0xff),
+    Step::Write(0x45, 0x26),
+    Step::Write(0x46, 0x05),
+    Step::Write(0x40, 0x40),
+    Step::Write(0x0e, 0x06),
+    Step::Write(0x20, 0x1a),
+    Step::Write(0x43, 0x40),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x34, 0x03),
+    Step::Write(0x35, 0x44),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x31, 0x04),
+    Step::Write(0x4b, 0x09),
+    Step::Write(0x4c, 0x05),
+    Step::Write(0x4d, 0x04),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x44, 0x00),
+    Step::Write(0x45, 0x20),
+    Step::Write(0x47, 0x08),
+    Step::Write(0x48, 0x28),
+    Step::Write(0x67, 0x00),
+    Step::Write(0x70, 0x04),
+    Step::Write(0x71, 0x01),
+    Step::Write(0x72, 0xfe),
+    Step::Write(0x76, 0x00),
+    Step::Write(0x77, 0x00),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x0d, 0x01),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x80, 0x01),
+    Step::Write(0x01, 0xf8),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x8e, 0x01),
+    Step::Write(0x00, 0x01),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x80, 0x00),
+    // Drive GPIO1 low when a new sample is ready
+    Step::Write(Registers::SystemInterruptConfigGpio as u8, 0x04),
+    Step::ClearBits(Registers::GpioHvMuxActiveHigh as u8, 0x10),
+    Step::Write(Registers::SystemInterruptClear as u8, 0x01),
+    // VHV calibration
+    Step::Write(Registers::SystemSequenceConfig as u8, 0x01),
+    Step::Write(Registers::SysrangeStart as u8, 0x41),
+    Step::WaitForBits(Registers::ResultInterruptStatus as u8, 0x07),
+    Step::Write(Registers::SystemInterruptClear as u8, 0x01),
+    Step::Write(Registers::SysrangeStart as u8, 0x00),
+    // Phase calibration
+    Step::Write(Registers::SystemSequenceConfig as u8, 0x02),
+    Step::Write(Registers::SysrangeStart as u8, 0x01),
+    Step::WaitForBits(Registers::ResultInterruptStatus as u8, 0x07),
+    Step::Write(Registers::SystemInterruptClear as u8, 0x01),
+    Step::Write(Registers::SysrangeStart as u8, 0x00),
+    // Restore the sequence steps used for ranging
+    Step::Write(Registers::SystemSequenceConfig as u8, 0xe8),
+    Step::ReadOscCalibrate,
+];
+
+/// Start a single measurement or continuous ranging.
+const START: &[Step] = &[
+    Step::Write(0x80, 0x01),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x00, 0x00),
+    Step::WriteStopVariable,
+    Step::Write(0x00, 0x01),
+    Step::Write(0xff, 0x00),
+    Step::Write(0x80, 0x00),
+    Step::SetPeriod,
+    Step::StartRanging,
+];
+
+/// Stop continuous ranging.
+const STOP: &[Step] = &[
+    Step::Write(Registers::SysrangeStart as u8, 0x01),
+    Step::Write(0xff, 0x01),
+    Step::Write(0x00, 0x00),
+    Step::Write(0x91, 0x00),
+    Step::Write(0x00, 0x01),
+    Step::Write(0xff, 0x00),
+];
+
+/// Read a measurement and clear the interrupt.
+const READ_RESULT: &[Step] = &[
+    Step::ReadResult,
+    Step::Write(Registers::SystemInterruptClear as u8, 0x01),
+];
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Sequence {
+    Init,
+    Start,
+    Stop,
+    ReadResult,
+}
+
+impl Sequence {
+    fn steps(&self) -> &'static [Step] {
+        match self {
+            Sequence::Init => INIT,
+            Sequence::Start => START,
+            Sequence::Stop => STOP,
+            Sequence::ReadResult => READ_RESULT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Running a register sequence, at the given step and phase.
+    Sequence(Sequence, usize, Phase),
+    /// Waiting for GPIO1 to signal a measurement.
+    Ranging,
+}
+
+#[derive(Default)]
+pub struct App {}
+
+pub struct Vl53l0x<'a, I: i2c::I2CDevice> {
+    i2c: &'a I,
+    interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+    ctx: Cell<Context>,
+    initialized: Cell<bool>,
+    polls: Cell<usize>,
+    apps: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    owning_process: OptionalCell<ProcessId>,
+}
+
+impl<'a, I: i2c::I2CDevice> Vl53l0x<'a, I> {
+    pub fn new(
+        i2c: &'a I,
+        interrupt_pin: &'a dyn gpio::InterruptPin<'a>,
+        buffer: &'static mut [u8],
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<0>>,
+    ) -> Vl53l0x<'a, I> {
+        Vl53l0x {
+            i2c,
+            interrupt_pin,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            ctx: Cell::new(Context::new()),
+            initialized: Cell::new(false),
+            polls: Cell::new(0),
+            apps: grant,
+            owning_process: OptionalCell::empty(),
+        }
+    }
+
+    fn start(&self, mode: Mode) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+
+        let mut ctx = self.ctx.get();
+        ctx.mode = mode;
+        self.ctx.set(ctx);
+
+        self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+            self.interrupt_pin.make_input();
+            self.i2c.enable();
+            if self.initialized.get() {
+                self.run(buffer, State::Sequence(Sequence::Start, 0, Phase::First))
+            } else {
+                self.run(buffer, State::Sequence(Sequence::Init, 0, Phase::First))
+            }
+        })
+    }
+
+    fn stop(&self) -> Result<(), ErrorCode> {
+        match (self.state.get(), self.ctx.get().mode) {
+            (State::Ranging, Mode::Continuous(_)) => {
+                self.interrupt_pin.disable_interrupts();
+                self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buffer| {
+                    self.i2c.enable();
+                    self.run(buffer, State::Sequence(Sequence::Stop, 0, Phase::First))
+                })
+            }
+            (State::Idle, _) => Err(ErrorCode::ALREADY),
+            _ => Err(ErrorCode::BUSY),
+        }
+    }
+
+    /// Starts the transfer for `state`, which must be a `State::Sequence`.
+    fn run(&self, buffer: &'static mut [u8], state: State) -> Result<(), ErrorCode> {
+        let (sequence, index, phase) = match state {
+            State::Sequence(sequence, index, phase) => (sequence, index, phase),
+            _ => {
+                self.buffer.replace(buffer);
+                return Err(ErrorCode::FAIL);
+            }
+        };
+
+        let step = sequence.steps()[index];
+        let (write_len, read_len) = step.transfer(phase, &self.ctx.get(), buffer);
+        let res = if read_len == 0 {
+            self.i2c.write(buffer, write_len)
+        } else {
+            self.i2c.write_read(buffer, write_len, read_len)
+        };
+
+        match res {
+            Ok(()) => {
+                self.state.set(state);
+                Ok(())
+            }
+            Err((error, buffer)) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Idle);
+                Err(error.into())
+            }
+        }
+    }
+
+    /// Called once all steps of `sequence` have completed.
+    fn finish(&self, sequence: Sequence, buffer: &'static mut [u8]) -> Result<(), ErrorCode> {
+        match sequence {
+            Sequence::Init => {
+                self.initialized.set(true);
+                self.run(buffer, State::Sequence(Sequence::Start, 0, Phase::First))
+            }
+            Sequence::Start => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Ranging);
+                self.interrupt_pin
+                    .enable_interrupts(gpio::InterruptEdge::FallingEdge);
+                Ok(())
+            }
+            Sequence::ReadResult => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                let ctx = self.ctx.get();
+                if ctx.mode == Mode::Single {
+                    self.interrupt_pin.disable_interrupts();
+                    self.state.set(State::Idle);
+                } else {
+                    self.state.set(State::Ranging);
+                }
+                let (distance, status) = ctx.result;
+                self.schedule_upcall(Ok(()), distance as usize, status as usize);
+                Ok(())
+            }
+            Sequence::Stop => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Idle);
+                Ok(())
+            }
+        }
+    }
+
+    fn schedule_upcall(&self, status: Result<(), ErrorCode>, distance: usize, range: usize) {
+        self.owning_process.map(|pid| {
+            let _ = self.apps.enter(pid, |_app, kernel_data| {
+                kernel_data
+                    .schedule_upcall(
+                        0,
+                        (kernel::errorcode::into_statuscode(status), distance, range),
+                    )
+                    .ok();
+            });
+        });
+    }
+
+    /// Reports a failed operation to the app.
+    fn report(&self, res: Result<(), ErrorCode>) {
+        if let Err(e) = res {
+            self.interrupt_pin.disable_interrupts();
+            self.schedule_upcall(Err(e), 0, RangeStatus::Invalid as usize);
+        }
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> gpio::Client for Vl53l0x<'a, I> {
+    fn fired(&self) {
+        if self.state.get() != State::Ranging {
+            return;
+        }
+        self.buffer.take().map(|buffer| {
+            self.i2c.enable();
+            if let Err(e) = self.run(
+                buffer,
+                State::Sequence(Sequence::ReadResult, 0, Phase::First),
+            ) {
+                self.interrupt_pin.disable_interrupts();
+                self.schedule_upcall(Err(e), 0, RangeStatus::Invalid as usize);
+            }
+        });
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> i2c::I2CClient for Vl53l0x<'a, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        let res = match (status, self.state.get()) {
+            (Err(i2c_error), _) => {
+                self.buffer.replace(buffer);
+                self.i2c.disable();
+                self.state.set(State::Idle);
+                Err(i2c_error.into())
+            }
+            (Ok(()), State::Sequence(sequence, index, phase)) => {
+                let mut ctx = self.ctx.get();
+                let progress = sequence.steps()[index].complete(phase, &mut ctx, buffer);
+                self.ctx.set(ctx);
+
+                let next = match progress {
+                    Progress::Next => {
+                        self.polls.set(0);
+                        index + 1
+                    }
+                    Progress::Second => {
+                        let state = State::Sequence(sequence, index, Phase::Second);
+                        return self.report(self.run(buffer, state));
+                    }
+                    Progress::Poll => {
+                        self.polls.set(self.polls.get() + 1);
+                        if self.polls.get() > MAX_POLLS {
+                            self.polls.set(0);
+                            self.buffer.replace(buffer);
+                            self.i2c.disable();
+                            self.state.set(State::Idle);
+                            return self.report(Err(ErrorCode::FAIL));
+                        }
+                        index
+                    }
+                };
+
+                if next < sequence.steps().len() {
+                    self.run(buffer, State::Sequence(sequence, next, Phase::First))
+                } else {
+                    self.finish(sequence, buffer)
+                }
+            }
+            (Ok(()), State::Idle) | (Ok(()), State::Ranging) => {
+                self.buffer.replace(buffer);
+                Ok(())
+            }
+        };
+        self.report(res);
+    }
+}
+
+impl<'a, I: i2c::I2CDevice> SyscallDriver for Vl53l0x<'a, I> {
+    fn command(
+        &self,
+        command_num: usize,
+        data1: usize,
+        _: usize,
+        process_id: ProcessId,
+    ) -> CommandReturn {
+        if command_num == 0 {
+            // Handle this first as it should be returned
+            // unconditionally
+            return CommandReturn::success();
+        }
+        // Check if this non-virtualized driver is already in use by
+        // some (alive) process
+        let match_or_empty_or_nonexistant = self.owning_process.map_or(true, |current_process| {
+            self.apps
+                .enter(current_process, |_, _| current_process == process_id)
+                .unwrap_or(true)
+        });
+        if match_or_empty_or_nonexistant {
+            self.owning_process.set(process_id);
+        } else {
+            return CommandReturn::failure(ErrorCode::NOMEM);
+        }
+
+        match command_num {
+            // Single measurement
+            1 => self.start(Mode::Single).into(),
+            // Start continuous ranging
+            2 => self.start(Mode::Continuous(data1 as u32)).into(),
+            // Stop continuous ranging
+            3 => self.stop().into(),
+            // default
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.apps.enter(processid, |_, _| {})
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A simulated sensor, applying transfers to a register file.
+    struct MockSensor {
+        registers: [u8; 256],
+        /// Every register write, in order.
+        writes: [(u8, u8); 256],
+        write_count: usize,
+        /// Reads of `0x83` and `RESULT_INTERRUPT_STATUS` that return "not
+        /// ready" before the sensor is done.
+        spad_polls: usize,
+        calibration_polls: usize,
+    }
+
+    impl MockSensor {
+        fn new() -> MockSensor {
+            let mut registers = [0; 256];
+            registers[0x91] = 0x3c;
+            // 5 aperture SPADs
+            registers[0x92] = 0x85;
+            registers[Registers::GlobalConfigSpadEnablesRef0 as usize..][..6]
+                .copy_from_slice(&[0xff; 6]);
+            registers[Registers::OscCalibrateVal as usize] = 0x00;
+            registers[Registers::OscCalibrateVal as usize + 1] = 0xa5;
+            MockSensor {
+                registers,
+                writes: [(0, 0); 256],
+                write_count: 0,
+                spad_polls: 0,
+                calibration_polls: 0,
+            }
+        }
+
+        /// Performs an I2C transfer of `write_len` bytes, followed by a read
+        /// of `read_len` bytes into `buffer`.
+        fn transfer(&mut self, buffer: &mut [u8], write_len: usize, read_len: usize) {
+            let reg = buffer[0] as usize;
+            for (i, value) in buffer[1..write_len].iter().enumerate() {
+                self.registers[reg + i] = *value;
+                if self.write_count < self.writes.len() {
+                    self.writes[self.write_count] = ((reg + i) as u8, *value);
+                    self.write_count += 1;
+                }
+            }
+
+            // Model the sensor completing operations after a few polls.
+            match (reg, write_len) {
+                (0x83, 2) if buffer[1] == 0x00 => self.spad_polls = 2,
+                (0x00, 2) if buffer[1] & 0x01 != 0 => self.calibration_polls = 3,
+                _ => {}
+            }
+            if read_len > 0 {
+                let (polls, ready) = match reg {
+                    0x83 => (&mut self.spad_polls, 0x10),
+                    0x13 => (&mut self.calibration_polls, 0x04),
+                    _ => (&mut 0, self.registers[reg]),
+                };
+                if *polls > 0 {
+                    *polls -= 1;
+                    self.registers[reg] = 0;
+                } else {
+                    self.registers[reg] = ready;
+                }
+            }
+
+            buffer[..read_len].copy_from_slice(&self.registers[reg..reg + read_len]);
+        }
+
+        fn written(&self) -> &[(u8, u8)] {
+            &self.writes[..self.write_count]
+        }
+    }
+
+    /// Runs all steps of `sequence` against `sensor`, returning the number
+    /// of I2C transfers.
+    fn run_sequence(sensor: &mut MockSensor, ctx: &mut Context, sequence: &[Step]) -> usize {
+        let mut buffer = [0; BUF_LEN];
+        let mut transfers = 0;
+        let mut index = 0;
+        let mut phase = Phase::First;
+        while index < sequence.len() {
+            let step = sequence[index];
+            let (write_len, read_len) = step.transfer(phase, ctx, &mut buffer);
+            sensor.transfer(&mut buffer, write_len, read_len);
+            transfers += 1;
+            assert!(transfers < 1000, "sequence does not terminate");
+
+            match step.complete(phase, ctx, &buffer) {
+                Progress::Next => {
+                    index += 1;
+                    phase = Phase::First;
+                }
+                Progress::Second => phase = Phase::Second,
+                Progress::Poll => phase = Phase::First,
+            }
+        }
+        transfers
+    }
+
+    #[test]
+    fn init_sequence() {
+        let mut sensor = MockSensor::new();
+        let mut ctx = Context::new();
+        let transfers = run_sequence(&mut sensor, &mut ctx, INIT);
+
+        // Read-modify-write steps take two transfers, and the SPAD and the
+        // two calibration waits each poll a few times.
+        let rmw = INIT
+            .iter()
+            .filter(|step| {
+                matches!(
+                    step,
+                    Step::SetBits(_, _) | Step::ClearBits(_, _) | Step::SetRefSpads
+                )
+            })
+            .count();
+        assert_eq!(transfers, INIT.len() + rmw + 2 + 3 + 3);
+
+        assert_eq!(ctx.stop_variable, 0x3c);
+        assert_eq!(ctx.spad_count, 5);
+        assert!(ctx.spad_aperture);
+        assert_eq!(ctx.osc_calibrate, 0xa5);
+
+        // The first five aperture SPADs, starting at SPAD 12, are enabled.
+        assert_eq!(
+            sensor.registers[Registers::GlobalConfigSpadEnablesRef0 as usize..][..6],
+            [0x00, 0xf0, 0x01, 0x00, 0x00, 0x00]
+        );
+
+        let written = sensor.written();
+        // 2V8 mode was enabled by a read-modify-write first.
+        assert_eq!(written[0], (0x89, 0x01));
+        assert_eq!(written[1], (0x88, 0x00));
+        // Signal rate limit
+        assert!(written
+            .windows(2)
+            .any(|w| w == [(0x44, 0x00), (0x45, 0x20)]));
+        // GPIO1 is active low, and the sequence ends with the VHV and phase
+        // calibrations.
+        assert_eq!(
+            sensor.registers[Registers::GpioHvMuxActiveHigh as usize] & 0x10,
+            0
+        );
+        assert_eq!(
+            written[written.len() - 9..],
+            [
+                (0x01, 0x01),
+                (0x00, 0x41),
+                (0x0b, 0x01),
+                (0x00, 0x00),
+                (0x01, 0x02),
+                (0x00, 0x01),
+                (0x0b, 0x01),
+                (0x00, 0x00),
+                (0x01, 0xe8),
+            ]
+        );
+    }
+
+    #[test]
+    fn start_and_read() {
+        let mut sensor = MockSensor::new();
+        let mut ctx = Context::new();
+        ctx.stop_variable = 0x3c;
+        ctx.osc_calibrate = 0xa5;
+        ctx.mode = Mode::Continuous(100);
+        run_sequence(&mut sensor, &mut ctx, START);
+
+        let written = sensor.written();
+        assert!(written.contains(&(0x91, 0x3c)));
+        // Timed ranging with a period of 100 ms, scaled by the oscillator
+        // calibration.
+        let period = 100u32 * 0xa5;
+        assert_eq!(
+            sensor.registers[Registers::SystemIntermeasurementPeriod as usize..][..4],
+            period.to_be_bytes()
+        );
+        assert_eq!(written.last(), Some(&(0x00, 0x04)));
+
+        // A measurement of 342 mm is ready.
+        sensor.registers[Registers::ResultRangeStatus as usize] = DEVICE_RANGE_COMPLETE << 3;
+        sensor.registers[0x1e] = 0x01;
+        sensor.registers[0x1f] = 0x56;
+        run_sequence(&mut sensor, &mut ctx, READ_RESULT);
+        assert_eq!(ctx.result, (342, RangeStatus::Valid));
+        assert_eq!(sensor.written().last(), Some(&(0x0b, 0x01)));
+    }
+
+    #[test]
+    fn result_decoding() {
+        let mut result = [0; 12];
+        result[0] = DEVICE_RANGE_COMPLETE << 3 | 0x01;
+        result[10] = 0x00;
+        result[11] = 0x64;
+        assert_eq!(decode_result(&result), (100, RangeStatus::Valid));
+
+        // No target found
+        result[10] = 0x1f;
+        result[11] = 0xfe;
+        assert_eq!(decode_result(&result), (8190, RangeStatus::OutOfRange));
+
+        // Signal failure
+        result[0] = 4 << 3;
+        result[10] = 0x00;
+        result[11] = 0x14;
+        assert_eq!(decode_result(&result), (20, RangeStatus::Invalid));
+    }
+
+    #[test]
+    fn ref_spad_selection() {
+        // Non-aperture SPADs start at 0, skipping disabled ones.
+        let mut map = [0b1010_1010, 0xff, 0, 0, 0, 0];
+        filter_ref_spads(&mut map, 6, false);
+        assert_eq!(map, [0b1010_1010, 0b0000_0011, 0, 0, 0, 0]);
+
+        let mut map = [0xff; 6];
+        filter_ref_spads(&mut map, 3, true);
+        assert_eq!(map, [0x00, 0x70, 0, 0, 0, 0]);
+    }
+}
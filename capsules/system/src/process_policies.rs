@@ -10,7 +10,7 @@
 
 use kernel::process;
 use kernel::process::Process;
-use kernel::process::ProcessFaultPolicy;
+use kernel::process::{ProcessFaultPolicy, RestartMemoryPolicy};
 
 /// Simply panic the entire board if a process faults.
 pub struct PanicFaultPolicy {}
@@ -113,3 +113,89 @@ impl ProcessFaultPolicy for ThresholdRestartThenPanicFaultPolicy {
         }
     }
 }
+
+/// Implementation of `ProcessFaultPolicy` that picks the policy for each
+/// process by its name, so that different apps can respond differently to
+/// faults. Processes that are not listed use the default policy.
+///
+/// The policy is passed to `load_processes()` like any other, for example to
+/// restart one app while faults in any other app panic the board:
+///
+/// ```rust,ignore
+/// const FAULT_RESPONSE: PerProcessFaultPolicy = PerProcessFaultPolicy::new(
+///     &[("sensor_logger", &RestartFaultPolicy {})],
+///     &PanicFaultPolicy {},
+/// );
+/// ```
+pub struct PerProcessFaultPolicy {
+    policies: &'static [(&'static str, &'static dyn ProcessFaultPolicy)],
+    default: &'static dyn ProcessFaultPolicy,
+}
+
+impl PerProcessFaultPolicy {
+    pub const fn new(
+        policies: &'static [(&'static str, &'static dyn ProcessFaultPolicy)],
+        default: &'static dyn ProcessFaultPolicy,
+    ) -> PerProcessFaultPolicy {
+        PerProcessFaultPolicy { policies, default }
+    }
+
+    fn policy(&self, process: &dyn Process) -> &'static dyn ProcessFaultPolicy {
+        let name = process.get_process_name();
+        self.policies
+            .iter()
+            .find(|(policy_name, _)| *policy_name == name)
+            .map_or(self.default, |(_, policy)| *policy)
+    }
+}
+
+impl ProcessFaultPolicy for PerProcessFaultPolicy {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        self.policy(process).action(process)
+    }
+
+    fn restart_memory_policy(&self, process: &dyn Process) -> RestartMemoryPolicy {
+        self.policy(process).restart_memory_policy(process)
+    }
+}
+
+/// Wrapper around another `ProcessFaultPolicy` that clears process memory
+/// before the process is restarted.
+///
+/// The restart decision is left to the inner policy; this wrapper only sets
+/// the `RestartMemoryPolicy`. This is useful for processes handling secrets,
+/// which should not linger in RAM after a restart, and ensures a restarted
+/// process never reads stale state from its previous run.
+pub struct ZeroMemoryOnRestartPolicy<P: ProcessFaultPolicy> {
+    policy: P,
+    memory_policy: RestartMemoryPolicy,
+}
+
+impl<P: ProcessFaultPolicy> ZeroMemoryOnRestartPolicy<P> {
+    /// Zero the process-accessible memory on restart.
+    pub const fn new(policy: P) -> ZeroMemoryOnRestartPolicy<P> {
+        ZeroMemoryOnRestartPolicy {
+            policy,
+            memory_policy: RestartMemoryPolicy::ZeroAppMemory,
+        }
+    }
+
+    /// Zero the process-accessible memory and the process's grant regions on
+    /// restart.
+    pub const fn new_zero_all(policy: P) -> ZeroMemoryOnRestartPolicy<P> {
+        ZeroMemoryOnRestartPolicy {
+            policy,
+            memory_policy: RestartMemoryPolicy::ZeroAll,
+        }
+    }
+}
+
+impl<P: ProcessFaultPolicy> ProcessFaultPolicy for ZeroMemoryOnRestartPolicy<P> {
+    fn action(&self, process: &dyn Process) -> process::FaultAction {
+        self.policy.action(process)
+    }
+
+    fn restart_memory_policy(&self, _process: &dyn Process) -> RestartMemoryPolicy {
+        self.memory_policy
+    }
+}
@@ -152,6 +152,8 @@ pub struct SpiHost<'a> {
     rx_len: Cell<usize>,
     tx_offset: Cell<usize>,
     rx_offset: Cell<usize>,
+    /// Keep chip select asserted after the last segment of a transfer.
+    hold_cs: Cell<bool>,
 }
 // SPI Host Command Direction: Bidirectional
 const SPI_HOST_CMD_BIDIRECTIONAL: u32 = 3;
@@ -173,6 +175,7 @@ impl<'a> SpiHost<'a> {
             rx_len: Cell::new(0),
             tx_offset: Cell::new(0),
             rx_offset: Cell::new(0),
+            hold_cs: Cell::new(false),
         }
     }
 
@@ -374,8 +377,8 @@ impl<'a> SpiHost<'a> {
         self.enable_tx_interrupt();
 
         //Flush all data in TXFIFO and assert CSAAT for all
-        // but the last transfer segment.
-        if self.tx_offset.get() >= self.tx_len.get() {
+        // but the last transfer segment, unless chip select is held.
+        if self.tx_offset.get() >= self.tx_len.get() && !self.hold_cs.get() {
             regs.command.write(
                 command::LEN.val(num_transfer_bytes)
                     + command::DIRECTION.val(SPI_HOST_CMD_BIDIRECTIONAL)
@@ -722,15 +725,15 @@ impl<'a> hil::spi::SpiMaster<'a> for SpiHost<'a> {
         }
     }
 
-    /// hold_low is controlled by IP based on command segments issued
-    /// force holds are not supported
+    /// Keep chip select asserted after the last command segment of each
+    /// transfer.
     fn hold_low(&self) {
-        unimplemented!("spi_host: does not support hold low");
+        self.hold_cs.set(true);
     }
 
-    /// release_low is controlled by IP based on command segments issued
-    /// force releases are not supported
+    /// Deassert chip select with the last command segment of the next
+    /// transfer.
     fn release_low(&self) {
-        unimplemented!("spi_host: does not support release low");
+        self.hold_cs.set(false);
     }
 }
@@ -540,6 +540,8 @@ pub struct Radio<'a> {
     rx_client: OptionalCell<&'a dyn ble_advertising::RxClient>,
     tx_client: OptionalCell<&'a dyn ble_advertising::TxClient>,
     buffer: TakeCell<'static, [u8]>,
+    clock: OptionalCell<&'a crate::clock::Clock>,
+    high_clock_requested: Cell<bool>,
 }
 
 impl<'a> Radio<'a> {
@@ -550,9 +552,16 @@ impl<'a> Radio<'a> {
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
             buffer: TakeCell::empty(),
+            clock: OptionalCell::empty(),
+            high_clock_requested: Cell::new(false),
         }
     }
 
+    /// Set the clock used to request the HFXO while the radio is powered.
+    pub fn set_clock_ref(&self, clock: &'a crate::clock::Clock) {
+        self.clock.set(clock);
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.registers.mode.matches_all(Mode::MODE::BLE_1MBIT)
     }
@@ -580,6 +589,11 @@ impl<'a> Radio<'a> {
     }
 
     fn radio_on(&self) {
+        // The radio needs the HFXO while it is powered.
+        if !self.high_clock_requested.replace(true) {
+            self.clock.map(|clock| clock.high_request());
+        }
+
         // reset and enable power
         self.registers.power.write(Task::ENABLE::CLEAR);
         self.registers.power.write(Task::ENABLE::SET);
@@ -587,6 +601,10 @@ impl<'a> Radio<'a> {
 
     fn radio_off(&self) {
         self.registers.power.write(Task::ENABLE::CLEAR);
+
+        if self.high_clock_requested.replace(false) {
+            self.clock.map(|clock| clock.high_release());
+        }
     }
 
     fn set_tx_power(&self) {
@@ -46,6 +46,7 @@ pub struct Nrf52DefaultPeripherals<'a> {
     pub nvmc: crate::nvmc::Nvmc,
     pub clock: crate::clock::Clock,
     pub pwm0: crate::pwm::Pwm,
+    pub wdt: crate::wdt::Wdt,
 }
 
 impl<'a> Nrf52DefaultPeripherals<'a> {
@@ -70,10 +71,13 @@ impl<'a> Nrf52DefaultPeripherals<'a> {
             nvmc: crate::nvmc::Nvmc::new(),
             clock: crate::clock::Clock::new(),
             pwm0: crate::pwm::Pwm::new(),
+            wdt: crate::wdt::Wdt::new(),
         }
     }
     // Necessary for setting up circular dependencies
     pub fn init(&'static self) {
+        self.ble_radio.set_clock_ref(&self.clock);
+        self.temp.set_clock_ref(&self.clock);
         kernel::deferred_call::DeferredCallClient::register(&self.nvmc);
     }
 }
@@ -82,7 +86,10 @@ impl<'a> kernel::platform::chip::InterruptService for Nrf52DefaultPeripherals<'a
         match interrupt {
             crate::peripheral_interrupts::COMP => self.acomp.handle_interrupt(),
             crate::peripheral_interrupts::ECB => self.ecb.handle_interrupt(),
-            crate::peripheral_interrupts::POWER_CLOCK => self.pwr_clk.handle_interrupt(),
+            crate::peripheral_interrupts::POWER_CLOCK => {
+                self.pwr_clk.handle_interrupt();
+                self.clock.handle_interrupt();
+            }
             crate::peripheral_interrupts::RADIO => match self.ble_radio.is_enabled() {
                 false => (),
                 true => self.ble_radio.handle_interrupt(),
@@ -19,7 +19,22 @@
 //! * 32.768 kHz crystal oscillator (LFXO)
 //! * 32.768 kHz synthesized from HFCLK (LFSYNT)
 //!
+//! Peripherals that need the HFXO (e.g. the radio) should use
+//! [`Clock::high_request`] and [`Clock::high_release`] rather than starting
+//! and stopping it directly. The HFXO is kept running as long as at least one
+//! request is outstanding, and stopped (falling back to HFINT) otherwise.
+//!
+//! Starting a crystal oscillator takes a while, up to a few hundred
+//! milliseconds for the LFXO. Rather than spinning on [`Clock::low_started`]
+//! or [`Clock::high_started`], a board can enable the `LFCLKSTARTED` and
+//! `HFCLKSTARTED` interrupts and get a [`ClockClient`] callback once the
+//! clock has started, and carry on with other initialization in the meantime.
+//! [`Clock::high_request_start`] takes a HFXO request without waiting for it
+//! to start; [`Clock::high_request`] waits, and so only blocks if the HFXO has
+//! not started yet by the time a peripheral needs it.
+//!
 
+use core::cell::Cell;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
 use kernel::utilities::registers::{
@@ -37,8 +52,8 @@ register_structs! {
         (0x014 => tasks_ctstart: WriteOnly<u32, Control::Register>),
         (0x018 => tasks_ctstop: WriteOnly<u32, Control::Register>),
         (0x01C => _reserved1),
-        (0x100 => events_hfclkstarted: ReadOnly<u32, Status::Register>),
-        (0x104 => events_lfclkstarted: ReadOnly<u32, Status::Register>),
+        (0x100 => events_hfclkstarted: ReadWrite<u32, Status::Register>),
+        (0x104 => events_lfclkstarted: ReadWrite<u32, Status::Register>),
         (0x108 => _reserved2),
         (0x10C => events_done: ReadOnly<u32, Status::Register>),
         (0x110 => events_ctto: ReadOnly<u32, Status::Register>),
@@ -130,6 +145,7 @@ const CLOCK_BASE: StaticRef<ClockRegisters> =
     unsafe { StaticRef::new(0x40000000 as *const ClockRegisters) };
 
 /// Interrupt sources
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum InterruptField {
     HFCLKSTARTED = 1 << 0,
     LFCLKSTARTED = 1 << 1,
@@ -155,14 +171,24 @@ pub enum HighClockSource {
 pub struct Clock {
     registers: StaticRef<ClockRegisters>,
     client: OptionalCell<&'static dyn ClockClient>,
+    high_requests: Cell<usize>,
+}
+
+impl nrf5x::temperature::HighClock for Clock {
+    fn request(&self) {
+        self.high_request();
+    }
+
+    fn release(&self) {
+        self.high_release();
+    }
 }
 
 pub trait ClockClient {
-    /// All clock interrupts are control signals, e.g., when
-    /// a clock has started etc. We don't actually handle any
-    /// of them for now, but keep this trait in place for if we
-    /// do need to in the future.
-    fn event(&self);
+    /// Called when an enabled clock interrupt fires. Only the
+    /// `HFCLKSTARTED` and `LFCLKSTARTED` events are reported; each
+    /// interrupt is disabled again once it has been reported.
+    fn event(&self, event: InterruptField);
 }
 
 impl Clock {
@@ -171,6 +197,7 @@ impl Clock {
         Clock {
             registers: CLOCK_BASE,
             client: OptionalCell::empty(),
+            high_requests: Cell::new(0),
         }
     }
 
@@ -198,20 +225,51 @@ impl Clock {
     pub fn interrupt_disable(&self, interrupt: InterruptField) {
         // this is a little too verbose
         match interrupt {
-            InterruptField::CTTO => self.registers.intenset.write(Interrupt::CTTO::SET),
-            InterruptField::DONE => self.registers.intenset.write(Interrupt::DONE::SET),
+            InterruptField::CTTO => self.registers.intenclr.write(Interrupt::CTTO::SET),
+            InterruptField::DONE => self.registers.intenclr.write(Interrupt::DONE::SET),
             InterruptField::HFCLKSTARTED => {
-                self.registers.intenset.write(Interrupt::HFCLKSTARTED::SET)
+                self.registers.intenclr.write(Interrupt::HFCLKSTARTED::SET)
             }
             InterruptField::LFCLKSTARTED => {
-                self.registers.intenset.write(Interrupt::LFCLKSTARTED::SET)
+                self.registers.intenclr.write(Interrupt::LFCLKSTARTED::SET)
             }
         }
     }
 
+    /// Handle a `POWER_CLOCK` interrupt.
+    ///
+    /// Only events whose interrupt is enabled are handled, so that boards
+    /// polling `low_started()` or `high_started()` are not affected.
+    pub fn handle_interrupt(&self) {
+        if self.registers.intenset.is_set(Interrupt::HFCLKSTARTED)
+            && self.registers.events_hfclkstarted.is_set(Status::READY)
+        {
+            self.registers
+                .events_hfclkstarted
+                .write(Status::READY::CLEAR);
+            self.interrupt_disable(InterruptField::HFCLKSTARTED);
+            self.client
+                .map(|client| client.event(InterruptField::HFCLKSTARTED));
+        }
+
+        if self.registers.intenset.is_set(Interrupt::LFCLKSTARTED)
+            && self.registers.events_lfclkstarted.is_set(Status::READY)
+        {
+            self.registers
+                .events_lfclkstarted
+                .write(Status::READY::CLEAR);
+            self.interrupt_disable(InterruptField::LFCLKSTARTED);
+            self.client
+                .map(|client| client.event(InterruptField::LFCLKSTARTED));
+        }
+    }
+
     /// Start the high frequency clock - specifically HFXO, and sets the high frequency
     /// clock source to HFXO
     pub fn high_start(&self) {
+        self.registers
+            .events_hfclkstarted
+            .write(Status::READY::CLEAR);
         self.registers.tasks_hfclkstart.write(Control::ENABLE::SET);
     }
 
@@ -242,8 +300,44 @@ impl Clock {
             .matches_all(HfClkStat::STATE::RUNNING)
     }
 
+    /// Request the HFXO to be running, starting it if this is the first
+    /// outstanding request. Blocks until the HFXO is the active high
+    /// frequency clock source.
+    pub fn high_request(&self) {
+        self.high_request_start();
+        while !self
+            .registers
+            .hfclkstat
+            .matches_all(HfClkStat::STATE::RUNNING + HfClkStat::SRC::XTAL)
+        {}
+    }
+
+    /// Request the HFXO to be running like `high_request`, but without
+    /// waiting for it to start. If the `HFCLKSTARTED` interrupt is enabled
+    /// the client is notified once it has.
+    pub fn high_request_start(&self) {
+        let requests = self.high_requests.get();
+        if requests == 0 {
+            self.high_start();
+        }
+        self.high_requests.set(requests + 1);
+    }
+
+    /// Release a request made with `high_request`, stopping the HFXO once no
+    /// requests are outstanding.
+    pub fn high_release(&self) {
+        let requests = self.high_requests.get().saturating_sub(1);
+        self.high_requests.set(requests);
+        if requests == 0 {
+            self.high_stop();
+        }
+    }
+
     /// Start the low frequency clock
     pub fn low_start(&self) {
+        self.registers
+            .events_lfclkstarted
+            .write(Status::READY::CLEAR);
         self.registers.tasks_lfclkstart.write(Control::ENABLE::SET);
     }
 
@@ -411,6 +411,21 @@ impl Ficr {
         }
     }
 
+    /// Size of the code flash in bytes.
+    pub fn flash_size(&self) -> u32 {
+        let page_size = self.registers.codepagesize.read(CodePageSize::CODEPAGESIZE);
+        let pages = self.registers.codesize.read(CodeSize::CODESIZE);
+        page_size.saturating_mul(pages)
+    }
+
+    /// Size of the RAM in bytes, or 0 if the RAM variant is not recorded.
+    pub fn ram_size(&self) -> u32 {
+        match self.registers.info_ram.get() {
+            0xffffffff => 0,
+            kib => kib.saturating_mul(1024),
+        }
+    }
+
     pub fn id(&self) -> [u8; 8] {
         let lo = self.registers.deviceid0.read(DeviceId0::DEVICEID);
         let hi = self.registers.deviceid1.read(DeviceId1::DEVICEID);
@@ -23,6 +23,7 @@ pub mod spi;
 pub mod uart;
 pub mod uicr;
 pub mod usbd;
+pub mod wdt;
 
 pub use crate::crt1::init;
 pub use nrf5x::{
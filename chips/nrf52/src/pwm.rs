@@ -175,6 +175,14 @@ const PWM0_BASE: StaticRef<PwmRegisters> =
 /// be passed a pointer.
 static mut DUTY_CYCLES: [u16; 4] = [0; 4];
 
+/// Bit 15 of a sequence value selects the falling edge polarity, where the
+/// output starts each period high and falls on the compare match.
+const SEQ_POLARITY_FALLING_EDGE: u16 = 1 << 15;
+
+/// A compare value above any usable COUNTERTOP. It never matches, so the
+/// output stays at its starting level for the whole period.
+const SEQ_COMPARE_NEVER: u16 = 0x7FFF;
+
 pub struct Pwm {
     registers: StaticRef<PwmRegisters>,
 }
@@ -207,7 +215,16 @@ impl Pwm {
         //                               duty_cycle
         //  dc_out = counter_top * (1 -  ---------- )
         //                                5333333
-        let dc_out = counter_top - ((3 * duty_cycle) / frequency_hz);
+        //
+        // At 0% and 100% the integer division can leave a one tick pulse in
+        // every period, so hold the output at a constant level instead.
+        let dc_out = if duty_cycle == 0 {
+            SEQ_COMPARE_NEVER
+        } else if duty_cycle >= hil::pwm::Pwm::get_maximum_duty_cycle(self) {
+            SEQ_COMPARE_NEVER | SEQ_POLARITY_FALLING_EDGE
+        } else {
+            (counter_top - ((3 * duty_cycle) / frequency_hz)) as u16
+        };
 
         // Configure the pin
         self.registers.psel_out[0].set(*pin);
@@ -231,7 +248,7 @@ impl Pwm {
 
         // Setup the duty cycles
         unsafe {
-            DUTY_CYCLES[0] = dc_out as u16;
+            DUTY_CYCLES[0] = dc_out;
             self.registers
                 .seq0
                 .seq_ptr
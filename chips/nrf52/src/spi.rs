@@ -24,8 +24,20 @@
 //! * ✓ get_polarity
 //! * ✓ set_phase
 //! * ✓ get_phase
-//! * hold_low
-//! * release_low
+//! * ✓ hold_low
+//! * ✓ release_low
+//!
+//! EasyDMA can move at most 255 bytes per transfer on the nRF52832 (the
+//! `MAXCNT` registers are 8 bits wide there), so longer transfers are split
+//! into chunks that are run back to back with chip select held low. The
+//! client sees a single `read_write_done()` for the whole transfer. The
+//! nRF52833 and nRF52840 call `set_easydma_limits()` to use their 16 bit
+//! `MAXCNT` registers instead.
+//!
+//! On the nRF52832 a transfer that receives one byte and sends at most one
+//! clocks out an additional byte (anomaly 58), which the device on the bus
+//! would see. Chunks are split so that a longer transfer never ends in such a
+//! chunk, and a transfer that is only one byte long is rejected with `SIZE`.
 //!
 //! Author
 //! -------------------
@@ -51,6 +63,10 @@ const INSTANCES: [StaticRef<SpimRegisters>; 3] = unsafe {
     ]
 };
 
+/// Largest number of bytes EasyDMA moves in one chunk on the nRF52832. The
+/// other nRF52 chips allow larger transfers.
+const NRF52832_DMA_MAX_LEN: usize = 255;
+
 #[repr(C)]
 struct SpimRegisters {
     _reserved0: [u8; 16],                            // reserved
@@ -245,6 +261,18 @@ pub struct SPIM<'a> {
     tx_buf: TakeCell<'static, [u8]>,
     rx_buf: TakeCell<'static, [u8]>,
     transfer_len: Cell<usize>,
+    /// Number of bytes to send and receive in the current transfer.
+    tx_len: Cell<usize>,
+    rx_len: Cell<usize>,
+    /// Start and length of the chunk that is in flight.
+    offset: Cell<usize>,
+    chunk_len: Cell<usize>,
+    /// Keep chip select low after the current transfer completes.
+    hold_cs: Cell<bool>,
+    /// Largest number of bytes EasyDMA moves in one chunk.
+    dma_max_len: Cell<usize>,
+    /// Whether one byte reads clock out an extra byte (anomaly 58).
+    anomaly_58: Cell<bool>,
 }
 
 impl<'a> SPIM<'a> {
@@ -257,9 +285,60 @@ impl<'a> SPIM<'a> {
             tx_buf: TakeCell::empty(),
             rx_buf: TakeCell::empty(),
             transfer_len: Cell::new(0),
+            tx_len: Cell::new(0),
+            rx_len: Cell::new(0),
+            offset: Cell::new(0),
+            chunk_len: Cell::new(0),
+            hold_cs: Cell::new(false),
+            dma_max_len: Cell::new(NRF52832_DMA_MAX_LEN),
+            anomaly_58: Cell::new(true),
         }
     }
 
+    /// Sets the largest number of bytes EasyDMA can move at once, and whether
+    /// the chip has anomaly 58. The defaults are those of the nRF52832, which
+    /// work on every nRF52 but split transfers into smaller chunks and reject
+    /// one byte reads.
+    pub fn set_easydma_limits(&self, max_len: usize, anomaly_58: bool) {
+        self.dma_max_len.set(max_len);
+        self.anomaly_58.set(anomaly_58);
+    }
+
+    /// Points EasyDMA at the next chunk of the transfer, starting at
+    /// `offset`.
+    fn setup_chunk(&self, offset: usize) {
+        let (tx_len, rx_len) = (self.tx_len.get(), self.rx_len.get());
+        let remaining = cmp::max(tx_len, rx_len) - offset;
+        let mut chunk = cmp::min(remaining, self.dma_max_len.get());
+        if self.anomaly_58.get() && remaining - chunk == 1 {
+            // Leave two bytes for the last chunk so it does not hit
+            // anomaly 58.
+            chunk -= 1;
+        }
+
+        let tx_chunk = cmp::min(tx_len.saturating_sub(offset), chunk);
+        self.tx_buf.map(|buf| {
+            self.registers
+                .txd_ptr
+                .set(buf[cmp::min(offset, tx_len)..].as_ptr());
+        });
+        self.registers
+            .txd_maxcnt
+            .write(MAXCNT::MAXCNT.val(tx_chunk as u32));
+
+        let rx_chunk = cmp::min(rx_len.saturating_sub(offset), chunk);
+        let rx_ptr = self.rx_buf.map_or(ptr::null_mut(), |buf| {
+            buf[cmp::min(offset, rx_len)..].as_mut_ptr()
+        });
+        self.registers.rxd_ptr.set(rx_ptr);
+        self.registers
+            .rxd_maxcnt
+            .write(MAXCNT::MAXCNT.val(rx_chunk as u32));
+
+        self.offset.set(offset);
+        self.chunk_len.set(chunk);
+    }
+
     #[inline(never)]
     pub fn handle_interrupt(&self) {
         if self.registers.events_end.is_set(EVENT::EVENT) {
@@ -270,9 +349,20 @@ impl<'a> SPIM<'a> {
                 return;
             }
 
-            self.chip_select.map(|cs| cs.set());
             self.registers.events_end.write(EVENT::EVENT::CLEAR);
 
+            let offset = self.offset.get() + self.chunk_len.get();
+            if offset < cmp::max(self.tx_len.get(), self.rx_len.get()) {
+                // Keep chip select low and move on to the next chunk.
+                self.setup_chunk(offset);
+                self.registers.tasks_start.write(TASK::TASK::SET);
+                return;
+            }
+
+            if !self.hold_cs.get() {
+                self.chip_select.map(|cs| cs.set());
+            }
+
             // When we are no longer active or busy we can disable the
             // peripheral.
             self.disable();
@@ -361,34 +451,31 @@ impl<'a> hil::spi::SpiMaster<'a> for SPIM<'a> {
         debug_assert!(self.tx_buf.is_none());
         debug_assert!(self.rx_buf.is_none());
 
+        // Setup transmit and receive lengths
+        let tx_len = cmp::min(len, tx_buf.len());
+        let rx_len = rx_buf.as_ref().map_or(0, |buf| cmp::min(len, buf.len()));
+        if self.anomaly_58.get() && rx_len == 1 && tx_len <= 1 {
+            // This would clock out a second byte.
+            return Err((ErrorCode::SIZE, tx_buf, rx_buf));
+        }
+
         // Clear (set to low) chip-select
         if self.chip_select.is_none() {
             return Err((ErrorCode::NODEVICE, tx_buf, rx_buf));
         }
         self.chip_select.map(|cs| cs.clear());
 
-        // Setup transmit data registers
-        let tx_len: u32 = cmp::min(len, tx_buf.len()) as u32;
-        self.registers.txd_ptr.set(tx_buf.as_ptr());
-        self.registers.txd_maxcnt.write(MAXCNT::MAXCNT.val(tx_len));
+        self.tx_len.set(tx_len);
+        self.rx_len.set(rx_len);
+        self.transfer_len.set(match rx_buf {
+            None => tx_len,
+            Some(_) => cmp::min(tx_len, rx_len),
+        });
         self.tx_buf.replace(tx_buf);
+        self.rx_buf.put(rx_buf);
 
-        // Setup receive data registers
-        match rx_buf {
-            None => {
-                self.registers.rxd_ptr.set(ptr::null_mut());
-                self.registers.rxd_maxcnt.write(MAXCNT::MAXCNT.val(0));
-                self.transfer_len.set(tx_len as usize);
-                self.rx_buf.put(None);
-            }
-            Some(buf) => {
-                self.registers.rxd_ptr.set(buf.as_mut_ptr());
-                let rx_len: u32 = cmp::min(len, buf.len()) as u32;
-                self.registers.rxd_maxcnt.write(MAXCNT::MAXCNT.val(rx_len));
-                self.transfer_len.set(cmp::min(tx_len, rx_len) as usize);
-                self.rx_buf.put(Some(buf));
-            }
-        }
+        // Setup data registers for the first chunk
+        self.setup_chunk(0);
 
         // Start the transfer
         self.busy.set(true);
@@ -472,14 +559,13 @@ impl<'a> hil::spi::SpiMaster<'a> for SPIM<'a> {
         }
     }
 
-    // The following two trait functions are not implemented for
-    // SAM4L, and appear to not provide much functionality. Let's not
-    // bother implementing them unless needed.
+    /// Keep chip select low after each transfer completes.
     fn hold_low(&self) {
-        unimplemented!("SPI: Use `read_write_bytes()` instead.");
+        self.hold_cs.set(true);
     }
 
+    /// Raise chip select again once the next transfer completes.
     fn release_low(&self) {
-        unimplemented!("SPI: Use `read_write_bytes()` instead.");
+        self.hold_cs.set(false);
     }
 }
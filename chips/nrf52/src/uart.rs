@@ -239,7 +239,7 @@ impl<'a> Uarte<'a> {
         self.enable_uart();
     }
 
-    fn set_baud_rate(&self, baud_rate: u32) {
+    fn set_baud_rate(&self, baud_rate: u32) -> Result<(), ErrorCode> {
         match baud_rate {
             1200 => self.registers.baudrate.set(0x0004F000),
             2400 => self.registers.baudrate.set(0x0009D000),
@@ -257,8 +257,9 @@ impl<'a> Uarte<'a> {
             460800 => self.registers.baudrate.set(0x07400000),
             921600 => self.registers.baudrate.set(0x0F000000),
             1000000 => self.registers.baudrate.set(0x10000000),
-            _ => self.registers.baudrate.set(0x01D60000), //setting default to 115200
+            _ => return Err(ErrorCode::INVAL),
         }
+        Ok(())
     }
 
     // Enable UART peripheral, this need to disabled for low power applications
@@ -482,9 +483,7 @@ impl<'a> uart::Configure for Uarte<'a> {
             return Err(ErrorCode::NOSUPPORT);
         }
 
-        self.set_baud_rate(params.baud_rate);
-
-        Ok(())
+        self.set_baud_rate(params.baud_rate)
     }
 }
 
@@ -0,0 +1,156 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Watchdog Timer
+//!
+//! <https://infocenter.nordicsemi.com/topic/ps_nrf52840/wdt.html>
+//!
+//! The watchdog resets the chip if the kernel loop stops running, for example
+//! because a capsule is stuck in a busy loop. It is enabled by the board by
+//! setting a timeout with [`Wdt::set_timeout_ms`] and returning the `Wdt` as
+//! the platform's `WatchDog`. The kernel then starts it when the kernel loop
+//! starts and reloads it on every iteration.
+//!
+//! Once started, the nRF52 watchdog can not be stopped or reconfigured; only
+//! a reset does. Enabling it is therefore a deliberate board choice.
+//!
+//! The kernel reloads the watchdog only between iterations of the kernel
+//! loop, so the timeout must be longer than the longest operation that
+//! blocks the CPU. The longest in this chip is a flash page erase, which
+//! blocks the CPU for up to 85 ms. Timeouts shorter than [`MIN_TIMEOUT_MS`]
+//! are raised to it. The watchdog is paused while the CPU sleeps and while it
+//! is halted by a debugger.
+//!
+//! ```rust,ignore
+//! base_peripherals.wdt.set_timeout_ms(1000);
+//!
+//! impl KernelResources<..> for Platform {
+//!     type WatchDog = nrf52::wdt::Wdt;
+//!
+//!     fn watchdog(&self) -> &Self::WatchDog {
+//!         &self.base_peripherals.wdt
+//!     }
+//! }
+//! ```
+
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::registers::interfaces::{Readable, Writeable};
+use kernel::utilities::registers::{
+    register_bitfields, register_structs, ReadOnly, ReadWrite, WriteOnly,
+};
+use kernel::utilities::StaticRef;
+
+const WDT_BASE: StaticRef<WdtRegisters> =
+    unsafe { StaticRef::new(0x40010000 as *const WdtRegisters) };
+
+/// The value to write to a reload request register to reload the watchdog.
+const RELOAD_VALUE: u32 = 0x6E524635;
+
+/// The watchdog counts at the 32.768 kHz low frequency clock.
+const WDT_FREQUENCY_HZ: u64 = 32768;
+
+/// The shortest timeout the watchdog is configured with, long enough to not
+/// fire during a flash page erase.
+pub const MIN_TIMEOUT_MS: u32 = 100;
+
+register_structs! {
+    WdtRegisters {
+        (0x000 => task_start: WriteOnly<u32, Task::Register>),
+        (0x004 => _reserved0),
+        (0x400 => runstatus: ReadOnly<u32, RunStatus::Register>),
+        (0x404 => _reserved1),
+        (0x504 => crv: ReadWrite<u32>),
+        (0x508 => rren: ReadWrite<u32, ReloadRequests::Register>),
+        (0x50C => config: ReadWrite<u32, Config::Register>),
+        (0x510 => _reserved2),
+        (0x600 => rr: [WriteOnly<u32>; 8]),
+        (0x620 => @END),
+    }
+}
+
+register_bitfields! [u32,
+    Task [
+        ENABLE OFFSET(0) NUMBITS(1)
+    ],
+    RunStatus [
+        RUNNING OFFSET(0) NUMBITS(1)
+    ],
+    ReloadRequests [
+        RR0 OFFSET(0) NUMBITS(1)
+    ],
+    Config [
+        /// Keep the watchdog running while the CPU is sleeping
+        SLEEP OFFSET(0) NUMBITS(1) [
+            Pause = 0,
+            Run = 1
+        ],
+        /// Keep the watchdog running while the CPU is halted by the debugger
+        HALT OFFSET(3) NUMBITS(1) [
+            Pause = 0,
+            Run = 1
+        ]
+    ]
+];
+
+pub struct Wdt {
+    registers: StaticRef<WdtRegisters>,
+    timeout_ms: OptionalCell<u32>,
+}
+
+impl Wdt {
+    pub const fn new() -> Wdt {
+        Wdt {
+            registers: WDT_BASE,
+            timeout_ms: OptionalCell::empty(),
+        }
+    }
+
+    /// Enables the watchdog with the given timeout. It is started by the
+    /// kernel when the kernel loop starts, and can not be stopped afterwards.
+    pub fn set_timeout_ms(&self, timeout_ms: u32) {
+        self.timeout_ms.set(timeout_ms.max(MIN_TIMEOUT_MS));
+    }
+
+    fn is_running(&self) -> bool {
+        self.registers.runstatus.is_set(RunStatus::RUNNING)
+    }
+
+    fn start(&self, timeout_ms: u32) {
+        // The configuration can only be written while the watchdog is
+        // stopped; if it is already running (e.g. started by a
+        // bootloader), keep it as is.
+        if self.is_running() {
+            return;
+        }
+
+        // The timeout is (CRV + 1) / 32768 s.
+        let crv = (timeout_ms as u64 * WDT_FREQUENCY_HZ / 1000).saturating_sub(1);
+        self.registers.crv.set(crv.min(u32::MAX as u64) as u32);
+        self.registers.rren.write(ReloadRequests::RR0::SET);
+        self.registers
+            .config
+            .write(Config::SLEEP::Pause + Config::HALT::Pause);
+        self.registers.task_start.write(Task::ENABLE::SET);
+    }
+
+    fn feed(&self) {
+        self.registers.rr[0].set(RELOAD_VALUE);
+    }
+}
+
+impl kernel::platform::watchdog::WatchDog for Wdt {
+    fn setup(&self) {
+        self.timeout_ms.map(|timeout_ms| self.start(timeout_ms));
+    }
+
+    fn tickle(&self) {
+        if self.is_running() {
+            self.feed();
+        }
+    }
+
+    // The watchdog can not be stopped, but it does not count while the CPU
+    // sleeps, so there is nothing to do before sleeping.
+    fn suspend(&self) {}
+}
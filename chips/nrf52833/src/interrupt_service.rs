@@ -21,6 +21,10 @@ impl<'a> Nrf52833DefaultPeripherals<'a> {
     }
     // Necessary for setting up circular dependencies
     pub fn init(&'static self) {
+        // EasyDMA MAXCNT is 16 bits wide, and there is no anomaly 58.
+        for spim in [&self.nrf52.spim0, &self.nrf52.spim2] {
+            spim.set_easydma_limits(0xFFFF, false);
+        }
         self.nrf52.init();
     }
 }
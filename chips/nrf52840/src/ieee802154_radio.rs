@@ -103,6 +103,16 @@ pub const ACK_BUF_SIZE: usize =
 // forever when we tried to return the frame buffer.
 const BUF_PREFIX_SIZE: u32 = 1;
 
+/// Offset in dBm of the energy detect level. The nRF52840 product
+/// specification (section 6.20.12.5) gives the energy in dBm as
+/// `ED_RSSIOFFS + EDLVL`.
+const ED_RSSIOFFS: i16 = -94;
+
+/// Converts an energy detect level to dBm.
+fn ed_level_to_dbm(level: u32) -> i8 {
+    (ED_RSSIOFFS + level as i16).clamp(i8::MIN.into(), i8::MAX.into()) as i8
+}
+
 #[repr(C)]
 struct RadioRegisters {
     /// Enable Radio in TX mode
@@ -140,8 +150,14 @@ struct RadioRegisters {
     /// Stop the bit counter
     /// - Address: 0x030 - 0x034
     task_ccastop: WriteOnly<u32, Task::Register>,
+    /// Start the energy detect measurement used in IEEE 802.15.4 mode
+    /// - Address: 0x034 - 0x038
+    task_edstart: WriteOnly<u32, Task::Register>,
+    /// Stop the energy detect measurement
+    /// - Address: 0x038 - 0x03c
+    task_edstop: WriteOnly<u32, Task::Register>,
     /// Reserved
-    _reserved2: [u32; 51],
+    _reserved2: [u32; 49],
     /// Radio has ramped up and is ready to be started
     /// - Address: 0x100 - 0x104
     event_ready: ReadWrite<u32, Event::Register>,
@@ -182,8 +198,12 @@ struct RadioRegisters {
     /// IEEE 802.15.4 length field received
     /// - Address: 0x138 - 0x13c
     event_framestart: ReadWrite<u32, Event::Register>,
-    /// Reserved
-    _reserved5: [u32; 2],
+    /// Sampling of energy detection complete
+    /// - Address: 0x13c - 0x140
+    event_edend: ReadWrite<u32, Event::Register>,
+    /// The sampling of energy detection has stopped
+    /// - Address: 0x140 - 0x144
+    event_edstopped: ReadWrite<u32, Event::Register>,
     /// Wireless medium in idle - clear to send
     /// - Address: 0x144-0x148
     event_ccaidle: ReadWrite<u32, Event::Register>,
@@ -310,12 +330,20 @@ struct RadioRegisters {
     /// - Address: 0x650 - 0x654
     modecnf0: ReadWrite<u32, RadioModeConfig::Register>,
     /// Reserved
-    _reserved16: [u32; 6],
+    _reserved16: [u32; 2],
+    /// IEEE 802.15.4 energy detect loop count
+    /// - Address: 0x65C - 0x660
+    edcnt: ReadWrite<u32, EnergyDetectCount::Register>,
+    /// IEEE 802.15.4 energy detect level
+    /// - Address: 0x660 - 0x664
+    edsample: ReadOnly<u32, EnergyDetectSample::Register>,
+    /// Reserved
+    _reserved17: [u32; 2],
     /// Clear Channel Assesment (CCA) control register
     /// - Address: 0x66C - 0x670
     ccactrl: ReadWrite<u32, CCAControl::Register>,
     /// Reserved
-    _reserved17: [u32; 611],
+    _reserved18: [u32; 611],
     /// Peripheral power control
     /// - Address: 0xFFC - 0x1000
     power: ReadWrite<u32, Task::Register>,
@@ -384,6 +412,8 @@ register_bitfields! [u32,
         CRCERROR OFFSET(13) NUMBITS(1),
         /// CCAIDLE event
         FRAMESTART OFFSET(14) NUMBITS(1),
+        /// EDEND event
+        EDEND OFFSET(15) NUMBITS(1),
         /// CCAIDLE event
         CCAIDLE OFFSET(17) NUMBITS(1),
         /// CCABUSY event
@@ -578,6 +608,16 @@ register_bitfields! [u32,
         /// RSSI sample result
         RSSISAMPLE OFFSET(0) NUMBITS(7)
     ],
+    /// Energy detect loop count register
+    EnergyDetectCount [
+        /// Number of extra 128 us energy detect iterations
+        EDCNT OFFSET(0) NUMBITS(21)
+    ],
+    /// Energy detect level register
+    EnergyDetectSample [
+        /// Highest energy level measured during energy detection
+        EDLVL OFFSET(0) NUMBITS(8)
+    ],
     /// Radio state register
     State [
         /// Current radio state
@@ -688,11 +728,16 @@ pub struct Radio<'a> {
     registers: StaticRef<RadioRegisters>,
     rx_client: OptionalCell<&'a dyn radio::RxClient>,
     tx_client: OptionalCell<&'a dyn radio::TxClient>,
+    energy_detect_client: OptionalCell<&'a dyn radio::EnergyDetectClient>,
+    /// An energy detection is in progress.
+    energy_detecting: Cell<bool>,
     config_client: OptionalCell<&'a dyn radio::ConfigClient>,
     power_client: OptionalCell<&'a dyn radio::PowerClient>,
     tx_power: Cell<TxPower>,
     tx_buf: TakeCell<'static, [u8]>,
     rx_buf: TakeCell<'static, [u8]>,
+    /// RSSI in dBm of the frame in `rx_buf`, kept while an ACK is sent.
+    rx_rssi: Cell<i8>,
     ack_buf: TakeCell<'static, [u8]>,
     addr: Cell<u16>,
     addr_long: Cell<[u8; 8]>,
@@ -705,6 +750,8 @@ pub struct Radio<'a> {
     state: Cell<RadioState>,
     deferred_call: DeferredCall,
     deferred_call_operation: OptionalCell<DeferredOperation>,
+    clock: OptionalCell<&'a crate::clock::Clock>,
+    high_clock_requested: Cell<bool>,
 }
 
 impl<'a> AlarmClient for Radio<'a> {
@@ -721,11 +768,14 @@ impl<'a> Radio<'a> {
             registers: RADIO_BASE,
             rx_client: OptionalCell::empty(),
             tx_client: OptionalCell::empty(),
+            energy_detect_client: OptionalCell::empty(),
+            energy_detecting: Cell::new(false),
             config_client: OptionalCell::empty(),
             power_client: OptionalCell::empty(),
             tx_power: Cell::new(TxPower::ZerodBm),
             tx_buf: TakeCell::empty(),
             rx_buf: TakeCell::empty(),
+            rx_rssi: Cell::new(0),
             ack_buf: TakeCell::new(ack_buf),
             addr: Cell::new(0),
             addr_long: Cell::new([0x00; 8]),
@@ -738,6 +788,8 @@ impl<'a> Radio<'a> {
             state: Cell::new(RadioState::OFF),
             deferred_call: DeferredCall::new(),
             deferred_call_operation: OptionalCell::empty(),
+            clock: OptionalCell::empty(),
+            high_clock_requested: Cell::new(false),
         }
     }
 
@@ -745,6 +797,11 @@ impl<'a> Radio<'a> {
         self.timer0.set(timer);
     }
 
+    /// Set the clock used to request the HFXO while the radio is powered.
+    pub fn set_clock_ref(&self, clock: &'a crate::clock::Clock) {
+        self.clock.set(clock);
+    }
+
     pub fn is_enabled(&self) -> bool {
         self.registers
             .mode
@@ -761,12 +818,20 @@ impl<'a> Radio<'a> {
 
         // Instruct radio hardware to automatically progress from RXIDLE to RX
         // state upon receipt of internal `READY` signal after radio ramp-up completes.
-        self.registers.shorts.write(Shortcut::READY_START::SET);
+        // The RSSI of each frame is sampled when its SFD is received.
+        self.registers
+            .shorts
+            .write(Shortcut::READY_START::SET + Shortcut::ADDRESS_RSSISTART::SET);
 
         self.registers.task_rxen.write(Task::ENABLE::SET);
     }
 
     fn radio_on(&self) {
+        // The radio needs the HFXO while it is powered.
+        if !self.high_clock_requested.replace(true) {
+            self.clock.map(|clock| clock.high_request());
+        }
+
         // reset and enable power
         self.registers.power.write(Task::ENABLE::CLEAR);
         self.registers.power.write(Task::ENABLE::SET);
@@ -776,6 +841,10 @@ impl<'a> Radio<'a> {
         self.state.set(RadioState::OFF);
 
         self.registers.power.write(Task::ENABLE::CLEAR);
+
+        if self.high_clock_requested.replace(false) {
+            self.clock.map(|clock| clock.high_release());
+        }
     }
 
     fn radio_is_on(&self) -> bool {
@@ -808,6 +877,17 @@ impl<'a> Radio<'a> {
         let mut start_task = false;
         let mut rx_init = false;
 
+        // Energy detection runs alongside reception, so its event is checked
+        // before the state machine.
+        if self.registers.event_edend.is_set(Event::READY) {
+            self.registers.event_edend.write(Event::READY::CLEAR);
+            if self.energy_detecting.replace(false) {
+                let level = self.registers.edsample.read(EnergyDetectSample::EDLVL);
+                self.energy_detect_client
+                    .map(|client| client.energy_detect_done(Ok(ed_level_to_dbm(level))));
+            }
+        }
+
         match self.state.get() {
             // It should not be possible to receive an interrupt while the
             // tracked radio state is OFF.
@@ -857,8 +937,15 @@ impl<'a> Radio<'a> {
                     // LQI is found just after the data received.
                     let lqi = rbuf[data_len];
 
-                    // We drop the CRC bytes (the MFR) from our frame.
-                    let frame_len = data_len - radio::MFR_SIZE;
+                    // The RSSI was sampled at the SFD. RSSISAMPLE holds the
+                    // magnitude of the (negative) signal strength in dBm.
+                    let rssi = -(self.registers.rssisample.read(RssiSample::RSSISAMPLE) as i8);
+                    self.rx_rssi.set(rssi);
+
+                    // We drop the CRC bytes (the MFR) from our frame. A PHR
+                    // too short to hold the MFR yields an empty frame, which
+                    // the client drops.
+                    let frame_len = data_len.saturating_sub(radio::MFR_SIZE);
 
                     // 6th bit in the first byte of the MAC header determines if
                     // sender requested ACK. If so send ACK first before handing
@@ -910,6 +997,7 @@ impl<'a> Radio<'a> {
                                         self.rx_buf.take().unwrap(),
                                         frame_len,
                                         lqi,
+                                        Some(rssi),
                                         crc.is_ok(),
                                         Err(err),
                                     );
@@ -925,7 +1013,7 @@ impl<'a> Radio<'a> {
                         // receiving state to listen for new packets.
                         self.rx_client.map(|client| {
                             start_task = true;
-                            client.receive(rbuf, frame_len, lqi, crc.is_ok(), Ok(()));
+                            client.receive(rbuf, frame_len, lqi, Some(rssi), crc.is_ok(), Ok(()));
                         });
                     }
                 }
@@ -1048,11 +1136,12 @@ impl<'a> Radio<'a> {
                         // See the RX case above for how these values are set.
                         let data_len = (rbuf[radio::PHR_OFFSET] & 0x7F) as usize;
                         let lqi = rbuf[data_len];
-                        let frame_len = data_len - radio::MFR_SIZE;
+                        let frame_len = data_len.saturating_sub(radio::MFR_SIZE);
+                        let rssi = self.rx_rssi.get();
 
                         // We know the CRC passed because otherwise we would not
                         // have transmitted an ACK.
-                        client.receive(rbuf, frame_len, lqi, true, Ok(()));
+                        client.receive(rbuf, frame_len, lqi, Some(rssi), true, Ok(()));
                     });
                 }
             }
@@ -1074,9 +1163,12 @@ impl<'a> Radio<'a> {
     }
 
     pub fn enable_interrupts(&self) {
-        self.registers
-            .intenset
-            .write(Interrupt::READY::SET + Interrupt::CCABUSY::SET + Interrupt::END::SET);
+        self.registers.intenset.write(
+            Interrupt::READY::SET
+                + Interrupt::CCABUSY::SET
+                + Interrupt::END::SET
+                + Interrupt::EDEND::SET,
+        );
     }
 
     pub fn enable_interrupt(&self, intr: u32) {
@@ -1238,7 +1330,7 @@ impl<'a> kernel::hil::radio::RadioConfig<'a> for Radio<'a> {
 
     fn busy(&self) -> bool {
         // `tx_buf` is only occupied when a transmission is underway.
-        self.tx_buf.is_some()
+        self.tx_buf.is_some() || self.energy_detecting.get()
     }
 
     fn set_config_client(&self, client: &'a dyn radio::ConfigClient) {
@@ -1344,6 +1436,26 @@ impl<'a> kernel::hil::radio::RadioData<'a> for Radio<'a> {
         self.tx_client.set(client);
     }
 
+    fn set_energy_detect_client(&self, client: &'a dyn radio::EnergyDetectClient) {
+        self.energy_detect_client.set(client);
+    }
+
+    /// Measures the energy on the current channel for 128 us, the 8 symbol
+    /// periods the IEEE 802.15.4 ED measurement requires.
+    fn energy_detect(&self) -> Result<(), ErrorCode> {
+        if self.state.get() == RadioState::OFF {
+            return Err(ErrorCode::OFF);
+        } else if self.state.get() != RadioState::RX || self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.energy_detecting.set(true);
+        self.registers.edcnt.write(EnergyDetectCount::EDCNT.val(0));
+        self.registers.event_edend.write(Event::READY::CLEAR);
+        self.registers.task_edstart.write(Task::ENABLE::SET);
+        Ok(())
+    }
+
     fn transmit(
         &self,
         buf: &'static mut [u8],
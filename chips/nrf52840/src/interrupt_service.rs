@@ -31,10 +31,15 @@ impl<'a> Nrf52840DefaultPeripherals<'a> {
     // Necessary for setting up circular dependencies
     pub fn init(&'static self) {
         self.ieee802154_radio.set_timer_ref(&self.nrf52.timer0);
+        self.ieee802154_radio.set_clock_ref(&self.nrf52.clock);
         self.nrf52.timer0.set_alarm_client(&self.ieee802154_radio);
         self.nrf52.pwr_clk.set_usb_client(&self.usbd);
         self.usbd.set_power_ref(&self.nrf52.pwr_clk);
         kernel::deferred_call::DeferredCallClient::register(&self.ieee802154_radio);
+        // EasyDMA MAXCNT is 16 bits wide, and there is no anomaly 58.
+        for spim in [&self.nrf52.spim0, &self.nrf52.spim2] {
+            spim.set_easydma_limits(0xFFFF, false);
+        }
         self.nrf52.init();
     }
 }
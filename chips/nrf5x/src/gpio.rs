@@ -17,6 +17,7 @@ use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{ReadWriteable, Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadWrite};
 use kernel::utilities::StaticRef;
+use kernel::ErrorCode;
 
 #[cfg(feature = "nrf51")]
 const NUM_GPIOTE: usize = 4;
@@ -424,6 +425,17 @@ impl hil::gpio::Configure for GPIOPin<'_> {
         }
     }
 
+    fn set_drive_strength(&self, strength: hil::gpio::DriveStrength) -> Result<(), ErrorCode> {
+        let drive = match strength {
+            hil::gpio::DriveStrength::Standard => PinConfig::DRIVE::S0S1,
+            hil::gpio::DriveStrength::High => PinConfig::DRIVE::H0H1,
+            hil::gpio::DriveStrength::StandardOpenDrain => PinConfig::DRIVE::S0D1,
+            hil::gpio::DriveStrength::HighOpenDrain => PinConfig::DRIVE::H0D1,
+        };
+        self.gpio_registers.pin_cnf[self.pin as usize].modify(drive);
+        Ok(())
+    }
+
     fn make_output(&self) -> hil::gpio::Configuration {
         self.gpio_registers.pin_cnf[self.pin as usize].modify(PinConfig::DIR::Output);
         hil::gpio::Configuration::Output
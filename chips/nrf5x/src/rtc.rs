@@ -3,12 +3,28 @@
 // Copyright Tock Contributors 2022.
 
 //! RTC driver, nRF5X-family
+//!
+//! The RTC has one 24-bit counter and four compare (CC) channels, which all
+//! compare against that counter. The channels are used as follows:
+//!
+//! - CC\[0\] backs the `Alarm` implemented by [`Rtc`] itself, which boards
+//!   usually virtualize with a `MuxAlarm`.
+//! - CC\[1\] to CC\[3\] are free, and can each be used as a dedicated
+//!   hardware `Alarm` through [`RtcCompare`]. This avoids going through the
+//!   virtual alarm mux for latency-sensitive users, such as 802.15.4 MAC
+//!   timing.
+//!
+//! All channels share the counter, so `now()` is the same for every alarm,
+//! and every alarm wraps with the counter at 2^24 ticks. Expiry times are
+//! computed with `Ticks24` arithmetic, which handles that overflow.
 
 use core::cell::Cell;
 use kernel::hil::time::{self, Alarm, Ticks, Time};
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
-use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
+use kernel::utilities::registers::{
+    register_bitfields, FieldValue, ReadOnly, ReadWrite, WriteOnly,
+};
 use kernel::utilities::StaticRef;
 use kernel::ErrorCode;
 
@@ -88,10 +104,35 @@ register_bitfields![u32,
     ]
 ];
 
+/// A compare channel of the RTC that is not used by [`Rtc`] itself.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CompareChannel {
+    CC1 = 1,
+    CC2 = 2,
+    CC3 = 3,
+}
+
+/// Compare channel used by the `Alarm` of [`Rtc`].
+const CC_ALARM: usize = 0;
+
+/// Number of compare channels.
+const NUM_CC: usize = 4;
+
+fn compare_interrupt(channel: usize) -> FieldValue<u32, Inte::Register> {
+    match channel {
+        0 => Inte::COMPARE0::SET,
+        1 => Inte::COMPARE1::SET,
+        2 => Inte::COMPARE2::SET,
+        _ => Inte::COMPARE3::SET,
+    }
+}
+
 pub struct Rtc<'a> {
     registers: StaticRef<RtcRegisters>,
     overflow_client: OptionalCell<&'a dyn time::OverflowClient>,
-    alarm_client: OptionalCell<&'a dyn time::AlarmClient>,
+    /// Clients of the compare channels, indexed by channel. The client of
+    /// CC\[0\] is the client of the `Alarm` of this `Rtc`.
+    alarm_clients: [OptionalCell<&'a dyn time::AlarmClient>; NUM_CC],
     enabled: Cell<bool>,
 }
 
@@ -100,7 +141,12 @@ impl<'a> Rtc<'a> {
         Self {
             registers: RTC1_BASE,
             overflow_client: OptionalCell::empty(),
-            alarm_client: OptionalCell::empty(),
+            alarm_clients: [
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+                OptionalCell::empty(),
+            ],
             enabled: Cell::new(false),
         }
     }
@@ -110,14 +156,54 @@ impl<'a> Rtc<'a> {
             self.registers.events_ovrflw.write(Event::READY::CLEAR);
             self.overflow_client.map(|client| client.overflow());
         }
-        if self.registers.events_compare[0].is_set(Event::READY) {
-            self.registers.intenclr.write(Inte::COMPARE0::SET);
-            self.registers.events_compare[0].write(Event::READY::CLEAR);
-            self.alarm_client.map(|client| {
-                client.alarm();
-            });
+        for channel in 0..NUM_CC {
+            // A compare event is raised whether or not its interrupt is
+            // enabled, so only handle the channels that are armed.
+            if self.compare_armed(channel)
+                && self.registers.events_compare[channel].is_set(Event::READY)
+            {
+                self.disarm_compare(channel);
+                self.alarm_clients[channel].map(|client| {
+                    client.alarm();
+                });
+            }
         }
     }
+
+    fn arm_compare(&self, channel: usize, reference: time::Ticks24, dt: time::Ticks24) {
+        const SYNC_TICS: u32 = 2;
+        let regs = &*self.registers;
+
+        let mut expire = reference.wrapping_add(dt);
+
+        let now = self.now();
+        let earliest_possible = now.wrapping_add(time::Ticks24::from(SYNC_TICS));
+
+        if !now.within_range(reference, expire) || expire.wrapping_sub(now).into_u32() <= SYNC_TICS
+        {
+            expire = earliest_possible;
+        }
+
+        regs.cc[channel].write(Counter::VALUE.val(expire.into_u32()));
+        regs.events_compare[channel].write(Event::READY::CLEAR);
+        regs.intenset.write(compare_interrupt(channel));
+    }
+
+    fn disarm_compare(&self, channel: usize) {
+        let regs = &*self.registers;
+        regs.intenclr.write(compare_interrupt(channel));
+        regs.events_compare[channel].write(Event::READY::CLEAR);
+    }
+
+    fn compare_armed(&self, channel: usize) -> bool {
+        self.registers
+            .intenset
+            .any_matching_bits_set(compare_interrupt(channel))
+    }
+
+    fn compare_value(&self, channel: usize) -> time::Ticks24 {
+        time::Ticks24::from(self.registers.cc[channel].read(Counter::VALUE))
+    }
 }
 
 impl Time for Rtc<'_> {
@@ -161,45 +247,90 @@ impl<'a> time::Counter<'a> for Rtc<'a> {
 
 impl<'a> Alarm<'a> for Rtc<'a> {
     fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
-        self.alarm_client.set(client);
+        self.alarm_clients[CC_ALARM].set(client);
     }
 
     fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
-        const SYNC_TICS: u32 = 2;
-        let regs = &*self.registers;
+        self.arm_compare(CC_ALARM, reference, dt);
+    }
 
-        let mut expire = reference.wrapping_add(dt);
+    fn get_alarm(&self) -> Self::Ticks {
+        self.compare_value(CC_ALARM)
+    }
 
-        let now = self.now();
-        let earliest_possible = now.wrapping_add(Self::Ticks::from(SYNC_TICS));
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        self.disarm_compare(CC_ALARM);
+        Ok(())
+    }
 
-        if !now.within_range(reference, expire) || expire.wrapping_sub(now).into_u32() <= SYNC_TICS
-        {
-            expire = earliest_possible;
+    fn is_armed(&self) -> bool {
+        self.compare_armed(CC_ALARM)
+    }
+
+    fn minimum_dt(&self) -> Self::Ticks {
+        // TODO: not tested, arbitrary value
+        Self::Ticks::from(10)
+    }
+}
+
+/// A dedicated hardware alarm on one of the free compare channels of the
+/// RTC.
+///
+/// The alarm counts with the same counter as the [`Rtc`], which must be
+/// started for it to run. Its interrupt is handled by the `Rtc`.
+///
+/// ```rust,ignore
+/// let mac_alarm = static_init!(
+///     nrf52840::rtc::RtcCompare<'static>,
+///     nrf52840::rtc::RtcCompare::new(&base_peripherals.rtc, nrf52840::rtc::CompareChannel::CC1)
+/// );
+/// ```
+pub struct RtcCompare<'a> {
+    rtc: &'a Rtc<'a>,
+    channel: usize,
+}
+
+impl<'a> RtcCompare<'a> {
+    pub const fn new(rtc: &'a Rtc<'a>, channel: CompareChannel) -> Self {
+        Self {
+            rtc,
+            channel: channel as usize,
         }
+    }
+}
 
-        regs.cc[0].write(Counter::VALUE.val(expire.into_u32()));
-        regs.events_compare[0].write(Event::READY::CLEAR);
-        regs.intenset.write(Inte::COMPARE0::SET);
+impl Time for RtcCompare<'_> {
+    type Frequency = time::Freq32KHz;
+    type Ticks = time::Ticks24;
+
+    fn now(&self) -> Self::Ticks {
+        self.rtc.now()
+    }
+}
+
+impl<'a> Alarm<'a> for RtcCompare<'a> {
+    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
+        self.rtc.alarm_clients[self.channel].set(client);
+    }
+
+    fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+        self.rtc.arm_compare(self.channel, reference, dt);
     }
 
     fn get_alarm(&self) -> Self::Ticks {
-        Self::Ticks::from(self.registers.cc[0].read(Counter::VALUE))
+        self.rtc.compare_value(self.channel)
     }
 
     fn disarm(&self) -> Result<(), ErrorCode> {
-        let regs = &*self.registers;
-        regs.intenclr.write(Inte::COMPARE0::SET);
-        regs.events_compare[0].write(Event::READY::CLEAR);
+        self.rtc.disarm_compare(self.channel);
         Ok(())
     }
 
     fn is_armed(&self) -> bool {
-        self.registers.evten.is_set(Inte::COMPARE0)
+        self.rtc.compare_armed(self.channel)
     }
 
     fn minimum_dt(&self) -> Self::Ticks {
-        // TODO: not tested, arbitrary value
-        Self::Ticks::from(10)
+        self.rtc.minimum_dt()
     }
 }
@@ -6,12 +6,18 @@
 //!
 //! Generates a simple temperature measurement without sampling
 //!
+//! The sensor is only accurate while the high frequency clock runs from the
+//! crystal oscillator (HFXO). If a [`HighClock`] is set with
+//! `set_clock_ref()`, the HFXO is requested for each measurement and
+//! released again once it completes.
+//!
 //! Authors
 //! -------------------
 //! * Niklas Adolfsson <niklasadolfsson1@gmail.com>
 //! * Fredrik Nilsson <frednils@student.chalmers.se>
 //! * Date: March 03, 2017
 
+use core::cell::Cell;
 use kernel::utilities::cells::OptionalCell;
 use kernel::utilities::registers::interfaces::{Readable, Writeable};
 use kernel::utilities::registers::{register_bitfields, ReadOnly, ReadWrite, WriteOnly};
@@ -109,9 +115,20 @@ register_bitfields! [u32,
     ]
 ];
 
+/// Control of the high frequency crystal oscillator (HFXO).
+pub trait HighClock {
+    /// Request the HFXO to be running, blocking until it is.
+    fn request(&self);
+
+    /// Release a request made with `request`.
+    fn release(&self);
+}
+
 pub struct Temp<'a> {
     registers: StaticRef<TempRegisters>,
     client: OptionalCell<&'a dyn kernel::hil::sensors::TemperatureClient>,
+    clock: OptionalCell<&'a dyn HighClock>,
+    high_clock_requested: Cell<bool>,
 }
 
 impl<'a> Temp<'a> {
@@ -119,9 +136,16 @@ impl<'a> Temp<'a> {
         Temp {
             registers: TEMP_BASE,
             client: OptionalCell::empty(),
+            clock: OptionalCell::empty(),
+            high_clock_requested: Cell::new(false),
         }
     }
 
+    /// Set the clock used to request the HFXO during measurements.
+    pub fn set_clock_ref(&self, clock: &'a dyn HighClock) {
+        self.clock.set(clock);
+    }
+
     /// Temperature interrupt handler
     pub fn handle_interrupt(&self) {
         // disable interrupts
@@ -138,7 +162,14 @@ impl<'a> Temp<'a> {
         self.disable_interrupts();
 
         // trigger callback with temperature
+        let high_clock_requested = self.high_clock_requested.replace(false);
         self.client.map(|client| client.callback(Ok(temp)));
+
+        // Release the HFXO after the callback, so that it keeps running if
+        // the client starts another measurement.
+        if high_clock_requested {
+            self.clock.map(|clock| clock.release());
+        }
     }
 
     fn enable_interrupts(&self) {
@@ -152,6 +183,9 @@ impl<'a> Temp<'a> {
 
 impl<'a> kernel::hil::sensors::TemperatureDriver<'a> for Temp<'a> {
     fn read_temperature(&self) -> Result<(), ErrorCode> {
+        if !self.high_clock_requested.replace(true) {
+            self.clock.map(|clock| clock.request());
+        }
         self.enable_interrupts();
         self.registers.event_datardy.write(Event::READY::CLEAR);
         self.registers.task_start.write(Task::ENABLE::SET);
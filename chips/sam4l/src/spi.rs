@@ -8,6 +8,13 @@
 //! Driver for the SPI hardware (separate from the USARTS), described in chapter
 //! 26 of the datasheet.
 //!
+//! Transfers are 8 bits per word unless set otherwise with `set_word_size`.
+//! The word size only applies to `read_write_word`: the byte functions and
+//! the buffer transfers always program 8-bit words into the CSR of the
+//! active peripheral before they start, as the DMA moves one byte per word.
+//! Peripherals with longer words, such as 16-bit ADCs and DACs, are driven
+//! one word at a time with `read_write_word`.
+//!
 //! - Authors: Sam Crow <samcrow@uw.edu>, Philip Levis <pal@cs.stanford.edu>
 
 use crate::dma::DMAChannel;
@@ -170,6 +177,10 @@ mod spi_consts {
     }
 }
 
+/// Fewest and most bits per transfer supported by `set_word_size`.
+const MIN_WORD_BITS: u8 = 8;
+const MAX_WORD_BITS: u8 = 16;
+
 /// Values for selected peripherals
 #[derive(Copy, Clone)]
 pub enum Peripheral {
@@ -199,6 +210,9 @@ pub struct SpiHw<'a> {
     slave_client: OptionalCell<&'a dyn SpiSlaveClient>,
     role: Cell<SpiRole>,
     pm: &'a pm::PowerManager,
+
+    /// Bits per transfer for `read_write_word`.
+    word_bits: Cell<u8>,
 }
 
 const SPI_BASE: StaticRef<SpiRegisters> =
@@ -241,6 +255,7 @@ impl<'a> SpiHw<'a> {
             slave_client: OptionalCell::empty(),
             role: Cell::new(SpiRole::SpiMaster),
             pm,
+            word_bits: Cell::new(MIN_WORD_BITS),
         }
     }
 
@@ -253,8 +268,7 @@ impl<'a> SpiHw<'a> {
         }
 
         // Sets bits per transfer to 8
-        let csr = self.get_active_csr(spi);
-        csr.modify(ChipSelectParams::BITS::Eight);
+        self.set_transfer_bits(spi, MIN_WORD_BITS);
 
         // Set mode to master or slave
         let mode = match self.role.get() {
@@ -332,9 +346,15 @@ impl<'a> SpiHw<'a> {
 
     fn get_baud_rate(&self) -> u32 {
         let spi = &SpiRegisterManager::new(self);
-        let clock = 48000000;
+        let clock = self.pm.get_system_frequency();
         let scbr = self.get_active_csr(spi).read(ChipSelectParams::SCBR);
-        clock / scbr
+        // SCBR is zero until a rate has been set, which the datasheet marks
+        // as forbidden. No clock is generated in that case.
+        if scbr == 0 {
+            0
+        } else {
+            clock / scbr
+        }
     }
 
     fn set_polarity(&self, polarity: ClockPolarity) {
@@ -375,6 +395,46 @@ impl<'a> SpiHw<'a> {
         }
     }
 
+    /// Sets the number of bits per word for `read_write_word`, from 8 to
+    /// 16. Returns `INVAL` for any other size.
+    pub fn set_word_size(&self, bits: u8) -> Result<(), ErrorCode> {
+        if !(MIN_WORD_BITS..=MAX_WORD_BITS).contains(&bits) {
+            return Err(ErrorCode::INVAL);
+        }
+        self.word_bits.set(bits);
+        Ok(())
+    }
+
+    /// Returns the number of bits per word for `read_write_word`.
+    pub fn get_word_size(&self) -> u8 {
+        self.word_bits.get()
+    }
+
+    /// Programs the BITS field of the active CSR for transfers of `bits` bits.
+    ///
+    /// BITS encodes 8 to 16 bits as 0 to 8; the values 9 to 12 select 4 to 7
+    /// bits, which are not used here.
+    fn set_transfer_bits(&self, spi: &SpiRegisterManager<'a, '_>, bits: u8) {
+        let csr = self.get_active_csr(spi);
+        csr.modify(ChipSelectParams::BITS.val(u32::from(bits - MIN_WORD_BITS)));
+    }
+
+    /// Writes a word of the size set with `set_word_size` to the SPI and
+    /// returns the word read. Bits of `val` above the word size are not
+    /// sent.
+    pub fn read_write_word(&self, val: u16) -> Result<u16, ErrorCode> {
+        let spi = &SpiRegisterManager::new(self);
+        let bits = self.word_bits.get();
+        self.set_transfer_bits(spi, bits);
+
+        let mask = (1u32 << bits) - 1;
+        let tdr = u32::from(val) & mask & spi_consts::tdr::TD;
+        while !spi.registers.sr.is_set(Status::TDRE) {}
+        spi.registers.tdr.set(tdr);
+        while !spi.registers.sr.is_set(Status::RDRF) {}
+        Ok((spi.registers.rdr.get() & mask & spi_consts::rdr::RD) as u16)
+    }
+
     pub fn set_active_peripheral(&self, peripheral: Peripheral) {
         // Slave cannot set active peripheral
         if self.role.get() == SpiRole::SpiMaster {
@@ -466,8 +526,10 @@ impl<'a> SpiHw<'a> {
             return Err((ErrorCode::INVAL, write_buffer, read_buffer));
         }
 
-        // Start by enabling the SPI driver.
+        // Start by enabling the SPI driver. The DMA moves one byte per
+        // word, so words must be 8 bits long.
         self.enable();
+        self.set_transfer_bits(&SpiRegisterManager::new(self), MIN_WORD_BITS);
 
         // Determine how many bytes to move based on the shortest of the
         // write_buffer length, the read_buffer length, and the user requested
@@ -542,6 +604,7 @@ impl<'a> spi::SpiMaster<'a> for SpiHw<'a> {
     /// asynchronous operation is outstanding, do nothing.
     fn write_byte(&self, out_byte: u8) -> Result<(), ErrorCode> {
         let spi = &SpiRegisterManager::new(self);
+        self.set_transfer_bits(spi, MIN_WORD_BITS);
 
         let tdr = (out_byte as u32) & spi_consts::tdr::TD;
         // Wait for data to leave TDR and enter serializer, so TDR is free
@@ -671,7 +734,8 @@ impl<'a> spi::SpiSlave<'a> for SpiHw<'a> {
     /// This sets the value in the TDR register, to be sent as soon as the
     /// chip select pin is low.
     fn set_write_byte(&self, write_byte: u8) {
-        let spi = SpiRegisterManager::new(self);
+        let spi = &SpiRegisterManager::new(self);
+        self.set_transfer_bits(spi, MIN_WORD_BITS);
         spi.registers.tdr.set(write_byte as u32);
     }
 
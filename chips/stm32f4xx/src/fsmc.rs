@@ -387,6 +387,16 @@ impl Bus8080<'static> for Fsmc<'_> {
         }
     }
 
+    fn flush(&self) -> Result<(), ErrorCode> {
+        if self.buffer.is_some() {
+            return Err(ErrorCode::BUSY);
+        }
+        // The write FIFO is disabled and every write is followed by a data
+        // synchronization barrier, so all writes have already left the bus.
+        self.deferred_call.set();
+        Ok(())
+    }
+
     fn set_client(&self, client: &'static dyn Client) {
         self.client.replace(client);
     }
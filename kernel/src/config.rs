@@ -43,6 +43,15 @@ pub(crate) struct Config {
     /// system call or upcall parameters.
     pub(crate) trace_syscalls: bool,
 
+    /// Whether the kernel should record scheduling events to the board's
+    /// trace.
+    ///
+    /// If enabled, the kernel will record every scheduling decision, every
+    /// round of interrupt and deferred call handling, every system call and
+    /// every process fault, with a timestamp, into the ring buffer set with
+    /// `kernel::trace::set_trace()`.
+    pub(crate) trace_scheduling: bool,
+
     /// Whether the kernel should show debugging output when loading processes.
     ///
     /// If enabled, the kernel will show from which addresses processes are
@@ -89,6 +98,7 @@ pub(crate) struct Config {
 /// Cargo features.
 pub(crate) const CONFIG: Config = Config {
     trace_syscalls: cfg!(feature = "trace_syscalls"),
+    trace_scheduling: cfg!(feature = "trace_scheduling"),
     debug_load_processes: cfg!(feature = "debug_load_processes"),
     debug_panics: !cfg!(feature = "no_debug_panics"),
     debug_process_credentials: cfg!(feature = "debug_process_credentials"),
@@ -42,13 +42,22 @@ pub trait Bus8080<'a> {
         len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])>;
 
+    /// Wait until all previous writes have left the bus.
+    ///
+    /// Buses that buffer writes return `Ok(())` and call `command_complete`
+    /// without a buffer once the buffer is empty. Returns `ALREADY` if
+    /// nothing is buffered, and `BUSY` if an operation is still in progress.
+    fn flush(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::ALREADY)
+    }
+
     fn set_client(&self, client: &'a dyn Client);
 }
 
 pub trait Client {
-    /// Called when set_addr, write or read are complete
+    /// Called when set_addr, write, read or flush are complete
     ///
-    /// set_address does not return a buffer
+    /// set_address and flush do not return a buffer
     /// write and read return a buffer
     /// len should be set to the number of data elements written
     fn command_complete(
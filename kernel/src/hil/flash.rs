@@ -135,6 +135,25 @@ pub trait Flash {
     fn erase_page(&self, page_number: usize) -> Result<(), ErrorCode>;
 }
 
+/// Flash that can be put into a low power state while it is idle.
+///
+/// Reads, writes and erases issued while the flash is powered down wake it
+/// first, so callers only need to call `power_down()` when they expect the
+/// flash to stay idle for a while.
+pub trait PowerControl {
+    /// Put the flash into its low power state.
+    ///
+    /// Returns `ALREADY` if the flash is already powered down, and `BUSY` if
+    /// an operation is in progress.
+    fn power_down(&self) -> Result<(), ErrorCode>;
+
+    /// Wake the flash from its low power state, ahead of an operation.
+    ///
+    /// Returns `ALREADY` if the flash is not powered down, and `BUSY` if it
+    /// is in the middle of powering down.
+    fn wake(&self) -> Result<(), ErrorCode>;
+}
+
 /// Implement `Client` to receive callbacks from `Flash`.
 pub trait Client<F: Flash> {
     /// Flash read complete.
@@ -17,6 +17,20 @@ pub enum FloatingState {
     PullNone,
 }
 
+/// Enum for configuring how strongly a GPIO pin is driven as an output.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DriveStrength {
+    /// The default drive strength of the chip.
+    Standard,
+    /// A stronger drive, for loads that need more current.
+    High,
+    /// Drive low with standard strength, but float instead of driving high
+    /// (open-drain, e.g. for buses shared with other devices).
+    StandardOpenDrain,
+    /// Drive low with high strength, but float instead of driving high.
+    HighOpenDrain,
+}
+
 /// Enum for selecting which edge to trigger interrupts on.
 #[derive(Clone, Copy, Debug)]
 pub enum InterruptEdge {
@@ -113,6 +127,17 @@ pub trait Configure {
     /// Return the current floating state of the pin.
     fn floating_state(&self) -> FloatingState;
 
+    /// Set how strongly the pin is driven when it is an output.
+    ///
+    /// Returns `NOSUPPORT` if the chip can not drive the pin this way. All
+    /// chips support `DriveStrength::Standard`.
+    fn set_drive_strength(&self, strength: DriveStrength) -> Result<(), ErrorCode> {
+        match strength {
+            DriveStrength::Standard => Ok(()),
+            _ => Err(ErrorCode::NOSUPPORT),
+        }
+    }
+
     /// Return whether the pin is an input (reading from
     /// the Input trait will return valid results). Returns
     /// true if the pin is in Configuration::Input or
@@ -353,6 +378,10 @@ impl<'a, IP: InterruptPin<'a>> Configure for InterruptValueWrapper<'a, IP> {
         self.source.floating_state()
     }
 
+    fn set_drive_strength(&self, strength: DriveStrength) -> Result<(), ErrorCode> {
+        self.source.set_drive_strength(strength)
+    }
+
     fn is_input(&self) -> bool {
         self.source.is_input()
     }
@@ -39,6 +39,18 @@ pub trait LogRead<'a> {
     /// modifying the read position if the given entry ID is invalid or no longer in the log.
     fn seek(&self, entry: Self::EntryID) -> Result<(), ErrorCode>;
 
+    /// Seek to the end of the log, so that only entries appended from now on are read.
+    fn seek_to_end(&self) -> Result<(), ErrorCode> {
+        self.seek(self.log_end())
+    }
+
+    /// Move the read position forward (positive `entries`) or backward (negative `entries`) by a
+    /// number of entries, stopping at the start or end of the log. Entry IDs need not be evenly
+    /// spaced, so logs that cannot count entries return `NOSUPPORT`.
+    fn seek_relative(&self, _entries: isize) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
+
     /// Get approximate log capacity in bytes.
     fn get_size(&self) -> usize;
 }
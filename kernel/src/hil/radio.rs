@@ -52,6 +52,8 @@ pub trait RxClient {
     ///   802.15.4 specification (section 6.9.8), with value 0 being the lowest
     ///   detectable signal and value 0xff as the highest quality detectable
     ///   signal.
+    /// - `rssi`: The received signal strength of the packet in dBm, or `None`
+    ///   if the radio does not measure it.
     /// - `crc_valid`: Whether the CRC check matched the received frame. Note,
     ///   the MFR bytes are not required to be stored in `buf` so using this
     ///   argument is the only reliable method for checking the CRC.
@@ -65,6 +67,7 @@ pub trait RxClient {
         buf: &'static mut [u8],
         frame_len: usize,
         lqi: u8,
+        rssi: Option<i8>,
         crc_valid: bool,
         result: Result<(), ErrorCode>,
     );
@@ -82,6 +85,18 @@ pub trait ConfigClient {
     fn config_done(&self, result: Result<(), ErrorCode>);
 }
 
+/// Client for energy detection on the radio channel.
+pub trait EnergyDetectClient {
+    /// Measuring the energy on the channel has finished.
+    ///
+    /// ## Arguments
+    ///
+    /// - `result`: The energy measured on the channel in dBm. On `Err()`,
+    ///   valid errors are:
+    ///   - `ErrorCode::FAIL`: Internal error occurred.
+    fn energy_detect_done(&self, result: Result<i8, ErrorCode>);
+}
+
 /// Client for callbacks when the radio's power state changes.
 pub trait PowerClient {
     /// The power state of the radio changed. This is called when the radio has
@@ -367,6 +382,27 @@ pub trait RadioData<'a> {
         buf: &'static mut [u8],
         frame_len: usize,
     ) -> Result<(), (ErrorCode, &'static mut [u8])>;
+
+    /// Set the client that will be called when energy detection finishes.
+    ///
+    /// Radios that do not support energy detection ignore the client.
+    fn set_energy_detect_client(&self, _client: &'a dyn EnergyDetectClient) {}
+
+    /// Measure the energy on the current channel without transmitting, as for
+    /// the IEEE 802.15.4 energy detection (ED) and clear channel assessment.
+    /// The result is passed to the energy detect client.
+    ///
+    /// ## Return
+    ///
+    /// `Ok(())` on success. On `Err()`, valid errors are:
+    ///
+    /// - `ErrorCode::OFF`: The radio is off and cannot measure.
+    /// - `ErrorCode::BUSY`: The radio is transmitting, receiving, or already
+    ///   measuring.
+    /// - `ErrorCode::NOSUPPORT`: The radio does not support energy detection.
+    fn energy_detect(&self) -> Result<(), ErrorCode> {
+        Err(ErrorCode::NOSUPPORT)
+    }
 }
 
 /// IEEE 802.15.4 valid channels.
@@ -233,3 +233,25 @@ pub trait PressureClient {
     /// Returns the value in hPa.
     fn callback(&self, pressure: Result<u32, ErrorCode>);
 }
+
+/// A basic interface for a GNSS receiver that outputs NMEA 0183 sentences.
+pub trait NmeaDriver<'a> {
+    /// Set the client to be notified when a sentence has been read.
+    fn set_client(&self, client: &'a dyn NmeaClient);
+
+    /// Read the next complete NMEA sentence from the receiver.
+    ///
+    /// This function might return the following errors:
+    /// - `BUSY`: Indicates that a sentence is already being read.
+    /// - `FAIL`: Failed to correctly communicate over communication protocol.
+    fn read_sentence(&self) -> Result<(), ErrorCode>;
+}
+
+/// Client for receiving NMEA sentences.
+pub trait NmeaClient {
+    /// Called when a sentence has been read.
+    ///
+    /// - `sentence`: The complete sentence, starting with the `$` and with
+    /// the terminating CRLF removed, or Err on failure.
+    fn callback(&self, sentence: Result<&str, ErrorCode>);
+}
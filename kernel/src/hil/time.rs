@@ -328,6 +328,16 @@ pub trait Alarm<'a>: Time {
     /// Return the minimum dt value that is supported. Any dt smaller than
     /// this will automatically be increased to this minimum value.
     fn minimum_dt(&self) -> Self::Ticks;
+
+    /// Set the alarm to fire `ms` milliseconds from now.
+    ///
+    /// The conversion to ticks uses 64-bit arithmetic, so it does not
+    /// overflow for long delays or fast clocks. A delay longer than the
+    /// alarm can represent is clamped to `Ticks::max_value()`.
+    fn set_alarm_from_now_ms(&self, ms: u32) {
+        let dt = self.ticks_from_ms(ms);
+        self.set_alarm(self.now(), dt);
+    }
 }
 
 /// Callback handler for when a timer fires.
@@ -826,6 +836,7 @@ impl Eq for Ticks64 {}
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::cell::Cell;
 
     struct Test1MHz64();
     impl Time for Test1MHz64 {
@@ -951,4 +962,60 @@ mod tests {
         let us = time.ticks_to_us(5_000_000u32.into());
         assert_eq!(us, u32::MAX);
     }
+
+    struct Test16MHzAlarm {
+        alarm: Cell<Option<(Ticks32, Ticks32)>>,
+    }
+    impl Time for Test16MHzAlarm {
+        type Frequency = Freq16MHz;
+        type Ticks = Ticks32;
+
+        fn now(&self) -> Self::Ticks {
+            0xffff_0000u32.into()
+        }
+    }
+    impl<'a> Alarm<'a> for Test16MHzAlarm {
+        fn set_alarm_client(&self, _client: &'a dyn AlarmClient) {}
+        fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+            self.alarm.set(Some((reference, dt)));
+        }
+        fn get_alarm(&self) -> Self::Ticks {
+            self.alarm
+                .get()
+                .map_or(0u32.into(), |(reference, dt)| reference.wrapping_add(dt))
+        }
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.alarm.set(None);
+            Ok(())
+        }
+        fn is_armed(&self) -> bool {
+            self.alarm.get().is_some()
+        }
+        fn minimum_dt(&self) -> Self::Ticks {
+            1u32.into()
+        }
+    }
+
+    #[test]
+    fn test_set_alarm_from_now_ms() {
+        let alarm = Test16MHzAlarm {
+            alarm: Cell::new(None),
+        };
+
+        // 5 s at 16 MHz overflows u32 when ms is multiplied by the frequency
+        // in 32 bits, but the delay itself fits.
+        alarm.set_alarm_from_now_ms(5_000);
+        assert_eq!(
+            alarm.alarm.get(),
+            Some((0xffff_0000u32.into(), 80_000_000u32.into()))
+        );
+        assert_eq!(alarm.get_alarm().into_u32(), 80_000_000 - 0x1_0000);
+
+        // 300 s does not fit in 32 bits of 16 MHz ticks, so it is clamped.
+        alarm.set_alarm_from_now_ms(300_000);
+        assert_eq!(
+            alarm.alarm.get(),
+            Some((0xffff_0000u32.into(), u32::MAX.into()))
+        );
+    }
 }
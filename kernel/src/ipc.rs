@@ -6,6 +6,9 @@
 //!
 //! This is a special syscall driver that allows userspace applications to
 //! share memory.
+//!
+//! The [`tlv`] module provides a framing for structured data in the shared
+//! buffers.
 
 use crate::capabilities::MemoryAllocationCapability;
 use crate::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
@@ -16,6 +19,8 @@ use crate::processbuffer::ReadableProcessBuffer;
 use crate::syscall_driver::{CommandReturn, SyscallDriver};
 use crate::ErrorCode;
 
+pub mod tlv;
+
 /// Syscall number
 pub const DRIVER_NUM: usize = 0x10000;
 
@@ -0,0 +1,231 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Type-length-value framing for structured data in IPC shared buffers.
+//!
+//! IPC only shares memory between processes, so the layout of the data in
+//! a shared buffer is up to the processes. This module provides a simple
+//! framing that both capsules and userspace libraries can follow: a
+//! sequence of fields, each made of
+//!
+//! ```text
+//! +------+--------------+-----------------+
+//! | type | length (LE)  | value           |
+//! | 1 B  | 2 B          | `length` bytes  |
+//! +------+--------------+-----------------+
+//! ```
+//!
+//! Type 0 is reserved to mark the end of the fields, so a zero-filled
+//! remainder of a shared buffer reads as the end of the data. A buffer that
+//! is completely full of fields needs no end marker.
+//!
+//! The helpers work on caller-provided slices and do not allocate. Capsules
+//! can encode into a local buffer and copy it into a process buffer, or copy
+//! a process buffer out and decode it.
+//!
+//! ```rust
+//! use kernel::ipc::tlv::{TlvReader, TlvWriter};
+//!
+//! let mut buffer = [0; 16];
+//! let mut writer = TlvWriter::new(&mut buffer);
+//! writer.write(1, &[0x2a]).unwrap();
+//! writer.write(2, b"tock").unwrap();
+//!
+//! let mut fields = TlvReader::new(&buffer);
+//! assert_eq!(fields.next().unwrap().unwrap().value, &[0x2a]);
+//! assert_eq!(fields.next().unwrap().unwrap().value, b"tock");
+//! assert!(fields.next().is_none());
+//! ```
+
+use crate::ErrorCode;
+
+/// Type that marks the end of the fields.
+pub const END: u8 = 0;
+
+/// Length of the type and length header of a field.
+pub const HEADER_LEN: usize = 3;
+
+/// A field read from a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Field<'a> {
+    pub field_type: u8,
+    pub value: &'a [u8],
+}
+
+/// Appends fields to a buffer.
+pub struct TlvWriter<'a> {
+    buffer: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> TlvWriter<'a> {
+    pub fn new(buffer: &'a mut [u8]) -> TlvWriter<'a> {
+        TlvWriter { buffer, len: 0 }
+    }
+
+    /// Appends a field, and an end marker if there is room for one.
+    ///
+    /// Returns `INVAL` if `field_type` is [`END`], and `SIZE` if the field
+    /// does not fit, in which case the buffer is unchanged.
+    pub fn write(&mut self, field_type: u8, value: &[u8]) -> Result<(), ErrorCode> {
+        if field_type == END {
+            return Err(ErrorCode::INVAL);
+        }
+        let value_len = u16::try_from(value.len()).map_err(|_| ErrorCode::SIZE)?;
+        let end = self.len + HEADER_LEN + value.len();
+        if end > self.buffer.len() {
+            return Err(ErrorCode::SIZE);
+        }
+
+        let field = &mut self.buffer[self.len..end];
+        field[0] = field_type;
+        field[1..HEADER_LEN].copy_from_slice(&value_len.to_le_bytes());
+        field[HEADER_LEN..].copy_from_slice(value);
+        if let Some(marker) = self.buffer.get_mut(end) {
+            *marker = END;
+        }
+        self.len = end;
+        Ok(())
+    }
+
+    /// Number of bytes written, not counting the end marker.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// Iterates over the fields in a buffer.
+///
+/// A field that runs past the end of the buffer is returned as a `SIZE`
+/// error, after which the iteration stops.
+pub struct TlvReader<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> TlvReader<'a> {
+    pub fn new(buffer: &'a [u8]) -> TlvReader<'a> {
+        TlvReader { buffer }
+    }
+
+    /// Returns the value of the first field of type `field_type`.
+    pub fn find(self, field_type: u8) -> Option<&'a [u8]> {
+        self.map_while(Result::ok)
+            .find(|field| field.field_type == field_type)
+            .map(|field| field.value)
+    }
+}
+
+impl<'a> Iterator for TlvReader<'a> {
+    type Item = Result<Field<'a>, ErrorCode>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (&field_type, rest) = self.buffer.split_first()?;
+        if field_type == END {
+            self.buffer = &[];
+            return None;
+        }
+
+        let field = rest
+            .get(..HEADER_LEN - 1)
+            .map(|len| usize::from(u16::from_le_bytes([len[0], len[1]])))
+            .and_then(|len| rest.get(HEADER_LEN - 1..HEADER_LEN - 1 + len));
+        match field {
+            Some(value) => {
+                self.buffer = &self.buffer[HEADER_LEN + value.len()..];
+                Some(Ok(Field { field_type, value }))
+            }
+            None => {
+                self.buffer = &[];
+                Some(Err(ErrorCode::SIZE))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = [0xff; 32];
+        let mut writer = TlvWriter::new(&mut buffer);
+        assert!(writer.is_empty());
+        assert_eq!(writer.write(1, &[]), Ok(()));
+        assert_eq!(writer.write(2, &0x12345678u32.to_le_bytes()), Ok(()));
+        assert_eq!(writer.write(0x80, b"hello"), Ok(()));
+        assert_eq!(writer.len(), 3 * HEADER_LEN + 4 + 5);
+
+        let mut fields = TlvReader::new(&buffer);
+        assert_eq!(
+            fields.next(),
+            Some(Ok(Field {
+                field_type: 1,
+                value: &[],
+            }))
+        );
+        assert_eq!(
+            fields.next(),
+            Some(Ok(Field {
+                field_type: 2,
+                value: &[0x78, 0x56, 0x34, 0x12],
+            }))
+        );
+        assert_eq!(
+            fields.next(),
+            Some(Ok(Field {
+                field_type: 0x80,
+                value: b"hello",
+            }))
+        );
+        // The end marker hides the old contents of the buffer.
+        assert_eq!(fields.next(), None);
+
+        assert_eq!(TlvReader::new(&buffer).find(0x80), Some(&b"hello"[..]));
+        assert_eq!(TlvReader::new(&buffer).find(3), None);
+    }
+
+    #[test]
+    fn full_buffer() {
+        let mut buffer = [0; 2 * HEADER_LEN + 3];
+        let mut writer = TlvWriter::new(&mut buffer);
+        assert_eq!(writer.write(END, &[1]), Err(ErrorCode::INVAL));
+        assert_eq!(writer.write(1, &[1, 2]), Ok(()));
+        assert_eq!(writer.write(2, &[3, 4]), Err(ErrorCode::SIZE));
+        // A field that exactly fills the buffer needs no end marker.
+        assert_eq!(writer.write(2, &[3]), Ok(()));
+        assert_eq!(writer.write(3, &[]), Err(ErrorCode::SIZE));
+
+        let types: [u8; 2] = [1, 2];
+        let mut fields = TlvReader::new(&buffer);
+        for field_type in types {
+            assert_eq!(fields.next().unwrap().unwrap().field_type, field_type);
+        }
+        assert_eq!(fields.next(), None);
+    }
+
+    #[test]
+    fn truncated_field() {
+        // The length claims more bytes than the buffer holds.
+        let buffer = [1, 1, 0, 0xaa, 2, 4, 0, 0xbb];
+        let mut fields = TlvReader::new(&buffer);
+        assert_eq!(
+            fields.next(),
+            Some(Ok(Field {
+                field_type: 1,
+                value: &[0xaa],
+            }))
+        );
+        assert_eq!(fields.next(), Some(Err(ErrorCode::SIZE)));
+        assert_eq!(fields.next(), None);
+
+        // So does a header cut short.
+        assert_eq!(TlvReader::new(&[1, 0]).next(), Some(Err(ErrorCode::SIZE)));
+        assert_eq!(TlvReader::new(&buffer).find(2), None);
+    }
+}
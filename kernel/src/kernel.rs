@@ -32,6 +32,7 @@ use crate::syscall::SyscallDriver;
 use crate::syscall::{ContextSwitchReason, SyscallReturn};
 use crate::syscall::{Syscall, YieldCall};
 use crate::syscall_driver::CommandReturn;
+use crate::trace::{self, TraceEvent};
 use crate::upcall::{Upcall, UpcallId};
 use crate::utilities::cells::NumericCellExt;
 
@@ -375,6 +376,13 @@ impl Kernel {
             // processes instead, or there may be no kernel work to do.
             match scheduler.do_kernel_work_now(chip) {
                 true => {
+                    if config::CONFIG.trace_scheduling {
+                        trace::record(TraceEvent::KernelWork {
+                            interrupts: chip.has_pending_interrupts(),
+                            deferred_calls: DeferredCall::has_tasks(),
+                        });
+                    }
+
                     // Execute kernel work. This includes handling
                     // interrupts and is how code in the chips/ and capsules
                     // crates is able to execute.
@@ -385,8 +393,20 @@ impl Kernel {
                     match scheduler.next() {
                         SchedulingDecision::RunProcess((processid, timeslice_us)) => {
                             self.process_map_or((), processid, |process| {
+                                if config::CONFIG.trace_scheduling {
+                                    trace::record(TraceEvent::RunProcess {
+                                        process: processid.id(),
+                                        timeslice_us,
+                                    });
+                                }
                                 let (reason, time_executed) =
                                     self.do_process(resources, chip, process, ipc, timeslice_us);
+                                if config::CONFIG.trace_scheduling {
+                                    trace::record(TraceEvent::ProcessStopped {
+                                        process: processid.id(),
+                                        reason,
+                                    });
+                                }
                                 scheduler.result(reason, time_executed);
                             });
                         }
@@ -408,6 +428,9 @@ impl Kernel {
                                     // from sleep.
                                     if !chip.has_pending_interrupts() && !DeferredCall::has_tasks()
                                     {
+                                        if config::CONFIG.trace_scheduling {
+                                            trace::record(TraceEvent::Sleep);
+                                        }
                                         resources.watchdog().suspend();
                                         chip.sleep();
                                         resources.watchdog().resume();
@@ -559,6 +582,12 @@ impl Kernel {
                     // why and handle the process as appropriate.
                     match context_switch_reason {
                         Some(ContextSwitchReason::Fault) => {
+                            if config::CONFIG.trace_scheduling {
+                                trace::record(TraceEvent::ProcessFault {
+                                    process: process.processid().id(),
+                                });
+                            }
+
                             // The app faulted, check if the chip wants to
                             // handle the fault.
                             if resources
@@ -742,6 +771,10 @@ impl Kernel {
         // Hook for process debugging.
         process.debug_syscall_called(syscall);
 
+        if config::CONFIG.trace_scheduling {
+            trace::record(TraceEvent::syscall(process.processid(), &syscall));
+        }
+
         // Enforce platform-specific syscall filtering here.
         //
         // Before continuing to handle non-yield syscalls the kernel first
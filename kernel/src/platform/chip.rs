@@ -48,6 +48,10 @@ pub trait Chip {
     /// a low power sleep state. This low power sleep state should allow
     /// interrupts to still be active so that the next interrupt event wakes the
     /// chip and resumes the scheduler.
+    ///
+    /// Chips with several sleep states should not enter a state deeper than
+    /// the one allowed by the board's
+    /// [`PowerStateCoordinator`](crate::platform::power::PowerStateCoordinator).
     fn sleep(&self);
 
     /// Run a function in an atomic state, which means that interrupts are
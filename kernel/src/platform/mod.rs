@@ -8,6 +8,7 @@
 
 pub mod chip;
 pub mod mpu;
+pub mod power;
 pub mod scheduler_timer;
 pub mod watchdog;
 
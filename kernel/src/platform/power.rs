@@ -0,0 +1,192 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Coordination of the sleep states the chip may enter when idle.
+//!
+//! Entering a deep sleep state while a peripheral is in the middle of an
+//! operation can corrupt it, for example a DMA transfer stops when the clock
+//! it runs from is turned off. To prevent this, drivers register a
+//! [`PowerConstraint`] with the board's [`PowerStateCoordinator`] and use it
+//! to vote on the deepest sleep state that is currently safe for them. A
+//! driver raises its constraint when it starts an operation and releases it
+//! when the operation completes.
+//!
+//! The chip's `sleep()` implementation asks the coordinator for the deepest
+//! state all drivers allow and must not sleep any deeper than that:
+//!
+//! ```rust,ignore
+//! fn sleep(&self) {
+//!     match self.power.deepest_allowed() {
+//!         SleepState::DeepSleep => enter_deep_sleep(),
+//!         SleepState::Sleep => enter_sleep(),
+//!         SleepState::Idle => wait_for_interrupt(),
+//!     }
+//! }
+//! ```
+//!
+//! And in a driver:
+//!
+//! ```rust,ignore
+//! fn transmit(&self, buffer: &'static mut [u8]) {
+//!     self.power.limit(SleepState::Idle);
+//!     self.start_dma(buffer);
+//! }
+//!
+//! fn handle_interrupt(&self) {
+//!     self.power.release();
+//!     // ...
+//! }
+//! ```
+
+use core::cell::Cell;
+
+use crate::collections::list::{List, ListLink, ListNode};
+
+/// The states the chip can sleep in, from the lightest to the deepest.
+///
+/// What each state turns off is chip specific, but deeper states must only
+/// turn off more, so that a driver that is safe in one state is also safe in
+/// every lighter one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SleepState {
+    /// Only the core is stopped. All clocks and peripherals keep running.
+    Idle,
+    /// The core and high-frequency clocks may be stopped, so DMA and fast
+    /// peripherals do not make progress.
+    Sleep,
+    /// Only low-power timers and wakeup sources remain active.
+    DeepSleep,
+}
+
+/// A driver's vote on the deepest sleep state it can tolerate.
+pub struct PowerConstraint<'a> {
+    allowed: Cell<SleepState>,
+    next: ListLink<'a, PowerConstraint<'a>>,
+}
+
+impl<'a> PowerConstraint<'a> {
+    /// Creates a constraint that initially allows every sleep state.
+    pub const fn new() -> PowerConstraint<'a> {
+        PowerConstraint {
+            allowed: Cell::new(SleepState::DeepSleep),
+            next: ListLink::empty(),
+        }
+    }
+
+    /// Prevents the chip from sleeping deeper than `state`, for example
+    /// when starting an operation.
+    pub fn limit(&self, state: SleepState) {
+        self.allowed.set(state);
+    }
+
+    /// Allows every sleep state again, for example when an operation has
+    /// completed.
+    pub fn release(&self) {
+        self.allowed.set(SleepState::DeepSleep);
+    }
+
+    /// Returns the deepest sleep state this constraint allows.
+    pub fn allowed(&self) -> SleepState {
+        self.allowed.get()
+    }
+}
+
+impl<'a> Default for PowerConstraint<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> ListNode<'a, PowerConstraint<'a>> for PowerConstraint<'a> {
+    fn next(&'a self) -> &'a ListLink<'a, PowerConstraint<'a>> {
+        &self.next
+    }
+}
+
+/// Collects the constraints of all drivers and determines the deepest sleep
+/// state that is safe to enter.
+pub struct PowerStateCoordinator<'a> {
+    constraints: List<'a, PowerConstraint<'a>>,
+}
+
+impl<'a> PowerStateCoordinator<'a> {
+    pub const fn new() -> PowerStateCoordinator<'a> {
+        PowerStateCoordinator {
+            constraints: List::new(),
+        }
+    }
+
+    /// Registers a driver's constraint. This is likely called in a board's
+    /// main.rs.
+    pub fn register(&self, constraint: &'a PowerConstraint<'a>) {
+        self.constraints.push_head(constraint);
+    }
+
+    /// Returns the deepest sleep state allowed by every registered
+    /// constraint.
+    pub fn deepest_allowed(&self) -> SleepState {
+        self.constraints
+            .iter()
+            .map(|constraint| constraint.allowed())
+            .min()
+            .unwrap_or(SleepState::DeepSleep)
+    }
+}
+
+impl<'a> Default for PowerStateCoordinator<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A driver that uses DMA, which must not run in `Sleep` or deeper.
+    struct MockDmaDriver<'a> {
+        power: &'a PowerConstraint<'a>,
+    }
+
+    impl<'a> MockDmaDriver<'a> {
+        fn start_transfer(&self) {
+            self.power.limit(SleepState::Idle);
+        }
+
+        fn transfer_done(&self) {
+            self.power.release();
+        }
+    }
+
+    /// A chip's idle hook, returning the state it entered.
+    fn sleep(coordinator: &PowerStateCoordinator) -> SleepState {
+        coordinator.deepest_allowed()
+    }
+
+    #[test]
+    fn voting() {
+        let coordinator = PowerStateCoordinator::new();
+        assert_eq!(sleep(&coordinator), SleepState::DeepSleep);
+
+        let dma_power = PowerConstraint::new();
+        let timer_power = PowerConstraint::new();
+        coordinator.register(&dma_power);
+        coordinator.register(&timer_power);
+        let dma = MockDmaDriver { power: &dma_power };
+
+        assert_eq!(sleep(&coordinator), SleepState::DeepSleep);
+
+        timer_power.limit(SleepState::Sleep);
+        assert_eq!(sleep(&coordinator), SleepState::Sleep);
+
+        dma.start_transfer();
+        assert_eq!(sleep(&coordinator), SleepState::Idle);
+
+        timer_power.release();
+        assert_eq!(sleep(&coordinator), SleepState::Idle);
+
+        dma.transfer_done();
+        assert_eq!(sleep(&coordinator), SleepState::DeepSleep);
+    }
+}
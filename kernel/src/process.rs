@@ -29,7 +29,7 @@ pub use crate::process_loading::load_processes;
 pub use crate::process_loading::ProcessLoadError;
 pub use crate::process_loading::SequentialProcessLoaderMachine;
 pub use crate::process_loading::{ProcessLoadingAsync, ProcessLoadingAsyncClient};
-pub use crate::process_policies::ProcessFaultPolicy;
+pub use crate::process_policies::{ProcessFaultPolicy, RestartMemoryPolicy};
 pub use crate::process_printer::{ProcessPrinter, ProcessPrinterContext};
 pub use crate::process_standard::ProcessStandard;
 
@@ -289,7 +289,7 @@ impl core::fmt::Display for ShortId {
 ///
 /// This is publicly exported to allow for schedulers implemented outside of the
 /// kernel crate.
-#[derive(PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum StoppedExecutingReason {
     /// The process returned because it is no longer ready to run.
     NoWorkLeft,
@@ -11,6 +11,20 @@
 use crate::process;
 use crate::process::Process;
 
+/// What the kernel does with a process's RAM before the process is restarted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RestartMemoryPolicy {
+    /// Leave the contents of the process's RAM as they were.
+    Retain,
+    /// Zero the memory the process could access (its stack, data and heap,
+    /// up to its current `brk`).
+    ZeroAppMemory,
+    /// Zero the memory the process could access as well as the grant regions
+    /// the kernel allocated on its behalf, up to the kernel's own bookkeeping
+    /// at the top of the process's memory region.
+    ZeroAll,
+}
+
 /// Generic trait for implementing a policy on what to do when a process faults.
 ///
 /// Implementations can use the `Process` reference to decide which action to
@@ -19,4 +33,11 @@ pub trait ProcessFaultPolicy {
     /// Decide which action the kernel should take in response to `process`
     /// faulting.
     fn action(&self, process: &dyn Process) -> process::FaultAction;
+
+    /// Decide what the kernel should do with the RAM of `process` before it
+    /// is restarted, whether because it faulted or because it asked to be
+    /// restarted. By default the RAM is retained.
+    fn restart_memory_policy(&self, _process: &dyn Process) -> RestartMemoryPolicy {
+        RestartMemoryPolicy::Retain
+    }
 }
@@ -30,7 +30,7 @@ use crate::process::{ProcessAddresses, ProcessSizes, ShortId};
 use crate::process::{State, StoppedState};
 use crate::process_checker::AcceptedCredential;
 use crate::process_loading::ProcessLoadError;
-use crate::process_policies::ProcessFaultPolicy;
+use crate::process_policies::{ProcessFaultPolicy, RestartMemoryPolicy};
 use crate::processbuffer::{ReadOnlyProcessBuffer, ReadWriteProcessBuffer};
 use crate::storage_permissions;
 use crate::syscall::{self, Syscall, SyscallReturn, UserspaceKernelBoundary};
@@ -1779,6 +1779,31 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         self.process_id
             .set(ProcessId::new(self.kernel, new_identifier, old_index));
 
+        // Recalculate initial_kernel_memory_size as was done in create()
+        let grant_ptr_size = mem::size_of::<(usize, *mut u8)>();
+        let grant_ptrs_num = self.kernel.get_grant_count_and_finalize();
+        let grant_ptrs_offset = grant_ptrs_num * grant_ptr_size;
+
+        let initial_kernel_memory_size =
+            grant_ptrs_offset + Self::CALLBACKS_OFFSET + Self::PROCESS_STRUCT_OFFSET;
+
+        // Clear the old contents of the process's memory if the policy asks for
+        // it. This must happen before the memory pointers are reset below.
+        //
+        // Safety: everything below the initial kernel memory at the top of the
+        // process's memory region (the grant pointers, upcall queue and this
+        // struct) is either process memory or grant memory. The process is not
+        // running and its grants have been freed, so nothing else refers to
+        // this memory.
+        unsafe {
+            zero_restart_memory(
+                self.fault_policy.restart_memory_policy(self),
+                self.memory_start as *mut u8,
+                self.app_break.get(),
+                self.mem_end().wrapping_sub(initial_kernel_memory_size),
+            );
+        }
+
         // Reset debug information that is per-execution and not per-process.
         self.debug.map(|debug| {
             debug.syscall_count = 0;
@@ -1827,14 +1852,6 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
             .userspace_kernel_boundary()
             .initial_process_app_brk_size();
 
-        // Recalculate initial_kernel_memory_size as was done in create()
-        let grant_ptr_size = mem::size_of::<(usize, *mut u8)>();
-        let grant_ptrs_num = self.kernel.get_grant_count_and_finalize();
-        let grant_ptrs_offset = grant_ptrs_num * grant_ptr_size;
-
-        let initial_kernel_memory_size =
-            grant_ptrs_offset + Self::CALLBACKS_OFFSET + Self::PROCESS_STRUCT_OFFSET;
-
         let app_mpu_mem = self.chip.mpu().allocate_app_memory_region(
             self.mem_start(),
             self.memory_len,
@@ -2081,3 +2098,69 @@ impl<C: 'static + Chip> ProcessStandard<'_, C> {
         self.app_break.get()
     }
 }
+
+/// Zero the memory of a process that is about to be restarted, as `policy`
+/// asks for.
+///
+/// The process's accessible memory runs from `memory_start` to `app_break`.
+/// The grant regions the kernel allocated on its behalf run from there up to
+/// `grants_end`, where the kernel memory that is kept across the restart
+/// starts.
+///
+/// # Safety
+///
+/// `memory_start` to `grants_end` must be valid for writes, and nothing may
+/// refer to the memory that is zeroed.
+unsafe fn zero_restart_memory(
+    policy: RestartMemoryPolicy,
+    memory_start: *mut u8,
+    app_break: *const u8,
+    grants_end: *const u8,
+) {
+    let zero_end = match policy {
+        RestartMemoryPolicy::Retain => return,
+        RestartMemoryPolicy::ZeroAppMemory => app_break,
+        RestartMemoryPolicy::ZeroAll => grants_end,
+    };
+    let zero_len = (zero_end as usize).saturating_sub(memory_start as usize);
+    ptr::write_bytes(memory_start, 0, zero_len);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SENTINEL: u8 = 0xA5;
+
+    /// Restarts a process with 64 bytes of RAM, in which the process's
+    /// accessible memory ends at 16 and the grant regions at 48, and returns
+    /// that RAM.
+    fn restart(policy: RestartMemoryPolicy) -> [u8; 64] {
+        let mut ram = [SENTINEL; 64];
+        let start = ram.as_mut_ptr();
+        unsafe {
+            zero_restart_memory(policy, start, start.add(16), start.add(48));
+        }
+        ram
+    }
+
+    #[test]
+    fn retain() {
+        assert_eq!(restart(RestartMemoryPolicy::Retain), [SENTINEL; 64]);
+    }
+
+    #[test]
+    fn zero_app_memory() {
+        let ram = restart(RestartMemoryPolicy::ZeroAppMemory);
+        assert_eq!(ram[..16], [0; 16]);
+        assert_eq!(ram[16..], [SENTINEL; 48]);
+    }
+
+    #[test]
+    fn zero_all() {
+        let ram = restart(RestartMemoryPolicy::ZeroAll);
+        assert_eq!(ram[..48], [0; 48]);
+        // The kernel memory kept across the restart is left alone.
+        assert_eq!(ram[48..], [SENTINEL; 16]);
+    }
+}
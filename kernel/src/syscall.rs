@@ -93,7 +93,7 @@ fn u64_to_be_u32s(src: u64) -> (u32, u32) {
 /// These are encoded as 8 bit values as on some architectures the value can be
 /// encoded in the instruction itself.
 #[repr(u8)]
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum SyscallClass {
     Yield = 0,
     Subscribe = 1,
@@ -304,6 +304,20 @@ impl Syscall {
         }
     }
 
+    /// Get the [`SyscallClass`] of the syscall.
+    pub fn class(&self) -> SyscallClass {
+        match *self {
+            Syscall::Yield { .. } => SyscallClass::Yield,
+            Syscall::Subscribe { .. } => SyscallClass::Subscribe,
+            Syscall::Command { .. } => SyscallClass::Command,
+            Syscall::ReadWriteAllow { .. } => SyscallClass::ReadWriteAllow,
+            Syscall::UserspaceReadableAllow { .. } => SyscallClass::UserspaceReadableAllow,
+            Syscall::ReadOnlyAllow { .. } => SyscallClass::ReadOnlyAllow,
+            Syscall::Memop { .. } => SyscallClass::Memop,
+            Syscall::Exit { .. } => SyscallClass::Exit,
+        }
+    }
+
     /// Get the `driver_number` for the syscall classes that use driver numbers.
     pub fn driver_number(&self) -> Option<usize> {
         match *self {
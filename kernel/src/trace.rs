@@ -0,0 +1,324 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2022.
+
+//! Compact trace of kernel scheduling events.
+//!
+//! Bugs which depend on the exact interleaving of interrupts, kernel work and
+//! processes are hard to reproduce. When the kernel crate is built with the
+//! `trace_scheduling` feature, the main loop records every scheduling
+//! decision, every round of kernel work (interrupts and deferred calls),
+//! every system call and every process fault into a board-provided ring
+//! buffer, together with a timestamp. After a fault, the board can read back
+//! the trace to reconstruct the sequence of events leading up to it.
+//!
+//! The ring buffer overwrites its oldest records when full, so it always holds
+//! the most recent events. Without the feature, no events are recorded and
+//! the compiler removes the tracing code entirely.
+//!
+//! Usage
+//! -----
+//!
+//! In the board's `main.rs`:
+//!
+//! ```rust,ignore
+//! let trace_buffer = static_init!(
+//!     [kernel::trace::TraceRecord; 64],
+//!     [kernel::trace::TraceRecord::EMPTY; 64]
+//! );
+//! let trace = static_init!(
+//!     kernel::trace::Trace<'static>,
+//!     kernel::trace::Trace::new(
+//!         static_init!(
+//!             RingBuffer<'static, kernel::trace::TraceRecord>,
+//!             RingBuffer::new(trace_buffer)
+//!         ),
+//!         Some(alarm),
+//!     )
+//! );
+//! kernel::trace::set_trace(trace);
+//! ```
+//!
+//! And to dump the trace, for instance from the panic handler:
+//!
+//! ```rust,ignore
+//! trace.drain(|record| debug!("{:?}", record));
+//! ```
+
+use crate::collections::queue::Queue;
+use crate::collections::ring_buffer::RingBuffer;
+use crate::config;
+use crate::hil::time::{Ticks, Time};
+use crate::process::{ProcessId, StoppedExecutingReason};
+use crate::syscall::{Syscall, SyscallClass};
+use crate::utilities::cells::TakeCell;
+
+/// A source of timestamps for trace records.
+pub trait TraceClock {
+    /// The current time, in ticks of the underlying clock.
+    fn now(&self) -> u32;
+}
+
+impl<T: Time> TraceClock for T {
+    fn now(&self) -> u32 {
+        Time::now(self).into_u32()
+    }
+}
+
+/// An event recorded in the trace. Processes are identified by their
+/// [`ProcessId::id`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// No event. Used to initialize record buffers.
+    None,
+    /// The kernel is about to service pending interrupts and deferred calls.
+    KernelWork {
+        interrupts: bool,
+        deferred_calls: bool,
+    },
+    /// The scheduler decided to run a process.
+    RunProcess {
+        process: usize,
+        timeslice_us: Option<u32>,
+    },
+    /// A process stopped executing and control returned to the scheduler.
+    ProcessStopped {
+        process: usize,
+        reason: StoppedExecutingReason,
+    },
+    /// The chip is about to go to sleep, as there is no work to do.
+    Sleep,
+    /// A process issued a system call.
+    Syscall {
+        process: usize,
+        class: SyscallClass,
+        driver_number: Option<usize>,
+        subdriver_number: Option<usize>,
+    },
+    /// A process faulted.
+    ProcessFault { process: usize },
+}
+
+impl TraceEvent {
+    /// The event for `process` issuing `syscall`.
+    pub fn syscall(process: ProcessId, syscall: &Syscall) -> TraceEvent {
+        TraceEvent::Syscall {
+            process: process.id(),
+            class: syscall.class(),
+            driver_number: syscall.driver_number(),
+            subdriver_number: syscall.subdriver_number(),
+        }
+    }
+}
+
+/// A recorded event, and the time at which it happened.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Timestamp from the trace's [`TraceClock`], or `0` if it has none.
+    pub timestamp: u32,
+    pub event: TraceEvent,
+}
+
+impl TraceRecord {
+    pub const EMPTY: TraceRecord = TraceRecord {
+        timestamp: 0,
+        event: TraceEvent::None,
+    };
+}
+
+/// A trace of kernel events, stored in a ring buffer.
+pub struct Trace<'a> {
+    records: TakeCell<'a, RingBuffer<'a, TraceRecord>>,
+    clock: Option<&'a dyn TraceClock>,
+}
+
+impl<'a> Trace<'a> {
+    /// Record events into `records`, timestamped with `clock` if provided.
+    pub fn new(
+        records: &'a mut RingBuffer<'a, TraceRecord>,
+        clock: Option<&'a dyn TraceClock>,
+    ) -> Trace<'a> {
+        Trace {
+            records: TakeCell::new(records),
+            clock,
+        }
+    }
+
+    /// Record `event`, overwriting the oldest record if the trace is full.
+    pub fn record(&self, event: TraceEvent) {
+        let timestamp = self.clock.map_or(0, |clock| clock.now());
+        self.records.map(|records| {
+            records.push(TraceRecord { timestamp, event });
+        });
+    }
+
+    /// The number of records currently in the trace.
+    pub fn len(&self) -> usize {
+        self.records.map_or(0, |records| records.len())
+    }
+
+    /// Whether the trace holds no records.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Calls `f` with every record in the trace, oldest first, and empties the
+    /// trace.
+    pub fn drain<F: FnMut(TraceRecord)>(&self, mut f: F) {
+        // Take the buffer out while calling `f`, so that events recorded by
+        // `f` itself (for instance, when printing) are dropped rather than
+        // extending the drain forever.
+        if let Some(records) = self.records.take() {
+            while let Some(record) = records.dequeue() {
+                f(record);
+            }
+            self.records.replace(records);
+        }
+    }
+}
+
+static mut TRACE: Option<&'static Trace<'static>> = None;
+
+/// Function used by board main.rs to set the trace the kernel records into.
+pub unsafe fn set_trace(trace: &'static Trace<'static>) {
+    TRACE = Some(trace);
+}
+
+/// Record `event` into the board's trace, if scheduling traces are enabled
+/// and the board has set one.
+pub(crate) fn record(event: TraceEvent) {
+    if config::CONFIG.trace_scheduling {
+        // Safety: `TRACE` is only written by `set_trace` during board
+        // initialization, before the kernel loop starts.
+        if let Some(trace) = unsafe { TRACE } {
+            trace.record(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct MockClock(Cell<u32>);
+
+    impl TraceClock for MockClock {
+        fn now(&self) -> u32 {
+            // Every read advances the clock, so records are distinguishable.
+            let now = self.0.get();
+            self.0.set(now + 10);
+            now
+        }
+    }
+
+    #[test]
+    fn syscall_sequence() {
+        let mut buffer = [TraceRecord::EMPTY; 8];
+        let mut records = RingBuffer::new(&mut buffer);
+        let clock = MockClock(Cell::new(100));
+        let trace = Trace::new(&mut records, Some(&clock));
+        let process = 3;
+
+        // A process subscribes, issues a command and waits for the upcall,
+        // which arrives through an interrupt while it is yielded.
+        let syscalls = [
+            Syscall::from_register_arguments(1, 0x60000, 0, 0x1000, 0).unwrap(),
+            Syscall::from_register_arguments(2, 0x60000, 1, 0, 0).unwrap(),
+            Syscall::from_register_arguments(0, 1, 0, 0, 0).unwrap(),
+        ];
+        trace.record(TraceEvent::RunProcess {
+            process,
+            timeslice_us: Some(10000),
+        });
+        for syscall in syscalls.iter() {
+            trace.record(TraceEvent::Syscall {
+                process,
+                class: syscall.class(),
+                driver_number: syscall.driver_number(),
+                subdriver_number: syscall.subdriver_number(),
+            });
+        }
+        trace.record(TraceEvent::ProcessStopped {
+            process,
+            reason: StoppedExecutingReason::NoWorkLeft,
+        });
+        trace.record(TraceEvent::Sleep);
+        trace.record(TraceEvent::KernelWork {
+            interrupts: true,
+            deferred_calls: false,
+        });
+
+        let mut events = [TraceRecord::EMPTY; 7];
+        let mut count = 0;
+        trace.drain(|record| {
+            events[count] = record;
+            count += 1;
+        });
+        assert_eq!(count, 7);
+        assert!(trace.is_empty());
+
+        let expected = [
+            TraceEvent::RunProcess {
+                process,
+                timeslice_us: Some(10000),
+            },
+            TraceEvent::Syscall {
+                process,
+                class: SyscallClass::Subscribe,
+                driver_number: Some(0x60000),
+                subdriver_number: Some(0),
+            },
+            TraceEvent::Syscall {
+                process,
+                class: SyscallClass::Command,
+                driver_number: Some(0x60000),
+                subdriver_number: Some(1),
+            },
+            TraceEvent::Syscall {
+                process,
+                class: SyscallClass::Yield,
+                driver_number: None,
+                subdriver_number: None,
+            },
+            TraceEvent::ProcessStopped {
+                process,
+                reason: StoppedExecutingReason::NoWorkLeft,
+            },
+            TraceEvent::Sleep,
+            TraceEvent::KernelWork {
+                interrupts: true,
+                deferred_calls: false,
+            },
+        ];
+        for (i, (record, event)) in events.iter().zip(expected.iter()).enumerate() {
+            assert_eq!(record.event, *event);
+            assert_eq!(record.timestamp, 100 + 10 * i as u32);
+        }
+    }
+
+    #[test]
+    fn keeps_latest_records() {
+        let mut buffer = [TraceRecord::EMPTY; 4];
+        let mut records = RingBuffer::new(&mut buffer);
+        let trace = Trace::new(&mut records, None);
+        for process in 0..10 {
+            trace.record(TraceEvent::ProcessFault { process });
+        }
+
+        // The ring buffer keeps one slot free to tell full and empty apart.
+        let mut expected = [7, 8, 9].iter();
+        trace.drain(|record| {
+            assert_eq!(
+                record,
+                TraceRecord {
+                    timestamp: 0,
+                    event: TraceEvent::ProcessFault {
+                        process: *expected.next().unwrap()
+                    },
+                }
+            );
+        });
+        assert!(expected.next().is_none());
+    }
+}
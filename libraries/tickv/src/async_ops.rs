@@ -84,10 +84,7 @@
 //!                   &mut read_buf, 0x1000);
 //!
 //! let mut ret = tickv.initialise(hash_function.finish());
-//! while ret.is_err() {
-//!     // There is no actual delay here, in a real implementation wait on some event
-//!     ret = tickv.continue_operation().0;
-//!
+//! loop {
 //!     match ret {
 //!         Err(ErrorCode::ReadNotReady(reg)) => {
 //!             tickv.set_read_buffer(&tickv.tickv.controller.buf.borrow()[reg]);
@@ -97,6 +94,9 @@
 //!         Err(ErrorCode::EraseNotReady(reg)) => {}
 //!         _ => unreachable!(),
 //!     }
+//!
+//!     // There is no actual delay here, in a real implementation wait on some event
+//!     ret = tickv.continue_operation().0;
 //! }
 //!
 //! // Then when calling the TicKV function check for the error. For example
@@ -142,6 +142,12 @@ type ContinueReturn = (
     usize,
 );
 
+/// The keys and value buffers of a `get_keys()` operation.
+type KeysBuffer = &'static mut [(u64, &'static mut [u8])];
+
+/// The status of each key of a `get_keys()` operation.
+type StatusBuffer = &'static mut [Result<usize, ErrorCode>];
+
 /// The struct storing all of the TicKV information for the async implementation.
 pub struct AsyncTicKV<'a, C: FlashController<S>, const S: usize> {
     /// The main TicKV struct
@@ -149,6 +155,8 @@ pub struct AsyncTicKV<'a, C: FlashController<S>, const S: usize> {
     key: Cell<Option<u64>>,
     value: Cell<Option<&'static mut [u8]>>,
     value_length: Cell<usize>,
+    keys: Cell<Option<KeysBuffer>>,
+    keys_status: Cell<Option<StatusBuffer>>,
 }
 
 impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
@@ -164,6 +172,19 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
             key: Cell::new(None),
             value: Cell::new(None),
             value_length: Cell::new(0),
+            keys: Cell::new(None),
+            keys_status: Cell::new(None),
+        }
+    }
+
+    /// Returns `ErrorCode::Busy` if a previous operation has not yet been
+    /// completed by `continue_operation()`. Starting a new operation in that
+    /// case would overwrite the state of the pending one.
+    fn check_idle(&self) -> Result<(), ErrorCode> {
+        if self.tickv.state.get() != State::None {
+            Err(ErrorCode::Busy)
+        } else {
+            Ok(())
         }
     }
 
@@ -178,10 +199,24 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// On success a `SuccessCode` will be returned.
     /// On error a `ErrorCode` will be returned.
     pub fn initialise(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
+        self.check_idle()?;
         self.key.replace(Some(hashed_main_key));
         self.tickv.initialise(hashed_main_key)
     }
 
+    /// Erase every region and set up the flash region as an empty
+    /// key-value store again. All existing keys are lost.
+    ///
+    /// `hashed_main_key`: The u64 hash of the const string `MAIN_KEY`.
+    ///
+    /// On success a `SuccessCode` will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn format(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
+        self.check_idle()?;
+        self.key.replace(Some(hashed_main_key));
+        self.tickv.format(hashed_main_key)
+    }
+
     /// Appends the key/value pair to flash storage.
     ///
     /// `hash`: A hashed key. This key will be used in future to retrieve
@@ -196,6 +231,9 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         value: &'static mut [u8],
         length: usize,
     ) -> Result<SuccessCode, (&'static mut [u8], ErrorCode)> {
+        if let Err(e) = self.check_idle() {
+            return Err((value, e));
+        }
         match self.tickv.append_key(hash, &value[0..length]) {
             Ok(_code) => {
                 // Ok is a problem, since that means no asynchronous operations
@@ -237,6 +275,12 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         hash: u64,
         buf: &'static mut [u8],
     ) -> Result<SuccessCode, (&'static mut [u8], ErrorCode)> {
+        if let Err(e) = self.check_idle() {
+            return Err((buf, e));
+        }
+        // A lookup served from the cached region would complete without an
+        // asynchronous operation, so always read the region again.
+        self.tickv.cached_region.set(None);
         match self.tickv.get_key(hash, buf) {
             Ok(_code) => {
                 // Ok is a problem, since that means no asynchronous operations
@@ -257,6 +301,72 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
         }
     }
 
+    /// Retrieves the values of several keys in a single pass over the flash.
+    ///
+    /// `keys`: The hashed keys and the buffers to store their values to.
+    /// `status`: The result for each entry of `keys`, see
+    ///           `TicKV::get_keys()`. Must be as long as `keys`.
+    ///
+    /// On success a `SuccessCode` will be returned. Once
+    /// `continue_operation()` has completed the operation `keys` and
+    /// `status` can be retrieved with `take_keys()`.
+    /// On error a `ErrorCode` will be returned along with `keys` and
+    /// `status`.
+    pub fn get_keys(
+        &self,
+        keys: KeysBuffer,
+        status: StatusBuffer,
+    ) -> Result<SuccessCode, (KeysBuffer, StatusBuffer, ErrorCode)> {
+        if let Err(e) = self.check_idle() {
+            return Err((keys, status, e));
+        }
+        // As with `get_key()`, make sure the first region is read again.
+        self.tickv.cached_region.set(None);
+        match self.tickv.get_keys(keys, status) {
+            Ok(_code) => Err((keys, status, ErrorCode::ReadFail)),
+            Err(ErrorCode::ReadNotReady(_)) => {
+                self.keys.replace(Some(keys));
+                self.keys_status.replace(Some(status));
+                Ok(SuccessCode::Queued)
+            }
+            Err(e) => Err((keys, status, e)),
+        }
+    }
+
+    /// Returns the `keys` and `status` buffers passed to `get_keys()`.
+    ///
+    /// Returns `None` while the `get_keys()` operation is still waiting to
+    /// be continued, or if there are no buffers to return.
+    pub fn take_keys(&self) -> Option<(KeysBuffer, StatusBuffer)> {
+        if let State::GetKeys(_) = self.tickv.state.get() {
+            return None;
+        }
+        let keys = self.keys.take()?;
+        let status = self.keys_status.take()?;
+        Some((keys, status))
+    }
+
+    /// Finds the region that stores the value for a key.
+    ///
+    /// `hash`: A hashed key.
+    ///
+    /// On success a `SuccessCode` will be returned, and the region number
+    /// is returned as the length by `continue_operation()`.
+    /// On error a `ErrorCode` will be returned.
+    pub fn find_key_region(&self, hash: u64) -> Result<SuccessCode, ErrorCode> {
+        self.check_idle()?;
+        match self.tickv.find_key_region(hash) {
+            Ok(_region) => Err(ErrorCode::ReadFail),
+            Err(e) => match e {
+                ErrorCode::ReadNotReady(_) => {
+                    self.key.replace(Some(hash));
+                    Ok(SuccessCode::Queued)
+                }
+                _ => Err(e),
+            },
+        }
+    }
+
     /// Invalidates the key in flash storage
     ///
     /// `hash`: A hashed key.
@@ -268,6 +378,7 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// If a power loss occurs before success is returned the data is
     /// assumed to be lost.
     pub fn invalidate_key(&self, hash: u64) -> Result<SuccessCode, ErrorCode> {
+        self.check_idle()?;
         match self.tickv.invalidate_key(hash) {
             Ok(_code) => Err(ErrorCode::WriteFail),
             Err(_e) => {
@@ -288,6 +399,7 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// If a power loss occurs before success is returned the data is
     /// assumed to be lost.
     pub fn zeroise_key(&self, hash: u64) -> Result<SuccessCode, ErrorCode> {
+        self.check_idle()?;
         match self.tickv.zeroise_key(hash) {
             Ok(_code) => Err(ErrorCode::WriteFail),
             Err(_e) => {
@@ -302,6 +414,7 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// On success a `SuccessCode` will be returned.
     /// On error a `ErrorCode` will be returned.
     pub fn garbage_collect(&self) -> Result<SuccessCode, ErrorCode> {
+        self.check_idle()?;
         match self.tickv.garbage_collect() {
             Ok(_code) => Err(ErrorCode::EraseFail),
             Err(_e) => Ok(SuccessCode::Queued),
@@ -314,6 +427,7 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     /// completed.
     pub fn set_read_buffer(&self, read_buffer: &[u8]) {
         let buf = self.tickv.read_buffer.take().unwrap();
+        self.tickv.cached_region.set(None);
         buf.copy_from_slice(read_buffer);
         self.tickv.read_buffer.replace(Some(buf));
     }
@@ -334,7 +448,14 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
     ///        An option of the buf buffer used
     ///    Length usize:
     ///        The number of valid bytes in the buffer. 0 if Buf is None.
+    ///        For `garbage_collect()` this is the number of bytes freed, and
+    ///        for `find_key_region()` the region number.
     /// The buffers will only be returned on a non async error or on success.
+    /// The buffers of `get_keys()` are instead returned by `take_keys()`.
+    ///
+    /// The operation to continue is tracked internally. If no operation is
+    /// waiting to be continued `ErrorCode::NoOperation` is returned and
+    /// nothing else happens.
     pub fn continue_operation(&self) -> ContinueReturn {
         let (ret, length) = match self.tickv.state.get() {
             State::Init(_) => (self.tickv.initialise(self.key.get().unwrap()), 0),
@@ -356,13 +477,25 @@ impl<'a, C: FlashController<S>, const S: usize> AsyncTicKV<'a, C, S> {
                     Err(e) => (Err(e), 0),
                 }
             }
+            State::FindKeyRegion(_) => match self.tickv.find_key_region(self.key.get().unwrap()) {
+                Ok(region) => (Ok(SuccessCode::Complete), region),
+                Err(e) => (Err(e), 0),
+            },
             State::InvalidateKey(_) => (self.tickv.invalidate_key(self.key.get().unwrap()), 0),
             State::ZeroiseKey(_) => (self.tickv.zeroise_key(self.key.get().unwrap()), 0),
             State::GarbageCollect(_) => match self.tickv.garbage_collect() {
                 Ok(bytes_freed) => (Ok(SuccessCode::Complete), bytes_freed),
                 Err(e) => (Err(e), 0),
             },
-            _ => unreachable!(),
+            State::GetKeys(_) => {
+                let keys = self.keys.take().unwrap();
+                let status = self.keys_status.take().unwrap();
+                let ret = self.tickv.get_keys(keys, status);
+                self.keys.replace(Some(keys));
+                self.keys_status.replace(Some(status));
+                (ret, 0)
+            }
+            State::None => return (Err(ErrorCode::NoOperation), None, 0),
         };
 
         match ret {
@@ -391,13 +524,14 @@ mod tests {
 
     /// Tests using a flash controller that can store data
     mod store_flast_ctrl {
-        use crate::async_ops::AsyncTicKV;
+        use crate::async_ops::{AsyncTicKV, KeysBuffer, StatusBuffer};
         use crate::error_codes::ErrorCode;
         use crate::flash_controller::FlashController;
         use crate::success_codes::SuccessCode;
         use crate::tickv::{HASH_OFFSET, LEN_OFFSET, MAIN_KEY, VERSION, VERSION_OFFSET};
         use core::hash::{Hash, Hasher};
         use core::ptr::addr_of_mut;
+        use std::boxed::Box;
         use std::cell::Cell;
         use std::cell::RefCell;
         use std::collections::hash_map::DefaultHasher;
@@ -665,6 +799,186 @@ mod tests {
             }
         }
 
+        #[test]
+        fn test_busy_while_pending() {
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let mut hash_function = DefaultHasher::new();
+            MAIN_KEY.hash(&mut hash_function);
+
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(
+                FlashCtrl::new(true),
+                &mut read_buf,
+                0x1000,
+            );
+
+            let mut ret = tickv.initialise(hash_function.finish());
+            while ret.is_err() {
+                flash_ctrl_callback(&tickv);
+
+                // There is no actual delay in the test, just continue now
+                let (r, _buf, _len) = tickv.continue_operation();
+                ret = r;
+            }
+
+            static mut VALUE: [u8; 32] = [0x23; 32];
+            static mut BUF: [u8; 32] = [0; 32];
+
+            let ret =
+                unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(VALUE), 32) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+
+            // The append is waiting on a read, so nothing else can start.
+            match unsafe { tickv.get_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(BUF)) } {
+                Err((_buf, ErrorCode::Busy)) => {}
+                _ => panic!("get_key should be busy"),
+            }
+            assert_eq!(
+                tickv.invalidate_key(get_hashed_key(b"ONE")),
+                Err(ErrorCode::Busy)
+            );
+            assert_eq!(tickv.garbage_collect(), Err(ErrorCode::Busy));
+
+            // The pending append is unaffected and can still complete.
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+
+            let ret = unsafe { tickv.get_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(BUF)) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+            assert_eq!(unsafe { BUF }, [0x23; 32]);
+
+            // Continuing with nothing in progress is an error.
+            let (ret, buf, len) = tickv.continue_operation();
+            assert_eq!(ret, Err(ErrorCode::NoOperation));
+            assert!(buf.is_none());
+            assert_eq!(len, 0);
+        }
+
+        #[test]
+        fn test_get_same_region_twice() {
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let mut hash_function = DefaultHasher::new();
+            MAIN_KEY.hash(&mut hash_function);
+
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(
+                FlashCtrl::new(false),
+                &mut read_buf,
+                0x1000,
+            );
+
+            let mut ret = tickv.initialise(hash_function.finish());
+            while ret.is_err() {
+                flash_ctrl_callback(&tickv);
+
+                // There is no actual delay in the test, just continue now
+                let (r, _buf, _len) = tickv.continue_operation();
+                ret = r;
+            }
+
+            static mut VALUE: [u8; 32] = [0x23; 32];
+
+            let ret =
+                unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(VALUE), 32) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+
+            // The second lookup is in the region the first one just read, but
+            // must still complete through `continue_operation()`.
+            for _ in 0..2 {
+                static mut BUF: [u8; 32] = [0; 32];
+                let ret = unsafe { tickv.get_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(BUF)) };
+                assert_eq!(ret, Ok(SuccessCode::Queued));
+                flash_ctrl_callback(&tickv);
+                let (ret, buf, len) = tickv.continue_operation();
+                assert_eq!(ret, Ok(SuccessCode::Complete));
+                assert_eq!(len, 32);
+                assert_eq!(buf.unwrap(), &[0x23; 32]);
+            }
+        }
+
+        #[test]
+        fn test_get_keys() {
+            let mut read_buf: [u8; 1024] = [0; 1024];
+            let mut hash_function = DefaultHasher::new();
+            MAIN_KEY.hash(&mut hash_function);
+
+            let tickv = AsyncTicKV::<FlashCtrl<1024>, 1024>::new(
+                FlashCtrl::new(false),
+                &mut read_buf,
+                0x1000,
+            );
+
+            let mut ret = tickv.initialise(hash_function.finish());
+            while ret.is_err() {
+                flash_ctrl_callback(&tickv);
+
+                // There is no actual delay in the test, just continue now
+                let (r, _buf, _len) = tickv.continue_operation();
+                ret = r;
+            }
+
+            static mut ONE: [u8; 32] = [0x23; 32];
+            static mut TWO: [u8; 16] = [0x42; 16];
+
+            let ret =
+                unsafe { tickv.append_key(get_hashed_key(b"ONE"), &mut *addr_of_mut!(ONE), 32) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+
+            let ret =
+                unsafe { tickv.append_key(get_hashed_key(b"TWO"), &mut *addr_of_mut!(TWO), 16) };
+            assert_eq!(ret, Ok(SuccessCode::Queued));
+            flash_ctrl_callback(&tickv);
+            tickv.continue_operation().0.unwrap();
+
+            let keys: KeysBuffer = Box::leak(Box::new([
+                (
+                    get_hashed_key(b"ONE"),
+                    &mut Box::leak(Box::new([0_u8; 32]))[..],
+                ),
+                (
+                    get_hashed_key(b"TWO"),
+                    &mut Box::leak(Box::new([0_u8; 32]))[..],
+                ),
+                (
+                    get_hashed_key(b"THREE"),
+                    &mut Box::leak(Box::new([0_u8; 32]))[..],
+                ),
+            ]));
+            let status: StatusBuffer = Box::leak(Box::new([Ok(0); 3]));
+
+            match tickv.get_keys(keys, status) {
+                Ok(SuccessCode::Queued) => {}
+                _ => panic!("get_keys should be queued"),
+            }
+            let mut ret = Err(ErrorCode::ReadNotReady(0));
+            while ret.is_err() {
+                // Nothing is returned until the operation has completed.
+                assert!(tickv.take_keys().is_none());
+
+                flash_ctrl_callback(&tickv);
+                let (r, buf, _len) = tickv.continue_operation();
+                assert!(buf.is_none());
+                ret = r;
+                if let Err(e) = ret {
+                    assert!(matches!(e, ErrorCode::ReadNotReady(_)));
+                }
+            }
+            assert_eq!(ret, Ok(SuccessCode::Complete));
+
+            let (keys, status) = tickv.take_keys().unwrap();
+            assert_eq!(status, [Ok(32), Ok(16), Err(ErrorCode::KeyNotFound)]);
+            assert_eq!(keys[0].1, [0x23; 32]);
+            assert_eq!(keys[1].1[0..16], [0x42; 16]);
+            assert!(tickv.take_keys().is_none());
+
+            // The operation has finished, so nothing is left to continue.
+            assert_eq!(tickv.continue_operation().0, Err(ErrorCode::NoOperation));
+        }
+
         #[test]
         fn test_double_append() {
             let mut read_buf: [u8; 1024] = [0; 1024];
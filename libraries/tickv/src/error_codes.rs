@@ -34,6 +34,9 @@ pub enum ErrorCode {
     EraseFail,
     /// The object is larger then 0x7FFF
     ObjectTooLarge,
+    /// The value can never fit in a single region, even if the flash
+    /// is empty. See `TicKV::max_value_size()`.
+    ValueTooLarge,
     /// The supplied buffer is too small.
     /// The error code includes the total length of the value.
     BufferTooSmall(usize),
@@ -44,6 +47,16 @@ pub enum ErrorCode {
     WriteNotReady(usize),
     /// Indicates that the flash erase operation is not yet ready.
     EraseNotReady(usize),
+    /// A previous operation is still waiting to be continued after a
+    /// `NotReady` error, so a new one can't be started.
+    Busy,
+    /// The first object header of the region has an unknown version, so the
+    /// region is probably corrupt. Only returned when initialising. The error
+    /// code includes the region number.
+    CorruptRegion(usize),
+    /// `continue_operation()` was called, but no asynchronous operation is
+    /// waiting to be continued.
+    NoOperation,
 }
 
 impl From<ErrorCode> for isize {
@@ -64,6 +77,10 @@ impl From<ErrorCode> for isize {
             ErrorCode::ReadNotReady(_) => -13,
             ErrorCode::WriteNotReady(_) => -14,
             ErrorCode::EraseNotReady(_) => -15,
+            ErrorCode::ValueTooLarge => -16,
+            ErrorCode::Busy => -17,
+            ErrorCode::CorruptRegion(_) => -18,
+            ErrorCode::NoOperation => -19,
         }
     }
 }
@@ -305,9 +305,7 @@ mod store_flast_ctrl {
 
         fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
             println!("Erase region: {}", region_number);
-            let mut local_buf = self.buf.borrow_mut()[region_number];
-
-            for d in local_buf.iter_mut() {
+            for d in self.buf.borrow_mut()[region_number].iter_mut() {
                 *d = 0xFF;
             }
 
@@ -527,19 +525,180 @@ mod store_flast_ctrl {
         println!("Add Key ONE");
         tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
     }
+
+    #[test]
+    fn test_find_key_region() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+
+        println!("Add key ONE");
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+
+        println!("Find key ONE");
+        let region = tickv.find_key_region(get_hashed_key(b"ONE")).unwrap();
+        check_region_one(&tickv.controller.buf.borrow()[region]);
+
+        println!("Find non-existant key TWO");
+        assert_eq!(
+            tickv.find_key_region(get_hashed_key(b"TWO")),
+            Err(ErrorCode::KeyNotFound)
+        );
+    }
+
+    #[test]
+    fn test_get_keys() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+        tickv
+            .append_key(get_hashed_key(b"THREE"), &[0x33; 16])
+            .unwrap();
+        tickv
+            .append_key(get_hashed_key(b"FOUR"), &[0x44; 8])
+            .unwrap();
+        tickv
+            .append_key(get_hashed_key(b"FIVE"), &[0x55; 4])
+            .unwrap();
+
+        println!("Get five keys");
+        let mut buf_one: [u8; 32] = [0; 32];
+        let mut buf_two: [u8; 32] = [0; 32];
+        let mut buf_three: [u8; 32] = [0; 32];
+        let mut buf_four: [u8; 32] = [0; 32];
+        let mut buf_five: [u8; 32] = [0; 32];
+        let mut keys: [(u64, &mut [u8]); 5] = [
+            (get_hashed_key(b"FIVE"), &mut buf_five),
+            (get_hashed_key(b"ONE"), &mut buf_one),
+            (get_hashed_key(b"FOUR"), &mut buf_four),
+            (get_hashed_key(b"TWO"), &mut buf_two),
+            (get_hashed_key(b"THREE"), &mut buf_three),
+        ];
+        let mut status = [Ok(0); 5];
+        tickv.get_keys(&mut keys, &mut status).unwrap();
+
+        assert_eq!(status, [Ok(4), Ok(32), Ok(8), Ok(32), Ok(16)]);
+        assert_eq!(buf_one, [0x23; 32]);
+        assert_eq!(buf_two, [0x23; 32]);
+        assert_eq!(buf_three[..16], [0x33; 16]);
+        assert_eq!(buf_four[..8], [0x44; 8]);
+        assert_eq!(buf_five[..4], [0x55; 4]);
+
+        println!("Get an existing and a non-existant key");
+        let mut keys: [(u64, &mut [u8]); 2] = [
+            (get_hashed_key(b"SIX"), &mut buf_one),
+            (get_hashed_key(b"FOUR"), &mut buf_two),
+        ];
+        let mut status = [Ok(0); 2];
+        tickv.get_keys(&mut keys, &mut status).unwrap();
+        assert_eq!(status, [Err(ErrorCode::KeyNotFound), Ok(8)]);
+    }
+
+    #[test]
+    fn test_corrupt_region() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        // The region the main key is stored in
+        let region = (hash as usize & 0xFFFF) % 64;
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.controller.buf.borrow_mut()[region][VERSION_OFFSET] = VERSION + 1;
+
+        println!("Initialise with a corrupt region");
+        assert_eq!(
+            tickv.initialise(hash),
+            Err(ErrorCode::CorruptRegion(region))
+        );
+        // Nothing was erased
+        assert_eq!(
+            tickv.controller.buf.borrow()[region][VERSION_OFFSET],
+            VERSION + 1
+        );
+
+        println!("Format to recover");
+        tickv.format(hash).unwrap();
+        tickv.initialise(hash).unwrap();
+
+        println!("Only initialise reports corrupt regions");
+        tickv.controller.buf.borrow_mut()[region][VERSION_OFFSET] = VERSION + 1;
+        tickv.cached_region.set(None);
+        let mut buf: [u8; 0] = [];
+        assert_eq!(
+            tickv.get_key(hash, &mut buf),
+            Err(ErrorCode::UnsupportedVersion)
+        );
+    }
+
+    #[test]
+    fn test_format() {
+        let mut read_buf: [u8; 1024] = [0; 1024];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 1024>::new(FlashCtrl::new(), &mut read_buf, 0x10000);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+
+        println!("Add keys ONE, TWO and THREE");
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+        tickv.append_key(get_hashed_key(b"THREE"), &value).unwrap();
+
+        println!("Format");
+        tickv.format(hash).unwrap();
+
+        for key in [&b"ONE"[..], b"TWO", b"THREE"] {
+            assert_eq!(
+                tickv.get_key(get_hashed_key(key), &mut buf),
+                Err(ErrorCode::KeyNotFound)
+            );
+        }
+
+        // Only the main key is left.
+        let flash = tickv.controller.buf.borrow();
+        let mut used = flash.iter().filter(|region| region[0] != 0xFF);
+        check_region_main(used.next().unwrap());
+        assert!(used.next().is_none());
+    }
 }
 
 mod no_check_store_flast_ctrl {
     use super::*;
+    use crate::success_codes::SuccessCode;
     // An example FlashCtrl implementation
     struct FlashCtrl {
         buf: RefCell<[[u8; 256]; 2]>,
+        reads: Cell<usize>,
+        // Report the next read as not ready, after filling the buffer
+        read_not_ready: Cell<bool>,
     }
 
     impl FlashCtrl {
         fn new() -> Self {
             Self {
                 buf: RefCell::new([[0xFF; 256]; 2]),
+                reads: Cell::new(0),
+                read_not_ready: Cell::new(false),
             }
         }
     }
@@ -547,11 +706,15 @@ mod no_check_store_flast_ctrl {
     impl FlashController<256> for FlashCtrl {
         fn read_region(&self, region_number: usize, buf: &mut [u8; 256]) -> Result<(), ErrorCode> {
             println!("Read from region: {}", region_number);
+            self.reads.set(self.reads.get() + 1);
 
             for (i, b) in buf.iter_mut().enumerate() {
                 *b = self.buf.borrow()[region_number][i]
             }
 
+            if self.read_not_ready.take() {
+                return Err(ErrorCode::ReadNotReady(region_number));
+            }
             Ok(())
         }
 
@@ -571,15 +734,100 @@ mod no_check_store_flast_ctrl {
 
         fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
             println!("Erase region: {}", region_number);
-            let mut local_buf = self.buf.borrow_mut()[region_number];
-
-            for d in local_buf.iter_mut() {
+            for d in self.buf.borrow_mut()[region_number].iter_mut() {
                 *d = 0xFF;
             }
 
             Ok(())
         }
     }
+
+    #[test]
+    fn test_busy_while_pending() {
+        let mut read_buf: [u8; 256] = [0; 256];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x200);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+
+        // Leave a lookup waiting to be continued.
+        tickv.cached_region.set(None);
+        tickv.controller.read_not_ready.set(true);
+        assert!(matches!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Err(ErrorCode::ReadNotReady(_))
+        ));
+
+        // Nothing else can start in the meantime.
+        assert_eq!(
+            tickv.append_key(get_hashed_key(b"TWO"), &value),
+            Err(ErrorCode::Busy)
+        );
+        assert_eq!(
+            tickv.invalidate_key(get_hashed_key(b"ONE")),
+            Err(ErrorCode::Busy)
+        );
+        assert_eq!(
+            tickv.zeroise_key(get_hashed_key(b"ONE")),
+            Err(ErrorCode::Busy)
+        );
+        assert_eq!(
+            tickv.find_key_region(get_hashed_key(b"ONE")),
+            Err(ErrorCode::Busy)
+        );
+        assert_eq!(tickv.garbage_collect(), Err(ErrorCode::Busy));
+        assert_eq!(tickv.initialise(hash), Err(ErrorCode::Busy));
+        let mut other: [u8; 32] = [0; 32];
+        let mut keys = [(get_hashed_key(b"ONE"), &mut other[..])];
+        let mut status = [Ok(0)];
+        assert_eq!(tickv.get_keys(&mut keys, &mut status), Err(ErrorCode::Busy));
+
+        // The pending lookup is unaffected and can still complete.
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Ok((SuccessCode::Complete, 32))
+        );
+        assert_eq!(buf, value);
+    }
+
+    #[test]
+    fn test_get_key_cached() {
+        let mut read_buf: [u8; 256] = [0; 256];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x200);
+        tickv.initialise(hash).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+
+        println!("Get key ONE twice");
+        tickv.get_key(get_hashed_key(b"ONE"), &mut buf).unwrap();
+        let reads = tickv.controller.reads.get();
+        buf = [0; 32];
+        tickv.get_key(get_hashed_key(b"ONE"), &mut buf).unwrap();
+        assert_eq!(buf, value);
+        assert_eq!(tickv.controller.reads.get(), reads);
+
+        println!("Erase the flash without reading it");
+        tickv.write_raw(&[0xFF; 0x200]).unwrap();
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+        assert!(tickv.controller.reads.get() > reads);
+    }
+
     #[test]
     fn test_region_full() {
         let mut read_buf: [u8; 256] = [0; 256];
@@ -665,4 +913,415 @@ mod no_check_store_flast_ctrl {
             Err(ErrorCode::KeyNotFound)
         );
     }
+
+    #[test]
+    fn test_value_too_large() {
+        let mut read_buf: [u8; 256] = [0; 256];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x200);
+        tickv.initialise(hash).unwrap();
+
+        assert_eq!(tickv.max_value_size(), 256 - 15);
+
+        let value: [u8; 256 - 14] = [0x23; 256 - 14];
+        let mut buf: [u8; 256 - 14] = [0; 256 - 14];
+
+        println!("Add Key ONE, too large");
+        assert_eq!(
+            tickv.append_key(get_hashed_key(b"ONE"), &value),
+            Err(ErrorCode::ValueTooLarge)
+        );
+
+        println!("Add Key ONE, maximum size");
+        tickv
+            .append_key(get_hashed_key(b"ONE"), &value[..tickv.max_value_size()])
+            .unwrap();
+
+        println!("Get key ONE");
+        tickv.get_key(get_hashed_key(b"ONE"), &mut buf).unwrap();
+    }
+
+    #[test]
+    fn test_write_raw() {
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+
+        // Build an image using a separate TicKV instance.
+        let mut image: [u8; 0x200] = [0; 0x200];
+        let mut build_buf: [u8; 256] = [0; 256];
+        let builder = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut build_buf, 0x200);
+        builder.initialise(hash).unwrap();
+        builder.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        for (region, data) in image.chunks_mut(256).enumerate() {
+            builder
+                .controller
+                .read_region(region, data.try_into().unwrap())
+                .unwrap();
+        }
+
+        let mut read_buf: [u8; 256] = [0; 256];
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x200);
+        tickv.initialise(hash).unwrap();
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+
+        println!("Write raw image");
+        assert_eq!(tickv.write_raw(&[0; 0x201]), Err(ErrorCode::FlashFull));
+        tickv.write_raw(&image).unwrap();
+        tickv.remount(hash).unwrap();
+
+        println!("Get key ONE");
+        tickv.get_key(get_hashed_key(b"ONE"), &mut buf).unwrap();
+        assert_eq!(buf, value);
+
+        println!("Get key TWO");
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"TWO"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+    }
+}
+
+/// Tests using a flash controller that can lose power while writing
+mod power_fail_flash_ctrl {
+    use super::*;
+    use crate::success_codes::SuccessCode;
+    use crate::tickv::CHECK_SUM_LEN;
+
+    // An example FlashCtrl implementation
+    struct FlashCtrl {
+        buf: RefCell<[[u8; 256]; 4]>,
+        power_fail: Cell<bool>,
+    }
+
+    impl FlashCtrl {
+        fn new() -> Self {
+            Self {
+                buf: RefCell::new([[0xFF; 256]; 4]),
+                power_fail: Cell::new(false),
+            }
+        }
+    }
+
+    impl FlashController<256> for FlashCtrl {
+        fn read_region(&self, region_number: usize, buf: &mut [u8; 256]) -> Result<(), ErrorCode> {
+            println!("Read from region: {}", region_number);
+
+            buf.copy_from_slice(&self.buf.borrow()[region_number]);
+
+            Ok(())
+        }
+
+        fn write(&self, address: usize, buf: &[u8]) -> Result<(), ErrorCode> {
+            println!(
+                "Write to address: {:#x}, region: {}",
+                address,
+                address / 256
+            );
+
+            // Power fails after the value is written, before the check sum
+            let len = if self.power_fail.take() {
+                buf.len() - CHECK_SUM_LEN
+            } else {
+                buf.len()
+            };
+
+            for (i, d) in buf[..len].iter().enumerate() {
+                self.buf.borrow_mut()[address / 256][(address % 256) + i] &= *d;
+            }
+
+            if len < buf.len() {
+                return Err(ErrorCode::WriteNotReady(address / 256));
+            }
+
+            Ok(())
+        }
+
+        fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
+            println!("Erase region: {}", region_number);
+            for d in self.buf.borrow_mut()[region_number].iter_mut() {
+                *d = 0xFF;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_power_fail_append() {
+        let mut read_buf: [u8; 256] = [0; 256];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x400);
+        tickv.initialise(hash_function.finish()).unwrap();
+
+        let value: [u8; 32] = [0x23; 32];
+        let mut buf: [u8; 32] = [0; 32];
+
+        println!("Add key ONE");
+        tickv.append_key(get_hashed_key(b"ONE"), &value).unwrap();
+        assert_eq!(tickv.flush_pending(), Ok(SuccessCode::Complete));
+
+        println!("Add key TWO, losing power before the check sum");
+        tickv.controller.power_fail.set(true);
+        assert_eq!(
+            tickv.append_key(get_hashed_key(b"TWO"), &value),
+            Ok(SuccessCode::Queued)
+        );
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"TWO"), &mut buf),
+            Err(ErrorCode::InvalidCheckSum)
+        );
+        assert_eq!(tickv.flush_pending(), Ok(SuccessCode::Written));
+        assert_eq!(tickv.flush_pending(), Ok(SuccessCode::Complete));
+
+        println!("Get key TWO");
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"TWO"), &mut buf),
+            Err(ErrorCode::KeyNotFound)
+        );
+
+        println!("Get key ONE");
+        tickv.get_key(get_hashed_key(b"ONE"), &mut buf).unwrap();
+        assert_eq!(buf, value);
+
+        println!("Add key TWO again");
+        tickv.append_key(get_hashed_key(b"TWO"), &value).unwrap();
+        tickv.get_key(get_hashed_key(b"TWO"), &mut buf).unwrap();
+        assert_eq!(buf, value);
+    }
+}
+
+/// Tests that count how often each region is erased
+mod erase_count_flash_ctrl {
+    use super::*;
+    // An example FlashCtrl implementation
+    struct FlashCtrl {
+        buf: RefCell<[[u8; 256]; 16]>,
+    }
+
+    impl FlashCtrl {
+        fn new() -> Self {
+            Self {
+                buf: RefCell::new([[0xFF; 256]; 16]),
+            }
+        }
+    }
+
+    impl FlashController<256> for FlashCtrl {
+        fn read_region(&self, region_number: usize, buf: &mut [u8; 256]) -> Result<(), ErrorCode> {
+            buf.copy_from_slice(&self.buf.borrow()[region_number]);
+            Ok(())
+        }
+
+        fn write(&self, address: usize, buf: &[u8]) -> Result<(), ErrorCode> {
+            let offset = address % 256;
+            self.buf.borrow_mut()[address / 256][offset..(offset + buf.len())].copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
+            println!("Erase region: {}", region_number);
+            self.buf.borrow_mut()[region_number] = [0xFF; 256];
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_wear_leveling() {
+        const ROUNDS: u64 = 42;
+        const DISTANCE: usize = 3;
+
+        let mut read_buf: [u8; 256] = [0; 256];
+        let erase_counts: [Cell<u32>; 16] = Default::default();
+        let hash = get_hashed_key(MAIN_KEY);
+
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x1000);
+        tickv.set_wear_leveling(&erase_counts, DISTANCE);
+        tickv.initialise(hash).unwrap();
+
+        // Initialising blank flash erases every region once.
+        assert!(erase_counts.iter().all(|count| count.get() == 1));
+
+        // Hammer a region far enough from the main key that it is never
+        // skipped over. The region holding the main key is never erased.
+        let main_region = tickv.find_key_region(hash).unwrap();
+        let home = if main_region < 8 {
+            main_region + 6
+        } else {
+            main_region - 6
+        };
+
+        let value: [u8; 32] = [0x23; 32];
+
+        for round in 0..ROUNDS {
+            // All of these keys hash to the `home` region and fit in a single
+            // region together.
+            let keys = (0..4).map(|key| (round << 24) | (key << 16) | home as u64);
+
+            for key in keys.clone() {
+                tickv.append_key(key, &value).unwrap();
+            }
+            for key in keys {
+                let mut buf: [u8; 32] = [0; 32];
+                tickv.get_key(key, &mut buf).unwrap();
+                assert_eq!(buf, value);
+                tickv.invalidate_key(key).unwrap();
+            }
+
+            assert_eq!(tickv.garbage_collect(), Ok(256));
+        }
+
+        // The erases are spread over every region within the distance, rather
+        // than all hitting `home`.
+        let first = home.saturating_sub(DISTANCE);
+        let last = core::cmp::min(home + DISTANCE, 15);
+        let window = &erase_counts[first..=last];
+        let max = window.iter().map(Cell::get).max().unwrap();
+        let min = window.iter().map(Cell::get).min().unwrap();
+        println!("Erase counts: {:?}", erase_counts);
+        assert!(max - min <= 1);
+        assert_eq!(
+            window.iter().map(Cell::get).sum::<u32>(),
+            window.len() as u32 + ROUNDS as u32
+        );
+        for (region, count) in erase_counts.iter().enumerate() {
+            assert_eq!(tickv.erase_count(region), Some(count.get()));
+            if !(first..=last).contains(&region) {
+                assert_eq!(count.get(), 1);
+            }
+        }
+
+        // The main key can still be found.
+        tickv.initialise(hash).unwrap();
+    }
+
+    #[test]
+    fn test_wear_leveling_existing_key_in_neighbour() {
+        const DISTANCE: usize = 3;
+
+        let mut read_buf: [u8; 256] = [0; 256];
+        let erase_counts: [Cell<u32>; 16] = Default::default();
+        let hash = get_hashed_key(MAIN_KEY);
+
+        let tickv = TicKV::<FlashCtrl, 256>::new(FlashCtrl::new(), &mut read_buf, 0x1000);
+        tickv.set_wear_leveling(&erase_counts, DISTANCE);
+        tickv.initialise(hash).unwrap();
+
+        let main_region = tickv.find_key_region(hash).unwrap();
+        let home = if main_region < 8 {
+            main_region + 6
+        } else {
+            main_region - 6
+        };
+        let key = (1 << 16) | home as u64;
+        let value: [u8; 32] = [0x23; 32];
+
+        // Make the neighbour the least worn region, so the key is put there.
+        for count in erase_counts.iter() {
+            count.set(5);
+        }
+        erase_counts[home + 1].set(1);
+        tickv.append_key(key, &value).unwrap();
+        assert_eq!(tickv.find_key_region(key), Ok(home + 1));
+
+        // Now the hashed region is the least worn, as it would be after a
+        // reboot resets the erase counts. The key must still be found.
+        for count in erase_counts.iter() {
+            count.set(1);
+        }
+        assert_eq!(
+            tickv.append_key(key, &[0x42; 32]),
+            Err(ErrorCode::KeyAlreadyExists)
+        );
+
+        // Only one copy was stored, so invalidating it removes the key.
+        let mut buf: [u8; 32] = [0; 32];
+        tickv.get_key(key, &mut buf).unwrap();
+        assert_eq!(buf, value);
+        tickv.invalidate_key(key).unwrap();
+        assert_eq!(tickv.get_key(key, &mut buf), Err(ErrorCode::KeyNotFound));
+    }
+}
+
+mod large_region_flash_ctrl {
+    use super::*;
+
+    const REGION_SIZE: usize = 0x1000;
+
+    // A FlashCtrl whose regions are larger than the biggest object
+    struct FlashCtrl {
+        buf: RefCell<[[u8; REGION_SIZE]; 2]>,
+    }
+
+    impl FlashCtrl {
+        fn new() -> Self {
+            Self {
+                buf: RefCell::new([[0xFF; REGION_SIZE]; 2]),
+            }
+        }
+    }
+
+    impl FlashController<REGION_SIZE> for FlashCtrl {
+        fn read_region(
+            &self,
+            region_number: usize,
+            buf: &mut [u8; REGION_SIZE],
+        ) -> Result<(), ErrorCode> {
+            buf.copy_from_slice(&self.buf.borrow()[region_number]);
+            Ok(())
+        }
+
+        fn write(&self, address: usize, buf: &[u8]) -> Result<(), ErrorCode> {
+            let region = address / REGION_SIZE;
+            let offset = address % REGION_SIZE;
+            self.buf.borrow_mut()[region][offset..offset + buf.len()].copy_from_slice(buf);
+            Ok(())
+        }
+
+        fn erase_region(&self, region_number: usize) -> Result<(), ErrorCode> {
+            self.buf.borrow_mut()[region_number] = [0xFF; REGION_SIZE];
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_max_value_size_is_accepted() {
+        let mut read_buf: [u8; REGION_SIZE] = [0; REGION_SIZE];
+        let mut hash_function = DefaultHasher::new();
+        MAIN_KEY.hash(&mut hash_function);
+        let hash = hash_function.finish();
+
+        let tickv =
+            TicKV::<FlashCtrl, REGION_SIZE>::new(FlashCtrl::new(), &mut read_buf, 2 * REGION_SIZE);
+        tickv.initialise(hash).unwrap();
+
+        // Limited by the 12-bit length in the header, not the region size.
+        let max = tickv.max_value_size();
+        assert_eq!(max, 0xFFE - 15);
+
+        let value = [0x23; 0xFFF];
+        let mut buf = [0; 0xFFF];
+
+        assert_eq!(
+            tickv.append_key(get_hashed_key(b"ONE"), &value[..max + 1]),
+            Err(ErrorCode::ValueTooLarge)
+        );
+
+        tickv
+            .append_key(get_hashed_key(b"ONE"), &value[..max])
+            .unwrap();
+        assert_eq!(
+            tickv.get_key(get_hashed_key(b"ONE"), &mut buf).unwrap().1,
+            max
+        );
+        assert_eq!(buf[..max], value[..max]);
+    }
 }
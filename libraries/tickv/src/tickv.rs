@@ -15,6 +15,8 @@ pub const VERSION: u8 = 1;
 
 #[derive(Clone, Copy, PartialEq)]
 pub(crate) enum InitState {
+    /// Formatting, all regions need to be erased
+    Format,
     /// Trying to read the key from a region
     GetKeyReadRegion(usize),
     /// Trying to erase a region
@@ -49,6 +51,10 @@ pub(crate) enum State {
     AppendKey(KeyState),
     /// Getting a key
     GetKey(KeyState),
+    /// Getting several keys
+    GetKeys(KeyState),
+    /// Finding the region of a key
+    FindKeyRegion(KeyState),
     /// Invalidating a key
     InvalidateKey(KeyState),
     /// Zeroizing a key
@@ -62,8 +68,25 @@ pub struct TicKV<'a, C: FlashController<S>, const S: usize> {
     /// The controller used for flash commands
     pub controller: C,
     flash_size: usize,
+    max_value_size: usize,
     pub(crate) read_buffer: Cell<Option<&'a mut [u8; S]>>,
     pub(crate) state: Cell<State>,
+    /// The flash address and length byte of an appended object whose write
+    /// has not been reported as complete.
+    pending_append: Cell<Option<(usize, u8)>>,
+    /// The region whose contents are in `read_buffer`, if `get_key()` can
+    /// use them without reading the region again. `AsyncTicKV` does not use
+    /// it, as its callers expect every lookup to complete asynchronously.
+    pub(crate) cached_region: Cell<Option<usize>>,
+    /// The erase count of each region and the wear leveling distance, set by
+    /// `set_wear_leveling()`.
+    wear_leveling: Cell<Option<(&'a [Cell<u32>], usize)>>,
+    /// The least worn region with space for the object being appended that
+    /// `append_key()` has found so far.
+    append_best: Cell<Option<usize>>,
+    /// The region `append_key()` chose for the object being appended, once
+    /// it has looked at every region within the wear leveling distance.
+    append_target: Cell<Option<usize>>,
 }
 
 /// This is the current object header used for TicKV objects
@@ -80,7 +103,7 @@ pub(crate) const FLAGS_VALID: u8 = 8;
 
 impl ObjectHeader {
     fn new(hashed_key: u64, len: u16) -> Self {
-        assert!(len < 0xFFF);
+        assert!(len as usize <= MAX_OBJECT_LENGTH);
         Self {
             version: VERSION,
             flags: FLAGS_VALID,
@@ -96,6 +119,8 @@ pub(crate) const LEN_OFFSET: usize = 1;
 pub(crate) const HASH_OFFSET: usize = 3;
 pub(crate) const HEADER_LENGTH: usize = HASH_OFFSET + 8;
 pub(crate) const CHECK_SUM_LEN: usize = 4;
+// The largest object length that can be encoded in the header
+const MAX_OBJECT_LENGTH: usize = 0xFFE;
 
 /// The main key. A hashed version of this should be passed to
 /// `initialise()`.
@@ -110,12 +135,164 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// `controller`: An new struct implementing `FlashController`
     /// `flash_size`: The total size of the flash used for TicKV
     pub fn new(controller: C, read_buffer: &'a mut [u8; S], flash_size: usize) -> Self {
+        // An object must fit inside a single region and its length must be
+        // representable in the header.
+        let max_object_length = core::cmp::min(S, MAX_OBJECT_LENGTH);
+
         Self {
             controller,
             flash_size,
+            max_value_size: max_object_length.saturating_sub(HEADER_LENGTH + CHECK_SUM_LEN),
             read_buffer: Cell::new(Some(read_buffer)),
             state: Cell::new(State::None),
+            pending_append: Cell::new(None),
+            cached_region: Cell::new(None),
+            wear_leveling: Cell::new(None),
+            append_best: Cell::new(None),
+            append_target: Cell::new(None),
+        }
+    }
+
+    /// Reads a region into the read buffer, replacing the cached region.
+    fn flash_read_region(&self, region: usize, buf: &mut [u8; S]) -> Result<(), ErrorCode> {
+        self.cached_region.set(None);
+        self.controller.read_region(region, buf)
+    }
+
+    /// Writes to flash, invalidating the cached region if it is written to.
+    fn flash_write(&self, address: usize, buf: &[u8]) -> Result<(), ErrorCode> {
+        if let Some(region) = self.cached_region.get() {
+            let end = address + buf.len();
+            if address < (region + 1) * S && end > region * S {
+                self.cached_region.set(None);
+            }
+        }
+        self.controller.write(address, buf)
+    }
+
+    /// Erases a region, invalidating the cached region if it is erased and
+    /// counting the erase.
+    fn flash_erase_region(&self, region: usize) -> Result<(), ErrorCode> {
+        if self.cached_region.get() == Some(region) {
+            self.cached_region.set(None);
+        }
+        let ret = self.controller.erase_region(region);
+        if let Ok(()) | Err(ErrorCode::EraseNotReady(_)) = ret {
+            // The erase has been done or started.
+            if let Some(count) = self
+                .wear_leveling
+                .get()
+                .and_then(|(erase_counts, _)| erase_counts.get(region))
+            {
+                count.set(count.get().saturating_add(1));
+            }
+        }
+        ret
+    }
+
+    /// Spreads erases more evenly across the regions, by placing objects in
+    /// less worn regions.
+    ///
+    /// `erase_counts` holds one counter per region, which is incremented
+    /// every time the region is erased. The counts are not stored in flash.
+    /// To keep them across resets the platform must save them elsewhere and
+    /// restore them here, otherwise they start again from zero.
+    ///
+    /// `distance` is how far from the region selected by its hash an object
+    /// may be placed. `append_key()` writes the object to the region with
+    /// the lowest erase count among the regions within `distance` that have
+    /// enough space for it, preferring regions closer to the hashed region
+    /// on a tie. Lookups and appends search every region within `distance`,
+    /// so a larger distance spreads the wear further but an append, or a
+    /// lookup of a missing key, reads up to `2 * distance + 1` regions. A
+    /// distance of 0 only counts erases.
+    ///
+    /// This must be called before `initialise()`, with the same `distance`
+    /// every time the flash is mounted.
+    pub fn set_wear_leveling(&self, erase_counts: &'a [Cell<u32>], distance: usize) {
+        assert_eq!(erase_counts.len(), self.flash_size / S);
+        self.wear_leveling.set(Some((erase_counts, distance)));
+    }
+
+    /// Returns the number of times `region` has been erased, if erase counts
+    /// are tracked with `set_wear_leveling()`.
+    pub fn erase_count(&self, region: usize) -> Option<u32> {
+        self.wear_leveling
+            .get()
+            .and_then(|(erase_counts, _)| erase_counts.get(region))
+            .map(|count| count.get())
+    }
+
+    fn wear_leveling_distance(&self) -> usize {
+        self.wear_leveling.get().map_or(0, |(_, distance)| distance)
+    }
+
+    /// Returns the largest value, in bytes, that can be stored.
+    ///
+    /// This is the region size minus the object header and check sum
+    /// overhead. Values larger than this can never be stored, no matter
+    /// how much free space there is.
+    pub fn max_value_size(&self) -> usize {
+        self.max_value_size
+    }
+
+    /// Write a raw TicKV image directly to the start of the flash.
+    ///
+    /// Every region covered by `image` is erased and then written with the
+    /// matching part of the image. This is intended for provisioning, for
+    /// example writing an image that was built on a host by another TicKV
+    /// instance. Use `remount()` afterwards to pick up the new contents.
+    ///
+    /// The flash controller itself is available through the public
+    /// `controller` field for any other direct maintenance.
+    ///
+    /// WARNING: This bypasses all of TicKV's integrity guarantees. It must
+    /// only be called while no other operation is in progress, and only
+    /// with a `FlashController` that completes synchronously.
+    ///
+    /// `image`: The raw image, at most `flash_size` bytes long.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn write_raw(&self, image: &[u8]) -> Result<(), ErrorCode> {
+        if image.len() > self.flash_size {
+            return Err(ErrorCode::FlashFull);
+        }
+
+        for (region, data) in image.chunks(S).enumerate() {
+            self.flash_erase_region(region)?;
+            self.flash_write(region * S, data)?;
         }
+
+        Ok(())
+    }
+
+    /// Re-initialise TicKV from the current contents of the flash, for
+    /// example after the flash was written with `write_raw()`.
+    ///
+    /// Any operation that was in progress is abandoned. Otherwise this
+    /// behaves like `initialise()`, so if the flash does not contain a
+    /// valid TicKV image it will be erased.
+    pub fn remount(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
+        self.state.set(State::None);
+        self.initialise(hashed_main_key)
+    }
+
+    /// Erase every region and set up the flash region as an empty
+    /// key-value store again, as `initialise()` does for blank flash.
+    /// All existing keys are lost.
+    ///
+    /// `hashed_main_key`: The u64 hash of the const string `MAIN_KEY`.
+    ///
+    /// This must only be called while no other operation is in progress.
+    /// If the `FlashController` returns `EraseNotReady` the format is
+    /// resumed by calling `initialise()` again once the erase completes.
+    ///
+    /// On success nothing will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn format(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
+        self.state.set(State::Init(InitState::Format));
+        self.initialise(hashed_main_key)
     }
 
     /// This function setups the flash region to be used as a key-value store.
@@ -126,18 +303,24 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// If the specified region has not already been setup for TicKV
     /// the entire region will be erased.
     ///
+    /// If a region read while looking for the main key starts with an object
+    /// header of an unknown version, for example after bit rot or a partial
+    /// erase, nothing is erased and `ErrorCode::CorruptRegion` is returned
+    /// with the region number. The platform can then decide whether to
+    /// `format()` the flash.
+    ///
     /// On success nothing will be returned.
     /// On error a `ErrorCode` will be returned.
     pub fn initialise(&self, hashed_main_key: u64) -> Result<SuccessCode, ErrorCode> {
         let mut buf: [u8; 0] = [0; 0];
 
         let key_ret = match self.state.get() {
-            State::None => self.get_key(hashed_main_key, &mut buf),
+            State::None => self.lookup_key(hashed_main_key, &mut buf, true),
             State::Init(state) => match state {
-                InitState::GetKeyReadRegion(_) => self.get_key(hashed_main_key, &mut buf),
+                InitState::GetKeyReadRegion(_) => self.lookup_key(hashed_main_key, &mut buf, true),
                 _ => Err(ErrorCode::EraseNotReady(0)),
             },
-            _ => unreachable!(),
+            _ => return Err(ErrorCode::Busy),
         };
 
         match key_ret {
@@ -149,9 +332,14 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                             .set(State::Init(InitState::GetKeyReadRegion(reg)));
                         Err(ErrorCode::ReadNotReady(reg))
                     }
+                    ErrorCode::CorruptRegion(_) => {
+                        self.state.set(State::None);
+                        Err(e)
+                    }
                     _ => {
                         match self.state.get() {
                             State::None
+                            | State::Init(InitState::Format)
                             | State::Init(InitState::GetKeyReadRegion(_))
                             | State::Init(InitState::EraseRegion(_)) => {
                                 // Erase all regions
@@ -163,7 +351,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
 
                                 if start < (self.flash_size / S) {
                                     for r in start..(self.flash_size / S) {
-                                        match self.controller.erase_region(r) {
+                                        match self.flash_erase_region(r) {
                                             Ok(()) => {}
                                             Err(e) => {
                                                 self.state
@@ -262,6 +450,32 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         None
     }
 
+    // Determine the next region offset to try when looking for a key that
+    // was not found at `region_offset`.
+    //
+    // `cont` and `e` are the error returned by `find_key_offset()`. Lookups
+    // stop at the first empty region, as `append_key()` does not place
+    // objects past it, except within the wear leveling distance where
+    // `append_key()` may have skipped over empty regions.
+    //
+    // Returns None if the key is not stored in flash.
+    fn next_lookup_offset(
+        &self,
+        region: usize,
+        region_offset: isize,
+        cont: bool,
+        e: ErrorCode,
+    ) -> Option<isize> {
+        let new_offset = self.increment_region_offset(region, region_offset)?;
+        let within_distance = e == ErrorCode::KeyNotFound
+            && new_offset.unsigned_abs() <= self.wear_leveling_distance();
+        if cont || within_distance {
+            Some(new_offset)
+        } else {
+            None
+        }
+    }
+
     /// Find a key in some loaded region data.
     ///
     /// On success return the offset in the region_data where the key is and the
@@ -382,6 +596,81 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         }
     }
 
+    /// Copy the value of the object at `offset` in some loaded region data
+    /// into `buf` and verify its check sum.
+    ///
+    /// On success return the length of the value.
+    /// If `buf` is too small it is filled with as much of the value as fits
+    /// and `ErrorCode::BufferTooSmall` is returned.
+    fn read_value(
+        &self,
+        region_data: &[u8],
+        offset: usize,
+        total_length: u16,
+        buf: &mut [u8],
+    ) -> Result<usize, ErrorCode> {
+        let check_sum = crc32::Crc32::new();
+
+        // Add the header data to the check hash
+        check_sum.update(
+            region_data
+                .get(offset..(HEADER_LENGTH + offset))
+                .ok_or(ErrorCode::ObjectTooLarge)?,
+        );
+
+        // The size of the stored object's actual data;
+        let value_length = total_length as usize - HEADER_LENGTH - CHECK_SUM_LEN;
+
+        // Make sure if will fit in the buffer
+        if buf.len() < value_length {
+            // The entire value is not going to fit,
+            // Let's still copy in what we can and return an error
+            for i in 0..buf.len() {
+                *buf.get_mut(i)
+                    .ok_or(ErrorCode::BufferTooSmall(value_length))? = *region_data
+                    .get(offset + HEADER_LENGTH + i)
+                    .ok_or(ErrorCode::BufferTooSmall(value_length))?;
+            }
+
+            return Err(ErrorCode::BufferTooSmall(value_length));
+        }
+
+        // Copy in the value
+        for i in 0..value_length {
+            *buf.get_mut(i)
+                .ok_or(ErrorCode::BufferTooSmall(value_length))? = *region_data
+                .get(offset + HEADER_LENGTH + i)
+                .ok_or(ErrorCode::CorruptData)?;
+            check_sum.update(&[*buf.get(i).ok_or(ErrorCode::CorruptData)?])
+        }
+
+        // Check the hash
+        let check_sum = check_sum.finalise();
+        let check_sum = check_sum.to_ne_bytes();
+
+        if *check_sum.get(3).ok_or(ErrorCode::InvalidCheckSum)?
+            != *region_data
+                .get(offset + total_length as usize - 1)
+                .ok_or(ErrorCode::InvalidCheckSum)?
+            || *check_sum.get(2).ok_or(ErrorCode::InvalidCheckSum)?
+                != *region_data
+                    .get(offset + total_length as usize - 2)
+                    .ok_or(ErrorCode::InvalidCheckSum)?
+            || *check_sum.get(1).ok_or(ErrorCode::InvalidCheckSum)?
+                != *region_data
+                    .get(offset + total_length as usize - 3)
+                    .ok_or(ErrorCode::InvalidCheckSum)?
+            || *check_sum.first().ok_or(ErrorCode::InvalidCheckSum)?
+                != *region_data
+                    .get(offset + total_length as usize - 4)
+                    .ok_or(ErrorCode::InvalidCheckSum)?
+        {
+            return Err(ErrorCode::InvalidCheckSum);
+        }
+
+        Ok(value_length)
+    }
+
     /// Appends the key/value pair to flash storage.
     ///
     /// `hash`: A hashed key. This key will be used in future to retrieve
@@ -389,22 +678,39 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// `value`: A buffer containing the data to be stored to flash.
     ///
     /// On success nothing will be returned.
-    /// On error a `ErrorCode` will be returned.
+    /// On error a `ErrorCode` will be returned. If the value is larger
+    /// than `max_value_size()` `ErrorCode::ValueTooLarge` is returned.
     pub fn append_key(&self, hash: u64, value: &[u8]) -> Result<SuccessCode, ErrorCode> {
+        if value.len() > self.max_value_size {
+            return Err(ErrorCode::ValueTooLarge);
+        }
+
         let region = self.get_region(hash);
-        let check_sum = crc32::Crc32::new();
 
         // Length not including check sum
         let package_length = HEADER_LENGTH + value.len();
         let object_length = HEADER_LENGTH + value.len() + CHECK_SUM_LEN;
 
-        if object_length > 0xFFF {
+        if object_length > MAX_OBJECT_LENGTH {
             return Err(ErrorCode::ObjectTooLarge);
         }
 
         // Create the header:
         let header = ObjectHeader::new(hash, object_length as u16);
 
+        let distance = self.wear_leveling_distance();
+
+        match self.state.get() {
+            // This continues an append that was waiting for a read.
+            State::AppendKey(_) | State::Init(InitState::AppendKeyReadRegion(_)) => {}
+            // Look for the least worn region afresh.
+            State::None | State::Init(_) => {
+                self.append_best.set(None);
+                self.append_target.set(None);
+            }
+            _ => return Err(ErrorCode::Busy),
+        }
+
         let mut region_offset: isize = 0;
 
         loop {
@@ -422,14 +728,15 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 State::AppendKey(key_state) => match key_state {
                     KeyState::ReadRegion(reg) => reg,
                 },
-                _ => unreachable!(),
+                _ => return Err(ErrorCode::Busy),
             };
+            region_offset = new_region as isize - region as isize;
 
             let region_data = self.read_buffer.take().unwrap();
             if self.state.get() != State::AppendKey(KeyState::ReadRegion(new_region))
                 && self.state.get() != State::Init(InitState::AppendKeyReadRegion(new_region))
             {
-                match self.controller.read_region(new_region, region_data) {
+                match self.flash_read_region(new_region, region_data) {
                     Ok(()) => {}
                     Err(e) => {
                         self.read_buffer.replace(Some(region_data));
@@ -447,17 +754,59 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 return Err(ErrorCode::KeyAlreadyExists);
             }
 
-            let mut offset: usize = 0;
+            let free_offset = match self.find_free_offset(region_data, package_length) {
+                Ok(free_offset) => free_offset,
+                Err(e) => {
+                    self.read_buffer.replace(Some(region_data));
+                    return Err(e);
+                }
+            };
 
-            loop {
-                if offset + package_length >= S {
-                    // We have reached the end of the region
-                    // We will need to try the next region
+            if self.append_target.get().is_none() && region_offset.unsigned_abs() <= distance {
+                // Within the wear leveling distance, remember the least worn
+                // region with enough space. Every region within the distance
+                // is checked, as the key may already be in any of them.
+                let erase_count = self.erase_count(new_region).unwrap_or(0);
+                if free_offset.is_some()
+                    && self.append_best.get().map_or(true, |best| {
+                        erase_count < self.erase_count(best).unwrap_or(0)
+                    })
+                {
+                    self.append_best.set(Some(new_region));
+                }
+
+                let next_offset = self
+                    .increment_region_offset(region, region_offset)
+                    .filter(|o| o.unsigned_abs() <= distance);
 
-                    // Replace the buffer
+                match (next_offset, self.append_best.get()) {
+                    (Some(o), _) => {
+                        self.read_buffer.replace(Some(region_data));
+                        region_offset = o;
+                        self.state.set(State::None);
+                        continue;
+                    }
+                    (None, Some(best)) if best != new_region => {
+                        // Go back to the chosen region to write the object.
+                        self.read_buffer.replace(Some(region_data));
+                        self.append_target.set(Some(best));
+                        region_offset = best as isize - region as isize;
+                        self.state.set(State::None);
+                        continue;
+                    }
+                    // Either the object is written to this region, or no
+                    // region within the distance has enough space and the
+                    // regions further away are tried in turn.
+                    _ => {}
+                }
+            }
+
+            let offset = match free_offset {
+                Some(offset) => offset,
+                None => {
+                    // We will need to try the next region
                     self.read_buffer.replace(Some(region_data));
 
-                    region_offset = new_region as isize - region as isize;
                     match self.increment_region_offset(region, region_offset) {
                         Some(o) => {
                             region_offset = o;
@@ -467,191 +816,213 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                             return Err(ErrorCode::FlashFull);
                         }
                     }
-                    break;
+                    continue;
                 }
+            };
+
+            let ret = self.write_object(new_region, offset, region_data, &header, value);
+            self.read_buffer.replace(Some(region_data));
+            return ret;
+        }
+    }
+
+    /// Find the offset of the free space in some loaded region data, if an
+    /// object of `package_length` bytes, not including the check sum, fits
+    /// in it.
+    fn find_free_offset(
+        &self,
+        region_data: &[u8],
+        package_length: usize,
+    ) -> Result<Option<usize>, ErrorCode> {
+        let mut offset: usize = 0;
 
-                // Check to see if we have data
+        loop {
+            if offset + package_length >= S {
+                // We have reached the end of the region
+                return Ok(None);
+            }
+
+            // Check to see if we have data
+            if *region_data
+                .get(offset + VERSION_OFFSET)
+                .ok_or(ErrorCode::KeyNotFound)?
+                != 0xFF
+            {
+                // We found a version, check that we support it
                 if *region_data
                     .get(offset + VERSION_OFFSET)
                     .ok_or(ErrorCode::KeyNotFound)?
-                    != 0xFF
+                    != VERSION
                 {
-                    // We found a version, check that we support it
-                    if *region_data
-                        .get(offset + VERSION_OFFSET)
-                        .ok_or(ErrorCode::KeyNotFound)?
-                        != VERSION
-                    {
-                        self.read_buffer.replace(Some(region_data));
-                        return Err(ErrorCode::UnsupportedVersion);
-                    }
+                    return Err(ErrorCode::UnsupportedVersion);
+                }
 
-                    // Find this entries length
-                    let total_length = ((*region_data
-                        .get(offset + LEN_OFFSET)
-                        .ok_or(ErrorCode::CorruptData)?
-                        as u16)
-                        & !0xF0)
-                        << 8
-                        | *region_data
-                            .get(offset + LEN_OFFSET + 1)
-                            .ok_or(ErrorCode::CorruptData)? as u16;
+                // Find this entries length
+                let total_length = ((*region_data
+                    .get(offset + LEN_OFFSET)
+                    .ok_or(ErrorCode::CorruptData)? as u16)
+                    & !0xF0)
+                    << 8
+                    | *region_data
+                        .get(offset + LEN_OFFSET + 1)
+                        .ok_or(ErrorCode::CorruptData)? as u16;
 
-                    // Increment our offset by the length and repeat the loop
-                    offset += total_length as usize;
-                    continue;
-                }
+                // Increment our offset by the length and repeat the loop
+                offset += total_length as usize;
+                continue;
+            }
 
-                // If we get here we have found an empty spot
-                // Double check that there is no valid hash
+            // If we get here we have found an empty spot
+            // Double check that there is no valid hash, the entire hash
+            // should be 0xFFFF_FFFF_FFFF_FFFF
+            if region_data
+                .get((offset + HASH_OFFSET)..(offset + HEADER_LENGTH))
+                .ok_or(ErrorCode::CorruptData)?
+                .iter()
+                .any(|&byte| byte != 0xFF)
+            {
+                return Err(ErrorCode::CorruptData);
+            }
 
-                // Check to see if the entire header is 0xFFFF_FFFF_FFFF_FFFF
-                // To avoid operating on 64-bit values check every 8 bytes at a time
-                if *region_data
-                    .get(offset + HASH_OFFSET)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
-                if *region_data
-                    .get(offset + HASH_OFFSET + 1)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
-                if *region_data
-                    .get(offset + HASH_OFFSET + 2)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
-                if *region_data
-                    .get(offset + HASH_OFFSET + 3)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
-                if *region_data
-                    .get(offset + HASH_OFFSET + 4)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
-                if *region_data
-                    .get(offset + HASH_OFFSET + 5)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
-                if *region_data
-                    .get(offset + HASH_OFFSET + 6)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
-                if *region_data
-                    .get(offset + HASH_OFFSET + 7)
-                    .ok_or(ErrorCode::CorruptData)?
-                    != 0xFF
-                {
-                    self.read_buffer.replace(Some(region_data));
-                    return Err(ErrorCode::CorruptData);
-                }
+            return Ok(Some(offset));
+        }
+    }
 
-                // If we get here we have found an empty spot
-
-                // Copy in new header
-                // This is a little painful, but avoids any unsafe Rust
-                *region_data
-                    .get_mut(offset + VERSION_OFFSET)
-                    .ok_or(ErrorCode::RegionFull)? = header.version;
-                *region_data
-                    .get_mut(offset + LEN_OFFSET)
-                    .ok_or(ErrorCode::RegionFull)? =
-                    (header.len >> 8) as u8 & 0x0F | (header.flags << 4) & 0xF0;
-                *region_data
-                    .get_mut(offset + LEN_OFFSET + 1)
-                    .ok_or(ErrorCode::RegionFull)? = (header.len & 0xFF) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 56) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET + 1)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 48) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET + 2)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 40) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET + 3)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 32) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET + 4)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 24) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET + 5)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 16) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET + 6)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 8) as u8;
-                *region_data
-                    .get_mut(offset + HASH_OFFSET + 7)
-                    .ok_or(ErrorCode::RegionFull)? = (header.hashed_key) as u8;
-
-                // Hash the new header data
-                check_sum.update(
-                    region_data
-                        .get(offset + VERSION_OFFSET..=offset + HASH_OFFSET + 7)
-                        .ok_or(ErrorCode::CorruptData)?,
-                );
-
-                // Copy the value
-                let slice = region_data
-                    .get_mut((offset + HEADER_LENGTH)..(offset + package_length))
-                    .ok_or(ErrorCode::ObjectTooLarge)?;
-                slice.copy_from_slice(value);
-
-                // Include the value in the hash
-                check_sum.update(value);
-
-                // Append a Check Hash
-                let check_sum = check_sum.finalise();
-                let slice = region_data
-                    .get_mut((offset + package_length)..(offset + package_length + CHECK_SUM_LEN))
-                    .ok_or(ErrorCode::ObjectTooLarge)?;
-                slice.copy_from_slice(&check_sum.to_ne_bytes());
-
-                // Write the data back to the region
-                if let Err(e) = self.controller.write(
-                    S * new_region + offset,
-                    region_data
-                        .get(offset..(offset + package_length + CHECK_SUM_LEN))
-                        .ok_or(ErrorCode::ObjectTooLarge)?,
-                ) {
-                    self.read_buffer.replace(Some(region_data));
-                    match e {
-                        ErrorCode::WriteNotReady(_) => return Ok(SuccessCode::Queued),
-                        _ => return Err(e),
-                    }
-                }
+    /// Write an object to the free space at `offset` of `region`, whose data
+    /// is loaded in `region_data`.
+    fn write_object(
+        &self,
+        region: usize,
+        offset: usize,
+        region_data: &mut [u8; S],
+        header: &ObjectHeader,
+        value: &[u8],
+    ) -> Result<SuccessCode, ErrorCode> {
+        let check_sum = crc32::Crc32::new();
 
-                self.read_buffer.replace(Some(region_data));
-                return Ok(SuccessCode::Written);
+        // Length not including check sum
+        let package_length = HEADER_LENGTH + value.len();
+
+        // Copy in new header
+        // This is a little painful, but avoids any unsafe Rust
+        *region_data
+            .get_mut(offset + VERSION_OFFSET)
+            .ok_or(ErrorCode::RegionFull)? = header.version;
+        *region_data
+            .get_mut(offset + LEN_OFFSET)
+            .ok_or(ErrorCode::RegionFull)? =
+            (header.len >> 8) as u8 & 0x0F | (header.flags << 4) & 0xF0;
+        *region_data
+            .get_mut(offset + LEN_OFFSET + 1)
+            .ok_or(ErrorCode::RegionFull)? = (header.len & 0xFF) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 56) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET + 1)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 48) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET + 2)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 40) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET + 3)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 32) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET + 4)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 24) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET + 5)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 16) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET + 6)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key >> 8) as u8;
+        *region_data
+            .get_mut(offset + HASH_OFFSET + 7)
+            .ok_or(ErrorCode::RegionFull)? = (header.hashed_key) as u8;
+
+        // Hash the new header data
+        check_sum.update(
+            region_data
+                .get(offset + VERSION_OFFSET..=offset + HASH_OFFSET + 7)
+                .ok_or(ErrorCode::CorruptData)?,
+        );
+
+        // Copy the value
+        let slice = region_data
+            .get_mut((offset + HEADER_LENGTH)..(offset + package_length))
+            .ok_or(ErrorCode::ObjectTooLarge)?;
+        slice.copy_from_slice(value);
+
+        // Include the value in the hash
+        check_sum.update(value);
+
+        // Append a Check Hash
+        let check_sum = check_sum.finalise();
+        let slice = region_data
+            .get_mut((offset + package_length)..(offset + package_length + CHECK_SUM_LEN))
+            .ok_or(ErrorCode::ObjectTooLarge)?;
+        slice.copy_from_slice(&check_sum.to_ne_bytes());
+
+        // Write the data back to the region
+        // Until the write completes the object may only be partially
+        // written, so keep track of it for `flush_pending()`.
+        self.pending_append.set(Some((
+            S * region + offset,
+            *region_data
+                .get(offset + LEN_OFFSET)
+                .ok_or(ErrorCode::CorruptData)?,
+        )));
+        if let Err(e) = self.flash_write(
+            S * region + offset,
+            region_data
+                .get(offset..(offset + package_length + CHECK_SUM_LEN))
+                .ok_or(ErrorCode::ObjectTooLarge)?,
+        ) {
+            match e {
+                ErrorCode::WriteNotReady(_) => return Ok(SuccessCode::Queued),
+                _ => return Err(e),
             }
         }
+
+        self.pending_append.set(None);
+        Ok(SuccessCode::Written)
+    }
+
+    /// Marks the last appended object as completely written.
+    ///
+    /// If `FlashController::write()` returns `ErrorCode::WriteNotReady`
+    /// while appending a key, this must be called once the write has
+    /// finished. Otherwise a later `flush_pending()` invalidates the key.
+    pub fn append_complete(&self) {
+        self.pending_append.set(None);
+    }
+
+    /// Makes sure no partially written object is left in flash, for example
+    /// when a brown-out is imminent.
+    ///
+    /// If an append is still being written, or its write failed, the object
+    /// is invalidated by clearing the valid flag in its header. The key is
+    /// then not found by `get_key()` and the space is reclaimed by garbage
+    /// collection, as for `invalidate_key()`. The key must be appended
+    /// again once power is restored.
+    ///
+    /// The controller must accept this write while the append write is in
+    /// progress, for example by aborting it first.
+    ///
+    /// On success a `SuccessCode` will be returned. If no append was
+    /// pending `SuccessCode::Complete` is returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn flush_pending(&self) -> Result<SuccessCode, ErrorCode> {
+        let (address, len) = match self.pending_append.take() {
+            Some(pending) => pending,
+            None => return Ok(SuccessCode::Complete),
+        };
+
+        match self.flash_write(address + LEN_OFFSET, &[len & !0x80]) {
+            Ok(()) => Ok(SuccessCode::Written),
+            Err(ErrorCode::WriteNotReady(_)) => Ok(SuccessCode::Queued),
+            Err(e) => Err(e),
+        }
     }
 
     /// Retrieves the value from flash storage.
@@ -665,12 +1036,23 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
     /// If a power loss occurs before success is returned the data is assumed to
     /// be lost.
     pub fn get_key(&self, hash: u64, buf: &mut [u8]) -> Result<(SuccessCode, usize), ErrorCode> {
+        self.lookup_key(hash, buf, false)
+    }
+
+    /// Looks up a key like `get_key()`. When `initialising`, a region whose
+    /// first object header has an unknown version is reported as
+    /// `ErrorCode::CorruptRegion` instead of being searched.
+    fn lookup_key(
+        &self,
+        hash: u64,
+        buf: &mut [u8],
+        initialising: bool,
+    ) -> Result<(SuccessCode, usize), ErrorCode> {
         let region = self.get_region(hash);
 
         let mut region_offset: isize = 0;
 
         loop {
-            let check_sum = crc32::Crc32::new();
             let new_region = match self.state.get() {
                 State::None => (region as isize + region_offset) as usize,
                 State::Init(state) => {
@@ -685,15 +1067,16 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 State::GetKey(key_state) => match key_state {
                     KeyState::ReadRegion(reg) => reg,
                 },
-                _ => unreachable!(),
+                _ => return Err(ErrorCode::Busy),
             };
 
             // Get the data from that region
             let region_data = self.read_buffer.take().unwrap();
             if self.state.get() != State::GetKey(KeyState::ReadRegion(new_region))
                 && self.state.get() != State::Init(InitState::GetKeyReadRegion(new_region))
+                && self.cached_region.get() != Some(new_region)
             {
-                match self.controller.read_region(new_region, region_data) {
+                match self.flash_read_region(new_region, region_data) {
                     Ok(()) => {}
                     Err(e) => {
                         self.read_buffer.replace(Some(region_data));
@@ -704,88 +1087,181 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     }
                 };
             }
+            // The read buffer now holds the region, so the next lookup in it
+            // does not need to read it again.
+            self.cached_region.set(Some(new_region));
+
+            if initialising
+                && region_data[VERSION_OFFSET] != 0xFF
+                && region_data[VERSION_OFFSET] != VERSION
+            {
+                self.read_buffer.replace(Some(region_data));
+                return Err(ErrorCode::CorruptRegion(new_region));
+            }
 
             match self.find_key_offset(hash, region_data) {
                 Ok((offset, total_length)) => {
-                    // Add the header data to the check hash
-                    check_sum.update(
-                        region_data
-                            .get(offset..(HEADER_LENGTH + offset))
-                            .ok_or(ErrorCode::ObjectTooLarge)?,
-                    );
-
-                    // The size of the stored object's actual data;
-                    let value_length = total_length as usize - HEADER_LENGTH - CHECK_SUM_LEN;
-
-                    // Make sure if will fit in the buffer
-                    if buf.len() < value_length {
-                        // The entire value is not going to fit,
-                        // Let's still copy in what we can and return an error
-                        for i in 0..buf.len() {
-                            *buf.get_mut(i)
-                                .ok_or(ErrorCode::BufferTooSmall(value_length))? = *region_data
-                                .get(offset + HEADER_LENGTH + i)
-                                .ok_or(ErrorCode::BufferTooSmall(value_length))?;
+                    let ret = self.read_value(region_data, offset, total_length, buf);
+                    self.read_buffer.replace(Some(region_data));
+                    return ret.map(|value_length| (SuccessCode::Complete, value_length));
+                }
+                Err((cont, e)) => {
+                    self.read_buffer.replace(Some(region_data));
+
+                    region_offset = new_region as isize - region as isize;
+                    match self.next_lookup_offset(region, region_offset, cont, e) {
+                        Some(o) => {
+                            region_offset = o;
+                            self.state.set(State::None);
                         }
+                        None => {
+                            return Err(e);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Retrieves the values of several keys in a single pass over the flash.
+    ///
+    /// Each region is read at most once and checked for all of the requested
+    /// keys, so this is cheaper than calling `get_key()` for each key when
+    /// many keys are needed at once. The pass stops early once every key has
+    /// been found.
+    ///
+    /// - `keys`: The hashed keys and the buffers to store their values to.
+    /// - `status`: The result for each entry of `keys`, the same as
+    ///   `get_key()` would return for it. Keys that are not in flash are
+    ///   reported as `ErrorCode::KeyNotFound`. Must be as long as `keys`.
+    ///
+    /// On success a `SuccessCode` will be returned and `status` is filled in.
+    /// On error a `ErrorCode` will be returned. If the error is
+    /// `ErrorCode::ReadNotReady` the operation is continued by calling this
+    /// again with the same `keys` and `status`.
+    /// When using `AsyncTicKV` call `AsyncTicKV::get_keys()` instead, which
+    /// continues the operation from `continue_operation()`.
+    pub fn get_keys(
+        &self,
+        keys: &mut [(u64, &mut [u8])],
+        status: &mut [Result<usize, ErrorCode>],
+    ) -> Result<SuccessCode, ErrorCode> {
+        assert_eq!(keys.len(), status.len());
+
+        let num_region = self.flash_size / S;
+
+        let (mut region, mut read_ready) = match self.state.get() {
+            State::None => {
+                status.fill(Err(ErrorCode::KeyNotFound));
+                (0, false)
+            }
+            State::GetKeys(key_state) => match key_state {
+                KeyState::ReadRegion(reg) => (reg, true),
+            },
+            _ => return Err(ErrorCode::Busy),
+        };
 
+        while region < num_region && status.contains(&Err(ErrorCode::KeyNotFound)) {
+            // Get the data from that region
+            let region_data = self.read_buffer.take().unwrap();
+            if !read_ready {
+                match self.flash_read_region(region, region_data) {
+                    Ok(()) => {}
+                    Err(e) => {
                         self.read_buffer.replace(Some(region_data));
-                        return Err(ErrorCode::BufferTooSmall(value_length));
+                        if let ErrorCode::ReadNotReady(reg) = e {
+                            self.state.set(State::GetKeys(KeyState::ReadRegion(reg)));
+                        } else {
+                            self.state.set(State::None);
+                        }
+                        return Err(e);
                     }
+                };
+            }
+            read_ready = false;
 
-                    // Copy in the value
-                    for i in 0..value_length {
-                        *buf.get_mut(i)
-                            .ok_or(ErrorCode::BufferTooSmall(value_length))? = *region_data
-                            .get(offset + HEADER_LENGTH + i)
-                            .ok_or(ErrorCode::CorruptData)?;
-                        check_sum.update(&[*buf.get(i).ok_or(ErrorCode::CorruptData)?])
-                    }
+            for ((hash, buf), status) in keys.iter_mut().zip(status.iter_mut()) {
+                if *status != Err(ErrorCode::KeyNotFound) {
+                    continue;
+                }
 
-                    // Check the hash
-                    let check_sum = check_sum.finalise();
-                    let check_sum = check_sum.to_ne_bytes();
-
-                    if *check_sum.get(3).ok_or(ErrorCode::InvalidCheckSum)?
-                        != *region_data
-                            .get(offset + total_length as usize - 1)
-                            .ok_or(ErrorCode::InvalidCheckSum)?
-                        || *check_sum.get(2).ok_or(ErrorCode::InvalidCheckSum)?
-                            != *region_data
-                                .get(offset + total_length as usize - 2)
-                                .ok_or(ErrorCode::InvalidCheckSum)?
-                        || *check_sum.get(1).ok_or(ErrorCode::InvalidCheckSum)?
-                            != *region_data
-                                .get(offset + total_length as usize - 3)
-                                .ok_or(ErrorCode::InvalidCheckSum)?
-                        || *check_sum.first().ok_or(ErrorCode::InvalidCheckSum)?
-                            != *region_data
-                                .get(offset + total_length as usize - 4)
-                                .ok_or(ErrorCode::InvalidCheckSum)?
-                    {
+                match self.find_key_offset(*hash, region_data) {
+                    Ok((offset, total_length)) => {
+                        *status = self.read_value(region_data, offset, total_length, buf);
+                    }
+                    Err((_, ErrorCode::KeyNotFound)) => {}
+                    Err((_, e)) => {
                         self.read_buffer.replace(Some(region_data));
-                        return Err(ErrorCode::InvalidCheckSum);
+                        self.state.set(State::None);
+                        return Err(e);
                     }
-
-                    self.read_buffer.replace(Some(region_data));
-                    return Ok((SuccessCode::Complete, value_length));
                 }
-                Err((cont, e)) => {
-                    self.read_buffer.replace(Some(region_data));
+            }
 
-                    if cont {
-                        region_offset = new_region as isize - region as isize;
-                        match self.increment_region_offset(region, region_offset) {
-                            Some(o) => {
-                                region_offset = o;
-                                self.state.set(State::None);
-                            }
-                            None => {
-                                return Err(e);
-                            }
+            self.read_buffer.replace(Some(region_data));
+            region += 1;
+        }
+
+        self.state.set(State::None);
+        Ok(SuccessCode::Complete)
+    }
+
+    /// Finds the region that stores the value for a key, without reading
+    /// the value.
+    ///
+    /// This is intended for diagnostics, such as mapping how keys are spread
+    /// across the flash regions.
+    ///
+    /// - `hash`: A hashed key.
+    ///
+    /// On success the region number will be returned.
+    /// On error a `ErrorCode` will be returned.
+    pub fn find_key_region(&self, hash: u64) -> Result<usize, ErrorCode> {
+        let region = self.get_region(hash);
+
+        let mut region_offset: isize = 0;
+
+        loop {
+            let new_region = match self.state.get() {
+                State::None => (region as isize + region_offset) as usize,
+                State::FindKeyRegion(key_state) => match key_state {
+                    KeyState::ReadRegion(reg) => reg,
+                },
+                _ => return Err(ErrorCode::Busy),
+            };
+
+            // Get the data from that region
+            let region_data = self.read_buffer.take().unwrap();
+            if self.state.get() != State::FindKeyRegion(KeyState::ReadRegion(new_region)) {
+                match self.flash_read_region(new_region, region_data) {
+                    Ok(()) => {}
+                    Err(e) => {
+                        self.read_buffer.replace(Some(region_data));
+                        if let ErrorCode::ReadNotReady(reg) = e {
+                            self.state
+                                .set(State::FindKeyRegion(KeyState::ReadRegion(reg)));
                         }
-                    } else {
                         return Err(e);
                     }
+                };
+            }
+
+            let ret = self.find_key_offset(hash, region_data);
+            self.read_buffer.replace(Some(region_data));
+
+            match ret {
+                Ok(_) => return Ok(new_region),
+                Err((cont, e)) => {
+                    region_offset = new_region as isize - region as isize;
+                    match self.next_lookup_offset(region, region_offset, cont, e) {
+                        Some(o) => {
+                            region_offset = o;
+                            self.state.set(State::None);
+                        }
+                        None => {
+                            return Err(e);
+                        }
+                    }
                 }
             }
         }
@@ -812,13 +1288,13 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 State::InvalidateKey(key_state) => match key_state {
                     KeyState::ReadRegion(reg) => reg,
                 },
-                _ => unreachable!(),
+                _ => return Err(ErrorCode::Busy),
             };
 
             // Get the data from that region
             let region_data = self.read_buffer.take().unwrap();
             if self.state.get() != State::InvalidateKey(KeyState::ReadRegion(new_region)) {
-                match self.controller.read_region(new_region, region_data) {
+                match self.flash_read_region(new_region, region_data) {
                     Ok(()) => {}
                     Err(e) => {
                         self.read_buffer.replace(Some(region_data));
@@ -838,7 +1314,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                         .get_mut(offset + LEN_OFFSET)
                         .ok_or(ErrorCode::CorruptData)? &= !0x80;
 
-                    if let Err(e) = self.controller.write(
+                    if let Err(e) = self.flash_write(
                         S * new_region + offset + LEN_OFFSET,
                         region_data
                             .get(offset + LEN_OFFSET..offset + LEN_OFFSET + 1)
@@ -857,19 +1333,15 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 Err((cont, e)) => {
                     self.read_buffer.replace(Some(region_data));
 
-                    if cont {
-                        region_offset = new_region as isize - region as isize;
-                        match self.increment_region_offset(region, region_offset) {
-                            Some(o) => {
-                                region_offset = o;
-                                self.state.set(State::None);
-                            }
-                            None => {
-                                return Err(e);
-                            }
+                    region_offset = new_region as isize - region as isize;
+                    match self.next_lookup_offset(region, region_offset, cont, e) {
+                        Some(o) => {
+                            region_offset = o;
+                            self.state.set(State::None);
+                        }
+                        None => {
+                            return Err(e);
                         }
-                    } else {
-                        return Err(e);
                     }
                 }
             }
@@ -911,13 +1383,13 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 State::ZeroiseKey(key_state) => match key_state {
                     KeyState::ReadRegion(reg) => reg,
                 },
-                _ => unreachable!(),
+                _ => return Err(ErrorCode::Busy),
             };
 
             // Get the data from that region
             let region_data = self.read_buffer.take().unwrap();
             if self.state.get() != State::ZeroiseKey(KeyState::ReadRegion(new_region)) {
-                match self.controller.read_region(new_region, region_data) {
+                match self.flash_read_region(new_region, region_data) {
                     Ok(()) => {}
                     Err(e) => {
                         self.read_buffer.replace(Some(region_data));
@@ -945,7 +1417,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
 
                     let write_len = data_len as usize;
 
-                    if let Err(e) = self.controller.write(
+                    if let Err(e) = self.flash_write(
                         S * new_region + offset,
                         region_data
                             .get(offset..offset + write_len)
@@ -964,19 +1436,15 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                 Err((cont, e)) => {
                     self.read_buffer.replace(Some(region_data));
 
-                    if cont {
-                        region_offset = new_region as isize - region as isize;
-                        match self.increment_region_offset(region, region_offset) {
-                            Some(o) => {
-                                region_offset = o;
-                                self.state.set(State::None);
-                            }
-                            None => {
-                                return Err(e);
-                            }
+                    region_offset = new_region as isize - region as isize;
+                    match self.next_lookup_offset(region, region_offset, cont, e) {
+                        Some(o) => {
+                            region_offset = o;
+                            self.state.set(State::None);
+                        }
+                        None => {
+                            return Err(e);
                         }
-                    } else {
-                        return Err(e);
                     }
                 }
             }
@@ -992,7 +1460,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
         let region_data = self.read_buffer.take().unwrap();
         if self.state.get() != State::GarbageCollect(RubbishState::ReadRegion(region, flash_freed))
         {
-            match self.controller.read_region(region, region_data) {
+            match self.flash_read_region(region, region_data) {
                 Ok(()) => {}
                 Err(e) => {
                     self.read_buffer.replace(Some(region_data));
@@ -1083,7 +1551,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
 
         // If we got down here, the region is ready to be erased.
 
-        if let Err(e) = self.controller.erase_region(region) {
+        if let Err(e) = self.flash_erase_region(region) {
             if let ErrorCode::EraseNotReady(reg) = e {
                 self.state
                     .set(State::GarbageCollect(RubbishState::EraseRegion(
@@ -1117,7 +1585,7 @@ impl<'a, C: FlashController<S>, const S: usize> TicKV<'a, C, S> {
                     reg + 1
                 }
             },
-            _ => unreachable!(),
+            _ => return Err(ErrorCode::Busy),
         };
 
         for i in start..num_region {